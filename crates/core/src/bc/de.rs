@@ -10,9 +10,11 @@ use nom::{
 type IResult<I, O, E = nom::error::VerboseError<I>> = Result<(I, O), nom::Err<E>>;
 
 impl Bc {
-    /// Returns Ok(deserialized data, the amount of data consumed)
-    /// Can then use this as the amount that should be remove from a buffer
-    pub(crate) fn deserialize(context: &BcContext, buf: &mut BytesMut) -> Result<Bc, Error> {
+    /// Deserialize a single message from the front of the buffer
+    ///
+    /// The consumed bytes are removed from the buffer. If the buffer does not
+    /// yet hold a complete message [`Error::NomIncomplete`] is returned
+    pub fn deserialize(context: &BcContext, buf: &mut BytesMut) -> Result<Bc, Error> {
         let parser = BcParser { context };
         let (result, amount) = match consumed(parser)(buf) {
             Ok((_, (parsed_buff, result))) => Ok((result, parsed_buff.len())),
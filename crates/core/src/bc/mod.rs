@@ -41,4 +41,5 @@ pub mod xml;
 /// Contains the encryption protocols
 pub mod crypto;
 
+#[cfg(feature = "network")]
 pub(crate) mod codex;
@@ -18,10 +18,20 @@ pub const MSG_ID_LOGOUT: u32 = 2;
 pub const MSG_ID_VIDEO: u32 = 3;
 /// ID used to stop the video stream
 pub const MSG_ID_VIDEO_STOP: u32 = 4;
+/// Stop the download of a file from the SD card
+pub const MSG_ID_FILE_STOP: u32 = 7;
+/// Download a file from the SD card, the video follows as binary data
+pub const MSG_ID_FILE_DOWNLOAD: u32 = 8;
 /// TalkAbility messages have this ID
 pub const MSG_ID_TALKABILITY: u32 = 10;
 /// TalkReset messages have this ID
 pub const MSG_ID_TALKRESET: u32 = 11;
+/// Start a search of the files on the SD card, the reply has the search handle
+pub const MSG_ID_FILE_SEARCH_OPEN: u32 = 14;
+/// Get the next files of a search of the SD card
+pub const MSG_ID_FILE_SEARCH_NEXT: u32 = 15;
+/// End a search of the files on the SD card
+pub const MSG_ID_FILE_SEARCH_CLOSE: u32 = 16;
 /// PtzControl messages have this ID
 pub const MSG_ID_PTZ_CONTROL: u32 = 18;
 /// PTZ goto preset position
@@ -36,8 +46,20 @@ pub const MSG_ID_MOTION: u32 = 33;
 pub const MSG_ID_SET_SERVICE_PORTS: u32 = 36;
 /// Get service ports
 pub const MSG_ID_GET_SERVICE_PORTS: u32 = 37;
+/// Get OSD (including the channel name) messages have this ID
+pub const MSG_ID_GET_OSD: u32 = 44;
+/// Set OSD (including the channel name) messages have this ID
+pub const MSG_ID_SET_OSD: u32 = 45;
+/// Get the privacy mask (shelter) areas
+pub const MSG_ID_GET_SHELTER: u32 = 52;
+/// Set the privacy mask (shelter) areas
+pub const MSG_ID_SET_SHELTER: u32 = 53;
 /// Version messages have this ID
 pub const MSG_ID_VERSION: u32 = 80;
+/// Get the recording schedule messages have this ID
+pub const MSG_ID_GET_RECORD: u32 = 81;
+/// Set the recording schedule messages have this ID
+pub const MSG_ID_SET_RECORD: u32 = 82;
 /// Ping messages have this ID
 pub const MSG_ID_PING: u32 = 93;
 /// General system info messages have this ID
@@ -48,6 +70,8 @@ pub const MSG_ID_SET_GENERAL: u32 = 105;
 pub const MSG_ID_SNAP: u32 = 109;
 /// Used to grab the UID
 pub const MSG_ID_UID: u32 = 114;
+/// Wifi signal strength messages have this ID
+pub const MSG_ID_WIFI_SIGNAL: u32 = 115;
 /// Used to pass the token and client ID for push notifications
 pub const MSG_ID_PUSH_INFO: u32 = 124;
 /// StreamInfoList messages have this ID
@@ -91,6 +115,73 @@ pub const MSG_ID_SET_ZOOM_FOCUS: u32 = 295;
 /// Get the floodlight task xml
 pub const MSG_ID_FLOODLIGHT_TASKS_READ: u32 = 438;
 
+/// Registry of the known message IDs and a short name for each
+///
+/// Used by the developer tools to pretty print messages. When adding
+/// a new `MSG_ID_*` it should also be added here
+pub const MSG_ID_NAMES: &[(u32, &str)] = &[
+    (MSG_ID_LOGIN, "Login"),
+    (MSG_ID_LOGOUT, "Logout"),
+    (MSG_ID_VIDEO, "Video"),
+    (MSG_ID_VIDEO_STOP, "VideoStop"),
+    (MSG_ID_FILE_STOP, "FileStop"),
+    (MSG_ID_FILE_DOWNLOAD, "FileDownload"),
+    (MSG_ID_TALKABILITY, "TalkAbility"),
+    (MSG_ID_TALKRESET, "TalkReset"),
+    (MSG_ID_FILE_SEARCH_OPEN, "FileSearchOpen"),
+    (MSG_ID_FILE_SEARCH_NEXT, "FileSearchNext"),
+    (MSG_ID_FILE_SEARCH_CLOSE, "FileSearchClose"),
+    (MSG_ID_PTZ_CONTROL, "PtzControl"),
+    (MSG_ID_PTZ_CONTROL_PRESET, "PtzControlPreset"),
+    (MSG_ID_REBOOT, "Reboot"),
+    (MSG_ID_MOTION_REQUEST, "MotionRequest"),
+    (MSG_ID_MOTION, "Motion"),
+    (MSG_ID_SET_SERVICE_PORTS, "SetServicePorts"),
+    (MSG_ID_GET_SERVICE_PORTS, "GetServicePorts"),
+    (MSG_ID_GET_OSD, "GetOsd"),
+    (MSG_ID_SET_OSD, "SetOsd"),
+    (MSG_ID_GET_SHELTER, "GetShelter"),
+    (MSG_ID_SET_SHELTER, "SetShelter"),
+    (MSG_ID_VERSION, "Version"),
+    (MSG_ID_GET_RECORD, "GetRecord"),
+    (MSG_ID_SET_RECORD, "SetRecord"),
+    (MSG_ID_PING, "Ping"),
+    (MSG_ID_GET_GENERAL, "GetGeneral"),
+    (MSG_ID_SET_GENERAL, "SetGeneral"),
+    (MSG_ID_SNAP, "Snap"),
+    (MSG_ID_UID, "Uid"),
+    (MSG_ID_WIFI_SIGNAL, "WifiSignal"),
+    (MSG_ID_PUSH_INFO, "PushInfo"),
+    (MSG_ID_STREAM_INFO_LIST, "StreamInfoList"),
+    (MSG_ID_ABILITY_INFO, "AbilityInfo"),
+    (MSG_ID_GET_PTZ_PRESET, "GetPtzPreset"),
+    (MSG_ID_GET_SUPPORT, "GetSupport"),
+    (MSG_ID_TALKCONFIG, "TalkConfig"),
+    (MSG_ID_TALK, "Talk"),
+    (MSG_ID_GET_LED_STATUS, "GetLedStatus"),
+    (MSG_ID_SET_LED_STATUS, "SetLedStatus"),
+    (MSG_ID_GET_PIR_ALARM, "GetPirAlarm"),
+    (MSG_ID_START_PIR_ALARM, "StartPirAlarm"),
+    (MSG_ID_UDP_KEEP_ALIVE, "UdpKeepAlive"),
+    (MSG_ID_BATTERY_INFO_LIST, "BatteryInfoList"),
+    (MSG_ID_BATTERY_INFO, "BatteryInfo"),
+    (MSG_ID_PLAY_AUDIO, "PlayAudio"),
+    (MSG_ID_FLOODLIGHT_MANUAL, "FloodlightManual"),
+    (MSG_ID_FLOODLIGHT_TASKS_WRITE, "FloodlightTasksWrite"),
+    (MSG_ID_FLOODLIGHT_STATUS_LIST, "FloodlightStatusList"),
+    (MSG_ID_GET_ZOOM_FOCUS, "GetZoomFocus"),
+    (MSG_ID_SET_ZOOM_FOCUS, "SetZoomFocus"),
+    (MSG_ID_FLOODLIGHT_TASKS_READ, "FloodlightTasksRead"),
+];
+
+/// Get the short name of a message ID or `None` if the ID is unknown
+pub fn msg_id_name(msg_id: u32) -> Option<&'static str> {
+    MSG_ID_NAMES
+        .iter()
+        .find(|(id, _)| *id == msg_id)
+        .map(|(_, name)| *name)
+}
+
 /// An empty password in legacy format
 pub const EMPTY_LEGACY_PASSWORD: &str =
     "\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0";
@@ -211,8 +302,14 @@ pub(super) struct BcSendInfo {
     pub payload_offset: Option<u32>,
 }
 
+/// The state needed to (de)serialize a stream of messages
+///
+/// This tracks the negotiated encryption and which message numbers
+/// have switched to binary payloads
 #[derive(Debug)]
-pub(crate) struct BcContext {
+pub struct BcContext {
+    // Only the connection codex needs these to make the AES key
+    #[cfg_attr(not(feature = "network"), allow(dead_code))]
     pub(crate) credentials: Credentials,
     pub(crate) in_bin_mode: HashSet<u16>,
     pub(crate) encryption_protocol: EncryptionProtocol,
@@ -253,7 +350,8 @@ impl Bc {
 }
 
 impl BcContext {
-    pub(crate) fn new(credentials: Credentials) -> BcContext {
+    /// Create a new context for an unencrypted connection
+    pub fn new(credentials: Credentials) -> BcContext {
         BcContext {
             credentials,
             in_bin_mode: HashSet::new(),
@@ -262,8 +360,8 @@ impl BcContext {
         }
     }
 
-    #[allow(unused)] // Used in tests
-    pub(crate) fn new_with_encryption(encryption_protocol: EncryptionProtocol) -> BcContext {
+    /// Create a new context with an already negotiated encryption
+    pub fn new_with_encryption(encryption_protocol: EncryptionProtocol) -> BcContext {
         BcContext {
             credentials: Default::default(),
             in_bin_mode: HashSet::new(),
@@ -272,24 +370,28 @@ impl BcContext {
         }
     }
 
-    pub(crate) fn set_encrypted(&mut self, encryption_protocol: EncryptionProtocol) {
+    /// Set the encryption, usually after it has been negotiated during login
+    pub fn set_encrypted(&mut self, encryption_protocol: EncryptionProtocol) {
         self.encryption_protocol = encryption_protocol;
     }
 
-    pub(crate) fn get_encrypted(&self) -> &EncryptionProtocol {
+    /// Get the current encryption
+    pub fn get_encrypted(&self) -> &EncryptionProtocol {
         &self.encryption_protocol
     }
 
-    pub(crate) fn binary_on(&mut self, msg_id: u16) {
+    /// Mark the message number as carrying binary payloads
+    pub fn binary_on(&mut self, msg_id: u16) {
         self.in_bin_mode.insert(msg_id);
     }
 
-    #[allow(unused)] // Used in tests
-    pub(crate) fn binary_off(&mut self, msg_id: u16) {
+    /// Mark the message number as carrying xml payloads again
+    pub fn binary_off(&mut self, msg_id: u16) {
         self.in_bin_mode.remove(&msg_id);
     }
 
-    pub(crate) fn debug_on(&mut self) {
+    /// Print the xml of each message as it is (de)serialized
+    pub fn debug_on(&mut self) {
         self.debug = true;
     }
 }
@@ -357,3 +459,13 @@ pub(super) fn has_payload_offset(class: u16) -> bool {
     // See BcHeader::is_modern() for a description of which packets have the bin offset
     class == 0x6414 || class == 0x0000
 }
+
+#[test]
+fn test_msg_id_names_unique() {
+    let mut seen = HashSet::new();
+    for (id, _) in MSG_ID_NAMES.iter() {
+        assert!(seen.insert(*id), "Duplicate msg id {} in registry", id);
+    }
+    assert_eq!(msg_id_name(MSG_ID_LOGIN), Some("Login"));
+    assert_eq!(msg_id_name(0xFFFF_FFFF), None);
+}
@@ -7,7 +7,8 @@ use log::error;
 use std::io::Write;
 
 impl Bc {
-    pub(crate) fn serialize<W: Write>(
+    /// Serialize the message into the writer using the given encryption
+    pub fn serialize<W: Write>(
         &self,
         buf: W,
         encryption_protocol: &EncryptionProtocol,
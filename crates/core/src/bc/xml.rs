@@ -132,6 +132,25 @@ pub struct BcXml {
     /// For changing rtmp server port
     #[serde(rename = "OnvifPort", skip_serializing_if = "Option::is_none")]
     pub onvif_port: Option<OnvifPort>,
+    /// The channel name as shown on the OSD and in the NVR
+    #[serde(rename = "OsdChannelName", skip_serializing_if = "Option::is_none")]
+    pub osd_channel_name: Option<OsdChannelName>,
+    /// The date time as shown on the OSD
+    #[serde(rename = "OsdDatetime", skip_serializing_if = "Option::is_none")]
+    pub osd_datetime: Option<OsdDatetime>,
+    /// Recieved on request for the wifi signal strength
+    #[serde(rename = "WifiSignal", skip_serializing_if = "Option::is_none")]
+    pub wifi_signal: Option<WifiSignal>,
+    /// The recording schedule of the SD card
+    #[serde(rename = "Record", skip_serializing_if = "Option::is_none")]
+    pub record: Option<Record>,
+    /// The privacy mask areas that the camera blacks out
+    #[serde(rename = "Shelter", skip_serializing_if = "Option::is_none")]
+    pub shelter: Option<Shelter>,
+    /// Sent to search and download the files of the SD card and recieved
+    /// with the search handle and the files found
+    #[serde(rename = "FileInfoList", skip_serializing_if = "Option::is_none")]
+    pub file_info_list: Option<FileInfoList>,
 }
 
 impl BcXml {
@@ -411,6 +430,76 @@ pub struct LedState {
     /// State of the LED status light (blue on light), values are "open", "close"
     #[serde(rename = "lightState")]
     pub light_state: String,
+    /// State of the light around the button of doorbells, values are "auto", "open", "close"
+    ///
+    /// Only doorbells send this
+    #[serde(rename = "doorbellLightState", skip_serializing_if = "Option::is_none")]
+    pub doorbell_light_state: Option<String>,
+}
+
+/// WifiSignal xml
+#[derive(PartialEq, Eq, Default, Debug, Deserialize, Serialize, Clone)]
+pub struct WifiSignal {
+    /// XML Version
+    #[serde(rename = "@version")]
+    pub version: String,
+    /// The signal strength in dBm e.g. `-40`
+    pub signal: i32,
+}
+
+/// OsdChannelName xml
+#[derive(PartialEq, Eq, Default, Debug, Deserialize, Serialize, Clone)]
+pub struct OsdChannelName {
+    /// XML Version
+    #[serde(rename = "@version")]
+    pub version: String,
+    /// Channel ID of the camera
+    #[serde(rename = "channelId")]
+    pub channel_id: u8,
+    /// The channel name, for an NVR this is the title of the channel
+    pub name: String,
+    /// Whether the name is shown on the OSD: 1 for shown 0 for hidden
+    pub enable: u8,
+    /// Position of the name on the OSD
+    #[serde(rename = "topLeftX", skip_serializing_if = "Option::is_none")]
+    pub top_left_x: Option<u32>,
+    /// Position of the name on the OSD
+    #[serde(rename = "topLeftY", skip_serializing_if = "Option::is_none")]
+    pub top_left_y: Option<u32>,
+    /// Whether the watermark is shown
+    #[serde(rename = "enWatermark", skip_serializing_if = "Option::is_none")]
+    pub en_watermark: Option<u8>,
+    /// Whether the text has a background color
+    #[serde(rename = "enBgcolor", skip_serializing_if = "Option::is_none")]
+    pub en_bgcolor: Option<u8>,
+}
+
+/// OsdDatetime xml
+#[derive(PartialEq, Eq, Default, Debug, Deserialize, Serialize, Clone)]
+pub struct OsdDatetime {
+    /// XML Version
+    #[serde(rename = "@version")]
+    pub version: String,
+    /// Channel ID of the camera
+    #[serde(rename = "channelId")]
+    pub channel_id: u8,
+    /// Whether the date time is shown on the OSD: 1 for shown 0 for hidden
+    pub enable: u8,
+    /// Position of the date time on the OSD
+    #[serde(rename = "topLeftX", skip_serializing_if = "Option::is_none")]
+    pub top_left_x: Option<u32>,
+    /// Position of the date time on the OSD
+    #[serde(rename = "topLeftY", skip_serializing_if = "Option::is_none")]
+    pub top_left_y: Option<u32>,
+    /// Width, this is recieved but should not be sent
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub width: Option<u32>,
+    /// Height, this is recieved but should not be sent
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub height: Option<u32>,
+    /// Language of the date time
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub language: Option<String>,
 }
 
 /// FloodlightStatus xml
@@ -475,7 +564,7 @@ pub struct RfAlarmCfg {
 }
 
 /// TimeBlockList XML
-#[derive(PartialEq, Eq, Default, Debug, Deserialize, Serialize)]
+#[derive(PartialEq, Eq, Default, Debug, Deserialize, Serialize, Clone)]
 #[serde(rename = "timeBlockList")]
 pub struct TimeBlockList {
     /// List of time block entries which disable/enable the PIR at a time
@@ -484,7 +573,7 @@ pub struct TimeBlockList {
 }
 
 /// TimeBlock XML Used to set the time to enable/disable PIR dectection
-#[derive(PartialEq, Eq, Default, Debug, Deserialize, Serialize)]
+#[derive(PartialEq, Eq, Default, Debug, Deserialize, Serialize, Clone)]
 #[serde(rename = "timeBlock")]
 pub struct TimeBlock {
     /// Whether to enable or disable for this time block
@@ -499,6 +588,173 @@ pub struct TimeBlock {
     pub end_hour: u8,
 }
 
+/// Record xml, the recording schedule of the SD card
+#[derive(PartialEq, Eq, Default, Debug, Deserialize, Serialize, Clone)]
+pub struct Record {
+    /// XML Version
+    #[serde(rename = "@version")]
+    pub version: String,
+    /// Channel ID of the camera
+    #[serde(rename = "channelId")]
+    pub channel_id: u8,
+    /// Whether recording is enabled at all: 1 for enabled 0 for disabled
+    pub enable: u8,
+    /// The schedules of what triggers a recording
+    #[serde(rename = "ScheduleList")]
+    pub schedule_list: ScheduleList,
+}
+
+/// Shelter xml
+///
+/// This is the camera's privacy mask, the areas are blacked out by the
+/// camera itself so they are hidden from every client and recording
+#[derive(PartialEq, Eq, Default, Debug, Deserialize, Serialize, Clone)]
+pub struct Shelter {
+    /// XML Version
+    #[serde(rename = "@version")]
+    pub version: String,
+    /// Channel ID of the camera
+    #[serde(rename = "channelId")]
+    pub channel_id: u8,
+    /// Whether the mask is drawn at all: 1 for enabled 0 for disabled
+    pub enable: u8,
+    /// Width of the coordinate space of the areas, this is recieved but should not be sent
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub width: Option<u32>,
+    /// Height of the coordinate space of the areas, this is recieved but should not be sent
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub height: Option<u32>,
+    /// The masked areas
+    #[serde(rename = "ShelterList")]
+    pub shelter_list: ShelterList,
+}
+
+/// ShelterList xml
+#[derive(PartialEq, Eq, Default, Debug, Deserialize, Serialize, Clone)]
+pub struct ShelterList {
+    /// Each area that is masked, cameras support a small number of these (usually 4)
+    #[serde(default, rename = "shelterInfo")]
+    pub shelter_info: Vec<ShelterInfo>,
+}
+
+/// ShelterInfo xml
+#[derive(PartialEq, Eq, Default, Debug, Deserialize, Serialize, Clone)]
+pub struct ShelterInfo {
+    /// Index of the area
+    pub id: u8,
+    /// Whether this area is masked: 1 for enabled 0 for disabled
+    pub enable: u8,
+    /// Left edge in the coordinate space of the [Shelter]
+    pub x: u32,
+    /// Top edge in the coordinate space of the [Shelter]
+    pub y: u32,
+    /// Width in the coordinate space of the [Shelter]
+    pub width: u32,
+    /// Height in the coordinate space of the [Shelter]
+    pub height: u32,
+}
+
+/// FileInfoList xml
+///
+/// Used for all the messages of searching and downloading the SD card
+#[derive(PartialEq, Eq, Default, Debug, Deserialize, Serialize, Clone)]
+pub struct FileInfoList {
+    /// XML Version
+    #[serde(rename = "@version", skip_serializing_if = "Option::is_none")]
+    pub version: Option<String>,
+    /// The files, only one is sent in a request
+    #[serde(default, rename = "FileInfo")]
+    pub file_info: Vec<FileInfo>,
+}
+
+/// FileInfo xml
+///
+/// In a request it has the search or file and in a reply the search handle
+/// or a file that was found
+#[derive(PartialEq, Eq, Default, Debug, Deserialize, Serialize, Clone)]
+pub struct FileInfo {
+    /// Unknown, observed values: 0
+    /// value is only set on request
+    #[serde(rename = "uid", skip_serializing_if = "Option::is_none")]
+    pub uid: Option<u32>,
+    /// Channel ID of the camera
+    #[serde(rename = "channelId")]
+    pub channel_id: u8,
+    /// Handle of the search, set on the reply to the start of a search
+    /// and then sent to get the files and end the search
+    #[serde(rename = "handle", skip_serializing_if = "Option::is_none")]
+    pub handle: Option<u32>,
+    /// Stream name, observed values: `mainStream`, `subStream`
+    #[serde(rename = "streamType", skip_serializing_if = "Option::is_none")]
+    pub stream_type: Option<String>,
+    /// What caused the recording e.g. `md`, `sched` or `manual`. In a search
+    /// a comma separated list of the types to find
+    #[serde(rename = "recordType", skip_serializing_if = "Option::is_none")]
+    pub record_type: Option<String>,
+    /// Name of the file on the SD card
+    #[serde(rename = "name", skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    /// Size of the file in bytes
+    /// value is only set on recieve
+    #[serde(
+        rename = "fileSize",
+        alias = "size",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub file_size: Option<u64>,
+    /// Start of the search or of the recording in the camera's time
+    #[serde(rename = "startTime", skip_serializing_if = "Option::is_none")]
+    pub start_time: Option<FileTime>,
+    /// End of the search or of the recording in the camera's time
+    #[serde(rename = "endTime", skip_serializing_if = "Option::is_none")]
+    pub end_time: Option<FileTime>,
+    /// Unknown, observed values: 0
+    /// value is only set on download
+    #[serde(rename = "supportSub", skip_serializing_if = "Option::is_none")]
+    pub support_sub: Option<u8>,
+    /// Speed that the file is sent at, observed values: 32
+    /// value is only set on download
+    #[serde(rename = "playSpeed", skip_serializing_if = "Option::is_none")]
+    pub play_speed: Option<u32>,
+}
+
+/// A time of the [FileInfo]
+#[derive(PartialEq, Eq, Default, Debug, Deserialize, Serialize, Clone, Copy)]
+pub struct FileTime {
+    /// Year such as 2024
+    pub year: i32,
+    /// Month from 1 to 12
+    pub month: u8,
+    /// Day of the month from 1
+    pub day: u8,
+    /// Hour from 0 to 23
+    pub hour: u8,
+    /// Minute from 0 to 59
+    pub minute: u8,
+    /// Second from 0 to 59
+    pub second: u8,
+}
+
+/// ScheduleList xml
+#[derive(PartialEq, Eq, Default, Debug, Deserialize, Serialize, Clone)]
+pub struct ScheduleList {
+    /// Each schedule is a set of time blocks for one alarm type
+    #[serde(default, rename = "Schedule")]
+    pub schedule: Vec<RecordSchedule>,
+}
+
+/// Schedule xml of the ScheduleList
+#[derive(PartialEq, Eq, Default, Debug, Deserialize, Serialize, Clone)]
+pub struct RecordSchedule {
+    /// What triggers the recording in these time blocks. Known values
+    /// `"MD"` to record on motion and `"none"` to always record
+    #[serde(rename = "alarmType")]
+    pub alarm_type: String,
+    /// The time blocks of this schedule
+    #[serde(rename = "timeBlockList")]
+    pub time_block_list: TimeBlockList,
+}
+
 #[derive(PartialEq, Eq, Default, Debug, Deserialize, Serialize)]
 /// AlarmHandle Xml
 pub struct AlarmHandle {
@@ -630,6 +886,13 @@ pub struct AlarmEvent {
     /// AI status. Known values are `"people"` or `"none"`
     #[serde(rename = "AItype", skip_serializing_if = "Option::is_none")]
     pub ai_type: Option<String>,
+    /// Smart detection status of models with line crossing or intrusion zones
+    /// such as `"crossline"`, `"intrusion"`, `"loitering"` or `"none"`
+    #[serde(rename = "smartType", skip_serializing_if = "Option::is_none")]
+    pub smart_type: Option<String>,
+    /// The index of the line or zone that triggered the smart detection
+    #[serde(rename = "smartId", skip_serializing_if = "Option::is_none")]
+    pub smart_id: Option<u8>,
     /// The recording status. Known values `0` or `1`
     pub recording: i32,
     /// The timestamp associated with the recording. `0` if not recording
@@ -649,7 +912,8 @@ pub struct PtzControl {
     /// The amount of movement to perform
     pub speed: f32,
     /// The direction to transverse. Known values are `"left"`, `"right"`, `"up"`, `"down"`,
-    /// `"leftUp"`, `"leftDown"`, `"rightUp"`, `"rightDown"` and `"stop"`
+    /// `"leftUp"`, `"leftDown"`, `"rightUp"`, `"rightDown"` and `"stop"`. Cameras with a
+    /// motorized lens also take `"zoomInc"`, `"zoomDec"`, `"focusInc"` and `"focusDec"`
     pub command: String,
 }
 
@@ -1379,7 +1643,7 @@ pub struct AudioPlayInfo {
     /// Channel ID
     #[serde(rename = "channelId")]
     pub channel_id: u8,
-    /// Playmode: 0
+    /// Playmode: 0 to play `play_times` times, 1 to play until `on_off` is 0
     #[serde(rename = "playMode")]
     pub play_mode: u32,
     /// Duration: 0
@@ -1388,7 +1652,7 @@ pub struct AudioPlayInfo {
     /// Times to play: 1
     #[serde(rename = "playTimes")]
     pub play_times: u32,
-    /// On or Off: 0
+    /// On or Off when the play mode is 1: 0
     #[serde(rename = "onOff")]
     pub on_off: u32,
 }
@@ -1827,3 +2091,233 @@ fn test_empty_floodlight_status_list() {
         _ => panic!(),
     }
 }
+
+#[test]
+fn test_osd_channel_name() {
+    let _ = env_logger::builder().is_test(true).try_init();
+    let sample = indoc!(
+        r#"<?xml version="1.0" encoding="UTF-8" ?>
+        <body>
+        <OsdChannelName version="1.1">
+        <channelId>0</channelId>
+        <name>Cammy02</name>
+        <enable>1</enable>
+        <topLeftX>65536</topLeftX>
+        <topLeftY>65536</topLeftY>
+        <enWatermark>0</enWatermark>
+        <enBgcolor>0</enBgcolor>
+        </OsdChannelName>
+        <OsdDatetime version="1.1">
+        <channelId>0</channelId>
+        <enable>1</enable>
+        <topLeftX>65537</topLeftX>
+        <topLeftY>1</topLeftY>
+        <width>0</width>
+        <height>0</height>
+        <language>Chinese</language>
+        </OsdDatetime>
+        </body>
+        "#
+    );
+    let b = BcXml::try_parse(sample.as_bytes()).unwrap();
+    match b {
+        BcXml {
+            osd_channel_name: Some(OsdChannelName { name, enable: 1, .. }),
+            osd_datetime: Some(OsdDatetime { language, .. }),
+            ..
+        } if name == "Cammy02" && language.as_deref() == Some("Chinese") => {}
+        _ => panic!(),
+    }
+}
+
+#[test]
+fn test_record() {
+    let _ = env_logger::builder().is_test(true).try_init();
+    let sample = indoc!(
+        r#"<?xml version="1.0" encoding="UTF-8" ?>
+        <body>
+        <Record version="1.1">
+        <channelId>0</channelId>
+        <enable>1</enable>
+        <ScheduleList>
+        <Schedule>
+        <alarmType>MD</alarmType>
+        <timeBlockList>
+        <timeBlock>
+        <enable>1</enable>
+        <weekDay>Tuesday</weekDay>
+        <beginHour>0</beginHour>
+        <endHour>12</endHour>
+        </timeBlock>
+        <timeBlock>
+        <enable>1</enable>
+        <weekDay>Tuesday</weekDay>
+        <beginHour>14</beginHour>
+        <endHour>23</endHour>
+        </timeBlock>
+        </timeBlockList>
+        </Schedule>
+        <Schedule>
+        <alarmType>none</alarmType>
+        <timeBlockList>
+        <timeBlock>
+        <enable>1</enable>
+        <weekDay>Tuesday</weekDay>
+        <beginHour>13</beginHour>
+        <endHour>13</endHour>
+        </timeBlock>
+        </timeBlockList>
+        </Schedule>
+        </ScheduleList>
+        </Record>
+        </body>
+        "#
+    );
+    let b = BcXml::try_parse(sample.as_bytes()).unwrap();
+    match b {
+        BcXml {
+            record: Some(Record {
+                enable: 1,
+                schedule_list: ScheduleList { schedule },
+                ..
+            }),
+            ..
+        } if schedule.len() == 2
+            && schedule[0].alarm_type == "MD"
+            && schedule[0].time_block_list.time_block.len() == 2
+            && schedule[1].alarm_type == "none" => {}
+        _ => panic!(),
+    }
+}
+
+#[test]
+fn test_shelter() {
+    let _ = env_logger::builder().is_test(true).try_init();
+    let sample = indoc!(
+        r#"<?xml version="1.0" encoding="UTF-8" ?>
+        <body>
+        <Shelter version="1.1">
+        <channelId>0</channelId>
+        <enable>1</enable>
+        <width>704</width>
+        <height>480</height>
+        <ShelterList>
+        <shelterInfo>
+        <id>0</id>
+        <enable>1</enable>
+        <x>500</x>
+        <y>0</y>
+        <width>204</width>
+        <height>120</height>
+        </shelterInfo>
+        <shelterInfo>
+        <id>1</id>
+        <enable>0</enable>
+        <x>0</x>
+        <y>0</y>
+        <width>0</width>
+        <height>0</height>
+        </shelterInfo>
+        </ShelterList>
+        </Shelter>
+        </body>
+        "#
+    );
+    let b = BcXml::try_parse(sample.as_bytes()).unwrap();
+    match b {
+        BcXml {
+            shelter:
+                Some(Shelter {
+                    enable: 1,
+                    width: Some(704),
+                    shelter_list: ShelterList { shelter_info },
+                    ..
+                }),
+            ..
+        } if shelter_info.len() == 2
+            && shelter_info[0].x == 500
+            && shelter_info[0].width == 204
+            && shelter_info[1].enable == 0 => {}
+        _ => panic!(),
+    }
+}
+
+#[test]
+fn test_alarm_event_smart() {
+    let _ = env_logger::builder().is_test(true).try_init();
+    let sample = indoc!(
+        r#"<?xml version="1.0" encoding="UTF-8" ?>
+        <body>
+        <AlarmEventList version="1.1">
+        <AlarmEvent version="1.1">
+        <channelId>0</channelId>
+        <status>MD</status>
+        <AItype>people,vehicle</AItype>
+        <smartType>crossline</smartType>
+        <smartId>1</smartId>
+        <recording>0</recording>
+        <timeStamp>0</timeStamp>
+        </AlarmEvent>
+        </AlarmEventList>
+        </body>
+        "#
+    );
+    let b = BcXml::try_parse(sample.as_bytes()).unwrap();
+    match b {
+        BcXml {
+            alarm_event_list: Some(AlarmEventList { alarm_events, .. }),
+            ..
+        } if alarm_events.len() == 1
+            && alarm_events[0].smart_type.as_deref() == Some("crossline")
+            && alarm_events[0].smart_id == Some(1) => {}
+        _ => panic!(),
+    }
+}
+
+#[test]
+fn test_file_info_list() {
+    let _ = env_logger::builder().is_test(true).try_init();
+    let sample = indoc!(
+        r#"<?xml version="1.0" encoding="UTF-8" ?>
+        <body>
+        <FileInfoList version="1.1">
+        <FileInfo>
+        <channelId>0</channelId>
+        <handle>8</handle>
+        <streamType>mainStream</streamType>
+        <recordType>md</recordType>
+        <name>Mp4Record/2024-03-01/RecM01_20240301_101530_101612_6D28808_1A2B3C.mp4</name>
+        <fileSize>1715004</fileSize>
+        <startTime>
+        <year>2024</year>
+        <month>3</month>
+        <day>1</day>
+        <hour>10</hour>
+        <minute>15</minute>
+        <second>30</second>
+        </startTime>
+        <endTime>
+        <year>2024</year>
+        <month>3</month>
+        <day>1</day>
+        <hour>10</hour>
+        <minute>16</minute>
+        <second>12</second>
+        </endTime>
+        </FileInfo>
+        </FileInfoList>
+        </body>
+        "#
+    );
+    let b = BcXml::try_parse(sample.as_bytes()).unwrap();
+    match b {
+        BcXml {
+            file_info_list: Some(FileInfoList { file_info, .. }),
+            ..
+        } if file_info.len() == 1
+            && file_info[0].handle == Some(8)
+            && file_info[0].file_size == Some(1715004)
+            && file_info[0].end_time.map(|t| t.minute) == Some(16) => {}
+        _ => panic!(),
+    }
+}
@@ -18,19 +18,25 @@ mod connection;
 mod credentials;
 mod errors;
 mod floodlight;
+mod general;
+mod irled;
 mod keepalive;
 mod ledstate;
 mod link;
 mod login;
 mod logout;
 mod motion;
+mod osd;
 mod ping;
 mod pirstate;
+mod playback;
 mod ptz;
 mod pushinfo;
 mod reboot;
+mod record;
 mod resolution;
 mod services;
+mod shelter;
 mod siren;
 mod snap;
 mod stream;
@@ -40,19 +46,23 @@ mod talk;
 mod time;
 mod uid;
 mod version;
+mod wifi;
 
 pub(crate) use connection::*;
+pub use abilityinfo::{AbilityAccess, AbilityReport};
+pub use battery::{BatteryInfo, ChargeStatus};
 pub use credentials::*;
 pub use errors::Error;
 pub use ledstate::LightState;
 pub use login::MaxEncryption;
-pub use motion::{MotionData, MotionStatus};
+pub use motion::{AlarmTrigger, MotionData, MotionStatus, SmartEvent, SmartEventKind};
 pub use pirstate::PirState;
-pub use ptz::Direction;
+pub use playback::{FileDownload, RecordedFile};
+pub use ptz::{Direction, FocusDirection, ZoomDirection};
 pub use pushinfo::PhoneType;
 pub use resolution::*;
 use std::sync::Arc;
-pub use stream::{StreamData, StreamKind};
+pub use stream::{StreamData, StreamHooks, StreamKind};
 
 pub(crate) type Result<T> = std::result::Result<T, Error>;
 
@@ -74,6 +84,7 @@ pub struct BcCamera {
     // Certain commands such as logout require the username/pass in plain text.... why....???
     credentials: Credentials,
     abilities: RwLock<HashMap<String, ReadKind>>,
+    transport: Transport,
     #[allow(dead_code)]
     cancel: CancellationToken,
 }
@@ -102,6 +113,83 @@ pub struct BcCameraOpt {
     pub credentials: Credentials,
     /// Toggle debug print of underlying data
     pub debug: bool,
+    /// Tuning for the UDP transport
+    pub udp: UdpOptions,
+    /// Servers used for the remote, map and relay discovery
+    pub servers: DiscoveryServers,
+}
+
+/// The servers used to look up a UID, register for map discovery and relay the connection
+///
+/// Left empty these are the reolink servers. The register and relay servers are
+/// normally the ones that the lookup replies with
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(default)]
+pub struct DiscoveryServers {
+    /// `host` or `host:port` of the servers that are asked where a UID is registered,
+    /// the port defaults to 9999
+    pub lookup: Vec<String>,
+    /// `host:port` of the server used in place of the register server from the lookup
+    pub register: Option<String>,
+    /// `host:port` of the server used in place of the relay server from the lookup
+    pub relay: Option<String>,
+}
+
+/// Tuning of the UDP transport used for UID connections
+///
+/// The defaults follow the offical client but with larger buffers
+/// so that relayed connections can keep up with the main stream
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(default)]
+pub struct UdpOptions {
+    /// How often in ms to send an ACK to the camera
+    ///
+    /// The camera paces its sending on our ACKs, the offical client uses 10ms
+    pub ack_interval: u64,
+    /// How often in ms to resend packets that the camera has not ACKed
+    pub resend_interval: u64,
+    /// Size in bytes of the OS receive buffer for the socket, 0 leaves it at the OS default
+    ///
+    /// The OS may cap this value (e.g. `net.core.rmem_max` on linux)
+    pub recv_buffer: usize,
+    /// How many packets can be queued between the socket and the decoder
+    /// before they are dropped and left for the camera to resend
+    pub queue_size: usize,
+    /// How many times a packet is resent to the camera before the connection
+    /// is considered lost, 0 for no limit
+    pub max_resends: u32,
+    /// How long in ms to wait for the camera to resend a missing packet before
+    /// the connection is considered lost, 0 for no limit
+    ///
+    /// The packets form a single ordered stream so a missing packet cannot be
+    /// skipped. Giving up triggers a reconnect rather than a stalled stream
+    pub missing_timeout: u64,
+    /// Largest UDP packet in bytes that will be sent or requested from the camera
+    ///
+    /// This is advertised to the camera during discovery and it sizes its packets
+    /// to fit. Lower it on links with a small MTU (PPPoE, VPNs) where the large
+    /// packets would otherwise be silently dropped
+    pub mtu: u32,
+    /// UDP port that the camera listens on for local discovery, tried along
+    /// with the usual 2015 and 2018
+    ///
+    /// Needed when the camera's port has been forwarded to a different one
+    pub port: Option<u16>,
+}
+
+impl Default for UdpOptions {
+    fn default() -> Self {
+        Self {
+            ack_interval: 10,
+            resend_interval: 500,
+            recv_buffer: 2 * 1024 * 1024,
+            queue_size: 2000,
+            max_resends: 0,
+            missing_timeout: 0,
+            mtu: 1350,
+            port: None,
+        }
+    }
 }
 
 /// Used to choose the print format of various status messages like battery levels
@@ -130,16 +218,55 @@ pub enum ConnectionProtocol {
     Udp,
 }
 
+/// The transport that was used to reach the camera
+///
+/// These are ordered from the most to the least preferred
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Transport {
+    /// A direct TCP connection
+    Tcp,
+    /// UDP to a camera on the local network
+    Local,
+    /// UDP to the camera's address as registered with reolink
+    Remote,
+    /// UDP through a port mapped by the reolink servers
+    Map,
+    /// UDP relayed through the reolink servers
+    Relay,
+}
+
+impl std::fmt::Display for Transport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            Transport::Tcp => "tcp",
+            Transport::Local => "local",
+            Transport::Remote => "remote",
+            Transport::Map => "map",
+            Transport::Relay => "relay",
+        };
+        write!(f, "{}", name)
+    }
+}
+
 enum CameraLocation {
     Tcp(SocketAddr),
-    Udp(DiscoveryResult),
+    Udp(DiscoveryResult, Transport),
+}
+
+impl CameraLocation {
+    fn transport(&self) -> Transport {
+        match self {
+            CameraLocation::Tcp(_) => Transport::Tcp,
+            CameraLocation::Udp(_, transport) => *transport,
+        }
+    }
 }
 
 impl BcCamera {
     /// Try to connect to the camera via appropaite methods and return
     /// the location that should be used
     async fn find_camera(options: &BcCameraOpt) -> Result<CameraLocation> {
-        let discovery = Discovery::new().await?;
+        let discovery = Discovery::new(options.udp.mtu).await?;
         if let ConnectionProtocol::Tcp | ConnectionProtocol::TcpUdp = options.protocol {
             let mut sockets = vec![];
             match options.port {
@@ -173,7 +300,7 @@ impl BcCamera {
             (options.uid.as_ref(), options.protocol)
         {
             let mut sockets = vec![];
-            match options.port {
+            match options.udp.port.or(options.port) {
                 None | Some(2015) | Some(2018) => {
                     for addr in options.addrs.iter() {
                         sockets.push(SocketAddr::new(*addr, 2018));
@@ -202,7 +329,7 @@ impl BcCamera {
                 Ok(v) = async {
                     let uid_local = uid.clone();
                     info!("{}: Trying local discovery", options.name);
-                    let result = discovery.local(&uid_local, Some(sockets)).await;
+                    let result = discovery.local(&uid_local, Some(sockets), options.udp.port).await;
                     match result {
                         Ok(disc) => {
                             info!(
@@ -211,13 +338,13 @@ impl BcCamera {
                                 uid_local,
                                 disc.get_addr()
                             );
-                            Ok(CameraLocation::Udp(disc))
+                            Ok(CameraLocation::Udp(disc, Transport::Local))
                         },
                         Err(e) => Err(e)
                     }
                 }, if allow_local => Ok(v),
                 Ok(v) = async {
-                    let mut discovery = Discovery::new().await?;
+                    let mut discovery = Discovery::new(options.udp.mtu).await?;
                     let reg_result;
                     // Registration is looped as it seems that reolink
                     // only updates the registration lazily when someone attempts
@@ -230,7 +357,7 @@ impl BcCamera {
                     let max_retry: usize = options.max_discovery_retries;
                     loop {
                         tokio::task::yield_now().await;
-                        if let Ok(result) = discovery.get_registration(uid).await {
+                        if let Ok(result) = discovery.get_registration(uid, &options.servers).await {
                             reg_result = result;
                             break;
                         }
@@ -241,7 +368,7 @@ impl BcCamera {
                         retry += 1;
                         tokio::time::sleep(tokio::time::Duration::from_secs(1)).await;
                         // New discovery to get new client IDs
-                        discovery = Discovery::new().await?;
+                        discovery = Discovery::new(options.udp.mtu).await?;
                     };
                     tokio::select! {
                         Ok(v) = async {
@@ -258,7 +385,7 @@ impl BcCamera {
                                         uid_remote,
                                         disc.get_addr()
                                     );
-                                    Ok(CameraLocation::Udp(disc))
+                                    Ok(CameraLocation::Udp(disc, Transport::Remote))
                                 },
                                 Err(e) => Err(e)
                             }
@@ -276,7 +403,7 @@ impl BcCamera {
                                         uid_map,
                                         disc.get_addr()
                                     );
-                                    Ok(CameraLocation::Udp(disc))
+                                    Ok(CameraLocation::Udp(disc, Transport::Map))
                                 },
                                 Err(e) => Err(e),
                             }
@@ -294,7 +421,7 @@ impl BcCamera {
                                         uid_relay,
                                         disc.get_addr()
                                     );
-                                    Ok(CameraLocation::Udp(disc))
+                                    Ok(CameraLocation::Udp(disc, Transport::Relay))
                                 },
                                 Err(e) => Err(e),
                             }
@@ -328,20 +455,23 @@ impl BcCamera {
         let username: String = options.credentials.username.clone();
         let passwd: Option<String> = options.credentials.password.clone();
 
+        let location = BcCamera::find_camera(options).await?;
+        let transport = location.transport();
         let (sink, source): (BcConnSink, BcConnSource) = {
-            match BcCamera::find_camera(options).await? {
+            match location {
                 CameraLocation::Tcp(addr) => {
                     let (x, r) = TcpSource::new(addr, &username, passwd.as_ref(), options.debug)
                         .await?
                         .split();
                     (Box::new(x), Box::new(r))
                 }
-                CameraLocation::Udp(discovery) => {
+                CameraLocation::Udp(discovery, _) => {
                     let (x, r) = UdpSource::new_from_discovery(
                         discovery,
                         &username,
                         passwd.as_ref(),
                         options.debug,
+                        options.udp,
                     )
                     .await?
                     .split();
@@ -360,12 +490,26 @@ impl BcCamera {
             logged_in: AtomicBool::new(false),
             credentials: Credentials::new(username, passwd),
             abilities: Default::default(),
+            transport,
             cancel: CancellationToken::new(),
         };
         me.keepalive().await?;
         Ok(me)
     }
 
+    /// Find the best transport that can currently reach the camera without connecting to it
+    ///
+    /// This runs the same discovery as [`BcCamera::new`] and can be used to check
+    /// if a better transport has become available for a live camera
+    pub async fn probe_transport(options: &BcCameraOpt) -> Result<Transport> {
+        Ok(BcCamera::find_camera(options).await?.transport())
+    }
+
+    /// The transport that this camera is connected over
+    pub fn transport(&self) -> Transport {
+        self.transport
+    }
+
     /// This method will get a new message number and increment the message count atomically
     pub fn new_message_num(&self) -> u16 {
         self.message_num.fetch_add(1, Ordering::Relaxed)
@@ -1,6 +1,102 @@
 use super::{BcCamera, Error, Result};
 use crate::bc::{model::*, xml::*};
 use log::*;
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+/// How much of an ability the user is allowed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum AbilityAccess {
+    /// The setting can be read but not changed
+    #[serde(rename = "ro")]
+    ReadOnly,
+    /// The setting can be read and changed
+    #[serde(rename = "rw")]
+    ReadWrite,
+}
+
+/// The abilities of the user on this camera as reported by the camera
+///
+/// Abilities are grouped by the module that they are reported in such as
+/// `system`, `replay` or `PTZ`. These depend on the model, the firmware
+/// and the permissions of the user
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct AbilityReport {
+    /// The user that the abilities are for
+    pub username: String,
+    /// The abilities of each module by name
+    pub modules: BTreeMap<String, BTreeMap<String, AbilityAccess>>,
+}
+
+impl AbilityReport {
+    /// The access to an ability in any module, `None` when it is not supported
+    pub fn access(&self, name: &str) -> Option<AbilityAccess> {
+        self.modules
+            .values()
+            .filter_map(|abilities| abilities.get(name).copied())
+            .max_by_key(|access| matches!(access, AbilityAccess::ReadWrite))
+    }
+
+    /// If the ability can be read
+    pub fn can_read(&self, name: &str) -> bool {
+        self.access(name).is_some()
+    }
+
+    /// If the ability can be changed
+    pub fn can_write(&self, name: &str) -> bool {
+        self.access(name) == Some(AbilityAccess::ReadWrite)
+    }
+}
+
+impl From<&AbilityInfo> for AbilityReport {
+    fn from(info: &AbilityInfo) -> Self {
+        let tokens = [
+            ("system", info.system.as_ref()),
+            ("network", info.network.as_ref()),
+            ("alarm", info.alarm.as_ref()),
+            ("image", info.image.as_ref()),
+            ("video", info.video.as_ref()),
+            ("security", info.security.as_ref()),
+            ("replay", info.replay.as_ref()),
+            ("PTZ", info.ptz.as_ref()),
+            ("IO", info.io.as_ref()),
+            ("streaming", info.streaming.as_ref()),
+        ];
+
+        let mut modules = BTreeMap::new();
+        for (module, token) in tokens {
+            let Some(token) = token else {
+                continue;
+            };
+            let abilities: &mut BTreeMap<_, _> = modules.entry(module.to_string()).or_default();
+            // Values are like this: `general_rw, norm_rw, version_ro`
+            for ability in token
+                .sub_module
+                .iter()
+                .flat_map(|sub_module| sub_module.ability_value.split(','))
+            {
+                let Some((name, kind)) = ability.trim().rsplit_once('_') else {
+                    continue;
+                };
+                let access = match kind {
+                    "rw" => AbilityAccess::ReadWrite,
+                    "ro" => AbilityAccess::ReadOnly,
+                    _ => continue,
+                };
+                // Keep the most access when it is in more than one sub module
+                let entry = abilities.entry(name.to_string()).or_insert(access);
+                if access == AbilityAccess::ReadWrite {
+                    *entry = access;
+                }
+            }
+        }
+
+        Self {
+            username: info.username.clone(),
+            modules,
+        }
+    }
+}
 
 impl BcCamera {
     /// Get the ability info xml for the current user
@@ -54,6 +150,11 @@ impl BcCamera {
         }
     }
 
+    /// Get the abilities of the current user as a structured report
+    pub async fn get_ability_report(&self) -> Result<AbilityReport> {
+        Ok(AbilityReport::from(&self.get_abilityinfo().await?))
+    }
+
     /// Populate ability list of the camera
     pub async fn polulate_abilities(&self) -> Result<()> {
         let info = self.get_abilityinfo().await?;
@@ -63,53 +164,63 @@ impl BcCamera {
             debug!("Abilities: {}", info_str);
         }
 
-        let mut abilities: Vec<String> = vec![];
-
-        let mut tokens: Vec<Option<&AbilityInfoToken>> = vec![
-            info.system.as_ref(),
-            info.network.as_ref(),
-            info.alarm.as_ref(),
-            info.image.as_ref(),
-            info.video.as_ref(),
-            info.security.as_ref(),
-            info.replay.as_ref(),
-            info.ptz.as_ref(),
-            info.io.as_ref(),
-            info.streaming.as_ref(),
-        ];
-
-        for token in tokens.drain(..).flatten() {
-            for sub_module in token.sub_module.iter() {
-                abilities.extend(
-                    sub_module
-                        .ability_value
-                        .replace(' ', "")
-                        .split(',')
-                        .map(|s| s.to_string()),
-                );
-            }
-        }
-
+        let report = AbilityReport::from(&info);
         let mut locked_abilities = self.abilities.write().await;
-        for ability in abilities.iter() {
-            let mut abilities_ro = ability.split('_').map(|s| s.to_string());
-            if let (Some(ability_name), Some(ability_kind)) =
-                (abilities_ro.next(), abilities_ro.next())
-            {
-                match ability_kind.as_str() {
-                    "rw" => {
-                        locked_abilities.insert(ability_name, super::ReadKind::ReadWrite);
-                    }
-                    "ro" => {
-                        locked_abilities.insert(ability_name, super::ReadKind::ReadOnly);
-                    }
-                    _ => {
-                        continue;
-                    }
-                }
+        for (name, access) in report.modules.values().flatten() {
+            let kind = match access {
+                AbilityAccess::ReadWrite => super::ReadKind::ReadWrite,
+                AbilityAccess::ReadOnly => super::ReadKind::ReadOnly,
+            };
+            // Keep the most access when it is in more than one module
+            if !matches!(locked_abilities.get(name), Some(super::ReadKind::ReadWrite)) {
+                locked_abilities.insert(name.clone(), kind);
             }
         }
 
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ability_report() {
+        let info = AbilityInfo {
+            username: "admin".to_string(),
+            system: Some(AbilityInfoToken {
+                sub_module: vec![
+                    AbilityInfoSubModule {
+                        channel_id: None,
+                        ability_value: "general_rw, norm_rw, version_ro".to_string(),
+                    },
+                    AbilityInfoSubModule {
+                        channel_id: Some(0),
+                        ability_value: "version_rw,ledState_ro,bogus".to_string(),
+                    },
+                ],
+            }),
+            replay: Some(AbilityInfoToken {
+                sub_module: vec![AbilityInfoSubModule {
+                    channel_id: Some(0),
+                    ability_value: "replay_ro, dog_cat_rw".to_string(),
+                }],
+            }),
+            ..Default::default()
+        };
+
+        let report = AbilityReport::from(&info);
+        assert_eq!(report.username, "admin");
+        assert_eq!(
+            report.modules.keys().collect::<Vec<_>>(),
+            vec!["replay", "system"]
+        );
+        assert_eq!(report.access("version"), Some(AbilityAccess::ReadWrite));
+        assert!(report.can_read("ledState"));
+        assert!(!report.can_write("ledState"));
+        assert!(report.can_write("dog_cat"));
+        assert!(!report.can_read("bogus"));
+        assert!(!report.can_read("PTZ"));
+    }
+}
@@ -7,9 +7,87 @@
 
 use super::{BcCamera, PrintFormat, Result};
 use crate::{
-    bc::{model::*, xml::BatteryInfo},
+    bc::{model::*, xml},
     Error,
 };
+use serde::Serialize;
+
+/// Whether the battery is being charged
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ChargeStatus {
+    /// The battery is not being charged
+    NotCharging,
+    /// The battery is being charged
+    Charging,
+    /// The battery is charged and still connected to power
+    ChargeComplete,
+    /// A status that is not yet known to neolink
+    Other(String),
+}
+
+impl ChargeStatus {
+    /// The status as `not_charging`, `charging`, `charge_complete` or as
+    /// reported by the camera when it is not known
+    pub fn as_str(&self) -> &str {
+        match self {
+            Self::NotCharging => "not_charging",
+            Self::Charging => "charging",
+            Self::ChargeComplete => "charge_complete",
+            Self::Other(other) => other.as_str(),
+        }
+    }
+}
+
+impl Serialize for ChargeStatus {
+    fn serialize<S>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        serializer.serialize_str(self.as_str())
+    }
+}
+
+impl From<&str> for ChargeStatus {
+    fn from(status: &str) -> Self {
+        match status {
+            "none" => Self::NotCharging,
+            "charging" => Self::Charging,
+            "chargeComplete" => Self::ChargeComplete,
+            other => Self::Other(other.to_string()),
+        }
+    }
+}
+
+/// The battery level and charging state of the camera
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct BatteryInfo {
+    /// % charge from 0-100
+    pub percent: u32,
+    /// Whether the battery is being charged
+    pub charging: ChargeStatus,
+    /// What is connected to the charging port, `None` when nothing is.
+    /// Known values: `"solarPanel"`
+    pub adapter: Option<String>,
+    /// If the camera reports that the battery is low
+    pub low_power: bool,
+    /// Temperature of the battery in °C
+    pub temperature: i32,
+}
+
+impl From<&xml::BatteryInfo> for BatteryInfo {
+    fn from(info: &xml::BatteryInfo) -> Self {
+        Self {
+            percent: info.battery_percent,
+            charging: ChargeStatus::from(info.charge_status.as_str()),
+            adapter: match info.adapter_status.as_str() {
+                "" | "none" => None,
+                adapter => Some(adapter.to_string()),
+            },
+            low_power: info.low_power != 0,
+            temperature: info.temperature,
+        }
+    }
+}
 
 impl BcCamera {
     /// Create a handller to respond to battery messages
@@ -77,8 +155,13 @@ impl BcCamera {
         Ok(())
     }
 
+    /// Requests the current battery level and charging state of the camera
+    pub async fn get_battery_info(&self) -> Result<BatteryInfo> {
+        Ok(BatteryInfo::from(&self.battery_info().await?))
+    }
+
     /// Requests the current battery status of the camera
-    pub async fn battery_info(&self) -> Result<BatteryInfo> {
+    pub async fn battery_info(&self) -> Result<xml::BatteryInfo> {
         let connection = self.get_connection();
 
         let msg_num = self.new_message_num();
@@ -129,3 +212,39 @@ impl BcCamera {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_battery_info() {
+        let xml = xml::BatteryInfo {
+            channel_id: 0,
+            charge_status: "charging".to_string(),
+            adapter_status: "solarPanel".to_string(),
+            voltage: 8123,
+            current: 120,
+            temperature: 21,
+            battery_percent: 87,
+            low_power: 0,
+            battery_version: 2,
+        };
+        let info = BatteryInfo::from(&xml);
+        assert_eq!(info.percent, 87);
+        assert_eq!(info.charging, ChargeStatus::Charging);
+        assert_eq!(info.adapter.as_deref(), Some("solarPanel"));
+        assert!(!info.low_power);
+
+        let xml = xml::BatteryInfo {
+            charge_status: "none".to_string(),
+            adapter_status: "none".to_string(),
+            low_power: 1,
+            ..xml
+        };
+        let info = BatteryInfo::from(&xml);
+        assert_eq!(info.charging, ChargeStatus::NotCharging);
+        assert_eq!(info.adapter, None);
+        assert!(info.low_power);
+    }
+}
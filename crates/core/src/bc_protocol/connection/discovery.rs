@@ -4,7 +4,7 @@
 //!
 use super::DiscoveryResult;
 use crate::bc::model::*;
-use crate::bc_protocol::{md5_string, Md5Trunc, TcpSource};
+use crate::bc_protocol::{md5_string, DiscoveryServers, Md5Trunc, TcpSource};
 use crate::bcudp::codex::BcUdpCodex;
 use crate::bcudp::model::*;
 use crate::bcudp::xml::*;
@@ -58,7 +58,6 @@ struct UidLookupResults {
     relay: SocketAddr,
 }
 
-const MTU: u32 = 1350;
 lazy_static! {
     static ref P2P_RELAY_HOSTNAMES: [&'static str; 12] = [
         "p2p.reolink.com",
@@ -102,12 +101,22 @@ pub(crate) struct Discoverer {
     handlers: Handlers,
     local_addr: SocketAddr,
     cancel: CancellationToken,
+    /// The MTU we advertise to the camera, it sizes its packets to fit
+    mtu: u32,
 }
 
 fn valid_ip(ip: &str) -> bool {
     !ip.is_empty() && matches!(ip.parse::<Ipv4Addr>(), Ok(addr) if addr.octets()[3] != 0)
 }
 
+/// Resolve a `host` or `host:port` using the default port if none is given
+fn resolve(host: &str, default_port: u16) -> Vec<SocketAddr> {
+    host.to_socket_addrs()
+        .or_else(|_| (host, default_port).to_socket_addrs())
+        .map(|i| i.collect())
+        .unwrap_or_default()
+}
+
 fn valid_port(port: u16) -> bool {
     port != 0
 }
@@ -117,7 +126,7 @@ fn valid_addr(ip_port: &IpPort) -> bool {
 }
 
 impl Discoverer {
-    async fn new() -> Result<Discoverer> {
+    async fn new(mtu: u32) -> Result<Discoverer> {
         let socket = Arc::new(connect().await?);
         let local_addr = socket.local_addr()?;
         let inner: ArcFramedSocket = UdpFramed::new(socket.clone(), BcUdpCodex::new());
@@ -201,6 +210,7 @@ impl Discoverer {
             handlers,
             local_addr,
             cancel,
+            mtu,
         })
     }
 
@@ -356,7 +366,7 @@ impl Discoverer {
                 uid: uid.to_string(),
                 cli: ClientList { port: port as u32 },
                 cid: client_id,
-                mtu: MTU,
+                mtu: self.mtu,
                 debug: false,
                 os: "MAC".to_string(),
             }),
@@ -406,27 +416,53 @@ impl Discoverer {
     async fn uid_lookup_all<'a>(
         &'a self,
         uid: &'a str,
+        servers: &DiscoveryServers,
     ) -> Result<impl Stream<Item = UidLookupResults> + 'a> {
+        let thread_servers = servers.clone();
         let task = tokio::task::spawn_blocking(move || {
+            let servers = thread_servers;
             let mut addrs = vec![];
-            for p2p_relay in P2P_RELAY_HOSTNAMES.iter() {
-                addrs.append(
-                    &mut format!("{}:9999", p2p_relay)
-                        .to_socket_addrs()
-                        .map(|i| i.collect::<Vec<SocketAddr>>())
-                        .unwrap_or_else(|_| vec![]),
-                );
+            if servers.lookup.is_empty() {
+                for p2p_relay in P2P_RELAY_HOSTNAMES.iter() {
+                    addrs.append(&mut resolve(p2p_relay, 9999));
+                }
+            } else {
+                for host in servers.lookup.iter() {
+                    addrs.append(&mut resolve(host, 9999));
+                }
             }
-            addrs
+            let register = servers
+                .register
+                .as_ref()
+                .and_then(|host| resolve(host, 9999).first().copied());
+            let relay = servers
+                .relay
+                .as_ref()
+                .and_then(|host| resolve(host, 9999).first().copied());
+            (addrs, register, relay)
         });
-        let mut addrs = timeout(*MAXIMUM_WAIT, task).await??;
+        let (mut addrs, register, relay) = timeout(*MAXIMUM_WAIT, task).await??;
         trace!("Uid lookup to: {:?}", addrs);
+        if servers.register.is_some() && register.is_none() {
+            warn!(
+                "Could not resolve the register server {:?}",
+                servers.register
+            );
+        }
+        if servers.relay.is_some() && relay.is_none() {
+            warn!("Could not resolve the relay server {:?}", servers.relay);
+        }
 
         Ok(addrs
             .drain(..)
             .map(|addr| self.uid_lookup(uid, addr))
             .collect::<FuturesUnordered<_>>()
-            .filter_map(|f| async { f.ok() }))
+            .filter_map(move |f| async move {
+                f.ok().map(|lookup| UidLookupResults {
+                    reg: register.unwrap_or(lookup.reg),
+                    relay: relay.unwrap_or(lookup.relay),
+                })
+            }))
     }
     /// This function will contact the p2p relay servers
     ///
@@ -638,7 +674,7 @@ impl Discoverer {
                 conn: "local".to_string(),
                 cid: register_result.client_id,
                 did: local_did,
-                mtu: MTU,
+                mtu: self.mtu,
             }),
         };
 
@@ -749,7 +785,7 @@ impl Discoverer {
                 conn: "map".to_string(),
                 cid: register_result.client_id,
                 did: local_did,
-                mtu: MTU,
+                mtu: self.mtu,
             }),
         };
 
@@ -825,7 +861,7 @@ impl Discoverer {
             payload: UdpXml::C2dT(C2dT {
                 sid: register_result.sid,
                 cid: register_result.client_id,
-                mtu: MTU,
+                mtu: self.mtu,
                 conn: "local".to_string(),
             }),
         };
@@ -888,7 +924,7 @@ impl Discoverer {
             payload: UdpXml::C2dT(C2dT {
                 sid: register_result.sid,
                 cid: register_result.client_id,
-                mtu: MTU,
+                mtu: self.mtu,
                 conn: "relay".to_string(),
             }),
         };
@@ -1031,15 +1067,19 @@ pub(crate) struct Discovery {
 }
 
 impl Discovery {
-    pub(crate) async fn new() -> Result<Self> {
+    pub(crate) async fn new(mtu: u32) -> Result<Self> {
         Ok(Self {
-            discoverer: Discoverer::new().await?,
+            discoverer: Discoverer::new(mtu).await?,
             client_id: generate_cid(),
         })
     }
 
-    pub(crate) async fn get_registration(&self, uid: &str) -> Result<RegisterResult> {
-        let lookups = self.discoverer.uid_lookup_all(uid).await?;
+    pub(crate) async fn get_registration(
+        &self,
+        uid: &str,
+        servers: &DiscoveryServers,
+    ) -> Result<RegisterResult> {
+        let lookups = self.discoverer.uid_lookup_all(uid, servers).await?;
 
         let checked_reg = Arc::new(RwLock::new(HashSet::new()));
         let reg_result = Box::pin(
@@ -1115,8 +1155,15 @@ impl Discovery {
         &self,
         uid: &str,
         mut optional_addrs: Option<Vec<SocketAddr>>,
+        optional_port: Option<u16>,
     ) -> Result<DiscoveryResult> {
-        let mut dests = get_broadcasts(&[2015, 2018])?;
+        let mut ports = vec![2015, 2018];
+        if let Some(port) = optional_port {
+            if !ports.contains(&port) {
+                ports.push(port);
+            }
+        }
+        let mut dests = get_broadcasts(&ports)?;
         if let Some(mut optional_addrs) = optional_addrs.take() {
             debug!("Also sending to {:?}", optional_addrs);
             dests.append(&mut optional_addrs);
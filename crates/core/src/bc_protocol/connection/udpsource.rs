@@ -3,7 +3,7 @@ use crate::bc::codex::BcCodex;
 use crate::bc::model::*;
 use crate::bcudp::codex::BcUdpCodex;
 use crate::bcudp::{model::*, xml::*};
-use crate::{Credentials, Error, Result};
+use crate::{bc_protocol::UdpOptions, Credentials, Error, Result};
 use delegate::delegate;
 use futures::{
     sink::{Sink, SinkExt},
@@ -21,7 +21,7 @@ use tokio::{
     net::UdpSocket,
     sync::{
         mpsc::channel,
-        watch::{channel as watch, Sender as WatchSender},
+        watch::{channel as watch, Receiver as WatchReceiver, Sender as WatchSender},
     },
     task::JoinSet,
     time::{interval, sleep, Duration, Instant, Interval},
@@ -34,8 +34,9 @@ use tokio_util::{
     udp::UdpFramed,
 };
 
-const MTU: usize = 1350;
 const UDPDATA_HEADER_SIZE: usize = 20;
+/// The most skipped packets that are remembered to tell a resend from a late packet
+const MAX_GAPS: u32 = 4096;
 
 pub(crate) type InnerFramed = Framed<Compat<IntoAsyncRead<UdpPayloadSource>>, BcCodex>;
 pub(crate) struct UdpSource {
@@ -51,11 +52,12 @@ impl UdpSource {
         username: T,
         password: Option<U>,
         debug: bool,
+        options: UdpOptions,
     ) -> Result<Self> {
         let stream = Arc::new(connect().await?);
 
         Self::new_from_socket(
-            stream, addr, client_id, camera_id, username, password, debug,
+            stream, addr, client_id, camera_id, username, password, debug, options,
         )
         .await
     }
@@ -64,6 +66,7 @@ impl UdpSource {
         username: T,
         password: Option<U>,
         debug: bool,
+        options: UdpOptions,
     ) -> Result<Self> {
         // Ensure that the discovery keep alive are all stopped here
         // We now handle all coms in UdpSource
@@ -76,10 +79,12 @@ impl UdpSource {
             username,
             password,
             debug,
+            options,
         )
         .await
     }
 
+    #[allow(clippy::too_many_arguments)]
     pub(crate) async fn new_from_socket<T: Into<String>, U: Into<String>>(
         stream: Arc<UdpSocket>,
         addr: SocketAddr,
@@ -88,9 +93,13 @@ impl UdpSource {
         username: T,
         password: Option<U>,
         debug: bool,
+        options: UdpOptions,
     ) -> Result<Self> {
+        set_recv_buffer(&stream, options.recv_buffer);
         let bcudp_source = BcUdpSource::new_from_socket(stream, addr).await?;
-        let payload_source = bcudp_source.into_payload_source(client_id, camera_id).await;
+        let payload_source = bcudp_source
+            .into_payload_source(client_id, camera_id, options)
+            .await;
         let async_read = payload_source.into_async_read().compat();
         let codex = if debug {
             BcCodex::new_with_debug(Credentials::new(username, password))
@@ -174,8 +183,9 @@ impl BcUdpSource {
         self,
         client_id: i32,
         camera_id: i32,
+        options: UdpOptions,
     ) -> UdpPayloadSource {
-        UdpPayloadSource::new(self, client_id, camera_id).await
+        UdpPayloadSource::new(self, client_id, camera_id, options).await
     }
 }
 
@@ -268,6 +278,98 @@ impl AckLatency {
     }
 }
 
+/// Counts of how the packets fared, these are logged
+/// periodically and when the connection closes
+#[derive(Default, Debug)]
+struct UdpStats {
+    /// Packets we resent to the camera
+    resent: u64,
+    /// Packets from the camera that filled a gap after we asked for them again
+    recovered: u64,
+    /// Packets from the camera that filled a gap before we asked for them again
+    reordered: u64,
+    /// Packets from the camera that we already had
+    duplicates: u64,
+    /// Packets that were given up on
+    lost: u64,
+}
+
+/// Keeps track of the packets from the camera that were skipped over
+/// to tell a resent packet from a late one and to notice lost packets
+#[derive(Default, Debug)]
+struct GapTracker {
+    highest_recieved: Option<u32>,
+    /// The packets that were skipped over and when that was seen
+    gaps: BTreeMap<u32, Instant>,
+    /// The packet we are waiting on and when we started waiting for it
+    missing_since: Option<(u32, Instant)>,
+}
+
+impl GapTracker {
+    /// A packet that we did not have yet arrived from the camera
+    ///
+    /// `ack_sent` is when an ack, which asks the camera to resend
+    /// the gaps, last went out
+    fn recieved(
+        &mut self,
+        packet_id: u32,
+        packets_want: u32,
+        ack_sent: Instant,
+        now: Instant,
+        stats: &mut UdpStats,
+    ) {
+        match self.highest_recieved {
+            Some(highest) if packet_id < highest => {
+                // It filled a gap, it was only resent if an ack
+                // asking for it went out after the gap was seen
+                match self.gaps.remove(&packet_id) {
+                    Some(seen) if ack_sent > seen => {
+                        stats.recovered += 1;
+                    }
+                    _ => {
+                        stats.reordered += 1;
+                    }
+                }
+            }
+            highest => {
+                let next = highest.map(|highest| highest + 1).unwrap_or(packets_want);
+                // A bad packet id should not fill the memory
+                for skipped in next.max(packet_id.saturating_sub(MAX_GAPS))..packet_id {
+                    self.gaps.insert(skipped, now);
+                }
+                while self.gaps.len() > MAX_GAPS as usize {
+                    self.gaps.pop_first();
+                }
+                self.highest_recieved = Some(packet_id);
+            }
+        }
+    }
+
+    /// The packets before `packets_want` were passed on, `holding` is
+    /// true if there are packets after a gap that are waiting for it
+    fn consumed(&mut self, packets_want: u32, holding: bool, now: Instant) {
+        self.gaps.retain(|&packet_id, _| packet_id >= packets_want);
+        if !holding {
+            self.missing_since = None;
+        } else if !matches!(self.missing_since, Some((packet_id, _)) if packet_id == packets_want) {
+            // There is a gap before the packets we are holding
+            self.missing_since = Some((packets_want, now));
+        }
+    }
+
+    /// True and the packet is counted as lost if the packet that we are
+    /// waiting on has been missing for longer than `timeout`
+    fn check_missing(&mut self, timeout: Duration, now: Instant, stats: &mut UdpStats) -> bool {
+        match self.missing_since {
+            Some((_, missing_since)) if now.saturating_duration_since(missing_since) > timeout => {
+                stats.lost += 1;
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
 pub(crate) struct UdpPayloadSource {
     inner_stream: Pin<Box<ReceiverStream<IoResult<Vec<u8>>>>>,
     inner_sink: PollSender<Vec<u8>>,
@@ -299,8 +401,14 @@ struct UdpPayloadInner {
     camera_id: i32,
     packets_sent: u32,
     packets_want: u32,
-    sent: BTreeMap<u32, UdpData>,
+    sent: BTreeMap<u32, (UdpData, u32)>,
     recieved: BTreeMap<u32, Vec<u8>>,
+    gaps: GapTracker,
+    /// When an ack was last sent, an ack asks the camera to resend the gaps
+    ack_sent: WatchReceiver<Instant>,
+    options: UdpOptions,
+    stats: UdpStats,
+    last_stats_log: Instant,
     /// Offical Client does ack every 10ms if we don't also do this the camera
     /// seems to think we have a poor connection and will abort
     /// This `ack_interval` controls how ofen we do this
@@ -318,6 +426,7 @@ impl UdpPayloadInner {
         thread_sink: ReceiverStream<Vec<u8>>,
         client_id: i32,
         camera_id: i32,
+        options: UdpOptions,
     ) -> Self {
         let mut set = JoinSet::new();
         let camera_addr = inner.addr;
@@ -328,8 +437,9 @@ impl UdpPayloadInner {
         // In order to achieve this we use dedicated threads for ACK
         // and the socket
 
-        let (socket_in_tx, socket_in_rx) = channel::<BcUdp>(500);
-        let (socket_out_tx, socket_out_rx) = channel::<(BcUdp, SocketAddr)>(500);
+        let queue_size = options.queue_size.max(1);
+        let (socket_in_tx, socket_in_rx) = channel::<BcUdp>(queue_size);
+        let (socket_out_tx, socket_out_rx) = channel::<(BcUdp, SocketAddr)>(queue_size);
         // let (mut socket_tx, mut socket_rx) = inner.split();
 
         // Send/Recv on the socket
@@ -358,7 +468,17 @@ impl UdpPayloadInner {
                                 let packet = packet.ok_or(Error::DroppedConnection)??;
                                 recv_timeout.as_mut().reset(Instant::now() + Duration::from_secs(TIME_OUT));
                                 // let packet = socket_rx.next().await.ok_or(Error::DroppedConnection)??;
-                                socket_out_tx.try_send(packet).map_err(|_| Error::DroppedConnection)?;
+                                match socket_out_tx.try_send(packet) {
+                                    Ok(()) => {},
+                                    Err(tokio::sync::mpsc::error::TrySendError::Full(_)) => {
+                                        // We are behind, drop it and let the camera resend
+                                        // rather than blocking the socket
+                                        log::trace!("UDP queue full dropping packet");
+                                    },
+                                    Err(tokio::sync::mpsc::error::TrySendError::Closed(_)) => {
+                                        break Err(Error::DroppedConnection);
+                                    },
+                                }
                                 continue;
                             },
                             packet = socket_in_rx.next() => {
@@ -373,6 +493,7 @@ impl UdpPayloadInner {
                                         // Seems to happen with network reconnects like over
                                         // a lossy cellular network
                                         let stream = Arc::new(tokio::time::timeout(tokio::time::Duration::from_millis(250), connect_try_port(inner.inner.get_ref().local_addr()?.port())).await.map_err(|_| Error::DroppedConnection)??);
+                                        set_recv_buffer(&stream, options.recv_buffer);
                                         inner = tokio::time::timeout(tokio::time::Duration::from_millis(250), BcUdpSource::new_from_socket(stream, inner.addr)).await.map_err(|_| Error::DroppedConnection)??;
 
                                         // Inform the camera that we are the same client
@@ -407,9 +528,10 @@ impl UdpPayloadInner {
 
         // Queue up ack packets
         let ack_cancel = cancel.clone();
-        let mut ack_interval = interval(Duration::from_millis(10)); // Offical Client does ack every 10ms
+        let mut ack_interval = interval(Duration::from_millis(options.ack_interval.max(1))); // Offical Client does ack every 10ms
         ack_interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
         let (ack_tx, ack_rx) = watch(UdpAck::empty(camera_id));
+        let (ack_sent_tx, ack_sent_rx) = watch(Instant::now());
         let ack_socket_in_tx = socket_in_tx.clone();
         set.spawn(async move {
             tokio::select! {
@@ -423,6 +545,7 @@ impl UdpPayloadInner {
                         log::trace!("send ack");
                         let ack_packet = BcUdp::Ack(ack_rx.borrow().clone());
                         ack_socket_in_tx.send(ack_packet).await?;
+                        ack_sent_tx.send_replace(Instant::now());
                     }
                 } => v,
             }
@@ -471,7 +594,12 @@ impl UdpPayloadInner {
             packets_want: 0,
             sent: Default::default(),
             recieved: Default::default(),
-            resend_interval: interval(Duration::from_millis(500)), // Offical Client does resend every 500ms
+            gaps: Default::default(),
+            ack_sent: ack_sent_rx,
+            options,
+            stats: Default::default(),
+            last_stats_log: Instant::now(),
+            resend_interval: interval(Duration::from_millis(options.resend_interval.max(1))), // Offical Client does resend every 500ms
             ack_latency: Default::default(),
             cancel,
             set,
@@ -482,10 +610,22 @@ impl UdpPayloadInner {
         tokio::select! {
             _ = self.resend_interval.tick() => {
                 log::trace!("Resend Tick");
-                for (_, resend) in self.sent.iter() {
+                for (packet_id, (resend, count)) in self.sent.iter_mut() {
+                    *count += 1;
+                    if self.options.max_resends > 0 && *count > self.options.max_resends {
+                        self.stats.lost += 1;
+                        log::debug!("Packet {} was not acknowledged after {} resends", packet_id, self.options.max_resends);
+                        return Err(Error::DroppedConnection);
+                    }
+                    self.stats.resent += 1;
                     self.socket_in.feed(BcUdp::Data(resend.clone())).await?;
                 }
                 self.ack_tx.send_replace(self.build_send_ack()); // Ensure we update the ack packet sometimes too
+                self.check_missing()?;
+                if self.last_stats_log.elapsed() > Duration::from_secs(60) {
+                    log::debug!("UDP Stats: {:?}", self.stats);
+                    self.last_stats_log = Instant::now();
+                }
                 Result::Ok(())
             },
             v = self.thread_sink.next() => {
@@ -497,14 +637,17 @@ impl UdpPayloadInner {
                 }
                 let item = v.ok_or(Error::DroppedConnection)?;
 
-                for chunk in item.chunks(MTU - UDPDATA_HEADER_SIZE) {
+                let max_payload = (self.options.mtu as usize)
+                    .saturating_sub(UDPDATA_HEADER_SIZE)
+                    .max(1);
+                for chunk in item.chunks(max_payload) {
                     let udp_data = UdpData {
                         connection_id: self.camera_id,
                         packet_id: self.packets_sent,
                         payload: chunk.to_vec(),
                     };
                     self.packets_sent += 1;
-                    self.sent.insert(udp_data.packet_id, udp_data.clone());
+                    self.sent.insert(udp_data.packet_id, (udp_data.clone(), 0));
                     self.socket_in.feed(BcUdp::Data(udp_data)).await?;
                 }
                 Ok(())
@@ -542,10 +685,20 @@ impl UdpPayloadInner {
                         BcUdp::Data(data)  => {
                             if data.connection_id == self.client_id {
                                 let packet_id = data.packet_id;
-                                if packet_id >= self.packets_want {
+                                if packet_id >= self.packets_want && !self.recieved.contains_key(&packet_id) {
                                     // error!("packets_want: {}", this.packets_want);
+                                    let ack_sent = *self.ack_sent.borrow();
+                                    self.gaps.recieved(
+                                        packet_id,
+                                        self.packets_want,
+                                        ack_sent,
+                                        Instant::now(),
+                                        &mut self.stats,
+                                    );
                                     self.recieved.insert(packet_id, data.payload);
                                     self.ack_tx.send_replace(self.build_send_ack());
+                                } else {
+                                    self.stats.duplicates += 1;
                                 }
                             }
                         },
@@ -562,6 +715,8 @@ impl UdpPayloadInner {
             self.thread_stream.feed(Ok(payload)).await?;
         }
         log::trace!("recieved: {}", self.recieved.len());
+        self.gaps
+            .consumed(self.packets_want, !self.recieved.is_empty(), Instant::now());
         log::trace!("Flush");
         self.socket_in.flush().await?;
         self.thread_stream.flush().await?;
@@ -569,6 +724,27 @@ impl UdpPayloadInner {
         Ok(())
     }
 
+    /// Give up on the connection if a missing packet has not
+    /// been resent within the `missing_timeout`
+    fn check_missing(&mut self) -> Result<()> {
+        if self.options.missing_timeout == 0 {
+            return Ok(());
+        }
+        if self.gaps.check_missing(
+            Duration::from_millis(self.options.missing_timeout),
+            Instant::now(),
+            &mut self.stats,
+        ) {
+            log::debug!(
+                "Packet {} was not resent by the camera within {}ms",
+                self.packets_want,
+                self.options.missing_timeout
+            );
+            return Err(Error::DroppedConnection);
+        }
+        Ok(())
+    }
+
     fn build_send_ack(&self) -> UdpAck {
         if self.packets_want > 0 {
             let mut first_missing: u32 = self.packets_want;
@@ -626,6 +802,7 @@ impl UdpPayloadInner {
 impl Drop for UdpPayloadInner {
     fn drop(&mut self) {
         log::trace!("Drop UdpPayloadInner");
+        log::debug!("UDP Stats: {:?}", self.stats);
         self.cancel.cancel();
         let _gt = tokio::runtime::Handle::current().enter();
         let mut set = std::mem::take(&mut self.set);
@@ -636,7 +813,12 @@ impl Drop for UdpPayloadInner {
     }
 }
 impl UdpPayloadSource {
-    async fn new(inner: BcUdpSource, client_id: i32, camera_id: i32) -> Self {
+    async fn new(
+        inner: BcUdpSource,
+        client_id: i32,
+        camera_id: i32,
+        options: UdpOptions,
+    ) -> Self {
         let (inner_sink, thread_sink) = channel(100);
         let (thread_stream, inner_stream) = channel(100);
 
@@ -646,6 +828,7 @@ impl UdpPayloadSource {
             ReceiverStream::new(thread_sink),
             client_id,
             camera_id,
+            options,
         );
         let cancel_token = tokio_util::sync::CancellationToken::new();
 
@@ -765,6 +948,27 @@ impl futures::AsyncWrite for UdpPayloadSource {
     }
 }
 
+/// Grow the OS receive buffer so that bursts of packets
+/// are not lost while we are busy
+fn set_recv_buffer(socket: &UdpSocket, size: usize) {
+    if size == 0 {
+        return;
+    }
+    let sock_ref = socket2::SockRef::from(socket);
+    if let Err(e) = sock_ref.set_recv_buffer_size(size) {
+        log::debug!("Could not set the UDP receive buffer: {:?}", e);
+    }
+    if let Ok(actual) = sock_ref.recv_buffer_size() {
+        if actual < size {
+            log::debug!(
+                "UDP receive buffer is {} bytes, the OS capped the requested {} bytes",
+                actual,
+                size
+            );
+        }
+    }
+}
+
 /// Helper to create a UdpStream
 async fn connect() -> Result<UdpSocket> {
     let mut ports: Vec<u16> = (53500..54000).collect();
@@ -800,3 +1004,113 @@ async fn connect_try_port(port: u16) -> Result<UdpSocket> {
 
     Ok(socket)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_gap_insertion() {
+        let now = Instant::now();
+        let mut stats = UdpStats::default();
+        let mut tracker = GapTracker::default();
+
+        tracker.recieved(0, 0, now, now, &mut stats);
+        assert!(tracker.gaps.is_empty());
+        // 1, 2 and 3 were skipped
+        tracker.recieved(4, 1, now, now, &mut stats);
+        assert_eq!(tracker.gaps.keys().copied().collect::<Vec<_>>(), [1, 2, 3]);
+        assert_eq!(tracker.highest_recieved, Some(4));
+        // Nothing is skipped between the highest and the next
+        tracker.recieved(5, 1, now, now, &mut stats);
+        assert_eq!(tracker.gaps.len(), 3);
+        // The first packet can be skipped too
+        let mut tracker = GapTracker::default();
+        tracker.recieved(2, 0, now, now, &mut stats);
+        assert_eq!(tracker.gaps.keys().copied().collect::<Vec<_>>(), [0, 1]);
+    }
+
+    #[test]
+    fn test_gap_recovered() {
+        let seen = Instant::now();
+        let ack_sent = seen + Duration::from_millis(10);
+        let mut stats = UdpStats::default();
+        let mut tracker = GapTracker::default();
+
+        tracker.recieved(3, 0, seen, seen, &mut stats);
+        // No ack asked for 0 since the gap was seen so it was only late
+        tracker.recieved(0, 0, seen, seen, &mut stats);
+        assert_eq!(stats.reordered, 1);
+        assert_eq!(stats.recovered, 0);
+        // An ack went out after the gap was seen so it was resent
+        tracker.recieved(1, 0, ack_sent, ack_sent, &mut stats);
+        assert_eq!(stats.recovered, 1);
+        assert_eq!(tracker.gaps.keys().copied().collect::<Vec<_>>(), [2]);
+    }
+
+    #[test]
+    fn test_gap_eviction() {
+        let now = Instant::now();
+        let mut stats = UdpStats::default();
+        let mut tracker = GapTracker::default();
+
+        // A single jump only remembers the last MAX_GAPS packets
+        tracker.recieved(MAX_GAPS * 2, 0, now, now, &mut stats);
+        assert_eq!(tracker.gaps.len(), MAX_GAPS as usize);
+        assert_eq!(tracker.gaps.keys().next(), Some(&MAX_GAPS));
+        // Later jumps forget the oldest gaps
+        tracker.recieved(MAX_GAPS * 2 + 11, 0, now, now, &mut stats);
+        assert_eq!(tracker.gaps.len(), MAX_GAPS as usize);
+        assert_eq!(tracker.gaps.keys().next(), Some(&(MAX_GAPS + 10)));
+        assert_eq!(tracker.gaps.keys().next_back(), Some(&(MAX_GAPS * 2 + 10)));
+        // A forgotten gap that is filled is counted as late
+        tracker.recieved(MAX_GAPS, 0, now, now, &mut stats);
+        assert_eq!(stats.reordered, 1);
+    }
+
+    #[test]
+    fn test_gap_consumed() {
+        let now = Instant::now();
+        let later = now + Duration::from_millis(10);
+        let mut stats = UdpStats::default();
+        let mut tracker = GapTracker::default();
+
+        tracker.recieved(5, 0, now, now, &mut stats);
+        tracker.consumed(0, true, now);
+        assert_eq!(tracker.missing_since, Some((0, now)));
+        // Still waiting on the same packet so the time is kept
+        tracker.consumed(0, true, later);
+        assert_eq!(tracker.missing_since, Some((0, now)));
+        // Packets 0 to 2 arrive and are passed on
+        tracker.consumed(3, true, later);
+        assert_eq!(tracker.gaps.keys().copied().collect::<Vec<_>>(), [3, 4]);
+        assert_eq!(tracker.missing_since, Some((3, later)));
+        tracker.consumed(6, false, later);
+        assert!(tracker.gaps.is_empty());
+        assert_eq!(tracker.missing_since, None);
+    }
+
+    #[test]
+    fn test_gap_lost() {
+        let now = Instant::now();
+        let timeout = Duration::from_millis(100);
+        let mut stats = UdpStats::default();
+        let mut tracker = GapTracker::default();
+
+        // Nothing is missing
+        assert!(!tracker.check_missing(timeout, now + timeout * 2, &mut stats));
+
+        tracker.recieved(1, 0, now, now, &mut stats);
+        tracker.consumed(0, true, now);
+        assert!(!tracker.check_missing(timeout, now + timeout, &mut stats));
+        assert_eq!(stats.lost, 0);
+        assert!(tracker.check_missing(timeout, now + timeout * 2, &mut stats));
+        assert_eq!(stats.lost, 1);
+
+        // Once it arrives nothing is lost
+        tracker.recieved(0, 0, now, now, &mut stats);
+        tracker.consumed(2, false, now);
+        assert!(!tracker.check_missing(timeout, now + timeout * 2, &mut stats));
+        assert_eq!(stats.lost, 1);
+    }
+}
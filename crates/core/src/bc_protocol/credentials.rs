@@ -31,7 +31,8 @@ impl std::fmt::Debug for Credentials {
 }
 
 impl Credentials {
-    pub(crate) fn new<T: Into<String>, U: Into<String>>(username: T, password: Option<U>) -> Self {
+    /// Create new credentials from a username and optional password
+    pub fn new<T: Into<String>, U: Into<String>>(username: T, password: Option<U>) -> Self {
         Self {
             username: username.into(),
             password: password.map(|t| t.into()),
@@ -40,7 +41,7 @@ impl Credentials {
 
     /// This is a convience function to make an AES key from the login password and the NONCE
     /// negotiated during login
-    pub(crate) fn make_aeskey<T: AsRef<str>>(&self, nonce: T) -> [u8; 16] {
+    pub fn make_aeskey<T: AsRef<str>>(&self, nonce: T) -> [u8; 16] {
         let key_phrase = format!(
             "{}-{}",
             nonce.as_ref(),
@@ -60,6 +60,14 @@ pub enum Error {
     #[error("Camera responded with Err during login")]
     CameraLoginFail,
 
+    /// Raised when the camera refuses the login because all of its client
+    /// sessions are in use, such as when the official app is connected
+    ///
+    /// The login does not raise this yet as the reply code of such a refusal
+    /// has not been confirmed from a capture, it is a [`Error::CameraLoginFail`]
+    #[error("Camera refused the login as it has reached its maximum number of clients")]
+    MaxClients,
+
     /// Raised when a connection is dropped.
     #[error("Dropped connection")]
     DroppedConnection,
@@ -101,6 +101,25 @@ impl BcCamera {
         }
     }
 
+    /// Turn the floodlight on or off and optionally set its `brightness` in %
+    ///
+    /// The brightness is clamped to what the camera supports and is also
+    /// used by the camera for its own floodlight tasks. When turned on the
+    /// light stays on for 3 minutes unless it is turned off sooner
+    pub async fn set_floodlight(&self, state: bool, brightness: Option<u32>) -> Result<()> {
+        if let Some(brightness) = brightness {
+            let mut tasks = self.get_flightlight_tasks().await?;
+            let min = tasks.brightness_min.unwrap_or(1);
+            let max = tasks.brightness_max.unwrap_or(100).max(min);
+            let brightness = brightness.max(min).min(max);
+            if tasks.brightness_cur != brightness {
+                tasks.brightness_cur = brightness;
+                self.set_flightlight_tasks(tasks).await?;
+            }
+        }
+        self.set_floodlight_manual(state, 180).await
+    }
+
     /// Get the Flood Light tasks XML
     pub async fn get_flightlight_tasks(&self) -> Result<FloodlightTask> {
         let connection = self.get_connection();
@@ -0,0 +1,109 @@
+use super::{BcCamera, Error, Result};
+use crate::bc::{model::*, xml::*};
+
+impl BcCamera {
+    /// Get the [SystemGeneral] xml
+    ///
+    /// This has the clock of the camera and its device name
+    pub async fn get_general(&self) -> Result<SystemGeneral> {
+        self.has_ability_ro("general").await?;
+        let connection = self.get_connection();
+        let msg_num = self.new_message_num();
+        let mut sub_get = connection.subscribe(MSG_ID_GET_GENERAL, msg_num).await?;
+        let get = Bc {
+            meta: BcMeta {
+                msg_id: MSG_ID_GET_GENERAL,
+                channel_id: self.channel_id,
+                msg_num,
+                response_code: 0,
+                stream_type: 0,
+                class: 0x6414,
+            },
+            body: BcBody::ModernMsg(ModernMsg::default()),
+        };
+
+        sub_get.send(get).await?;
+        let msg = sub_get.recv().await?;
+        if msg.meta.response_code != 200 {
+            return Err(Error::CameraServiceUnavailable {
+                id: msg.meta.msg_id,
+                code: msg.meta.response_code,
+            });
+        }
+
+        if let BcBody::ModernMsg(ModernMsg {
+            payload:
+                Some(BcPayloads::BcXml(BcXml {
+                    system_general: Some(system_general),
+                    ..
+                })),
+            ..
+        }) = msg.body
+        {
+            Ok(system_general)
+        } else {
+            Err(Error::UnintelligibleReply {
+                reply: std::sync::Arc::new(Box::new(msg)),
+                why: "Expected SystemGeneral xml but it was not recieved",
+            })
+        }
+    }
+
+    /// Set the [SystemGeneral] xml
+    ///
+    /// Only the fields that are given are changed
+    pub async fn set_general(&self, system_general: SystemGeneral) -> Result<()> {
+        self.has_ability_rw("general").await?;
+        let connection = self.get_connection();
+        let msg_num = self.new_message_num();
+        let mut sub_set = connection.subscribe(MSG_ID_SET_GENERAL, msg_num).await?;
+        let set = Bc::new_from_xml(
+            BcMeta {
+                msg_id: MSG_ID_SET_GENERAL,
+                channel_id: self.channel_id,
+                msg_num,
+                response_code: 0,
+                stream_type: 0,
+                class: 0x6414,
+            },
+            BcXml {
+                system_general: Some(system_general),
+                ..Default::default()
+            },
+        );
+
+        sub_set.send(set).await?;
+        let msg = sub_set.recv().await?;
+        if let BcMeta {
+            response_code: 200, ..
+        } = msg.meta
+        {
+            Ok(())
+        } else {
+            Err(Error::UnintelligibleReply {
+                reply: std::sync::Arc::new(Box::new(msg)),
+                why: "The camera did not except the SystemGeneral xml",
+            })
+        }
+    }
+
+    /// Get the device name
+    ///
+    /// This is the name of the camera in the Reolink app, it is not
+    /// the name drawn on the OSD
+    pub async fn get_device_name(&self) -> Result<String> {
+        Ok(self.get_general().await?.device_name.unwrap_or_default())
+    }
+
+    /// Set the device name
+    ///
+    /// Only the name is sent so that the clock of the camera is not changed
+    pub async fn set_device_name(&self, name: &str) -> Result<()> {
+        self.set_general(SystemGeneral {
+            version: xml_ver(),
+            device_name: Some(name.to_string()),
+            ..Default::default()
+        })
+        .await
+    }
+}
@@ -0,0 +1,42 @@
+use super::{BcCamera, Error, LightState, Result};
+
+impl BcCamera {
+    /// Get the state of the IR LEDs used for night vision
+    ///
+    /// This is the `state` of the [crate::bc::xml::LedState] xml
+    pub async fn get_ir_led(&self) -> Result<LightState> {
+        let led_state = self.get_ledstate().await?;
+        match led_state.state.as_str() {
+            "open" => Ok(LightState::On),
+            "close" => Ok(LightState::Off),
+            "auto" => Ok(LightState::Auto),
+            _ => Err(Error::OtherString(format!(
+                "Unknown IR LED state {}",
+                led_state.state
+            ))),
+        }
+    }
+
+    /// Set the IR LEDs used for night vision
+    ///
+    /// On and off force the lights regardless of the light level while
+    /// auto lets the camera turn them on in low light
+    pub async fn set_ir_led(&self, state: LightState) -> Result<()> {
+        let mut led_state = self.get_ledstate().await?;
+        led_state.state = match state {
+            LightState::On => "open".to_string(),
+            LightState::Off => "close".to_string(),
+            LightState::Auto => "auto".to_string(),
+        };
+        self.set_ledstate(led_state).await?;
+        Ok(())
+    }
+
+    /// This is a convience function to control the IR LED lights
+    ///
+    /// This is for the RED IR lights that can come on automaitcally
+    /// during low light. It is the same as [BcCamera::set_ir_led]
+    pub async fn irled_light_set(&self, state: LightState) -> Result<()> {
+        self.set_ir_led(state).await
+    }
+}
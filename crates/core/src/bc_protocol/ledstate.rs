@@ -108,21 +108,6 @@ impl BcCamera {
         }
     }
 
-    /// This is a convience function to control the IR LED lights
-    ///
-    /// This is for the RED IR lights that can come on automaitcally
-    /// during low light.
-    pub async fn irled_light_set(&self, state: LightState) -> Result<()> {
-        let mut led_state = self.get_ledstate().await?;
-        led_state.state = match state {
-            LightState::On => "open".to_string(),
-            LightState::Off => "close".to_string(),
-            LightState::Auto => "auto".to_string(),
-        };
-        self.set_ledstate(led_state).await?;
-        Ok(())
-    }
-
     /// This is a convience function to control the LED light
     /// True is on and false is off
     ///
@@ -136,9 +121,31 @@ impl BcCamera {
         self.set_ledstate(led_state).await?;
         Ok(())
     }
+
+    /// This is a convience function to control the light of the doorbell button
+    ///
+    /// It can be on, off or auto where it lights up when the button is pressed.
+    /// Only doorbells have this light so other cameras return an error
+    pub async fn doorbell_light_set(&self, state: LightState) -> Result<()> {
+        let mut led_state = self.get_ledstate().await?;
+        if led_state.doorbell_light_state.is_none() {
+            return Err(Error::Other("The camera has no doorbell light"));
+        }
+        led_state.doorbell_light_state = Some(
+            match state {
+                LightState::On => "open",
+                LightState::Off => "close",
+                LightState::Auto => "auto",
+            }
+            .to_string(),
+        );
+        self.set_ledstate(led_state).await?;
+        Ok(())
+    }
 }
 
-/// This is pased to `irled_light_set` to turn it on, off or set it to light based auto
+/// This is pased to `set_ir_led` and `doorbell_light_set` to turn it on, off or set it to light based auto
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum LightState {
     /// Turn the light on
     On,
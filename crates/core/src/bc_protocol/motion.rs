@@ -16,6 +16,68 @@ pub enum MotionStatus {
     NoChange(Instant),
 }
 
+/// The kind of a smart detection
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SmartEventKind {
+    /// Something crossed a line
+    LineCrossing,
+    /// Something entered a zone
+    Intrusion,
+    /// Something stayed in a zone
+    Loitering,
+    /// A smart detection that is not yet known, with the name the camera gave it
+    Other(String),
+}
+
+impl std::fmt::Display for SmartEventKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SmartEventKind::LineCrossing => write!(f, "line_crossing"),
+            SmartEventKind::Intrusion => write!(f, "intrusion"),
+            SmartEventKind::Loitering => write!(f, "loitering"),
+            SmartEventKind::Other(name) => write!(f, "{}", name),
+        }
+    }
+}
+
+impl From<&str> for SmartEventKind {
+    fn from(name: &str) -> Self {
+        match name {
+            "crossline" | "linecross" | "line_crossing" => SmartEventKind::LineCrossing,
+            "intrusion" | "perimeter" => SmartEventKind::Intrusion,
+            "loitering" | "linger" => SmartEventKind::Loitering,
+            other => SmartEventKind::Other(other.to_string()),
+        }
+    }
+}
+
+/// A smart detection such as a line crossing, reported separately from motion
+#[derive(Clone, Debug)]
+pub struct SmartEvent {
+    /// When it was received
+    pub at: Instant,
+    /// What was detected
+    pub kind: SmartEventKind,
+    /// The index of the line or zone if the camera gave one
+    pub zone: Option<u8>,
+    /// What the AI classified the object as, e.g. `"people"` or `"vehicle"`
+    pub ai_types: Vec<String>,
+}
+
+/// An alarm from the camera with everything that triggered it
+///
+/// One movement is often reported by several sources at once such as the
+/// motion detection, the PIR and the AI
+#[derive(Clone, Debug)]
+pub struct AlarmTrigger {
+    /// When it was received
+    pub at: Instant,
+    /// The alarm as the camera named it such as `"MD"` or `"PIR"`, `None` if only the AI triggered
+    pub status: Option<String>,
+    /// What the AI classified the object as, e.g. `"people"` or `"vehicle"`
+    pub ai_types: Vec<String>,
+}
+
 /// A handle on current motion related events comming from the camera
 ///
 /// When this object is dropped the motion events are stopped
@@ -23,6 +85,8 @@ pub struct MotionData {
     handle: JoinSet<Result<()>>,
     cancel: CancellationToken,
     rx: Receiver<Result<MotionStatus>>,
+    smart_rx: Option<Receiver<SmartEvent>>,
+    trigger_rx: Option<Receiver<AlarmTrigger>>,
     last_update: MotionStatus,
 }
 
@@ -87,6 +151,23 @@ impl MotionData {
         }
     }
 
+    /// Take the receiver of smart detections such as a line crossing
+    ///
+    /// These are sent alongside the motion events by cameras that
+    /// support them, others never send one. It can only be taken once
+    /// so that it may be awaited at the same time as the motion
+    pub fn take_smart_events(&mut self) -> Option<Receiver<SmartEvent>> {
+        self.smart_rx.take()
+    }
+
+    /// Take the receiver of what triggered each alarm
+    ///
+    /// Unlike the motion events these keep the source of each alarm. It can
+    /// only be taken once so that it may be awaited at the same time as the motion
+    pub fn take_triggers(&mut self) -> Option<Receiver<AlarmTrigger>> {
+        self.trigger_rx.take()
+    }
+
     /// Wait for the motion to stop
     ///
     /// It must be stopped for at least the given duration
@@ -211,6 +292,8 @@ impl BcCamera {
         // After start_motion_query (MSG_ID 31) the camera sends motion messages
         // when whenever motion is detected.
         let (tx, rx) = channel(20);
+        let (smart_tx, smart_rx) = channel(20);
+        let (trigger_tx, trigger_rx) = channel(20);
 
         let mut set = JoinSet::new();
         let channel_id = self.channel_id;
@@ -239,6 +322,39 @@ impl BcCamera {
                                     let mut result = MotionStatus::NoChange(Instant::now());
                                     for alarm_event in &alarm_event_list.alarm_events {
                                         if alarm_event.channel_id == channel_id {
+                                            let ai_types: Vec<String> = alarm_event
+                                                .ai_type
+                                                .iter()
+                                                .flat_map(|ai_type| ai_type.split(','))
+                                                .map(|ai_type| ai_type.trim())
+                                                .filter(|ai_type| !ai_type.is_empty() && *ai_type != "none")
+                                                .map(|ai_type| ai_type.to_string())
+                                                .collect();
+                                            if let Some(smart_type) = alarm_event
+                                                .smart_type
+                                                .as_deref()
+                                                .filter(|smart_type| *smart_type != "none")
+                                            {
+                                                let event = SmartEvent {
+                                                    at: Instant::now(),
+                                                    kind: smart_type.into(),
+                                                    zone: alarm_event.smart_id,
+                                                    ai_types: ai_types.clone(),
+                                                };
+                                                // Nobody may be listening for these
+                                                let _ = smart_tx.try_send(event);
+                                            }
+                                            let status = Some(alarm_event.status.clone())
+                                                .filter(|status| status != "none");
+                                            if status.is_some() || !ai_types.is_empty() {
+                                                let trigger = AlarmTrigger {
+                                                    at: Instant::now(),
+                                                    status,
+                                                    ai_types,
+                                                };
+                                                // Nobody may be listening for these either
+                                                let _ = trigger_tx.try_send(trigger);
+                                            }
                                             if alarm_event.status != "none"
                                                 || alarm_event
                                                     .ai_type
@@ -277,6 +393,8 @@ impl BcCamera {
             handle: set,
             cancel,
             rx,
+            smart_rx: Some(smart_rx),
+            trigger_rx: Some(trigger_rx),
             last_update: MotionStatus::NoChange(Instant::now()),
         })
     }
@@ -0,0 +1,128 @@
+use super::{BcCamera, Error, Result};
+use crate::bc::{model::*, xml::*};
+
+impl BcCamera {
+    /// Get the [OsdChannelName] and [OsdDatetime] xml
+    ///
+    /// The [OsdChannelName] contains the name of the channel, on an
+    /// NVR this is the title given to the channel
+    pub async fn get_osd(&self) -> Result<(OsdChannelName, Option<OsdDatetime>)> {
+        self.has_ability_ro("osdName").await?;
+        let connection = self.get_connection();
+        let msg_num = self.new_message_num();
+        let mut sub_get = connection.subscribe(MSG_ID_GET_OSD, msg_num).await?;
+        let get = Bc {
+            meta: BcMeta {
+                msg_id: MSG_ID_GET_OSD,
+                channel_id: self.channel_id,
+                msg_num,
+                response_code: 0,
+                stream_type: 0,
+                class: 0x6414,
+            },
+            body: BcBody::ModernMsg(ModernMsg {
+                extension: Some(Extension {
+                    channel_id: Some(self.channel_id),
+                    ..Default::default()
+                }),
+                payload: None,
+            }),
+        };
+
+        sub_get.send(get).await?;
+        let msg = sub_get.recv().await?;
+        if msg.meta.response_code != 200 {
+            return Err(Error::CameraServiceUnavailable {
+                id: msg.meta.msg_id,
+                code: msg.meta.response_code,
+            });
+        }
+
+        if let BcBody::ModernMsg(ModernMsg {
+            payload:
+                Some(BcPayloads::BcXml(BcXml {
+                    osd_channel_name: Some(osd_channel_name),
+                    osd_datetime,
+                    ..
+                })),
+            ..
+        }) = msg.body
+        {
+            Ok((osd_channel_name, osd_datetime))
+        } else {
+            Err(Error::UnintelligibleReply {
+                reply: std::sync::Arc::new(Box::new(msg)),
+                why: "Expected OsdChannelName xml but it was not recieved",
+            })
+        }
+    }
+
+    /// Set the OSD using the [OsdChannelName] and optionally the [OsdDatetime] xml
+    pub async fn set_osd(
+        &self,
+        osd_channel_name: OsdChannelName,
+        mut osd_datetime: Option<OsdDatetime>,
+    ) -> Result<()> {
+        self.has_ability_rw("osdName").await?;
+        let connection = self.get_connection();
+        let msg_num = self.new_message_num();
+        let mut sub_set = connection.subscribe(MSG_ID_SET_OSD, msg_num).await?;
+
+        // width and height are received from the camera but not sent
+        if let Some(osd_datetime) = osd_datetime.as_mut() {
+            osd_datetime.width = None;
+            osd_datetime.height = None;
+        }
+        let set = Bc {
+            meta: BcMeta {
+                msg_id: MSG_ID_SET_OSD,
+                channel_id: self.channel_id,
+                msg_num,
+                response_code: 0,
+                stream_type: 0,
+                class: 0x6414,
+            },
+            body: BcBody::ModernMsg(ModernMsg {
+                extension: Some(Extension {
+                    channel_id: Some(self.channel_id),
+                    ..Default::default()
+                }),
+                payload: Some(BcPayloads::BcXml(BcXml {
+                    osd_channel_name: Some(osd_channel_name),
+                    osd_datetime,
+                    ..Default::default()
+                })),
+            }),
+        };
+
+        sub_set.send(set).await?;
+        let msg = sub_set.recv().await?;
+        if let BcMeta {
+            response_code: 200, ..
+        } = msg.meta
+        {
+            Ok(())
+        } else {
+            Err(Error::UnintelligibleReply {
+                reply: std::sync::Arc::new(Box::new(msg)),
+                why: "The camera did not except the OsdChannelName xml",
+            })
+        }
+    }
+
+    /// Get the channel name
+    ///
+    /// On an NVR this is the title of the channel
+    pub async fn get_channel_name(&self) -> Result<String> {
+        Ok(self.get_osd().await?.0.name)
+    }
+
+    /// Set the channel name
+    ///
+    /// This is the name shown on the OSD and in the NVR/app
+    pub async fn set_channel_name(&self, name: &str) -> Result<()> {
+        let (mut osd_channel_name, osd_datetime) = self.get_osd().await?;
+        osd_channel_name.name = name.to_string();
+        self.set_osd(osd_channel_name, osd_datetime).await
+    }
+}
@@ -0,0 +1,369 @@
+use super::{BcCamera, Error, Result, StreamKind};
+use crate::{
+    bc::{model::*, xml::*},
+    bcmedia::{codex::BcMediaCodex, model::*},
+};
+use futures::stream::{StreamExt, TryStreamExt};
+use std::convert::{TryFrom, TryInto};
+use std::io::{Error as IoError, ErrorKind};
+use time::{Date, Month, PrimitiveDateTime, Time};
+use tokio::sync::mpsc::{channel, Receiver};
+use tokio::task::{self, JoinHandle};
+use tokio::time::{timeout, Duration};
+use tokio_stream::wrappers::ReceiverStream;
+use tokio_util::{codec::FramedRead, compat::FuturesAsyncReadCompatExt, sync::CancellationToken};
+
+/// The record types that are searched for, all of those that are known
+const RECORD_TYPES: &str =
+    "manual, sched, io, md, people, face, vehicle, dog_cat, visitor, other, package";
+
+/// The most batches of files that are asked for in one search
+const MAX_SEARCH_BATCHES: usize = 1000;
+
+/// How long the camera may go without sending data during a download
+const DOWNLOAD_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// A recording on the camera's SD card
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RecordedFile {
+    /// Name of the file on the SD card, used to download it
+    pub name: String,
+    /// What caused the recording e.g. `md`, `sched` or `manual`
+    pub record_type: Option<String>,
+    /// Size of the file in bytes
+    pub size: Option<u64>,
+    /// Start of the recording in the camera's time
+    pub start: PrimitiveDateTime,
+    /// End of the recording in the camera's time
+    pub end: PrimitiveDateTime,
+}
+
+/// A handle on a file that is being downloaded
+///
+/// The data can be pulled using `get_data` which returns raw BcMedia packets
+///
+/// When this object is dropped the download is stopped
+pub struct FileDownload {
+    handle: Option<JoinHandle<Result<()>>>,
+    rx: Receiver<Result<BcMedia>>,
+    abort_handle: CancellationToken,
+}
+
+impl FileDownload {
+    /// Pull the next packet of the file
+    ///
+    /// Returns `None` once the whole file has been recieved
+    pub async fn get_data(&mut self) -> Result<Option<BcMedia>> {
+        match self.rx.recv().await {
+            Some(data) => data.map(Some),
+            None => {
+                // The task has finished, report why
+                if let Some(handle) = self.handle.take() {
+                    handle.await??;
+                }
+                Ok(None)
+            }
+        }
+    }
+}
+
+impl Drop for FileDownload {
+    fn drop(&mut self) {
+        log::trace!("Drop FileDownload");
+        self.abort_handle.cancel();
+        if let Some(handle) = self.handle.take() {
+            let _gt = tokio::runtime::Handle::current().enter();
+            tokio::task::spawn(async move {
+                let _ = handle.await;
+            });
+        }
+        log::trace!("Dropped FileDownload");
+    }
+}
+
+impl BcCamera {
+    /// Search the SD card for the recordings between `start` and `end`
+    ///
+    /// The times are in the camera's own time
+    pub async fn search_files(
+        &self,
+        start: PrimitiveDateTime,
+        end: PrimitiveDateTime,
+        stream: StreamKind,
+    ) -> Result<Vec<RecordedFile>> {
+        self.has_ability_ro("replay").await?;
+
+        let reply = self
+            .file_request(
+                MSG_ID_FILE_SEARCH_OPEN,
+                FileInfo {
+                    uid: Some(0),
+                    channel_id: self.channel_id,
+                    stream_type: Some(stream.to_string()),
+                    record_type: Some(RECORD_TYPES.to_string()),
+                    start_time: Some(start.into()),
+                    end_time: Some(end.into()),
+                    ..Default::default()
+                },
+            )
+            .await?;
+        let handle = reply
+            .first()
+            .and_then(|info| info.handle)
+            .ok_or(Error::Other("The camera did not start the search"))?;
+
+        let mut files = vec![];
+        let res: Result<()> = async {
+            for _ in 0..MAX_SEARCH_BATCHES {
+                let batch = match self
+                    .file_request(
+                        MSG_ID_FILE_SEARCH_NEXT,
+                        FileInfo {
+                            channel_id: self.channel_id,
+                            handle: Some(handle),
+                            ..Default::default()
+                        },
+                    )
+                    .await
+                {
+                    Ok(batch) => batch,
+                    // The camera replies with an error once there are no more files
+                    Err(Error::CameraServiceUnavailable { .. }) => break,
+                    Err(e) => return Err(e),
+                };
+                let found = batch
+                    .into_iter()
+                    .filter_map(|info| RecordedFile::try_from(info).ok())
+                    .collect::<Vec<_>>();
+                if found.is_empty() {
+                    break;
+                }
+                files.extend(found);
+            }
+            Ok(())
+        }
+        .await;
+
+        // Always free the search on the camera
+        let _ = self
+            .file_request(
+                MSG_ID_FILE_SEARCH_CLOSE,
+                FileInfo {
+                    channel_id: self.channel_id,
+                    handle: Some(handle),
+                    ..Default::default()
+                },
+            )
+            .await;
+        res?;
+
+        files.sort_by_key(|file| file.start);
+        files.dedup_by(|a, b| a.name == b.name);
+        Ok(files)
+    }
+
+    /// Download a recording from the SD card
+    ///
+    /// The returned object gives the BcMedia packets of the file, when it is
+    /// dropped the download is stopped. If the camera refuses the download
+    /// the error is returned by the first `get_data`
+    pub async fn download_file(&self, name: &str, stream: StreamKind) -> Result<FileDownload> {
+        self.has_ability_ro("replay").await?;
+
+        let connection = self.get_connection();
+        let msg_num = self.new_message_num();
+        let channel_id = self.channel_id;
+        let file_info = FileInfo {
+            uid: Some(0),
+            channel_id,
+            name: Some(name.to_string()),
+            stream_type: Some(stream.to_string()),
+            support_sub: Some(0),
+            play_speed: Some(32),
+            ..Default::default()
+        };
+
+        let abort_handle = CancellationToken::new();
+        let abort_handle_thread = abort_handle.clone();
+        let (tx, rx) = channel(100);
+
+        let handle = task::spawn(async move {
+            let mut sub_download = connection.subscribe(MSG_ID_FILE_DOWNLOAD, msg_num).await?;
+            sub_download
+                .send(file_msg(
+                    MSG_ID_FILE_DOWNLOAD,
+                    channel_id,
+                    msg_num,
+                    file_info.clone(),
+                ))
+                .await?;
+            let msg = sub_download.recv().await?;
+            if msg.meta.response_code != 200 {
+                return Err(Error::CameraServiceUnavailable {
+                    id: msg.meta.msg_id,
+                    code: msg.meta.response_code,
+                });
+            }
+
+            // The file is sent as binary with 200 while there is more and 201 on the last part
+            let (data_tx, data_rx) = channel(100);
+            let read_file = async move {
+                loop {
+                    let msg = match timeout(DOWNLOAD_TIMEOUT, sub_download.recv()).await {
+                        Ok(Ok(msg)) => msg,
+                        Ok(Err(e)) => {
+                            let _ = data_tx.send(Err(IoError::new(ErrorKind::Other, e))).await;
+                            break;
+                        }
+                        Err(_) => {
+                            let _ = data_tx
+                                .send(Err(IoError::new(ErrorKind::TimedOut, "Download stalled")))
+                                .await;
+                            break;
+                        }
+                    };
+                    let last = msg.meta.response_code != 200;
+                    if let BcBody::ModernMsg(ModernMsg {
+                        payload: Some(BcPayloads::Binary(data)),
+                        ..
+                    }) = msg.body
+                    {
+                        // An empty read would end the file early
+                        if !data.is_empty() && data_tx.send(Ok(data)).await.is_err() {
+                            break; // Download dropped
+                        }
+                    }
+                    if last {
+                        break;
+                    }
+                }
+            };
+
+            let payloads = ReceiverStream::new(data_rx).into_async_read().compat();
+            let mut media = FramedRead::new(payloads, BcMediaCodex::new(false));
+            let send_media = async move {
+                while let Some(bc_media) = media.next().await {
+                    if tx.send(bc_media).await.is_err() {
+                        break; // Download dropped
+                    }
+                }
+            };
+
+            tokio::select! {
+                _ = abort_handle_thread.cancelled() => {},
+                _ = futures::future::join(read_file, send_media) => {}
+            }
+
+            // Tell the camera to stop in case it has not finished
+            let mut sub_stop = connection.subscribe(MSG_ID_FILE_STOP, msg_num).await?;
+            sub_stop
+                .send(file_msg(MSG_ID_FILE_STOP, channel_id, msg_num, file_info))
+                .await?;
+            let _ = timeout(Duration::from_secs(2), sub_stop.recv()).await;
+            Ok(())
+        });
+
+        Ok(FileDownload {
+            handle: Some(handle),
+            rx,
+            abort_handle,
+        })
+    }
+
+    /// Send a FileInfoList with a single file and return the files of the reply
+    async fn file_request(&self, msg_id: u32, file_info: FileInfo) -> Result<Vec<FileInfo>> {
+        let connection = self.get_connection();
+        let msg_num = self.new_message_num();
+        let mut sub = connection.subscribe(msg_id, msg_num).await?;
+        sub.send(file_msg(msg_id, self.channel_id, msg_num, file_info))
+            .await?;
+        let msg = sub.recv().await?;
+        if msg.meta.response_code != 200 {
+            return Err(Error::CameraServiceUnavailable {
+                id: msg.meta.msg_id,
+                code: msg.meta.response_code,
+            });
+        }
+
+        match msg.body {
+            BcBody::ModernMsg(ModernMsg {
+                payload:
+                    Some(BcPayloads::BcXml(BcXml {
+                        file_info_list: Some(FileInfoList { file_info, .. }),
+                        ..
+                    })),
+                ..
+            }) => Ok(file_info),
+            // Replies without a list such as to the close have no files
+            BcBody::ModernMsg(ModernMsg { payload: None, .. }) => Ok(vec![]),
+            _ => Err(Error::UnintelligibleReply {
+                reply: std::sync::Arc::new(Box::new(msg)),
+                why: "Expected FileInfoList xml but it was not recieved",
+            }),
+        }
+    }
+}
+
+fn file_msg(msg_id: u32, channel_id: u8, msg_num: u16, file_info: FileInfo) -> Bc {
+    Bc::new_from_xml(
+        BcMeta {
+            msg_id,
+            channel_id,
+            msg_num,
+            stream_type: 0,
+            response_code: 0,
+            class: 0x6414,
+        },
+        BcXml {
+            file_info_list: Some(FileInfoList {
+                version: Some(xml_ver()),
+                file_info: vec![file_info],
+            }),
+            ..Default::default()
+        },
+    )
+}
+
+impl From<PrimitiveDateTime> for FileTime {
+    fn from(time: PrimitiveDateTime) -> Self {
+        Self {
+            year: time.year(),
+            month: time.month().into(),
+            day: time.day(),
+            hour: time.hour(),
+            minute: time.minute(),
+            second: time.second(),
+        }
+    }
+}
+
+impl TryFrom<FileTime> for PrimitiveDateTime {
+    type Error = Error;
+
+    fn try_from(time: FileTime) -> Result<Self> {
+        Ok(PrimitiveDateTime::new(
+            Date::from_calendar_date(time.year, Month::try_from(time.month)?, time.day)?,
+            Time::from_hms(time.hour, time.minute, time.second)?,
+        ))
+    }
+}
+
+impl TryFrom<FileInfo> for RecordedFile {
+    type Error = Error;
+
+    fn try_from(info: FileInfo) -> Result<Self> {
+        Ok(Self {
+            name: info.name.ok_or(Error::Other("File without a name"))?,
+            record_type: info.record_type,
+            size: info.file_size,
+            start: info
+                .start_time
+                .ok_or(Error::Other("File without a start"))?
+                .try_into()?,
+            end: info
+                .end_time
+                .ok_or(Error::Other("File without an end"))?
+                .try_into()?,
+        })
+    }
+}
@@ -16,22 +16,67 @@ pub enum Direction {
     Stop,
 }
 
+/// Directions of the zoom of a motorized lens
+#[derive(Clone, Copy, Eq, PartialEq, Debug)]
+pub enum ZoomDirection {
+    /// Zoom in, narrowing the view
+    In,
+    /// Zoom out, widening the view
+    Out,
+}
+
+/// Directions of the focus of a motorized lens
+#[derive(Clone, Copy, Eq, PartialEq, Debug)]
+pub enum FocusDirection {
+    /// Focus on things closer to the camera
+    Near,
+    /// Focus on things further from the camera
+    Far,
+}
+
 impl BcCamera {
     /// Send a PTZ message to the camera
     pub async fn send_ptz(&self, direction: Direction, amount: f32) -> Result<()> {
-        self.has_ability_rw("control").await?;
-        let connection = self.get_connection();
-        let msg_num = self.new_message_num();
-        let mut sub_set = connection.subscribe(MSG_ID_PTZ_CONTROL, msg_num).await?;
-
-        let direction_str = match direction {
+        let command = match direction {
             Direction::Up => "up",
             Direction::Down => "down",
             Direction::Left => "left",
             Direction::Right => "right",
             Direction::Stop => "stop",
-        }
-        .to_string();
+        };
+        self.send_ptz_command(command, amount).await
+    }
+
+    /// Start zooming the lens in or out
+    ///
+    /// The lens keeps moving until it reaches the end or it is stopped
+    /// with `send_ptz(Direction::Stop, ..)`
+    pub async fn zoom(&self, direction: ZoomDirection, speed: f32) -> Result<()> {
+        let command = match direction {
+            ZoomDirection::In => "zoomInc",
+            ZoomDirection::Out => "zoomDec",
+        };
+        self.send_ptz_command(command, speed).await
+    }
+
+    /// Start moving the focus of the lens nearer or further
+    ///
+    /// The lens keeps moving until it reaches the end or it is stopped
+    /// with `send_ptz(Direction::Stop, ..)`
+    pub async fn focus(&self, direction: FocusDirection, speed: f32) -> Result<()> {
+        let command = match direction {
+            FocusDirection::Near => "focusDec",
+            FocusDirection::Far => "focusInc",
+        };
+        self.send_ptz_command(command, speed).await
+    }
+
+    async fn send_ptz_command(&self, command: &str, amount: f32) -> Result<()> {
+        self.has_ability_rw("control").await?;
+        let connection = self.get_connection();
+        let msg_num = self.new_message_num();
+        let mut sub_set = connection.subscribe(MSG_ID_PTZ_CONTROL, msg_num).await?;
+
         let send = Bc {
             meta: BcMeta {
                 msg_id: MSG_ID_PTZ_CONTROL,
@@ -51,7 +96,7 @@ impl BcCamera {
                         version: xml_ver(),
                         channel_id: self.channel_id,
                         speed: amount,
-                        command: direction_str,
+                        command: command.to_string(),
                     }),
                     ..Default::default()
                 })),
@@ -236,6 +281,25 @@ impl BcCamera {
         }
     }
 
+    /// The camera will attempt to move to the preset with the given name
+    ///
+    /// The name is matched without regard to case against the presets of [`BcCamera::get_ptz_preset`]
+    pub async fn moveto_ptz_preset_named(&self, name: &str) -> Result<()> {
+        let presets = self.get_ptz_preset().await?;
+        let preset = presets
+            .preset_list
+            .preset
+            .iter()
+            .find(|preset| {
+                preset
+                    .name
+                    .as_deref()
+                    .is_some_and(|preset_name| preset_name.trim().eq_ignore_ascii_case(name.trim()))
+            })
+            .ok_or(Error::Other("No PTZ preset with this name"))?;
+        self.moveto_ptz_preset(preset.id).await
+    }
+
     /// The camera will zoom to a given zoom amount.
     /// Not sure what the units for this are, seems to be 1000 is 1x and 2000 is 2x
     pub async fn zoom_to(&self, zoom_pos: u32) -> Result<()> {
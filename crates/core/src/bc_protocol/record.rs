@@ -0,0 +1,143 @@
+use super::{BcCamera, Error, Result};
+use crate::bc::{model::*, xml::*};
+
+impl BcCamera {
+    /// Get the [Record] xml which contains the SD card recording schedule
+    pub async fn get_record(&self) -> Result<Record> {
+        let connection = self.get_connection();
+        let msg_num = self.new_message_num();
+        let mut sub_get = connection.subscribe(MSG_ID_GET_RECORD, msg_num).await?;
+        let get = Bc {
+            meta: BcMeta {
+                msg_id: MSG_ID_GET_RECORD,
+                channel_id: self.channel_id,
+                msg_num,
+                response_code: 0,
+                stream_type: 0,
+                class: 0x6414,
+            },
+            body: BcBody::ModernMsg(ModernMsg {
+                extension: Some(Extension {
+                    channel_id: Some(self.channel_id),
+                    ..Default::default()
+                }),
+                payload: None,
+            }),
+        };
+
+        sub_get.send(get).await?;
+        let msg = sub_get.recv().await?;
+        if msg.meta.response_code != 200 {
+            return Err(Error::CameraServiceUnavailable {
+                id: msg.meta.msg_id,
+                code: msg.meta.response_code,
+            });
+        }
+
+        if let BcBody::ModernMsg(ModernMsg {
+            payload:
+                Some(BcPayloads::BcXml(BcXml {
+                    record: Some(record),
+                    ..
+                })),
+            ..
+        }) = msg.body
+        {
+            Ok(record)
+        } else {
+            Err(Error::UnintelligibleReply {
+                reply: std::sync::Arc::new(Box::new(msg)),
+                why: "Expected Record xml but it was not recieved",
+            })
+        }
+    }
+
+    /// Set the SD card recording schedule using the [Record] xml
+    pub async fn set_record(&self, record: Record) -> Result<()> {
+        let connection = self.get_connection();
+        let msg_num = self.new_message_num();
+        let mut sub_set = connection.subscribe(MSG_ID_SET_RECORD, msg_num).await?;
+        let set = Bc {
+            meta: BcMeta {
+                msg_id: MSG_ID_SET_RECORD,
+                channel_id: self.channel_id,
+                msg_num,
+                response_code: 0,
+                stream_type: 0,
+                class: 0x6414,
+            },
+            body: BcBody::ModernMsg(ModernMsg {
+                extension: Some(Extension {
+                    channel_id: Some(self.channel_id),
+                    ..Default::default()
+                }),
+                payload: Some(BcPayloads::BcXml(BcXml {
+                    record: Some(record),
+                    ..Default::default()
+                })),
+            }),
+        };
+
+        sub_set.send(set).await?;
+        let msg = sub_set.recv().await?;
+        if let BcMeta {
+            response_code: 200, ..
+        } = msg.meta
+        {
+            Ok(())
+        } else {
+            Err(Error::UnintelligibleReply {
+                reply: std::sync::Arc::new(Box::new(msg)),
+                why: "The camera did not except the Record xml",
+            })
+        }
+    }
+
+    /// Start recording to the SD card regardless of motion
+    ///
+    /// The camera has no manual record message so this is done by replacing
+    /// the recording schedule with one that always records. The schedule that
+    /// was replaced is returned so it can be restored by [`BcCamera::stop_manual_record`]
+    pub async fn start_manual_record(&self) -> Result<Record> {
+        let current = self.get_record().await?;
+        let mut always = current.clone();
+        always.enable = 1;
+        always.schedule_list = all_week_schedule("none");
+        self.set_record(always).await?;
+        Ok(current)
+    }
+
+    /// Stop recording to the SD card regardless of motion
+    ///
+    /// The `previous` schedule from [`BcCamera::start_manual_record`] is restored
+    pub async fn stop_manual_record(&self, previous: Record) -> Result<()> {
+        self.set_record(previous).await
+    }
+}
+
+fn all_week_schedule(alarm_type: &str) -> ScheduleList {
+    ScheduleList {
+        schedule: vec![RecordSchedule {
+            alarm_type: alarm_type.to_string(),
+            time_block_list: TimeBlockList {
+                time_block: [
+                    "Sunday",
+                    "Monday",
+                    "Tuesday",
+                    "Wednesday",
+                    "Thursday",
+                    "Friday",
+                    "Saturday",
+                ]
+                .iter()
+                .map(|day| TimeBlock {
+                    enable: 1,
+                    weekDay: day.to_string(),
+                    begin_hour: 0,
+                    end_hour: 23,
+                })
+                .collect(),
+            },
+        }],
+    }
+}
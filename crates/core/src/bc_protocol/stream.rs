@@ -41,6 +41,44 @@ pub struct StreamData {
     handle: Option<JoinHandle<Result<()>>>,
     rx: Receiver<Result<BcMedia>>,
     abort_handle: CancellationToken,
+    hooks: StreamHooks,
+}
+
+/// Start and stop hooks on a stream
+///
+/// These can be cloned out of the `StreamData` to follow the stream from another
+/// task, such as one that tears the stream down when it is no longer needed
+#[derive(Clone)]
+pub struct StreamHooks {
+    abort_handle: CancellationToken,
+    started: CancellationToken,
+    finished: CancellationToken,
+}
+
+impl StreamHooks {
+    /// Wait until the camera has accepted the stream start command
+    ///
+    /// Returns false if the stream ended before it was started
+    pub async fn started(&self) -> bool {
+        tokio::select! {
+            _ = self.started.cancelled() => true,
+            _ = self.finished.cancelled() => self.started.is_cancelled(),
+        }
+    }
+
+    /// Stop the stream and wait until the camera has stopped sending
+    ///
+    /// The camera is given 2s to reply to the stop command before
+    /// the stream is considered stopped
+    pub async fn stop(&self) {
+        self.abort_handle.cancel();
+        self.finished.cancelled().await;
+    }
+
+    /// True once the stream has been stopped and the camera told
+    pub fn is_stopped(&self) -> bool {
+        self.finished.is_cancelled()
+    }
 }
 
 impl StreamData {
@@ -66,6 +104,11 @@ impl StreamData {
         }
     }
 
+    /// The start and stop hooks of this stream
+    pub fn hooks(&self) -> StreamHooks {
+        self.hooks.clone()
+    }
+
     /// Attempts to gracefully shutdown this will cancel the background task and send
     /// the Stop command to the camera
     pub async fn shutdown(&mut self) -> Result<()> {
@@ -122,6 +165,13 @@ impl BcCamera {
 
         let abort_handle = CancellationToken::new();
         let abort_handle_thread = abort_handle.clone();
+        let hooks = StreamHooks {
+            abort_handle: abort_handle.clone(),
+            started: CancellationToken::new(),
+            finished: CancellationToken::new(),
+        };
+        let started = hooks.started.clone();
+        let finished = hooks.finished.clone();
 
         if buffer_size == 0 {
             buffer_size = 100;
@@ -130,6 +180,8 @@ impl BcCamera {
         let channel_id = self.channel_id;
 
         let handle = task::spawn(async move {
+            // However the task ends the stream is finished
+            let _finished = finished.drop_guard();
             let mut sub_video = connection.subscribe(MSG_ID_VIDEO, msg_num).await?;
 
             // On an E1 and swann cameras:
@@ -201,6 +253,7 @@ impl BcCamera {
                     why: "The camera did not accept the stream start command.",
                 });
             }
+            started.cancel();
 
             {
                 let mut media_sub = sub_video.bcmedia_stream(strict);
@@ -272,6 +325,7 @@ impl BcCamera {
             handle: Some(handle),
             rx,
             abort_handle,
+            hooks,
         })
     }
 
@@ -9,7 +9,8 @@ type IResult<I, O, E = nom::error::VerboseError<I>> = Result<(I, O), nom::Err<E>
 const PAD_SIZE: u32 = 8;
 
 impl BcMedia {
-    pub(crate) fn deserialize(buf: &mut BytesMut) -> Result<BcMedia, Error> {
+    /// Deserialize a single packet from the front of the buffer, removing the consumed bytes
+    pub fn deserialize(buf: &mut BytesMut) -> Result<BcMedia, Error> {
         let (result, len) = match consumed(bcmedia)(buf) {
             Ok((_, (parsed_buff, result))) => Ok((result, parsed_buff.len())),
             Err(e) => Err(e),
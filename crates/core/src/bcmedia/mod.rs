@@ -1,3 +1,4 @@
+#[cfg(feature = "network")]
 pub(crate) mod codex;
 /// Deserlizer for BCMedia
 pub mod de;
@@ -10,7 +10,8 @@ use std::io::Write;
 const PAD_SIZE: u32 = 8;
 
 impl BcMedia {
-    pub(crate) fn serialize<W: Write>(&self, buf: W) -> Result<W, Error> {
+    /// Serialize the packet into the writer
+    pub fn serialize<W: Write>(&self, buf: W) -> Result<W, Error> {
         let (buf, _) = match &self {
             BcMedia::InfoV1(payload) => gen(bcmedia_info_v1(payload), buf)?,
             BcMedia::InfoV2(payload) => gen(bcmedia_info_v2(payload), buf)?,
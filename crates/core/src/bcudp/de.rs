@@ -20,7 +20,8 @@ where
 }
 
 impl BcUdp {
-    pub(crate) fn deserialize(buf: &mut BytesMut) -> Result<BcUdp, Error> {
+    /// Deserialize a single packet from the front of the buffer, removing the consumed bytes
+    pub fn deserialize(buf: &mut BytesMut) -> Result<BcUdp, Error> {
         const TYPICAL_HEADER: usize = 20;
         let (result, len) = match consumed(bcudp)(buf) {
             Ok((_, (parsed_buff, result))) => Ok((result, parsed_buff.len())),
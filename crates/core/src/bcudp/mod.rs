@@ -21,6 +21,7 @@
 //! be split accross multiple UDP Data packets
 //!
 
+#[cfg(feature = "network")]
 pub(crate) mod codex;
 mod crc;
 /// Functions to deserialize udp packets
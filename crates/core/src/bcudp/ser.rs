@@ -7,7 +7,8 @@ use cookie_factory::{combinator::*, gen};
 use std::io::Write;
 
 impl BcUdp {
-    pub(crate) fn serialize<W: Write>(&self, buf: W) -> Result<W, Error> {
+    /// Serialize the packet into the writer
+    pub fn serialize<W: Write>(&self, buf: W) -> Result<W, Error> {
         let (buf, _) = match &self {
             BcUdp::Discovery(payload) => {
                 let xml_payload = encrypt(payload.tid, &payload.payload.serialize(vec![]).unwrap());
@@ -25,6 +25,8 @@
 //!     },
 //!     debug: false,
 //!     max_discovery_retries: 10,
+//!     udp: Default::default(),
+//!     servers: Default::default(),
 //! };
 //! let mut camera = BcCamera::new(&options).await.unwrap();
 //! # })
@@ -49,6 +51,8 @@
 //! #    },
 //! #    debug: false,
 //! #    max_discovery_retries: 10,
+//! #    udp: Default::default(),
+//! #    servers: Default::default(),
 //! # };
 //! # let mut camera = BcCamera::new(&options).await.unwrap();
 //! camera.login().await;
@@ -56,11 +60,35 @@
 //! ```
 //! For further commands see the [`bc_protocol::BcCamera`] struct.
 //!
+//! # Features
+//!
+//! The `network` feature is on by default. It contains the camera
+//! connections and all of the high level controls. Without it only the
+//! message (de)serialisation in [`bc`], [`bcmedia`] and [`bcudp`] is built.
+//! This can be compiled to `wasm32-unknown-unknown` for browser based tooling
+//!
+//! ```bash
+//! cargo build -p neolink_core --no-default-features --target wasm32-unknown-unknown
+//! ```
+//!
 
 /// Contains low level BC structures and formats
 pub mod bc;
 /// Contains high level interfaces for the camera
+#[cfg(feature = "network")]
 pub mod bc_protocol;
+/// Without the `network` feature only the errors and
+/// credentials needed by the codecs are available
+#[cfg(not(feature = "network"))]
+pub mod bc_protocol {
+    use crate::bc;
+
+    mod credentials;
+    mod errors;
+
+    pub use credentials::*;
+    pub use errors::Error;
+}
 /// Contains low level structures and formats for the media substream
 pub mod bcmedia;
 ///  Contains low level structures and formats for the udpstream
@@ -71,6 +99,8 @@ pub mod bcudp;
 /// Most commands will either return their `Ok(result)` or this `Err(Error)`
 pub use bc_protocol::Error;
 
-pub(crate) use bc_protocol::{Credentials, Result};
+pub(crate) use bc_protocol::Credentials;
+#[cfg(feature = "network")]
+pub(crate) use bc_protocol::Result;
 
 pub(crate) type NomErrorType<'a> = nom::error::VerboseError<&'a [u8]>;
@@ -0,0 +1,97 @@
+/*
+ * C interface to neolink_core
+ *
+ * All handles are opaque and must be released with their matching
+ * _free/_stop function. Callbacks are called from neolink's worker
+ * threads and must not call back into the blocking functions.
+ */
+#ifndef NEOLINK_H
+#define NEOLINK_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+#define NEOLINK_OK 0
+#define NEOLINK_ERR_INVALID_ARGUMENT -1
+#define NEOLINK_ERR_CONNECT -2
+#define NEOLINK_ERR_COMMAND -3
+
+#define NEOLINK_FRAME_END -1
+#define NEOLINK_FRAME_INFO 0
+#define NEOLINK_FRAME_IFRAME_H264 1
+#define NEOLINK_FRAME_IFRAME_H265 2
+#define NEOLINK_FRAME_PFRAME_H264 3
+#define NEOLINK_FRAME_PFRAME_H265 4
+#define NEOLINK_FRAME_AAC 5
+#define NEOLINK_FRAME_ADPCM 6
+
+#define NEOLINK_PTZ_UP 0
+#define NEOLINK_PTZ_DOWN 1
+#define NEOLINK_PTZ_LEFT 2
+#define NEOLINK_PTZ_RIGHT 3
+#define NEOLINK_PTZ_STOP 4
+
+#define NEOLINK_STREAM_MAIN 0
+#define NEOLINK_STREAM_SUB 1
+#define NEOLINK_STREAM_EXTERN 2
+
+typedef struct NeolinkCamera NeolinkCamera;
+typedef struct NeolinkTask NeolinkTask;
+
+typedef struct NeolinkCameraOptions {
+  /* Name used in logs, may be NULL */
+  const char *name;
+  const char *username;
+  /* May be NULL */
+  const char *password;
+  /* "host" or "host:port", may be NULL if uid is given */
+  const char *address;
+  /* May be NULL if address is given */
+  const char *uid;
+  /* 0 unless the camera is on an NVR */
+  uint8_t channel_id;
+} NeolinkCameraOptions;
+
+/* data is only valid for the duration of the call */
+typedef void (*NeolinkFrameCallback)(void *user_data, int32_t kind,
+                                     const uint8_t *data, size_t len,
+                                     uint32_t microseconds);
+/* motion is 1 on start and 0 on stop */
+typedef void (*NeolinkMotionCallback)(void *user_data, int32_t motion);
+
+/* Returns NULL on failure */
+NeolinkCamera *neolink_camera_connect(const NeolinkCameraOptions *options);
+void neolink_camera_free(NeolinkCamera *camera);
+
+/* On success *data must be released with neolink_buffer_free */
+int32_t neolink_camera_snapshot(const NeolinkCamera *camera, uint8_t **data,
+                                size_t *len);
+void neolink_buffer_free(uint8_t *data, size_t len);
+
+int32_t neolink_camera_ptz(const NeolinkCamera *camera, int32_t direction,
+                           float speed);
+int32_t neolink_camera_ptz_preset(const NeolinkCamera *camera,
+                                  uint8_t preset_id);
+int32_t neolink_camera_reboot(const NeolinkCamera *camera);
+
+/* Returns NULL on failure */
+NeolinkTask *neolink_stream_start(const NeolinkCamera *camera, int32_t stream,
+                                  NeolinkFrameCallback callback,
+                                  void *user_data);
+void neolink_stream_stop(NeolinkTask *stream);
+
+/* Returns NULL on failure */
+NeolinkTask *neolink_motion_start(const NeolinkCamera *camera,
+                                  NeolinkMotionCallback callback,
+                                  void *user_data);
+void neolink_motion_stop(NeolinkTask *motion);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* NEOLINK_H */
@@ -0,0 +1,538 @@
+#![warn(unused_crate_dependencies)]
+#![warn(missing_docs)]
+//! # Neolink-FFI
+//!
+//! A C compatible interface to [`neolink_core`] so that existing C/C++
+//! NVR software can use neolink as their Reolink driver.
+//!
+//! The matching header is in `include/neolink.h`.
+//!
+//! All handles are opaque pointers that must be released with their
+//! matching `_free`/`_stop` function. Each camera owns its own tokio
+//! runtime and the blocking functions wait on that runtime. The
+//! callbacks are called from the runtime's worker threads so they
+//! must not call back into any of the blocking functions.
+//!
+use neolink_core::{
+    bc_protocol::{
+        BcCamera, BcCameraOpt, ConnectionProtocol, Credentials, Direction, DiscoveryMethods,
+        MotionStatus, StreamKind,
+    },
+    bcmedia::model::{BcMedia, VideoType},
+};
+use std::{
+    ffi::{c_void, CStr},
+    net::{IpAddr, ToSocketAddrs},
+    os::raw::c_char,
+    str::FromStr,
+    sync::Arc,
+};
+use tokio::{
+    runtime::Runtime,
+    sync::oneshot::{channel as oneshot, Sender as OneshotSender},
+    task::JoinHandle,
+};
+
+/// Success
+pub const NEOLINK_OK: i32 = 0;
+/// A null pointer or an unknown enum value was given
+pub const NEOLINK_ERR_INVALID_ARGUMENT: i32 = -1;
+/// The camera could not be reached or the login failed
+pub const NEOLINK_ERR_CONNECT: i32 = -2;
+/// The camera did not accept the command
+pub const NEOLINK_ERR_COMMAND: i32 = -3;
+
+/// Stream info, `data` is null
+pub const NEOLINK_FRAME_INFO: i32 = 0;
+/// H264 IFrame
+pub const NEOLINK_FRAME_IFRAME_H264: i32 = 1;
+/// H265 IFrame
+pub const NEOLINK_FRAME_IFRAME_H265: i32 = 2;
+/// H264 PFrame
+pub const NEOLINK_FRAME_PFRAME_H264: i32 = 3;
+/// H265 PFrame
+pub const NEOLINK_FRAME_PFRAME_H265: i32 = 4;
+/// AAC audio
+pub const NEOLINK_FRAME_AAC: i32 = 5;
+/// ADPCM audio in DVI-4 layout
+pub const NEOLINK_FRAME_ADPCM: i32 = 6;
+/// The stream has ended, this is always the last callback
+pub const NEOLINK_FRAME_END: i32 = -1;
+
+/// Options used to connect to a camera
+///
+/// Strings are null terminated UTF8. Either `address` or `uid` must be non-null
+#[repr(C)]
+pub struct NeolinkCameraOptions {
+    /// Name used in logs
+    pub name: *const c_char,
+    /// Username for login
+    pub username: *const c_char,
+    /// Password for login, may be null
+    pub password: *const c_char,
+    /// Address as `host` or `host:port`, may be null
+    pub address: *const c_char,
+    /// UID of the camera, may be null
+    pub uid: *const c_char,
+    /// Channel of the camera, 0 unless it is on an NVR
+    pub channel_id: u8,
+}
+
+/// Called on each frame of a stream
+///
+/// `data` is only valid for the duration of the call
+pub type NeolinkFrameCallback = extern "C" fn(
+    user_data: *mut c_void,
+    kind: i32,
+    data: *const u8,
+    len: usize,
+    microseconds: u32,
+);
+
+/// Called on each motion event `1` for start and `0` for stop
+pub type NeolinkMotionCallback = extern "C" fn(user_data: *mut c_void, motion: i32);
+
+/// An opaque handle on a connected camera
+pub struct NeolinkCamera {
+    runtime: Arc<Runtime>,
+    camera: Option<Arc<BcCamera>>,
+}
+
+/// An opaque handle on a running stream or motion listener
+pub struct NeolinkTask {
+    runtime: Arc<Runtime>,
+    stop: Option<OneshotSender<()>>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl Drop for NeolinkTask {
+    fn drop(&mut self) {
+        if let Some(stop) = self.stop.take() {
+            let _ = stop.send(());
+        }
+        if let Some(handle) = self.handle.take() {
+            let _ = self.runtime.block_on(handle);
+        }
+    }
+}
+
+// The user data is owned by the caller and they are responsible for its thread safety
+struct UserData(*mut c_void);
+unsafe impl Send for UserData {}
+unsafe impl Sync for UserData {}
+
+unsafe fn opt_str(ptr: *const c_char) -> Option<Option<String>> {
+    if ptr.is_null() {
+        Some(None)
+    } else {
+        CStr::from_ptr(ptr)
+            .to_str()
+            .ok()
+            .map(|s| Some(s.to_string()))
+    }
+}
+
+fn parse_address(src: Option<&str>) -> Option<(Option<u16>, Vec<IpAddr>)> {
+    if let Some(addr_str) = src {
+        match addr_str.to_socket_addrs() {
+            Ok(addr_iter) => {
+                let mut port = None;
+                let mut ipaddrs = vec![];
+                for addr in addr_iter {
+                    port = Some(addr.port());
+                    ipaddrs.push(addr.ip());
+                }
+                Some((port, ipaddrs))
+            }
+            Err(_) => IpAddr::from_str(addr_str).ok().map(|ip| (None, vec![ip])),
+        }
+    } else {
+        Some((None, vec![]))
+    }
+}
+
+unsafe fn camera_ref<'a>(camera: *const NeolinkCamera) -> Option<&'a NeolinkCamera> {
+    camera.as_ref().filter(|c| c.camera.is_some())
+}
+
+fn result_code<T>(result: Result<T, neolink_core::Error>) -> i32 {
+    match result {
+        Ok(_) => NEOLINK_OK,
+        Err(e) => {
+            log::debug!("Neolink command failed: {:?}", e);
+            NEOLINK_ERR_COMMAND
+        }
+    }
+}
+
+/// Connect and login to a camera
+///
+/// Returns null on failure
+///
+/// # Safety
+///
+/// `options` must point to a valid [`NeolinkCameraOptions`] whose strings are
+/// either null or valid null terminated strings
+#[no_mangle]
+pub unsafe extern "C" fn neolink_camera_connect(
+    options: *const NeolinkCameraOptions,
+) -> *mut NeolinkCamera {
+    let options = match options.as_ref() {
+        Some(options) => options,
+        None => return std::ptr::null_mut(),
+    };
+    let (name, username, password, address, uid) = match (
+        opt_str(options.name),
+        opt_str(options.username),
+        opt_str(options.password),
+        opt_str(options.address),
+        opt_str(options.uid),
+    ) {
+        (Some(name), Some(Some(username)), Some(password), Some(address), Some(uid)) => {
+            (name, username, password, address, uid)
+        }
+        _ => return std::ptr::null_mut(),
+    };
+    if address.is_none() && uid.is_none() {
+        return std::ptr::null_mut();
+    }
+    let (port, addrs) = match parse_address(address.as_deref()) {
+        Some(v) => v,
+        None => return std::ptr::null_mut(),
+    };
+
+    let bc_options = BcCameraOpt {
+        name: name.unwrap_or_else(|| "neolink".to_string()),
+        channel_id: options.channel_id,
+        addrs,
+        port,
+        uid,
+        protocol: ConnectionProtocol::TcpUdp,
+        discovery: DiscoveryMethods::Relay,
+        credentials: Credentials { username, password },
+        debug: false,
+        max_discovery_retries: 10,
+        udp: Default::default(),
+        servers: Default::default(),
+    };
+
+    let runtime = match tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .build()
+    {
+        Ok(runtime) => Arc::new(runtime),
+        Err(_) => return std::ptr::null_mut(),
+    };
+    let camera = runtime.block_on(async {
+        let camera = BcCamera::new(&bc_options).await?;
+        camera.login().await?;
+        Ok::<_, neolink_core::Error>(camera)
+    });
+    match camera {
+        Ok(camera) => Box::into_raw(Box::new(NeolinkCamera {
+            runtime,
+            camera: Some(Arc::new(camera)),
+        })),
+        Err(e) => {
+            log::error!("{}: Failed to connect: {:?}", bc_options.name, e);
+            std::ptr::null_mut()
+        }
+    }
+}
+
+/// Logout, disconnect and free the camera
+///
+/// All streams and motion listeners of this camera should be stopped first
+///
+/// # Safety
+///
+/// `camera` must be null or a pointer returned from [`neolink_camera_connect`]
+/// that has not already been freed
+#[no_mangle]
+pub unsafe extern "C" fn neolink_camera_free(camera: *mut NeolinkCamera) {
+    if camera.is_null() {
+        return;
+    }
+    let mut camera = Box::from_raw(camera);
+    if let Some(cam) = camera.camera.take() {
+        camera.runtime.block_on(async {
+            let _ = cam.logout().await;
+            let _ = cam.shutdown().await;
+        });
+        // Must be dropped inside the runtime
+        let _gt = camera.runtime.enter();
+        drop(cam);
+    }
+}
+
+/// Take a jpeg snapshot
+///
+/// On success `data` and `len` are set to a buffer that must be released with
+/// [`neolink_buffer_free`]
+///
+/// # Safety
+///
+/// `camera` must be a valid camera handle and `data`/`len` valid pointers
+#[no_mangle]
+pub unsafe extern "C" fn neolink_camera_snapshot(
+    camera: *const NeolinkCamera,
+    data: *mut *mut u8,
+    len: *mut usize,
+) -> i32 {
+    let camera = match camera_ref(camera) {
+        Some(camera) => camera,
+        None => return NEOLINK_ERR_INVALID_ARGUMENT,
+    };
+    if data.is_null() || len.is_null() {
+        return NEOLINK_ERR_INVALID_ARGUMENT;
+    }
+    let cam = camera.camera.clone().expect("Checked in camera_ref");
+    match camera.runtime.block_on(cam.get_snapshot()) {
+        Ok(jpeg) => {
+            let jpeg = jpeg.into_boxed_slice();
+            *len = jpeg.len();
+            *data = Box::into_raw(jpeg) as *mut u8;
+            NEOLINK_OK
+        }
+        Err(e) => result_code::<()>(Err(e)),
+    }
+}
+
+/// Free a buffer returned from [`neolink_camera_snapshot`]
+///
+/// # Safety
+///
+/// `data` and `len` must be exactly as returned from [`neolink_camera_snapshot`]
+#[no_mangle]
+pub unsafe extern "C" fn neolink_buffer_free(data: *mut u8, len: usize) {
+    if !data.is_null() {
+        drop(Box::from_raw(std::ptr::slice_from_raw_parts_mut(data, len)));
+    }
+}
+
+/// Move the camera, direction is `0` up, `1` down, `2` left, `3` right and `4` stop
+///
+/// # Safety
+///
+/// `camera` must be a valid camera handle
+#[no_mangle]
+pub unsafe extern "C" fn neolink_camera_ptz(
+    camera: *const NeolinkCamera,
+    direction: i32,
+    speed: f32,
+) -> i32 {
+    let camera = match camera_ref(camera) {
+        Some(camera) => camera,
+        None => return NEOLINK_ERR_INVALID_ARGUMENT,
+    };
+    let direction = match direction {
+        0 => Direction::Up,
+        1 => Direction::Down,
+        2 => Direction::Left,
+        3 => Direction::Right,
+        4 => Direction::Stop,
+        _ => return NEOLINK_ERR_INVALID_ARGUMENT,
+    };
+    let cam = camera.camera.clone().expect("Checked in camera_ref");
+    result_code(camera.runtime.block_on(cam.send_ptz(direction, speed)))
+}
+
+/// Move the camera to a saved preset
+///
+/// # Safety
+///
+/// `camera` must be a valid camera handle
+#[no_mangle]
+pub unsafe extern "C" fn neolink_camera_ptz_preset(
+    camera: *const NeolinkCamera,
+    preset_id: u8,
+) -> i32 {
+    let camera = match camera_ref(camera) {
+        Some(camera) => camera,
+        None => return NEOLINK_ERR_INVALID_ARGUMENT,
+    };
+    let cam = camera.camera.clone().expect("Checked in camera_ref");
+    result_code(camera.runtime.block_on(cam.moveto_ptz_preset(preset_id)))
+}
+
+/// Reboot the camera
+///
+/// # Safety
+///
+/// `camera` must be a valid camera handle
+#[no_mangle]
+pub unsafe extern "C" fn neolink_camera_reboot(camera: *const NeolinkCamera) -> i32 {
+    let camera = match camera_ref(camera) {
+        Some(camera) => camera,
+        None => return NEOLINK_ERR_INVALID_ARGUMENT,
+    };
+    let cam = camera.camera.clone().expect("Checked in camera_ref");
+    result_code(camera.runtime.block_on(cam.reboot()))
+}
+
+/// Start a stream, stream is `0` main, `1` sub and `2` extern
+///
+/// The callback is called on every frame and finally with
+/// `NEOLINK_FRAME_END` once the stream stops for any reason
+///
+/// Returns null on failure
+///
+/// # Safety
+///
+/// `camera` must be a valid camera handle and `user_data` must be safe to use
+/// from another thread until [`neolink_stream_stop`] returns
+#[no_mangle]
+pub unsafe extern "C" fn neolink_stream_start(
+    camera: *const NeolinkCamera,
+    stream: i32,
+    callback: NeolinkFrameCallback,
+    user_data: *mut c_void,
+) -> *mut NeolinkTask {
+    let camera = match camera_ref(camera) {
+        Some(camera) => camera,
+        None => return std::ptr::null_mut(),
+    };
+    let stream = match stream {
+        0 => StreamKind::Main,
+        1 => StreamKind::Sub,
+        2 => StreamKind::Extern,
+        _ => return std::ptr::null_mut(),
+    };
+    let cam = camera.camera.clone().expect("Checked in camera_ref");
+    let user_data = UserData(user_data);
+    let (stop_tx, mut stop_rx) = oneshot();
+    let handle = camera.runtime.spawn(async move {
+        let user_data = user_data;
+        let send = |kind: i32, data: &[u8], microseconds: u32| {
+            callback(user_data.0, kind, data.as_ptr(), data.len(), microseconds)
+        };
+        match cam.start_video(stream, 0, false).await {
+            Ok(mut data) => {
+                loop {
+                    let media = tokio::select! {
+                        _ = &mut stop_rx => break,
+                        v = data.get_data() => v,
+                    };
+                    match media {
+                        Ok(Ok(BcMedia::InfoV1(_))) | Ok(Ok(BcMedia::InfoV2(_))) => {
+                            send(NEOLINK_FRAME_INFO, &[], 0)
+                        }
+                        Ok(Ok(BcMedia::Iframe(frame))) => send(
+                            match frame.video_type {
+                                VideoType::H264 => NEOLINK_FRAME_IFRAME_H264,
+                                VideoType::H265 => NEOLINK_FRAME_IFRAME_H265,
+                            },
+                            &frame.data,
+                            frame.microseconds,
+                        ),
+                        Ok(Ok(BcMedia::Pframe(frame))) => send(
+                            match frame.video_type {
+                                VideoType::H264 => NEOLINK_FRAME_PFRAME_H264,
+                                VideoType::H265 => NEOLINK_FRAME_PFRAME_H265,
+                            },
+                            &frame.data,
+                            frame.microseconds,
+                        ),
+                        Ok(Ok(BcMedia::Aac(aac))) => send(NEOLINK_FRAME_AAC, &aac.data, 0),
+                        Ok(Ok(BcMedia::Adpcm(adpcm))) => {
+                            send(NEOLINK_FRAME_ADPCM, &adpcm.data, 0)
+                        }
+                        Ok(Err(e)) | Err(e) => {
+                            log::debug!("Stream ended: {:?}", e);
+                            break;
+                        }
+                    }
+                }
+                let _ = data.shutdown().await;
+            }
+            Err(e) => {
+                log::error!("Failed to start stream: {:?}", e);
+            }
+        }
+        callback(user_data.0, NEOLINK_FRAME_END, std::ptr::null(), 0, 0);
+    });
+
+    Box::into_raw(Box::new(NeolinkTask {
+        runtime: camera.runtime.clone(),
+        stop: Some(stop_tx),
+        handle: Some(handle),
+    }))
+}
+
+/// Stop a stream and wait for the final callback
+///
+/// # Safety
+///
+/// `stream` must be null or a pointer returned from [`neolink_stream_start`] that
+/// has not already been stopped. It must not be called from inside a callback
+#[no_mangle]
+pub unsafe extern "C" fn neolink_stream_stop(stream: *mut NeolinkTask) {
+    if !stream.is_null() {
+        drop(Box::from_raw(stream));
+    }
+}
+
+/// Listen for motion events
+///
+/// Returns null on failure
+///
+/// # Safety
+///
+/// `camera` must be a valid camera handle and `user_data` must be safe to use
+/// from another thread until [`neolink_motion_stop`] returns
+#[no_mangle]
+pub unsafe extern "C" fn neolink_motion_start(
+    camera: *const NeolinkCamera,
+    callback: NeolinkMotionCallback,
+    user_data: *mut c_void,
+) -> *mut NeolinkTask {
+    let camera = match camera_ref(camera) {
+        Some(camera) => camera,
+        None => return std::ptr::null_mut(),
+    };
+    let cam = camera.camera.clone().expect("Checked in camera_ref");
+    let user_data = UserData(user_data);
+    let (stop_tx, mut stop_rx) = oneshot();
+    let handle = camera.runtime.spawn(async move {
+        let user_data = user_data;
+        match cam.listen_on_motion().await {
+            Ok(mut motion) => loop {
+                let status = tokio::select! {
+                    _ = &mut stop_rx => break,
+                    v = motion.next_motion() => v,
+                };
+                match status {
+                    Ok(MotionStatus::Start(_)) => callback(user_data.0, 1),
+                    Ok(MotionStatus::Stop(_)) => callback(user_data.0, 0),
+                    Ok(MotionStatus::NoChange(_)) => {}
+                    Err(e) => {
+                        log::debug!("Motion ended: {:?}", e);
+                        break;
+                    }
+                }
+            },
+            Err(e) => {
+                log::error!("Failed to listen on motion: {:?}", e);
+            }
+        }
+    });
+
+    Box::into_raw(Box::new(NeolinkTask {
+        runtime: camera.runtime.clone(),
+        stop: Some(stop_tx),
+        handle: Some(handle),
+    }))
+}
+
+/// Stop listening for motion events
+///
+/// # Safety
+///
+/// `motion` must be null or a pointer returned from [`neolink_motion_start`] that
+/// has not already been stopped. It must not be called from inside a callback
+#[no_mangle]
+pub unsafe extern "C" fn neolink_motion_stop(motion: *mut NeolinkTask) {
+    if !motion.is_null() {
+        drop(Box::from_raw(motion));
+    }
+}
@@ -0,0 +1,35 @@
+#![warn(unused_crate_dependencies)]
+#![warn(missing_docs)]
+//! # Neolink-Python
+//!
+//! Python bindings for [`neolink_core`]
+//!
+//! The bindings are only compiled with the `python` feature
+//! enabled. They are best built with [maturin](https://www.maturin.rs)
+//!
+//! ```bash
+//! maturin develop -m crates/python/Cargo.toml --features python
+//! ```
+//!
+//! Once built the camera can be used from python with
+//!
+//! ```python
+//! import neolink
+//!
+//! cam = neolink.Camera.connect("Garage", "admin", password="pass", address="192.168.1.10")
+//! print(cam.version())
+//! with open("snap.jpeg", "wb") as f:
+//!     f.write(cam.snapshot())
+//!
+//! cam.ptz("left", 32)
+//!
+//! for event in cam.events():
+//!     print(event)
+//!
+//! for frame in cam.stream("sub"):
+//!     print(frame["kind"], len(frame["data"]))
+//! ```
+//!
+
+#[cfg(feature = "python")]
+mod python;
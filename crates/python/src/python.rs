@@ -0,0 +1,385 @@
+//! The pyo3 module definition
+//!
+//! Each python object holds a handle on a shared tokio runtime. Calls
+//! into the camera release the GIL and block on that runtime so that
+//! other python threads can continue while we wait on the camera.
+use neolink_core::{
+    bc_protocol::{
+        BcCamera, BcCameraOpt, ConnectionProtocol, Credentials, Direction, DiscoveryMethods,
+        MotionData, MotionStatus, StreamData, StreamKind,
+    },
+    bcmedia::model::{BcMedia, VideoType},
+};
+use pyo3::{
+    exceptions::{PyRuntimeError, PyStopIteration, PyValueError},
+    prelude::*,
+    types::{PyBytes, PyDict},
+};
+use std::{
+    net::{IpAddr, ToSocketAddrs},
+    str::FromStr,
+    sync::Arc,
+};
+use tokio::runtime::Runtime;
+
+fn to_pyerr(e: neolink_core::Error) -> PyErr {
+    PyRuntimeError::new_err(e.to_string())
+}
+
+fn parse_discovery(src: &str) -> PyResult<DiscoveryMethods> {
+    match src.to_lowercase().as_str() {
+        "none" => Ok(DiscoveryMethods::None),
+        "local" => Ok(DiscoveryMethods::Local),
+        "remote" => Ok(DiscoveryMethods::Remote),
+        "map" => Ok(DiscoveryMethods::Map),
+        "relay" => Ok(DiscoveryMethods::Relay),
+        "cellular" => Ok(DiscoveryMethods::Cellular),
+        _ => Err(PyValueError::new_err(format!(
+            "Unknown discovery method {}, should be one of none, local, remote, map, relay or cellular",
+            src
+        ))),
+    }
+}
+
+fn parse_stream(src: &str) -> PyResult<StreamKind> {
+    match src.to_lowercase().as_str() {
+        "main" | "mainstream" => Ok(StreamKind::Main),
+        "sub" | "substream" => Ok(StreamKind::Sub),
+        "extern" | "externstream" => Ok(StreamKind::Extern),
+        _ => Err(PyValueError::new_err(format!(
+            "Unknown stream {}, should be one of main, sub or extern",
+            src
+        ))),
+    }
+}
+
+fn parse_direction(src: &str) -> PyResult<Direction> {
+    match src.to_lowercase().as_str() {
+        "up" => Ok(Direction::Up),
+        "down" => Ok(Direction::Down),
+        "left" => Ok(Direction::Left),
+        "right" => Ok(Direction::Right),
+        "stop" => Ok(Direction::Stop),
+        _ => Err(PyValueError::new_err(format!(
+            "Unknown direction {}, should be one of up, down, left, right or stop",
+            src
+        ))),
+    }
+}
+
+fn parse_address(src: Option<&str>) -> PyResult<(Option<u16>, Vec<IpAddr>)> {
+    if let Some(addr_str) = src {
+        match addr_str.to_socket_addrs() {
+            Ok(addr_iter) => {
+                let mut port = None;
+                let mut ipaddrs = vec![];
+                for addr in addr_iter {
+                    port = Some(addr.port());
+                    ipaddrs.push(addr.ip());
+                }
+                Ok((port, ipaddrs))
+            }
+            Err(_) => match IpAddr::from_str(addr_str) {
+                Ok(ip) => Ok((None, vec![ip])),
+                Err(_) => Err(PyValueError::new_err(format!(
+                    "Could not parse address {}",
+                    addr_str
+                ))),
+            },
+        }
+    } else {
+        Ok((None, vec![]))
+    }
+}
+
+/// A connected and logged in camera
+#[pyclass(module = "neolink")]
+struct Camera {
+    runtime: Arc<Runtime>,
+    // Option so that we can drop it inside the runtime
+    camera: Option<Arc<BcCamera>>,
+}
+
+impl Camera {
+    fn camera(&self) -> PyResult<Arc<BcCamera>> {
+        self.camera
+            .clone()
+            .ok_or_else(|| PyRuntimeError::new_err("Camera is closed"))
+    }
+}
+
+#[pymethods]
+impl Camera {
+    /// Connect and login to a camera
+    ///
+    /// Either an address or a uid must be given
+    #[staticmethod]
+    #[pyo3(signature = (name, username, password=None, address=None, uid=None, channel_id=0, discovery="relay"))]
+    #[allow(clippy::too_many_arguments)]
+    fn connect(
+        py: Python<'_>,
+        name: String,
+        username: String,
+        password: Option<String>,
+        address: Option<&str>,
+        uid: Option<String>,
+        channel_id: u8,
+        discovery: &str,
+    ) -> PyResult<Self> {
+        if address.is_none() && uid.is_none() {
+            return Err(PyValueError::new_err(
+                "Either camera address or uid must be given",
+            ));
+        }
+        let (port, addrs) = parse_address(address)?;
+        let options = BcCameraOpt {
+            name,
+            channel_id,
+            addrs,
+            port,
+            uid,
+            protocol: ConnectionProtocol::TcpUdp,
+            discovery: parse_discovery(discovery)?,
+            credentials: Credentials {
+                username,
+                password,
+            },
+            debug: false,
+            max_discovery_retries: 10,
+            udp: Default::default(),
+            servers: Default::default(),
+        };
+
+        let runtime = Arc::new(
+            tokio::runtime::Builder::new_multi_thread()
+                .enable_all()
+                .build()
+                .map_err(|e| PyRuntimeError::new_err(e.to_string()))?,
+        );
+        let camera = py.allow_threads(|| {
+            runtime.block_on(async {
+                let camera = BcCamera::new(&options).await?;
+                camera.login().await?;
+                Ok::<_, neolink_core::Error>(camera)
+            })
+        });
+        let camera = camera.map_err(to_pyerr)?;
+
+        Ok(Self {
+            runtime,
+            camera: Some(Arc::new(camera)),
+        })
+    }
+
+    /// Get the model and firmware version as a tuple
+    fn version(&self, py: Python<'_>) -> PyResult<(Option<String>, String)> {
+        let camera = self.camera()?;
+        let version = py
+            .allow_threads(|| self.runtime.block_on(camera.version()))
+            .map_err(to_pyerr)?;
+        Ok((version.model, version.firmwareVersion))
+    }
+
+    /// Take a snapshot and return it as jpeg bytes
+    fn snapshot(&self, py: Python<'_>) -> PyResult<Py<PyBytes>> {
+        let camera = self.camera()?;
+        let jpeg = py
+            .allow_threads(|| self.runtime.block_on(camera.get_snapshot()))
+            .map_err(to_pyerr)?;
+        Ok(PyBytes::new_bound(py, &jpeg).unbind())
+    }
+
+    /// Move the camera in a direction at the given speed
+    #[pyo3(signature = (direction, amount=32.0))]
+    fn ptz(&self, py: Python<'_>, direction: &str, amount: f32) -> PyResult<()> {
+        let camera = self.camera()?;
+        let direction = parse_direction(direction)?;
+        py.allow_threads(|| self.runtime.block_on(camera.send_ptz(direction, amount)))
+            .map_err(to_pyerr)
+    }
+
+    /// Move the camera to a saved preset
+    fn ptz_preset(&self, py: Python<'_>, preset_id: u8) -> PyResult<()> {
+        let camera = self.camera()?;
+        py.allow_threads(|| self.runtime.block_on(camera.moveto_ptz_preset(preset_id)))
+            .map_err(to_pyerr)
+    }
+
+    /// Listen for motion events
+    ///
+    /// The returned object is an iterator that blocks until the
+    /// next event
+    fn events(&self, py: Python<'_>) -> PyResult<Events> {
+        let camera = self.camera()?;
+        let motion = py
+            .allow_threads(|| self.runtime.block_on(camera.listen_on_motion()))
+            .map_err(to_pyerr)?;
+        Ok(Events {
+            runtime: self.runtime.clone(),
+            motion: Some(motion),
+        })
+    }
+
+    /// Start a stream
+    ///
+    /// The returned object is an iterator of frames. Each frame is a
+    /// dict with `kind`, `codec`, `microseconds` and `data`
+    #[pyo3(signature = (stream="main", buffer_size=100))]
+    fn stream(&self, py: Python<'_>, stream: &str, buffer_size: usize) -> PyResult<Frames> {
+        let camera = self.camera()?;
+        let stream = parse_stream(stream)?;
+        let data = py
+            .allow_threads(|| {
+                self.runtime
+                    .block_on(camera.start_video(stream, buffer_size, false))
+            })
+            .map_err(to_pyerr)?;
+        Ok(Frames {
+            runtime: self.runtime.clone(),
+            stream: Some(data),
+        })
+    }
+
+    /// Logout and disconnect from the camera
+    fn close(&mut self, py: Python<'_>) -> PyResult<()> {
+        if let Some(camera) = self.camera.take() {
+            py.allow_threads(|| {
+                self.runtime.block_on(async {
+                    let _ = camera.logout().await;
+                    camera.shutdown().await
+                })
+            })
+            .map_err(to_pyerr)?;
+        }
+        Ok(())
+    }
+}
+
+impl Drop for Camera {
+    fn drop(&mut self) {
+        // The camera spawns tasks on drop so it must be dropped inside the runtime
+        let _gt = self.runtime.enter();
+        drop(self.camera.take());
+    }
+}
+
+/// An iterator of motion events
+#[pyclass(module = "neolink")]
+struct Events {
+    runtime: Arc<Runtime>,
+    motion: Option<MotionData>,
+}
+
+#[pymethods]
+impl Events {
+    fn __iter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
+    }
+
+    fn __next__(mut slf: PyRefMut<'_, Self>) -> PyResult<&'static str> {
+        let py = slf.py();
+        let this = &mut *slf;
+        let runtime = this.runtime.clone();
+        let motion = this.motion.as_mut().ok_or_else(|| PyStopIteration::new_err(()))?;
+        let status = py
+            .allow_threads(|| runtime.block_on(motion.next_motion()))
+            .map_err(to_pyerr)?;
+        Ok(match status {
+            MotionStatus::Start(_) => "start",
+            MotionStatus::Stop(_) => "stop",
+            MotionStatus::NoChange(_) => "other",
+        })
+    }
+}
+
+impl Drop for Events {
+    fn drop(&mut self) {
+        let _gt = self.runtime.enter();
+        drop(self.motion.take());
+    }
+}
+
+/// An iterator of stream frames
+#[pyclass(module = "neolink")]
+struct Frames {
+    runtime: Arc<Runtime>,
+    stream: Option<StreamData>,
+}
+
+#[pymethods]
+impl Frames {
+    fn __iter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
+    }
+
+    fn __next__(mut slf: PyRefMut<'_, Self>) -> PyResult<PyObject> {
+        let py = slf.py();
+        let this = &mut *slf;
+        let runtime = this.runtime.clone();
+        let stream = this.stream.as_mut().ok_or_else(|| PyStopIteration::new_err(()))?;
+        let media = py
+            .allow_threads(|| runtime.block_on(stream.get_data()))
+            .map_err(to_pyerr)?
+            .map_err(to_pyerr)?;
+
+        let codec = |video_type: &VideoType| match video_type {
+            VideoType::H264 => "h264",
+            VideoType::H265 => "h265",
+        };
+        let frame = PyDict::new_bound(py);
+        match media {
+            BcMedia::InfoV1(info) => {
+                frame.set_item("kind", "info")?;
+                frame.set_item("width", info.video_width)?;
+                frame.set_item("height", info.video_height)?;
+                frame.set_item("fps", info.fps)?;
+            }
+            BcMedia::InfoV2(info) => {
+                frame.set_item("kind", "info")?;
+                frame.set_item("width", info.video_width)?;
+                frame.set_item("height", info.video_height)?;
+                frame.set_item("fps", info.fps)?;
+            }
+            BcMedia::Iframe(iframe) => {
+                frame.set_item("kind", "iframe")?;
+                frame.set_item("codec", codec(&iframe.video_type))?;
+                frame.set_item("microseconds", iframe.microseconds)?;
+                frame.set_item("time", iframe.time)?;
+                frame.set_item("data", PyBytes::new_bound(py, &iframe.data))?;
+            }
+            BcMedia::Pframe(pframe) => {
+                frame.set_item("kind", "pframe")?;
+                frame.set_item("codec", codec(&pframe.video_type))?;
+                frame.set_item("microseconds", pframe.microseconds)?;
+                frame.set_item("data", PyBytes::new_bound(py, &pframe.data))?;
+            }
+            BcMedia::Aac(aac) => {
+                frame.set_item("kind", "audio")?;
+                frame.set_item("codec", "aac")?;
+                frame.set_item("data", PyBytes::new_bound(py, &aac.data))?;
+            }
+            BcMedia::Adpcm(adpcm) => {
+                frame.set_item("kind", "audio")?;
+                frame.set_item("codec", "adpcm")?;
+                frame.set_item("data", PyBytes::new_bound(py, &adpcm.data))?;
+            }
+        }
+        Ok(frame.into_any().unbind())
+    }
+}
+
+impl Drop for Frames {
+    fn drop(&mut self) {
+        let _gt = self.runtime.enter();
+        drop(self.stream.take());
+    }
+}
+
+/// Python bindings for neolink_core
+#[pymodule]
+fn neolink(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_class::<Camera>()?;
+    m.add_class::<Events>()?;
+    m.add_class::<Frames>()?;
+    Ok(())
+}
@@ -0,0 +1,11 @@
+use clap::Parser;
+
+/// The capabilities command prints what this build of neolink supports as JSON
+///
+/// This does not need a config or a camera
+#[derive(Parser, Debug)]
+pub struct Opt {
+    /// Print the JSON on one line
+    #[arg(long)]
+    pub compact: bool,
+}
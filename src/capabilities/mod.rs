@@ -0,0 +1,96 @@
+///
+/// # Neolink Capabilities
+///
+/// This module handles the capabilities subcommand
+///
+/// It prints the commands, camera config options and protocol messages
+/// that this build supports as JSON so that frontends and scripts can
+/// adapt to older versions of neolink. It does not need a config or a camera
+///
+/// # Usage
+///
+/// ```bash
+/// neolink capabilities
+/// ```
+///
+/// Which prints something like
+///
+/// ```json
+/// {
+///   "version": "v0.6.3 (abcdef0)",
+///   "commands": ["rtsp", "mqtt", ...],
+///   "camera_options": ["address", "buffer_duration", ...],
+///   "messages": [{"id": 1, "name": "Login"}, ...]
+/// }
+/// ```
+///
+use anyhow::{Context, Result};
+use clap::CommandFactory;
+use neolink_core::bc::model::MSG_ID_NAMES;
+use serde::Serialize;
+
+mod cmdline;
+
+use crate::{cmdline::Opt as NeolinkOpt, config::CameraConfig};
+pub(crate) use cmdline::Opt;
+
+#[derive(Serialize)]
+struct Capabilities {
+    version: &'static str,
+    commands: Vec<String>,
+    camera_options: Vec<String>,
+    messages: Vec<Message>,
+}
+
+#[derive(Serialize)]
+struct Message {
+    id: u32,
+    name: &'static str,
+}
+
+/// Entry point for the capabilities subcommand
+///
+/// Opt is the command line options
+pub(crate) fn main(opt: Opt) -> Result<()> {
+    let capabilities = Capabilities {
+        version: env!("NEOLINK_VERSION"),
+        commands: NeolinkOpt::command()
+            .get_subcommands()
+            .map(|cmd| cmd.get_name().to_string())
+            .collect(),
+        camera_options: camera_options()?,
+        messages: MSG_ID_NAMES
+            .iter()
+            .map(|(id, name)| Message { id: *id, name })
+            .collect(),
+    };
+
+    let json = if opt.compact {
+        serde_json::to_string(&capabilities)?
+    } else {
+        serde_json::to_string_pretty(&capabilities)?
+    };
+    println!("{}", json);
+    Ok(())
+}
+
+/// The keys of a camera in the config
+///
+/// These are found from the defaults of a minimal camera so that
+/// they are always those of this build
+fn camera_options() -> Result<Vec<String>> {
+    let camera: CameraConfig = toml::from_str(
+        r#"
+        name = "capabilities"
+        username = "admin"
+        address = "127.0.0.1"
+        "#,
+    )
+    .context("The minimal camera config should parse")?;
+    let mut options = match serde_json::to_value(&camera)? {
+        serde_json::Value::Object(map) => map.keys().cloned().collect::<Vec<_>>(),
+        _ => vec![],
+    };
+    options.sort();
+    Ok(options)
+}
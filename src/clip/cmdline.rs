@@ -0,0 +1,22 @@
+use clap::Parser;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+/// The clip command records a clip of a camera's stream to a file and exits
+#[derive(Parser, Debug)]
+pub struct Opt {
+    /// The name of the camera. Must be a name in the config
+    pub camera: String,
+    /// Seconds of video in the clip
+    #[arg(short, long, default_value_t = 30, value_parser = clap::value_parser!(u64).range(1..))]
+    pub duration: u64,
+    /// The file to write, `.mkv` for Matroska otherwise MP4
+    #[arg(short, long, value_parser = PathBuf::from_str)]
+    pub output: PathBuf,
+    /// Record the substream instead of the main stream
+    #[arg(long)]
+    pub sub: bool,
+    /// Leave out the audio
+    #[arg(long)]
+    pub no_audio: bool,
+}
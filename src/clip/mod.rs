@@ -0,0 +1,273 @@
+///
+/// # Neolink Clip
+///
+/// This module records a clip of a camera's stream to a file and exits, such
+/// as for a timelapse taken from cron without a neolink running all of the
+/// time
+///
+/// The clip starts on the next keyframe and is `--duration` seconds long. The
+/// frames are timed from the camera's stream rather than from when they
+/// arrived, so a clip plays smoothly even when the network is not
+///
+/// # Usage
+///
+/// ```bash
+/// neolink clip --config=config.toml CameraName --duration=30 --output=clip.mp4
+/// # The substream without audio as Matroska
+/// neolink clip --config=config.toml CameraName --sub --no-audio -o clip.mkv
+/// ```
+///
+use anyhow::{anyhow, Context, Result};
+use futures::stream::StreamExt;
+use gstreamer::{
+    parse::launch_full, prelude::*, ClockTime, MessageView, ParseFlags, Pipeline, State,
+};
+use gstreamer_app::AppSrc;
+use neolink_core::bc_protocol::StreamKind;
+use std::path::Path;
+use tokio::{
+    task::JoinSet,
+    time::{timeout, Duration},
+};
+use tokio_stream::wrappers::BroadcastStream;
+
+mod cmdline;
+
+use crate::{
+    common::{AudFormat, NeoReactor, StampedData, VidFormat},
+    config::RecordFormat,
+    AnyResult,
+};
+pub(crate) use cmdline::Opt;
+
+/// Entry point for the clip subcommand
+///
+/// Opt is the command line options
+pub(crate) async fn main(opt: Opt, reactor: NeoReactor) -> Result<()> {
+    let format = if opt
+        .output
+        .extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case(RecordFormat::Mkv.extension()))
+    {
+        RecordFormat::Mkv
+    } else {
+        RecordFormat::Mp4
+    };
+    let camera = reactor.get(&opt.camera).await?;
+    let kind = if opt.sub {
+        StreamKind::Sub
+    } else {
+        StreamKind::Main
+    };
+    let mut stream = camera.stream(kind).await.context("Failed to start video")?;
+    stream.config.wait_for(|config| config.vid_ready()).await?;
+    // After vid give it 1s to look for audio
+    if let Ok(v) = timeout(
+        Duration::from_secs(1),
+        stream.config.wait_for(|config| config.aud_ready()),
+    )
+    .await
+    {
+        v?;
+    }
+    let stream_config = stream.config.borrow().clone();
+    // Only AAC can be put into the containers as is
+    let audio = !opt.no_audio && matches!(stream_config.aud_format, AudFormat::Aac);
+
+    let (pipeline, vidsrc, audsrc) =
+        create_pipeline(stream_config.vid_format, audio, format, &opt.output)?;
+    let mut set = JoinSet::<AnyResult<()>>::new();
+    let thread_pipeline = pipeline.clone();
+    set.spawn_blocking(move || start_pipeline(thread_pipeline));
+    log::info!(
+        "{}: Recording {}s of the {:?} stream to {}",
+        opt.camera,
+        opt.duration,
+        kind,
+        opt.output.display()
+    );
+
+    let duration = Duration::from_secs(opt.duration);
+    let mut vid = BroadcastStream::new(stream.vid.resubscribe());
+    let mut aud = BroadcastStream::new(stream.aud.resubscribe());
+    let res = tokio::select! {
+        v = async {
+            // The time of the keyframe that the clip starts on
+            let mut start = None;
+            let mut aud_started = false;
+            loop {
+                tokio::select! {
+                    data = vid.next() => match data {
+                        Some(Ok(StampedData { data, keyframe, ts })) => {
+                            if start.is_none() && keyframe {
+                                start = Some(ts);
+                            }
+                            if let Some(start) = start {
+                                let pts = ts.saturating_sub(start);
+                                if pts >= duration {
+                                    return Ok(());
+                                }
+                                push(&vidsrc, &data, Some(pts))?;
+                            }
+                        }
+                        // Lagged, the muxer copes with a gap
+                        Some(Err(_)) => {}
+                        None => return Err(anyhow!("The stream ended")),
+                    },
+                    data = aud.next(), if audsrc.is_some() => match (data, audsrc.as_ref(), start) {
+                        (Some(Ok(StampedData { data, ts, .. })), Some(audsrc), Some(start)) => {
+                            // Every audio frame of a video frame has its time
+                            // so aacparse times the rest from the first
+                            let pts = (!aud_started).then(|| ts.saturating_sub(start));
+                            aud_started = true;
+                            push(audsrc, &data, pts)?;
+                        }
+                        (None, _, _) => return Err(anyhow!("The audio ended")),
+                        _ => {}
+                    },
+                }
+            }
+        } => v,
+        v = stream.config.wait_for(|config| config != &stream_config) => {
+            v?;
+            Err(anyhow!("The stream format changed"))
+        },
+        v = set.join_next() => v
+            .unwrap_or(Ok(Ok(())))
+            .map_err(anyhow::Error::from)
+            .and_then(|v| v)
+            .and_then(|_| Err(anyhow!("The muxer stopped"))),
+    };
+
+    // An mp4 can only be played once the muxer has finished it
+    let _ = vidsrc.end_of_stream();
+    if let Some(audsrc) = audsrc.as_ref() {
+        let _ = audsrc.end_of_stream();
+    }
+    if timeout(Duration::from_secs(10), async {
+        while let Some(v) = set.join_next().await {
+            if let Ok(Err(e)) = v {
+                log::warn!("{}: Failed to finish the clip: {:?}", opt.camera, e);
+            }
+        }
+    })
+    .await
+    .is_err()
+    {
+        log::warn!("{}: The clip was not finished in time", opt.camera);
+    }
+    let _ = pipeline.set_state(State::Null);
+    while set.join_next().await.is_some() {}
+
+    res.with_context(|| format!("{}: Recording the clip failed", opt.camera))?;
+    log::info!("{}: Saved the clip to {}", opt.camera, opt.output.display());
+    Ok(())
+}
+
+fn push(source: &AppSrc, data: &[u8], pts: Option<Duration>) -> AnyResult<()> {
+    let mut buf = gstreamer::Buffer::with_size(data.len())?;
+    {
+        let buf = buf
+            .get_mut()
+            .ok_or_else(|| anyhow!("New buffer should be writable"))?;
+        buf.set_pts(pts.map(|pts| ClockTime::from_nseconds(pts.as_nanos() as u64)));
+        buf.copy_from_slice(0, data)
+            .map_err(|_| anyhow!("Failed to copy the frame"))?;
+    }
+    source
+        .push_buffer(buf)
+        .map_err(|e| anyhow!("Streamer Error: {e:?}"))?;
+    Ok(())
+}
+
+fn start_pipeline(pipeline: Pipeline) -> AnyResult<()> {
+    pipeline.set_state(State::Playing)?;
+
+    let bus = pipeline
+        .bus()
+        .expect("Pipeline without bus. Shouldn't happen!");
+
+    let res = loop {
+        match bus.timed_pop(ClockTime::from_mseconds(500)) {
+            Some(msg) => match msg.view() {
+                MessageView::Eos(..) => break Ok(()),
+                MessageView::Error(err) => {
+                    break Err(anyhow!("Error from gstreamer in the muxer: {:?}", err));
+                }
+                _ => (),
+            },
+            None if pipeline.current_state() == State::Null => break Ok(()),
+            None => (),
+        }
+    };
+
+    pipeline
+        .set_state(State::Null)
+        .context("Error in gstreamer when setting state to Null")?;
+    res
+}
+
+/// Mux the stream into the file
+fn create_pipeline(
+    vid_format: VidFormat,
+    audio: bool,
+    format: RecordFormat,
+    output: &Path,
+) -> AnyResult<(Pipeline, AppSrc, Option<AppSrc>)> {
+    gstreamer::init()
+        .context("Unable to start gstreamer ensure it and all plugins are installed")?;
+
+    let parser = match vid_format {
+        VidFormat::H264 => "h264parse",
+        VidFormat::H265 => "h265parse",
+        VidFormat::None => unreachable!(),
+    };
+    // The frames are timed by push so the sources are not live
+    let mut launch_str = format!(
+        "{} name=mux ! filesink location=\"{}\" \
+        appsrc name=vidsrc format=time \
+        ! {} \
+        ! queue \
+        ! mux.",
+        format.muxer(),
+        output.display(),
+        parser,
+    );
+    if audio {
+        launch_str.push_str(
+            " appsrc name=audsrc format=time \
+            ! aacparse \
+            ! queue \
+            ! mux.",
+        );
+    }
+    log::debug!("{}", launch_str);
+
+    let pipeline = launch_full(&launch_str, None, ParseFlags::empty())
+        .context("Unable to load gstreamer pipeline ensure all gstramer plugins are installed")?
+        .dynamic_cast::<Pipeline>()
+        .map_err(|_| {
+            anyhow!("Unable to create gstreamer pipeline ensure all gstramer plugins are installed")
+        })?;
+
+    let vidsrc = pipeline
+        .by_name("vidsrc")
+        .expect("There shoud be a `vidsrc`")
+        .dynamic_cast::<AppSrc>()
+        .map_err(|_| anyhow!("Cannot find appsource in gstreamer, check your gstreamer plugins"))?;
+    let audsrc = if audio {
+        Some(
+            pipeline
+                .by_name("audsrc")
+                .expect("There shoud be an `audsrc`")
+                .dynamic_cast::<AppSrc>()
+                .map_err(|_| {
+                    anyhow!("Cannot find appsource in gstreamer, check your gstreamer plugins")
+                })?,
+        )
+    } else {
+        None
+    };
+
+    Ok((pipeline, vidsrc, audsrc))
+}
@@ -11,21 +11,62 @@ use std::str::FromStr;
 pub struct Opt {
     #[arg(short, long, global = true, value_parser = PathBuf::from_str)]
     pub config: Option<PathBuf>,
+    /// Print the config as neolink sees it, with defaults filled in and passwords redacted, then exit
+    #[arg(long, global = true)]
+    pub dump_config: bool,
     #[structopt(subcommand)]
     pub cmd: Option<Command>,
 }
 
 #[derive(Parser, Debug)]
 pub enum Command {
+    #[cfg(feature = "gstreamer")]
     Rtsp(super::rtsp::Opt),
+    Serve(super::serve::Opt),
     StatusLight(super::statusled::Opt),
     Reboot(super::reboot::Opt),
     Pir(super::pir::Opt),
+    Ir(super::ir::Opt),
     Ptz(super::ptz::Opt),
+    #[cfg(feature = "gstreamer")]
     Talk(super::talk::Opt),
     Mqtt(super::mqtt::Opt),
+    #[cfg(feature = "gstreamer")]
     MqttRtsp(super::mqtt::Opt),
+    #[cfg(feature = "gstreamer")]
     Image(super::image::Opt),
     Battery(super::battery::Opt),
     Services(super::services::Opt),
+    Decode(super::decode::Opt),
+    Init(super::init::Opt),
+    Name(super::name::Opt),
+    Osd(super::osd::Opt),
+    Status(super::status::Opt),
+    Diagnose(super::diagnose::Opt),
+    PrivacyMask(super::privacymask::Opt),
+    Selftest(super::selftest::Opt),
+    Capabilities(super::capabilities::Opt),
+    Onvif(super::onvif::Opt),
+    Http(super::http::Opt),
+    #[cfg(feature = "gstreamer")]
+    Intercom(super::intercom::Opt),
+    #[cfg(feature = "gstreamer")]
+    Record(super::record::Opt),
+    #[cfg(feature = "gstreamer")]
+    Download(super::download::Opt),
+    Info(super::info::Opt),
+    Floodlight(super::floodlight::Opt),
+    Siren(super::siren::Opt),
+    Ctl(super::ctl::Opt),
+    #[cfg(feature = "gstreamer")]
+    Stream(super::stream::Opt),
+    #[cfg(feature = "gstreamer")]
+    Webrtc(super::webrtc::Opt),
+    #[cfg(feature = "gstreamer")]
+    Hls(super::hls::Opt),
+    #[cfg(feature = "gstreamer")]
+    Clip(super::clip::Opt),
+    Motion(super::motion::Opt),
+    #[cfg(feature = "gstreamer")]
+    Timelapse(super::timelapse::Opt),
 }
@@ -0,0 +1,112 @@
+//! The audit log of the control actions
+//!
+//! When several people and automations share the cameras it is useful to know
+//! who rebooted a camera or moved it. Each control action, such as a reboot,
+//! a PTZ move or a PIR change, is written to the `[audit]` file as a line of
+//! JSON with the camera, the action and where it came from
+//!
+//! The file is rotated by the `[rotation]` config, see [`super::logfile`]
+use once_cell::sync::OnceCell;
+use serde::Serialize;
+use std::{fs::OpenOptions, path::PathBuf};
+use time::{format_description::well_known::Rfc3339, OffsetDateTime};
+
+use super::logfile;
+use crate::{config::AuditConfig, AnyResult};
+
+static AUDIT_FILE: OnceCell<PathBuf> = OnceCell::new();
+
+/// A line of the audit log
+#[derive(Serialize)]
+struct AuditEntry<'a> {
+    time: String,
+    camera: &'a str,
+    /// Such as `reboot`, `ptz` or `pir`
+    action: &'a str,
+    /// The arguments of the action such as `on`
+    args: &'a str,
+    /// `cli` or `mqtt`
+    source: &'a str,
+    /// The user that ran the command line or the topic of the MQTT message
+    who: &'a str,
+    /// `ok`, `failed` or `requested` when the result is not known
+    result: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+/// Open the audit log, without this nothing is logged
+pub(crate) fn init(config: &AuditConfig) -> AnyResult<()> {
+    // Fail now rather than on the first action if the file cannot be written
+    OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&config.path)?;
+    let _ = AUDIT_FILE.set(config.path.clone());
+    Ok(())
+}
+
+/// Log a control action of the command line
+pub(crate) fn cli<T>(camera: &str, action: &str, args: &str, result: &AnyResult<T>) {
+    let who = std::env::var("USER")
+        .or_else(|_| std::env::var("USERNAME"))
+        .unwrap_or_default();
+    let (result, error) = match result {
+        Ok(_) => ("ok", None),
+        Err(e) => ("failed", Some(format!("{:#}", e))),
+    };
+    write(AuditEntry {
+        time: now(),
+        camera,
+        action,
+        args,
+        source: "cli",
+        who: &who,
+        result,
+        error,
+    });
+}
+
+/// Log a control action of an MQTT message
+///
+/// The topic is relative to the camera such as `control/reboot`, the camera
+/// is empty for the topics of all cameras such as `control/alarm`
+pub(crate) fn mqtt(camera: &str, topic: &str, message: &str) {
+    let action = topic.strip_prefix("control/").unwrap_or(topic);
+    let who = if camera.is_empty() {
+        format!("neolink/{}", topic)
+    } else {
+        format!("neolink/{}/{}", camera, topic)
+    };
+    write(AuditEntry {
+        time: now(),
+        camera,
+        action,
+        args: message,
+        source: "mqtt",
+        who: &who,
+        result: "requested",
+        error: None,
+    });
+}
+
+fn now() -> String {
+    let now = OffsetDateTime::now_utc();
+    now.format(&Rfc3339).unwrap_or_else(|_| now.to_string())
+}
+
+fn write(entry: AuditEntry) {
+    let Some(file) = AUDIT_FILE.get() else {
+        return;
+    };
+    let line = match serde_json::to_string(&entry) {
+        Ok(line) => line,
+        Err(e) => {
+            log::warn!("Audit: Failed to serialise the entry: {:?}", e);
+            return;
+        }
+    };
+    if let Err(e) = logfile::append_line(file, &line) {
+        log::warn!("Audit: Failed to write the audit log: {:?}", e);
+    }
+}
@@ -0,0 +1,90 @@
+//! A lock on the camera shared between neolink processes
+//!
+//! Cameras only allow a limited number of clients. When two neolink
+//! commands are run at the same time against the same camera they can
+//! fight over the session. If `lock` is enabled in the camera config
+//! a file lock is taken before connecting, so that concurrent invocations
+//! wait for their turn rather than both failing.
+//!
+//! The lock file is placed in the temp dir and is named after the
+//! uid or address of the camera so that it is shared even if the
+//! camera has a different name in another config
+use anyhow::Context;
+use std::{fs::File, io::Write, path::PathBuf};
+use tokio::time::{sleep, Duration, Instant};
+
+use crate::{config::CameraConfig, AnyResult};
+
+pub(crate) struct CameraLock {
+    _file: File,
+}
+
+impl CameraLock {
+    fn path(config: &CameraConfig) -> PathBuf {
+        let key = config
+            .camera_uid
+            .as_ref()
+            .or(config.camera_addr.as_ref())
+            .unwrap_or(&config.name);
+        let key = key
+            .chars()
+            .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+            .collect::<String>();
+        std::env::temp_dir().join(format!("neolink-{}-{}.lock", key, config.channel_id))
+    }
+
+    /// Wait until this process holds the lock for the camera
+    pub(crate) async fn acquire(config: &CameraConfig) -> AnyResult<Self> {
+        let path = Self::path(config);
+        let mut file = std::fs::OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .read(true)
+            .write(true)
+            .open(&path)
+            .with_context(|| format!("Could not open the camera lock {:?}", path))?;
+
+        let mut last_log: Option<Instant> = None;
+        while !try_lock(&file)? {
+            if last_log.map(|t| t.elapsed() > Duration::from_secs(30)).unwrap_or(true) {
+                let holder = std::fs::read_to_string(&path).unwrap_or_default();
+                log::info!(
+                    "{}: Camera is in use by another neolink (pid {}), waiting for it to finish",
+                    config.name,
+                    holder.trim()
+                );
+                last_log = Some(Instant::now());
+            }
+            sleep(Duration::from_secs(1)).await;
+        }
+        log::debug!("{}: Acquired camera lock {:?}", config.name, path);
+
+        file.set_len(0)?;
+        write!(file, "{}", std::process::id())?;
+        Ok(Self { _file: file })
+    }
+}
+
+/// The lock is released by the OS when the file is closed
+#[cfg(unix)]
+fn try_lock(file: &File) -> AnyResult<bool> {
+    use std::os::unix::io::AsRawFd;
+    // SAFETY: The fd is valid for the lifetime of file
+    let res = unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX | libc::LOCK_NB) };
+    if res == 0 {
+        Ok(true)
+    } else {
+        let err = std::io::Error::last_os_error();
+        if err.kind() == std::io::ErrorKind::WouldBlock {
+            Ok(false)
+        } else {
+            Err(err).context("Could not lock the camera lock file")
+        }
+    }
+}
+
+#[cfg(not(unix))]
+fn try_lock(_file: &File) -> AnyResult<bool> {
+    log::debug!("Camera locks are only supported on unix");
+    Ok(true)
+}
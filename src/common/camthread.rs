@@ -1,12 +1,41 @@
-use std::sync::{Arc, Weak};
+use serde::Serialize;
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Weak},
+};
+use time::{OffsetDateTime, UtcOffset};
 use tokio::{
     sync::watch::{Receiver as WatchReceiver, Sender as WatchSender},
-    time::{interval, sleep, timeout, Duration, Instant},
+    time::{interval, sleep, timeout, Duration, Instant, MissedTickBehavior},
 };
 use tokio_util::sync::CancellationToken;
 
-use crate::{config::CameraConfig, utils::connect_and_login, AnyResult};
-use neolink_core::bc_protocol::BcCamera;
+use super::{sleep_until_wall, CameraError, CameraLock, ConnectGate, ErrorHistory, Wake};
+use crate::{
+    config::CameraConfig,
+    utils::{connect_and_login, probe_transport, Backoff},
+    AnyResult,
+};
+use neolink_core::bc_protocol::{BcCamera, Transport};
+
+/// Why a camera connection ended without an error
+enum CameraExit {
+    /// The connection was closed or the thread cancelled
+    Shutdown,
+    /// The connection was poor and a better transport was found
+    SwitchTransport,
+}
+
+/// How far the camera's clock was from the host's when it was last checked
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub(crate) struct ClockDrift {
+    /// Seconds that the camera is ahead of the host, negative when behind
+    pub(crate) seconds: i64,
+    /// If it was more than the `max_drift`
+    pub(crate) exceeded: bool,
+    /// If the camera's clock was then set to the host's
+    pub(crate) corrected: bool,
+}
 
 #[derive(Eq, PartialEq, Copy, Clone)]
 pub(crate) enum NeoCamThreadState {
@@ -19,6 +48,11 @@ pub(crate) struct NeoCamThread {
     config: WatchReceiver<CameraConfig>,
     cancel: CancellationToken,
     camera_watch: WatchSender<Weak<BcCamera>>,
+    busy: WatchSender<bool>,
+    errors: WatchSender<ErrorHistory>,
+    clock_drift: WatchSender<Option<ClockDrift>>,
+    logins: WatchSender<u64>,
+    gate: ConnectGate,
 }
 
 impl NeoCamThread {
@@ -26,6 +60,11 @@ impl NeoCamThread {
         watch_state_rx: WatchReceiver<NeoCamThreadState>,
         watch_config_rx: WatchReceiver<CameraConfig>,
         camera_watch_tx: WatchSender<Weak<BcCamera>>,
+        busy_tx: WatchSender<bool>,
+        errors_tx: WatchSender<ErrorHistory>,
+        clock_drift_tx: WatchSender<Option<ClockDrift>>,
+        logins_tx: WatchSender<u64>,
+        gate: ConnectGate,
         cancel: CancellationToken,
     ) -> Self {
         Self {
@@ -33,64 +72,59 @@ impl NeoCamThread {
             config: watch_config_rx,
             cancel,
             camera_watch: camera_watch_tx,
+            busy: busy_tx,
+            errors: errors_tx,
+            clock_drift: clock_drift_tx,
+            logins: logins_tx,
+            gate,
         }
     }
-    async fn run_camera(&mut self, config: &CameraConfig) -> AnyResult<()> {
+    async fn run_camera(&mut self, config: &CameraConfig, retries: u32) -> AnyResult<CameraExit> {
         let name = config.name.clone();
-        let camera = Arc::new(connect_and_login(config).await?);
+        let _lock = if config.lock {
+            Some(CameraLock::acquire(config).await?)
+        } else {
+            None
+        };
+        let camera = {
+            let _permit = self.gate.acquire(config).await;
+            Arc::new(connect_and_login(config).await?)
+        };
+        if retries > 0 {
+            log::info!("{name}: Reconnected after {retries} failed attempts");
+        }
+        self.busy.send_replace(false);
+        self.logins.send_modify(|logins| *logins += 1);
 
         sleep(Duration::from_secs(2)).await; // Delay a little since some calls will error if camera is waking up
-        update_camera_time(&camera, &name, config.update_time).await?;
+        let offset = config.timezone.as_ref().map(|_| config.utc_offset());
+        update_camera_time(&camera, &name, config.update_time, offset).await?;
+        if config.sync_name {
+            update_camera_name(&camera, &name).await;
+        }
         sleep(Duration::from_secs(2)).await; // Delay a little since some calls will error if camera is waking up
 
         self.camera_watch.send_replace(Arc::downgrade(&camera));
 
         let cancel_check = self.cancel.clone();
         // Now we wait for a disconnect
-        tokio::select! {
+        let exit = tokio::select! {
             _ = cancel_check.cancelled() => {
-                AnyResult::Ok(())
+                AnyResult::Ok(CameraExit::Shutdown)
             }
             v = camera.join() => {
                 v?;
-                Ok(())
+                Ok(CameraExit::Shutdown)
             },
-            v = async {
-                let mut interval = interval(Duration::from_secs(5));
-                let mut missed_pings = 0;
-                loop {
-                    interval.tick().await;
-                    match timeout(Duration::from_secs(5), camera.get_linktype()).await {
-                        Ok(Ok(_)) => {
-                            missed_pings = 0;
-                            continue
-                        },
-                        Ok(Err(neolink_core::Error::UnintelligibleReply { .. })) => {
-                            // Camera does not support pings just wait forever
-                            futures::future::pending().await
-                        },
-                        Ok(Err(e)) => {
-                            break Err(e.into());
-                        },
-                        Err(_) => {
-                            // Timeout
-                            if missed_pings < 5 {
-                                missed_pings += 1;
-                                continue;
-                            } else {
-                                log::debug!("Timed out waiting for camera ping reply. Assuming unsupported");
-                                futures::future::pending().await
-                            }
-                        }
-                    }
-                }
-            } => v,
+            v = monitor_link(&camera, config) => v,
+            v = keep_camera_time(&camera, config) => v,
+            v = check_camera_clock(&camera, config, &self.clock_drift) => v,
         }?;
 
         let _ = camera.logout().await;
         let _ = camera.shutdown().await;
 
-        Ok(())
+        Ok(exit)
     }
 
     // Will run and attempt to maintain the connection
@@ -98,10 +132,8 @@ impl NeoCamThread {
     // A watch sender is used to send the new camera
     // whenever it changes
     pub(crate) async fn run(&mut self) -> AnyResult<()> {
-        const MAX_BACKOFF: Duration = Duration::from_secs(5);
-        const MIN_BACKOFF: Duration = Duration::from_millis(50);
-
-        let mut backoff = MIN_BACKOFF;
+        let mut reconnect = self.config.borrow().reconnect.clone();
+        let mut backoff = Backoff::new(&reconnect);
 
         loop {
             self.state
@@ -111,6 +143,10 @@ impl NeoCamThread {
             let mut config_rec = self.config.clone();
 
             let config = config_rec.borrow_and_update().clone();
+            if config.reconnect != reconnect {
+                reconnect = config.reconnect.clone();
+                backoff = Backoff::new(&reconnect);
+            }
             let now = Instant::now();
             let name = config.name.clone();
 
@@ -123,7 +159,7 @@ impl NeoCamThread {
                 Ok(_) = state.wait_for(|state| matches!(state, NeoCamThreadState::Disconnected)) => {
                     None
                 }
-                v = self.run_camera(&config) => {
+                v = self.run_camera(&config, backoff.attempts()) => {
                     Some(v)
                 }
             };
@@ -139,22 +175,26 @@ impl NeoCamThread {
             // Else we see what the result actually was
             let result = res.unwrap();
 
-            if now.elapsed() > Duration::from_secs(60) {
+            if now.elapsed() > Duration::from_secs(reconnect.reset_after) {
                 // Command ran long enough to be considered a success
-                backoff = MIN_BACKOFF;
-            }
-            if backoff > MAX_BACKOFF {
-                backoff = MAX_BACKOFF;
+                backoff.reset();
             }
 
             match result {
-                Ok(()) => {
+                Ok(CameraExit::Shutdown) => {
                     // Normal shutdown
                     self.cancel.cancel();
                     return Ok(());
                 }
+                Ok(CameraExit::SwitchTransport) => {
+                    // Reconnect straight away over the better transport
+                    backoff.reset();
+                }
                 Err(e) => {
                     // An error
+                    self.errors.send_modify(|errors| {
+                        errors.push(CameraError::new(&e), config.error_history)
+                    });
                     // Check if it is non-retry
                     let e_inner = e.downcast_ref::<neolink_core::Error>();
                     match e_inner {
@@ -164,12 +204,52 @@ impl NeoCamThread {
                             self.cancel.cancel();
                             return Err(e);
                         }
+                        Some(neolink_core::Error::MaxClients) if config.busy_retry == 0 => {
+                            // Fatal when retries are disabled
+                            log::error!("{name}: The camera has reached its maximum number of clients");
+                            self.busy.send_replace(true);
+                            self.cancel.cancel();
+                            return Err(e);
+                        }
+                        Some(neolink_core::Error::MaxClients) => {
+                            // Wait for a client such as the app to disconnect
+                            let retry = Duration::from_secs(config.busy_retry);
+                            log::warn!(
+                                "{name}: The camera has reached its maximum number of clients, retrying in {:?}",
+                                retry
+                            );
+                            self.busy.send_replace(true);
+                            tokio::select! {
+                                _ = self.cancel.cancelled() => return Ok(()),
+                                _ = sleep(retry) => {}
+                            }
+                        }
                         _ => {
                             // Non fatal
                             log::warn!("{name}: Connection Lost: {:?}", e);
-                            log::info!("{name}: Attempt reconnect in {:?}", backoff);
-                            sleep(backoff).await;
-                            backoff *= 2;
+                            let Some(delay) = backoff.next_delay() else {
+                                log::error!(
+                                    "{name}: Giving up after {} failed reconnects",
+                                    backoff.attempts()
+                                );
+                                self.cancel.cancel();
+                                return Err(e);
+                            };
+                            if reconnect.max_retries > 0 {
+                                log::info!(
+                                    "{name}: Attempt reconnect {}/{} in {:?}",
+                                    backoff.attempts(),
+                                    reconnect.max_retries,
+                                    delay
+                                );
+                            } else {
+                                log::info!(
+                                    "{name}: Attempt reconnect {} in {:?}",
+                                    backoff.attempts(),
+                                    delay
+                                );
+                            }
+                            sleep(delay).await;
                         }
                     }
                 }
@@ -184,24 +264,249 @@ impl Drop for NeoCamThread {
     }
 }
 
-async fn update_camera_time(camera: &BcCamera, name: &str, update_time: bool) -> AnyResult<()> {
+/// The ping replies of the last minute, used to score the link
+#[derive(Default)]
+struct LinkQuality {
+    /// Round trip time of each ping or None if it was missed
+    samples: VecDeque<Option<Duration>>,
+}
+
+impl LinkQuality {
+    /// One minute of pings at the default 5s keepalive
+    const WINDOW: usize = 12;
+
+    /// Points above `min_score` that a poor link must score to recover
+    /// so that a score close to `min_score` does not flap
+    const RECOVER_MARGIN: u8 = 10;
+
+    fn push(&mut self, sample: Option<Duration>) {
+        if self.samples.len() >= Self::WINDOW {
+            self.samples.pop_front();
+        }
+        self.samples.push_back(sample);
+    }
+
+    fn is_full(&self) -> bool {
+        self.samples.len() >= Self::WINDOW
+    }
+
+    /// Score out of 100
+    ///
+    /// Each percent of missed pings costs a point and each 10ms
+    /// of average round trip time costs a point up to a maximum of 50
+    fn score(&self) -> u8 {
+        let total = self.samples.len().max(1) as u64;
+        let replies = self.samples.iter().flatten().collect::<Vec<_>>();
+        let loss = (total - replies.len() as u64) * 100 / total;
+        let latency_ms = replies
+            .iter()
+            .map(|rtt| rtt.as_millis() as u64)
+            .sum::<u64>()
+            .checked_div(replies.len() as u64)
+            .unwrap_or(0);
+        100u64
+            .saturating_sub(loss)
+            .saturating_sub((latency_ms / 10).min(50)) as u8
+    }
+
+    /// Whether the link is poor given whether it `was_poor` before
+    fn is_poor(&self, min_score: u8, was_poor: bool) -> bool {
+        let score = self.score();
+        if was_poor {
+            score < min_score.saturating_add(Self::RECOVER_MARGIN).min(100)
+        } else {
+            score < min_score
+        }
+    }
+}
+
+/// Ping the camera to score the link and when it is poor
+/// look for a better transport
+///
+/// Returns an error if the camera stops responding and
+/// never returns if the camera does not support pings
+async fn monitor_link(camera: &BcCamera, config: &CameraConfig) -> AnyResult<CameraExit> {
+    let name = &config.name;
+    let transport = camera.transport();
+    let mut interval = interval(Duration::from_secs(config.idle_policy.keepalive));
+    interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    let mut quality = LinkQuality::default();
+    let mut missed_pings = 0;
+    let mut poor = false;
+    let mut last_probe: Option<Instant> = None;
+    loop {
+        interval.tick().await;
+        let sent = Instant::now();
+        match timeout(Duration::from_secs(5), camera.get_linktype()).await {
+            Ok(Ok(_)) => {
+                missed_pings = 0;
+                quality.push(Some(sent.elapsed()));
+            }
+            Ok(Err(neolink_core::Error::UnintelligibleReply { .. })) => {
+                // Camera does not support pings just wait forever
+                futures::future::pending().await
+            }
+            Ok(Err(e)) => {
+                return Err(e.into());
+            }
+            Err(_) => {
+                // Timeout
+                quality.push(None);
+                if missed_pings < 5 {
+                    missed_pings += 1;
+                } else {
+                    log::debug!("Timed out waiting for camera ping reply. Assuming unsupported");
+                    futures::future::pending().await
+                }
+            }
+        }
+
+        if !quality.is_full() {
+            continue;
+        }
+        let score = quality.score();
+        if !quality.is_poor(config.quality.min_score, poor) {
+            if poor {
+                log::info!("{name}: Connection over {transport} has recovered, quality {score}/100");
+                poor = false;
+            }
+            continue;
+        } else if !poor {
+            log::warn!("{name}: Connection over {transport} is poor, quality {score}/100");
+            poor = true;
+        }
+
+        if !config.quality.auto_switch || transport == Transport::Tcp {
+            continue;
+        }
+        if last_probe.is_some_and(|probed| {
+            probed.elapsed() < Duration::from_secs(config.quality.probe_interval)
+        }) {
+            continue;
+        }
+        last_probe = Some(Instant::now());
+        log::info!("{name}: Looking for a better transport than {transport}");
+        match timeout(Duration::from_secs(30), probe_transport(config)).await {
+            Ok(Ok(found)) if found < transport => {
+                log::info!("{name}: Switching from {transport} to {found}");
+                return Ok(CameraExit::SwitchTransport);
+            }
+            Ok(Ok(_)) => {
+                log::info!("{name}: No better transport than {transport} is available");
+            }
+            Ok(Err(e)) => {
+                log::debug!("{name}: Transport probe failed: {:?}", e);
+            }
+            Err(_) => {
+                log::debug!("{name}: Transport probe timed out");
+            }
+        }
+    }
+}
+
+/// Set the camera's time again when daylight saving changes the offset or
+/// the host clock jumps, such as when NTP corrects it
+///
+/// Never returns
+async fn keep_camera_time(camera: &BcCamera, config: &CameraConfig) -> AnyResult<CameraExit> {
+    let name = &config.name;
+    let timezone = config.timezone();
+    loop {
+        let now = OffsetDateTime::now_utc();
+        let change = timezone.next_change(now);
+        match sleep_until_wall(change.unwrap_or(now + time::Duration::DAY)).await {
+            Wake::Reached if change.is_some() => {
+                log::info!("{name}: Daylight saving has changed the offset");
+            }
+            Wake::Reached => continue,
+            Wake::Jumped(_) => {}
+        }
+        let offset = config.timezone.as_ref().map(|_| timezone.now().offset());
+        if let Err(e) = update_camera_time(camera, name, config.update_time, offset).await {
+            log::warn!("{name}: Could not update the camera time: {:?}", e);
+        }
+    }
+}
+
+/// Compare the camera's clock to the host's at connect and then every
+/// `interval`, warning and optionally correcting it when it has drifted
+///
+/// Never returns
+async fn check_camera_clock(
+    camera: &BcCamera,
+    config: &CameraConfig,
+    drift_tx: &WatchSender<Option<ClockDrift>>,
+) -> AnyResult<CameraExit> {
+    let name = &config.name;
+    let check = &config.clock_check;
+    loop {
+        match camera.get_time().await {
+            Ok(Some(cam_time)) => {
+                let seconds = (cam_time - OffsetDateTime::now_utc()).whole_seconds();
+                let exceeded = seconds.unsigned_abs() > check.max_drift;
+                let mut corrected = false;
+                if exceeded {
+                    log::warn!("{name}: Camera clock is {seconds}s off the host's");
+                    if check.correct {
+                        let new_time = OffsetDateTime::now_utc().to_offset(cam_time.offset());
+                        match camera.set_time(new_time).await {
+                            Ok(_) => {
+                                log::info!("{name}: Corrected the camera clock to {new_time}");
+                                corrected = true;
+                            }
+                            Err(e) => {
+                                log::warn!("{name}: Could not correct the camera clock: {:?}", e);
+                            }
+                        }
+                    }
+                } else {
+                    log::debug!("{name}: Camera clock is {seconds}s off the host's");
+                }
+                drift_tx.send_replace(Some(ClockDrift {
+                    seconds,
+                    exceeded,
+                    corrected,
+                }));
+            }
+            Ok(None) => {
+                log::debug!("{name}: Camera has no time to check");
+            }
+            Err(e) => {
+                log::debug!("{name}: Could not check the camera clock: {:?}", e);
+            }
+        }
+        if check.interval == 0 {
+            futures::future::pending::<()>().await;
+        }
+        sleep(Duration::from_secs(check.interval)).await;
+    }
+}
+
+async fn update_camera_time(
+    camera: &BcCamera,
+    name: &str,
+    update_time: bool,
+    offset: Option<UtcOffset>,
+) -> AnyResult<()> {
     let cam_time = camera.get_time().await?;
     let mut update = false;
     if let Some(time) = cam_time {
         log::info!("{}: Camera time is already set: {}", name, time);
         if update_time {
             update = true;
+        } else if offset.is_some_and(|offset| offset != time.offset()) {
+            log::info!("{}: Camera timezone does not match the config, Updating", name);
+            update = true;
         }
     } else {
         update = true;
         log::warn!("{}: Camera has no time set, Updating", name);
     }
     if update {
-        use std::time::SystemTime;
-        let new_time = SystemTime::now();
+        let new_time = OffsetDateTime::now_utc().to_offset(offset.unwrap_or(UtcOffset::UTC));
 
-        log::info!("{}: Setting time to {:?}", name, new_time);
-        match camera.set_time(new_time.into()).await {
+        log::info!("{}: Setting time to {}", name, new_time);
+        match camera.set_time(new_time).await {
             Ok(_) => {
                 let cam_time = camera.get_time().await?;
                 if let Some(time) = cam_time {
@@ -219,3 +524,113 @@ async fn update_camera_time(camera: &BcCamera, name: &str, update_time: bool) ->
     }
     Ok(())
 }
+
+async fn update_camera_name(camera: &BcCamera, name: &str) {
+    match camera.get_device_name().await {
+        Ok(current) if current == name => {
+            log::debug!("{}: Camera device name is already set", name);
+        }
+        Ok(current) => {
+            log::info!("{}: Setting device name from {:?}", name, current);
+            if let Err(e) = camera.set_device_name(name).await {
+                log::error!(
+                    "{}: Camera did not accept new device name (is user an admin?): Error: {:?}",
+                    name,
+                    e
+                );
+            }
+        }
+        Err(e) => {
+            log::warn!("{}: Could not get the device name: {:?}", name, e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn link(replies: &[Option<u64>]) -> LinkQuality {
+        let mut quality = LinkQuality::default();
+        for reply in replies {
+            quality.push(reply.map(Duration::from_millis));
+        }
+        quality
+    }
+
+    #[test]
+    fn test_link_quality_window() {
+        let mut quality = LinkQuality::default();
+        for _ in 0..LinkQuality::WINDOW - 1 {
+            quality.push(None);
+        }
+        assert!(!quality.is_full());
+        quality.push(None);
+        assert!(quality.is_full());
+        assert_eq!(quality.score(), 0);
+
+        // The missed pings leave the window as replies come in
+        for _ in 0..LinkQuality::WINDOW {
+            quality.push(Some(Duration::ZERO));
+        }
+        assert_eq!(quality.samples.len(), LinkQuality::WINDOW);
+        assert_eq!(quality.score(), 100);
+    }
+
+    #[test]
+    fn test_link_quality_score() {
+        assert_eq!(link(&[Some(0); 12]).score(), 100);
+        // Half the pings missed
+        assert_eq!(link(&[Some(0), None, Some(0), None]).score(), 50);
+        // Latency costs a point per 10ms up to 50
+        assert_eq!(link(&[Some(100); 12]).score(), 90);
+        assert_eq!(link(&[Some(5000); 12]).score(), 50);
+        // Missed pings do not count towards the latency
+        assert_eq!(link(&[Some(100), None]).score(), 40);
+    }
+
+    #[test]
+    fn test_link_quality_score_monotonic() {
+        // The score never rises as more pings are missed
+        let mut last = 100;
+        for missed in 0..=LinkQuality::WINDOW {
+            let mut replies = vec![Some(20); LinkQuality::WINDOW];
+            replies[..missed].fill(None);
+            let score = link(&replies).score();
+            assert!(score <= last, "{} missed scored {} > {}", missed, score, last);
+            last = score;
+        }
+        assert_eq!(last, 0);
+
+        // Nor as the latency rises
+        let mut last = 100;
+        for latency in (0..1000).step_by(7) {
+            let score = link(&[Some(latency); LinkQuality::WINDOW]).score();
+            assert!(score <= last, "{}ms scored {} > {}", latency, score, last);
+            last = score;
+        }
+    }
+
+    #[test]
+    fn test_link_quality_hysteresis() {
+        // Scores 50 with half the pings missed
+        let at_min = link(&[Some(0), None]);
+        // Scores 55 with 450ms round trips
+        let above_min = link(&[Some(450)]);
+        // Scores 60 with 400ms round trips
+        let recovered = link(&[Some(400)]);
+
+        assert!(!at_min.is_poor(50, false));
+        assert!(link(&[Some(0), None, None]).is_poor(50, false));
+        // A poor link stays poor until it scores the margin above the minimum
+        assert!(at_min.is_poor(50, true));
+        assert!(above_min.is_poor(50, true));
+        assert!(!recovered.is_poor(50, true));
+        // But a good link does not become poor until below the minimum
+        assert!(!above_min.is_poor(50, false));
+
+        // The margin does not go past a perfect score
+        assert!(!link(&[Some(0)]).is_poor(95, true));
+        assert!(link(&[Some(10)]).is_poor(95, true));
+    }
+}
@@ -0,0 +1,439 @@
+//! Timers that follow the host's wall clock
+//!
+//! Tokio's timers follow the monotonic clock so a sleep until a time of day
+//! such as midnight fires at the wrong time if the host clock is changed by
+//! NTP or by hand. The sleeps here wake at least each minute to check the
+//! wall clock and report when it has jumped so that timers can be rearmed.
+//!
+//! The timezones are either a fixed offset or a POSIX TZ rule so that
+//! daylight saving is followed without a timezone database
+use once_cell::sync::Lazy;
+use regex::Regex;
+use std::convert::TryFrom;
+use time::{Date, Duration, Month, OffsetDateTime, PrimitiveDateTime, Time, UtcOffset};
+use tokio::time::{sleep, Instant};
+
+static RE_FIXED_OFFSET: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"^(?:(?:UTC|GMT)?([+-])(\d{1,2})(?::?(\d{2}))?|UTC|GMT|Z)$").unwrap());
+
+/// Longest sleep before the wall clock is checked again
+const MAX_NAP: std::time::Duration = std::time::Duration::from_secs(60);
+/// Smallest difference between the wall and monotonic clocks that is a jump
+const MIN_JUMP: Duration = Duration::seconds(2);
+
+/// A timezone of the config
+///
+/// Either a fixed UTC offset such as `+10:00` or `UTC-5`, or a POSIX TZ
+/// rule with daylight saving such as `CET-1CEST,M3.5.0,M10.5.0/3`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Timezone {
+    Fixed(UtcOffset),
+    Rule {
+        std: UtcOffset,
+        dst: UtcOffset,
+        /// When daylight saving starts, in standard time
+        start: Transition,
+        /// When daylight saving ends, in daylight saving time
+        end: Transition,
+    },
+}
+
+/// The day and local time of a change to or from daylight saving
+///
+/// This is the `Mm.w.d/time` of a POSIX TZ rule
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Transition {
+    month: Month,
+    /// The week of the month from 1 to 5, 5 is the last week
+    week: u8,
+    /// The day of the week, 0 is Sunday
+    weekday: u8,
+    /// Seconds after the local midnight
+    time: i32,
+}
+
+impl Default for Timezone {
+    fn default() -> Self {
+        Timezone::Fixed(UtcOffset::UTC)
+    }
+}
+
+impl Timezone {
+    /// Parse a fixed offset or a POSIX TZ rule
+    pub(crate) fn parse(timezone: &str) -> Option<Self> {
+        if let Some(caps) = RE_FIXED_OFFSET.captures(timezone) {
+            let Some(sign) = caps.get(1) else {
+                return Some(Timezone::Fixed(UtcOffset::UTC));
+            };
+            let sign = if sign.as_str() == "-" { -1 } else { 1 };
+            let hours: i32 = caps.get(2)?.as_str().parse().ok()?;
+            let minutes: i32 = caps
+                .get(3)
+                .map(|m| m.as_str().parse())
+                .transpose()
+                .ok()?
+                .unwrap_or(0);
+            return offset(sign * (hours * 3600 + minutes * 60)).map(Timezone::Fixed);
+        }
+        parse_rule(timezone)
+    }
+
+    /// The UTC offset at the time `utc`
+    pub(crate) fn offset_at(&self, utc: OffsetDateTime) -> UtcOffset {
+        match *self {
+            Timezone::Fixed(offset) => offset,
+            Timezone::Rule {
+                std,
+                dst,
+                start,
+                end,
+            } => {
+                let year = utc.to_offset(std).year();
+                let start = start.at(year, std);
+                let end = end.at(year, dst);
+                let summer = if start < end {
+                    start <= utc && utc < end
+                } else {
+                    // Daylight saving over the new year such as in the southern hemisphere
+                    utc >= start || utc < end
+                };
+                if summer {
+                    dst
+                } else {
+                    std
+                }
+            }
+        }
+    }
+
+    /// The current time in this timezone
+    pub(crate) fn now(&self) -> OffsetDateTime {
+        let utc = OffsetDateTime::now_utc();
+        utc.to_offset(self.offset_at(utc))
+    }
+
+    /// The next time after `after` that the UTC offset changes
+    pub(crate) fn next_change(&self, after: OffsetDateTime) -> Option<OffsetDateTime> {
+        let Timezone::Rule {
+            std,
+            dst,
+            start,
+            end,
+        } = *self
+        else {
+            return None;
+        };
+        let year = after.to_offset(std).year();
+        (year..=year + 1)
+            .flat_map(|year| [start.at(year, std), end.at(year, dst)])
+            .filter(|change| *change > after)
+            .min()
+    }
+
+    /// The next local midnight after `after`
+    pub(crate) fn next_midnight(&self, after: OffsetDateTime) -> OffsetDateTime {
+        let local = after.to_offset(self.offset_at(after));
+        let midnight = PrimitiveDateTime::new(local.date(), Time::MIDNIGHT) + Duration::DAY;
+        // The offset can change at the midnight itself
+        let guess = midnight.assume_offset(local.offset());
+        midnight.assume_offset(self.offset_at(guess))
+    }
+}
+
+impl Transition {
+    /// The time of this transition in `year` while the clocks are at `offset`
+    fn at(&self, year: i32, offset: UtcOffset) -> OffsetDateTime {
+        let days = time::util::days_in_year_month(year, self.month);
+        // The first of the month always exists
+        let first = Date::from_calendar_date(year, self.month, 1).unwrap();
+        let mut day = 1
+            + (self.weekday as i32 - first.weekday().number_days_from_sunday() as i32)
+                .rem_euclid(7)
+            + (self.week as i32 - 1) * 7;
+        while day > days as i32 {
+            day -= 7;
+        }
+        let date = first + Duration::days(day as i64 - 1);
+        (PrimitiveDateTime::new(date, Time::MIDNIGHT) + Duration::seconds(self.time as i64))
+            .assume_offset(offset)
+    }
+}
+
+/// How a wall clock sleep ended
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Wake {
+    /// The time was reached
+    Reached,
+    /// The host clock jumped by this much, the time should be worked out again
+    Jumped(Duration),
+}
+
+/// Sleep until the wall clock reaches `until`
+///
+/// This returns early if the host clock jumps
+pub(crate) async fn sleep_until_wall(until: OffsetDateTime) -> Wake {
+    loop {
+        let wall = OffsetDateTime::now_utc();
+        if wall >= until {
+            return Wake::Reached;
+        }
+        let mono = Instant::now();
+        let nap = std::time::Duration::try_from(until - wall)
+            .unwrap_or_default()
+            .min(MAX_NAP);
+        sleep(nap).await;
+
+        let jump = (OffsetDateTime::now_utc() - wall) - mono.elapsed();
+        if jump.abs() >= MIN_JUMP {
+            log::info!("The host clock jumped by {}", jump);
+            return Wake::Jumped(jump);
+        }
+    }
+}
+
+fn offset(seconds: i32) -> Option<UtcOffset> {
+    UtcOffset::from_whole_seconds(seconds).ok()
+}
+
+/// Parse a POSIX TZ rule such as `EST5EDT,M3.2.0,M11.1.0`
+///
+/// Only the `Mm.w.d` form of the transitions is supported
+fn parse_rule(rule: &str) -> Option<Timezone> {
+    let rest = parse_name(rule)?;
+    let (std, rest) = parse_seconds(rest)?;
+    // POSIX offsets are the time to add to get UTC
+    let std = offset(-std)?;
+    if rest.is_empty() {
+        return Some(Timezone::Fixed(std));
+    }
+    let rest = parse_name(rest)?;
+    let (dst, rest) = match rest.strip_prefix(',') {
+        Some(_) => (offset(std.whole_seconds() + 3600)?, rest),
+        None => {
+            let (dst, rest) = parse_seconds(rest)?;
+            (offset(-dst)?, rest)
+        }
+    };
+    let (start, end) = rest.strip_prefix(',')?.split_once(',')?;
+    Some(Timezone::Rule {
+        std,
+        dst,
+        start: parse_transition(start)?,
+        end: parse_transition(end)?,
+    })
+}
+
+/// Skip the name of the zone such as `CET` or `<+10>`
+fn parse_name(rule: &str) -> Option<&str> {
+    let len = match rule.strip_prefix('<') {
+        Some(quoted) => quoted.find('>').filter(|len| *len > 0)? + 2,
+        None => rule
+            .find(|c: char| !c.is_ascii_alphabetic())
+            .unwrap_or(rule.len()),
+    };
+    (len >= 3).then(|| &rule[len..])
+}
+
+/// Parse a time such as `-1`, `5:30` or `/3` as seconds
+fn parse_seconds(rule: &str) -> Option<(i32, &str)> {
+    let (sign, rule) = match rule.as_bytes().first()? {
+        b'-' => (-1, &rule[1..]),
+        b'+' => (1, &rule[1..]),
+        _ => (1, rule),
+    };
+    let end = rule
+        .find(|c: char| !c.is_ascii_digit() && c != ':')
+        .unwrap_or(rule.len());
+    let mut seconds = 0;
+    for (i, part) in rule[..end].split(':').enumerate() {
+        if i > 2 || part.is_empty() || part.len() > 3 {
+            return None;
+        }
+        let value: i32 = part.parse().ok()?;
+        seconds += value * [3600, 60, 1][i];
+    }
+    Some((sign * seconds, &rule[end..]))
+}
+
+/// Parse a transition such as `M3.5.0/3`
+fn parse_transition(rule: &str) -> Option<Transition> {
+    let (date, time) = match rule.split_once('/') {
+        Some((date, time)) => {
+            let (time, rest) = parse_seconds(time)?;
+            if !rest.is_empty() {
+                return None;
+            }
+            (date, time)
+        }
+        None => (rule, 2 * 3600),
+    };
+    let mut parts = date.strip_prefix('M')?.split('.');
+    let month: u8 = parts.next()?.parse().ok()?;
+    let week: u8 = parts.next()?.parse().ok()?;
+    let weekday: u8 = parts.next()?.parse().ok()?;
+    if parts.next().is_some() || !(1..=5).contains(&week) || weekday > 6 {
+        return None;
+    }
+    Some(Transition {
+        month: Month::try_from(month).ok()?,
+        week,
+        weekday,
+        time,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn utc(year: i32, month: Month, day: u8, hour: u8, minute: u8) -> OffsetDateTime {
+        let date = Date::from_calendar_date(year, month, day).unwrap();
+        PrimitiveDateTime::new(date, Time::from_hms(hour, minute, 0).unwrap()).assume_utc()
+    }
+
+    fn hours(hours: i8) -> UtcOffset {
+        UtcOffset::from_hms(hours, 0, 0).unwrap()
+    }
+
+    #[test]
+    fn test_parse_fixed() {
+        assert_eq!(Timezone::parse("+10:00"), Some(Timezone::Fixed(hours(10))));
+        assert_eq!(Timezone::parse("UTC-5"), Some(Timezone::Fixed(hours(-5))));
+        assert_eq!(
+            Timezone::parse("UTC"),
+            Some(Timezone::Fixed(UtcOffset::UTC))
+        );
+        // POSIX offsets are the other way around
+        assert_eq!(parse_rule("<+10>-10"), Some(Timezone::Fixed(hours(10))));
+        assert_eq!(parse_rule("EST5"), Some(Timezone::Fixed(hours(-5))));
+        assert_eq!(parse_rule("EST5EDT"), None);
+        assert_eq!(parse_rule("E5"), None);
+    }
+
+    #[test]
+    fn test_parse_rule() {
+        assert_eq!(
+            parse_rule("EST5EDT,M3.2.0,M11.1.0"),
+            Some(Timezone::Rule {
+                std: hours(-5),
+                dst: hours(-4),
+                start: Transition {
+                    month: Month::March,
+                    week: 2,
+                    weekday: 0,
+                    time: 2 * 3600,
+                },
+                end: Transition {
+                    month: Month::November,
+                    week: 1,
+                    weekday: 0,
+                    time: 2 * 3600,
+                },
+            })
+        );
+        assert_eq!(
+            parse_rule("AEST-10AEDT,M10.1.0,M4.1.0/3"),
+            Some(Timezone::Rule {
+                std: hours(10),
+                dst: hours(11),
+                start: Transition {
+                    month: Month::October,
+                    week: 1,
+                    weekday: 0,
+                    time: 2 * 3600,
+                },
+                end: Transition {
+                    month: Month::April,
+                    week: 1,
+                    weekday: 0,
+                    time: 3 * 3600,
+                },
+            })
+        );
+        assert_eq!(parse_rule("EST5EDT,M3.6.0,M11.1.0"), None);
+        assert_eq!(parse_rule("EST5EDT,M3.2.7,M11.1.0"), None);
+        assert_eq!(parse_rule("EST5EDT,J60,J300"), None);
+    }
+
+    #[test]
+    fn test_offset_at_northern() {
+        let tz = Timezone::parse("EST5EDT,M3.2.0,M11.1.0").unwrap();
+        // 2am EST on 8 March 2026
+        assert_eq!(tz.offset_at(utc(2026, Month::March, 8, 6, 30)), hours(-5));
+        assert_eq!(tz.offset_at(utc(2026, Month::March, 8, 7, 0)), hours(-4));
+        assert_eq!(tz.offset_at(utc(2026, Month::March, 8, 7, 30)), hours(-4));
+        // 2am EDT on 1 November 2026
+        assert_eq!(
+            tz.offset_at(utc(2026, Month::November, 1, 5, 30)),
+            hours(-4)
+        );
+        assert_eq!(tz.offset_at(utc(2026, Month::November, 1, 6, 0)), hours(-5));
+        assert_eq!(
+            tz.offset_at(utc(2026, Month::November, 1, 6, 30)),
+            hours(-5)
+        );
+    }
+
+    #[test]
+    fn test_offset_at_southern() {
+        let tz = Timezone::parse("AEST-10AEDT,M10.1.0,M4.1.0/3").unwrap();
+        // 3am AEDT on 5 April 2026
+        assert_eq!(tz.offset_at(utc(2026, Month::April, 4, 15, 30)), hours(11));
+        assert_eq!(tz.offset_at(utc(2026, Month::April, 4, 16, 0)), hours(10));
+        assert_eq!(tz.offset_at(utc(2026, Month::April, 4, 16, 30)), hours(10));
+        // 2am AEST on 4 October 2026
+        assert_eq!(
+            tz.offset_at(utc(2026, Month::October, 3, 15, 30)),
+            hours(10)
+        );
+        assert_eq!(tz.offset_at(utc(2026, Month::October, 3, 16, 0)), hours(11));
+        assert_eq!(
+            tz.offset_at(utc(2026, Month::October, 3, 16, 30)),
+            hours(11)
+        );
+        // Either side of the new year is daylight saving
+        assert_eq!(
+            tz.offset_at(utc(2026, Month::December, 31, 12, 0)),
+            hours(11)
+        );
+        assert_eq!(
+            tz.offset_at(utc(2026, Month::December, 31, 14, 0)),
+            hours(11)
+        );
+        assert_eq!(tz.offset_at(utc(2027, Month::January, 15, 0, 0)), hours(11));
+    }
+
+    #[test]
+    fn test_next_change() {
+        let tz = Timezone::parse("EST5EDT,M3.2.0,M11.1.0").unwrap();
+        assert_eq!(
+            tz.next_change(utc(2026, Month::January, 1, 0, 0)),
+            Some(utc(2026, Month::March, 8, 7, 0))
+        );
+        assert_eq!(
+            tz.next_change(utc(2026, Month::March, 8, 7, 0)),
+            Some(utc(2026, Month::November, 1, 6, 0))
+        );
+        assert_eq!(
+            tz.next_change(utc(2026, Month::December, 1, 0, 0)),
+            Some(utc(2027, Month::March, 14, 7, 0))
+        );
+
+        let tz = Timezone::parse("AEST-10AEDT,M10.1.0,M4.1.0/3").unwrap();
+        assert_eq!(
+            tz.next_change(utc(2026, Month::January, 1, 0, 0)),
+            Some(utc(2026, Month::April, 4, 16, 0))
+        );
+        assert_eq!(
+            tz.next_change(utc(2026, Month::May, 1, 0, 0)),
+            Some(utc(2026, Month::October, 3, 16, 0))
+        );
+        assert_eq!(
+            tz.next_change(utc(2026, Month::October, 3, 16, 0)),
+            Some(utc(2027, Month::April, 3, 16, 0))
+        );
+
+        let tz = Timezone::parse("+10:00").unwrap();
+        assert_eq!(tz.next_change(utc(2026, Month::January, 1, 0, 0)), None);
+    }
+}
@@ -0,0 +1,87 @@
+//! Limits how quickly the cameras connect
+//!
+//! Connecting many cameras at once, such as on startup or after the
+//! network returns, can saturate the uplink so that some time out. The
+//! gate spaces out the start of each connection by the `stagger` and
+//! limits how many cameras at the same address or uid connect at once.
+//!
+//! The gate is shared by every camera of the reactor and only held
+//! while connecting and logging in
+use std::{collections::HashMap, sync::Arc};
+use tokio::{
+    sync::{watch::Receiver as WatchReceiver, Mutex, OwnedSemaphorePermit, Semaphore},
+    time::{sleep_until, Duration, Instant},
+};
+
+use crate::config::{CameraConfig, Config};
+
+#[derive(Clone)]
+pub(crate) struct ConnectGate {
+    config: WatchReceiver<Config>,
+    next_slot: Arc<Mutex<Instant>>,
+    hosts: Arc<Mutex<HashMap<String, Arc<Semaphore>>>>,
+}
+
+/// Held while the camera connects
+pub(crate) struct ConnectPermit {
+    _host: Option<OwnedSemaphorePermit>,
+}
+
+impl ConnectGate {
+    pub(crate) fn new(config: WatchReceiver<Config>) -> Self {
+        Self {
+            config,
+            next_slot: Arc::new(Mutex::new(Instant::now())),
+            hosts: Default::default(),
+        }
+    }
+
+    /// Wait for the camera's turn to connect
+    pub(crate) async fn acquire(&self, camera: &CameraConfig) -> ConnectPermit {
+        let connect = self.config.borrow().connect.clone();
+
+        let host = if connect.max_per_host > 0 {
+            let key = camera
+                .camera_addr
+                .as_ref()
+                .or(camera.camera_uid.as_ref())
+                .unwrap_or(&camera.name)
+                .clone();
+            let semaphore = self
+                .hosts
+                .lock()
+                .await
+                .entry(key)
+                .or_insert_with(|| Arc::new(Semaphore::new(connect.max_per_host)))
+                .clone();
+            if semaphore.available_permits() == 0 {
+                log::debug!(
+                    "{}: Waiting for other cameras at the same host to connect",
+                    camera.name
+                );
+            }
+            semaphore.acquire_owned().await.ok()
+        } else {
+            None
+        };
+
+        if connect.stagger > 0.0 {
+            let at = {
+                let mut next_slot = self.next_slot.lock().await;
+                let at = (*next_slot).max(Instant::now());
+                *next_slot = at + Duration::from_secs_f64(connect.stagger);
+                at
+            };
+            if at > Instant::now() {
+                log::debug!(
+                    "{}: Connecting in {:?} to stagger the cameras",
+                    camera.name,
+                    at - Instant::now()
+                );
+            }
+            sleep_until(at).await;
+        }
+
+        ConnectPermit { _host: host }
+    }
+}
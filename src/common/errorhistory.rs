@@ -0,0 +1,133 @@
+//! The last errors of each camera
+//!
+//! A camera that drops now and then is hard to debug with the logs alone
+//! since the debug logs are rarely left on. The errors that end a connection
+//! are kept here with their time and kind so that they can be looked at later
+//! with `neolink status --errors`, the http server or `neolink ctl dump-state`
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, VecDeque};
+use time::OffsetDateTime;
+
+use neolink_core::Error as CoreError;
+
+/// The kind of an error of a camera
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum ErrorCategory {
+    /// The credentials were not accepted
+    Login,
+    /// The camera has reached its maximum number of clients
+    Busy,
+    /// The camera did not reply in time
+    Timeout,
+    /// The connection was dropped or could not be made
+    Connection,
+    /// The camera could not be found over uid discovery
+    Discovery,
+    /// The camera sent something that could not be understood
+    Protocol,
+    /// The camera refused a command
+    Camera,
+    /// Anything else
+    Other,
+}
+
+impl ErrorCategory {
+    /// The kind of an error
+    pub(crate) fn of(error: &anyhow::Error) -> Self {
+        if error
+            .downcast_ref::<tokio::time::error::Elapsed>()
+            .is_some()
+        {
+            return ErrorCategory::Timeout;
+        }
+        let Some(error) = error.downcast_ref::<CoreError>() else {
+            return ErrorCategory::Other;
+        };
+        match error {
+            CoreError::CameraLoginFail | CoreError::AuthFailed => ErrorCategory::Login,
+            CoreError::MaxClients => ErrorCategory::Busy,
+            CoreError::Timeout(_) | CoreError::TimeoutError(_) | CoreError::TimeoutDisconnected => {
+                ErrorCategory::Timeout
+            }
+            CoreError::Io(_)
+            | CoreError::DroppedConnection
+            | CoreError::DroppedConnectionTry(_)
+            | CoreError::BroadcastDroppedConnectionTry(_)
+            | CoreError::TokioBcSendError
+            | CoreError::ConnectionUnavailable
+            | CoreError::DroppedSubscriber
+            | CoreError::CameraTerminate
+            | CoreError::CannotInitCamera => ErrorCategory::Connection,
+            CoreError::DiscoveryTimeout
+            | CoreError::AddrResolutionError
+            | CoreError::RegisterError
+            | CoreError::RelayTerminate
+            | CoreError::NoDmap
+            | CoreError::NoDev => ErrorCategory::Discovery,
+            CoreError::UnintelligibleReply { .. }
+            | CoreError::UnintelligibleXml { .. }
+            | CoreError::UnknownEncryption(_)
+            | CoreError::NomIncomplete(_)
+            | CoreError::NomError(_) => ErrorCategory::Protocol,
+            CoreError::CameraServiceUnavailable { .. } | CoreError::MissingAbility { .. } => {
+                ErrorCategory::Camera
+            }
+            _ => ErrorCategory::Other,
+        }
+    }
+}
+
+impl std::fmt::Display for ErrorCategory {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let name = match self {
+            ErrorCategory::Login => "login",
+            ErrorCategory::Busy => "busy",
+            ErrorCategory::Timeout => "timeout",
+            ErrorCategory::Connection => "connection",
+            ErrorCategory::Discovery => "discovery",
+            ErrorCategory::Protocol => "protocol",
+            ErrorCategory::Camera => "camera",
+            ErrorCategory::Other => "other",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// An error that ended the connection to the camera
+#[derive(Clone, Debug)]
+pub(crate) struct CameraError {
+    pub(crate) time: OffsetDateTime,
+    pub(crate) category: ErrorCategory,
+    pub(crate) error: String,
+}
+
+impl CameraError {
+    pub(crate) fn new(error: &anyhow::Error) -> Self {
+        Self {
+            time: OffsetDateTime::now_utc(),
+            category: ErrorCategory::of(error),
+            error: format!("{:#}", error),
+        }
+    }
+}
+
+/// The last errors of a camera
+#[derive(Clone, Debug, Default)]
+pub(crate) struct ErrorHistory {
+    /// The kept errors, oldest first
+    pub(crate) errors: VecDeque<CameraError>,
+    /// How many errors of each kind there have been since neolink started
+    pub(crate) counts: BTreeMap<ErrorCategory, u64>,
+}
+
+impl ErrorHistory {
+    /// Add an error, dropping the oldest to keep at most `capacity`
+    pub(crate) fn push(&mut self, error: CameraError, capacity: usize) {
+        *self.counts.entry(error.category).or_default() += 1;
+        self.errors.push_back(error);
+        while self.errors.len() > capacity {
+            self.errors.pop_front();
+        }
+    }
+}
@@ -0,0 +1,93 @@
+//! Merges the triggers of one physical event into a single event
+//!
+//! Someone walking past a camera can be reported by its motion detection,
+//! PIR, AI and a line crossing within a second of each other. Triggers that
+//! arrive within the camera's `event_window` of the first one are merged into
+//! one [`DetectionEvent`] with all of their sources.
+
+use std::collections::BTreeSet;
+use tokio::{
+    sync::{
+        broadcast::Sender as BroadcastSender, mpsc::Receiver as MpscReceiver,
+        watch::Receiver as WatchReceiver,
+    },
+    time::{sleep_until, Duration, Instant},
+};
+
+use crate::config::CameraConfig;
+use neolink_core::bc_protocol::{AlarmTrigger, SmartEvent};
+
+/// Something that can start an event
+pub(crate) enum Trigger {
+    /// An alarm from the camera
+    Alarm(AlarmTrigger),
+    /// A smart detection such as a line crossing
+    Smart(SmartEvent),
+    /// Neolink's own motion detection
+    Software,
+}
+
+/// The triggers of one physical event
+#[derive(Clone, Debug, Default)]
+pub(crate) struct DetectionEvent {
+    /// What triggered it such as `md`, `pir`, `ai`, `software` or `line_crossing`
+    pub(crate) sources: BTreeSet<String>,
+    /// What the AI classified the objects as, e.g. `people` or `vehicle`
+    pub(crate) objects: BTreeSet<String>,
+    /// The lines or zones of the smart detections
+    pub(crate) zones: BTreeSet<u8>,
+    /// The number of triggers that were merged
+    pub(crate) triggers: usize,
+}
+
+impl DetectionEvent {
+    fn add(&mut self, trigger: Trigger) {
+        self.triggers += 1;
+        match trigger {
+            Trigger::Alarm(alarm) => {
+                if let Some(status) = alarm.status {
+                    self.sources.insert(status.to_lowercase());
+                }
+                if !alarm.ai_types.is_empty() {
+                    self.sources.insert("ai".to_string());
+                }
+                self.objects.extend(alarm.ai_types);
+            }
+            Trigger::Smart(smart) => {
+                self.sources.insert(smart.kind.to_string());
+                self.zones.extend(smart.zone);
+                self.objects.extend(smart.ai_types);
+            }
+            Trigger::Software => {
+                self.sources.insert("software".to_string());
+            }
+        }
+    }
+}
+
+/// Merge the triggers into events until all trigger senders are dropped
+pub(crate) async fn correlate(
+    mut triggers: MpscReceiver<Trigger>,
+    config: WatchReceiver<CameraConfig>,
+    events: &BroadcastSender<DetectionEvent>,
+) {
+    while let Some(trigger) = triggers.recv().await {
+        let until = Instant::now() + Duration::from_secs_f64(config.borrow().event_window);
+        let mut event = DetectionEvent::default();
+        event.add(trigger);
+        loop {
+            tokio::select! {
+                // With no window every trigger is its own event
+                biased;
+                _ = sleep_until(until) => break,
+                trigger = triggers.recv() => match trigger {
+                    Some(trigger) => event.add(trigger),
+                    None => break,
+                },
+            }
+        }
+        log::debug!("Event from {:?}", event.sources);
+        // No one may be listening
+        let _ = events.send(event);
+    }
+}
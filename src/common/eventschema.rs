@@ -0,0 +1,246 @@
+//! The JSON of the camera events that neolink sends out
+//!
+//! MQTT, webhooks, the bookmarks file and the event log all send the same
+//! [`Event`] so that consumers only need to parse one structure. The
+//! `version` field is bumped whenever a field is renamed, removed or changes
+//! its meaning. Adding an optional field does not bump it so consumers should
+//! ignore fields they do not know.
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use time::{format_description::well_known::Rfc3339, OffsetDateTime};
+
+use super::events::DetectionEvent;
+use neolink_core::bc_protocol::SmartEvent;
+
+/// The current version of the [`Event`] JSON
+pub(crate) const EVENT_VERSION: u32 = 1;
+
+/// What happened
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum EventKind {
+    /// The camera reported motion
+    MotionStart,
+    /// The camera reported the motion has stopped
+    MotionStop,
+    /// The triggers of one physical event merged together
+    Detection,
+    /// A smart detection such as a line crossing, its kind is the source
+    Smart,
+    /// A moment marked with `/control/bookmark`
+    Bookmark,
+    /// A push notification that someone pressed the doorbell
+    Visitor,
+    /// The camera connected
+    Connected,
+    /// The camera disconnected
+    Disconnected,
+}
+
+impl EventKind {
+    /// The name as it is in the JSON
+    pub(crate) fn name(&self) -> &'static str {
+        match self {
+            EventKind::MotionStart => "motion_start",
+            EventKind::MotionStop => "motion_stop",
+            EventKind::Detection => "detection",
+            EventKind::Smart => "smart",
+            EventKind::Bookmark => "bookmark",
+            EventKind::Visitor => "visitor",
+            EventKind::Connected => "connected",
+            EventKind::Disconnected => "disconnected",
+        }
+    }
+}
+
+/// One event of a camera
+///
+/// Only `version`, `camera`, `event` and `time` are always present, the rest
+/// are left out when the kind of event does not have them
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub(crate) struct Event {
+    /// The version of this JSON, see [`EVENT_VERSION`]
+    pub(crate) version: u32,
+    /// The name of the camera in the config
+    pub(crate) camera: String,
+    pub(crate) event: EventKind,
+    /// When it happened as RFC 3339 in the camera's `timezone`
+    pub(crate) time: String,
+    /// What triggered it such as `md`, `pir`, `ai`, `software` or `line_crossing`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub(crate) sources: Vec<String>,
+    /// What the AI classified the objects as, e.g. `people` or `vehicle`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub(crate) objects: Vec<String>,
+    /// The lines or zones of the smart detections
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub(crate) zones: Vec<u8>,
+    /// The number of triggers that were merged into a detection
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) triggers: Option<usize>,
+    /// The text sent with a bookmark
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) label: Option<String>,
+    /// True if the camera was recording to the SD card from `/control/record`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) recording: Option<bool>,
+    /// The base64 of a jpeg taken when it happened
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) snapshot: Option<String>,
+}
+
+impl Event {
+    /// An event with none of the optional fields
+    ///
+    /// `now` should be in the camera's `timezone`
+    pub(crate) fn new(camera: &str, event: EventKind, now: OffsetDateTime) -> Self {
+        Self {
+            version: EVENT_VERSION,
+            camera: camera.to_string(),
+            event,
+            time: now.format(&Rfc3339).unwrap_or_else(|_| now.to_string()),
+            sources: vec![],
+            objects: vec![],
+            zones: vec![],
+            triggers: None,
+            label: None,
+            recording: None,
+            snapshot: None,
+        }
+    }
+
+    pub(crate) fn detection(
+        camera: &str,
+        detection: &DetectionEvent,
+        now: OffsetDateTime,
+    ) -> Self {
+        Self {
+            sources: detection.sources.iter().cloned().collect(),
+            objects: detection.objects.iter().cloned().collect(),
+            zones: detection.zones.iter().copied().collect(),
+            triggers: Some(detection.triggers),
+            ..Self::new(camera, EventKind::Detection, now)
+        }
+    }
+
+    pub(crate) fn smart(camera: &str, smart: &SmartEvent, now: OffsetDateTime) -> Self {
+        Self {
+            sources: vec![smart.kind.to_string()],
+            objects: smart.ai_types.clone(),
+            zones: smart.zone.into_iter().collect(),
+            ..Self::new(camera, EventKind::Smart, now)
+        }
+    }
+
+    pub(crate) fn to_json(&self) -> Result<String> {
+        Ok(serde_json::to_string(self)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use time::{Date, Month, PrimitiveDateTime, Time, UtcOffset};
+
+    // These are what consumers parse, if one of them has to change then
+    // so must EVENT_VERSION unless it only adds an optional field
+
+    fn now() -> OffsetDateTime {
+        let date = Date::from_calendar_date(2024, Month::March, 1).unwrap();
+        PrimitiveDateTime::new(date, Time::from_hms(12, 30, 0).unwrap())
+            .assume_offset(UtcOffset::from_hms(10, 0, 0).unwrap())
+    }
+
+    #[test]
+    fn test_event_version() {
+        assert_eq!(EVENT_VERSION, 1);
+    }
+
+    #[test]
+    fn test_event_json() {
+        let event = Event::new("Camera01", EventKind::Connected, now());
+        assert_eq!(
+            event.to_json().unwrap(),
+            r#"{"version":1,"camera":"Camera01","event":"connected","time":"2024-03-01T12:30:00+10:00"}"#
+        );
+    }
+
+    #[test]
+    fn test_event_json_all_fields() {
+        let event = Event {
+            sources: vec!["md".to_string(), "ai".to_string()],
+            objects: vec!["people".to_string()],
+            zones: vec![0, 2],
+            triggers: Some(3),
+            label: Some("Parcel".to_string()),
+            recording: Some(true),
+            snapshot: Some("/9j/".to_string()),
+            ..Event::new("Camera01", EventKind::Bookmark, now())
+        };
+        let json = r#"{"version":1,"camera":"Camera01","event":"bookmark","time":"2024-03-01T12:30:00+10:00","sources":["md","ai"],"objects":["people"],"zones":[0,2],"triggers":3,"label":"Parcel","recording":true,"snapshot":"/9j/"}"#;
+        assert_eq!(event.to_json().unwrap(), json);
+        assert_eq!(serde_json::from_str::<Event>(json).unwrap(), event);
+    }
+
+    #[test]
+    fn test_event_json_detection() {
+        let detection = DetectionEvent {
+            sources: ["pir", "ai"].iter().map(|s| s.to_string()).collect(),
+            objects: ["vehicle"].iter().map(|s| s.to_string()).collect(),
+            zones: Default::default(),
+            triggers: 2,
+        };
+        assert_eq!(
+            Event::detection("Camera01", &detection, now())
+                .to_json()
+                .unwrap(),
+            r#"{"version":1,"camera":"Camera01","event":"detection","time":"2024-03-01T12:30:00+10:00","sources":["ai","pir"],"objects":["vehicle"],"triggers":2}"#
+        );
+    }
+
+    #[test]
+    fn test_event_json_smart() {
+        let smart = SmartEvent {
+            at: std::time::Instant::now(),
+            kind: "crossline".into(),
+            zone: Some(1),
+            ai_types: vec!["people".to_string()],
+        };
+        assert_eq!(
+            Event::smart("Camera01", &smart, now()).to_json().unwrap(),
+            r#"{"version":1,"camera":"Camera01","event":"smart","time":"2024-03-01T12:30:00+10:00","sources":["line_crossing"],"objects":["people"],"zones":[1]}"#
+        );
+    }
+
+    #[test]
+    fn test_event_kind_names() {
+        let kinds = [
+            (EventKind::MotionStart, "motion_start"),
+            (EventKind::MotionStop, "motion_stop"),
+            (EventKind::Detection, "detection"),
+            (EventKind::Smart, "smart"),
+            (EventKind::Bookmark, "bookmark"),
+            (EventKind::Visitor, "visitor"),
+            (EventKind::Connected, "connected"),
+            (EventKind::Disconnected, "disconnected"),
+        ];
+        for (kind, name) in kinds.iter() {
+            assert_eq!(kind.name(), *name);
+            assert_eq!(
+                serde_json::to_string(kind).unwrap(),
+                format!("\"{}\"", name)
+            );
+        }
+    }
+
+    #[test]
+    fn test_event_json_unknown_field() {
+        // Fields added later must not break reading older events
+        let json = r#"{"version":1,"camera":"Camera01","event":"visitor","time":"2024-03-01T12:30:00+10:00","new_field":"ignored"}"#;
+        assert_eq!(
+            serde_json::from_str::<Event>(json).unwrap(),
+            Event::new("Camera01", EventKind::Visitor, now())
+        );
+    }
+}
@@ -0,0 +1,188 @@
+//! EXIF tags for the snapshots
+//!
+//! The jpegs of the cameras have no metadata so photo tools sort them by the
+//! time that the file was written and cannot tell which camera took them.
+//! This adds an EXIF segment with the capture time, the camera name and the
+//! model of the camera
+use time::OffsetDateTime;
+
+/// The largest payload of a jpeg segment
+const MAX_SEGMENT: usize = 0xFFFF - 2;
+
+const TAG_IMAGE_DESCRIPTION: u16 = 0x010E;
+const TAG_MAKE: u16 = 0x010F;
+const TAG_MODEL: u16 = 0x0110;
+const TAG_SOFTWARE: u16 = 0x0131;
+const TAG_DATE_TIME: u16 = 0x0132;
+const TAG_EXIF_IFD: u16 = 0x8769;
+const TAG_DATE_TIME_ORIGINAL: u16 = 0x9003;
+const TAG_OFFSET_TIME_ORIGINAL: u16 = 0x9011;
+
+/// The tags that are written to a snapshot
+pub(crate) struct ExifTags<'a> {
+    /// The name of the camera in the config
+    pub(crate) camera: &'a str,
+    /// The model that the camera reports, if it is known yet
+    pub(crate) model: Option<&'a str>,
+    /// When the image was captured, in the camera's timezone
+    pub(crate) time: OffsetDateTime,
+}
+
+enum Value {
+    Ascii(String),
+    Long(u32),
+}
+
+impl Value {
+    fn kind(&self) -> u16 {
+        match self {
+            Value::Ascii(_) => 2,
+            Value::Long(_) => 4,
+        }
+    }
+
+    fn data(&self) -> Vec<u8> {
+        match self {
+            Value::Ascii(text) => {
+                let mut data = text.as_bytes().to_vec();
+                data.push(0);
+                data
+            }
+            Value::Long(value) => value.to_be_bytes().to_vec(),
+        }
+    }
+
+    fn count(&self) -> u32 {
+        match self {
+            Value::Ascii(text) => text.len() as u32 + 1,
+            Value::Long(_) => 1,
+        }
+    }
+}
+
+/// Add the EXIF tags to a jpeg
+///
+/// Any EXIF that the jpeg already has is replaced. Data that is not a jpeg
+/// is returned as it is
+pub(crate) fn embed(jpeg: &[u8], tags: &ExifTags) -> Vec<u8> {
+    if !jpeg.starts_with(&[0xFF, 0xD8]) {
+        return jpeg.to_vec();
+    }
+    let segment = app1(tags);
+    if segment.len() > MAX_SEGMENT {
+        log::debug!("The EXIF segment is too large for a jpeg");
+        return jpeg.to_vec();
+    }
+
+    let mut out = Vec::with_capacity(jpeg.len() + segment.len() + 4);
+    out.extend_from_slice(&jpeg[..2]);
+    let mut pos = 2;
+    let mut written = false;
+    // Walk the APPn segments at the start, keeping the JFIF APP0 first and
+    // dropping any old EXIF
+    while pos + 4 <= jpeg.len() && jpeg[pos] == 0xFF && (0xE0..=0xEF).contains(&jpeg[pos + 1]) {
+        let marker = jpeg[pos + 1];
+        let len = u16::from_be_bytes([jpeg[pos + 2], jpeg[pos + 3]]) as usize;
+        let end = pos + 2 + len;
+        if len < 2 || end > jpeg.len() {
+            break;
+        }
+        let is_exif = marker == 0xE1 && jpeg[pos + 4..end].starts_with(b"Exif\0\0");
+        if marker != 0xE0 && !written {
+            write_segment(&mut out, &segment);
+            written = true;
+        }
+        if !is_exif {
+            out.extend_from_slice(&jpeg[pos..end]);
+        }
+        pos = end;
+    }
+    if !written {
+        write_segment(&mut out, &segment);
+    }
+    out.extend_from_slice(&jpeg[pos..]);
+    out
+}
+
+fn write_segment(out: &mut Vec<u8>, segment: &[u8]) {
+    out.extend_from_slice(&[0xFF, 0xE1]);
+    out.extend_from_slice(&(segment.len() as u16 + 2).to_be_bytes());
+    out.extend_from_slice(segment);
+}
+
+/// The payload of the APP1 segment, a big endian TIFF header and two IFDs
+fn app1(tags: &ExifTags) -> Vec<u8> {
+    let time = &tags.time;
+    let date_time = format!(
+        "{:04}:{:02}:{:02} {:02}:{:02}:{:02}",
+        time.year(),
+        time.month() as u8,
+        time.day(),
+        time.hour(),
+        time.minute(),
+        time.second()
+    );
+    let offset = time.offset();
+    let offset_time = format!(
+        "{}{:02}:{:02}",
+        if offset.is_negative() { '-' } else { '+' },
+        offset.whole_hours().abs(),
+        offset.minutes_past_hour().abs()
+    );
+
+    let mut ifd0 = vec![(TAG_IMAGE_DESCRIPTION, Value::Ascii(tags.camera.to_string()))];
+    ifd0.push((TAG_MAKE, Value::Ascii("Reolink".to_string())));
+    if let Some(model) = tags.model {
+        ifd0.push((TAG_MODEL, Value::Ascii(model.to_string())));
+    }
+    ifd0.push((TAG_SOFTWARE, Value::Ascii("neolink".to_string())));
+    ifd0.push((TAG_DATE_TIME, Value::Ascii(date_time.clone())));
+    // The offset of the EXIF IFD is filled in once the size of IFD0 is known
+    ifd0.push((TAG_EXIF_IFD, Value::Long(0)));
+    let exif_ifd = vec![
+        (TAG_DATE_TIME_ORIGINAL, Value::Ascii(date_time)),
+        (TAG_OFFSET_TIME_ORIGINAL, Value::Ascii(offset_time)),
+    ];
+
+    // The TIFF header is 8 bytes and IFD0 follows it
+    let ifd0_len = ifd(&ifd0, 8).len();
+    let exif_start = 8 + ifd0_len;
+    if let Some((_, value)) = ifd0.last_mut() {
+        *value = Value::Long(exif_start as u32);
+    }
+
+    let mut out = b"Exif\0\0".to_vec();
+    out.extend_from_slice(b"MM\0\x2A");
+    out.extend_from_slice(&8u32.to_be_bytes());
+    out.extend(ifd(&ifd0, 8));
+    out.extend(ifd(&exif_ifd, exif_start));
+    out
+}
+
+/// An IFD and its data, `start` is its offset from the TIFF header
+fn ifd(entries: &[(u16, Value)], start: usize) -> Vec<u8> {
+    let data_start = start + 2 + 12 * entries.len() + 4;
+    let mut out = (entries.len() as u16).to_be_bytes().to_vec();
+    let mut data = vec![];
+    for (tag, value) in entries.iter() {
+        out.extend_from_slice(&tag.to_be_bytes());
+        out.extend_from_slice(&value.kind().to_be_bytes());
+        out.extend_from_slice(&value.count().to_be_bytes());
+        let mut bytes = value.data();
+        if bytes.len() <= 4 {
+            bytes.resize(4, 0);
+            out.extend(bytes);
+        } else {
+            out.extend_from_slice(&((data_start + data.len()) as u32).to_be_bytes());
+            data.extend(bytes);
+            // Values start on a word boundary
+            if data.len() % 2 == 1 {
+                data.push(0);
+            }
+        }
+    }
+    // No next IFD
+    out.extend_from_slice(&0u32.to_be_bytes());
+    out.extend(data);
+    out
+}
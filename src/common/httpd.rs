@@ -0,0 +1,196 @@
+//! A minimal HTTP/1.1 server for the small web services of neolink
+//!
+//! Each connection carries a single request and is closed after the reply.
+//! The request must arrive within [`READ_TIMEOUT`] and its head and body are
+//! capped in size so that a slow or broken client cannot hold a task, a
+//! socket or memory
+use anyhow::{anyhow, Result};
+use log::*;
+use std::{future::Future, net::SocketAddr};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+    time::{timeout, Duration},
+};
+
+/// Largest request line and headers that are accepted
+const MAX_HEAD: usize = 16 * 1024;
+
+/// How long a client has to send the whole request
+const READ_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// A request to one of the servers
+pub(crate) struct Request {
+    pub(crate) method: String,
+    /// The path with the query
+    pub(crate) path: String,
+    pub(crate) body: Vec<u8>,
+    /// The address of neolink that the request came in on
+    pub(crate) local_addr: SocketAddr,
+    head: String,
+}
+
+impl Request {
+    /// The value of the header `name`, which is not case sensitive
+    pub(crate) fn header(&self, name: &str) -> Option<&str> {
+        self.head
+            .lines()
+            .skip(1)
+            .filter_map(|line| line.split_once(':'))
+            .find(|(key, _)| key.trim().eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.trim())
+    }
+}
+
+/// A reply to a request
+pub(crate) struct Response {
+    pub(crate) status: &'static str,
+    pub(crate) content_type: &'static str,
+    /// Headers other than those that every reply has
+    pub(crate) headers: Vec<(&'static str, String)>,
+    pub(crate) body: Vec<u8>,
+}
+
+impl Response {
+    pub(crate) fn new(status: &'static str, content_type: &'static str, body: Vec<u8>) -> Self {
+        Self {
+            status,
+            content_type,
+            headers: vec![],
+            body,
+        }
+    }
+
+    pub(crate) fn text(status: &'static str, body: &str) -> Self {
+        Self::new(
+            status,
+            "text/plain; charset=utf-8",
+            body.as_bytes().to_vec(),
+        )
+    }
+
+    /// Add a header to the reply
+    pub(crate) fn with_header(mut self, name: &'static str, value: impl Into<String>) -> Self {
+        self.headers.push((name, value.into()));
+        self
+    }
+}
+
+/// Accept connections and reply to each request with `handler`
+///
+/// `name` is used in the logs and `max_body` is the largest body that is
+/// accepted, 0 for servers that only answer GET
+pub(crate) async fn serve<F, Fut>(
+    listener: TcpListener,
+    name: &'static str,
+    max_body: usize,
+    handler: F,
+) -> Result<()>
+where
+    F: Fn(Request) -> Fut + Clone + Send + 'static,
+    Fut: Future<Output = Response> + Send + 'static,
+{
+    loop {
+        let (stream, addr) = listener.accept().await?;
+        let handler = handler.clone();
+        tokio::task::spawn(async move {
+            if let Err(e) = handle_connection(stream, name, max_body, handler).await {
+                debug!("{}: Request from {} failed: {:?}", name, addr, e);
+            }
+        });
+    }
+}
+
+/// Handles a single request, the connection is closed after the reply
+async fn handle_connection<F, Fut>(
+    mut stream: TcpStream,
+    name: &str,
+    max_body: usize,
+    handler: F,
+) -> Result<()>
+where
+    F: Fn(Request) -> Fut,
+    Fut: Future<Output = Response>,
+{
+    let Some(request) = timeout(READ_TIMEOUT, read_request(&mut stream, max_body))
+        .await
+        .map_err(|_| anyhow!("The request was not sent in time"))??
+    else {
+        return Ok(());
+    };
+    let method = request.method.clone();
+    let path = request.path.clone();
+    let response = handler(request).await;
+    trace!("{}: {} {} -> {}", name, method, path, response.status);
+
+    let mut reply = format!(
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nCache-Control: no-store\r\nConnection: close\r\n",
+        response.status,
+        response.content_type,
+        response.body.len()
+    );
+    for (name, value) in response.headers.iter() {
+        reply.push_str(&format!("{}: {}\r\n", name, value));
+    }
+    if response.status.starts_with("401") {
+        reply.push_str("WWW-Authenticate: Basic realm=\"neolink\"\r\n");
+    }
+    reply.push_str("\r\n");
+    stream.write_all(reply.as_bytes()).await?;
+    if method != "HEAD" {
+        stream.write_all(&response.body).await?;
+    }
+    stream.shutdown().await?;
+    Ok(())
+}
+
+/// Read the head and the body of a request
+///
+/// `None` if the client closed the connection without sending one
+async fn read_request(stream: &mut TcpStream, max_body: usize) -> Result<Option<Request>> {
+    let mut buf = vec![];
+    let mut chunk = [0u8; 4096];
+    let header_end = loop {
+        let n = stream.read(&mut chunk).await?;
+        if n == 0 {
+            return Ok(None);
+        }
+        buf.extend_from_slice(&chunk[..n]);
+        if let Some(pos) = buf.windows(4).position(|w| w == b"\r\n\r\n") {
+            break pos;
+        }
+        if buf.len() > MAX_HEAD {
+            return Err(anyhow!("Request headers are too large"));
+        }
+    };
+    let head = String::from_utf8_lossy(&buf[..header_end]).to_string();
+    let mut request_line = head.lines().next().unwrap_or_default().split_whitespace();
+    let method = request_line.next().unwrap_or_default().to_string();
+    let path = request_line.next().unwrap_or("/").to_string();
+    let mut request = Request {
+        method,
+        path,
+        body: vec![],
+        local_addr: stream.local_addr()?,
+        head,
+    };
+
+    let length = request
+        .header("content-length")
+        .and_then(|value| value.parse::<usize>().ok())
+        .unwrap_or(0);
+    if length > max_body {
+        return Err(anyhow!("Request body is too large"));
+    }
+    let mut body = buf.split_off(header_end + 4);
+    while body.len() < length {
+        let n = stream.read(&mut chunk).await?;
+        if n == 0 {
+            return Err(anyhow!("Request body ended early"));
+        }
+        body.extend_from_slice(&chunk[..n]);
+    }
+    body.truncate(length);
+    request.body = body;
+    Ok(Some(request))
+}
@@ -6,19 +6,103 @@
 //! whenever the camera is lost/updated
 use anyhow::{anyhow, Context};
 use futures::TryFutureExt;
-use std::sync::{Arc, Weak};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, HashSet},
+    sync::{Arc, Weak},
+};
+use time::{format_description::well_known::Rfc3339, OffsetDateTime};
 use tokio::{
     sync::{
-        mpsc::Sender as MpscSender, oneshot::channel as oneshot, watch::channel as watch,
-        watch::Receiver as WatchReceiver,
+        broadcast::Receiver as BroadcastReceiver, mpsc::Sender as MpscSender,
+        oneshot::channel as oneshot, watch::channel as watch, watch::Receiver as WatchReceiver,
     },
-    time::{sleep, Duration},
+    time::{sleep, timeout, Duration, Instant},
 };
 use tokio_util::sync::CancellationToken;
 
-use super::{MdState, NeoCamCommand, NeoCamThreadState, Permit, PushNoti, StreamInstance};
-use crate::{config::CameraConfig, AnyResult, Result};
-use neolink_core::bc_protocol::{BcCamera, StreamKind};
+use super::{
+    events::DetectionEvent,
+    exif::{self, ExifTags},
+    CameraError, ClockDrift, CommandQueue, ErrorCategory, ErrorHistory, MdState, NeoCamCommand,
+    NeoCamThreadState, Permit, PushNoti, SavedRecord, SnapshotCache, StreamInstance, StreamReport,
+    Subscriptions,
+};
+use crate::{
+    config::{CameraConfig, ZoomRect},
+    AnyResult, Result,
+};
+use neolink_core::bc_protocol::{AbilityReport, BcCamera, SmartEvent, StreamKind};
+
+/// The state of a camera as it appears in `neolink ctl dump-state`
+#[derive(Debug, Clone, Serialize)]
+pub(crate) struct CameraReport {
+    pub(crate) name: String,
+    /// If neolink wants to be connected, as set by `disconnect` and `connect`
+    pub(crate) state: String,
+    /// If there is a logged in connection to the camera
+    pub(crate) online: bool,
+    pub(crate) transport: Option<String>,
+    pub(crate) busy: bool,
+    pub(crate) muted: Vec<String>,
+    /// The streams that have been used since neolink started
+    pub(crate) streams: Vec<StreamReport>,
+    /// The control commands that are waiting or running
+    pub(crate) command_queue: usize,
+    /// How many errors of each kind there have been since neolink started
+    pub(crate) error_counts: BTreeMap<ErrorCategory, u64>,
+    /// The last errors that ended the connection, oldest first
+    pub(crate) errors: Vec<ErrorReport>,
+    /// How far the camera's clock was from the host's at the last check
+    pub(crate) clock_drift: Option<ClockDrift>,
+    /// The raised log level of the camera, if it is raised
+    pub(crate) log_level: Option<String>,
+    /// How many times neolink has logged in to the camera since it started
+    pub(crate) logins: u64,
+    /// The streams, motion and battery polling that are active on the camera
+    pub(crate) subscriptions: Vec<String>,
+    pub(crate) max_subscriptions: Option<usize>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct ErrorReport {
+    pub(crate) time: String,
+    pub(crate) category: ErrorCategory,
+    pub(crate) error: String,
+}
+
+impl From<&CameraError> for ErrorReport {
+    fn from(error: &CameraError) -> Self {
+        Self {
+            time: error
+                .time
+                .format(&Rfc3339)
+                .unwrap_or_else(|_| error.time.to_string()),
+            category: error.category,
+            error: error.error.clone(),
+        }
+    }
+}
+
+/// What a camera is doing as it appears in `neolink status`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct CameraActivity {
+    pub(crate) name: String,
+    /// The streams that are receiving frames
+    pub(crate) streams: Vec<String>,
+    /// When motion last started, if it has since neolink started
+    pub(crate) last_motion: Option<String>,
+}
+
+/// The error history of a camera as it appears in `neolink status --errors`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct CameraErrors {
+    pub(crate) name: String,
+    /// How many errors of each kind there have been since neolink started
+    pub(crate) counts: BTreeMap<ErrorCategory, u64>,
+    /// The last errors, oldest first
+    pub(crate) errors: Vec<ErrorReport>,
+}
 
 /// This instance is the primary interface used throughout the app
 ///
@@ -75,6 +159,55 @@ impl NeoInstance {
         self.run_passive_task(task).await
     }
 
+    /// As [`NeoInstance::run_task`] but for control commands
+    ///
+    /// The commands of all instances of the camera run one at a time in the
+    /// order that they were sent, so that a command that needs several
+    /// messages, such as a PTZ move and stop, is not interleaved with another.
+    /// It fails if it waits in the queue for longer than the `command_queue`
+    /// `wait` or runs for longer than its `timeout`
+    pub(crate) async fn run_command<F, T>(&self, task: F) -> AnyResult<T>
+    where
+        F: for<'a> Fn(
+            &'a BcCamera,
+        )
+            -> std::pin::Pin<Box<dyn futures::Future<Output = AnyResult<T>> + Send + 'a>>,
+    {
+        let (name, queue_config) = {
+            let config = self.config().await?;
+            let config = config.borrow();
+            (config.name.clone(), config.command_queue.clone())
+        };
+        let (instance_tx, instance_rx) = oneshot();
+        self.camera_control
+            .send(NeoCamCommand::CommandQueue(instance_tx))
+            .await?;
+        let queue: CommandQueue = instance_rx.await?;
+
+        // The tokio mutex is fair so the commands are run in order
+        let _turn = timeout(Duration::from_secs(queue_config.wait), queue.lock())
+            .await
+            .map_err(|_| {
+                anyhow!(
+                    "{}: The command waited {}s for the ones before it",
+                    name,
+                    queue_config.wait
+                )
+            })?;
+        timeout(
+            Duration::from_secs(queue_config.timeout),
+            self.run_task(task),
+        )
+        .await
+        .map_err(|_| {
+            anyhow!(
+                "{}: The command did not finish within {}s",
+                name,
+                queue_config.timeout
+            )
+        })?
+    }
+
     /// This is a helpful convience function
     ///
     /// Given an async task it will:
@@ -120,22 +253,28 @@ impl NeoInstance {
                     if let Some(cam) = camera.clone() {
                         let cam_ref = cam.as_ref();
                         let mut r = Err(anyhow!("No run"));
-                        for i in 0..5 {
+                        let mut retry = 0;
+                        loop {
                             r = task(cam_ref).await;
                             if let Err(e) = &r {
                                 log::debug!("- Task Error: {e:?}");
                             }
-                            if let Err(Some(e @ neolink_core::Error::CameraServiceUnavailable{code: 400, ..})) = r.as_ref().map_err(|e| e.downcast_ref::<neolink_core::Error>()) {
+                            if let Err(Some(e @ neolink_core::Error::CameraServiceUnavailable{id, code})) = r.as_ref().map_err(|e| e.downcast_ref::<neolink_core::Error>()) {
                                 // Retryable without a reconnect
-                                // Usually occurs when camera is starting up
-                                // or the connection is initialising
-                                log::debug!("Got a 400 code for {e:?} retry {i}/5, ");
-
-                                sleep(Duration::from_secs(1)).await;
-                                continue;
-                            } else {
-                                break;
+                                // Usually occurs when camera is starting up,
+                                // the connection is initialising or the camera is busy
+                                let policy = match self.config().await {
+                                    Ok(config) => config.borrow().retry.policy(*id).clone(),
+                                    Err(_) => Default::default(),
+                                };
+                                retry += 1;
+                                if retry < policy.attempts && policy.codes.contains(code) {
+                                    log::debug!("Got a {code} code for {e:?} retry {retry}/{}, ", policy.attempts - 1);
+                                    sleep(policy.delay(retry)).await;
+                                    continue;
+                                }
                             }
+                            break;
                         }
                         r
                     } else {
@@ -225,7 +364,21 @@ impl NeoInstance {
         }
     }
 
+    /// Fails when the camera's idle policy is `control_only`
+    async fn check_streaming(&self) -> Result<()> {
+        let config = self.config().await?;
+        let config = config.borrow();
+        if config.idle_policy.control_only {
+            return Err(anyhow!(
+                "{}: Streaming is disabled by the control_only idle policy",
+                config.name
+            ));
+        }
+        Ok(())
+    }
+
     pub(crate) async fn stream(&self, name: StreamKind) -> Result<StreamInstance> {
+        self.check_streaming().await?;
         let (instance_tx, instance_rx) = oneshot();
         self.camera_control
             .send(NeoCamCommand::Stream(name, instance_tx))
@@ -235,6 +388,7 @@ impl NeoInstance {
 
     #[allow(dead_code)]
     pub(crate) async fn low_stream(&self) -> Result<Option<StreamInstance>> {
+        self.check_streaming().await?;
         let (instance_tx, instance_rx) = oneshot();
         self.camera_control
             .send(NeoCamCommand::LowStream(instance_tx))
@@ -244,6 +398,7 @@ impl NeoInstance {
 
     #[allow(dead_code)]
     pub(crate) async fn high_stream(&self) -> Result<Option<StreamInstance>> {
+        self.check_streaming().await?;
         let (instance_tx, instance_rx) = oneshot();
         self.camera_control
             .send(NeoCamCommand::HighStream(instance_tx))
@@ -253,6 +408,7 @@ impl NeoInstance {
 
     #[allow(dead_code)]
     pub(crate) async fn streams(&self) -> Result<Vec<StreamInstance>> {
+        self.check_streaming().await?;
         let (instance_tx, instance_rx) = oneshot();
         self.camera_control
             .send(NeoCamCommand::Streams(instance_tx))
@@ -268,6 +424,52 @@ impl NeoInstance {
         Ok(reply_rx.await?)
     }
 
+    /// Expand a name template such as `{nvr}_{channel_name}_{stream}`
+    ///
+    /// - `{name}`: The name of the camera in the config
+    /// - `{nvr}`: The device name reported by the camera or NVR
+    /// - `{channel}`: The channel id
+    /// - `{channel_name}`: The title of the channel reported by the camera or NVR
+    /// - `{stream}`: The stream name `main`, `sub` or `extern`
+    ///
+    /// This will wait for the camera to connect
+    pub(crate) async fn expand_template(
+        &self,
+        template: &str,
+        stream: Option<StreamKind>,
+    ) -> Result<String> {
+        let config = self.config().await?.borrow().clone();
+        let (nvr, channel_name) = self
+            .run_task(|cam| {
+                Box::pin(async move {
+                    let nvr = cam.version().await?.name;
+                    // Not all devices support the osd so fallback to the channel id
+                    let channel_name = cam.get_channel_name().await.ok();
+                    AnyResult::Ok((nvr, channel_name))
+                })
+            })
+            .await?;
+        let channel = format!("{}", config.channel_id);
+        let stream = match stream {
+            Some(StreamKind::Main) => "main",
+            Some(StreamKind::Sub) => "sub",
+            Some(StreamKind::Extern) => "extern",
+            None => "",
+        };
+        Ok(template
+            .replace("{name}", &config.name)
+            .replace("{nvr}", &nvr)
+            .replace("{channel}", &channel)
+            .replace("{channel_name}", channel_name.as_deref().unwrap_or(&channel))
+            .replace("{stream}", stream)
+            .chars()
+            .map(|c| match c {
+                ' ' | '/' | '#' | '+' => '_',
+                c => c,
+            })
+            .collect())
+    }
+
     pub(crate) async fn push_notifications(&self) -> Result<WatchReceiver<Option<PushNoti>>> {
         let uid = self.uid().await?;
         let (instance_tx, instance_rx) = oneshot();
@@ -310,6 +512,26 @@ impl NeoInstance {
         Ok(instance_rx.await?)
     }
 
+    /// Smart detections such as line crossings, these are events rather than a state
+    /// and so are only received while subscribed
+    pub(crate) async fn smart_events(&self) -> Result<BroadcastReceiver<SmartEvent>> {
+        let (instance_tx, instance_rx) = oneshot();
+        self.camera_control
+            .send(NeoCamCommand::Smart(instance_tx))
+            .await?;
+        Ok(instance_rx.await?)
+    }
+
+    /// Motion, PIR, AI and smart detections merged into one event for each
+    /// physical event, these are only received while subscribed
+    pub(crate) async fn events(&self) -> Result<BroadcastReceiver<DetectionEvent>> {
+        let (instance_tx, instance_rx) = oneshot();
+        self.camera_control
+            .send(NeoCamCommand::Events(instance_tx))
+            .await?;
+        Ok(instance_rx.await?)
+    }
+
     pub(crate) async fn config(&self) -> Result<WatchReceiver<CameraConfig>> {
         let (instance_tx, instance_rx) = oneshot();
         self.camera_control
@@ -318,6 +540,203 @@ impl NeoInstance {
         Ok(instance_rx.await?)
     }
 
+    #[cfg(feature = "gstreamer")]
+    pub(crate) async fn digital_zoom(&self) -> Result<WatchReceiver<ZoomRect>> {
+        let (instance_tx, instance_rx) = oneshot();
+        self.camera_control
+            .send(NeoCamCommand::DigitalZoom(instance_tx))
+            .await?;
+        Ok(instance_rx.await?)
+    }
+
+    pub(crate) async fn set_digital_zoom(&self, rect: ZoomRect) -> Result<()> {
+        let (instance_tx, instance_rx) = oneshot();
+        self.camera_control
+            .send(NeoCamCommand::SetDigitalZoom(rect, instance_tx))
+            .await?;
+        Ok(instance_rx.await?)
+    }
+
+    /// The streams that are currently served without audio
+    pub(crate) async fn muted(&self) -> Result<WatchReceiver<HashSet<StreamKind>>> {
+        let (instance_tx, instance_rx) = oneshot();
+        self.camera_control
+            .send(NeoCamCommand::Mute(instance_tx))
+            .await?;
+        Ok(instance_rx.await?)
+    }
+
+    pub(crate) async fn set_mute(&self, stream: StreamKind, muted: bool) -> Result<()> {
+        let (instance_tx, instance_rx) = oneshot();
+        self.camera_control
+            .send(NeoCamCommand::SetMute(stream, muted, instance_tx))
+            .await?;
+        Ok(instance_rx.await?)
+    }
+
+    /// True while the camera is refusing logins because it has
+    /// reached its maximum number of clients
+    pub(crate) async fn busy(&self) -> Result<WatchReceiver<bool>> {
+        let (instance_tx, instance_rx) = oneshot();
+        self.camera_control
+            .send(NeoCamCommand::Busy(instance_tx))
+            .await?;
+        Ok(instance_rx.await?)
+    }
+
+    /// Get a jpeg snapshot from the camera
+    ///
+    /// If one was taken within the camera's `snapshot_max_age` it is
+    /// returned instead of asking the camera again
+    pub(crate) async fn snapshot(&self) -> AnyResult<Vec<u8>> {
+        self.cached_snapshot(false).await
+    }
+
+    /// As [`NeoInstance::snapshot`] but without taking out a use permit
+    /// so a fresh snapshot will not wake a camera that is disconnected
+    pub(crate) async fn passive_snapshot(&self) -> AnyResult<Vec<u8>> {
+        self.cached_snapshot(true).await
+    }
+
+    /// Get a small jpeg of the substream, `thumbnail_width` pixels wide
+    ///
+    /// The substream is played until a keyframe arrives so it can be decoded
+    #[cfg(feature = "gstreamer")]
+    pub(crate) async fn thumbnail(&self) -> AnyResult<Vec<u8>> {
+        let width = self.config().await?.borrow().thumbnail_width;
+        let stream = self
+            .low_stream()
+            .await?
+            .ok_or_else(|| anyhow!("The camera has no substream for thumbnails"))?;
+        super::thumbnail(&stream, width).await
+    }
+
+    /// Thumbnails need gstreamer to decode the substream
+    #[cfg(not(feature = "gstreamer"))]
+    pub(crate) async fn thumbnail(&self) -> AnyResult<Vec<u8>> {
+        Err(anyhow!("Thumbnails need neolink to be built with gstreamer"))
+    }
+
+    /// Get the abilities that the camera reports for the user
+    pub(crate) async fn ability_report(&self) -> AnyResult<AbilityReport> {
+        self.run_command(|cam| {
+            Box::pin(async move {
+                cam.get_ability_report()
+                    .await
+                    .context("Unable to get the camera's abilities")
+            })
+        })
+        .await
+    }
+
+    async fn cached_snapshot(&self, passive: bool) -> AnyResult<Vec<u8>> {
+        let max_age = Duration::from_secs(self.config().await?.borrow().snapshot_max_age);
+        let (instance_tx, instance_rx) = oneshot();
+        self.camera_control
+            .send(NeoCamCommand::Snapshot(instance_tx))
+            .await?;
+        let cache: SnapshotCache = instance_rx.await?;
+
+        // Holding the lock while fetching means that concurrent requests
+        // wait for this one and are then served from the cache
+        let mut cache = cache.lock().await;
+        if let Some((taken, data)) = cache.as_ref() {
+            if taken.elapsed() < max_age {
+                log::debug!("Using the cached snapshot from {:?} ago", taken.elapsed());
+                return Ok(data.clone());
+            }
+        }
+
+        let data = if passive {
+            self.run_passive_task(|cam| {
+                Box::pin(async move { AnyResult::Ok(cam.get_snapshot().await?) })
+            })
+            .await?
+        } else {
+            self.run_task(|cam| Box::pin(async move { AnyResult::Ok(cam.get_snapshot().await?) }))
+                .await?
+        };
+        let data = self.exif(&data).await?;
+        if !max_age.is_zero() {
+            *cache = Some((Instant::now(), data.clone()));
+        }
+        Ok(data)
+    }
+
+    /// Add the EXIF tags of the camera to a jpeg captured now
+    ///
+    /// The jpeg is returned as it is when `snapshot_exif` is off
+    pub(crate) async fn exif(&self, jpeg: &[u8]) -> AnyResult<Vec<u8>> {
+        let config = self.config().await?.borrow().clone();
+        if !config.snapshot_exif {
+            return Ok(jpeg.to_vec());
+        }
+        let model = self.model().await?.borrow().clone();
+        Ok(exif::embed(
+            jpeg,
+            &ExifTags {
+                camera: &config.name,
+                model: model.as_deref(),
+                time: config.timezone().now(),
+            },
+        ))
+    }
+
+    /// How far the camera's clock was from the host's at the last check,
+    /// `None` until it has been checked
+    pub(crate) async fn clock_drift(&self) -> Result<WatchReceiver<Option<ClockDrift>>> {
+        let (instance_tx, instance_rx) = oneshot();
+        self.camera_control
+            .send(NeoCamCommand::ClockDrift(instance_tx))
+            .await?;
+        Ok(instance_rx.await?)
+    }
+
+    /// How many times neolink has logged in to the camera since it started
+    pub(crate) async fn logins(&self) -> Result<WatchReceiver<u64>> {
+        let (instance_tx, instance_rx) = oneshot();
+        self.camera_control
+            .send(NeoCamCommand::Logins(instance_tx))
+            .await?;
+        Ok(instance_rx.await?)
+    }
+
+    /// What is active on the camera, see [`Subscriptions`]
+    pub(crate) async fn subscriptions(&self) -> Result<Subscriptions> {
+        let (instance_tx, instance_rx) = oneshot();
+        self.camera_control
+            .send(NeoCamCommand::Subscriptions(instance_tx))
+            .await?;
+        Ok(instance_rx.await?)
+    }
+
+    /// When motion last started, `None` until it has
+    pub(crate) async fn last_motion(&self) -> Result<WatchReceiver<Option<OffsetDateTime>>> {
+        let (instance_tx, instance_rx) = oneshot();
+        self.camera_control
+            .send(NeoCamCommand::LastMotion(instance_tx))
+            .await?;
+        Ok(instance_rx.await?)
+    }
+
+    /// The recording schedule replaced by a manual record, see [`SavedRecord`]
+    pub(crate) async fn saved_record(&self) -> Result<SavedRecord> {
+        let (instance_tx, instance_rx) = oneshot();
+        self.camera_control
+            .send(NeoCamCommand::SavedRecord(instance_tx))
+            .await?;
+        Ok(instance_rx.await?)
+    }
+
+    /// The model that the camera reports, `None` until it has connected
+    pub(crate) async fn model(&self) -> Result<WatchReceiver<Option<String>>> {
+        let (instance_tx, instance_rx) = oneshot();
+        self.camera_control
+            .send(NeoCamCommand::Model(instance_tx))
+            .await?;
+        Ok(instance_rx.await?)
+    }
+
     pub(crate) fn camera(&self) -> WatchReceiver<Weak<BcCamera>> {
         self.camera_watch.clone()
     }
@@ -338,7 +757,6 @@ impl NeoInstance {
         Ok(instance_rx.await?)
     }
 
-    #[allow(dead_code)]
     pub(crate) async fn get_state(&self) -> Result<NeoCamThreadState> {
         let (instance_tx, instance_rx) = oneshot();
         self.camera_control
@@ -355,6 +773,113 @@ impl NeoInstance {
         Ok(instance_rx.await?)
     }
 
+    /// The last errors that ended the connection to the camera
+    pub(crate) async fn errors(&self) -> Result<WatchReceiver<ErrorHistory>> {
+        let (instance_tx, instance_rx) = oneshot();
+        self.camera_control
+            .send(NeoCamCommand::Errors(instance_tx))
+            .await?;
+        Ok(instance_rx.await?)
+    }
+
+    /// The error history of the camera
+    ///
+    /// This will not start the camera
+    pub(crate) async fn error_report(&self) -> Result<CameraErrors> {
+        let name = self.config().await?.borrow().name.clone();
+        let history = self.errors().await?.borrow().clone();
+        Ok(CameraErrors {
+            name,
+            counts: history.counts,
+            errors: history.errors.iter().map(ErrorReport::from).collect(),
+        })
+    }
+
+    /// The running streams and last motion of the camera
+    ///
+    /// Like [`NeoInstance::report`] this will not start the camera or its streams
+    pub(crate) async fn activity(&self) -> Result<CameraActivity> {
+        let name = self.config().await?.borrow().name.clone();
+        let (instance_tx, instance_rx) = oneshot();
+        self.camera_control
+            .send(NeoCamCommand::StreamReports(instance_tx))
+            .await?;
+        let streams = instance_rx
+            .await?
+            .into_iter()
+            .filter(|report| report.health == "started" || report.health == "bitrate_dropped")
+            .map(|report| report.stream)
+            .collect();
+        let last_motion = *self.last_motion().await?.borrow();
+        Ok(CameraActivity {
+            name,
+            streams,
+            last_motion: last_motion
+                .map(|time| time.format(&Rfc3339).unwrap_or_else(|_| time.to_string())),
+        })
+    }
+
+    /// A snapshot of the camera's state for bug reports
+    ///
+    /// Unlike the other calls this will not start the camera or its streams
+    pub(crate) async fn report(&self) -> Result<CameraReport> {
+        let config = self.config().await?.borrow().clone();
+        let camera = self.camera_watch.borrow().upgrade();
+
+        let (instance_tx, instance_rx) = oneshot();
+        self.camera_control
+            .send(NeoCamCommand::StreamReports(instance_tx))
+            .await?;
+        let streams = instance_rx.await?;
+
+        let (instance_tx, instance_rx) = oneshot();
+        self.camera_control
+            .send(NeoCamCommand::CommandQueue(instance_tx))
+            .await?;
+        let queue: CommandQueue = instance_rx.await?;
+        // Every command that is waiting or running holds a copy, as do
+        // the camera and ourselves
+        let command_queue = Arc::strong_count(&queue).saturating_sub(2);
+
+        let state = match self.get_state().await? {
+            NeoCamThreadState::Connected => "connected",
+            NeoCamThreadState::Disconnected => "disconnected",
+        };
+        let busy = *self.busy().await?.borrow();
+        let muted = self
+            .muted()
+            .await?
+            .borrow()
+            .iter()
+            .map(|stream| stream.to_string())
+            .collect();
+        let errors = self.error_report().await?;
+        let clock_drift = *self.clock_drift().await?.borrow();
+        let log_level = super::loglevel::boosts()
+            .get(&config.name)
+            .map(|level| level.to_string().to_lowercase());
+        let logins = *self.logins().await?.borrow();
+        let subscriptions = self.subscriptions().await?;
+
+        Ok(CameraReport {
+            name: config.name,
+            state: state.to_string(),
+            online: camera.is_some(),
+            transport: camera.map(|camera| camera.transport().to_string()),
+            busy,
+            muted,
+            streams,
+            command_queue,
+            error_counts: errors.counts,
+            errors: errors.errors,
+            clock_drift,
+            log_level,
+            logins,
+            subscriptions: subscriptions.active(),
+            max_subscriptions: subscriptions.limit(),
+        })
+    }
+
     pub(crate) fn drop_command<F>(self, task: F, timeout: tokio::time::Duration) -> DropRunTask<F>
     where
         F: for<'a> Fn(
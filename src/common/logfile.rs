@@ -0,0 +1,148 @@
+//! Files that neolink appends lines of JSON to, such as the audit log and the
+//! bookmarks
+//!
+//! These grow without end on a busy camera so when the `[rotation]` config has
+//! a `max_size` a file that reaches it is moved aside as `{path}.1`, the
+//! older ones move up to `{path}.2` and so on and the oldest past `keep` is
+//! removed. The moved files are compressed with gzip or zstd. The current file
+//! is never compressed so that it can still be appended to and read with
+//! `tail -f`
+use anyhow::Context;
+use once_cell::sync::Lazy;
+use std::{
+    ffi::OsString,
+    fs::{self, File, OpenOptions},
+    io::{self, Write},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex,
+    },
+};
+
+use crate::{
+    config::{Compression, RotationConfig},
+    AnyResult,
+};
+
+/// The `[rotation]` of the config, held while the file is moved aside so
+/// that two lines at once do not both rotate it
+static ROTATION: Lazy<Mutex<RotationConfig>> = Lazy::new(Default::default);
+
+/// Makes the names of the files that are waiting to be compressed unique
+static PENDING: AtomicU64 = AtomicU64::new(0);
+
+/// Use the `[rotation]` of the config, without this the files are never rotated
+pub(crate) fn init(rotation: &RotationConfig) {
+    *ROTATION.lock().unwrap_or_else(|e| e.into_inner()) = rotation.clone();
+}
+
+/// Append one line to the file, rotating it first if it would pass `max_size`
+///
+/// The line is one write so that the lines of other neolinks that share the
+/// file are not interleaved
+pub(crate) fn append_line(path: &Path, line: &str) -> AnyResult<()> {
+    let line = format!("{}\n", line);
+    let pending = {
+        let rotation = ROTATION.lock().unwrap_or_else(|e| e.into_inner());
+        let mut pending = None;
+        if rotation.max_size > 0 {
+            let size = fs::metadata(path).map(|meta| meta.len()).unwrap_or(0);
+            if size > 0 && size + line.len() as u64 > rotation.max_size * 1024 * 1024 {
+                pending = rotate(path, &rotation)
+                    .with_context(|| format!("Failed to rotate {:?}", path))?;
+            }
+        }
+        pending
+    };
+    if let Some((moved, first, compress)) = pending {
+        // Compressing a large file is slow so it is not done while holding
+        // the lock or on the thread of the caller
+        let job = move || {
+            if let Err(e) = compress_file(&moved, &first, compress) {
+                log::warn!("Failed to compress {:?} to {:?}: {:?}", moved, first, e);
+            }
+        };
+        if let Ok(handle) = tokio::runtime::Handle::try_current() {
+            handle.spawn_blocking(job);
+        } else {
+            std::thread::spawn(job);
+        }
+    }
+    OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)?
+        .write_all(line.as_bytes())?;
+    Ok(())
+}
+
+/// The name of the `n`th rotated file
+fn rotated(path: &Path, n: usize, compress: Compression) -> PathBuf {
+    let mut name = OsString::from(path.as_os_str());
+    name.push(format!(".{}", n));
+    match compress {
+        Compression::None => {}
+        Compression::Gzip => name.push(".gz"),
+        Compression::Zstd => name.push(".zst"),
+    }
+    PathBuf::from(name)
+}
+
+/// Move the older files up and the file aside
+///
+/// Only the renames are done here. When the file is to be compressed it is
+/// moved to a temporary name and that, the rotated name and the compression
+/// are returned so that it is compressed afterwards
+fn rotate(
+    path: &Path,
+    rotation: &RotationConfig,
+) -> AnyResult<Option<(PathBuf, PathBuf, Compression)>> {
+    let compress = rotation.compress;
+    match fs::remove_file(rotated(path, rotation.keep, compress)) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e.into()),
+        _ => {}
+    }
+    for n in (1..rotation.keep).rev() {
+        let from = rotated(path, n, compress);
+        if from.exists() {
+            fs::rename(&from, rotated(path, n + 1, compress))?;
+        }
+    }
+
+    let first = rotated(path, 1, compress);
+    if matches!(compress, Compression::None) {
+        fs::rename(path, &first)?;
+        log::debug!("Rotated {:?} to {:?}", path, first);
+        return Ok(None);
+    }
+    let mut moved = OsString::from(path.as_os_str());
+    moved.push(format!(
+        ".{}-{}.pending",
+        std::process::id(),
+        PENDING.fetch_add(1, Ordering::Relaxed)
+    ));
+    let moved = PathBuf::from(moved);
+    fs::rename(path, &moved)?;
+    Ok(Some((moved, first, compress)))
+}
+
+/// Compress the moved aside file to its rotated name and remove it
+fn compress_file(moved: &Path, first: &Path, compress: Compression) -> AnyResult<()> {
+    match compress {
+        Compression::None => fs::rename(moved, first)?,
+        Compression::Gzip => {
+            let mut encoder =
+                flate2::write::GzEncoder::new(File::create(first)?, flate2::Compression::default());
+            io::copy(&mut File::open(moved)?, &mut encoder)?;
+            encoder.finish()?;
+            fs::remove_file(moved)?;
+        }
+        Compression::Zstd => {
+            zstd::stream::copy_encode(File::open(moved)?, File::create(first)?, 0)?;
+            fs::remove_file(moved)?;
+        }
+    }
+    log::debug!("Rotated {:?} to {:?}", moved, first);
+    Ok(())
+}
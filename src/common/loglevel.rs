@@ -0,0 +1,160 @@
+//! Raising the log level of one camera while neolink runs
+//!
+//! Debugging a flaky camera needs its debug or trace logs but restarting with
+//! `RUST_LOG=trace` drops the connection that was misbehaving and floods the
+//! log with every other camera. A camera can instead be boosted to a level
+//! for some minutes, after which it reverts to the `RUST_LOG` level.
+//!
+//! A line belongs to a camera when it starts with `{CameraName}: ` as the
+//! camera logs of neolink do. The protocol logs of `neolink_core` do not name
+//! the camera so they stay at the `RUST_LOG` level
+use anyhow::anyhow;
+use env_logger::{Builder, Env, Logger};
+use log::{LevelFilter, Log, Metadata, Record};
+use once_cell::sync::OnceCell;
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        RwLock,
+    },
+};
+use tokio::time::{sleep, Duration};
+
+use crate::AnyResult;
+
+static LOGGER: OnceCell<CameraLogger> = OnceCell::new();
+
+/// Each boost is numbered so that an older timer does not revert a newer boost
+static BOOST_ID: AtomicU64 = AtomicU64::new(0);
+
+struct CameraLogger {
+    /// The logger of `RUST_LOG`
+    base: Logger,
+    /// Logs everything, used for the lines of boosted cameras
+    verbose: Logger,
+    /// The boosted cameras with their level and boost id
+    boosts: RwLock<HashMap<String, (LevelFilter, u64)>>,
+}
+
+impl CameraLogger {
+    /// The highest level of the base logger and the boosts
+    fn max_level(&self) -> LevelFilter {
+        self.boosts
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .values()
+            .map(|(level, _)| *level)
+            .fold(self.base.filter(), LevelFilter::max)
+    }
+
+    fn boosted(&self, record: &Record) -> bool {
+        let boosts = self.boosts.read().unwrap_or_else(|e| e.into_inner());
+        if boosts.is_empty() {
+            return false;
+        }
+        let line = record.args().to_string();
+        let Some((camera, _)) = line.split_once(": ") else {
+            return false;
+        };
+        boosts
+            .get(camera)
+            .is_some_and(|(level, _)| record.level() <= *level)
+    }
+}
+
+impl Log for CameraLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        self.base.enabled(metadata) || metadata.level() <= log::max_level()
+    }
+
+    fn log(&self, record: &Record) {
+        if self.base.matches(record) {
+            self.base.log(record);
+        } else if self.boosted(record) {
+            self.verbose.log(record);
+        }
+    }
+
+    fn flush(&self) {
+        self.base.flush();
+    }
+}
+
+/// Start logging with `RUST_LOG`, defaulting to info
+pub(crate) fn init() {
+    let logger = CameraLogger {
+        base: Builder::from_env(Env::default().default_filter_or("info")).build(),
+        verbose: Builder::new().filter_level(LevelFilter::Trace).build(),
+        boosts: Default::default(),
+    };
+    let max_level = logger.base.filter();
+    let logger = LOGGER.get_or_init(|| logger);
+    if log::set_logger(logger).is_ok() {
+        log::set_max_level(max_level);
+    }
+}
+
+/// Log a camera at `level` for `minutes`, then revert to the `RUST_LOG` level
+///
+/// A level that is not above the `RUST_LOG` level reverts it now
+pub(crate) fn boost(camera: &str, level: LevelFilter, minutes: u64) -> AnyResult<()> {
+    let logger = LOGGER
+        .get()
+        .ok_or_else(|| anyhow!("The log level can only be changed in a running neolink"))?;
+    let id = BOOST_ID.fetch_add(1, Ordering::Relaxed);
+    {
+        let mut boosts = logger.boosts.write().unwrap_or_else(|e| e.into_inner());
+        if level <= logger.base.filter() {
+            boosts.remove(camera);
+        } else {
+            boosts.insert(camera.to_string(), (level, id));
+        }
+    }
+    log::set_max_level(logger.max_level());
+    if level <= logger.base.filter() {
+        log::info!("{}: Log level reverted", camera);
+        return Ok(());
+    }
+
+    log::info!(
+        "{}: Log level raised to {} for {} minutes",
+        camera,
+        level,
+        minutes
+    );
+    let camera = camera.to_string();
+    tokio::task::spawn(async move {
+        sleep(Duration::from_secs(minutes * 60)).await;
+        let reverted = {
+            let mut boosts = logger.boosts.write().unwrap_or_else(|e| e.into_inner());
+            if boosts.get(&camera).is_some_and(|(_, boost)| *boost == id) {
+                boosts.remove(&camera);
+                true
+            } else {
+                false
+            }
+        };
+        if reverted {
+            log::set_max_level(logger.max_level());
+            log::info!("{}: Log level reverted", camera);
+        }
+    });
+    Ok(())
+}
+
+/// The boosted cameras and their levels
+pub(crate) fn boosts() -> HashMap<String, LevelFilter> {
+    LOGGER
+        .get()
+        .map(|logger| {
+            logger
+                .boosts
+                .read()
+                .unwrap_or_else(|e| e.into_inner())
+                .iter()
+                .map(|(camera, (level, _))| (camera.clone(), *level))
+                .collect()
+        })
+        .unwrap_or_default()
+}
@@ -1,11 +1,24 @@
 //! This thread will listen to motion messages
 //! from the camera.
+//!
+//! When software motion detection is configured it is
+//! also run from here and sends on the same watch
+//!
+//! Smart detections such as line crossings are sent on
+//! their own broadcast as they are events rather than a state
+//!
+//! Every trigger is also merged with those that arrive close
+//! to it into a single event, see [`super::events`]
 
 use anyhow::Context;
 use std::sync::Arc;
+use time::OffsetDateTime;
 use tokio::{
     sync::{
-        mpsc::Receiver as MpscReceiver,
+        broadcast::{
+            channel as broadcast, Receiver as BroadcastReceiver, Sender as BroadcastSender,
+        },
+        mpsc::{channel as mpsc, Receiver as MpscReceiver},
         oneshot::Sender as OneshotSender,
         watch::{channel as watch, Receiver as WatchReceiver, Sender as WatchSender},
     },
@@ -13,9 +26,12 @@ use tokio::{
 };
 use tokio_util::sync::CancellationToken;
 
-use super::NeoInstance;
+use super::{
+    events::{self, DetectionEvent, Trigger},
+    swmotion, NeoInstance,
+};
 use crate::{AnyResult, Result};
-use neolink_core::bc_protocol::MotionStatus;
+use neolink_core::bc_protocol::{MotionStatus, SmartEvent};
 
 pub(crate) enum MdState {
     #[allow(dead_code)]
@@ -26,6 +42,9 @@ pub(crate) enum MdState {
 
 pub(crate) struct NeoCamMdThread {
     md_watcher: Arc<WatchSender<MdState>>,
+    last_motion: Arc<WatchSender<Option<OffsetDateTime>>>,
+    smart_tx: BroadcastSender<SmartEvent>,
+    events_tx: BroadcastSender<DetectionEvent>,
     md_request_rx: MpscReceiver<MdRequest>,
     cancel: CancellationToken,
     instance: NeoInstance,
@@ -35,11 +54,17 @@ impl NeoCamMdThread {
     pub(crate) async fn new(
         md_request_rx: MpscReceiver<MdRequest>,
         instance: NeoInstance,
+        last_motion: WatchSender<Option<OffsetDateTime>>,
     ) -> Result<Self> {
         let (md_watcher, _) = watch(MdState::Unknown);
         let md_watcher = Arc::new(md_watcher);
+        let (smart_tx, _) = broadcast(20);
+        let (events_tx, _) = broadcast(20);
         Ok(Self {
             md_watcher,
+            last_motion: Arc::new(last_motion),
+            smart_tx,
+            events_tx,
             md_request_rx,
             cancel: CancellationToken::new(),
             instance,
@@ -49,7 +74,17 @@ impl NeoCamMdThread {
     pub(crate) async fn run(&mut self) -> Result<()> {
         let thread_cancel = self.cancel.clone();
         let watcher = self.md_watcher.clone();
+        let smart_tx = self.smart_tx.clone();
         let md_instance = self.instance.clone();
+        let subscriptions = self.instance.subscriptions().await?;
+        let sw_instance = self.instance.clone();
+        let sw_watcher = self.md_watcher.clone();
+        let events_tx = self.events_tx.clone();
+        let events_config = self.instance.config().await?;
+        let (trigger_tx, trigger_rx) = mpsc(20);
+        let sw_trigger_tx = trigger_tx.clone();
+        let mut last_md = self.md_watcher.subscribe();
+        let last_motion = self.last_motion.clone();
         tokio::select! {
             _ = thread_cancel.cancelled() => {
                 Ok(())
@@ -62,19 +97,47 @@ impl NeoCamMdThread {
                         } => {
                           let _ = sender.send(self.md_watcher.subscribe());
                         },
+                        MdRequest::Smart {
+                            sender
+                        } => {
+                          let _ = sender.send(self.smart_tx.subscribe());
+                        },
+                        MdRequest::Events {
+                            sender
+                        } => {
+                          let _ = sender.send(self.events_tx.subscribe());
+                        },
                     }
                 }
                 Ok(())
             } => v,
             v = async {
                 loop {
+                    let _subscription = subscriptions.acquire("motion").await?;
                     let r: AnyResult<()> = md_instance.run_passive_task(|cam| {
                         let watcher = watcher.clone();
+                        let smart_tx = smart_tx.clone();
+                        let trigger_tx = trigger_tx.clone();
                         Box::pin(
                         async move {
                             let mut md = cam.listen_on_motion().await.with_context(|| "Error in getting MD listen_on_motion")?;
+                            let mut smart_rx = md.take_smart_events();
+                            let mut alarm_rx = md.take_triggers();
                             loop {
-                                let event = md.next_motion().await.with_context(|| "Error in getting MD next_motion")?;
+                                let event = tokio::select! {
+                                    event = md.next_motion() => event.with_context(|| "Error in getting MD next_motion")?,
+                                    Some(smart) = async { smart_rx.as_mut()?.recv().await } => {
+                                        log::debug!("Smart detection {} in zone {:?}", smart.kind, smart.zone);
+                                        let _ = trigger_tx.try_send(Trigger::Smart(smart.clone()));
+                                        // No one may be listening
+                                        let _ = smart_tx.send(smart);
+                                        continue;
+                                    }
+                                    Some(alarm) = async { alarm_rx.as_mut()?.recv().await } => {
+                                        let _ = trigger_tx.try_send(Trigger::Alarm(alarm));
+                                        continue;
+                                    }
+                                };
                                 match event {
                                     MotionStatus::Start(at) => {
                                         watcher.send_replace(
@@ -94,7 +157,38 @@ impl NeoCamMdThread {
                     log::debug!("Error in MD task Restarting: {:?}", r);
                     sleep(Duration::from_secs(1)).await;
                 }
-            } => v
+            } => v,
+            v = async {
+                let mut config = sw_instance.config().await?;
+                loop {
+                    let sw_config = config.wait_for(|config| config.software_motion.is_some()).await?.software_motion.clone();
+                    let Some(sw_config) = sw_config else {
+                        continue;
+                    };
+                    tokio::select! {
+                        r = swmotion::run(&sw_instance, &sw_config, &sw_watcher, &sw_trigger_tx) => {
+                            log::debug!("Error in software MD Restarting: {:?}", r);
+                            sleep(Duration::from_secs(1)).await;
+                        },
+                        v = config.wait_for(|config| config.software_motion.as_ref() != Some(&sw_config)) => {
+                            v?;
+                            log::debug!("Software MD configuration changed");
+                        },
+                    }
+                }
+            } => v,
+            _ = events::correlate(trigger_rx, events_config, &events_tx) => Ok(()),
+            v = async {
+                // The watch only has the current state so the time that
+                // motion last started is kept for `neolink status`
+                while last_md.wait_for(|md| matches!(md, MdState::Start(_))).await.is_ok() {
+                    last_motion.send_replace(Some(OffsetDateTime::now_utc()));
+                    if last_md.wait_for(|md| !matches!(md, MdState::Start(_))).await.is_err() {
+                        break;
+                    }
+                }
+                Ok(())
+            } => v,
         }
     }
 }
@@ -112,4 +206,10 @@ pub(crate) enum MdRequest {
     Get {
         sender: OneshotSender<WatchReceiver<MdState>>,
     },
+    Smart {
+        sender: OneshotSender<BroadcastReceiver<SmartEvent>>,
+    },
+    Events {
+        sender: OneshotSender<BroadcastReceiver<DetectionEvent>>,
+    },
 }
@@ -1,17 +1,64 @@
+pub(crate) mod audit;
+mod camlock;
 mod camthread;
+mod clock;
+mod connectgate;
+mod errorhistory;
+mod exif;
+pub(crate) mod httpd;
+pub(crate) mod events;
+mod eventschema;
 mod instance;
+pub(crate) mod logfile;
+pub(crate) mod loglevel;
 mod mdthread;
 mod neocam;
+pub(crate) mod objects;
 mod pushnoti;
 mod reactor;
 mod streamthread;
+mod subscriptions;
+#[cfg(feature = "gstreamer")]
+pub(crate) mod swmotion;
+#[cfg(not(feature = "gstreamer"))]
+pub(crate) mod swmotion {
+    //! Software motion detection needs gstreamer to decode the frames
+    use tokio::sync::{mpsc::Sender as MpscSender, watch::Sender as WatchSender};
+
+    use super::{events::Trigger, MdState, NeoInstance};
+    use crate::{config::SoftwareMotionConfig, AnyResult};
+
+    /// Warn that there is no software motion detection and wait forever
+    pub(crate) async fn run(
+        instance: &NeoInstance,
+        _config: &SoftwareMotionConfig,
+        _watcher: &WatchSender<MdState>,
+        _triggers: &MpscSender<Trigger>,
+    ) -> AnyResult<()> {
+        log::warn!(
+            "{}: Software motion detection needs neolink to be built with gstreamer",
+            instance.config().await?.borrow().name
+        );
+        futures::future::pending().await
+    }
+}
+#[cfg(feature = "gstreamer")]
+mod thumbnail;
 mod usecounter;
 
+pub(crate) use camlock::*;
 pub(crate) use camthread::*;
+pub(crate) use clock::*;
+pub(crate) use connectgate::*;
+pub(crate) use errorhistory::*;
+pub(crate) use eventschema::*;
 pub(crate) use instance::*;
 pub(crate) use mdthread::*;
 pub(crate) use neocam::*;
 pub(crate) use pushnoti::*;
 pub(crate) use reactor::*;
 pub(crate) use streamthread::*;
+pub(crate) use subscriptions::*;
+#[cfg(feature = "gstreamer")]
+pub(crate) use thumbnail::*;
 pub(crate) use usecounter::*;
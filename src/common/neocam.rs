@@ -6,25 +6,38 @@
 //!    Clonable interface to share amongst threadsanyhow::anyhow;
 use anyhow::Context;
 use futures::{stream::StreamExt, TryFutureExt};
-use std::sync::Weak;
+use std::{
+    collections::HashSet,
+    sync::{Arc, Weak},
+};
+use time::OffsetDateTime;
 use tokio::{
     sync::{
+        broadcast::Receiver as BroadcastReceiver,
         mpsc::{channel as mpsc, Sender as MpscSender},
         oneshot::{channel as oneshot, Sender as OneshotSender},
         watch::{channel as watch, Receiver as WatchReceiver, Sender as WatchSender},
+        Mutex,
     },
     task::JoinSet,
-    time::{sleep, Duration},
+    time::{sleep, Duration, Instant},
 };
 use tokio_stream::wrappers::ReceiverStream;
 use tokio_util::sync::CancellationToken;
 
 use super::{
-    MdRequest, MdState, NeoCamMdThread, NeoCamStreamThread, NeoCamThread, NeoCamThreadState,
-    NeoInstance, Permit, PnRequest, PushNoti, StreamInstance, StreamRequest, UseCounter,
+    events::DetectionEvent, ClockDrift, ConnectGate, ErrorHistory, MdRequest, MdState,
+    NeoCamMdThread, NeoCamStreamThread, NeoCamThread, NeoCamThreadState, NeoInstance, Permit,
+    PnRequest, PushNoti, StreamInstance, StreamReport, StreamRequest, Subscriptions, UseCounter,
+};
+use crate::{
+    config::{CameraConfig, ZoomRect},
+    AnyResult, Result,
+};
+use neolink_core::{
+    bc::xml::Record,
+    bc_protocol::{BcCamera, SmartEvent, StreamKind},
 };
-use crate::{config::CameraConfig, AnyResult, Result};
-use neolink_core::bc_protocol::{BcCamera, StreamKind};
 
 #[allow(dead_code)]
 pub(crate) enum NeoCamCommand {
@@ -35,6 +48,8 @@ pub(crate) enum NeoCamCommand {
     LowStream(OneshotSender<Option<StreamInstance>>),
     Streams(OneshotSender<Vec<StreamInstance>>),
     Motion(OneshotSender<WatchReceiver<MdState>>),
+    Smart(OneshotSender<BroadcastReceiver<SmartEvent>>),
+    Events(OneshotSender<BroadcastReceiver<DetectionEvent>>),
     Config(OneshotSender<WatchReceiver<CameraConfig>>),
     Disconnect(OneshotSender<()>),
     Connect(OneshotSender<()>),
@@ -42,7 +57,35 @@ pub(crate) enum NeoCamCommand {
     GetPermit(OneshotSender<Permit>),
     PushNoti(OneshotSender<WatchReceiver<Option<PushNoti>>>),
     GetUid(OneshotSender<String>),
+    DigitalZoom(OneshotSender<WatchReceiver<ZoomRect>>),
+    SetDigitalZoom(ZoomRect, OneshotSender<()>),
+    Mute(OneshotSender<WatchReceiver<HashSet<StreamKind>>>),
+    SetMute(StreamKind, bool, OneshotSender<()>),
+    Busy(OneshotSender<WatchReceiver<bool>>),
+    Snapshot(OneshotSender<SnapshotCache>),
+    CommandQueue(OneshotSender<CommandQueue>),
+    SavedRecord(OneshotSender<SavedRecord>),
+    Errors(OneshotSender<WatchReceiver<ErrorHistory>>),
+    Model(OneshotSender<WatchReceiver<Option<String>>>),
+    ClockDrift(OneshotSender<WatchReceiver<Option<ClockDrift>>>),
+    Logins(OneshotSender<WatchReceiver<u64>>),
+    LastMotion(OneshotSender<WatchReceiver<Option<OffsetDateTime>>>),
+    StreamReports(OneshotSender<Vec<StreamReport>>),
+    Subscriptions(OneshotSender<Subscriptions>),
 }
+
+/// The last snapshot taken and when, shared by all instances so that
+/// several consumers asking at once do not each wake the camera
+pub(crate) type SnapshotCache = Arc<Mutex<Option<(Instant, Vec<u8>)>>>;
+
+/// Held while a control command runs so that the commands of all instances
+/// run one at a time, in the order that they were sent
+pub(crate) type CommandQueue = Arc<Mutex<()>>;
+
+/// The recording schedule replaced by a manual record or the alarm, kept
+/// here so that it is not lost when MQTT or the camera reconnects
+pub(crate) type SavedRecord = Arc<Mutex<Option<Record>>>;
+
 /// The underlying camera binding
 pub(crate) struct NeoCam {
     cancel: CancellationToken,
@@ -56,6 +99,7 @@ impl NeoCam {
     pub(crate) async fn new(
         config: CameraConfig,
         pn_request_tx: MpscSender<PnRequest>,
+        gate: ConnectGate,
     ) -> Result<NeoCam> {
         let (commander_tx, commander_rx) = mpsc(100);
         let (watch_config_tx, watch_config_rx) = watch(config.clone());
@@ -64,6 +108,30 @@ impl NeoCam {
         let (md_request_tx, md_request_rx) = mpsc(100);
         let (state_tx, state_rx) = watch(NeoCamThreadState::Connected);
         let (uid_tx, uid_rx) = watch(config.camera_uid.clone());
+        let (busy_tx, busy_rx) = watch(false);
+        let (errors_tx, errors_rx) = watch(ErrorHistory::default());
+        let (model_tx, model_rx) = watch(None::<String>);
+        let (clock_drift_tx, clock_drift_rx) = watch(None::<ClockDrift>);
+        let (logins_tx, logins_rx) = watch(0u64);
+        let (last_motion_tx, last_motion_rx) = watch(None::<OffsetDateTime>);
+        let snapshot_cache: SnapshotCache = Default::default();
+        let command_queue: CommandQueue = Default::default();
+        let saved_record: SavedRecord = Default::default();
+        let subscriptions = Subscriptions::new(watch_config_rx.clone());
+        let (zoom_tx, zoom_rx) = watch(
+            config
+                .digital_zoom
+                .as_ref()
+                .map(|zoom| zoom.rect())
+                .unwrap_or_default(),
+        );
+        let (mute_tx, mute_rx) = watch(
+            config
+                .mute
+                .as_stream_kinds()
+                .drain(..)
+                .collect::<HashSet<_>>(),
+        );
 
         let set = JoinSet::new();
         let users = UseCounter::new().await;
@@ -145,6 +213,20 @@ impl NeoCam {
                                     }
                                 ).await?;
                             },
+                            NeoCamCommand::Smart(sender) => {
+                                md_request_tx.send(
+                                    MdRequest::Smart {
+                                        sender,
+                                    }
+                                ).await?;
+                            },
+                            NeoCamCommand::Events(sender) => {
+                                md_request_tx.send(
+                                    MdRequest::Events {
+                                        sender,
+                                    }
+                                ).await?;
+                            },
                             NeoCamCommand::Config(sender) => {
                                 let _ = sender.send(thread_watch_config_rx.clone());
                             },
@@ -181,6 +263,63 @@ impl NeoCam {
                                     AnyResult::Ok(())
                                 });
                             },
+                            NeoCamCommand::DigitalZoom(sender) => {
+                                let _ = sender.send(zoom_rx.clone());
+                            },
+                            NeoCamCommand::SetDigitalZoom(rect, sender) => {
+                                zoom_tx.send_replace(rect);
+                                let _ = sender.send(());
+                            },
+                            NeoCamCommand::Mute(sender) => {
+                                let _ = sender.send(mute_rx.clone());
+                            },
+                            NeoCamCommand::SetMute(stream, muted, sender) => {
+                                mute_tx.send_if_modified(|streams| {
+                                    if muted {
+                                        streams.insert(stream)
+                                    } else {
+                                        streams.remove(&stream)
+                                    }
+                                });
+                                let _ = sender.send(());
+                            },
+                            NeoCamCommand::Busy(sender) => {
+                                let _ = sender.send(busy_rx.clone());
+                            },
+                            NeoCamCommand::Snapshot(sender) => {
+                                let _ = sender.send(snapshot_cache.clone());
+                            },
+                            NeoCamCommand::CommandQueue(sender) => {
+                                let _ = sender.send(command_queue.clone());
+                            },
+                            NeoCamCommand::SavedRecord(sender) => {
+                                let _ = sender.send(saved_record.clone());
+                            },
+                            NeoCamCommand::Errors(sender) => {
+                                let _ = sender.send(errors_rx.clone());
+                            },
+                            NeoCamCommand::Model(sender) => {
+                                let _ = sender.send(model_rx.clone());
+                            },
+                            NeoCamCommand::ClockDrift(sender) => {
+                                let _ = sender.send(clock_drift_rx.clone());
+                            },
+                            NeoCamCommand::Logins(sender) => {
+                                let _ = sender.send(logins_rx.clone());
+                            },
+                            NeoCamCommand::LastMotion(sender) => {
+                                let _ = sender.send(last_motion_rx.clone());
+                            },
+                            NeoCamCommand::StreamReports(sender) => {
+                                stream_request_tx.send(
+                                    StreamRequest::Report {
+                                        sender,
+                                    }
+                                ).await?;
+                            },
+                            NeoCamCommand::Subscriptions(sender) => {
+                                let _ = sender.send(subscriptions.clone());
+                            },
                         }
                     }
                     Ok(())
@@ -206,6 +345,11 @@ impl NeoCam {
             state_rx,
             thread_watch_config_rx,
             camera_watch_tx,
+            busy_tx,
+            errors_tx,
+            clock_drift_tx,
+            logins_tx,
+            gate,
             me.cancel.clone(),
         )
         .await;
@@ -227,7 +371,7 @@ impl NeoCam {
         // This thread monitors the motion
         let md_instance = instance.subscribe().await?;
         let md_cancel = me.cancel.clone();
-        let mut md_thread = NeoCamMdThread::new(md_request_rx, md_instance).await?;
+        let mut md_thread = NeoCamMdThread::new(md_request_rx, md_instance, last_motion_tx).await?;
         me.set.spawn(async move {
             tokio::select! {
                 _ = md_cancel.cancelled() => AnyResult::Ok(()),
@@ -252,7 +396,8 @@ impl NeoCam {
                             Ok(cam.version().await?)
                         }
                     )).await?;
-                    log::info!("{}: Model {}", report_name, version.model.unwrap_or("Undeclared".to_string()));
+                    log::info!("{}: Model {}", report_name, version.model.as_deref().unwrap_or("Undeclared"));
+                    let _ = model_tx.send(version.model.clone());
                     log::info!("{}: Firmware Version {}", report_name, version.firmwareVersion);
 
                     let stream_info = report_instance.run_task(|cam| Box::pin(
@@ -386,6 +531,7 @@ impl NeoCam {
                     loop {
                         // Wait for the green light
                         config_rx.wait_for(|config| config.idle_disconnect).await?;
+                        let idle_config = config_rx.clone();
 
                         let r = tokio::select!{
                             // Wait for red light
@@ -402,9 +548,10 @@ impl NeoCam {
                                     permit.aquired_users().await?;
                                     connect_instance.connect().await?;
                                     permit.dropped_users().await?;
-                                    // Wait 30s or if we hit another use then go back and wait again
+                                    // Wait for the idle timeout or if we hit another use then go back and wait again
+                                    let idle_timeout = Duration::from_secs(idle_config.borrow().idle_policy.timeout);
                                     tokio::select! {
-                                        _ = sleep(Duration::from_secs(30)) => {},
+                                        _ = sleep(idle_timeout) => {},
                                         _ = permit.aquired_users() => continue,
                                     };
                                     connect_instance.disconnect().await?;
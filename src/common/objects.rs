@@ -0,0 +1,94 @@
+//! Object detection by an external inference server
+//!
+//! A snapshot is posted to a DeepStack compatible endpoint such as
+//! DeepStack or CodeProject.AI as the `image` field of a form and the
+//! predictions in the reply are returned
+
+use anyhow::anyhow;
+use reqwest::header::CONTENT_TYPE;
+use serde::{Deserialize, Serialize};
+use tokio::time::Duration;
+
+use crate::{config::ObjectDetectionConfig, AnyResult};
+
+/// An object found in the image, the box is in pixels of the snapshot
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+pub(crate) struct DetectedObject {
+    pub(crate) label: String,
+    pub(crate) confidence: f64,
+    pub(crate) x_min: i32,
+    pub(crate) y_min: i32,
+    pub(crate) x_max: i32,
+    pub(crate) y_max: i32,
+}
+
+#[derive(Debug, Deserialize)]
+struct DetectionReply {
+    #[serde(default)]
+    success: bool,
+    #[serde(default)]
+    predictions: Vec<DetectedObject>,
+    #[serde(default)]
+    error: Option<String>,
+}
+
+/// Send the jpeg to the inference server and return the objects it found
+pub(crate) async fn detect(
+    config: &ObjectDetectionConfig,
+    jpeg: &[u8],
+) -> AnyResult<Vec<DetectedObject>> {
+    let boundary = format!("neolink{}", uuid::Uuid::new_v4().simple());
+    let mut body = vec![];
+    let mut field = |name: &str, extra: &str, value: &[u8]| {
+        body.extend_from_slice(
+            format!(
+                "--{}\r\nContent-Disposition: form-data; name=\"{}\"{}\r\n\r\n",
+                boundary, name, extra
+            )
+            .as_bytes(),
+        );
+        body.extend_from_slice(value);
+        body.extend_from_slice(b"\r\n");
+    };
+    field(
+        "image",
+        "; filename=\"snapshot.jpeg\"\r\nContent-Type: image/jpeg",
+        jpeg,
+    );
+    field(
+        "min_confidence",
+        "",
+        config.min_confidence.to_string().as_bytes(),
+    );
+    if let Some(api_key) = config.api_key.as_ref() {
+        field("api_key", "", api_key.as_bytes());
+    }
+    body.extend_from_slice(format!("--{}--\r\n", boundary).as_bytes());
+
+    let reply: DetectionReply = reqwest::Client::new()
+        .post(&config.url)
+        .header(
+            CONTENT_TYPE,
+            format!("multipart/form-data; boundary={}", boundary),
+        )
+        .timeout(Duration::from_secs(config.timeout))
+        .body(body)
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+    if !reply.success {
+        return Err(anyhow!(
+            "The inference server failed: {}",
+            reply.error.as_deref().unwrap_or("no reason given")
+        ));
+    }
+
+    Ok(reply
+        .predictions
+        .into_iter()
+        .filter(|object| object.confidence >= config.min_confidence)
+        .filter(|object| config.labels.is_empty() || config.labels.contains(&object.label))
+        .collect())
+}
@@ -16,7 +16,7 @@ use tokio::{
 };
 use tokio_util::sync::CancellationToken;
 
-use super::{NeoCam, NeoInstance};
+use super::{ConnectGate, NeoCam, NeoInstance};
 use crate::{common::PushNotiThread, config::Config, AnyResult, Result};
 
 #[allow(clippy::large_enum_variant)]
@@ -25,6 +25,7 @@ enum NeoReactorCommand {
     Config(OneshotSender<WatchReceiver<Config>>),
     UpdateConfig(Config, OneshotSender<Result<()>>),
     Get(String, OneshotSender<Result<Option<NeoInstance>>>),
+    Cameras(OneshotSender<Result<Vec<NeoInstance>>>),
 }
 
 /// Reactor handles the collection of cameras
@@ -48,6 +49,7 @@ impl NeoReactor {
         let cancel1 = cancel.clone();
         let cancel2 = cancel.clone();
         let thread_config_tx = config_tx.clone();
+        let gate = ConnectGate::new(config_tx.subscribe());
         set.spawn(async move {
             let mut instances: HashMap<String, NeoCam> = Default::default();
 
@@ -73,7 +75,7 @@ impl NeoReactor {
                                     Entry::Vacant(vac) => {
                                         let current_config: Config = (*thread_config_tx.borrow()).clone();
                                         if let Some(config) = current_config.cameras.iter().find(|cam| cam.name == name).cloned() {
-                                            let cam = NeoCam::new(config, push_noti.clone()).await?;
+                                            let cam = NeoCam::new(config, push_noti.clone(), gate.clone()).await?;
                                             Result::Ok(Some(
                                                 vac.insert(
                                                     cam,
@@ -88,6 +90,13 @@ impl NeoReactor {
                                 };
                                 let _ = sender.send(new);
                             },
+                            NeoReactorCommand::Cameras(sender) => {
+                                let mut cameras = vec![];
+                                for instance in instances.values() {
+                                    cameras.push(instance.subscribe().await?);
+                                }
+                                let _ = sender.send(Ok(cameras));
+                            },
                             NeoReactorCommand::UpdateConfig(new_conf, reply) => {
                                 // Shutdown or Notify instances of a change
                                 let mut names = new_conf.cameras.iter().filter(|cam_conf| cam_conf.enabled).map(|cam_conf| (cam_conf.name.clone(), cam_conf.clone())).collect::<HashMap<_,_>>();
@@ -158,6 +167,16 @@ impl NeoReactor {
             .ok_or(anyhow!("Camera `{name}` not found in config"))
     }
 
+    /// Get the cameras that have been created, without creating any others
+    pub(crate) async fn cameras(&self) -> Result<Vec<NeoInstance>> {
+        let (sender_tx, sender_rx) = oneshot();
+        self.commander
+            .send(NeoReactorCommand::Cameras(sender_tx))
+            .await?;
+
+        sender_rx.await?
+    }
+
     pub(crate) async fn config(&self) -> Result<WatchReceiver<Config>> {
         let (sender_tx, sender_rx) = oneshot();
         self.commander
@@ -4,9 +4,14 @@
 //! then it will hangup
 
 use futures::stream::{FuturesUnordered, StreamExt};
+use serde::Serialize;
 use std::{
     collections::{hash_map::Entry, HashMap, VecDeque},
-    sync::Arc,
+    fmt::{Display, Error as FmtError, Formatter},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
 };
 use tokio::{
     sync::{
@@ -18,13 +23,16 @@ use tokio::{
         watch::{channel as watch, Receiver as WatchReceiver, Sender as WatchSender},
     },
     task::JoinHandle,
-    time::{sleep, timeout, Duration},
+    time::{sleep, timeout, Duration, Instant},
 };
 use tokio_util::sync::CancellationToken;
 
 use super::{NeoInstance, Permit, UseCounter};
 use crate::{AnyResult, Result};
-use neolink_core::{bc_protocol::StreamKind, bcmedia::model::*};
+use neolink_core::{
+    bc_protocol::{StreamHooks, StreamKind},
+    bcmedia::model::*,
+};
 
 pub(crate) struct NeoCamStreamThread {
     streams: HashMap<StreamKind, StreamData>,
@@ -174,6 +182,11 @@ impl NeoCamStreamThread {
                                 }
                             ).collect::<FuturesUnordered<_>>().collect::<Vec<_>>().await;
                             let _ = sender.send(streams.drain(..).flatten().collect());
+                        },
+                        StreamRequest::Report {
+                            sender
+                        } => {
+                            let _ = sender.send(self.streams.values().map(StreamData::report).collect());
                         }
                     }
                 }
@@ -220,6 +233,10 @@ pub(crate) enum StreamRequest {
     All {
         sender: OneshotSender<Vec<StreamInstance>>,
     },
+    /// Report on the loaded streams without using them
+    Report {
+        sender: OneshotSender<Vec<StreamReport>>,
+    },
 }
 
 /// The data of a running stream
@@ -229,12 +246,25 @@ pub(crate) struct StreamData {
     vid_history: Arc<WatchSender<VecDeque<StampedData>>>,
     aud_history: Arc<WatchSender<VecDeque<StampedData>>>,
     config: Arc<WatchSender<StreamConfig>>,
+    health: Arc<WatchSender<StreamHealth>>,
     name: StreamKind,
     instance: NeoInstance,
     cancel: CancellationToken,
     handle: Option<JoinHandle<Result<()>>>,
     strict: bool,
     users: UseCounter,
+    stats: Arc<StreamStats>,
+}
+
+/// Counters of a stream since neolink started, for the metrics
+#[derive(Debug, Default)]
+pub(crate) struct StreamStats {
+    /// Video frames that were passed on to the users
+    pub(crate) frames: AtomicU64,
+    /// Video frames that were dropped because they came before a keyframe
+    pub(crate) dropped_frames: AtomicU64,
+    /// The bitrate measured over the last health window in bits per second
+    pub(crate) measured_bitrate: AtomicU64,
 }
 
 #[derive(Eq, PartialEq, Clone, Debug, Copy)]
@@ -259,6 +289,7 @@ pub(crate) struct StreamConfig {
     pub(crate) fps: u32,
 }
 
+#[cfg(feature = "gstreamer")]
 impl StreamConfig {
     pub(crate) fn vid_ready(&self) -> bool {
         self.resolution[0] > 0
@@ -272,21 +303,67 @@ impl StreamConfig {
     }
 }
 
+/// The health of a stream as seen by neolink
+#[derive(Eq, PartialEq, Clone, Debug, Copy)]
+pub(crate) enum StreamHealth {
+    /// Not streaming because nothing is using it
+    Stopped,
+    /// Receiving frames
+    Started,
+    /// No frames were received in time, it is being restarted
+    Stalled,
+    /// Receiving frames but well below the expected bitrate
+    BitrateDropped,
+}
+
+impl Display for StreamHealth {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), FmtError> {
+        match self {
+            StreamHealth::Stopped => write!(f, "stopped"),
+            StreamHealth::Started => write!(f, "started"),
+            StreamHealth::Stalled => write!(f, "stalled"),
+            StreamHealth::BitrateDropped => write!(f, "bitrate_dropped"),
+        }
+    }
+}
+
+/// The state of a loaded stream as it appears in `neolink ctl dump-state`
+#[derive(Debug, Clone, Serialize)]
+pub(crate) struct StreamReport {
+    pub(crate) stream: String,
+    /// The users that are active on the stream
+    pub(crate) users: u32,
+    pub(crate) health: String,
+    pub(crate) video: String,
+    pub(crate) audio: String,
+    pub(crate) resolution: [u32; 2],
+    pub(crate) bitrate: u32,
+    pub(crate) fps: u32,
+    /// The bitrate measured over the last health window in bits per second
+    pub(crate) measured_bitrate: u64,
+    pub(crate) frames: u64,
+    pub(crate) dropped_frames: u64,
+}
+
+// The frames are only read by the commands that need gstreamer
 #[derive(Debug, Clone)]
+#[cfg_attr(not(feature = "gstreamer"), allow(dead_code))]
 pub(crate) struct StampedData {
     pub(crate) keyframe: bool,
     pub(crate) data: Arc<Vec<u8>>,
     pub(crate) ts: Duration,
 }
 
+// The frames are only read by the commands that need gstreamer
+#[cfg_attr(not(feature = "gstreamer"), allow(dead_code))]
 pub(crate) struct StreamInstance {
-    #[allow(dead_code)]
     pub(crate) name: StreamKind,
     pub(crate) vid: BroadcastReceiver<StampedData>,
     pub(crate) vid_history: WatchReceiver<VecDeque<StampedData>>,
     pub(crate) aud: BroadcastReceiver<StampedData>,
     pub(crate) aud_history: WatchReceiver<VecDeque<StampedData>>,
     pub(crate) config: WatchReceiver<StreamConfig>,
+    pub(crate) health: WatchReceiver<StreamHealth>,
     in_use: Permit,
 }
 
@@ -299,9 +376,11 @@ impl StreamInstance {
             aud: data.aud.subscribe(),
             aud_history: data.aud_history.subscribe(),
             config: data.config.subscribe(),
+            health: data.health.subscribe(),
             in_use: data.users.create_activated().await?,
         })
     }
+    #[cfg(feature = "gstreamer")]
     pub(crate) async fn activate(&mut self) -> Result<()> {
         self.in_use.activate().await
     }
@@ -309,15 +388,40 @@ impl StreamInstance {
         self.in_use.deactivate().await
     }
 
+    #[cfg(feature = "gstreamer")]
     pub(crate) async fn activator_handle(&mut self) -> Permit {
         self.in_use.subscribe()
     }
 }
 
 impl StreamData {
+    fn report(&self) -> StreamReport {
+        let config = self.config.borrow();
+        StreamReport {
+            stream: self.name.to_string(),
+            users: self.users.count(),
+            health: self.health.borrow().to_string(),
+            video: format!("{:?}", config.vid_format),
+            audio: format!("{:?}", config.aud_format),
+            resolution: config.resolution,
+            bitrate: config.bitrate,
+            fps: config.fps,
+            // The last measurement is stale once the stream is not running
+            measured_bitrate: match *self.health.borrow() {
+                StreamHealth::Started | StreamHealth::BitrateDropped => {
+                    self.stats.measured_bitrate.load(Ordering::Relaxed)
+                }
+                StreamHealth::Stopped | StreamHealth::Stalled => 0,
+            },
+            frames: self.stats.frames.load(Ordering::Relaxed),
+            dropped_frames: self.stats.dropped_frames.load(Ordering::Relaxed),
+        }
+    }
+
     async fn new(name: StreamKind, instance: NeoInstance, strict: bool) -> Result<Self> {
         let buffer_duration =
             Duration::from_millis(instance.config().await?.borrow().buffer_duration);
+        let health_config = instance.config().await?.borrow().stream_health.clone();
         log::trace!("New StreamData::{name:?}");
         // At 30fps for 15s with audio is is about 900 frames
         let buffer_size: usize = 30usize * buffer_duration.as_millis() as usize / 1000usize;
@@ -383,10 +487,12 @@ impl StreamData {
             bitrate,
             fps,
         });
+        let (health_tx, _) = watch(StreamHealth::Stopped);
         let mut me = Self {
             name,
             cancel: CancellationToken::new(),
             config: Arc::new(config_tx),
+            health: Arc::new(health_tx),
             vid,
             vid_history,
             aud,
@@ -395,6 +501,7 @@ impl StreamData {
             handle: None,
             strict,
             users: UseCounter::new().await,
+            stats: Default::default(),
         };
 
         let cancel = me.cancel.clone();
@@ -406,10 +513,13 @@ impl StreamData {
         let print_name = format!("{cam_name}::{name}");
         let strict = me.strict;
         let config = me.config.clone();
+        let health = me.health.clone();
+        let stats = me.stats.clone();
         let thread_inuse = me.users.create_deactivated().await?;
         let vid_history = me.vid_history.clone();
         let aud_history = me.aud_history.clone();
         let mut permit = instance.permit().await?;
+        let subscriptions = instance.subscriptions().await?;
 
         // Rather than extract the time stamp from the frame data we
         // create a new one from the fps
@@ -421,13 +531,29 @@ impl StreamData {
             1000 / (config.borrow().fps as u64),
         )));
 
+        // Hooks of the camera stream that is currently running
+        let stream_hooks = Arc::new(watch(None::<StreamHooks>).0);
+
         me.handle = Some(tokio::task::spawn(async move {
             let r = tokio::select! {
                 _ = cancel.cancelled() => {
+                    health.send_replace(StreamHealth::Stopped);
                     Result::<(), anyhow::Error>::Ok(())
                 },
                 v = async {
                     loop {
+                        // Wait for the camera to be below its max_subscriptions
+                        // unless the users leave while waiting
+                        let subscription = tokio::select! {
+                            v = subscriptions.acquire(&name.to_string()) => v?,
+                            v = thread_inuse.dropped_users() => {
+                                v?;
+                                permit.deactivate().await?;
+                                thread_inuse.aquired_users().await?;
+                                permit.activate().await?;
+                                continue;
+                            },
+                        };
                         let (watchdog_tx, mut watchdog_rx) = mpsc(1);
                         let (watchdog_eat_tx, watchdog_eat_rx) = oneshot();
                         // Give the watchdog his own thread to play in
@@ -459,7 +585,15 @@ impl StreamData {
                             v = thread_inuse.dropped_users() => {
                                 // Handles the stop and restart when no active users
                                 log::trace!("Stopping StreamThread Permit");
+                                health.send_replace(StreamHealth::Stopped);
+                                // Wait for the camera to stop sending before giving up the permit
+                                // so that battery cameras can go back to sleep
+                                let hooks = stream_hooks.borrow().clone();
+                                if let Some(hooks) = hooks {
+                                    hooks.stop().await;
+                                }
                                 permit.deactivate().await?;
+                                drop(subscription);
                                 v?;
                                 log::trace!("Waiting for streamthread aquire users");
                                 thread_inuse.aquired_users().await?; // Wait for new users of the stream
@@ -469,6 +603,8 @@ impl StreamData {
                                 AnyResult::Ok(())
                             },
                             _ = watchdog_eat_rx => {
+                                log::info!("{print_name}: Video Stream Stalled");
+                                health.send_replace(StreamHealth::Stalled);
                                 sleep(Duration::from_secs(1)).await;
                                 AnyResult::Ok(())
                             },
@@ -482,6 +618,11 @@ impl StreamData {
                                     let fps_table = fps_table.clone();
                                     let master_ts = master_ts.clone();
                                     let fps_delta = fps_delta.clone();
+                                    let health = health.clone();
+                                    let stats = stats.clone();
+                                    let health_config = health_config.clone();
+                                    let print_name = print_name.clone();
+                                    let stream_hooks = stream_hooks.clone();
 
                                     Box::pin(async move {
                                         log::trace!("Starting streamthread TASK");
@@ -492,10 +633,49 @@ impl StreamData {
 
                                         let res = async {
                                             let mut stream_data = camera.start_video(name, 0, strict).await?;
+                                            stream_hooks.send_replace(Some(stream_data.hooks()));
+                                            let health_window = Duration::from_secs(health_config.window);
+                                            let mut window_start = Instant::now();
+                                            let mut window_bytes = 0usize;
                                             loop {
                                                 let data = stream_data.get_data().await??;
                                                 watchdog_tx.send(()).await?;  // Feed the watchdog
 
+                                                // Track the health of the stream
+                                                health.send_if_modified(|state| {
+                                                    if matches!(state, StreamHealth::Stopped | StreamHealth::Stalled) {
+                                                        *state = StreamHealth::Started;
+                                                        true
+                                                    } else {
+                                                        false
+                                                    }
+                                                });
+                                                window_bytes += match &data {
+                                                    BcMedia::Iframe(BcMediaIframe{data, ..}) | BcMedia::Pframe(BcMediaPframe{data, ..}) => data.len(),
+                                                    BcMedia::Aac(BcMediaAac{data, ..}) | BcMedia::Adpcm(BcMediaAdpcm{data, ..}) => data.len(),
+                                                    _ => 0,
+                                                };
+                                                if window_start.elapsed() >= health_window {
+                                                    let bitrate = (window_bytes * 8) as f64 / window_start.elapsed().as_secs_f64();
+                                                    let expected = stream_config.borrow().bitrate as f64;
+                                                    let dropped = bitrate < expected * health_config.min_bitrate;
+                                                    stats.measured_bitrate.store(bitrate as u64, Ordering::Relaxed);
+                                                    health.send_if_modified(|state| match (*state, dropped) {
+                                                        (StreamHealth::Started, true) => {
+                                                            log::info!("{print_name}: Bitrate dropped to {:.0}kbps", bitrate / 1024.0);
+                                                            *state = StreamHealth::BitrateDropped;
+                                                            true
+                                                        }
+                                                        (StreamHealth::BitrateDropped, false) => {
+                                                            *state = StreamHealth::Started;
+                                                            true
+                                                        }
+                                                        _ => false,
+                                                    });
+                                                    window_start = Instant::now();
+                                                    window_bytes = 0;
+                                                }
+
                                                 // Update the stream config with any information
                                                 match &data {
                                                     BcMedia::InfoV1(info) => {
@@ -604,6 +784,7 @@ impl StreamData {
                                                         });
                                                         recieved_iframe = true;
                                                         aud_keyframe = true;
+                                                        stats.frames.fetch_add(1, Ordering::Relaxed);
                                                         log::trace!("Sent Vid Key Frame: {:?}", master_ts.read().await);
                                                         *master_ts.write().await += *fps_delta.read().await;
                                                     },
@@ -623,8 +804,13 @@ impl StreamData {
                                                            }
                                                         });
                                                         *master_ts.write().await += *fps_delta.read().await;
+                                                        stats.frames.fetch_add(1, Ordering::Relaxed);
                                                         log::trace!("Sent Vid Frame: {:?}", master_ts.read().await);
                                                     }
+                                                    BcMedia::Pframe(_) => {
+                                                        // Cannot be decoded without the keyframe before it
+                                                        stats.dropped_frames.fetch_add(1, Ordering::Relaxed);
+                                                    }
                                                     BcMedia::Aac(BcMediaAac{data, ..}) | BcMedia::Adpcm(BcMediaAdpcm{data,..}) if recieved_iframe => {
                                                         let d = StampedData{
                                                             keyframe: aud_keyframe,
@@ -654,14 +840,17 @@ impl StreamData {
                                 match result {
                                     Ok(AnyResult::Ok(())) => {
                                         log::debug!("{print_name}: Video Stream Stopped due to no listeners");
+                                        health.send_replace(StreamHealth::Stopped);
                                         break Ok(());
                                     },
                                     Ok(Err(e)) => {
                                         log::debug!("{print_name}: Video Stream Restarting Due to Error: {:?}", e);
+                                        health.send_replace(StreamHealth::Stalled);
                                         AnyResult::Ok(())
                                     },
                                     Err(e) => {
                                         log::debug!("{print_name}: Video Stream Stopped Due to Instance Error: {:?}", e);
+                                        health.send_replace(StreamHealth::Stopped);
                                         break Err(e);
                                     },
                                 }
@@ -0,0 +1,103 @@
+//! Tracks what neolink is currently asking of a camera
+//!
+//! Each stream, the motion listener and the battery polling hold a
+//! [`Subscription`] while they are active on the camera. Cameras degrade
+//! without an error when too many of these are active at once so when the
+//! camera has a `max_subscriptions` the next one waits until another ends
+use std::sync::Arc;
+use tokio::sync::watch::{channel as watch, Receiver as WatchReceiver, Sender as WatchSender};
+
+use crate::{config::CameraConfig, Result};
+
+/// The active subscriptions of one camera, shared by all its instances
+#[derive(Clone)]
+pub(crate) struct Subscriptions {
+    active: Arc<WatchSender<Vec<String>>>,
+    config: WatchReceiver<CameraConfig>,
+}
+
+impl Subscriptions {
+    pub(crate) fn new(config: WatchReceiver<CameraConfig>) -> Self {
+        Self {
+            active: Arc::new(watch(vec![]).0),
+            config,
+        }
+    }
+
+    /// The names of the active subscriptions such as `mainStream` or `motion`
+    pub(crate) fn active(&self) -> Vec<String> {
+        self.active.borrow().clone()
+    }
+
+    /// The most subscriptions that are allowed at once, `None` for no limit
+    pub(crate) fn limit(&self) -> Option<usize> {
+        self.config.borrow().max_subscriptions
+    }
+
+    /// Wait until the camera is below its `max_subscriptions` and take one
+    ///
+    /// The subscription ends when the returned value is dropped
+    pub(crate) async fn acquire(&self, feature: &str) -> Result<Subscription> {
+        let mut config = self.config.clone();
+        let mut active = self.active.subscribe();
+        let mut warned = false;
+        loop {
+            active.borrow_and_update();
+            let (name, limit) = {
+                let config = config.borrow_and_update();
+                (config.name.clone(), config.max_subscriptions)
+            };
+            let acquired = self.active.send_if_modified(|active| {
+                if limit.map_or(true, |limit| active.len() < limit) {
+                    active.push(feature.to_string());
+                    true
+                } else {
+                    false
+                }
+            });
+            if acquired {
+                if warned {
+                    log::info!("{}: Starting {}", name, feature);
+                }
+                return Ok(Subscription {
+                    feature: feature.to_string(),
+                    active: self.active.clone(),
+                });
+            }
+            if !warned {
+                log::warn!(
+                    "{}: Waiting to start {} as the camera is at its max_subscriptions of {}: {}",
+                    name,
+                    feature,
+                    limit.unwrap_or_default(),
+                    self.active().join(", ")
+                );
+                warned = true;
+            }
+            tokio::select! {
+                v = config.changed() => v?,
+                v = active.changed() => v?,
+            }
+        }
+    }
+}
+
+/// Held while a feature is active on the camera
+pub(crate) struct Subscription {
+    feature: String,
+    active: Arc<WatchSender<Vec<String>>>,
+}
+
+impl Drop for Subscription {
+    fn drop(&mut self) {
+        self.active.send_if_modified(|active| {
+            match active.iter().position(|feature| feature == &self.feature) {
+                Some(index) => {
+                    active.remove(index);
+                    true
+                }
+                None => false,
+            }
+        });
+    }
+}
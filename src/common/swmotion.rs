@@ -0,0 +1,286 @@
+//! Software motion detection
+//!
+//! For cameras whose own motion detection is unreliable the substream is
+//! decoded at a low resolution and frame rate and each frame compared to the
+//! last. The result is sent on the same watch as the camera's own motion
+//! so everything that listens for motion also gets these events.
+
+use anyhow::{anyhow, Context};
+use futures::stream::StreamExt;
+use gstreamer::{
+    parse::launch_full, prelude::*, Caps, ClockTime, FlowError, FlowSuccess, MessageView,
+    ParseFlags, Pipeline, State,
+};
+use gstreamer_app::{AppSink, AppSinkCallbacks, AppSrc};
+use tokio::{
+    sync::{
+        mpsc::{channel as mpsc, Receiver as MpscReceiver, Sender as MpscSender},
+        watch::Sender as WatchSender,
+    },
+    task::JoinSet,
+    time::{sleep_until, Duration, Instant},
+};
+use tokio_stream::wrappers::BroadcastStream;
+
+use super::{events::Trigger, MdState, NeoInstance, StampedData, VidFormat};
+use crate::{
+    config::{SoftwareMotionConfig, ZoomRect},
+    AnyResult,
+};
+use neolink_core::bc_protocol::StreamKind;
+
+/// Size of the frames that are compared
+const WIDTH: usize = 160;
+const HEIGHT: usize = 90;
+
+/// How much a pixel must change to count as changed
+const PIXEL_THRESHOLD: u8 = 25;
+
+/// Run the software motion detection until the stream fails
+pub(crate) async fn run(
+    instance: &NeoInstance,
+    config: &SoftwareMotionConfig,
+    watcher: &WatchSender<MdState>,
+    triggers: &MpscSender<Trigger>,
+) -> AnyResult<()> {
+    let stream = match instance.low_stream().await? {
+        Some(stream) => stream,
+        None => instance.stream(StreamKind::Main).await?,
+    };
+    let mut stream_config = stream.config.clone();
+    let vid_format = stream_config
+        .wait_for(|config| config.vid_ready())
+        .await?
+        .vid_format;
+    log::info!(
+        "{}: Starting software motion detection on the {:?} stream",
+        instance.config().await?.borrow().name,
+        stream.name
+    );
+
+    let (pipeline, source, mut frames) = create_pipeline(vid_format, config.fps)?;
+    let mut set = JoinSet::<AnyResult<()>>::new();
+    let thread_pipeline = pipeline.clone();
+    set.spawn_blocking(move || start_pipeline(thread_pipeline));
+
+    let mut vid = BroadcastStream::new(stream.vid.resubscribe());
+    let mut differ = FrameDiffer::new(config);
+    let hold = Duration::from_secs_f64(config.hold);
+    let mut moving = false;
+    let mut last_motion = Instant::now();
+    let mut started = false;
+
+    let res: AnyResult<()> = async {
+        loop {
+            tokio::select! {
+                data = vid.next() => {
+                    match data {
+                        Some(Ok(StampedData { data, keyframe, .. })) => {
+                            // The decoder needs a keyframe to start from
+                            started |= keyframe;
+                            if started {
+                                let mut buf = gstreamer::Buffer::with_size(data.len())?;
+                                buf.get_mut()
+                                    .ok_or_else(|| anyhow!("New buffer should be writable"))?
+                                    .copy_from_slice(0, &data)
+                                    .map_err(|_| anyhow!("Failed to copy the frame"))?;
+                                source
+                                    .push_buffer(buf)
+                                    .map_err(|e| anyhow!("Streamer Error: {e:?}"))?;
+                            }
+                        }
+                        // Lagged, the detection does not need every frame
+                        Some(Err(_)) => {}
+                        None => return Err(anyhow!("The stream ended")),
+                    }
+                }
+                frame = frames.recv() => {
+                    let frame = frame.ok_or_else(|| anyhow!("The decoder stopped"))?;
+                    if differ.is_motion(frame) {
+                        last_motion = Instant::now();
+                        if !moving {
+                            moving = true;
+                            log::debug!("Software motion started");
+                            watcher.send_replace(MdState::Start(last_motion));
+                            let _ = triggers.try_send(Trigger::Software);
+                        }
+                    }
+                }
+                _ = sleep_until(last_motion + hold), if moving => {
+                    moving = false;
+                    log::debug!("Software motion stopped");
+                    watcher.send_replace(MdState::Stop(Instant::now()));
+                }
+            }
+        }
+    }
+    .await;
+
+    if moving {
+        watcher.send_replace(MdState::Stop(Instant::now()));
+    }
+    let _ = source.end_of_stream();
+    let _ = pipeline.set_state(State::Null);
+    while set.join_next().await.is_some() {}
+    res
+}
+
+/// Compares each frame to the one before it
+struct FrameDiffer {
+    previous: Option<Vec<u8>>,
+    /// Pixels that are compared, false for those in an ignored area
+    considered: Vec<bool>,
+    /// The number of pixels that must change for motion
+    min_changed: usize,
+}
+
+impl FrameDiffer {
+    fn new(config: &SoftwareMotionConfig) -> Self {
+        let ignored = config
+            .ignore
+            .iter()
+            .map(|area| area.rect())
+            .collect::<Vec<ZoomRect>>();
+        let considered = (0..WIDTH * HEIGHT)
+            .map(|i| {
+                let x = (i % WIDTH) as f64 / WIDTH as f64;
+                let y = (i / WIDTH) as f64 / HEIGHT as f64;
+                !ignored.iter().any(|rect| {
+                    x >= rect.x
+                        && x < rect.x + rect.width
+                        && y >= rect.y
+                        && y < rect.y + rect.height
+                })
+            })
+            .collect::<Vec<_>>();
+        // Sensitivity 100 needs 0.1% of the frame to change and 1 needs 10%
+        let fraction = (101 - config.sensitivity as usize) as f64 / 1000.0;
+        let count = considered.iter().filter(|c| **c).count();
+        Self {
+            previous: None,
+            considered,
+            min_changed: ((count as f64 * fraction) as usize).max(1),
+        }
+    }
+
+    fn is_motion(&mut self, frame: Vec<u8>) -> bool {
+        if frame.len() != WIDTH * HEIGHT {
+            log::trace!("Unexpected frame size for software motion");
+            return false;
+        }
+        let changed = self.previous.as_ref().map(|previous| {
+            previous
+                .iter()
+                .zip(frame.iter())
+                .zip(self.considered.iter())
+                .filter(|((a, b), considered)| **considered && a.abs_diff(**b) > PIXEL_THRESHOLD)
+                .count()
+        });
+        self.previous = Some(frame);
+        changed.is_some_and(|changed| changed >= self.min_changed)
+    }
+}
+
+fn start_pipeline(pipeline: Pipeline) -> AnyResult<()> {
+    pipeline.set_state(State::Playing)?;
+
+    let bus = pipeline
+        .bus()
+        .expect("Pipeline without bus. Shouldn't happen!");
+
+    loop {
+        match bus.timed_pop(ClockTime::from_mseconds(500)) {
+            Some(msg) => match msg.view() {
+                MessageView::Eos(..) => break,
+                MessageView::Error(err) => {
+                    log::warn!("Error from gstreamer in software motion: {:?}", err);
+                    break;
+                }
+                _ => (),
+            },
+            // Stopped by the detector
+            None if pipeline.current_state() == State::Null => break,
+            None => (),
+        }
+    }
+
+    pipeline
+        .set_state(State::Null)
+        .context("Error in gstreamer when setting state to Null")?;
+
+    Ok(())
+}
+
+/// Decode the stream into small grey frames at about `fps`
+fn create_pipeline(
+    format: VidFormat,
+    fps: f64,
+) -> AnyResult<(Pipeline, AppSrc, MpscReceiver<Vec<u8>>)> {
+    gstreamer::init()
+        .context("Unable to start gstreamer ensure it and all plugins are installed")?;
+
+    let (parser, decoder) = match format {
+        VidFormat::H264 => ("h264parse", "avdec_h264"),
+        VidFormat::H265 => ("h265parse", "avdec_h265"),
+        VidFormat::None => unreachable!(),
+    };
+    let launch_str = format!(
+        "appsrc name=thesource is-live=true do-timestamp=true \
+        ! {} \
+        ! {} \
+        ! videoconvert \
+        ! videoscale \
+        ! appsink name=thesink sync=false max-buffers=2 drop=true",
+        parser, decoder
+    );
+    log::debug!("{}", launch_str);
+
+    let pipeline = launch_full(&launch_str, None, ParseFlags::empty())
+        .context("Unable to load gstreamer pipeline ensure all gstramer plugins are installed")?
+        .dynamic_cast::<Pipeline>()
+        .map_err(|_| {
+            anyhow!("Unable to create gstreamer pipeline ensure all gstramer plugins are installed")
+        })?;
+
+    let source = pipeline
+        .by_name("thesource")
+        .expect("There shoud be a `thesource`")
+        .dynamic_cast::<AppSrc>()
+        .map_err(|_| anyhow!("Cannot find appsource in gstreamer, check your gstreamer plugins"))?;
+    let sink = pipeline
+        .by_name("thesink")
+        .expect("There shoud be a `thesink`")
+        .dynamic_cast::<AppSink>()
+        .map_err(|_| anyhow!("Cannot find appsink in gstreamer, check your gstreamer plugins"))?;
+    sink.set_caps(Some(
+        &Caps::builder("video/x-raw")
+            .field("format", "GRAY8")
+            .field("width", WIDTH as i32)
+            .field("height", HEIGHT as i32)
+            .build(),
+    ));
+
+    // Every frame has to be decoded but only a few are compared
+    let period = Duration::from_secs_f64(1.0 / fps);
+    let mut next = Instant::now();
+    let (tx, rx) = mpsc(2);
+    sink.set_callbacks(
+        AppSinkCallbacks::builder()
+            .new_sample(move |appsink| {
+                let sample = appsink.pull_sample().map_err(|_| FlowError::Eos)?;
+                let now = Instant::now();
+                if now < next {
+                    return Ok(FlowSuccess::Ok);
+                }
+                next = now + period;
+                let buffer = sample.buffer().ok_or(FlowError::Error)?;
+                let map = buffer.map_readable().map_err(|_| FlowError::Error)?;
+                // If the detector is behind skip the frame
+                let _ = tx.try_send(map.as_slice().to_vec());
+                Ok(FlowSuccess::Ok)
+            })
+            .build(),
+    );
+
+    Ok((pipeline, source, rx))
+}
@@ -0,0 +1,139 @@
+//! Small jpeg thumbnails decoded from the substream
+//!
+//! Dashboards show many cameras as small tiles. Rather than asking the camera
+//! for a full size SNAP the latest keyframe of the substream is decoded and
+//! scaled down. The main stream is never started for this.
+
+use anyhow::{anyhow, Context};
+use futures::stream::StreamExt;
+use gstreamer::{parse::launch_full, prelude::*, ClockTime, ParseFlags, Pipeline, State};
+use gstreamer_app::{AppSink, AppSrc};
+use std::sync::Arc;
+use tokio::time::{timeout, Duration};
+use tokio_stream::wrappers::BroadcastStream;
+
+use super::{StampedData, StreamInstance, VidFormat};
+use crate::AnyResult;
+
+/// How long to wait for a keyframe when there is none in the history
+const KEYFRAME_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Decode the latest keyframe of the stream into a jpeg `width` pixels wide
+pub(crate) async fn thumbnail(stream: &StreamInstance, width: u32) -> AnyResult<Vec<u8>> {
+    let stream_config = stream
+        .config
+        .clone()
+        .wait_for(|config| config.vid_ready())
+        .await?
+        .clone();
+
+    // Use the history when the stream is already playing for something else
+    let recent = stream
+        .vid_history
+        .borrow()
+        .iter()
+        .rev()
+        .find(|frame| frame.keyframe)
+        .map(|frame| frame.data.clone());
+    let keyframe = match recent {
+        Some(keyframe) => keyframe,
+        None => timeout(KEYFRAME_TIMEOUT, async {
+            let mut vid = BroadcastStream::new(stream.vid.resubscribe());
+            while let Some(frame) = vid.next().await {
+                if let Ok(StampedData {
+                    data,
+                    keyframe: true,
+                    ..
+                }) = frame
+                {
+                    return Ok(data);
+                }
+            }
+            Err(anyhow!("The stream ended"))
+        })
+        .await
+        .context("No keyframe for the thumbnail")??,
+    };
+
+    // Keep the aspect of the stream and an even height for the encoder
+    let [stream_width, stream_height] = stream_config.resolution;
+    let height = ((width as u64 * stream_height as u64 / stream_width as u64) as u32 & !1).max(2);
+    let format = stream_config.vid_format;
+    tokio::task::spawn_blocking(move || decode(format, keyframe, width, height)).await?
+}
+
+/// Decode a single keyframe into a jpeg of `width` by `height`
+fn decode(
+    format: VidFormat,
+    keyframe: Arc<Vec<u8>>,
+    width: u32,
+    height: u32,
+) -> AnyResult<Vec<u8>> {
+    gstreamer::init()
+        .context("Unable to start gstreamer ensure it and all plugins are installed")?;
+
+    let parser = match format {
+        VidFormat::H264 => "h264parse",
+        VidFormat::H265 => "h265parse",
+        VidFormat::None => unreachable!(),
+    };
+    let launch_str = format!(
+        "appsrc name=thesource \
+        ! {} \
+        ! decodebin \
+        ! videoconvert \
+        ! videoscale \
+        ! video/x-raw,width={},height={},pixel-aspect-ratio=1/1 \
+        ! jpegenc snapshot=TRUE \
+        ! appsink name=thesink sync=false",
+        parser, width, height
+    );
+    log::debug!("{}", launch_str);
+
+    let pipeline = launch_full(&launch_str, None, ParseFlags::empty())
+        .context("Unable to load gstreamer pipeline ensure all gstramer plugins are installed")?
+        .dynamic_cast::<Pipeline>()
+        .map_err(|_| {
+            anyhow!("Unable to create gstreamer pipeline ensure all gstramer plugins are installed")
+        })?;
+    let source = pipeline
+        .by_name("thesource")
+        .expect("There shoud be a `thesource`")
+        .dynamic_cast::<AppSrc>()
+        .map_err(|_| anyhow!("Cannot find appsource in gstreamer, check your gstreamer plugins"))?;
+    let sink = pipeline
+        .by_name("thesink")
+        .expect("There shoud be a `thesink`")
+        .dynamic_cast::<AppSink>()
+        .map_err(|_| anyhow!("Cannot find appsink in gstreamer, check your gstreamer plugins"))?;
+
+    pipeline.set_state(State::Playing)?;
+    let res = (|| {
+        let mut buf = gstreamer::Buffer::with_size(keyframe.len())?;
+        buf.get_mut()
+            .ok_or_else(|| anyhow!("New buffer should be writable"))?
+            .copy_from_slice(0, &keyframe)
+            .map_err(|_| anyhow!("Failed to copy the frame"))?;
+        source
+            .push_buffer(buf)
+            .map_err(|e| anyhow!("Streamer Error: {e:?}"))?;
+        // Flushes the decoder so that the one frame comes out
+        source
+            .end_of_stream()
+            .map_err(|e| anyhow!("Streamer Error: {e:?}"))?;
+        let sample = sink
+            .try_pull_sample(ClockTime::from_seconds(5))
+            .ok_or_else(|| anyhow!("The keyframe could not be decoded"))?;
+        let buffer = sample
+            .buffer()
+            .ok_or_else(|| anyhow!("The thumbnail has no data"))?;
+        let map = buffer
+            .map_readable()
+            .map_err(|_| anyhow!("The thumbnail could not be read"))?;
+        AnyResult::Ok(map.as_slice().to_vec())
+    })();
+    pipeline
+        .set_state(State::Null)
+        .context("Error in gstreamer when setting state to Null")?;
+    res
+}
@@ -66,6 +66,11 @@ impl UseCounter {
     pub(crate) async fn create_deactivated(&self) -> Result<Permit> {
         Ok(Permit::new(self))
     }
+
+    /// The number of permits that are currently active
+    pub(crate) fn count(&self) -> u32 {
+        *self.value.borrow()
+    }
 }
 
 impl Drop for UseCounter {
@@ -87,6 +92,7 @@ pub(crate) struct Permit {
 }
 
 impl Permit {
+    #[cfg(feature = "gstreamer")]
     pub(crate) fn subscribe(&self) -> Self {
         Self {
             is_active: false,
@@ -141,6 +147,7 @@ impl Permit {
         Ok(())
     }
 
+    #[cfg(feature = "gstreamer")]
     pub(crate) fn get_counter(&self) -> WatchReceiver<u32> {
         self.value.clone()
     }
@@ -1,16 +1,28 @@
+use crate::common::Timezone;
 use crate::mqtt::Discoveries;
-use neolink_core::bc_protocol::{DiscoveryMethods, PrintFormat, StreamKind};
+use neolink_core::bc::model::{msg_id_name, MSG_ID_NAMES};
+use neolink_core::bc_protocol::{
+    DiscoveryMethods, DiscoveryServers, PrintFormat, StreamKind, UdpOptions,
+};
 use once_cell::sync::Lazy;
 use regex::Regex;
 use serde::{Deserialize, Serialize};
 use std::clone::Clone;
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
+use time::UtcOffset;
 use validator::ValidationError;
 use validator_derive::Validate;
 
 static RE_TLS_CLIENT_AUTH: Lazy<Regex> =
     Lazy::new(|| Regex::new(r"^(none|request|require)$").unwrap());
 static RE_PAUSE_MODE: Lazy<Regex> = Lazy::new(|| Regex::new(r"^(black|still|test|none)$").unwrap());
+static RE_OVERLAY_POSITION: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"^(top|bottom)-(left|center|right)$").unwrap());
+static RE_SYSLOG_FACILITY: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"^(kern|user|mail|daemon|auth|syslog|lpr|news|uucp|cron|authpriv|ftp|local[0-7])$")
+        .unwrap()
+});
+static RE_RTSP_PATH: Lazy<Regex> = Lazy::new(|| Regex::new(r"^[\w.\-]+(/[\w.\-]+)*$").unwrap());
 static RE_MAXENC_SRC: Lazy<Regex> = Lazy::new(|| {
     Regex::new(r"^([nN]one|[Aa][Ee][Ss]|[Bb][Cc][Ee][Nn][Cc][Rr][Yy][Pp][Tt])$").unwrap()
 });
@@ -47,6 +59,112 @@ pub(crate) struct Config {
     #[validate]
     #[serde(default)]
     pub(crate) users: Vec<UserConfig>,
+
+    /// How quickly the cameras connect, such as on startup
+    #[validate]
+    #[serde(default, alias = "startup")]
+    pub(crate) connect: ConnectConfig,
+
+    /// The ONVIF server of `neolink onvif`
+    #[validate]
+    #[serde(default)]
+    pub(crate) onvif: OnvifConfig,
+
+    /// The still image server of `neolink http`
+    #[validate]
+    #[serde(default)]
+    pub(crate) http: HttpConfig,
+
+    /// The WHEP server of `neolink webrtc`
+    #[validate]
+    #[serde(default)]
+    pub(crate) webrtc: WebrtcConfig,
+
+    /// The HLS server of `neolink hls`
+    #[validate]
+    #[serde(default)]
+    pub(crate) hls: HlsConfig,
+
+    /// The services that `neolink serve` runs together
+    #[validate]
+    #[serde(default)]
+    pub(crate) serve: ServeConfig,
+
+    /// The control socket of a running neolink, used by `neolink ctl`
+    #[validate]
+    #[serde(default)]
+    pub(crate) control: Option<ControlConfig>,
+
+    /// Urls that the motion events are posted to
+    #[validate]
+    #[serde(default)]
+    pub(crate) webhooks: Option<WebhookConfig>,
+
+    /// The file that the control actions are logged to
+    #[validate]
+    #[serde(default)]
+    pub(crate) audit: Option<AuditConfig>,
+
+    /// Audio clips that can be played on the camera speakers by name
+    #[validate]
+    #[serde(default, alias = "clip")]
+    pub(crate) clips: Vec<ClipConfig>,
+
+    /// What the cameras do when the alarm is raised on `neolink/control/alarm`
+    #[validate]
+    #[serde(default)]
+    pub(crate) alarm: Option<AlarmConfig>,
+
+    /// Syslog or journald that the camera events are sent to
+    #[validate]
+    #[serde(default, alias = "syslog")]
+    pub(crate) event_log: Option<EventLogConfig>,
+
+    /// The Prometheus metrics and health check server
+    #[validate]
+    #[serde(default)]
+    pub(crate) metrics: Option<MetricsConfig>,
+
+    /// How the audit log and the bookmarks file are rotated and compressed
+    #[validate]
+    #[serde(default, alias = "log_rotation")]
+    pub(crate) rotation: RotationConfig,
+}
+
+impl Config {
+    /// A copy of the config with the passwords replaced, safe to print or share
+    pub(crate) fn redacted(&self) -> Self {
+        const REDACTED: &str = "<redacted>";
+        let mut config = self.clone();
+        for camera in config.cameras.iter_mut() {
+            if camera.password.is_some() {
+                camera.password = Some(REDACTED.to_string());
+            }
+            if let Some(api_key) = camera
+                .object_detection
+                .as_mut()
+                .and_then(|detection| detection.api_key.as_mut())
+            {
+                *api_key = REDACTED.to_string();
+            }
+        }
+        for user in config.users.iter_mut() {
+            user.pass = REDACTED.to_string();
+        }
+        if let Some((_, pass)) = config
+            .mqtt
+            .as_mut()
+            .and_then(|mqtt| mqtt.credentials.as_mut())
+        {
+            *pass = REDACTED.to_string();
+        }
+        if let Some(webhooks) = config.webhooks.as_mut() {
+            for value in webhooks.headers.values_mut() {
+                *value = REDACTED.to_string();
+            }
+        }
+        config
+    }
 }
 
 #[derive(Debug, Deserialize, Serialize, Clone, Validate, PartialEq, Eq)]
@@ -65,6 +183,15 @@ pub(crate) struct MqttServerConfig {
 
     #[serde(default)]
     pub(crate) client_auth: Option<(std::path::PathBuf, std::path::PathBuf)>,
+
+    /// When `online` is published here, such as when home assistant
+    /// restarts, the retained messages are published again. Empty to disable
+    #[serde(default = "default_birth_topic")]
+    pub(crate) birth_topic: String,
+}
+
+fn default_birth_topic() -> String {
+    "homeassistant/status".to_string()
 }
 
 #[derive(Debug, Deserialize, Serialize, Clone, Copy, Eq, PartialEq)]
@@ -134,6 +261,10 @@ pub(crate) struct CameraConfig {
     #[serde(rename = "uid")]
     pub(crate) camera_uid: Option<String>,
 
+    /// TCP port of the camera when `address` does not include one, defaults to 9000
+    #[serde(default, alias = "tcp_port")]
+    pub(crate) port: Option<u16>,
+
     pub(crate) username: String,
 
     #[serde(alias = "pass")]
@@ -203,6 +334,16 @@ pub(crate) struct CameraConfig {
     #[serde(default = "default_splash", alias = "pattern")]
     pub(crate) splash_pattern: SplashPattern,
 
+    /// If true the rtsp paths stay up while the camera is offline and show
+    /// a camera offline slate until it reconnects
+    #[serde(default = "default_true", alias = "offline_splash")]
+    pub(crate) offline_slate: bool,
+
+    /// Path to an image shown in place of the splash pattern on the
+    /// splash and offline slates
+    #[serde(default, alias = "slate_image")]
+    pub(crate) fallback_image: Option<std::path::PathBuf>,
+
     #[serde(
         default = "default_max_discovery_retries",
         alias = "retries",
@@ -210,11 +351,234 @@ pub(crate) struct CameraConfig {
     )]
     pub(crate) max_discovery_retries: usize,
 
+    /// Seconds between login attempts while the camera has reached its
+    /// maximum number of clients, 0 to give up instead
+    #[serde(default = "default_busy_retry", alias = "max_clients_retry")]
+    pub(crate) busy_retry: u64,
+
+    /// How many of the last errors of the camera are kept for `neolink status --errors`
+    #[validate(range(max = 1000, message = "Invalid error history", code = "error_history"))]
+    #[serde(default = "default_error_history", alias = "errors")]
+    pub(crate) error_history: usize,
+
+    /// Seconds that a snapshot is reused for before asking the camera
+    /// for a new one, 0 to always ask the camera
+    #[serde(default, alias = "snap_max_age")]
+    pub(crate) snapshot_max_age: u64,
+
+    /// Add the capture time, camera name and model to the snapshots as EXIF
+    #[serde(default = "default_true", alias = "exif")]
+    pub(crate) snapshot_exif: bool,
+
+    /// Width in pixels of the thumbnails made from the substream
+    #[validate(range(
+        min = 16,
+        max = 1920,
+        message = "Invalid thumbnail width",
+        code = "thumbnail_width"
+    ))]
+    #[serde(default = "default_thumbnail_width")]
+    pub(crate) thumbnail_width: u32,
+
     #[serde(default = "default_true", alias = "push", alias = "push_noti")]
     pub(crate) push_notifications: bool,
 
     #[serde(default = "default_false", alias = "idle", alias = "idle_disc")]
     pub(crate) idle_disconnect: bool,
+
+    /// How the connection is kept while the camera is not streaming
+    #[validate]
+    #[serde(default)]
+    pub(crate) idle_policy: IdlePolicyConfig,
+
+    /// How the control commands from mqtt and http wait for each other
+    #[validate]
+    #[serde(default, alias = "queue")]
+    pub(crate) command_queue: CommandQueueConfig,
+
+    /// Template for an additional rtsp path for each stream e.g. `{nvr}_{channel_name}_{stream}`
+    #[serde(default, alias = "rtsp_name")]
+    pub(crate) rtsp_template: Option<String>,
+
+    /// Template for the mqtt topic name used in place of `name` e.g. `{nvr}_{channel_name}`
+    #[serde(default, alias = "mqtt_name")]
+    pub(crate) mqtt_template: Option<String>,
+
+    /// The timezone of the camera as a UTC offset e.g. `+10:00` or `UTC-5`,
+    /// or a POSIX TZ rule with daylight saving e.g. `CET-1CEST,M3.5.0,M10.5.0/3`
+    ///
+    /// Used when setting the camera time and for event timestamps
+    #[validate(custom(function = "validate_timezone"))]
+    #[serde(default, alias = "tz")]
+    pub(crate) timezone: Option<String>,
+
+    /// If true the device name of the camera is set to `name` on connect
+    #[serde(default = "default_false", alias = "set_name")]
+    pub(crate) sync_name: bool,
+
+    /// If true only one neolink process at a time will connect to this camera
+    #[serde(default = "default_false", alias = "exclusive")]
+    pub(crate) lock: bool,
+
+    /// The most streams, motion listeners and battery polls that are active
+    /// on the camera at once, the next one waits for another to end
+    #[validate(range(
+        min = 1,
+        message = "Invalid max subscriptions",
+        code = "max_subscriptions"
+    ))]
+    #[serde(default, alias = "max_subs")]
+    pub(crate) max_subscriptions: Option<usize>,
+
+    /// Tuning for the UDP transport used by UID connections
+    #[serde(default)]
+    pub(crate) udp: UdpOptions,
+
+    /// Servers used in place of the reolink ones for remote, map and relay discovery
+    #[serde(default, alias = "servers")]
+    pub(crate) discovery_servers: DiscoveryServers,
+
+    /// Scoring of the connection quality and switching to a better transport
+    #[validate]
+    #[serde(default)]
+    pub(crate) quality: QualityConfig,
+
+    /// Checking the camera's clock against the host's
+    #[validate]
+    #[serde(default, alias = "clock")]
+    pub(crate) clock_check: ClockCheckConfig,
+
+    /// How long to wait between attempts to reconnect to the camera
+    #[validate]
+    #[serde(default, alias = "backoff")]
+    pub(crate) reconnect: ReconnectConfig,
+
+    /// A cropped and scaled copy of a stream served at `/{name}/zoom`
+    #[validate]
+    #[serde(default, alias = "dzoom")]
+    pub(crate) digital_zoom: Option<DigitalZoomConfig>,
+
+    /// Text, a clock or an image drawn onto the rtsp streams
+    #[validate]
+    #[serde(default)]
+    pub(crate) overlay: Option<OverlayConfig>,
+
+    /// Areas of the frame that are blacked out or blurred on the rtsp streams
+    #[validate]
+    #[serde(default, alias = "privacy_mask")]
+    pub(crate) privacy_masks: Vec<PrivacyMaskConfig>,
+
+    /// Custom rtsp paths of the streams, served at `/{name}/{path}`
+    ///
+    /// When given only these paths are served in place of `/{name}/main` etc
+    #[validate]
+    #[serde(default, alias = "paths")]
+    pub(crate) streams: Vec<RtspPathConfig>,
+
+    /// Streams that are served over rtsp without their audio track
+    #[serde(default = "default_mute", alias = "no_audio")]
+    pub(crate) mute: StreamConfig,
+
+    /// How the camera's audio is sent to the rtsp clients
+    #[serde(default)]
+    pub(crate) audio: AudioMode,
+
+    /// How the rtsp paths trade delay for smoothness, a path of `streams`
+    /// can set its own
+    #[serde(default)]
+    pub(crate) latency: LatencyMode,
+
+    /// Offer an ONVIF audio backchannel on the rtsp streams that is played on
+    /// the camera's speaker
+    #[serde(default = "default_false", alias = "talkback")]
+    pub(crate) backchannel: bool,
+
+    /// What the clients that keep failing to play the HEVC main stream are served instead
+    #[validate]
+    #[serde(default, alias = "fallback")]
+    pub(crate) codec_fallback: Option<CodecFallbackConfig>,
+
+    /// Motion detection done by neolink on the substream
+    #[validate]
+    #[serde(default, alias = "sw_motion")]
+    pub(crate) software_motion: Option<SoftwareMotionConfig>,
+
+    /// An inference server that is sent a snapshot on motion
+    #[validate]
+    #[serde(default, alias = "detection")]
+    pub(crate) object_detection: Option<ObjectDetectionConfig>,
+
+    /// Continuous recording of a stream to disk by `neolink record`
+    #[validate]
+    #[serde(default)]
+    pub(crate) record: Option<RecordConfig>,
+
+    /// Snapshots taken every `interval` by `neolink timelapse`
+    #[validate]
+    #[serde(default)]
+    pub(crate) timelapse: Option<TimelapseConfig>,
+
+    /// Seconds after the first trigger that the motion, PIR, AI and smart
+    /// detections are merged into one event, 0 for an event per trigger
+    #[validate(range(
+        min = 0.0,
+        max = 60.0,
+        message = "Invalid event window",
+        code = "event_window"
+    ))]
+    #[serde(default = "default_event_window", alias = "dedup_window")]
+    pub(crate) event_window: f64,
+
+    /// When a stream is reported as stalled or as having a dropped bitrate
+    #[validate]
+    #[serde(default)]
+    pub(crate) stream_health: StreamHealthConfig,
+
+    /// How messages that the camera replies to with an error are retried
+    #[validate]
+    #[serde(default)]
+    pub(crate) retry: RetryConfig,
+}
+
+impl CameraConfig {
+    /// The `timezone`, defaults to UTC
+    pub(crate) fn timezone(&self) -> Timezone {
+        self.timezone
+            .as_deref()
+            .and_then(Timezone::parse)
+            .unwrap_or_default()
+    }
+
+    /// The UTC offset of the `timezone` now
+    pub(crate) fn utc_offset(&self) -> UtcOffset {
+        self.timezone().now().offset()
+    }
+}
+
+/// A custom rtsp path of a stream
+#[derive(Debug, Deserialize, Serialize, Validate, Clone, PartialEq, Eq)]
+#[validate(schema(function = "validate_rtsp_path"))]
+pub(crate) struct RtspPathConfig {
+    /// The path after the camera name such as `low` for `/{name}/low`
+    #[validate(regex(path = *RE_RTSP_PATH, message = "Invalid rtsp path", code = "name"))]
+    #[serde(alias = "path")]
+    pub(crate) name: String,
+
+    /// The stream served at the path
+    #[serde(alias = "stream")]
+    pub(crate) kind: StreamConfig,
+
+    /// The `latency` of this path in place of the camera's
+    #[serde(default)]
+    pub(crate) latency: Option<LatencyMode>,
+}
+
+#[cfg(feature = "gstreamer")]
+impl RtspPathConfig {
+    /// The stream served at the path
+    pub(crate) fn stream_kind(&self) -> Option<StreamKind> {
+        self.kind.as_stream_kinds().first().copied()
+    }
 }
 
 #[derive(Debug, Deserialize, Serialize, Validate, Clone, PartialEq, Eq, Hash)]
@@ -254,6 +618,18 @@ pub(crate) struct MqttConfig {
     #[serde(default = "default_2000")]
     pub(crate) preview_update: u64,
 
+    /// Publish a thumbnail of the substream in `/status/thumbnail`
+    #[serde(default = "default_false")]
+    pub(crate) enable_thumbnail: bool,
+    /// Update time in ms
+    #[validate(range(
+        min = 1000,
+        message = "Update ms should be > 1000",
+        code = "thumbnail_update"
+    ))]
+    #[serde(default = "default_thumbnail_update")]
+    pub(crate) thumbnail_update: u64,
+
     /// Enable the flood light tasks status
     /// Will not do anything if no floodlight
     /// is detected
@@ -268,17 +644,42 @@ pub(crate) struct MqttConfig {
     #[serde(default = "default_2000")]
     pub(crate) floodlight_update: u64,
 
+    /// Publish the health of each stream to `/status/stream/{stream}`
+    #[serde(default = "default_true")]
+    pub(crate) enable_stream_health: bool,
+
+    /// File that each `/control/bookmark` is appended to as a line of JSON
+    #[serde(default)]
+    pub(crate) bookmarks: Option<std::path::PathBuf>,
+
+    /// File that the motion statistics are kept in so that they survive a restart
+    #[serde(default)]
+    pub(crate) motion_stats: Option<std::path::PathBuf>,
+
     #[serde(default)]
     pub(crate) discovery: Option<MqttDiscoveryConfig>,
 }
 
 #[derive(Debug, Deserialize, Serialize, Clone, Validate, PartialEq, Eq)]
 pub(crate) struct MqttDiscoveryConfig {
+    #[serde(default = "default_discovery_topic")]
     pub(crate) topic: String,
 
+    /// Defaults to those that every camera has
+    #[serde(default = "default_discovery_features")]
     pub(crate) features: HashSet<Discoveries>,
 }
 
+fn default_discovery_topic() -> String {
+    "homeassistant".to_string()
+}
+
+fn default_discovery_features() -> HashSet<Discoveries> {
+    [Discoveries::Camera, Discoveries::Motion]
+        .into_iter()
+        .collect()
+}
+
 fn validate_mqtt_server(config: &MqttServerConfig) -> Result<(), ValidationError> {
     if config.ca.is_some() && config.client_auth.is_some() {
         Err(ValidationError::new(
@@ -305,8 +706,13 @@ fn default_mqtt() -> MqttConfig {
         battery_update: 2000,
         enable_preview: true,
         preview_update: 2000,
+        enable_thumbnail: false,
+        thumbnail_update: 10000,
         enable_floodlight: true,
         floodlight_update: 2000,
+        enable_stream_health: true,
+        bookmarks: None,
+        motion_stats: None,
         discovery: Default::default(),
     }
 }
@@ -331,9 +737,21 @@ pub(crate) struct PauseConfig {
     #[serde(default = "default_on_disconnect", alias = "on_client")]
     pub(crate) on_disconnect: bool,
 
+    /// Only start the camera stream while a rtsp client is connected
+    ///
+    /// Unlike `on_disconnect` the stream is not kept running at startup to
+    /// build a buffer, which lets battery cameras sleep
+    #[serde(default = "default_on_demand")]
+    pub(crate) on_demand: bool,
+
     #[serde(default = "default_motion_timeout", alias = "timeout")]
     pub(crate) motion_timeout: f64,
 
+    /// Seconds to keep the stream after the last rtsp client disconnects
+    #[validate(range(min = 0.0, message = "Invalid linger", code = "linger"))]
+    #[serde(default = "default_linger")]
+    pub(crate) linger: f64,
+
     #[serde(default = "default_pause_mode")]
     #[validate(regex(
         path = *RE_PAUSE_MODE,
@@ -343,118 +761,1298 @@ pub(crate) struct PauseConfig {
     pub(crate) mode: String,
 }
 
-#[derive(Debug, Deserialize, Serialize, Clone, Copy, Eq, PartialEq)]
-pub(crate) enum SplashPattern {
-    #[serde(alias = "smpte")]
-    Smpte,
-    #[serde(alias = "snow")]
-    Snow,
-    #[serde(alias = "black")]
-    Black,
-    #[serde(alias = "white")]
-    White,
-    #[serde(alias = "red")]
-    Red,
-    #[serde(alias = "green")]
-    Green,
-    #[serde(alias = "blue")]
-    Blue,
-    #[serde(alias = "checkers-1")]
-    Checkers1,
-    #[serde(alias = "checkers-2")]
-    Checkers2,
-    #[serde(alias = "checkers-4")]
-    Checkers4,
-    #[serde(alias = "checkers-8")]
-    Checkers8,
-    #[serde(alias = "circular")]
-    Circular,
-    #[serde(alias = "blink")]
-    Blink,
-    #[serde(alias = "smpte75")]
-    Smpte75,
-    #[serde(alias = "zone-plate")]
-    ZonePlate,
-    #[serde(alias = "gamut")]
-    Gamut,
-    #[serde(alias = "chroma-zone-plate")]
-    ChromaZonePlate,
-    #[serde(alias = "solid-color")]
-    SolidColor,
-    #[serde(alias = "ball")]
-    Ball,
-    #[serde(alias = "smpte100")]
-    Smpte100,
-    #[serde(alias = "bar")]
-    Bar,
-    #[serde(alias = "pinwheel")]
-    Pinwheel,
-    #[serde(alias = "spokes")]
-    Spokes,
-    #[serde(alias = "gradient")]
-    Gradient,
-    #[serde(alias = "colors")]
-    Colors,
-    #[serde(alias = "smpte-rp-219")]
-    SmpteRp219,
+#[cfg(feature = "gstreamer")]
+impl PauseConfig {
+    /// True if the stream pauses while there is no rtsp client
+    pub(crate) fn on_client(&self) -> bool {
+        self.on_disconnect || self.on_demand
+    }
 }
 
-impl std::fmt::Display for SplashPattern {
-    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
-        let s = match self {
-            SplashPattern::Smpte => "smpte",
-            SplashPattern::Snow => "snow",
-            SplashPattern::Black => "black",
-            SplashPattern::White => "white",
-            SplashPattern::Red => "red",
-            SplashPattern::Green => "green",
-            SplashPattern::Blue => "blue",
-            SplashPattern::Checkers1 => "checkers-1",
-            SplashPattern::Checkers2 => "checkers-2",
-            SplashPattern::Checkers4 => "checkers-4",
-            SplashPattern::Checkers8 => "checkers-8",
-            SplashPattern::Circular => "circular",
-            SplashPattern::Blink => "blink",
-            SplashPattern::Smpte75 => "smpte75",
-            SplashPattern::ZonePlate => "zone-plate",
-            SplashPattern::Gamut => "gamut",
-            SplashPattern::ChromaZonePlate => "chroma-zone-plate",
-            SplashPattern::SolidColor => "solid-color",
-            SplashPattern::Ball => "ball",
-            SplashPattern::Smpte100 => "smpte100",
-            SplashPattern::Bar => "bar",
-            SplashPattern::Pinwheel => "pinwheel",
-            SplashPattern::Spokes => "spokes",
-            SplashPattern::Gradient => "gradient",
-            SplashPattern::Colors => "colors",
-            SplashPattern::SmpteRp219 => "smpte-rp-219",
+#[derive(Debug, Deserialize, Serialize, Validate, Clone, PartialEq, Eq)]
+#[serde(default)]
+pub(crate) struct ClockCheckConfig {
+    /// Seconds that the camera's clock can be off before it is reported
+    #[validate(range(min = 1, message = "Invalid max drift", code = "max_drift"))]
+    #[serde(alias = "drift")]
+    pub(crate) max_drift: u64,
+
+    /// Seconds between checks of the clock, 0 to only check at connect
+    pub(crate) interval: u64,
+
+    /// If true the camera's clock is set to the host's when it has drifted
+    #[serde(alias = "auto_correct")]
+    pub(crate) correct: bool,
+}
+
+impl Default for ClockCheckConfig {
+    fn default() -> Self {
+        Self {
+            max_drift: 5,
+            interval: 3600,
+            correct: false,
         }
-        .to_string();
-        write!(f, "{}", s)
     }
 }
 
-fn default_bind_addr() -> String {
-    "0.0.0.0".to_string()
+#[derive(Debug, Deserialize, Serialize, Validate, Clone, PartialEq)]
+#[validate(schema(function = "validate_reconnect"))]
+#[serde(default)]
+pub(crate) struct ReconnectConfig {
+    /// Seconds to wait before the first reconnect, this doubles on each attempt
+    pub(crate) backoff: f64,
+
+    /// The longest wait in seconds between attempts
+    pub(crate) max_backoff: f64,
+
+    /// Fraction of the wait that is randomly added or taken away so
+    /// that cameras on the same network do not all reconnect at once
+    #[validate(range(min = 0.0, max = 1.0, message = "Invalid jitter", code = "jitter"))]
+    pub(crate) jitter: f64,
+
+    /// Failed reconnects in a row before giving up on the camera, 0 to never give up
+    #[serde(alias = "retries")]
+    pub(crate) max_retries: u32,
+
+    /// Seconds a connection must last before the wait is reset to `backoff`
+    #[serde(alias = "reset")]
+    pub(crate) reset_after: u64,
 }
 
-fn default_bind_port() -> u16 {
-    8554
+impl Default for ReconnectConfig {
+    fn default() -> Self {
+        Self {
+            backoff: 0.05,
+            max_backoff: 5.0,
+            jitter: 0.2,
+            max_retries: 0,
+            reset_after: 60,
+        }
+    }
 }
 
-fn default_stream() -> StreamConfig {
-    StreamConfig::All
+#[derive(Debug, Deserialize, Serialize, Validate, Clone, PartialEq, Eq)]
+#[serde(default)]
+pub(crate) struct QualityConfig {
+    /// If true a camera on a poor connection is reconnected over
+    /// a better transport as soon as one is found
+    #[serde(alias = "switch")]
+    pub(crate) auto_switch: bool,
+
+    /// Score out of 100 below which the connection is considered poor
+    #[validate(range(min = 0, max = 100, message = "Invalid score", code = "min_score"))]
+    pub(crate) min_score: u8,
+
+    /// Seconds between probes for a better transport while the connection is poor
+    #[serde(alias = "interval")]
+    pub(crate) probe_interval: u64,
 }
 
-fn default_certificate() -> Option<String> {
-    None
+impl Default for QualityConfig {
+    fn default() -> Self {
+        Self {
+            auto_switch: false,
+            min_score: 50,
+            probe_interval: 300,
+        }
+    }
 }
 
-fn default_tls_client_auth() -> String {
-    "none".to_string()
+#[derive(Debug, Deserialize, Serialize, Validate, Clone, PartialEq)]
+#[serde(default)]
+pub(crate) struct StreamHealthConfig {
+    /// Fraction of the stream's nominal bitrate below which the
+    /// bitrate is considered to have dropped
+    #[validate(range(
+        min = 0.0,
+        max = 1.0,
+        message = "Invalid min bitrate",
+        code = "min_bitrate"
+    ))]
+    pub(crate) min_bitrate: f64,
+
+    /// Seconds over which the bitrate is measured
+    #[validate(range(min = 1, message = "Invalid bitrate window", code = "window"))]
+    pub(crate) window: u64,
 }
 
-fn default_tokio_console() -> bool {
+impl Default for StreamHealthConfig {
+    fn default() -> Self {
+        Self {
+            min_bitrate: 0.1,
+            window: 30,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, Validate, Clone, PartialEq)]
+#[serde(default)]
+pub(crate) struct IdlePolicyConfig {
+    /// Never start a stream so that only control messages are sent to the camera
+    pub(crate) control_only: bool,
+
+    /// Seconds between the pings that keep the connection alive
+    #[validate(range(min = 1, max = 300, message = "Invalid keepalive", code = "keepalive"))]
+    pub(crate) keepalive: u64,
+
+    /// Seconds after the last use that an `idle_disconnect` camera is disconnected
+    #[validate(range(min = 1, message = "Invalid idle timeout", code = "timeout"))]
+    pub(crate) timeout: u64,
+}
+
+impl Default for IdlePolicyConfig {
+    fn default() -> Self {
+        Self {
+            control_only: false,
+            keepalive: 5,
+            timeout: 30,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, Validate, Clone, PartialEq)]
+#[serde(default)]
+pub(crate) struct CommandQueueConfig {
+    /// Seconds that a command waits for the ones before it before it fails
+    #[validate(range(min = 1, message = "Invalid queue wait", code = "wait"))]
+    pub(crate) wait: u64,
+
+    /// Seconds that a command may run for before it fails and the next one starts
+    #[validate(range(min = 1, message = "Invalid command timeout", code = "timeout"))]
+    pub(crate) timeout: u64,
+}
+
+impl Default for CommandQueueConfig {
+    fn default() -> Self {
+        Self {
+            wait: 60,
+            timeout: 30,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, Validate, Clone, PartialEq, Default)]
+#[serde(default)]
+pub(crate) struct ConnectConfig {
+    /// Seconds between the start of each camera's connection, 0 to connect all at once
+    #[validate(range(min = 0.0, message = "Invalid connect stagger", code = "stagger"))]
+    pub(crate) stagger: f64,
+
+    /// The most cameras at one address or uid that connect at the same time, 0 for no limit
+    ///
+    /// This is mostly for the channels of an NVR
+    #[serde(alias = "per_host")]
+    pub(crate) max_per_host: usize,
+}
+
+#[derive(Debug, Deserialize, Serialize, Validate, Clone, PartialEq)]
+#[serde(default)]
+pub(crate) struct OnvifConfig {
+    /// The port of the ONVIF device and media services
+    pub(crate) port: u16,
+
+    /// The address that NVRs are told to use for neolink, the local address by default
+    pub(crate) address: Option<String>,
+}
+
+impl Default for OnvifConfig {
+    fn default() -> Self {
+        Self {
+            port: 8000,
+            address: None,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, Validate, Clone, PartialEq)]
+#[serde(default)]
+pub(crate) struct HttpConfig {
+    /// The address to listen on
+    pub(crate) bind: String,
+
+    pub(crate) port: u16,
+}
+
+impl Default for HttpConfig {
+    fn default() -> Self {
+        Self {
+            bind: "0.0.0.0".to_string(),
+            port: 8080,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, Validate, Clone, PartialEq)]
+#[serde(default)]
+pub(crate) struct WebrtcConfig {
+    /// The address to listen on
+    pub(crate) bind: String,
+
+    pub(crate) port: u16,
+
+    /// A STUN server such as `stun://stun.l.google.com:19302` for browsers
+    /// that are not on the same network
+    #[serde(alias = "stun")]
+    pub(crate) stun_server: Option<String>,
+}
+
+impl Default for WebrtcConfig {
+    fn default() -> Self {
+        Self {
+            bind: "0.0.0.0".to_string(),
+            port: 8889,
+            stun_server: None,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, Validate, Clone, PartialEq)]
+#[serde(default)]
+pub(crate) struct HlsConfig {
+    /// The address to listen on
+    pub(crate) bind: String,
+
+    pub(crate) port: u16,
+
+    /// Directory that the segments are written to while they are served, a
+    /// `neolink-hls` directory in the temp directory when not given
+    pub(crate) path: Option<std::path::PathBuf>,
+
+    /// The shortest segment in seconds, a segment ends on the first keyframe
+    /// after this
+    #[validate(range(min = 1, max = 10, message = "Invalid hls segment", code = "segment"))]
+    pub(crate) segment: u32,
+
+    /// Segments in the playlist
+    #[validate(range(
+        min = 2,
+        max = 20,
+        message = "Invalid hls playlist length",
+        code = "playlist_length"
+    ))]
+    pub(crate) playlist_length: u32,
+}
+
+impl Default for HlsConfig {
+    fn default() -> Self {
+        Self {
+            bind: "0.0.0.0".to_string(),
+            port: 8888,
+            path: None,
+            segment: 1,
+            playlist_length: 3,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, Validate, Clone, PartialEq)]
+#[serde(default)]
+pub(crate) struct ServeConfig {
+    /// Serve the cameras over rtsp
+    pub(crate) rtsp: bool,
+
+    /// Bridge the cameras to the broker of the `[mqtt]` config when there is one
+    pub(crate) mqtt: bool,
+
+    /// Record the cameras that have a `[cameras.record]`
+    pub(crate) record: bool,
+
+    /// Serve the ONVIF services of `[onvif]`
+    pub(crate) onvif: bool,
+
+    /// Serve the still images of `[http]`
+    pub(crate) http: bool,
+
+    /// Serve the cameras to browsers with the WHEP server of `[webrtc]`
+    pub(crate) webrtc: bool,
+
+    /// Serve the cameras as HLS with the server of `[hls]`
+    pub(crate) hls: bool,
+
+    /// Take the snapshots of the cameras that have a `[cameras.timelapse]`
+    pub(crate) timelapse: bool,
+}
+
+impl Default for ServeConfig {
+    fn default() -> Self {
+        Self {
+            rtsp: true,
+            mqtt: true,
+            record: true,
+            onvif: false,
+            http: false,
+            webrtc: false,
+            hls: false,
+            timelapse: true,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, Validate, Clone, PartialEq)]
+#[serde(default)]
+pub(crate) struct ControlConfig {
+    /// The address to listen on, keep it local as it is not authenticated
+    pub(crate) bind: String,
+
+    pub(crate) port: u16,
+
+    /// If true one-shot commands such as `neolink reboot` are run by the
+    /// running neolink over its camera connection instead of logging in again
+    #[serde(alias = "forward")]
+    pub(crate) share: bool,
+}
+
+impl Default for ControlConfig {
+    fn default() -> Self {
+        Self {
+            bind: "127.0.0.1".to_string(),
+            port: 8557,
+            share: true,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, Validate, Clone, PartialEq)]
+#[serde(default)]
+pub(crate) struct MetricsConfig {
+    /// The address and port to serve `/metrics` and `/health` on
+    #[validate(custom(function = "validate_bind"))]
+    pub(crate) bind: String,
+}
+
+impl Default for MetricsConfig {
+    fn default() -> Self {
+        Self {
+            bind: "0.0.0.0:9898".to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, Validate, Clone, PartialEq)]
+#[serde(default)]
+pub(crate) struct RotationConfig {
+    /// Size in MiB that a file is rotated at, 0 to never rotate
+    #[serde(alias = "size")]
+    pub(crate) max_size: u64,
+
+    /// How many rotated files are kept, the oldest is removed after this
+    #[validate(range(min = 1, max = 100, message = "Invalid rotation keep", code = "keep"))]
+    #[serde(alias = "files")]
+    pub(crate) keep: usize,
+
+    /// How the rotated files are compressed
+    pub(crate) compress: Compression,
+}
+
+impl Default for RotationConfig {
+    fn default() -> Self {
+        Self {
+            max_size: 0,
+            keep: 5,
+            compress: Compression::None,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, Eq, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum Compression {
+    #[serde(alias = "off")]
+    None,
+    /// `.gz` that any system can open
+    #[serde(alias = "gz")]
+    Gzip,
+    /// `.zst` that is smaller and faster
+    #[serde(alias = "zst")]
+    Zstd,
+}
+
+#[derive(Debug, Deserialize, Serialize, Validate, Clone, PartialEq)]
+pub(crate) struct AuditConfig {
+    /// The file that each control action is appended to as a line of JSON
+    #[serde(alias = "file")]
+    pub(crate) path: std::path::PathBuf,
+}
+
+#[derive(Debug, Deserialize, Serialize, Validate, Clone, PartialEq)]
+pub(crate) struct ClipConfig {
+    /// The name that the clip is played by such as `dog_bark`
+    #[validate(length(min = 1, message = "Invalid clip name", code = "name"))]
+    pub(crate) name: String,
+
+    /// An audio file in any format that gstreamer can decode
+    #[serde(alias = "file")]
+    pub(crate) path: std::path::PathBuf,
+
+    /// The volume of the clip where 1.0 is unchanged
+    #[validate(range(min = 0.0, max = 10.0, message = "Invalid clip volume", code = "volume"))]
+    #[serde(default = "default_clip_volume")]
+    pub(crate) volume: f32,
+}
+
+#[derive(Debug, Deserialize, Serialize, Validate, Clone, PartialEq)]
+#[serde(default)]
+pub(crate) struct AlarmConfig {
+    /// The cameras that take part in the alarm, all cameras when empty
+    pub(crate) cameras: Vec<String>,
+
+    /// Sound the siren until the alarm is turned off
+    pub(crate) siren: bool,
+
+    /// Turn on the floodlight at this brightness of 0-100% until the alarm is
+    /// turned off, the floodlight is left alone when not given
+    #[validate(range(max = 100, message = "Invalid brightness", code = "floodlight"))]
+    #[serde(alias = "brightness")]
+    pub(crate) floodlight: Option<u32>,
+
+    /// Record the main stream to the SD card regardless of motion until the
+    /// alarm is turned off
+    pub(crate) record: bool,
+
+    /// Publish the alarm state to `neolink/status/alarm` and the
+    /// `/status/alarm` of each camera
+    #[serde(alias = "notifications")]
+    pub(crate) notify: bool,
+}
+
+impl Default for AlarmConfig {
+    fn default() -> Self {
+        Self {
+            cameras: vec![],
+            siren: true,
+            floodlight: Some(100),
+            record: true,
+            notify: true,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, Eq, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum EventLogTarget {
+    /// RFC 5424 messages with the fields as structured data
+    Syslog,
+    /// The native journald protocol with the fields as journal fields
+    #[serde(alias = "journal", alias = "systemd")]
+    Journald,
+}
+
+#[derive(Debug, Deserialize, Serialize, Validate, Clone, PartialEq)]
+#[serde(default)]
+pub(crate) struct EventLogConfig {
+    /// Send the events to `syslog` or `journald`
+    pub(crate) target: EventLogTarget,
+
+    /// A unix socket such as `/dev/log` or the `host:port` of a UDP syslog
+    /// server, the local syslog or journald socket when not given
+    pub(crate) address: Option<String>,
+
+    /// The syslog facility such as `daemon` or `local0`
+    #[validate(regex(
+        path = *RE_SYSLOG_FACILITY,
+        message = "Incorrect syslog facility",
+        code = "facility"
+    ))]
+    pub(crate) facility: String,
+
+    /// The app name of the syslog messages and `SYSLOG_IDENTIFIER` of the journal
+    #[validate(length(min = 1, max = 48, message = "Invalid ident", code = "ident"))]
+    #[serde(alias = "app_name")]
+    pub(crate) ident: String,
+
+    /// Only send the events of these cameras, all cameras when empty
+    pub(crate) cameras: Vec<String>,
+}
+
+impl Default for EventLogConfig {
+    fn default() -> Self {
+        Self {
+            target: EventLogTarget::Syslog,
+            address: None,
+            facility: "daemon".to_string(),
+            ident: "neolink".to_string(),
+            cameras: vec![],
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, Validate, Clone, PartialEq)]
+#[serde(default)]
+pub(crate) struct WebhookConfig {
+    /// The urls that each event is posted to as JSON
+    #[validate(length(min = 1, message = "No webhook urls", code = "urls"))]
+    #[serde(alias = "url")]
+    pub(crate) urls: Vec<String>,
+
+    /// Only post the events of these cameras, all cameras when empty
+    pub(crate) cameras: Vec<String>,
+
+    /// Add a base64 jpeg snapshot to the motion start events
+    pub(crate) snapshot: bool,
+
+    /// Extra headers of the requests such as `Authorization`
+    pub(crate) headers: HashMap<String, String>,
+
+    /// Seconds to wait for the server to reply
+    #[validate(range(min = 1, message = "Invalid webhook timeout", code = "timeout"))]
+    pub(crate) timeout: u64,
+}
+
+impl Default for WebhookConfig {
+    fn default() -> Self {
+        Self {
+            urls: vec![],
+            cameras: vec![],
+            snapshot: false,
+            headers: Default::default(),
+            timeout: 10,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, Validate, Clone, PartialEq, Default)]
+#[validate(schema(function = "validate_retry"))]
+pub(crate) struct RetryConfig {
+    /// The policy of every message not listed in `messages`
+    #[serde(flatten)]
+    pub(crate) default: RetryPolicy,
+
+    /// Policies of single messages by their name such as `Snap` or `PtzControl`
+    #[serde(default)]
+    pub(crate) messages: HashMap<String, RetryPolicy>,
+}
+
+impl RetryConfig {
+    /// The policy of the message with this ID
+    pub(crate) fn policy(&self, msg_id: u32) -> &RetryPolicy {
+        msg_id_name(msg_id)
+            .and_then(|name| self.messages.get(name))
+            .unwrap_or(&self.default)
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+#[serde(default)]
+pub(crate) struct RetryPolicy {
+    /// The number of times the message is sent before the error is returned
+    pub(crate) attempts: u32,
+
+    /// Seconds to wait before the first retry, this doubles on each retry
+    pub(crate) backoff: f64,
+
+    /// The longest wait in seconds between retries
+    pub(crate) max_backoff: f64,
+
+    /// The error codes from the camera that are retried
+    pub(crate) codes: Vec<u16>,
+}
+
+impl RetryPolicy {
+    /// The wait before the given retry, the first retry is 1
+    pub(crate) fn delay(&self, retry: u32) -> std::time::Duration {
+        let secs = self.backoff * 2f64.powi(retry.saturating_sub(1).min(16) as i32);
+        std::time::Duration::from_secs_f64(secs.min(self.max_backoff).max(0.0))
+    }
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            attempts: 5,
+            backoff: 1.0,
+            max_backoff: 1.0,
+            // The camera is not ready yet, usually while starting up
+            codes: vec![400],
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, Validate, Clone, PartialEq)]
+#[validate(schema(function = "validate_digital_zoom"))]
+pub(crate) struct DigitalZoomConfig {
+    /// The stream to crop
+    #[serde(default = "default_zoom_stream")]
+    pub(crate) stream: StreamConfig,
+
+    /// Left edge of the crop as a fraction of the frame width
+    #[validate(range(min = 0.0, max = 1.0, message = "Invalid zoom x", code = "x"))]
+    #[serde(default)]
+    pub(crate) x: f64,
+
+    /// Top edge of the crop as a fraction of the frame height
+    #[validate(range(min = 0.0, max = 1.0, message = "Invalid zoom y", code = "y"))]
+    #[serde(default)]
+    pub(crate) y: f64,
+
+    /// Width of the crop as a fraction of the frame width
+    #[validate(range(min = 0.01, max = 1.0, message = "Invalid zoom width", code = "width"))]
+    #[serde(default = "default_zoom_size")]
+    pub(crate) width: f64,
+
+    /// Height of the crop as a fraction of the frame height
+    #[validate(range(
+        min = 0.01,
+        max = 1.0,
+        message = "Invalid zoom height",
+        code = "height"
+    ))]
+    #[serde(default = "default_zoom_size")]
+    pub(crate) height: f64,
+
+    /// Width in pixels of the served stream, defaults to the width of the crop
+    #[serde(default)]
+    pub(crate) output_width: Option<u32>,
+
+    /// Height in pixels of the served stream, defaults to the height of the crop
+    #[serde(default)]
+    pub(crate) output_height: Option<u32>,
+}
+
+impl DigitalZoomConfig {
+    pub(crate) fn rect(&self) -> ZoomRect {
+        ZoomRect {
+            x: self.x,
+            y: self.y,
+            width: self.width,
+            height: self.height,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, Validate, Clone, PartialEq)]
+#[serde(default)]
+pub(crate) struct CodecFallbackConfig {
+    /// The streams that a client of the main stream is moved through in order
+    #[validate(length(min = 1, message = "Invalid codec priority", code = "priority"))]
+    #[serde(alias = "codecs")]
+    pub(crate) priority: Vec<CodecFallback>,
+
+    /// Failed plays before a client is moved to the next stream
+    #[validate(range(min = 1, message = "Invalid fallback failures", code = "failures"))]
+    #[serde(alias = "retries")]
+    pub(crate) failures: u32,
+
+    /// Seconds that a client must play for before it is not counted as a failure
+    #[validate(range(min = 1, message = "Invalid fallback min_play", code = "min_play"))]
+    pub(crate) min_play: u64,
+}
+
+impl Default for CodecFallbackConfig {
+    fn default() -> Self {
+        Self {
+            priority: vec![CodecFallback::H265, CodecFallback::H264, CodecFallback::Sub],
+            failures: 3,
+            min_play: 10,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, Eq, PartialEq)]
+pub(crate) enum CodecFallback {
+    /// The main stream as the camera sends it
+    #[serde(alias = "h265", alias = "hevc", alias = "main")]
+    H265,
+    /// The main stream re-encoded as H264 at `/{name}/h264`
+    #[serde(alias = "h264", alias = "transcode")]
+    H264,
+    /// The substream
+    #[serde(alias = "sub", alias = "substream")]
+    Sub,
+}
+
+#[derive(Debug, Deserialize, Serialize, Validate, Clone, PartialEq)]
+#[validate(schema(function = "validate_record"))]
+pub(crate) struct RecordConfig {
+    /// Directory that the segments are written to
+    pub(crate) path: std::path::PathBuf,
+
+    /// The stream to record
+    #[serde(default = "default_record_stream")]
+    pub(crate) stream: StreamConfig,
+
+    /// Seconds of video in each segment
+    #[validate(range(
+        min = 10,
+        max = 86400,
+        message = "Invalid record segment",
+        code = "segment"
+    ))]
+    #[serde(default = "default_record_segment")]
+    pub(crate) segment: u64,
+
+    /// Hours that segments are kept for, 0 to keep them all
+    #[serde(default)]
+    pub(crate) retention: u64,
+
+    /// The container of the segments
+    #[serde(default)]
+    pub(crate) format: RecordFormat,
+
+    /// Record the audio when the stream has AAC audio
+    #[serde(default = "default_true")]
+    pub(crate) audio: bool,
+}
+
+#[derive(Debug, Deserialize, Serialize, Validate, Clone, PartialEq)]
+pub(crate) struct TimelapseConfig {
+    /// Directory that the snapshots and videos are written to
+    pub(crate) path: std::path::PathBuf,
+
+    /// Minutes between the snapshots
+    #[validate(range(
+        min = 1,
+        max = 1440,
+        message = "Invalid timelapse interval",
+        code = "interval"
+    ))]
+    #[serde(default = "default_timelapse_interval")]
+    pub(crate) interval: u64,
+
+    /// Decode the snapshot from a keyframe of the main stream rather than
+    /// using the snap command, for cameras without it
+    #[serde(default = "default_false")]
+    pub(crate) use_stream: bool,
+
+    /// Assemble each day's snapshots into a video after midnight
+    #[serde(default = "default_false", alias = "daily_video")]
+    pub(crate) video: bool,
+
+    /// Frames per second of the daily video
+    #[validate(range(min = 1, max = 60, message = "Invalid timelapse fps", code = "fps"))]
+    #[serde(default = "default_timelapse_fps")]
+    pub(crate) fps: u32,
+
+    /// Remove the day's snapshots once its video is made
+    #[serde(default = "default_false")]
+    pub(crate) remove_snapshots: bool,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, Eq, PartialEq, Default)]
+pub(crate) enum RecordFormat {
+    /// Matroska, playable up to the last frame if neolink is stopped
+    #[default]
+    #[serde(alias = "mkv", alias = "matroska")]
+    Mkv,
+    #[serde(alias = "mp4")]
+    Mp4,
+}
+
+#[cfg(feature = "gstreamer")]
+impl RecordFormat {
+    pub(crate) fn extension(&self) -> &'static str {
+        match self {
+            RecordFormat::Mkv => "mkv",
+            RecordFormat::Mp4 => "mp4",
+        }
+    }
+
+    pub(crate) fn muxer(&self) -> &'static str {
+        match self {
+            RecordFormat::Mkv => "matroskamux",
+            RecordFormat::Mp4 => "mp4mux",
+        }
+    }
+}
+
+/// The part of the frame shown by the digital zoom, as fractions of the frame size
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct ZoomRect {
+    pub(crate) x: f64,
+    pub(crate) y: f64,
+    pub(crate) width: f64,
+    pub(crate) height: f64,
+}
+
+impl Default for ZoomRect {
+    fn default() -> Self {
+        Self {
+            x: 0.0,
+            y: 0.0,
+            width: 1.0,
+            height: 1.0,
+        }
+    }
+}
+
+impl ZoomRect {
+    /// Parse a rect from the space seperated `x y width height`
+    pub(crate) fn parse(s: &str) -> Option<Self> {
+        let values = s
+            .split_whitespace()
+            .map(|v| v.parse::<f64>().ok())
+            .collect::<Option<Vec<_>>>()?;
+        let [x, y, width, height] = values[..] else {
+            return None;
+        };
+        let rect = Self {
+            x,
+            y,
+            width,
+            height,
+        };
+        rect.is_valid().then_some(rect)
+    }
+
+    /// True if the rect is inside the frame and not empty
+    pub(crate) fn is_valid(&self) -> bool {
+        self.x >= 0.0
+            && self.y >= 0.0
+            && self.width > 0.0
+            && self.height > 0.0
+            && self.x + self.width <= 1.0
+            && self.y + self.height <= 1.0
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, Validate, Clone, PartialEq, Eq)]
+pub(crate) struct OverlayConfig {
+    /// The streams that the overlay is drawn on
+    #[serde(default = "default_overlay_streams")]
+    pub(crate) streams: StreamConfig,
+
+    /// Text to draw, `{name}` and `{battery}` are replaced with
+    /// the camera name and battery percentage
+    #[serde(default)]
+    pub(crate) text: Option<String>,
+
+    /// Draw the time after the text
+    #[serde(default, alias = "clock")]
+    pub(crate) timestamp: bool,
+
+    /// strftime format of the time
+    #[serde(default = "default_overlay_time_format")]
+    pub(crate) time_format: String,
+
+    /// Where the text is drawn
+    #[serde(default = "default_overlay_position")]
+    #[validate(regex(
+        path = *RE_OVERLAY_POSITION,
+        message = "Incorrect overlay position",
+        code = "position"
+    ))]
+    pub(crate) position: String,
+
+    /// Font of the text
+    #[serde(default = "default_overlay_font")]
+    pub(crate) font: String,
+
+    /// Path to an image to draw as a watermark
+    #[serde(default, alias = "watermark")]
+    pub(crate) image: Option<std::path::PathBuf>,
+
+    /// Pixels from the left of the frame to the watermark
+    #[serde(default = "default_overlay_offset")]
+    pub(crate) image_x: i32,
+
+    /// Pixels from the top of the frame to the watermark
+    #[serde(default = "default_overlay_offset")]
+    pub(crate) image_y: i32,
+}
+
+#[cfg(feature = "gstreamer")]
+impl OverlayConfig {
+    /// True if this overlay is drawn on the given stream
+    pub(crate) fn applies_to(&self, stream: StreamKind) -> bool {
+        self.streams.as_stream_kinds().contains(&stream)
+    }
+
+    /// The `valignment` and `halignment` of the text
+    pub(crate) fn alignment(&self) -> (&str, &str) {
+        self.position.split_once('-').unwrap_or(("top", "left"))
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, Validate, Clone, PartialEq)]
+#[validate(schema(function = "validate_privacy_mask"))]
+pub(crate) struct PrivacyMaskConfig {
+    /// The streams that the mask is drawn on
+    #[serde(default = "default_overlay_streams")]
+    pub(crate) streams: StreamConfig,
+
+    /// Left edge of the mask as a fraction of the frame width
+    #[validate(range(min = 0.0, max = 1.0, message = "Invalid mask x", code = "x"))]
+    pub(crate) x: f64,
+
+    /// Top edge of the mask as a fraction of the frame height
+    #[validate(range(min = 0.0, max = 1.0, message = "Invalid mask y", code = "y"))]
+    pub(crate) y: f64,
+
+    /// Width of the mask as a fraction of the frame width
+    #[validate(range(min = 0.0, max = 1.0, message = "Invalid mask width", code = "width"))]
+    pub(crate) width: f64,
+
+    /// Height of the mask as a fraction of the frame height
+    #[validate(range(min = 0.0, max = 1.0, message = "Invalid mask height", code = "height"))]
+    pub(crate) height: f64,
+
+    /// How the masked area is hidden
+    #[serde(default)]
+    pub(crate) style: PrivacyMaskStyle,
+}
+
+impl PrivacyMaskConfig {
+    pub(crate) fn rect(&self) -> ZoomRect {
+        ZoomRect {
+            x: self.x,
+            y: self.y,
+            width: self.width,
+            height: self.height,
+        }
+    }
+
+    /// True if this mask is drawn on the given stream
+    #[cfg(feature = "gstreamer")]
+    pub(crate) fn applies_to(&self, stream: StreamKind) -> bool {
+        self.streams.as_stream_kinds().contains(&stream)
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, Validate, Clone, PartialEq)]
+pub(crate) struct SoftwareMotionConfig {
+    /// Frames per second that are compared
+    #[validate(range(min = 0.1, max = 15.0, message = "Invalid motion fps", code = "fps"))]
+    #[serde(default = "default_software_motion_fps")]
+    pub(crate) fps: f64,
+
+    /// From 1 where a tenth of the frame must change to 100 where
+    /// a thousandth must
+    #[validate(range(
+        min = 1,
+        max = 100,
+        message = "Invalid motion sensitivity",
+        code = "sensitivity"
+    ))]
+    #[serde(default = "default_software_motion_sensitivity")]
+    pub(crate) sensitivity: u8,
+
+    /// Seconds without motion before the motion stops
+    #[serde(default = "default_software_motion_hold")]
+    pub(crate) hold: f64,
+
+    /// Areas where changes are not motion such as trees or a road
+    #[validate]
+    #[serde(default)]
+    pub(crate) ignore: Vec<MotionAreaConfig>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Validate, Clone, PartialEq)]
+pub(crate) struct ObjectDetectionConfig {
+    /// The detection endpoint of a DeepStack compatible server
+    /// e.g. `http://127.0.0.1:5000/v1/vision/detection`
+    #[validate(url(message = "Invalid object detection url", code = "url"))]
+    pub(crate) url: String,
+
+    /// Sent as the `api_key` form field if the server needs one
+    #[serde(default)]
+    pub(crate) api_key: Option<String>,
+
+    /// Objects below this confidence are dropped
+    #[validate(range(
+        min = 0.0,
+        max = 1.0,
+        message = "Invalid object detection confidence",
+        code = "min_confidence"
+    ))]
+    #[serde(default = "default_detection_confidence")]
+    pub(crate) min_confidence: f64,
+
+    /// Only report these labels, all labels if empty
+    #[serde(default)]
+    pub(crate) labels: Vec<String>,
+
+    /// Seconds to wait for the server
+    #[serde(default = "default_detection_timeout")]
+    pub(crate) timeout: u64,
+}
+
+#[derive(Debug, Deserialize, Serialize, Validate, Clone, PartialEq)]
+#[validate(schema(function = "validate_motion_area"))]
+pub(crate) struct MotionAreaConfig {
+    /// Left edge of the area as a fraction of the frame width
+    pub(crate) x: f64,
+    /// Top edge of the area as a fraction of the frame height
+    pub(crate) y: f64,
+    /// Width of the area as a fraction of the frame width
+    pub(crate) width: f64,
+    /// Height of the area as a fraction of the frame height
+    pub(crate) height: f64,
+}
+
+impl MotionAreaConfig {
+    pub(crate) fn rect(&self) -> ZoomRect {
+        ZoomRect {
+            x: self.x,
+            y: self.y,
+            width: self.width,
+            height: self.height,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, Eq, PartialEq, Default)]
+pub(crate) enum PrivacyMaskStyle {
+    /// A solid black box
+    #[default]
+    #[serde(alias = "black")]
+    Black,
+    /// A coarse mosaic of the area
+    #[serde(alias = "blur", alias = "pixelate")]
+    Blur,
+}
+
+/// How the audio of the camera is served over rtsp
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, Eq, PartialEq, Default)]
+pub(crate) enum AudioMode {
+    /// Decoded to raw PCM that every client can play
+    #[default]
+    #[serde(rename = "transcode:pcm", alias = "pcm")]
+    Pcm,
+    /// The camera's AAC as it is. ADPCM has no RTP payload so it is still decoded
+    #[serde(rename = "passthru", alias = "passthrough")]
+    Passthru,
+    /// Encoded to Opus that browsers and WebRTC gateways can play
+    #[serde(rename = "transcode:opus", alias = "opus")]
+    Opus,
+    /// No audio track, the same as muting every stream
+    #[serde(rename = "off")]
+    Off,
+}
+
+/// How the rtsp streams trade delay for smoothness
+///
+/// Each is a preset of the buffering, the jitter buffer and where a new
+/// client starts in the stream
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, Eq, PartialEq, Default)]
+pub(crate) enum LatencyMode {
+    /// The least delay, the picture may stutter on a poor network
+    #[serde(rename = "realtime", alias = "low")]
+    Realtime,
+    /// A short buffer that hides most network jitter
+    #[default]
+    #[serde(rename = "balanced")]
+    Balanced,
+    /// A longer buffer for an unreliable network, such as over UDP or wifi
+    #[serde(rename = "smooth", alias = "high")]
+    Smooth,
+}
+
+#[cfg(feature = "gstreamer")]
+impl LatencyMode {
+    /// If a new client starts with the frames buffered over the last
+    /// `buffer_duration`, otherwise it starts at the last keyframe
+    pub(crate) fn replay_buffer(&self) -> bool {
+        !matches!(self, LatencyMode::Realtime)
+    }
+
+    /// If the stream waits for its buffer to fill before playing and pauses
+    /// when it runs low
+    pub(crate) fn jitter_buffer(&self) -> bool {
+        !matches!(self, LatencyMode::Realtime)
+    }
+
+    /// How many times the usual size the gstreamer buffers are
+    pub(crate) fn buffer_scale(&self) -> u32 {
+        match self {
+            LatencyMode::Realtime | LatencyMode::Balanced => 1,
+            LatencyMode::Smooth => 4,
+        }
+    }
+
+    /// The most that a queue holds, a `realtime` queue drops the oldest
+    /// frames past this rather than falling behind
+    pub(crate) fn queue_time(&self) -> std::time::Duration {
+        match self {
+            LatencyMode::Realtime => std::time::Duration::from_secs(1),
+            LatencyMode::Balanced => std::time::Duration::from_secs(5),
+            LatencyMode::Smooth => std::time::Duration::from_secs(15),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, Eq, PartialEq)]
+pub(crate) enum SplashPattern {
+    #[serde(alias = "smpte")]
+    Smpte,
+    #[serde(alias = "snow")]
+    Snow,
+    #[serde(alias = "black")]
+    Black,
+    #[serde(alias = "white")]
+    White,
+    #[serde(alias = "red")]
+    Red,
+    #[serde(alias = "green")]
+    Green,
+    #[serde(alias = "blue")]
+    Blue,
+    #[serde(alias = "checkers-1")]
+    Checkers1,
+    #[serde(alias = "checkers-2")]
+    Checkers2,
+    #[serde(alias = "checkers-4")]
+    Checkers4,
+    #[serde(alias = "checkers-8")]
+    Checkers8,
+    #[serde(alias = "circular")]
+    Circular,
+    #[serde(alias = "blink")]
+    Blink,
+    #[serde(alias = "smpte75")]
+    Smpte75,
+    #[serde(alias = "zone-plate")]
+    ZonePlate,
+    #[serde(alias = "gamut")]
+    Gamut,
+    #[serde(alias = "chroma-zone-plate")]
+    ChromaZonePlate,
+    #[serde(alias = "solid-color")]
+    SolidColor,
+    #[serde(alias = "ball")]
+    Ball,
+    #[serde(alias = "smpte100")]
+    Smpte100,
+    #[serde(alias = "bar")]
+    Bar,
+    #[serde(alias = "pinwheel")]
+    Pinwheel,
+    #[serde(alias = "spokes")]
+    Spokes,
+    #[serde(alias = "gradient")]
+    Gradient,
+    #[serde(alias = "colors")]
+    Colors,
+    #[serde(alias = "smpte-rp-219")]
+    SmpteRp219,
+}
+
+impl std::fmt::Display for SplashPattern {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let s = match self {
+            SplashPattern::Smpte => "smpte",
+            SplashPattern::Snow => "snow",
+            SplashPattern::Black => "black",
+            SplashPattern::White => "white",
+            SplashPattern::Red => "red",
+            SplashPattern::Green => "green",
+            SplashPattern::Blue => "blue",
+            SplashPattern::Checkers1 => "checkers-1",
+            SplashPattern::Checkers2 => "checkers-2",
+            SplashPattern::Checkers4 => "checkers-4",
+            SplashPattern::Checkers8 => "checkers-8",
+            SplashPattern::Circular => "circular",
+            SplashPattern::Blink => "blink",
+            SplashPattern::Smpte75 => "smpte75",
+            SplashPattern::ZonePlate => "zone-plate",
+            SplashPattern::Gamut => "gamut",
+            SplashPattern::ChromaZonePlate => "chroma-zone-plate",
+            SplashPattern::SolidColor => "solid-color",
+            SplashPattern::Ball => "ball",
+            SplashPattern::Smpte100 => "smpte100",
+            SplashPattern::Bar => "bar",
+            SplashPattern::Pinwheel => "pinwheel",
+            SplashPattern::Spokes => "spokes",
+            SplashPattern::Gradient => "gradient",
+            SplashPattern::Colors => "colors",
+            SplashPattern::SmpteRp219 => "smpte-rp-219",
+        }
+        .to_string();
+        write!(f, "{}", s)
+    }
+}
+
+fn default_bind_addr() -> String {
+    "0.0.0.0".to_string()
+}
+
+fn default_bind_port() -> u16 {
+    8554
+}
+
+fn default_stream() -> StreamConfig {
+    StreamConfig::All
+}
+
+fn default_zoom_stream() -> StreamConfig {
+    StreamConfig::Main
+}
+
+fn default_mute() -> StreamConfig {
+    StreamConfig::None
+}
+
+fn default_clip_volume() -> f32 {
+    1.0
+}
+
+fn default_busy_retry() -> u64 {
+    60
+}
+
+fn default_error_history() -> usize {
+    20
+}
+
+fn default_thumbnail_width() -> u32 {
+    320
+}
+
+fn default_thumbnail_update() -> u64 {
+    10000
+}
+
+fn default_record_stream() -> StreamConfig {
+    StreamConfig::Main
+}
+
+fn default_record_segment() -> u64 {
+    300
+}
+
+fn default_timelapse_interval() -> u64 {
+    10
+}
+
+fn default_timelapse_fps() -> u32 {
+    24
+}
+
+fn default_event_window() -> f64 {
+    2.0
+}
+
+fn default_overlay_streams() -> StreamConfig {
+    StreamConfig::All
+}
+
+fn default_overlay_time_format() -> String {
+    "%Y-%m-%d %H:%M:%S".to_string()
+}
+
+fn default_overlay_position() -> String {
+    "top-left".to_string()
+}
+
+fn default_overlay_font() -> String {
+    "Sans, 16".to_string()
+}
+
+fn default_overlay_offset() -> i32 {
+    16
+}
+
+fn default_zoom_size() -> f64 {
+    1.0
+}
+
+fn default_certificate() -> Option<String> {
+    None
+}
+
+fn default_tls_client_auth() -> String {
+    "none".to_string()
+}
+
+fn default_tokio_console() -> bool {
     false
 }
 
@@ -474,6 +2072,14 @@ fn default_on_disconnect() -> bool {
     false
 }
 
+fn default_on_demand() -> bool {
+    false
+}
+
+fn default_linger() -> f64 {
+    0.
+}
+
 fn default_on_motion() -> bool {
     false
 }
@@ -490,7 +2096,9 @@ fn default_pause() -> PauseConfig {
     PauseConfig {
         on_motion: default_on_motion(),
         on_disconnect: default_on_disconnect(),
+        on_demand: default_on_demand(),
         motion_timeout: default_motion_timeout(),
+        linger: default_linger(),
         mode: default_pause_mode(),
     }
 }
@@ -507,6 +2115,26 @@ fn default_2000() -> u64 {
     2000
 }
 
+fn default_software_motion_fps() -> f64 {
+    2.0
+}
+
+fn default_software_motion_sensitivity() -> u8 {
+    50
+}
+
+fn default_software_motion_hold() -> f64 {
+    5.0
+}
+
+fn default_detection_confidence() -> f64 {
+    0.5
+}
+
+fn default_detection_timeout() -> u64 {
+    10
+}
+
 fn default_splash() -> SplashPattern {
     SplashPattern::Snow
 }
@@ -522,7 +2150,29 @@ fn validate_username(name: &str) -> Result<(), ValidationError> {
     Ok(())
 }
 
+fn validate_timezone(timezone: &str) -> Result<(), ValidationError> {
+    match Timezone::parse(timezone) {
+        Some(_) => Ok(()),
+        None => Err(ValidationError::new("Invalid timezone")),
+    }
+}
+
+fn validate_bind(bind: &str) -> Result<(), ValidationError> {
+    match bind.parse::<std::net::SocketAddr>() {
+        Ok(_) => Ok(()),
+        Err(_) => Err(ValidationError::new("Invalid bind")),
+    }
+}
+
 fn validate_camera_config(camera_config: &CameraConfig) -> Result<(), ValidationError> {
+    let mut paths = HashSet::new();
+    if !camera_config
+        .streams
+        .iter()
+        .all(|path| paths.insert(path.name.as_str()))
+    {
+        return Err(ValidationError::new("The rtsp stream paths must be unique"));
+    }
     match (&camera_config.camera_addr, &camera_config.camera_uid) {
         (None, None) => Err(ValidationError::new(
             "Either camera address or uid must be given",
@@ -530,3 +2180,194 @@ fn validate_camera_config(camera_config: &CameraConfig) -> Result<(), Validation
         _ => Ok(()),
     }
 }
+
+fn validate_rtsp_path(config: &RtspPathConfig) -> Result<(), ValidationError> {
+    if config.kind.as_stream_kinds().len() != 1 {
+        return Err(ValidationError::new(
+            "The rtsp path stream must be one of main, sub or extern",
+        ));
+    }
+    Ok(())
+}
+
+fn validate_retry(config: &RetryConfig) -> Result<(), ValidationError> {
+    for name in config.messages.keys() {
+        if !MSG_ID_NAMES
+            .iter()
+            .any(|(_, known)| *known == name.as_str())
+        {
+            return Err(ValidationError::new(
+                "Unknown message name in the retry policy",
+            ));
+        }
+    }
+    for policy in std::iter::once(&config.default).chain(config.messages.values()) {
+        if policy.attempts == 0 {
+            return Err(ValidationError::new("Retry attempts must be at least 1"));
+        }
+        if !(policy.backoff.is_finite() && policy.backoff >= 0.0) {
+            return Err(ValidationError::new(
+                "Retry backoff must be a number of seconds that is not negative",
+            ));
+        }
+        if !(policy.max_backoff.is_finite() && policy.max_backoff >= 0.0) {
+            return Err(ValidationError::new(
+                "Retry max backoff must be a number of seconds that is not negative",
+            ));
+        }
+    }
+    Ok(())
+}
+
+fn validate_reconnect(config: &ReconnectConfig) -> Result<(), ValidationError> {
+    if !(config.backoff.is_finite() && config.backoff > 0.0) {
+        return Err(ValidationError::new(
+            "Reconnect backoff must be a positive number of seconds",
+        ));
+    }
+    if !(config.max_backoff.is_finite() && config.max_backoff >= config.backoff) {
+        return Err(ValidationError::new(
+            "Reconnect max backoff must be a number of seconds no less than the backoff",
+        ));
+    }
+    Ok(())
+}
+
+fn validate_privacy_mask(config: &PrivacyMaskConfig) -> Result<(), ValidationError> {
+    if !config.rect().is_valid() {
+        return Err(ValidationError::new(
+            "The privacy mask must be inside the frame",
+        ));
+    }
+    Ok(())
+}
+
+fn validate_motion_area(config: &MotionAreaConfig) -> Result<(), ValidationError> {
+    if !config.rect().is_valid() {
+        return Err(ValidationError::new(
+            "The motion ignore area must be inside the frame",
+        ));
+    }
+    Ok(())
+}
+
+fn validate_record(config: &RecordConfig) -> Result<(), ValidationError> {
+    if config.stream.as_stream_kinds().len() != 1 {
+        return Err(ValidationError::new(
+            "The record stream must be one of main, sub or extern",
+        ));
+    }
+    Ok(())
+}
+
+fn validate_digital_zoom(config: &DigitalZoomConfig) -> Result<(), ValidationError> {
+    if !config.rect().is_valid() {
+        return Err(ValidationError::new(
+            "The digital zoom must be inside the frame",
+        ));
+    }
+    if config.stream.as_stream_kinds().len() != 1 {
+        return Err(ValidationError::new(
+            "The digital zoom stream must be one of main, sub or extern",
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn test_retry_default_delay() {
+        // One second between each of the five attempts
+        let policy = RetryPolicy::default();
+        assert_eq!(policy.attempts, 5);
+        for retry in 1..policy.attempts {
+            assert_eq!(policy.delay(retry), Duration::from_secs(1));
+        }
+    }
+
+    #[test]
+    fn test_retry_delay() {
+        let policy = RetryPolicy {
+            backoff: 0.5,
+            max_backoff: 3.0,
+            ..Default::default()
+        };
+        let delays: Vec<_> = (1..=5).map(|retry| policy.delay(retry)).collect();
+        assert_eq!(
+            delays,
+            [500, 1000, 2000, 3000, 3000]
+                .iter()
+                .map(|&ms| Duration::from_millis(ms))
+                .collect::<Vec<_>>()
+        );
+        // Retry 0 is treated as the first retry and large retries stay capped
+        assert_eq!(policy.delay(0), Duration::from_millis(500));
+        assert_eq!(policy.delay(u32::MAX), Duration::from_secs(3));
+    }
+
+    #[test]
+    fn test_validate_retry() {
+        let mut config = RetryConfig::default();
+        assert!(validate_retry(&config).is_ok());
+
+        config.messages.insert(
+            "Snap".to_string(),
+            RetryPolicy {
+                attempts: 8,
+                ..Default::default()
+            },
+        );
+        assert!(validate_retry(&config).is_ok());
+
+        config
+            .messages
+            .insert("NotAMessage".to_string(), Default::default());
+        assert!(validate_retry(&config).is_err());
+    }
+
+    #[test]
+    fn test_validate_retry_policy() {
+        let invalid = [
+            RetryPolicy {
+                attempts: 0,
+                ..Default::default()
+            },
+            RetryPolicy {
+                backoff: -1.0,
+                ..Default::default()
+            },
+            RetryPolicy {
+                max_backoff: -1.0,
+                ..Default::default()
+            },
+            RetryPolicy {
+                backoff: f64::NAN,
+                ..Default::default()
+            },
+            RetryPolicy {
+                backoff: f64::INFINITY,
+                ..Default::default()
+            },
+            RetryPolicy {
+                max_backoff: f64::INFINITY,
+                ..Default::default()
+            },
+        ];
+        for policy in invalid {
+            // Both as the default policy and as that of a single message
+            let config = RetryConfig {
+                default: policy.clone(),
+                messages: Default::default(),
+            };
+            assert!(validate_retry(&config).is_err(), "{:?}", policy);
+
+            let mut config = RetryConfig::default();
+            config.messages.insert("Snap".to_string(), policy.clone());
+            assert!(validate_retry(&config).is_err(), "{:?}", policy);
+        }
+    }
+}
@@ -0,0 +1,47 @@
+use clap::Parser;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+/// The ctl command talks to a neolink that is already running
+///
+/// The running neolink must have a `[control]` section in its config
+#[derive(Parser, Debug)]
+#[command(args_conflicts_with_subcommands = true, arg_required_else_help = true)]
+pub struct Opt {
+    /// Run the commands of a file, `-` for stdin, and print a result for each
+    ///
+    /// Each line is a JSON object such as `{"id": 1, "command": ["pir",
+    /// "CameraName", "on"]}` and each result is a line of JSON. The cameras
+    /// stay logged in between the commands
+    #[arg(long, value_parser = PathBuf::from_str)]
+    pub script: Option<PathBuf>,
+    #[command(subcommand)]
+    pub cmd: Option<CtlCommand>,
+}
+
+#[derive(Parser, Debug)]
+pub enum CtlCommand {
+    /// Print a JSON snapshot of the running neolink for bug reports
+    ///
+    /// It has the config, the camera connections, their streams and the
+    /// last error of each camera. Passwords are redacted
+    DumpState,
+    /// Print what each camera has active and its max_subscriptions as JSON
+    ///
+    /// These are the streams, motion listeners and battery polls that
+    /// neolink currently holds on the camera
+    Usage,
+    /// Raise the log level of one camera for some minutes
+    ///
+    /// Only the lines that start with the camera's name are raised, `info`
+    /// reverts it now
+    LogLevel {
+        /// The name of the camera. Must be a name in the config
+        camera: String,
+        /// trace, debug or info
+        level: log::LevelFilter,
+        /// Minutes until the camera reverts to the `RUST_LOG` level
+        #[arg(long, default_value_t = 10)]
+        minutes: u64,
+    },
+}
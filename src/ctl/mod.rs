@@ -0,0 +1,480 @@
+///
+/// # Neolink Ctl
+///
+/// This module talks to a neolink that is already running, such as
+/// `neolink rtsp`, over its control socket
+///
+/// `dump-state` prints a JSON snapshot of the running neolink to attach to
+/// bug reports. It has the redacted config, whether each camera is connected
+/// and over which transport, the streams and how many are using them, the
+/// commands waiting on each camera and the last errors that dropped its
+/// connection
+///
+/// `usage` lists the streams, motion listeners and battery polls that are
+/// active on each camera along with its `max_subscriptions`
+///
+/// `errors` is the request behind `neolink status --errors`, it replies with
+/// the last errors of each camera
+///
+/// `log-level` raises the log level of one camera to debug a flaky camera
+/// without restarting, it reverts to the `RUST_LOG` level after the minutes
+///
+/// `--script` reads commands from a file or stdin, one JSON object per line,
+/// and prints a line of JSON with the result of each. The commands are the
+/// requests above and the one-shot commands that `run` can run. The one-shot
+/// commands go to the running neolink when `share` is on and it is running,
+/// otherwise they are run here and the cameras stay logged in until the
+/// script ends, so a tool can drive many commands without logging in for each
+///
+/// `run` is sent by the one-shot commands such as `neolink reboot` and
+/// `neolink pir` when `share` is on. The running neolink runs the command over
+/// the connection it already has to the camera, many cameras only allow a few
+/// clients and some reject a second login. When no neolink is running the
+/// command connects to the camera itself
+///
+/// Only the cameras that the running neolink has started are in the snapshot
+/// and taking it will not wake a camera or start a stream
+///
+/// # Usage
+///
+/// ```bash
+/// neolink ctl --config=config.toml dump-state
+/// neolink ctl --config=config.toml usage
+/// neolink ctl --config=config.toml log-level CameraName trace --minutes=10
+/// echo '{"id": 1, "command": ["ir", "CameraName", "auto"]}' | neolink ctl --config=config.toml --script -
+/// ```
+///
+/// # Example Config
+///
+/// ```toml
+/// [control]
+/// bind = "127.0.0.1"
+/// port = 8557
+/// ```
+///
+/// The socket is not authenticated so keep it bound to a local address
+///
+use anyhow::{anyhow, Context, Result};
+use clap::Parser;
+use log::*;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::path::Path;
+use time::{format_description::well_known::Rfc3339, OffsetDateTime};
+use tokio::{
+    io::{AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncWriteExt, BufReader},
+    net::{TcpListener, TcpStream},
+};
+
+mod cmdline;
+
+use crate::{
+    cmdline::{Command, Opt as NeolinkOpt},
+    common::{loglevel, CameraReport, NeoReactor},
+    config::{Config, ControlConfig},
+    floodlight, ir, pir, reboot, siren, statusled,
+    utils::lightstate_name,
+};
+pub(crate) use cmdline::{CtlCommand, Opt};
+
+/// Longest request line that is accepted
+const MAX_REQUEST: u64 = 1024;
+
+/// The reply to `dump-state`
+#[derive(Serialize)]
+struct StateReport {
+    version: &'static str,
+    time: String,
+    config: Config,
+    cameras: Vec<CameraReport>,
+}
+
+/// The reply to `usage` for one camera
+#[derive(Serialize)]
+struct UsageReport {
+    name: String,
+    subscriptions: Vec<String>,
+    max_subscriptions: Option<usize>,
+}
+
+/// A line of `--script`
+#[derive(Deserialize)]
+struct ScriptCommand {
+    /// Copied to the result so that results can be matched to commands
+    #[serde(default)]
+    id: Value,
+    /// The arguments as they are given to neolink, such as `["pir",
+    /// "CameraName", "on"]` or `["usage"]`
+    command: Vec<String>,
+}
+
+/// The result of a line of `--script`
+#[derive(Serialize)]
+struct ScriptResult {
+    #[serde(skip_serializing_if = "Value::is_null")]
+    id: Value,
+    ok: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    result: Option<Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+/// Entry point for the ctl subcommand
+///
+/// Opt is the command line options
+pub(crate) async fn main(opt: Opt, reactor: NeoReactor) -> Result<()> {
+    let control = reactor
+        .config()
+        .await?
+        .borrow()
+        .control
+        .clone()
+        .unwrap_or_default();
+    if let Some(path) = opt.script.as_ref() {
+        return script(path, &control, &reactor).await;
+    }
+    let cmd = opt.cmd.context("Expected a command or --script")?;
+
+    let reply = send_request(&control, &request(cmd)).await?;
+    println!("{}", reply.trim_end());
+    Ok(())
+}
+
+/// The request of the command to the control socket
+fn request(cmd: CtlCommand) -> String {
+    match cmd {
+        CtlCommand::DumpState => "dump-state".to_string(),
+        CtlCommand::Usage => "usage".to_string(),
+        // The camera is last as its name can have spaces
+        CtlCommand::LogLevel {
+            camera,
+            level,
+            minutes,
+        } => format!("log-level {} {} {}", level, minutes, camera),
+    }
+}
+
+/// Run the commands of the script, printing a line of JSON with the result of
+/// each as it finishes
+async fn script(path: &Path, control: &ControlConfig, reactor: &NeoReactor) -> Result<()> {
+    let input: Box<dyn AsyncRead + Unpin + Send> = if path == Path::new("-") {
+        Box::new(tokio::io::stdin())
+    } else {
+        Box::new(
+            tokio::fs::File::open(path)
+                .await
+                .with_context(|| format!("Failed to open the script {:?}", path))?,
+        )
+    };
+    let mut lines = BufReader::new(input).lines();
+    let mut stdout = tokio::io::stdout();
+    while let Some(line) = lines.next_line().await? {
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        let (id, result) = match serde_json::from_str::<ScriptCommand>(line) {
+            Ok(command) => (
+                command.id,
+                script_command(command.command, control, reactor).await,
+            ),
+            Err(e) => (Value::Null, Err(anyhow!("Invalid command: {}", e))),
+        };
+        let result = match result {
+            Ok(result) => ScriptResult {
+                id,
+                ok: true,
+                result: Some(result),
+                error: None,
+            },
+            Err(e) => ScriptResult {
+                id,
+                ok: false,
+                result: None,
+                error: Some(format!("{:#}", e)),
+            },
+        };
+        stdout
+            .write_all(format!("{}\n", serde_json::to_string(&result)?).as_bytes())
+            .await?;
+        stdout.flush().await?;
+    }
+    Ok(())
+}
+
+/// Run one command of the script
+async fn script_command(
+    args: Vec<String>,
+    control: &ControlConfig,
+    reactor: &NeoReactor,
+) -> Result<Value> {
+    // The requests to the running neolink such as `usage`
+    if let Ok(Opt { cmd: Some(cmd), .. }) =
+        Opt::try_parse_from(std::iter::once("ctl".to_string()).chain(args.iter().cloned()))
+    {
+        let reply = send_request(control, &request(cmd)).await?;
+        let reply = reply.trim_end();
+        return Ok(serde_json::from_str(reply).unwrap_or_else(|_| Value::from(reply)));
+    }
+
+    let cmd = oneshot(&args)?;
+    if !(control.share && forward(control, &args).await?) {
+        run_command(cmd, reactor).await?;
+    }
+    Ok(Value::from("OK"))
+}
+
+/// Send a request to the control socket of a running neolink and return its reply
+pub(crate) async fn send_request(control: &ControlConfig, request: &str) -> Result<String> {
+    let mut stream = TcpStream::connect((control.bind.as_str(), control.port))
+        .await
+        .with_context(|| {
+            format!(
+                "Failed to connect to neolink on {}:{}, is it running with a [control] config?",
+                control.bind, control.port
+            )
+        })?;
+    exchange(stream, request).await
+}
+
+/// Run a one-shot command on a running neolink
+///
+/// Returns false when no neolink is running so that the command is run here
+pub(crate) async fn forward(control: &ControlConfig, args: &[String]) -> Result<bool> {
+    let Ok(stream) = TcpStream::connect((control.bind.as_str(), control.port)).await else {
+        return Ok(false);
+    };
+    debug!("Running {} on the running neolink", args.join(" "));
+    exchange(stream, &format!("run {}", serde_json::to_string(args)?)).await?;
+    Ok(true)
+}
+
+/// The arguments of a one-shot command that a running neolink can run, these
+/// are the ones that only change the camera and print nothing
+pub(crate) fn forward_args(cmd: &Command) -> Option<Vec<String>> {
+    let onoff = |on: bool| if on { "on" } else { "off" }.to_string();
+    match cmd {
+        Command::Reboot(opt) => Some(vec!["reboot".to_string(), opt.camera.clone()]),
+        Command::StatusLight(opt) if opt.on.is_some() || opt.doorbell.is_some() => {
+            let mut args = vec!["status-light".to_string(), opt.camera.clone()];
+            if let Some(on) = opt.on {
+                args.push(onoff(on));
+            }
+            if let Some(state) = opt.doorbell {
+                args.push("--doorbell".to_string());
+                args.push(lightstate_name(state).to_string());
+            }
+            Some(args)
+        }
+        Command::Ir(opt) => opt.state.map(|state| {
+            vec![
+                "ir".to_string(),
+                opt.camera.clone(),
+                lightstate_name(state).to_string(),
+            ]
+        }),
+        Command::Pir(opt) => opt
+            .on
+            .map(|on| vec!["pir".to_string(), opt.camera.clone(), onoff(on)]),
+        Command::Floodlight(opt) => opt.on.map(|on| {
+            let mut args = vec!["floodlight".to_string(), opt.camera.clone(), onoff(on)];
+            if let Some(brightness) = opt.brightness {
+                args.extend(["--brightness".to_string(), brightness.to_string()]);
+            }
+            args
+        }),
+        Command::Siren(opt) => Some(match opt.on {
+            Some(on) => vec!["siren".to_string(), opt.camera.clone(), onoff(on)],
+            None => vec![
+                "siren".to_string(),
+                opt.camera.clone(),
+                "--times".to_string(),
+                opt.times.to_string(),
+            ],
+        }),
+        _ => None,
+    }
+}
+
+async fn exchange(mut stream: TcpStream, request: &str) -> Result<String> {
+    stream
+        .write_all(format!("{}\n", request).as_bytes())
+        .await?;
+
+    let mut reply = String::new();
+    stream.read_to_string(&mut reply).await?;
+    match reply.strip_prefix("ERROR: ") {
+        Some(error) => Err(anyhow!("{}", error.trim_end())),
+        None => Ok(reply),
+    }
+}
+
+/// Serve the control socket of a running neolink
+pub(crate) async fn serve(control: ControlConfig, reactor: NeoReactor) -> Result<()> {
+    let listener = TcpListener::bind((control.bind.as_str(), control.port))
+        .await
+        .with_context(|| {
+            format!(
+                "Failed to bind the control port {}:{}",
+                control.bind, control.port
+            )
+        })?;
+    info!("Control: Serving on {}:{}", control.bind, control.port);
+
+    loop {
+        let (stream, addr) = listener.accept().await?;
+        let reactor = reactor.clone();
+        tokio::task::spawn(async move {
+            if let Err(e) = handle_connection(stream, &reactor).await {
+                debug!("Control: Request from {} failed: {:?}", addr, e);
+            }
+        });
+    }
+}
+
+/// Handles a single request, the connection is closed after the reply
+async fn handle_connection(mut stream: TcpStream, reactor: &NeoReactor) -> Result<()> {
+    let mut request = String::new();
+    BufReader::new(&mut stream)
+        .take(MAX_REQUEST)
+        .read_line(&mut request)
+        .await?;
+
+    let reply = match request.trim() {
+        "dump-state" => match dump_state(reactor).await {
+            Ok(state) => state,
+            Err(e) => format!("ERROR: Failed to dump the state: {:?}", e),
+        },
+        "usage" => match usage(reactor).await {
+            Ok(usage) => usage,
+            Err(e) => format!("ERROR: Failed to get the usage: {:?}", e),
+        },
+        "errors" => match errors(reactor).await {
+            Ok(errors) => errors,
+            Err(e) => format!("ERROR: Failed to get the errors: {:?}", e),
+        },
+        "activity" => match activity(reactor).await {
+            Ok(activity) => activity,
+            Err(e) => format!("ERROR: Failed to get the activity: {:?}", e),
+        },
+        request if request.starts_with("log-level ") => match log_level(request, reactor).await {
+            Ok(()) => "OK\n".to_string(),
+            Err(e) => format!("ERROR: Failed to set the log level: {:?}", e),
+        },
+        request if request.starts_with("run ") => match run(request, reactor).await {
+            Ok(()) => "OK\n".to_string(),
+            Err(e) => format!("ERROR: {:?}", e),
+        },
+        request => format!("ERROR: Unknown request `{}`", request),
+    };
+    stream.write_all(reply.as_bytes()).await?;
+    stream.shutdown().await?;
+    Ok(())
+}
+
+async fn dump_state(reactor: &NeoReactor) -> Result<String> {
+    let config = reactor.config().await?.borrow().redacted();
+    let mut cameras = vec![];
+    for camera in reactor.cameras().await? {
+        cameras.push(camera.report().await?);
+    }
+    cameras.sort_by(|a, b| a.name.cmp(&b.name));
+
+    let state = StateReport {
+        version: env!("NEOLINK_VERSION"),
+        time: OffsetDateTime::now_utc().format(&Rfc3339)?,
+        config,
+        cameras,
+    };
+    Ok(serde_json::to_string_pretty(&state)?)
+}
+
+async fn usage(reactor: &NeoReactor) -> Result<String> {
+    let mut cameras = vec![];
+    for camera in reactor.cameras().await? {
+        let name = camera.config().await?.borrow().name.clone();
+        let subscriptions = camera.subscriptions().await?;
+        cameras.push(UsageReport {
+            name,
+            subscriptions: subscriptions.active(),
+            max_subscriptions: subscriptions.limit(),
+        });
+    }
+    cameras.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(serde_json::to_string_pretty(&cameras)?)
+}
+
+async fn errors(reactor: &NeoReactor) -> Result<String> {
+    let mut cameras = vec![];
+    for camera in reactor.cameras().await? {
+        cameras.push(camera.error_report().await?);
+    }
+    cameras.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(serde_json::to_string_pretty(&cameras)?)
+}
+
+async fn activity(reactor: &NeoReactor) -> Result<String> {
+    let mut cameras = vec![];
+    for camera in reactor.cameras().await? {
+        cameras.push(camera.activity().await?);
+    }
+    cameras.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(serde_json::to_string_pretty(&cameras)?)
+}
+
+/// `log-level {level} {minutes} {camera}`
+async fn log_level(request: &str, reactor: &NeoReactor) -> Result<()> {
+    let mut args = request.splitn(4, ' ').skip(1);
+    let (Some(level), Some(minutes), Some(camera)) = (args.next(), args.next(), args.next()) else {
+        return Err(anyhow!(
+            "Expected log-level {{level}} {{minutes}} {{camera}}"
+        ));
+    };
+    let level = level.parse().context("Invalid level")?;
+    let minutes = minutes.parse().context("Invalid minutes")?;
+    if !reactor
+        .config()
+        .await?
+        .borrow()
+        .cameras
+        .iter()
+        .any(|config| config.name == camera)
+    {
+        return Err(anyhow!("No camera named {}", camera));
+    }
+    loglevel::boost(camera, level, minutes)
+}
+
+/// `run {args}` with the args of the command as a JSON list
+async fn run(request: &str, reactor: &NeoReactor) -> Result<()> {
+    let args: Vec<String> =
+        serde_json::from_str(request.trim_start_matches("run ")).context("Invalid args")?;
+    run_command(oneshot(&args)?, reactor).await
+}
+
+/// The one-shot command of the args, only those that change the camera and
+/// print nothing
+fn oneshot(args: &[String]) -> Result<Command> {
+    let opt = NeolinkOpt::try_parse_from(
+        std::iter::once("neolink").chain(args.iter().map(String::as_str)),
+    )?;
+    let cmd = opt.cmd.context("No command")?;
+    if forward_args(&cmd).is_none() {
+        return Err(anyhow!("This command cannot be run by a running neolink"));
+    }
+    Ok(cmd)
+}
+
+async fn run_command(cmd: Command, reactor: &NeoReactor) -> Result<()> {
+    let reactor = reactor.clone();
+    match cmd {
+        Command::Reboot(opts) => reboot::main(opts, reactor).await,
+        Command::StatusLight(opts) => statusled::main(opts, reactor).await,
+        Command::Ir(opts) => ir::main(opts, reactor).await,
+        Command::Pir(opts) => pir::main(opts, reactor).await,
+        Command::Floodlight(opts) => floodlight::main(opts, reactor).await,
+        Command::Siren(opts) => siren::main(opts, reactor).await,
+        _ => Err(anyhow!("This command cannot be run by a running neolink")),
+    }
+}
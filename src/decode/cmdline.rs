@@ -0,0 +1,30 @@
+use clap::{Parser, ValueEnum};
+
+/// The decode command will pretty print a BC packet
+///
+/// This is a developer tool and does not contact any camera
+#[derive(Parser, Debug)]
+pub struct Opt {
+    /// The packet data as hex (as copied from wireshark) or base64
+    pub data: String,
+    /// Treat the data as base64 instead of auto detecting
+    #[arg(long)]
+    pub base64: bool,
+    /// The encryption used on the packet
+    #[arg(long, value_enum, default_value = "bc-encrypt")]
+    pub encryption: Encryption,
+    /// Camera password, used with the nonce to make the AES key
+    #[arg(long)]
+    pub password: Option<String>,
+    /// The login nonce, used with the password to make the AES key
+    #[arg(long)]
+    pub nonce: Option<String>,
+}
+
+#[derive(Parser, Debug, Clone, Copy, ValueEnum)]
+pub enum Encryption {
+    None,
+    BcEncrypt,
+    Aes,
+    FullAes,
+}
@@ -0,0 +1,208 @@
+///
+/// # Neolink Decode
+///
+/// This module handles the decode subcommand
+///
+/// It takes the hex or base64 of a BC packet and pretty prints
+/// the header, extension, payload and a guess at what any binary
+/// payload contains. It is intended to help with reverse engineering
+/// and does not need a config or a camera
+///
+/// # Usage
+///
+/// ```bash
+/// # Hex as copied from wireshark
+/// neolink decode f0debc0a5100000000000000...
+/// # Base64
+/// neolink decode --base64 8N68ClEAAAA...
+/// # AES encrypted packets need the password and login nonce
+/// neolink decode --encryption=aes --password=pass --nonce=NONCE f0debc0a...
+/// ```
+///
+use anyhow::{anyhow, Context, Result};
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine as _};
+use bytes::BytesMut;
+use neolink_core::{
+    bc::model::*,
+    bc_protocol::{Credentials, Error as BcError},
+    bcmedia::model::BcMedia,
+};
+
+mod cmdline;
+
+pub(crate) use cmdline::*;
+
+fn parse_data(opt: &Opt) -> Result<Vec<u8>> {
+    let cleaned: String = opt
+        .data
+        .chars()
+        .filter(|c| !c.is_whitespace() && *c != ':')
+        .collect();
+    let cleaned = cleaned.trim_start_matches("0x");
+    let is_hex = cleaned.len() % 2 == 0 && cleaned.chars().all(|c| c.is_ascii_hexdigit());
+    if is_hex && !opt.base64 {
+        (0..cleaned.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&cleaned[i..i + 2], 16))
+            .collect::<Result<Vec<_>, _>>()
+            .context("Invalid hex")
+    } else {
+        BASE64.decode(cleaned).context("Data is neither hex nor base64")
+    }
+}
+
+fn make_context(opt: &Opt) -> Result<BcContext> {
+    let aes_key = || -> Result<[u8; 16]> {
+        let nonce = opt
+            .nonce
+            .as_ref()
+            .ok_or_else(|| anyhow!("AES needs the login --nonce"))?;
+        let credentials = Credentials::new("", opt.password.clone());
+        Ok(credentials.make_aeskey(nonce))
+    };
+    let encryption = match opt.encryption {
+        Encryption::None => EncryptionProtocol::unencrypted(),
+        Encryption::BcEncrypt => EncryptionProtocol::bcencrypt(),
+        Encryption::Aes => EncryptionProtocol::aes(aes_key()?),
+        Encryption::FullAes => EncryptionProtocol::full_aes(aes_key()?),
+    };
+    Ok(BcContext::new_with_encryption(encryption))
+}
+
+fn to_xml<T: serde::Serialize>(value: &T) -> String {
+    let mut buf = bytes::BytesMut::new();
+    match quick_xml::se::to_writer(&mut buf, value) {
+        Ok(_) => String::from_utf8_lossy(&buf).to_string(),
+        Err(e) => format!("<!-- Could not serialise: {:?} -->", e),
+    }
+}
+
+fn classify_binary(data: &[u8]) -> String {
+    if data.starts_with(&[0xFF, 0xD8]) {
+        return format!("JPEG image ({} bytes)", data.len());
+    }
+    let mut buf = BytesMut::from(data);
+    match BcMedia::deserialize(&mut buf) {
+        Ok(media) => {
+            let kind = match &media {
+                BcMedia::InfoV1(info) => format!(
+                    "BcMedia InfoV1 {}x{} @ {}fps",
+                    info.video_width, info.video_height, info.fps
+                ),
+                BcMedia::InfoV2(info) => format!(
+                    "BcMedia InfoV2 {}x{} @ {}fps",
+                    info.video_width, info.video_height, info.fps
+                ),
+                BcMedia::Iframe(frame) => format!(
+                    "BcMedia IFrame {:?} at {}us ({} bytes)",
+                    frame.video_type,
+                    frame.microseconds,
+                    frame.data.len()
+                ),
+                BcMedia::Pframe(frame) => format!(
+                    "BcMedia PFrame {:?} at {}us ({} bytes)",
+                    frame.video_type,
+                    frame.microseconds,
+                    frame.data.len()
+                ),
+                BcMedia::Aac(aac) => format!("BcMedia AAC ({} bytes)", aac.data.len()),
+                BcMedia::Adpcm(adpcm) => format!("BcMedia ADPCM ({} bytes)", adpcm.data.len()),
+            };
+            if buf.is_empty() {
+                kind
+            } else {
+                format!("{} followed by {} more bytes", kind, buf.len())
+            }
+        }
+        Err(BcError::NomIncomplete(_)) => {
+            format!(
+                "Partial BcMedia or unknown binary ({} bytes) starting {:02X?}",
+                data.len(),
+                &data[0..std::cmp::min(16, data.len())]
+            )
+        }
+        Err(_) => format!(
+            "Unknown binary ({} bytes) starting {:02X?}",
+            data.len(),
+            &data[0..std::cmp::min(16, data.len())]
+        ),
+    }
+}
+
+fn print_bc(bc: &Bc) {
+    let meta = &bc.meta;
+    println!("==Header==");
+    println!(
+        "MsgID: {} ({})",
+        meta.msg_id,
+        msg_id_name(meta.msg_id).unwrap_or("Unknown")
+    );
+    println!("MsgNum: {}", meta.msg_num);
+    println!("Channel: {}", meta.channel_id);
+    println!("StreamType: {}", meta.stream_type);
+    println!("ResponseCode: {}", meta.response_code);
+    println!("Class: 0x{:04x}", meta.class);
+    match &bc.body {
+        BcBody::LegacyMsg(legacy) => {
+            println!("==Legacy Body==");
+            println!("{:?}", legacy);
+        }
+        BcBody::ModernMsg(modern) => {
+            if let Some(ext) = modern.extension.as_ref() {
+                println!("==Extension==");
+                println!("{}", to_xml(ext));
+            }
+            match modern.payload.as_ref() {
+                Some(BcPayloads::BcXml(xml)) => {
+                    println!("==Payload (XML)==");
+                    println!("{}", to_xml(xml));
+                }
+                Some(BcPayloads::Binary(data)) => {
+                    println!("==Payload (Binary)==");
+                    println!("{}", classify_binary(data));
+                }
+                None => {
+                    println!("==No Payload==");
+                }
+            }
+        }
+    }
+}
+
+/// Entry point for the decode subcommand
+///
+/// Opt is the command line options
+pub(crate) fn main(opt: Opt) -> Result<()> {
+    let data = parse_data(&opt)?;
+    let context = make_context(&opt)?;
+
+    let mut buf = BytesMut::from(data.as_slice());
+    let mut count = 0;
+    while !buf.is_empty() {
+        match Bc::deserialize(&context, &mut buf) {
+            Ok(bc) => {
+                if count > 0 {
+                    println!();
+                }
+                print_bc(&bc);
+                count += 1;
+            }
+            Err(BcError::NomIncomplete(needed)) => {
+                println!(
+                    "==Incomplete==\n{} bytes remaining, at least {} more bytes needed",
+                    buf.len(),
+                    needed
+                );
+                break;
+            }
+            Err(e) => {
+                return Err(e).context(format!(
+                    "Could not decode the packet (is --encryption correct?) starting {:02X?}",
+                    &buf[0..std::cmp::min(20, buf.len())]
+                ));
+            }
+        }
+    }
+
+    Ok(())
+}
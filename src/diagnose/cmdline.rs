@@ -0,0 +1,20 @@
+use clap::Parser;
+
+/// The diagnose command runs tests that help to find the cause of connection problems
+#[derive(Parser, Debug)]
+pub struct Opt {
+    #[command(subcommand)]
+    pub cmd: DiagnoseCommand,
+}
+
+#[derive(Parser, Debug)]
+pub enum DiagnoseCommand {
+    /// Pull the main stream as fast as possible over each transport and report the throughput
+    Bandwidth {
+        /// The name of the camera. Must be a name in the config
+        camera: String,
+        /// How long in seconds to pull the stream for over each transport
+        #[arg(short, long, default_value_t = 10)]
+        duration: u64,
+    },
+}
@@ -0,0 +1,186 @@
+///
+/// # Neolink Diagnose
+///
+/// This module handles the diagnose subcommand
+///
+/// `bandwidth` connects to the camera over each transport it can reach
+/// in turn and pulls the main stream as fast as it will go, then prints
+/// the sustained throughput of each. A low figure on every transport points
+/// to the camera's network (e.g. weak WiFi) rather than to neolink
+///
+/// # Usage
+///
+/// ```bash
+/// neolink diagnose --config=config.toml bandwidth CameraName --duration=10
+/// ```
+///
+/// Which prints something like
+///
+/// ```text
+/// Transport  Throughput   Frames  FPS
+/// tcp        4.12Mbit/s   250     25.0
+/// local      3.87Mbit/s   248     24.8
+/// relay      0.61Mbit/s   93      9.3
+/// ```
+///
+use anyhow::{anyhow, Context, Result};
+use neolink_core::{
+    bc_protocol::{DiscoveryMethods, StreamKind, Transport},
+    bcmedia::model::*,
+};
+use tokio::time::{timeout_at, Duration, Instant};
+
+mod cmdline;
+
+use crate::{common::NeoReactor, config::CameraConfig, utils::connect_and_login};
+use cmdline::DiagnoseCommand;
+pub(crate) use cmdline::Opt;
+
+struct Throughput {
+    transport: Transport,
+    bytes: usize,
+    frames: usize,
+    elapsed: Duration,
+}
+
+/// Entry point for the diagnose subcommand
+///
+/// Opt is the command line options
+pub(crate) async fn main(opt: Opt, reactor: NeoReactor) -> Result<()> {
+    match opt.cmd {
+        DiagnoseCommand::Bandwidth { camera, duration } => {
+            let config = reactor
+                .config()
+                .await?
+                .borrow()
+                .cameras
+                .iter()
+                .find(|cam_config| cam_config.name == camera)
+                .cloned()
+                .ok_or_else(|| anyhow!("Camera {} not found in the config", camera))?;
+            bandwidth(&config, Duration::from_secs(duration)).await
+        }
+    }
+}
+
+async fn bandwidth(config: &CameraConfig, duration: Duration) -> Result<()> {
+    let mut results: Vec<Throughput> = vec![];
+    for candidate in candidates(config) {
+        let result = match measure(&candidate, duration).await {
+            Ok(result) => result,
+            Err(e) => {
+                log::warn!("{}: Could not measure: {:#}", config.name, e);
+                continue;
+            }
+        };
+        // Several candidates can end up on the same transport, only report it once
+        if results.iter().any(|r| r.transport == result.transport) {
+            continue;
+        }
+        results.push(result);
+    }
+    if results.is_empty() {
+        return Err(anyhow!(
+            "Could not stream from {} over any transport",
+            config.name
+        ));
+    }
+
+    println!(
+        "{:<9}  {:<11}  {:<6}  FPS",
+        "Transport", "Throughput", "Frames"
+    );
+    for result in results.iter() {
+        let secs = result.elapsed.as_secs_f64().max(f64::EPSILON);
+        println!(
+            "{:<9}  {:<11}  {:<6}  {:.1}",
+            result.transport.to_string(),
+            format!(
+                "{:.2}Mbit/s",
+                (result.bytes * 8) as f64 / secs / 1_000_000.0
+            ),
+            result.frames,
+            result.frames as f64 / secs
+        );
+    }
+    Ok(())
+}
+
+/// The configs that reach the camera over each kind of transport
+///
+/// - TCP when there is an address
+/// - Local UDP discovery when there is a UID
+/// - Through the reolink servers when the discovery allows it
+fn candidates(config: &CameraConfig) -> Vec<CameraConfig> {
+    let mut candidates = vec![];
+    if config.camera_addr.is_some() {
+        candidates.push(CameraConfig {
+            camera_uid: None,
+            ..config.clone()
+        });
+    }
+    if config.camera_uid.is_some() {
+        candidates.push(CameraConfig {
+            camera_addr: None,
+            discovery: DiscoveryMethods::Local,
+            ..config.clone()
+        });
+        if matches!(
+            config.discovery,
+            DiscoveryMethods::Map | DiscoveryMethods::Relay | DiscoveryMethods::Cellular
+        ) {
+            // Cellular skips the local and remote discovery
+            candidates.push(CameraConfig {
+                camera_addr: None,
+                discovery: DiscoveryMethods::Cellular,
+                ..config.clone()
+            });
+        }
+    }
+    candidates
+}
+
+async fn measure(config: &CameraConfig, duration: Duration) -> Result<Throughput> {
+    let camera = connect_and_login(config).await?;
+    let transport = camera.transport();
+    log::info!(
+        "{}: Measuring over {} for {:?}",
+        config.name,
+        transport,
+        duration
+    );
+
+    let mut stream = camera
+        .start_video(StreamKind::Main, 0, config.strict)
+        .await
+        .context("Failed to start the stream")?;
+    let start = Instant::now();
+    let end = start + duration;
+    let mut bytes = 0;
+    let mut frames = 0;
+    while let Ok(data) = timeout_at(end, stream.get_data()).await {
+        match data?? {
+            BcMedia::Iframe(BcMediaIframe { data, .. })
+            | BcMedia::Pframe(BcMediaPframe { data, .. }) => {
+                bytes += data.len();
+                frames += 1;
+            }
+            BcMedia::Aac(BcMediaAac { data, .. }) | BcMedia::Adpcm(BcMediaAdpcm { data, .. }) => {
+                bytes += data.len();
+            }
+            _ => {}
+        }
+    }
+    let elapsed = start.elapsed();
+
+    drop(stream);
+    let _ = camera.logout().await;
+    let _ = camera.shutdown().await;
+
+    Ok(Throughput {
+        transport,
+        bytes,
+        frames,
+        elapsed,
+    })
+}
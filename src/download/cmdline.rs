@@ -0,0 +1,30 @@
+use clap::Parser;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+/// The download command lists and downloads the recordings on the camera's SD card
+#[derive(Parser, Debug)]
+pub struct Opt {
+    /// The name of the camera. Must be a name in the config
+    pub camera: String,
+    /// Start of the search in the camera's time as `YYYY-MM-DD` or
+    /// `YYYY-MM-DD HH:MM:SS`. Defaults to the start of today
+    #[arg(long)]
+    pub from: Option<String>,
+    /// End of the search in the camera's time as `YYYY-MM-DD` or
+    /// `YYYY-MM-DD HH:MM:SS`. Defaults to now
+    #[arg(long)]
+    pub to: Option<String>,
+    /// Only list the recordings without downloading them
+    #[arg(short, long)]
+    pub list: bool,
+    /// Only download the recording with this name as given by `--list`
+    #[arg(long)]
+    pub file: Option<String>,
+    /// Download the recordings of the substream instead of the main stream
+    #[arg(long)]
+    pub sub: bool,
+    /// The directory to save the recordings in
+    #[arg(short, long, value_parser = PathBuf::from_str, default_value = ".")]
+    pub output: PathBuf,
+}
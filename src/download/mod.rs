@@ -0,0 +1,298 @@
+///
+/// # Neolink Download
+///
+/// This module downloads the recordings on the camera's SD card over the
+/// Baichuan protocol, the same way as the Reolink app
+///
+/// The recordings are saved as `{CameraName}_{YYYYmmdd_HHMMSS}.mkv` with the
+/// video of the recording. The audio is not kept
+///
+/// # Usage
+///
+/// ```bash
+/// # List today's recordings
+/// neolink download --config=config.toml --list CameraName
+/// # Download the recordings of a morning
+/// neolink download --config=config.toml --from="2024-03-01 06:00:00" --to="2024-03-01 12:00:00" CameraName
+/// ```
+///
+use anyhow::{anyhow, Context, Result};
+use gstreamer::{
+    parse::launch_full, prelude::*, ClockTime, MessageView, ParseFlags, Pipeline, State,
+};
+use gstreamer_app::AppSrc;
+use neolink_core::{
+    bc_protocol::{RecordedFile, StreamKind},
+    bcmedia::model::*,
+};
+use std::path::{Path, PathBuf};
+use time::{Date, Month, OffsetDateTime, PrimitiveDateTime, Time};
+use tokio::task::JoinSet;
+
+mod cmdline;
+
+use crate::{common::NeoReactor, AnyResult};
+pub(crate) use cmdline::Opt;
+
+/// Entry point for the download subcommand
+///
+/// Opt is the command line options
+pub(crate) async fn main(opt: Opt, reactor: NeoReactor) -> Result<()> {
+    let camera = reactor.get(&opt.camera).await?;
+    let utc_offset = camera.config().await?.borrow().utc_offset();
+    let now = OffsetDateTime::now_utc().to_offset(utc_offset);
+    let now = PrimitiveDateTime::new(now.date(), now.time());
+    let start = match opt.from.as_deref() {
+        Some(from) => parse_time(from)?,
+        None => now.replace_time(Time::MIDNIGHT),
+    };
+    let end = match opt.to.as_deref() {
+        Some(to) => parse_time(to)?,
+        None => now,
+    };
+    if end <= start {
+        return Err(anyhow!("The end of the search must be after the start"));
+    }
+    let stream = if opt.sub {
+        StreamKind::Sub
+    } else {
+        StreamKind::Main
+    };
+
+    let files = camera
+        .run_task(|cam| {
+            Box::pin(async move {
+                cam.search_files(start, end, stream)
+                    .await
+                    .context("Unable to search the SD card")
+            })
+        })
+        .await?;
+    let files = files
+        .into_iter()
+        .filter(|file| opt.file.as_ref().map_or(true, |name| &file.name == name))
+        .collect::<Vec<_>>();
+
+    if opt.list {
+        for file in files.iter() {
+            println!(
+                "{}\t{}\t{}\t{}\t{}",
+                file.start,
+                file.end,
+                file.record_type.as_deref().unwrap_or("-"),
+                file.size
+                    .map(|s| s.to_string())
+                    .unwrap_or_else(|| "-".to_string()),
+                file.name
+            );
+        }
+        return Ok(());
+    }
+    if files.is_empty() {
+        return Err(anyhow!("No recordings were found"));
+    }
+
+    for file in files.iter() {
+        let path = file_path(&opt.output, &opt.camera, file);
+        log::info!("{}: Downloading {} to {:?}", opt.camera, file.name, path);
+        camera
+            .run_task(|cam| {
+                let file = file.clone();
+                let path = path.clone();
+                Box::pin(async move {
+                    download(cam, &file, stream, &path)
+                        .await
+                        .with_context(|| format!("Unable to download {}", file.name))
+                })
+            })
+            .await?;
+    }
+    log::info!("{}: Downloaded {} recordings", opt.camera, files.len());
+
+    Ok(())
+}
+
+/// Write the video of one recording to `path`
+async fn download(
+    cam: &neolink_core::bc_protocol::BcCamera,
+    file: &RecordedFile,
+    stream: StreamKind,
+    path: &Path,
+) -> AnyResult<()> {
+    let mut download = cam.download_file(&file.name, stream).await?;
+
+    // The format is only known once the first frame arrives
+    let mut writer: Option<(Pipeline, AppSrc, JoinSet<AnyResult<()>>)> = None;
+    let mut first_ts = None;
+    while let Some(media) = download.get_data().await? {
+        let (video_type, microseconds, data) = match media {
+            BcMedia::Iframe(BcMediaIframe {
+                video_type,
+                microseconds,
+                data,
+                ..
+            }) => (video_type, microseconds, data),
+            // Frames before the first keyframe cannot be decoded
+            BcMedia::Pframe(BcMediaPframe {
+                video_type,
+                microseconds,
+                data,
+            }) if writer.is_some() => (video_type, microseconds, data),
+            _ => continue,
+        };
+        if writer.is_none() {
+            let pipeline = create_pipeline(video_type, path)?;
+            let source = pipeline
+                .by_name("thesource")
+                .expect("There shoud be a `thesource`")
+                .dynamic_cast::<AppSrc>()
+                .map_err(|_| {
+                    anyhow!("Cannot find appsource in gstreamer, check your gstreamer plugins")
+                })?;
+            let mut set = JoinSet::new();
+            let thread_pipeline = pipeline.clone();
+            set.spawn_blocking(move || start_pipeline(thread_pipeline));
+            writer = Some((pipeline, source, set));
+        }
+        let Some((_, source, _)) = writer.as_ref() else {
+            unreachable!()
+        };
+
+        // The camera's clock of the recording, wraps after about an hour
+        let first = *first_ts.get_or_insert(microseconds);
+        let mut buf = gstreamer::Buffer::with_size(data.len())?;
+        {
+            let buf = buf
+                .get_mut()
+                .ok_or_else(|| anyhow!("New buffer should be writable"))?;
+            buf.set_pts(ClockTime::from_useconds(
+                microseconds.wrapping_sub(first) as u64
+            ));
+            buf.copy_from_slice(0, &data)
+                .map_err(|_| anyhow!("Failed to copy the frame"))?;
+        }
+        source
+            .push_buffer(buf)
+            .map_err(|e| anyhow!("Streamer Error: {e:?}"))?;
+    }
+
+    let Some((pipeline, source, mut set)) = writer else {
+        return Err(anyhow!("The recording had no video"));
+    };
+    // Let the muxer finish the file
+    let _ = source.end_of_stream();
+    let res = set
+        .join_next()
+        .await
+        .unwrap_or(Ok(Ok(())))
+        .map_err(anyhow::Error::from)
+        .and_then(|v| v);
+    let _ = pipeline.set_state(State::Null);
+    res
+}
+
+/// Parse `YYYY-MM-DD` or `YYYY-MM-DD HH:MM[:SS]`
+fn parse_time(value: &str) -> Result<PrimitiveDateTime> {
+    let invalid = || anyhow!("Invalid time {:?} expected YYYY-MM-DD HH:MM:SS", value);
+    let (date, time) = match value.trim().split_once([' ', 'T']) {
+        Some((date, time)) => (date, Some(time)),
+        None => (value.trim(), None),
+    };
+    let date = date
+        .split('-')
+        .map(|part| part.parse::<i32>().map_err(|_| invalid()))
+        .collect::<Result<Vec<_>>>()?;
+    let [year, month, day] = date[..] else {
+        return Err(invalid());
+    };
+    let date = Date::from_calendar_date(
+        year,
+        Month::try_from(u8::try_from(month)?)?,
+        u8::try_from(day)?,
+    )?;
+    let time = match time {
+        Some(time) => {
+            let parts = time
+                .split(':')
+                .map(|part| part.parse::<u8>().map_err(|_| invalid()))
+                .collect::<Result<Vec<_>>>()?;
+            match parts[..] {
+                [hour, minute] => Time::from_hms(hour, minute, 0)?,
+                [hour, minute, second] => Time::from_hms(hour, minute, second)?,
+                _ => return Err(invalid()),
+            }
+        }
+        None => Time::MIDNIGHT,
+    };
+    Ok(PrimitiveDateTime::new(date, time))
+}
+
+/// Where a recording is saved, named after its start
+fn file_path(dir: &Path, camera: &str, file: &RecordedFile) -> PathBuf {
+    let start = file.start;
+    dir.join(format!(
+        "{}_{:04}{:02}{:02}_{:02}{:02}{:02}.mkv",
+        camera.replace(['/', '\\'], "_"),
+        start.year(),
+        u8::from(start.month()),
+        start.day(),
+        start.hour(),
+        start.minute(),
+        start.second(),
+    ))
+}
+
+fn start_pipeline(pipeline: Pipeline) -> AnyResult<()> {
+    pipeline.set_state(State::Playing)?;
+
+    let bus = pipeline
+        .bus()
+        .expect("Pipeline without bus. Shouldn't happen!");
+
+    let res = loop {
+        match bus.timed_pop(ClockTime::from_mseconds(500)) {
+            Some(msg) => match msg.view() {
+                MessageView::Eos(..) => break Ok(()),
+                MessageView::Error(err) => {
+                    break Err(anyhow!("Error from gstreamer in the download: {:?}", err));
+                }
+                _ => (),
+            },
+            None if pipeline.current_state() == State::Null => break Ok(()),
+            None => (),
+        }
+    };
+
+    pipeline
+        .set_state(State::Null)
+        .context("Error in gstreamer when setting state to Null")?;
+
+    res
+}
+
+/// Mux the video into a file, the frames are timestamped by the download
+fn create_pipeline(format: VideoType, path: &Path) -> AnyResult<Pipeline> {
+    gstreamer::init()
+        .context("Unable to start gstreamer ensure it and all plugins are installed")?;
+
+    let parser = match format {
+        VideoType::H264 => "h264parse",
+        VideoType::H265 => "h265parse",
+    };
+    let launch_str = format!(
+        "appsrc name=thesource format=time \
+        ! {} \
+        ! matroskamux \
+        ! filesink location=\"{}\"",
+        parser,
+        path.display()
+    );
+    log::debug!("{}", launch_str);
+
+    launch_full(&launch_str, None, ParseFlags::empty())
+        .context("Unable to load gstreamer pipeline ensure all gstramer plugins are installed")?
+        .dynamic_cast::<Pipeline>()
+        .map_err(|_| {
+            anyhow!("Unable to create gstreamer pipeline ensure all gstramer plugins are installed")
+        })
+}
@@ -0,0 +1,380 @@
+///
+/// # Neolink Event Log
+///
+/// This module sends the events of the cameras to syslog or journald with
+/// their details as structured fields, for sites that collect their security
+/// events into a SIEM. Unlike the logs these are only the events of the
+/// cameras and they keep the same fields between releases
+///
+/// It runs alongside the commands that keep running such as `neolink rtsp`,
+/// `neolink mqtt` and `neolink http` when there is an `[event_log]` section in
+/// the config
+///
+/// # Example Config
+///
+/// ```toml
+/// [event_log]
+/// target = "syslog" # or "journald"
+/// address = "192.168.1.10:514" # The local syslog or journald when not given
+/// facility = "local0"
+/// ident = "neolink"
+/// cameras = ["Garage"] # All cameras when not given
+/// ```
+///
+/// The events are
+///
+/// - `detection` with the `sources`, `objects` and `zones` of the merged triggers
+/// - `motion_stop` when the camera reports the motion has stopped
+/// - `connected` and `disconnected` when the camera connection changes
+///
+/// Syslog messages are RFC 5424 with the fields as the `neolink@32473`
+/// structured data. Journal entries have the fields prefixed with `NEOLINK_`
+/// such as `NEOLINK_CAMERA` and `NEOLINK_EVENT`
+///
+use anyhow::{anyhow, Context, Result};
+use log::*;
+use std::{collections::HashMap, fmt::Write as _, net::SocketAddr, sync::Arc};
+#[cfg(unix)]
+use tokio::net::UnixDatagram;
+use tokio::{net::UdpSocket, sync::watch::Receiver as WatchReceiver, task::JoinSet};
+use tokio_util::sync::CancellationToken;
+
+use crate::{
+    common::{Event, EventKind, MdState, NeoInstance, NeoReactor},
+    config::{Config, EventLogConfig, EventLogTarget},
+    AnyResult,
+};
+
+/// The private enterprise number used for the structured data ID
+const SD_ID: &str = "neolink@32473";
+
+const SYSLOG_SOCKET: &str = "/dev/log";
+const JOURNALD_SOCKET: &str = "/run/systemd/journal/socket";
+
+/// Syslog severities
+const NOTICE: u8 = 5;
+const INFO: u8 = 6;
+const WARNING: u8 = 4;
+
+/// The fields of an event other than its camera, kind and time
+fn fields(event: &Event) -> Vec<(&'static str, String)> {
+    let join = |items: Vec<String>| items.join(",");
+    let mut fields = vec![
+        ("sources", join(event.sources.clone())),
+        ("objects", join(event.objects.clone())),
+        (
+            "zones",
+            join(event.zones.iter().map(|zone| zone.to_string()).collect()),
+        ),
+        (
+            "triggers",
+            event
+                .triggers
+                .map(|triggers| triggers.to_string())
+                .unwrap_or_default(),
+        ),
+        ("label", event.label.clone().unwrap_or_default()),
+    ];
+    fields.retain(|(_, value)| !value.is_empty());
+    fields
+}
+
+fn message(event: &Event) -> String {
+    let mut message = format!("{}: {}", event.camera, event.event.name());
+    for (key, value) in fields(event) {
+        let _ = write!(message, " {}={}", key, value);
+    }
+    message
+}
+
+/// Where the events are written
+enum Sink {
+    #[cfg(unix)]
+    Unix(UnixDatagram, String),
+    Udp(UdpSocket, SocketAddr),
+}
+
+impl Sink {
+    async fn open(config: &EventLogConfig) -> Result<Self> {
+        let default = match config.target {
+            EventLogTarget::Syslog => SYSLOG_SOCKET,
+            EventLogTarget::Journald => JOURNALD_SOCKET,
+        };
+        let address = config.address.as_deref().unwrap_or(default);
+        if let Ok(addr) = tokio::net::lookup_host(address)
+            .await
+            .map_err(anyhow::Error::from)
+            .and_then(|mut addrs| addrs.next().ok_or_else(|| anyhow!("No address")))
+        {
+            let bind = if addr.is_ipv4() {
+                "0.0.0.0:0"
+            } else {
+                "[::]:0"
+            };
+            return Ok(Sink::Udp(UdpSocket::bind(bind).await?, addr));
+        }
+        #[cfg(unix)]
+        {
+            Ok(Sink::Unix(UnixDatagram::unbound()?, address.to_string()))
+        }
+        #[cfg(not(unix))]
+        {
+            Err(anyhow!(
+                "Could not resolve the event log address {}",
+                address
+            ))
+        }
+    }
+
+    async fn send(&self, data: &[u8]) -> Result<()> {
+        match self {
+            #[cfg(unix)]
+            Sink::Unix(socket, path) => socket.send_to(data, path).await?,
+            Sink::Udp(socket, addr) => socket.send_to(data, addr).await?,
+        };
+        Ok(())
+    }
+}
+
+/// Send the events of the cameras while there is an `[event_log]` config
+///
+/// Cameras are followed as they are added to or removed from the config
+pub(crate) async fn serve(reactor: NeoReactor) -> Result<()> {
+    let mut config = reactor.config().await?;
+    let event_log = config
+        .borrow()
+        .event_log
+        .clone()
+        .context("No event log config")?;
+    let sink = Arc::new(
+        Sink::open(&event_log)
+            .await
+            .context("Failed to open the event log")?,
+    );
+    let mut set = JoinSet::<(String, AnyResult<()>)>::new();
+    let mut cameras: HashMap<String, CancellationToken> = Default::default();
+    loop {
+        let names = {
+            let config = config.borrow_and_update();
+            let event_log = config.event_log.as_ref();
+            config
+                .cameras
+                .iter()
+                .filter(|camera| camera.enabled)
+                .map(|camera| camera.name.clone())
+                .filter(|name| {
+                    event_log.is_some_and(|event_log| {
+                        event_log.cameras.is_empty() || event_log.cameras.contains(name)
+                    })
+                })
+                .collect::<Vec<_>>()
+        };
+
+        cameras.retain(|name, cancel| {
+            if names.contains(name) {
+                true
+            } else {
+                cancel.cancel();
+                false
+            }
+        });
+        for name in names {
+            if cameras.contains_key(&name) {
+                continue;
+            }
+            let cancel = CancellationToken::new();
+            cameras.insert(name.clone(), cancel.clone());
+            let reactor = reactor.clone();
+            let sink = sink.clone();
+            set.spawn(async move {
+                let result = tokio::select! {
+                    _ = cancel.cancelled() => AnyResult::Ok(()),
+                    v = async {
+                        let camera = reactor.get(&name).await?;
+                        camera_main(&camera, reactor.config().await?, &sink).await
+                    } => v,
+                };
+                (name, result)
+            });
+        }
+
+        tokio::select! {
+            v = config.changed() => v?,
+            Some(joined) = set.join_next() => {
+                // Only a cancelled camera ends without an error, those that
+                // failed are started again on the next config change
+                let (name, result) = joined?;
+                if let Err(e) = result {
+                    error!("{}: Event log: {:?}", name, e);
+                    cameras.remove(&name);
+                }
+            }
+        }
+    }
+}
+
+/// Send the events of one camera
+async fn camera_main(
+    camera: &NeoInstance,
+    config: WatchReceiver<Config>,
+    sink: &Sink,
+) -> AnyResult<()> {
+    let camera_config = camera.config().await?;
+    let mut detections = camera.events().await?;
+    let mut motion = camera.motion().await?;
+    let mut connection = camera.camera();
+    let mut connected = connection.borrow_and_update().upgrade().is_some();
+    // The name of the camera and the time in its `timezone`
+    let stamp = || {
+        let camera_config = camera_config.borrow();
+        (camera_config.name.clone(), camera_config.timezone().now())
+    };
+    loop {
+        let (event, severity) = tokio::select! {
+            v = detections.recv() => match v {
+                Ok(detection) => {
+                    let (name, now) = stamp();
+                    (Event::detection(&name, &detection, now), NOTICE)
+                }
+                Err(tokio::sync::broadcast::error::RecvError::Lagged(_)) => continue,
+                Err(e) => return Err(e.into()),
+            },
+            v = motion.changed() => {
+                v?;
+                if !matches!(*motion.borrow_and_update(), MdState::Stop(_)) {
+                    continue;
+                }
+                let (name, now) = stamp();
+                (Event::new(&name, EventKind::MotionStop, now), INFO)
+            },
+            v = connection.changed() => {
+                v?;
+                let now_connected = connection.borrow_and_update().upgrade().is_some();
+                if now_connected == connected {
+                    continue;
+                }
+                connected = now_connected;
+                let (name, now) = stamp();
+                if connected {
+                    (Event::new(&name, EventKind::Connected, now), INFO)
+                } else {
+                    (Event::new(&name, EventKind::Disconnected, now), WARNING)
+                }
+            },
+        };
+
+        let Some(event_log) = config.borrow().event_log.clone() else {
+            continue;
+        };
+        let data = match event_log.target {
+            EventLogTarget::Syslog => syslog_message(&event_log, &event, severity),
+            EventLogTarget::Journald => journal_entry(&event_log, &event, severity),
+        };
+        if let Err(e) = sink.send(&data).await {
+            warn!("{}: Event log write failed: {:?}", event.camera, e);
+        }
+    }
+}
+
+fn facility_code(facility: &str) -> u8 {
+    match facility {
+        "kern" => 0,
+        "user" => 1,
+        "mail" => 2,
+        "daemon" => 3,
+        "auth" => 4,
+        "syslog" => 5,
+        "lpr" => 6,
+        "news" => 7,
+        "uucp" => 8,
+        "cron" => 9,
+        "authpriv" => 10,
+        "ftp" => 11,
+        local => local
+            .strip_prefix("local")
+            .and_then(|n| n.parse::<u8>().ok())
+            .map(|n| 16 + n.min(7))
+            .unwrap_or(3),
+    }
+}
+
+fn hostname() -> String {
+    std::fs::read_to_string("/proc/sys/kernel/hostname")
+        .ok()
+        .or_else(|| std::env::var("HOSTNAME").ok())
+        .or_else(|| std::env::var("COMPUTERNAME").ok())
+        .map(|host| host.trim().to_string())
+        .filter(|host| !host.is_empty())
+        .unwrap_or_else(|| "-".to_string())
+}
+
+/// An RFC 5424 message with the fields as structured data
+fn syslog_message(config: &EventLogConfig, event: &Event, severity: u8) -> Vec<u8> {
+    // Param values escape `"`, `\` and `]`
+    let escape = |value: &str| {
+        value
+            .replace('\\', "\\\\")
+            .replace('"', "\\\"")
+            .replace(']', "\\]")
+    };
+    let pri = facility_code(&config.facility) * 8 + severity;
+    let mut data = format!(
+        "[{} camera=\"{}\" event=\"{}\"",
+        SD_ID,
+        escape(&event.camera),
+        event.event.name()
+    );
+    for (key, value) in fields(event) {
+        let _ = write!(data, " {}=\"{}\"", key, escape(value));
+    }
+    data.push(']');
+    format!(
+        "<{}>1 {} {} {} {} {} {} {}",
+        pri,
+        event.time,
+        hostname(),
+        config.ident,
+        std::process::id(),
+        event.event.name(),
+        data,
+        message(event)
+    )
+    .into_bytes()
+}
+
+/// A journal entry of the native journald protocol
+fn journal_entry(config: &EventLogConfig, event: &Event, severity: u8) -> Vec<u8> {
+    let mut entry = vec![
+        ("MESSAGE".to_string(), message(event)),
+        ("PRIORITY".to_string(), severity.to_string()),
+        (
+            "SYSLOG_FACILITY".to_string(),
+            facility_code(&config.facility).to_string(),
+        ),
+        ("SYSLOG_IDENTIFIER".to_string(), config.ident.clone()),
+        ("NEOLINK_CAMERA".to_string(), event.camera.clone()),
+        ("NEOLINK_EVENT".to_string(), event.event.name().to_string()),
+        ("NEOLINK_TIME".to_string(), event.time.clone()),
+        ("NEOLINK_VERSION".to_string(), event.version.to_string()),
+    ];
+    entry.extend(
+        fields(event)
+            .into_iter()
+            .map(|(key, value)| (format!("NEOLINK_{}", key.to_uppercase()), value)),
+    );
+
+    let mut data = vec![];
+    for (key, value) in entry {
+        data.extend_from_slice(key.as_bytes());
+        if value.contains('\n') {
+            // Values with newlines are sent with their length instead
+            data.push(b'\n');
+            data.extend_from_slice(&(value.len() as u64).to_le_bytes());
+        } else {
+            data.push(b'=');
+        }
+        data.extend_from_slice(value.as_bytes());
+        data.push(b'\n');
+    }
+    data
+}
@@ -0,0 +1,26 @@
+use anyhow::{anyhow, Result};
+use clap::Parser;
+
+fn onoff_parse(src: &str) -> Result<bool> {
+    match src {
+        "true" | "on" | "yes" => Ok(true),
+        "false" | "off" | "no" => Ok(false),
+        _ => Err(anyhow!(
+            "Could not understand {}, check your input, should be true/false, on/off or yes/no",
+            src
+        )),
+    }
+}
+
+/// The floodlight command will control the floodlight or spotlight of the camera
+#[derive(Parser, Debug)]
+pub struct Opt {
+    /// The name of the camera. Must be a name in the config
+    pub camera: String,
+    /// Whether to turn the floodlight ON or OFF
+    #[arg(value_parser = onoff_parse, action = clap::ArgAction::Set, name = "on|off")]
+    pub on: Option<bool>,
+    /// The brightness of the floodlight in % when turning it on or off
+    #[arg(short, long, value_parser = clap::value_parser!(u32).range(0..=100))]
+    pub brightness: Option<u32>,
+}
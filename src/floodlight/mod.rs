@@ -0,0 +1,72 @@
+///
+/// # Neolink Floodlight
+///
+/// This module handles the controls of the floodlight or spotlight
+/// of cameras such as the Duo Floodlight
+///
+///
+/// # Usage
+///
+/// ```bash
+/// # To turn the floodlight on at half brightness
+/// neolink floodlight --config=config.toml CameraName on --brightness=50
+/// # Or off
+/// neolink floodlight --config=config.toml CameraName off
+/// # To print the floodlight settings including the brightness
+/// neolink floodlight --config=config.toml CameraName
+/// ```
+///
+use anyhow::{Context, Result};
+
+mod cmdline;
+
+use crate::common::{audit, NeoReactor};
+pub(crate) use cmdline::Opt;
+
+/// Entry point for the floodlight subcommand
+///
+/// Opt is the command line options
+pub(crate) async fn main(opt: Opt, reactor: NeoReactor) -> Result<()> {
+    let camera = reactor.get(&opt.camera).await?;
+
+    if let Some(on) = opt.on {
+        let brightness = opt.brightness;
+        let result = camera
+            .run_task(|cam| {
+                Box::pin(async move {
+                    cam.set_floodlight(on, brightness)
+                        .await
+                        .context("Unable to set camera floodlight state")
+                })
+            })
+            .await;
+        let args = match (on, brightness) {
+            (true, Some(brightness)) => format!("on {}", brightness),
+            (true, None) => "on".to_string(),
+            (false, _) => "off".to_string(),
+        };
+        audit::cli(&opt.camera, "floodlight", &args, &result);
+        result?;
+    } else {
+        let tasks = camera
+            .run_task(|cam| {
+                Box::pin(async move {
+                    cam.get_flightlight_tasks()
+                        .await
+                        .context("Unable to get camera floodlight settings")
+                })
+            })
+            .await?;
+        let tasks_ser = String::from_utf8(
+            {
+                let mut buf = bytes::BytesMut::new();
+                quick_xml::se::to_writer(&mut buf, &tasks).map(|_| buf.to_vec())
+            }
+            .expect("Should Ser the struct"),
+        )
+        .expect("Should be UTF8");
+        println!("{}", tasks_ser);
+    }
+
+    Ok(())
+}
@@ -0,0 +1,8 @@
+use clap::Parser;
+
+/// The hls command serves the cameras as HLS
+///
+/// Each camera's playlist is at `/{CameraName}/index.m3u8` and its substream's
+/// at `/{CameraName}/sub/index.m3u8`
+#[derive(Parser, Debug)]
+pub struct Opt {}
@@ -0,0 +1,477 @@
+///
+/// # Neolink HLS
+///
+/// This module serves the cameras as HLS for players such as tablets and smart
+/// TVs that can play nothing else, without an rtsp to hls step in ffmpeg
+///
+/// A camera's stream is segmented when its playlist is first asked for and
+/// stops once nothing has asked for it for 30s. Each segment starts on a
+/// keyframe of the camera so the latency is a few of the camera's keyframe
+/// intervals
+///
+/// `http://my.ip.address:8888/{CameraName}/index.m3u8` is the playlist of
+/// the main stream and `http://my.ip.address:8888/{CameraName}/sub/index.m3u8`
+/// of the substream
+///
+/// `http://my.ip.address:8888/{CameraName}/` is a page that plays it
+///
+/// # Usage
+///
+/// ```bash
+/// neolink hls --config=config.toml
+/// ```
+///
+/// # Example Config
+///
+/// ```toml
+/// [hls]
+/// port = 8888
+/// # The shortest segment in seconds
+/// segment = 1
+/// # Segments in the playlist
+/// playlist_length = 3
+/// ```
+///
+/// When `users` are in the config the request must give one of them with HTTP
+/// basic auth, limited to the camera's `permitted_users` in the same way as rtsp
+///
+use anyhow::{anyhow, Context, Result};
+use futures::stream::StreamExt;
+use gstreamer::{
+    parse::launch_full, prelude::*, ClockTime, MessageView, ParseFlags, Pipeline, State,
+};
+use gstreamer_app::AppSrc;
+use log::*;
+use neolink_core::bc_protocol::StreamKind;
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+use tokio::{
+    net::TcpListener,
+    sync::watch::{channel as watch, Receiver as WatchReceiver, Sender as WatchSender},
+    task::JoinSet,
+    time::{interval, sleep, timeout, Duration, Instant},
+};
+use tokio_stream::wrappers::BroadcastStream;
+
+mod cmdline;
+
+use crate::{
+    common::{
+        httpd::{self, Response},
+        AudFormat, NeoReactor, StampedData, VidFormat,
+    },
+    config::{Config, HlsConfig},
+    http::{basic_auth, decode_name, permitted},
+    AnyResult,
+};
+pub(crate) use cmdline::Opt;
+
+/// A stream stops once nothing has asked for it for this long
+const IDLE: Duration = Duration::from_secs(30);
+
+/// How long a request for the playlist waits for the first segment
+const FIRST_SEGMENT: Duration = Duration::from_secs(20);
+
+/// The streams that are being segmented by their directory, with the time of
+/// their last request
+type Running = Arc<Mutex<HashMap<PathBuf, WatchSender<Instant>>>>;
+
+/// Entry point for the hls subcommand
+///
+/// Opt is the command line options
+pub(crate) async fn main(_opt: Opt, reactor: NeoReactor) -> Result<()> {
+    gstreamer::init()
+        .context("Unable to start gstreamer ensure it and all plugins are installed")?;
+    let config = reactor.config().await?;
+    let (bind, port) = {
+        let config = config.borrow();
+        (config.hls.bind.clone(), config.hls.port)
+    };
+    let listener = TcpListener::bind((bind.as_str(), port))
+        .await
+        .with_context(|| format!("Failed to bind the HLS port {}:{}", bind, port))?;
+    info!("HLS: Serving on {}:{}", bind, port);
+
+    let running: Running = Default::default();
+    httpd::serve(listener, "HLS", 0, move |request| {
+        let config = config.borrow().clone();
+        let reactor = reactor.clone();
+        let running = running.clone();
+        async move {
+            let response = match request.method.as_str() {
+                "GET" | "HEAD" => {
+                    let auth = request.header("authorization").and_then(basic_auth);
+                    route(&request.path, auth, &config, &reactor, &running).await
+                }
+                _ => Response::text("405 Method Not Allowed", "Only GET is supported"),
+            };
+            // So that players on other origins such as hls.js can fetch it
+            response.with_header("Access-Control-Allow-Origin", "*")
+        }
+    })
+    .await
+}
+
+fn stream_kind(name: &str) -> Option<StreamKind> {
+    match name {
+        "main" => Some(StreamKind::Main),
+        "sub" => Some(StreamKind::Sub),
+        "extern" => Some(StreamKind::Extern),
+        _ => None,
+    }
+}
+
+async fn route(
+    path: &str,
+    auth: Option<(String, String)>,
+    config: &Config,
+    reactor: &NeoReactor,
+    running: &Running,
+) -> Response {
+    let path = path.split('?').next().unwrap_or_default();
+    let parts = path.trim_start_matches('/').split('/').collect::<Vec<_>>();
+    let found = match parts.as_slice() {
+        [name] | [name, ""] => Some((*name, StreamKind::Main, "")),
+        [name, file] => Some(match stream_kind(file) {
+            Some(kind) => (*name, kind, ""),
+            None => (*name, StreamKind::Main, *file),
+        }),
+        [name, kind, file] => stream_kind(kind).map(|kind| (*name, kind, *file)),
+        _ => None,
+    };
+    let Some((name, kind, file)) = found else {
+        return Response::text(
+            "404 Not Found",
+            "Expected /{CameraName}/index.m3u8 or /{CameraName}/sub/index.m3u8",
+        );
+    };
+    let name = decode_name(name);
+    let Some(camera_config) = config
+        .cameras
+        .iter()
+        .find(|camera| camera.enabled && camera.name == name)
+    else {
+        return Response::text("404 Not Found", "No camera with this name");
+    };
+    if !permitted(config, camera_config, auth.as_ref()) {
+        return Response::text("401 Unauthorized", "A permitted user is required");
+    }
+
+    let dir = root(&config.hls).join(format!(
+        "{}_{}",
+        name.replace(|c: char| !c.is_ascii_alphanumeric(), "_"),
+        kind
+    ));
+    if file.is_empty() {
+        let base = if path.ends_with('/') {
+            path.to_string()
+        } else {
+            format!("{}/", path)
+        };
+        return Response::new(
+            "200 OK",
+            "text/html; charset=utf-8",
+            player(&format!("{}index.m3u8", base)).into_bytes(),
+        );
+    }
+
+    if file == "index.m3u8" {
+        touch(&dir, &name, kind, &config.hls, reactor, running);
+        let playlist = dir.join(file);
+        let started = timeout(FIRST_SEGMENT, async {
+            while tokio::fs::metadata(&playlist).await.is_err() {
+                sleep(Duration::from_millis(200)).await;
+            }
+        })
+        .await;
+        if started.is_err() {
+            return Response::text("503 Service Unavailable", "The stream has not started");
+        }
+        return match tokio::fs::read(&playlist).await {
+            Ok(body) => Response::new("200 OK", "application/vnd.apple.mpegurl", body),
+            Err(_) => Response::text("503 Service Unavailable", "The stream has stopped"),
+        };
+    }
+
+    // Only the segments that hlssink2 writes, so nothing else can be read
+    if !file.starts_with("segment")
+        || !file.ends_with(".ts")
+        || !file.chars().all(|c| c.is_ascii_alphanumeric() || c == '.')
+    {
+        return Response::text("404 Not Found", "No such file");
+    }
+    if let Some(last_request) = running.lock().unwrap_or_else(|e| e.into_inner()).get(&dir) {
+        last_request.send_replace(Instant::now());
+    }
+    match tokio::fs::read(dir.join(file)).await {
+        Ok(body) => Response::new("200 OK", "video/mp2t", body),
+        Err(_) => Response::text("404 Not Found", "No such segment"),
+    }
+}
+
+/// The page that plays the playlist, which most browsers on phones, tablets
+/// and TVs can do without any scripts
+fn player(playlist: &str) -> String {
+    format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n\
+        <meta name=\"viewport\" content=\"width=device-width, initial-scale=1\">\n\
+        <title>Neolink</title>\n\
+        <style>html, body {{ margin: 0; height: 100%; background: #000; }} \
+        video {{ width: 100%; height: 100%; object-fit: contain; }}</style>\n\
+        </head>\n<body>\n\
+        <video src=\"{}\" autoplay muted playsinline controls></video>\n\
+        </body>\n</html>\n",
+        playlist
+    )
+}
+
+/// The directory that the streams are segmented into
+fn root(hls: &HlsConfig) -> PathBuf {
+    hls.path
+        .clone()
+        .unwrap_or_else(|| std::env::temp_dir().join("neolink-hls"))
+}
+
+/// Note that the stream was asked for, starting it if it is not running
+fn touch(
+    dir: &Path,
+    name: &str,
+    kind: StreamKind,
+    hls: &HlsConfig,
+    reactor: &NeoReactor,
+    running: &Running,
+) {
+    let mut locked = running.lock().unwrap_or_else(|e| e.into_inner());
+    if let Some(last_request) = locked.get(dir) {
+        last_request.send_replace(Instant::now());
+        return;
+    }
+    let (last_request_tx, last_request) = watch(Instant::now());
+    locked.insert(dir.to_path_buf(), last_request_tx);
+    drop(locked);
+
+    let dir = dir.to_path_buf();
+    let name = name.to_string();
+    let hls = hls.clone();
+    let reactor = reactor.clone();
+    let running = running.clone();
+    tokio::task::spawn(async move {
+        info!("{}: HLS: Starting the {} stream", name, kind);
+        match segment(&dir, &name, kind, &hls, &reactor, last_request).await {
+            Ok(()) => info!(
+                "{}: HLS: Stopped the {} stream as no one is watching",
+                name, kind
+            ),
+            Err(e) => warn!("{}: HLS: The {} stream stopped: {:?}", name, kind, e),
+        }
+        // The directory is gone before it is removed from the running streams
+        // so that a new one does not lose its segments
+        let _ = tokio::fs::remove_dir_all(&dir).await;
+        running
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .remove(&dir);
+    });
+}
+
+/// Segment the stream into the directory until no one has asked for it for
+/// a while
+async fn segment(
+    dir: &Path,
+    name: &str,
+    kind: StreamKind,
+    hls: &HlsConfig,
+    reactor: &NeoReactor,
+    last_request: WatchReceiver<Instant>,
+) -> AnyResult<()> {
+    let camera = reactor.get(name).await?;
+    let mut stream = camera.stream(kind).await.context("Failed to start video")?;
+    stream.config.wait_for(|config| config.vid_ready()).await?;
+    // After vid give it 1s to look for audio
+    if let Ok(v) = timeout(
+        Duration::from_secs(1),
+        stream.config.wait_for(|config| config.aud_ready()),
+    )
+    .await
+    {
+        v?;
+    }
+    let stream_config = stream.config.borrow().clone();
+    // Only AAC can be put into the segments as is
+    let audio = matches!(stream_config.aud_format, AudFormat::Aac);
+
+    // Segments left over from a neolink that stopped abruptly
+    match tokio::fs::remove_dir_all(dir).await {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(e.into()),
+        _ => {}
+    }
+    tokio::fs::create_dir_all(dir)
+        .await
+        .with_context(|| format!("Failed to create {}", dir.display()))?;
+    let (pipeline, vidsrc, audsrc) = create_pipeline(stream_config.vid_format, audio, dir, hls)?;
+    let mut set = JoinSet::<AnyResult<()>>::new();
+    let thread_pipeline = pipeline.clone();
+    set.spawn_blocking(move || start_pipeline(thread_pipeline));
+
+    let mut config = stream.config.clone();
+    let mut vid = BroadcastStream::new(stream.vid.resubscribe());
+    let mut aud = BroadcastStream::new(stream.aud.resubscribe());
+    let res = tokio::select! {
+        v = async {
+            // Each segment must start on a keyframe
+            let mut started = false;
+            loop {
+                tokio::select! {
+                    data = vid.next() => match data {
+                        Some(Ok(StampedData { data, keyframe, .. })) => {
+                            started |= keyframe;
+                            if started {
+                                push(&vidsrc, &data)?;
+                            }
+                        }
+                        // Lagged, the player copes with a gap
+                        Some(Err(_)) => {}
+                        None => return Err(anyhow!("The stream ended")),
+                    },
+                    data = aud.next(), if audsrc.is_some() => match (data, audsrc.as_ref()) {
+                        (Some(Ok(StampedData { data, .. })), Some(audsrc)) if started => {
+                            push(audsrc, &data)?;
+                        }
+                        (None, _) => return Err(anyhow!("The audio ended")),
+                        _ => {}
+                    },
+                }
+            }
+        } => v,
+        _ = async {
+            let mut check = interval(Duration::from_secs(5));
+            loop {
+                check.tick().await;
+                if last_request.borrow().elapsed() > IDLE {
+                    break;
+                }
+            }
+        } => Ok(()),
+        v = config.wait_for(|config| config != &stream_config) => {
+            v?;
+            Err(anyhow!("The stream format changed"))
+        },
+        v = set.join_next() => v
+            .unwrap_or(Ok(Ok(())))
+            .map_err(anyhow::Error::from)
+            .and_then(|v| v),
+    };
+
+    let _ = pipeline.set_state(State::Null);
+    while set.join_next().await.is_some() {}
+    res
+}
+
+fn push(source: &AppSrc, data: &[u8]) -> AnyResult<()> {
+    let mut buf = gstreamer::Buffer::with_size(data.len())?;
+    buf.get_mut()
+        .ok_or_else(|| anyhow!("New buffer should be writable"))?
+        .copy_from_slice(0, data)
+        .map_err(|_| anyhow!("Failed to copy the frame"))?;
+    source
+        .push_buffer(buf)
+        .map_err(|e| anyhow!("Streamer Error: {e:?}"))?;
+    Ok(())
+}
+
+fn start_pipeline(pipeline: Pipeline) -> AnyResult<()> {
+    pipeline.set_state(State::Playing)?;
+
+    let bus = pipeline
+        .bus()
+        .expect("Pipeline without bus. Shouldn't happen!");
+
+    let res = loop {
+        match bus.timed_pop(ClockTime::from_mseconds(500)) {
+            Some(msg) => match msg.view() {
+                MessageView::Eos(..) => break Ok(()),
+                MessageView::Error(err) => {
+                    break Err(anyhow!("Error from gstreamer in the segmenter: {:?}", err));
+                }
+                _ => (),
+            },
+            // Stopped as no one is watching
+            None if pipeline.current_state() == State::Null => break Ok(()),
+            None => (),
+        }
+    };
+
+    pipeline
+        .set_state(State::Null)
+        .context("Error in gstreamer when setting state to Null")?;
+    res
+}
+
+/// Segment the stream into the directory with a playlist
+fn create_pipeline(
+    vid_format: VidFormat,
+    audio: bool,
+    dir: &Path,
+    hls: &HlsConfig,
+) -> AnyResult<(Pipeline, AppSrc, Option<AppSrc>)> {
+    let parser = match vid_format {
+        VidFormat::H264 => "h264parse",
+        VidFormat::H265 => "h265parse",
+        VidFormat::None => return Err(anyhow!("The camera has not sent its video format")),
+    };
+    // A few segments past the playlist are kept for players that are behind.
+    // The camera cannot be asked for a keyframe so a segment ends on the
+    // first one after `segment` seconds
+    let mut launch_str = format!(
+        "hlssink2 name=sink location=\"{}\" playlist-location=\"{}\" \
+        target-duration={} playlist-length={} max-files={} send-keyframe-requests=false \
+        appsrc name=vidsrc is-live=true do-timestamp=true format=time \
+        ! {} config-interval=-1 \
+        ! sink.video",
+        dir.join("segment%05d.ts").display(),
+        dir.join("index.m3u8").display(),
+        hls.segment,
+        hls.playlist_length,
+        hls.playlist_length + 3,
+        parser,
+    );
+    if audio {
+        launch_str.push_str(
+            " appsrc name=audsrc is-live=true do-timestamp=true format=time \
+            ! aacparse \
+            ! sink.audio",
+        );
+    }
+    debug!("{}", launch_str);
+
+    let pipeline = launch_full(&launch_str, None, ParseFlags::empty())
+        .context("Unable to load gstreamer pipeline ensure all gstramer plugins are installed")?
+        .dynamic_cast::<Pipeline>()
+        .map_err(|_| {
+            anyhow!("Unable to create gstreamer pipeline ensure all gstramer plugins are installed")
+        })?;
+
+    let vidsrc = pipeline
+        .by_name("vidsrc")
+        .expect("There shoud be a `vidsrc`")
+        .dynamic_cast::<AppSrc>()
+        .map_err(|_| anyhow!("Cannot find appsource in gstreamer, check your gstreamer plugins"))?;
+    let audsrc = if audio {
+        Some(
+            pipeline
+                .by_name("audsrc")
+                .expect("There shoud be an `audsrc`")
+                .dynamic_cast::<AppSrc>()
+                .map_err(|_| {
+                    anyhow!("Cannot find appsource in gstreamer, check your gstreamer plugins")
+                })?,
+        )
+    } else {
+        None
+    };
+
+    Ok((pipeline, vidsrc, audsrc))
+}
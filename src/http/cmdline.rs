@@ -0,0 +1,8 @@
+use clap::Parser;
+
+/// The http command serves still images of the cameras over HTTP
+///
+/// Each camera's image is at `/{CameraName}/snap.jpg` and a thumbnail of the
+/// substream at `/{CameraName}/thumb.jpg`
+#[derive(Parser, Debug)]
+pub struct Opt {}
@@ -0,0 +1,294 @@
+///
+/// # Neolink HTTP
+///
+/// This module serves a still image of each camera over HTTP for dashboards
+/// such as Home Assistant that want an image url
+///
+/// The cameras stay logged in between requests and the images are taken with
+/// the snap command in the same way as `neolink image`. Requests within the
+/// camera's `snapshot_max_age` share one image
+///
+/// `http://my.ip.address:8080/{CameraName}/snap.jpg`
+///
+/// A small thumbnail decoded from the substream is at `/{CameraName}/thumb.jpg`
+///
+/// The abilities that the camera reports for the user are at
+/// `/{CameraName}/capabilities.json`
+///
+/// The last errors that dropped the camera's connection are at
+/// `/{CameraName}/errors.json`
+///
+/// A clip of the `[[clips]]` config is played on the camera's speaker with a
+/// POST to `/{CameraName}/clips/{ClipName}`
+///
+/// # Usage
+///
+/// ```bash
+/// neolink http --config=config.toml
+/// ```
+///
+/// # Example Config
+///
+/// ```toml
+/// [http]
+/// port = 8080
+/// ```
+///
+/// When `users` are in the config the request must give one of them with HTTP
+/// basic auth, limited to the camera's `permitted_users` in the same way as rtsp
+///
+use anyhow::{Context, Result};
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine as _};
+use log::*;
+use tokio::{net::TcpListener, sync::watch::Receiver as WatchReceiver};
+
+mod cmdline;
+
+use crate::{
+    common::{
+        httpd::{self, Request, Response},
+        NeoReactor,
+    },
+    config::{CameraConfig, Config},
+};
+pub(crate) use cmdline::Opt;
+
+/// Largest request body that is accepted, the clips are played with an empty POST
+const MAX_BODY: usize = 16 * 1024;
+
+/// Entry point for the http subcommand
+///
+/// Opt is the command line options
+pub(crate) async fn main(_opt: Opt, reactor: NeoReactor) -> Result<()> {
+    let config = reactor.config().await?;
+    let (bind, port) = {
+        let config = config.borrow();
+        (config.http.bind.clone(), config.http.port)
+    };
+    let listener = TcpListener::bind((bind.as_str(), port))
+        .await
+        .with_context(|| format!("Failed to bind the HTTP port {}:{}", bind, port))?;
+    info!("HTTP: Serving on {}:{}", bind, port);
+
+    serve(listener, config, reactor).await
+}
+
+async fn serve(
+    listener: TcpListener,
+    config: WatchReceiver<Config>,
+    reactor: NeoReactor,
+) -> Result<()> {
+    httpd::serve(listener, "HTTP", MAX_BODY, move |request| {
+        let config = config.borrow().clone();
+        let reactor = reactor.clone();
+        async move { handle_request(request, &config, &reactor).await }
+    })
+    .await
+}
+
+async fn handle_request(request: Request, config: &Config, reactor: &NeoReactor) -> Response {
+    let auth = request.header("authorization").and_then(basic_auth);
+    match request.method.as_str() {
+        "POST" => play_clip(&request.path, auth, config, reactor).await,
+        "GET" | "HEAD" => route(&request.path, auth, config, reactor).await,
+        _ => Response::text("405 Method Not Allowed", "Only GET and POST are supported"),
+    }
+}
+
+async fn route(
+    path: &str,
+    auth: Option<(String, String)>,
+    config: &Config,
+    reactor: &NeoReactor,
+) -> Response {
+    let path = path.split('?').next().unwrap_or_default();
+    let Some((name, file)) = path
+        .strip_prefix('/')
+        .and_then(|path| path.rsplit_once('/'))
+        .filter(|(_, file)| {
+            ["snap.jpg", "thumb.jpg", "capabilities.json", "errors.json"].contains(file)
+        })
+        .map(|(name, file)| (decode_name(name), file))
+    else {
+        return Response::text(
+            "404 Not Found",
+            "Expected /{CameraName}/snap.jpg, /{CameraName}/thumb.jpg, /{CameraName}/capabilities.json or /{CameraName}/errors.json",
+        );
+    };
+    let Some(camera_config) = config
+        .cameras
+        .iter()
+        .find(|camera| camera.enabled && camera.name == name)
+    else {
+        return Response::text("404 Not Found", "No camera with this name");
+    };
+    if !permitted(config, camera_config, auth.as_ref()) {
+        return Response::text("401 Unauthorized", "A permitted user is required");
+    }
+
+    if file == "capabilities.json" {
+        let report = async {
+            let camera = reactor.get(&name).await?;
+            Ok::<_, anyhow::Error>(serde_json::to_vec(&camera.ability_report().await?)?)
+        }
+        .await;
+        return match report {
+            Ok(json) => Response::new("200 OK", "application/json", json),
+            Err(e) => {
+                warn!("{}: Failed to get the capabilities: {:?}", name, e);
+                Response::text("503 Service Unavailable", "Failed to get the capabilities")
+            }
+        };
+    }
+
+    if file == "errors.json" {
+        let report = async {
+            let camera = reactor.get(&name).await?;
+            Ok::<_, anyhow::Error>(serde_json::to_vec(&camera.error_report().await?)?)
+        }
+        .await;
+        return match report {
+            Ok(json) => Response::new("200 OK", "application/json", json),
+            Err(e) => {
+                warn!("{}: Failed to get the errors: {:?}", name, e);
+                Response::text("503 Service Unavailable", "Failed to get the errors")
+            }
+        };
+    }
+
+    let image = async {
+        let camera = reactor.get(&name).await?;
+        if file == "thumb.jpg" {
+            camera.thumbnail().await
+        } else {
+            camera.snapshot().await
+        }
+    }
+    .await;
+    match image {
+        Ok(jpeg) => Response::new("200 OK", "image/jpeg", jpeg),
+        Err(e) => {
+            warn!("{}: Failed to get the image: {:?}", name, e);
+            Response::text("503 Service Unavailable", "Failed to get the image")
+        }
+    }
+}
+
+/// `POST /{CameraName}/clips/{ClipName}`
+async fn play_clip(
+    path: &str,
+    auth: Option<(String, String)>,
+    config: &Config,
+    reactor: &NeoReactor,
+) -> Response {
+    let path = path.split('?').next().unwrap_or_default();
+    let Some((name, clip)) = path
+        .strip_prefix('/')
+        .and_then(|path| path.split_once("/clips/"))
+        .map(|(name, clip)| (decode_name(name), decode_name(clip)))
+    else {
+        return Response::text("404 Not Found", "Expected /{CameraName}/clips/{ClipName}");
+    };
+    let Some(camera_config) = config
+        .cameras
+        .iter()
+        .find(|camera| camera.enabled && camera.name == name)
+    else {
+        return Response::text("404 Not Found", "No camera with this name");
+    };
+    if !permitted(config, camera_config, auth.as_ref()) {
+        return Response::text("401 Unauthorized", "A permitted user is required");
+    }
+    if !config.clips.iter().any(|config| config.name == clip) {
+        return Response::text("404 Not Found", "No clip with this name");
+    }
+
+    #[cfg(feature = "gstreamer")]
+    {
+        let played = async {
+            let camera = reactor.get(&name).await?;
+            crate::talk::clips::play(&camera, &config.clips, &clip).await
+        }
+        .await;
+        match played {
+            Ok(()) => Response::text("200 OK", "OK"),
+            Err(e) => {
+                warn!("{}: Failed to play the clip: {:?}", name, e);
+                Response::text("503 Service Unavailable", "Failed to play the clip")
+            }
+        }
+    }
+    #[cfg(not(feature = "gstreamer"))]
+    {
+        let _ = reactor;
+        Response::text(
+            "501 Not Implemented",
+            "This neolink was built without gstreamer so it cannot play clips",
+        )
+    }
+}
+
+/// If the user may see the camera, following the rtsp rules
+///
+/// Without users in the config anyone may. Otherwise it must be a user of the
+/// config that is in the camera's `permitted_users`, all users when it has none
+/// or has `anyone`. `anonymous` in `permitted_users` needs no user
+pub(crate) fn permitted(
+    config: &Config,
+    camera: &CameraConfig,
+    auth: Option<&(String, String)>,
+) -> bool {
+    let permitted = camera.permitted_users.as_ref();
+    if config.users.is_empty()
+        || permitted.is_some_and(|users| users.iter().any(|u| u == "anonymous"))
+    {
+        return true;
+    }
+    let Some((name, pass)) = auth else {
+        return false;
+    };
+    let known = config
+        .users
+        .iter()
+        .any(|user| &user.name == name && &user.pass == pass);
+    known
+        && match permitted {
+            Some(users) => users.iter().any(|u| u == "anyone" || u == name),
+            None => true,
+        }
+}
+
+/// The user and password of a `Basic` authorization header
+pub(crate) fn basic_auth(value: &str) -> Option<(String, String)> {
+    let (scheme, credentials) = value.split_once(' ')?;
+    if !scheme.eq_ignore_ascii_case("basic") {
+        return None;
+    }
+    let decoded = String::from_utf8(BASE64.decode(credentials.trim()).ok()?).ok()?;
+    let (name, pass) = decoded.split_once(':')?;
+    Some((name.to_string(), pass.to_string()))
+}
+
+/// Undo the percent encoding of a camera name in a url
+pub(crate) fn decode_name(name: &str) -> String {
+    let bytes = name.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = bytes
+            .get(i + 1..i + 3)
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match (bytes[i], hex) {
+            (b'%', Some(b)) => {
+                decoded.push(b);
+                i += 3;
+            }
+            (b, _) => {
+                decoded.push(b);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).to_string()
+}
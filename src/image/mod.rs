@@ -17,17 +17,21 @@
 /// neolink image --config=config.toml --use_stream --file-path=filepath CameraName
 /// ```
 ///
+/// The capture time, camera name and model are added to the jpeg as EXIF
+/// unless `snapshot_exif = false` is set for the camera
+///
 use anyhow::{Context, Result};
 use futures::stream::StreamExt;
 use log::*;
 use neolink_core::bc_protocol::*;
+use std::path::Path;
 use tokio::{fs::File, io::AsyncWriteExt};
 use tokio_stream::wrappers::BroadcastStream;
 
 mod cmdline;
 mod gst;
 
-use crate::common::{NeoReactor, StampedData};
+use crate::common::{NeoInstance, NeoReactor, StampedData};
 pub(crate) use cmdline::Opt;
 
 /// Entry point for the image subcommand
@@ -35,8 +39,19 @@ pub(crate) use cmdline::Opt;
 /// Opt is the command line options
 pub(crate) async fn main(opt: Opt, reactor: NeoReactor) -> Result<()> {
     let camera = reactor.get(&opt.camera).await?;
+    capture(&camera, opt.use_stream, &opt.file_path).await
+}
 
-    if opt.use_stream {
+/// Save a still image of the camera to `file_path` as a jpeg
+///
+/// With `use_stream` the image is decoded from a keyframe of the main stream
+/// rather than taken with the snap command
+pub(crate) async fn capture(
+    camera: &NeoInstance,
+    use_stream: bool,
+    file_path: &Path,
+) -> Result<()> {
+    if use_stream {
         let stream_data = camera
             .stream(StreamKind::Main)
             .await
@@ -63,7 +78,7 @@ pub(crate) async fn main(opt: Opt, reactor: NeoReactor) -> Result<()> {
             }
         };
 
-        let mut sender = gst::from_input(vid_type, &opt.file_path).await?;
+        let mut sender = gst::from_input(vid_type, file_path).await?;
         sender.send(buf).await?; // Send first iframe
 
         // Keep sending both IFrame or PFrame until finished
@@ -85,14 +100,20 @@ pub(crate) async fn main(opt: Opt, reactor: NeoReactor) -> Result<()> {
         debug!("Sending EOS");
         let _ = sender.eos().await; // Ignore return because if pipeline is finished this will error
         let _ = sender.join().await;
+
+        // Tag the jpeg that gstreamer wrote, the snap command's are already tagged
+        let file_path = file_path.with_extension("jpeg");
+        let jpeg_data = tokio::fs::read(&file_path)
+            .await
+            .context("Failed to read back the image")?;
+        let jpeg_data = camera.exif(&jpeg_data).await?;
+        tokio::fs::write(&file_path, jpeg_data).await?;
     } else {
         // Simply use the snap command
         debug!("Using the snap command");
-        let file_path = opt.file_path.with_extension("jpeg");
+        let file_path = file_path.with_extension("jpeg");
         let mut buffer = File::create(file_path).await?;
-        let jpeg_data = camera
-            .run_task(|camera| Box::pin(async move { Ok(camera.get_snapshot().await?) }))
-            .await;
+        let jpeg_data = camera.snapshot().await;
         if jpeg_data.is_err() {
             log::debug!("jpeg_data: {:?}", jpeg_data);
         }
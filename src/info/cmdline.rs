@@ -0,0 +1,14 @@
+use clap::Parser;
+
+/// The info command prints what a camera is and what it supports as JSON
+#[derive(Parser, Debug)]
+pub struct Opt {
+    /// The name of the camera. Must be a name in the config
+    pub camera: String,
+    /// Include the abilities that the camera reports for the user
+    #[arg(long)]
+    pub capabilities: bool,
+    /// Print the JSON on one line
+    #[arg(long)]
+    pub compact: bool,
+}
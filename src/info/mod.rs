@@ -0,0 +1,89 @@
+///
+/// # Neolink Info
+///
+/// This module handles the info subcommand
+///
+/// It prints the model and firmware of a camera as JSON. With `--capabilities`
+/// the abilities that the camera reports for the user are included so that it
+/// is known which features this model and firmware allow
+///
+/// # Usage
+///
+/// ```bash
+/// neolink info --config=config.toml --capabilities CameraName
+/// ```
+///
+/// Which prints something like
+///
+/// ```json
+/// {
+///   "name": "CameraName",
+///   "model": "RLC-810A",
+///   "firmware": "v3.1.0.956_22041503",
+///   "hardware": "IPC_523128M8MP",
+///   "capabilities": {
+///     "username": "admin",
+///     "modules": {
+///       "PTZ": {"control": "rw", "preset": "rw"},
+///       "replay": {"replay": "ro"},
+///       ...
+///     }
+///   }
+/// }
+/// ```
+///
+use anyhow::{Context, Result};
+use neolink_core::bc_protocol::AbilityReport;
+use serde::Serialize;
+
+mod cmdline;
+
+use crate::common::NeoReactor;
+pub(crate) use cmdline::Opt;
+
+#[derive(Serialize)]
+struct Info {
+    name: String,
+    model: Option<String>,
+    firmware: String,
+    hardware: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    capabilities: Option<AbilityReport>,
+}
+
+/// Entry point for the info subcommand
+///
+/// Opt is the command line options
+pub(crate) async fn main(opt: Opt, reactor: NeoReactor) -> Result<()> {
+    let camera = reactor.get(&opt.camera).await?;
+
+    let version = camera
+        .run_task(|cam| {
+            Box::pin(async move {
+                cam.version()
+                    .await
+                    .context("Unable to get the camera's version")
+            })
+        })
+        .await?;
+    let capabilities = if opt.capabilities {
+        Some(camera.ability_report().await?)
+    } else {
+        None
+    };
+
+    let info = Info {
+        name: version.name,
+        model: version.model,
+        firmware: version.firmwareVersion,
+        hardware: version.hardwareVersion,
+        capabilities,
+    };
+    let json = if opt.compact {
+        serde_json::to_string(&info)?
+    } else {
+        serde_json::to_string_pretty(&info)?
+    };
+    println!("{}", json);
+    Ok(())
+}
@@ -0,0 +1,15 @@
+use clap::Parser;
+use std::net::Ipv4Addr;
+
+/// The init command interactively creates a starting config
+///
+/// The config is written to the path given by `--config`
+#[derive(Parser, Debug)]
+pub struct Opt {
+    /// Any address in the /24 subnet to scan for cameras. Defaults to the subnet of this machine
+    #[arg(long)]
+    pub subnet: Option<Ipv4Addr>,
+    /// Overwrite the config if it already exists
+    #[arg(long)]
+    pub force: bool,
+}
@@ -0,0 +1,254 @@
+///
+/// # Neolink Init
+///
+/// This module handles the init subcommand
+///
+/// It scans the local network for cameras, asks which ones to add
+/// and their credentials, checks that it can log in to each one
+/// and then writes a starting config
+///
+/// # Usage
+///
+/// ```bash
+/// neolink init --config=config.toml
+/// ```
+///
+use anyhow::{anyhow, Context, Result};
+use futures::future::join_all;
+use serde::Serialize;
+use std::{
+    fmt::{Display, Error as FmtError, Formatter},
+    fs,
+    io::{self, BufRead, Write},
+    net::{IpAddr, Ipv4Addr, SocketAddr, UdpSocket},
+    path::Path,
+};
+use tokio::{
+    net::TcpStream,
+    time::{timeout, Duration},
+};
+use validator::Validate;
+
+mod cmdline;
+
+use crate::{
+    config::{CameraConfig, Config},
+    utils::connect_and_login,
+};
+pub(crate) use cmdline::Opt;
+
+/// The port that cameras listen on for TCP connections
+const BC_PORT: u16 = 9000;
+
+/// The parts of a camera config that the wizard fills in
+#[derive(Serialize)]
+struct InitCamera {
+    name: String,
+    username: String,
+    password: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    address: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    uid: Option<String>,
+}
+
+#[derive(Serialize)]
+struct InitConfig {
+    bind: String,
+    cameras: Vec<InitCamera>,
+}
+
+enum Target {
+    Address(String),
+    Uid(String),
+}
+
+impl Display for Target {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), FmtError> {
+        match self {
+            Target::Address(addr) => write!(f, "Address: {}", addr),
+            Target::Uid(uid) => write!(f, "UID: {}", uid),
+        }
+    }
+}
+
+/// Entry point for the init subcommand
+///
+/// Opt is the command line options and conf_path is where the config is written
+pub(crate) async fn main(opt: Opt, conf_path: &Path) -> Result<()> {
+    if conf_path.exists() && !opt.force {
+        return Err(anyhow!(
+            "{:?} already exists, use --force to overwrite it",
+            conf_path
+        ));
+    }
+
+    let subnet = match opt.subnet {
+        Some(subnet) => subnet,
+        None => local_ipv4().context("Could not find the subnet of this machine, use --subnet")?,
+    };
+    let [a, b, c, _] = subnet.octets();
+    println!("Scanning {}.{}.{}.0/24 for cameras...", a, b, c);
+    let found = scan(subnet).await;
+
+    let default_selection = if found.is_empty() {
+        println!("No cameras were found on port {}", BC_PORT);
+        None
+    } else {
+        println!("Found:");
+        for (i, addr) in found.iter().enumerate() {
+            println!("  {}) {}", i + 1, addr);
+        }
+        Some("all")
+    };
+    println!("Battery cameras do not show up in the scan, add them by UID");
+    let selection = prompt(
+        "Cameras to add as numbers from the list, addresses or UIDs (comma separated)",
+        default_selection,
+    )?;
+    let targets = parse_selection(&selection, &found)?;
+
+    let mut cameras: Vec<InitCamera> = vec![];
+    for target in targets {
+        println!();
+        println!("{}", target);
+        loop {
+            let (address, uid) = match &target {
+                Target::Address(addr) => (Some(addr.clone()), None),
+                Target::Uid(uid) => (None, Some(uid.clone())),
+            };
+            let mut camera = InitCamera {
+                name: format!("Camera{:02}", cameras.len() + 1),
+                username: prompt("Username", Some("admin"))?,
+                password: prompt("Password", None)?,
+                address,
+                uid,
+            };
+            match verify(&camera).await {
+                Ok((device_name, uid)) => {
+                    println!("Logged in");
+                    let default_name = device_name
+                        .map(|name| name.split_whitespace().collect::<Vec<_>>().join("_"))
+                        .filter(|name| !name.is_empty())
+                        .unwrap_or_else(|| camera.name.clone());
+                    camera.name = prompt("Name", Some(&default_name))?;
+                    if camera.uid.is_none() {
+                        camera.uid = uid;
+                    }
+                    cameras.push(camera);
+                    break;
+                }
+                Err(e) => {
+                    println!("Login failed: {:#}", e);
+                    if prompt("Try again? (y/n)", Some("y"))?.to_lowercase() != "y" {
+                        break;
+                    }
+                }
+            }
+        }
+    }
+
+    if cameras.is_empty() {
+        return Err(anyhow!("No cameras were added, the config was not written"));
+    }
+
+    let text = toml::to_string(&InitConfig {
+        bind: "0.0.0.0".to_string(),
+        cameras,
+    })
+    .context("Failed to serialise the config")?;
+    // Check it round trips so that we never write a config neolink cannot load
+    let config: Config = toml::from_str(&text).context("Failed to parse the new config")?;
+    config
+        .validate()
+        .context("Failed to validate the new config")?;
+    fs::write(conf_path, text).with_context(|| format!("Failed to write {:?}", conf_path))?;
+
+    println!();
+    println!("Wrote {:?}", conf_path);
+    println!("Start the rtsp server with: neolink rtsp --config={:?}", conf_path);
+    Ok(())
+}
+
+/// The IPv4 address of the interface with the default route
+fn local_ipv4() -> Result<Ipv4Addr> {
+    // Connecting a UDP socket sends nothing, it only picks the interface
+    let socket = UdpSocket::bind("0.0.0.0:0")?;
+    socket.connect("8.8.8.8:80")?;
+    match socket.local_addr()?.ip() {
+        IpAddr::V4(ip) => Ok(ip),
+        IpAddr::V6(_) => Err(anyhow!("No IPv4 address")),
+    }
+}
+
+/// Find every address in the /24 that accepts TCP connections on the camera port
+async fn scan(subnet: Ipv4Addr) -> Vec<SocketAddr> {
+    let [a, b, c, _] = subnet.octets();
+    join_all((1..255).map(|d| async move {
+        let addr = SocketAddr::from(([a, b, c, d], BC_PORT));
+        match timeout(Duration::from_millis(500), TcpStream::connect(addr)).await {
+            Ok(Ok(_)) => Some(addr),
+            _ => None,
+        }
+    }))
+    .await
+    .into_iter()
+    .flatten()
+    .collect()
+}
+
+fn parse_selection(selection: &str, found: &[SocketAddr]) -> Result<Vec<Target>> {
+    let mut targets = vec![];
+    for item in selection
+        .split(',')
+        .map(|item| item.trim())
+        .filter(|item| !item.is_empty())
+    {
+        if item.eq_ignore_ascii_case("all") {
+            targets.extend(found.iter().map(|addr| Target::Address(addr.to_string())));
+        } else if let Ok(n) = item.parse::<usize>() {
+            let addr = n
+                .checked_sub(1)
+                .and_then(|i| found.get(i))
+                .ok_or_else(|| anyhow!("{} is not in the list", n))?;
+            targets.push(Target::Address(addr.to_string()));
+        } else if item.contains('.') || item.contains(':') {
+            targets.push(Target::Address(item.to_string()));
+        } else {
+            targets.push(Target::Uid(item.to_string()));
+        }
+    }
+    Ok(targets)
+}
+
+/// Log in to the camera and return its device name and UID
+async fn verify(camera: &InitCamera) -> Result<(Option<String>, Option<String>)> {
+    let config: CameraConfig = toml::from_str(&toml::to_string(camera)?)?;
+    let bc = timeout(Duration::from_secs(30), connect_and_login(&config))
+        .await
+        .context("Timed out connecting to the camera")??;
+    let name = bc.version().await.ok().map(|version| version.name);
+    let uid = bc.get_uid().await.ok().map(|uid| uid.uid);
+    let _ = bc.logout().await;
+    let _ = bc.shutdown().await;
+    Ok((name, uid))
+}
+
+/// Print the question and read a line, an empty line gives the default
+fn prompt(question: &str, default: Option<&str>) -> Result<String> {
+    match default {
+        Some(default) => print!("{} [{}]: ", question, default),
+        None => print!("{}: ", question),
+    }
+    io::stdout().flush()?;
+    let mut line = String::new();
+    if io::stdin().lock().read_line(&mut line)? == 0 {
+        return Err(anyhow!("No more input"));
+    }
+    let line = line.trim();
+    if line.is_empty() {
+        Ok(default.unwrap_or_default().to_string())
+    } else {
+        Ok(line.to_string())
+    }
+}
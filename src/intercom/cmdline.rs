@@ -0,0 +1,19 @@
+use clap::Parser;
+
+/// The intercom command links two cameras so that what one hears the other says
+///
+/// The audio of each camera's stream is played on the speaker of the other
+/// until neolink is stopped
+#[derive(Parser, Debug)]
+pub struct Opt {
+    /// The first camera. Must be a name in the config
+    pub first: String,
+    /// The second camera. Must be a name in the config
+    pub second: String,
+    /// Only send the audio of the first camera to the second and not back
+    #[arg(long)]
+    pub one_way: bool,
+    /// Use to change the volume of the audio that is passed on
+    #[arg(short, long, default_value = "1.0")]
+    pub volume: f32,
+}
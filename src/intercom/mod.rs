@@ -0,0 +1,127 @@
+///
+/// # Neolink Intercom
+///
+/// This module links two cameras so that the audio of each is played on the
+/// speaker of the other, turning two doorbells or cameras into a crude intercom
+///
+/// The audio is taken from the substream where there is one and encoded for
+/// the other camera in the same way as `neolink talk`. Cameras that are close
+/// enough to hear each other will echo
+///
+/// # Usage
+///
+/// ```bash
+/// neolink intercom --config=config.toml FrontDoor Garage
+/// # Only from the first to the second such as a baby monitor
+/// neolink intercom --config=config.toml --one-way Nursery Kitchen
+/// ```
+///
+use anyhow::{anyhow, Context, Result};
+use futures::stream::StreamExt;
+use tokio_stream::wrappers::BroadcastStream;
+
+mod cmdline;
+
+use crate::{
+    common::{AudFormat, NeoInstance, NeoReactor, StampedData},
+    talk::{gst, talk_config},
+    AnyResult,
+};
+pub(crate) use cmdline::Opt;
+use neolink_core::bc_protocol::StreamKind;
+
+/// Entry point for the intercom subcommand
+///
+/// Opt is the command line options
+pub(crate) async fn main(opt: Opt, reactor: NeoReactor) -> Result<()> {
+    if opt.first == opt.second {
+        return Err(anyhow!("The intercom needs two different cameras"));
+    }
+    let first = reactor.get(&opt.first).await?;
+    let second = reactor.get(&opt.second).await?;
+
+    log::info!("Intercom between {} and {}", opt.first, opt.second);
+    if opt.one_way {
+        relay(&first, &second, opt.volume).await
+    } else {
+        tokio::select! {
+            v = relay(&first, &second, opt.volume) => v,
+            v = relay(&second, &first, opt.volume) => v,
+        }
+    }
+}
+
+/// Play the audio of `from` on the speaker of `to` until either fails
+async fn relay(from: &NeoInstance, to: &NeoInstance, volume: f32) -> Result<()> {
+    let from_name = from.config().await?.borrow().name.clone();
+    let to_name = to.config().await?.borrow().name.clone();
+    let talk_config = talk_config(to)
+        .await
+        .with_context(|| format!("Camera {} does not support talk", to_name))?;
+    let format = (
+        (talk_config.audio_config.length_per_encoder / 2) + 4,
+        talk_config.audio_config.sample_rate,
+    );
+
+    // The substream has the same audio for less data
+    let stream = match from.low_stream().await? {
+        Some(stream) => stream,
+        None => from.stream(StreamKind::Main).await?,
+    };
+    let aud_format = stream
+        .config
+        .clone()
+        .wait_for(|config| config.aud_ready())
+        .await?
+        .aud_format;
+    let source = match aud_format {
+        AudFormat::Aac => {
+            "appsrc name=thesource is-live=true do-timestamp=true format=time ! aacparse"
+                .to_string()
+        }
+        AudFormat::Adpcm(block_size) => format!(
+            "appsrc name=thesource is-live=true do-timestamp=true format=time \
+            caps=\"audio/x-adpcm,layout=(string)dvi,block_align=(int){},channels=(int)1,rate=(int)8000\"",
+            block_size
+        ),
+        AudFormat::None => unreachable!(),
+    };
+    let (mut set, mut rxs, appsrc) = gst::from_appsrc(&source, volume, &[format])
+        .context("Failed to setup gst for the intercom")?;
+    let rx = rxs.remove(0);
+    log::info!("{} -> {}: Relaying audio", from_name, to_name);
+
+    let res = tokio::select! {
+        v = to.run_task(|cam| {
+            let rx = rx.clone();
+            let talk_config = talk_config.clone();
+            Box::pin(async move {
+                cam.talk_stream(rx, talk_config).await?;
+                Ok(())
+            })
+        }) => v.with_context(|| format!("{}: Talk stream ended early", to_name)),
+        v = async {
+            let mut aud = BroadcastStream::new(stream.aud.resubscribe());
+            while let Some(data) = aud.next().await {
+                // Lagged, a gap in the audio is better than a delay
+                let Ok(StampedData { data, .. }) = data else {
+                    continue;
+                };
+                let mut buf = gstreamer::Buffer::with_size(data.len())?;
+                buf.get_mut()
+                    .ok_or_else(|| anyhow!("New buffer should be writable"))?
+                    .copy_from_slice(0, &data)
+                    .map_err(|_| anyhow!("Failed to copy the audio"))?;
+                appsrc
+                    .push_buffer(buf)
+                    .map_err(|e| anyhow!("Streamer Error: {e:?}"))?;
+            }
+            AnyResult::Ok(())
+        } => v.and_then(|_| Err(anyhow!("{}: The audio ended", from_name))),
+    };
+
+    let _ = appsrc.end_of_stream();
+    drop(rx);
+    while set.join_next().await.is_some() {}
+    res
+}
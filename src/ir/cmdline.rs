@@ -0,0 +1,27 @@
+use anyhow::{anyhow, Result};
+use clap::Parser;
+use neolink_core::bc_protocol::LightState;
+
+fn lightstate_parse(src: &str) -> Result<LightState> {
+    match src {
+        "true" | "on" | "yes" => Ok(LightState::On),
+        "false" | "off" | "no" => Ok(LightState::Off),
+        "auto" => Ok(LightState::Auto),
+        _ => Err(anyhow!(
+            "Could not understand {}, check your input, should be on, off or auto",
+            src
+        )),
+    }
+}
+
+/// The ir command will control the IR lights used for night vision
+///
+/// Without a state it prints the current state of the IR lights
+#[derive(Parser, Debug)]
+pub struct Opt {
+    /// The name of the camera. Must be a name in the config
+    pub camera: String,
+    /// Force the IR lights on or off, or auto to let the camera turn them on in low light
+    #[arg(value_parser = lightstate_parse, action = clap::ArgAction::Set, name = "on|off|auto")]
+    pub state: Option<LightState>,
+}
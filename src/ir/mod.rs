@@ -0,0 +1,64 @@
+///
+/// # Neolink IR
+///
+/// This module handles the IR lights that the camera uses for night vision
+///
+/// Forcing them off is useful when the camera looks through a window as the
+/// IR reflects off the glass
+///
+/// # Usage
+///
+/// ```bash
+/// # To force the IR lights off
+/// neolink ir --config=config.toml CameraName off
+/// # Or on
+/// neolink ir --config=config.toml CameraName on
+/// # Or let the camera turn them on in low light
+/// neolink ir --config=config.toml CameraName auto
+/// # To print the current state
+/// neolink ir --config=config.toml CameraName
+/// ```
+///
+use anyhow::{Context, Result};
+
+mod cmdline;
+
+use crate::{
+    common::{audit, NeoReactor},
+    utils::lightstate_name,
+};
+pub(crate) use cmdline::Opt;
+
+/// Entry point for the ir subcommand
+///
+/// Opt is the command line options
+pub(crate) async fn main(opt: Opt, reactor: NeoReactor) -> Result<()> {
+    let camera = reactor.get(&opt.camera).await?;
+
+    if let Some(state) = opt.state {
+        let result = camera
+            .run_task(|cam| {
+                Box::pin(async move {
+                    cam.set_ir_led(state)
+                        .await
+                        .context("Unable to set camera IR state")
+                })
+            })
+            .await;
+        audit::cli(&opt.camera, "ir", lightstate_name(state), &result);
+        result?;
+    } else {
+        let state = camera
+            .run_task(|cam| {
+                Box::pin(async move {
+                    cam.get_ir_led()
+                        .await
+                        .context("Unable to get camera IR state")
+                })
+            })
+            .await?;
+        println!("{}", lightstate_name(state));
+    }
+
+    Ok(())
+}
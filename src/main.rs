@@ -31,25 +31,64 @@ static GLOBAL: Jemalloc = Jemalloc;
 
 use anyhow::{Context, Result};
 use clap::Parser;
-use env_logger::Env;
 use log::*;
 use std::fs;
 use validator::Validate;
 
 mod battery;
+mod capabilities;
+#[cfg(feature = "gstreamer")]
+mod clip;
 mod cmdline;
 mod common;
 mod config;
+mod ctl;
+mod decode;
+mod diagnose;
+#[cfg(feature = "gstreamer")]
+mod download;
+mod eventlog;
+mod floodlight;
+#[cfg(feature = "gstreamer")]
+mod hls;
+mod http;
+#[cfg(feature = "gstreamer")]
 mod image;
+mod info;
+mod init;
+#[cfg(feature = "gstreamer")]
+mod intercom;
+mod ir;
+mod metrics;
+mod motion;
 mod mqtt;
+mod name;
+mod onvif;
+mod osd;
 mod pir;
+mod privacymask;
 mod ptz;
 mod reboot;
+#[cfg(feature = "gstreamer")]
+mod record;
+#[cfg(feature = "gstreamer")]
 mod rtsp;
+mod selftest;
+mod serve;
 mod services;
+mod siren;
+mod status;
 mod statusled;
+#[cfg(feature = "gstreamer")]
+mod stream;
+#[cfg(feature = "gstreamer")]
 mod talk;
+#[cfg(feature = "gstreamer")]
+mod timelapse;
 mod utils;
+mod webhooks;
+#[cfg(feature = "gstreamer")]
+mod webrtc;
 
 use cmdline::{Command, Opt};
 use common::NeoReactor;
@@ -59,7 +98,7 @@ pub(crate) type AnyResult<T> = Result<T, anyhow::Error>;
 
 #[tokio::main]
 async fn main() -> Result<()> {
-    env_logger::Builder::from_env(Env::default().default_filter_or("info")).init();
+    common::loglevel::init();
 
     info!(
         "Neolink {} {}",
@@ -69,6 +108,19 @@ async fn main() -> Result<()> {
 
     let opt = Opt::parse();
 
+    // Developer tools that do not need a config
+    let cmd = match opt.cmd {
+        Some(Command::Decode(opts)) => return decode::main(opts),
+        Some(Command::Capabilities(opts)) => return capabilities::main(opts),
+        Some(Command::Init(opts)) => {
+            let conf_path = opt
+                .config
+                .context("Must supply --config file to write")?;
+            return init::main(opts, &conf_path).await;
+        }
+        cmd => cmd,
+    };
+
     let conf_path = opt.config.context("Must supply --config file")?;
     let config: Config = toml::from_str(
         &fs::read_to_string(&conf_path)
@@ -80,43 +132,159 @@ async fn main() -> Result<()> {
         .validate()
         .with_context(|| format!("Failed to validate the {:?} config file", conf_path))?;
 
+    common::logfile::init(&config.rotation);
+    if let Some(audit) = config.audit.as_ref() {
+        common::audit::init(audit)
+            .with_context(|| format!("Failed to open the audit log {:?}", audit.path))?;
+    }
+
+    if opt.dump_config {
+        print!(
+            "{}",
+            toml::to_string_pretty(&config.redacted()).context("Failed to serialise the config")?
+        );
+        return Ok(());
+    }
+
+    // One-shot commands are run over the camera connection of a running
+    // neolink when there is one, rather than logging in a second time
+    if let Some(control) = config.control.as_ref().filter(|control| control.share) {
+        if let Some(args) = cmd.as_ref().and_then(ctl::forward_args) {
+            if ctl::forward(control, &args).await? {
+                return Ok(());
+            }
+        }
+    }
+
     let neo_reactor = NeoReactor::new(config.clone()).await;
 
-    match opt.cmd {
+    // The commands that keep running can be asked for their state by `neolink ctl`
+    // and post webhooks and the event log
+    let daemon = match &cmd {
+        None
+        | Some(Command::Serve(_))
+        | Some(Command::Mqtt(_))
+        | Some(Command::Onvif(_))
+        | Some(Command::Http(_)) => true,
+        #[cfg(feature = "gstreamer")]
+        Some(Command::Rtsp(_))
+        | Some(Command::MqttRtsp(_))
+        | Some(Command::Record(_))
+        | Some(Command::Webrtc(_))
+        | Some(Command::Hls(_))
+        | Some(Command::Timelapse(_)) => true,
+        _ => false,
+    };
+    if let Some(control) = config.control.clone().filter(|_| daemon) {
+        let reactor = neo_reactor.clone();
+        tokio::task::spawn(async move {
+            if let Err(e) = ctl::serve(control, reactor).await {
+                error!("Control: {:?}", e);
+            }
+        });
+    }
+    if let Some(metrics) = config.metrics.clone().filter(|_| daemon) {
+        let reactor = neo_reactor.clone();
+        tokio::task::spawn(async move {
+            if let Err(e) = metrics::serve(metrics, reactor).await {
+                error!("Metrics: {:?}", e);
+            }
+        });
+    }
+    #[cfg(feature = "gstreamer")]
+    if !config.clips.is_empty() && daemon {
+        let reactor = neo_reactor.clone();
+        tokio::task::spawn(async move {
+            if let Err(e) = talk::clips::serve(reactor).await {
+                error!("Clips: {:?}", e);
+            }
+        });
+    }
+    if config.event_log.is_some() && daemon {
+        let reactor = neo_reactor.clone();
+        tokio::task::spawn(async move {
+            if let Err(e) = eventlog::serve(reactor).await {
+                error!("Event log: {:?}", e);
+            }
+        });
+    }
+    if config.webhooks.is_some() && daemon {
+        let reactor = neo_reactor.clone();
+        tokio::task::spawn(async move {
+            if let Err(e) = webhooks::serve(reactor).await {
+                error!("Webhooks: {:?}", e);
+            }
+        });
+    }
+
+    match cmd {
         None => {
             warn!(
                 "Deprecated command line option. Please use: `neolink rtsp --config={:?}`",
                 conf_path
             );
+            #[cfg(feature = "gstreamer")]
             rtsp::main(rtsp::Opt {}, neo_reactor.clone()).await?;
+            #[cfg(not(feature = "gstreamer"))]
+            return Err(anyhow::anyhow!(
+                "This neolink was built without gstreamer so it has no rtsp server"
+            ));
         }
+        #[cfg(feature = "gstreamer")]
         Some(Command::Rtsp(opts)) => {
             rtsp::main(opts, neo_reactor.clone()).await?;
         }
+        Some(Command::Serve(opts)) => {
+            serve::main(opts, neo_reactor.clone()).await?;
+        }
         Some(Command::StatusLight(opts)) => {
             statusled::main(opts, neo_reactor.clone()).await?;
         }
         Some(Command::Reboot(opts)) => {
             reboot::main(opts, neo_reactor.clone()).await?;
         }
+        Some(Command::Ir(opts)) => {
+            ir::main(opts, neo_reactor.clone()).await?;
+        }
         Some(Command::Pir(opts)) => {
             pir::main(opts, neo_reactor.clone()).await?;
         }
+        Some(Command::Name(opts)) => {
+            name::main(opts, neo_reactor.clone()).await?;
+        }
+        Some(Command::Osd(opts)) => {
+            osd::main(opts, neo_reactor.clone()).await?;
+        }
+        Some(Command::Status(opts)) => {
+            status::main(opts, neo_reactor.clone()).await?;
+        }
+        Some(Command::Diagnose(opts)) => {
+            diagnose::main(opts, neo_reactor.clone()).await?;
+        }
+        Some(Command::PrivacyMask(opts)) => {
+            privacymask::main(opts, neo_reactor.clone()).await?;
+        }
+        Some(Command::Selftest(opts)) => {
+            selftest::main(opts, neo_reactor.clone()).await?;
+        }
         Some(Command::Ptz(opts)) => {
             ptz::main(opts, neo_reactor.clone()).await?;
         }
+        #[cfg(feature = "gstreamer")]
         Some(Command::Talk(opts)) => {
             talk::main(opts, neo_reactor.clone()).await?;
         }
         Some(Command::Mqtt(opts)) => {
             mqtt::main(opts, neo_reactor.clone()).await?;
         }
+        #[cfg(feature = "gstreamer")]
         Some(Command::MqttRtsp(opts)) => {
             tokio::select! {
                 v = mqtt::main(opts, neo_reactor.clone()) => v,
                 v = rtsp::main(rtsp::Opt {}, neo_reactor.clone()) => v,
             }?;
         }
+        #[cfg(feature = "gstreamer")]
         Some(Command::Image(opts)) => {
             image::main(opts, neo_reactor.clone()).await?;
         }
@@ -126,6 +294,62 @@ async fn main() -> Result<()> {
         Some(Command::Services(opts)) => {
             services::main(opts, neo_reactor.clone()).await?;
         }
+        Some(Command::Onvif(opts)) => {
+            onvif::main(opts, neo_reactor.clone()).await?;
+        }
+        Some(Command::Http(opts)) => {
+            http::main(opts, neo_reactor.clone()).await?;
+        }
+        #[cfg(feature = "gstreamer")]
+        Some(Command::Intercom(opts)) => {
+            intercom::main(opts, neo_reactor.clone()).await?;
+        }
+        #[cfg(feature = "gstreamer")]
+        Some(Command::Record(opts)) => {
+            record::main(opts, neo_reactor.clone()).await?;
+        }
+        #[cfg(feature = "gstreamer")]
+        Some(Command::Download(opts)) => {
+            download::main(opts, neo_reactor.clone()).await?;
+        }
+        Some(Command::Info(opts)) => {
+            info::main(opts, neo_reactor.clone()).await?;
+        }
+        Some(Command::Floodlight(opts)) => {
+            floodlight::main(opts, neo_reactor.clone()).await?;
+        }
+        Some(Command::Siren(opts)) => {
+            siren::main(opts, neo_reactor.clone()).await?;
+        }
+        Some(Command::Ctl(opts)) => {
+            ctl::main(opts, neo_reactor.clone()).await?;
+        }
+        #[cfg(feature = "gstreamer")]
+        Some(Command::Stream(opts)) => {
+            stream::main(opts, neo_reactor.clone()).await?;
+        }
+        #[cfg(feature = "gstreamer")]
+        Some(Command::Webrtc(opts)) => {
+            webrtc::main(opts, neo_reactor.clone()).await?;
+        }
+        #[cfg(feature = "gstreamer")]
+        Some(Command::Hls(opts)) => {
+            hls::main(opts, neo_reactor.clone()).await?;
+        }
+        #[cfg(feature = "gstreamer")]
+        Some(Command::Clip(opts)) => {
+            clip::main(opts, neo_reactor.clone()).await?;
+        }
+        Some(Command::Motion(opts)) => {
+            motion::main(opts, neo_reactor.clone()).await?;
+        }
+        #[cfg(feature = "gstreamer")]
+        Some(Command::Timelapse(opts)) => {
+            timelapse::main(opts, neo_reactor.clone()).await?;
+        }
+        Some(Command::Decode(_)) | Some(Command::Init(_)) | Some(Command::Capabilities(_)) => {
+            unreachable!("Handled before the config is loaded")
+        }
     }
 
     Ok(())
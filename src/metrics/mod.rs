@@ -0,0 +1,324 @@
+///
+/// # Neolink Metrics
+///
+/// This module serves Prometheus metrics of the cameras and a health check so
+/// that neolink can be watched when it runs in a container
+///
+/// It runs alongside the commands that keep running such as `neolink rtsp`,
+/// `neolink mqtt` and `neolink http` when there is a `[metrics]` section in the
+/// config
+///
+/// # Example Config
+///
+/// ```toml
+/// [metrics]
+/// bind = "0.0.0.0:9898"
+/// ```
+///
+/// `http://my.ip.address:9898/metrics` has the metrics and
+/// `http://my.ip.address:9898/health` replies `OK` while neolink is running
+///
+/// Only the cameras that have started are reported and a scrape will not wake
+/// a camera or start a stream. The battery is only asked for while the camera
+/// is connected
+///
+use anyhow::{Context, Result};
+use log::*;
+use std::{
+    collections::HashSet,
+    fmt::Write as _,
+    sync::{Arc, Mutex},
+};
+use tokio::{
+    net::TcpListener,
+    time::{timeout, Duration},
+};
+
+use crate::{
+    common::{
+        httpd::{self, Request, Response},
+        CameraReport, NeoInstance, NeoReactor, StreamReport,
+    },
+    config::MetricsConfig,
+    AnyResult,
+};
+
+/// The cameras that did not reply to the battery request, they are not asked again
+type NoBattery = Arc<Mutex<HashSet<String>>>;
+
+/// Serve the metrics while there is a `[metrics]` config
+pub(crate) async fn serve(metrics: MetricsConfig, reactor: NeoReactor) -> Result<()> {
+    let listener = TcpListener::bind(&metrics.bind)
+        .await
+        .with_context(|| format!("Failed to bind the metrics port {}", metrics.bind))?;
+    info!("Metrics: Serving on {}", metrics.bind);
+
+    let no_battery: NoBattery = Default::default();
+    httpd::serve(listener, "Metrics", 0, move |request| {
+        let reactor = reactor.clone();
+        let no_battery = no_battery.clone();
+        async move { handle_request(&request, &reactor, &no_battery).await }
+    })
+    .await
+}
+
+async fn handle_request(
+    request: &Request,
+    reactor: &NeoReactor,
+    no_battery: &NoBattery,
+) -> Response {
+    match request.path.split('?').next().unwrap_or_default() {
+        "/metrics" => match metrics(reactor, no_battery).await {
+            Ok(body) => Response::new("200 OK", "text/plain; version=0.0.4", body.into_bytes()),
+            Err(e) => {
+                warn!("Metrics: Failed to collect the metrics: {:?}", e);
+                Response::text("503 Service Unavailable", "Failed to collect the metrics\n")
+            }
+        },
+        "/health" | "/healthz" => match reactor.config().await {
+            Ok(_) => Response::text("200 OK", "OK\n"),
+            Err(_) => Response::text("503 Service Unavailable", "Not running\n"),
+        },
+        _ => Response::text("404 Not Found", "Expected /metrics or /health\n"),
+    }
+}
+
+/// A camera's state at the time of the scrape
+struct Sample {
+    report: CameraReport,
+    battery: Option<u32>,
+}
+
+/// The metrics in the Prometheus text format
+async fn metrics(reactor: &NeoReactor, no_battery: &NoBattery) -> AnyResult<String> {
+    let mut samples = vec![];
+    for camera in reactor.cameras().await? {
+        let report = camera.report().await?;
+        let battery = if report.online {
+            battery(&camera, &report.name, no_battery).await
+        } else {
+            None
+        };
+        samples.push(Sample { report, battery });
+    }
+    samples.sort_by(|a, b| a.report.name.cmp(&b.report.name));
+
+    let mut out = String::new();
+    family(
+        &mut out,
+        "neolink_info",
+        "gauge",
+        "The version of neolink",
+        [(vec![("version", env!("NEOLINK_VERSION").to_string())], 1.0)],
+    );
+    family(
+        &mut out,
+        "neolink_camera_connected",
+        "gauge",
+        "1 while neolink keeps the camera connected, 0 once it was told to disconnect",
+        samples
+            .iter()
+            .map(|s| (camera(&s.report), flag(s.report.state == "connected"))),
+    );
+    family(
+        &mut out,
+        "neolink_camera_logged_in",
+        "gauge",
+        "1 while there is a logged in connection to the camera",
+        samples
+            .iter()
+            .map(|s| (camera(&s.report), flag(s.report.online))),
+    );
+    family(
+        &mut out,
+        "neolink_camera_busy",
+        "gauge",
+        "1 while the camera refuses logins as it has too many clients",
+        samples
+            .iter()
+            .map(|s| (camera(&s.report), flag(s.report.busy))),
+    );
+    family(
+        &mut out,
+        "neolink_camera_reconnects_total",
+        "counter",
+        "How many times neolink has logged in to the camera again",
+        samples
+            .iter()
+            .map(|s| (camera(&s.report), s.report.logins.saturating_sub(1) as f64)),
+    );
+    family(
+        &mut out,
+        "neolink_camera_errors_total",
+        "counter",
+        "The errors that dropped the connection by their category",
+        samples.iter().flat_map(|s| {
+            s.report.error_counts.iter().map(|(category, count)| {
+                let mut labels = camera(&s.report);
+                labels.push(("category", category.to_string()));
+                (labels, *count as f64)
+            })
+        }),
+    );
+    family(
+        &mut out,
+        "neolink_camera_command_queue",
+        "gauge",
+        "The control commands that are waiting or running",
+        samples
+            .iter()
+            .map(|s| (camera(&s.report), s.report.command_queue as f64)),
+    );
+    family(
+        &mut out,
+        "neolink_camera_subscriptions",
+        "gauge",
+        "The streams, motion listeners and battery polls that are active on the camera",
+        samples
+            .iter()
+            .map(|s| (camera(&s.report), s.report.subscriptions.len() as f64)),
+    );
+    family(
+        &mut out,
+        "neolink_camera_clock_drift_seconds",
+        "gauge",
+        "How far the camera's clock was ahead of the host's at the last check",
+        samples.iter().filter_map(|s| {
+            s.report
+                .clock_drift
+                .map(|drift| (camera(&s.report), drift.seconds as f64))
+        }),
+    );
+    family(
+        &mut out,
+        "neolink_camera_battery_percent",
+        "gauge",
+        "The charge of the camera's battery",
+        samples
+            .iter()
+            .filter_map(|s| s.battery.map(|percent| (camera(&s.report), percent as f64))),
+    );
+    family(
+        &mut out,
+        "neolink_stream_up",
+        "gauge",
+        "1 while frames of the stream are arriving",
+        streams(&samples, |stream| {
+            flag(stream.health == "started" || stream.health == "bitrate_dropped")
+        }),
+    );
+    family(
+        &mut out,
+        "neolink_stream_users",
+        "gauge",
+        "The users of the stream",
+        streams(&samples, |stream| stream.users as f64),
+    );
+    family(
+        &mut out,
+        "neolink_stream_bitrate_bits_per_second",
+        "gauge",
+        "The bitrate of the stream measured over the last health window",
+        streams(&samples, |stream| stream.measured_bitrate as f64),
+    );
+    family(
+        &mut out,
+        "neolink_stream_frames_total",
+        "counter",
+        "The video frames of the stream that were passed on",
+        streams(&samples, |stream| stream.frames as f64),
+    );
+    family(
+        &mut out,
+        "neolink_stream_dropped_frames_total",
+        "counter",
+        "The video frames of the stream that were dropped as they came before a keyframe",
+        streams(&samples, |stream| stream.dropped_frames as f64),
+    );
+    Ok(out)
+}
+
+/// Ask a connected camera for its battery, giving up on cameras without one
+async fn battery(camera: &NeoInstance, name: &str, no_battery: &NoBattery) -> Option<u32> {
+    if no_battery
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .contains(name)
+    {
+        return None;
+    }
+    let info = timeout(
+        Duration::from_secs(2),
+        camera.run_passive_task(|cam| {
+            Box::pin(async move { AnyResult::Ok(cam.battery_info().await?) })
+        }),
+    )
+    .await;
+    match info {
+        Ok(Ok(info)) => Some(info.battery_percent),
+        _ => {
+            debug!("{}: Metrics: No battery", name);
+            no_battery
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .insert(name.to_string());
+            None
+        }
+    }
+}
+
+type Labels = Vec<(&'static str, String)>;
+
+fn camera(report: &CameraReport) -> Labels {
+    vec![("camera", report.name.clone())]
+}
+
+fn flag(value: bool) -> f64 {
+    if value {
+        1.0
+    } else {
+        0.0
+    }
+}
+
+/// A value of each stream of each camera
+fn streams(
+    samples: &[Sample],
+    value: fn(&StreamReport) -> f64,
+) -> impl Iterator<Item = (Labels, f64)> + '_ {
+    samples.iter().flat_map(move |s| {
+        s.report.streams.iter().map(move |stream| {
+            let mut labels = camera(&s.report);
+            labels.push(("stream", stream.stream.to_lowercase()));
+            (labels, value(stream))
+        })
+    })
+}
+
+/// Write a metric with its help and type followed by its samples
+fn family(
+    out: &mut String,
+    name: &str,
+    kind: &str,
+    help: &str,
+    samples: impl IntoIterator<Item = (Labels, f64)>,
+) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} {}", name, kind);
+    for (labels, value) in samples {
+        let labels = labels
+            .iter()
+            .map(|(key, value)| format!("{}=\"{}\"", key, escape(value)))
+            .collect::<Vec<_>>()
+            .join(",");
+        let _ = writeln!(out, "{}{{{}}} {}", name, labels, value);
+    }
+}
+
+/// Escape a label value
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
@@ -0,0 +1,14 @@
+use clap::Parser;
+
+/// The motion command prints whether a camera sees motion
+///
+/// With `--follow` it prints the motion and detection events of the camera
+/// as lines of JSON as they happen
+#[derive(Parser, Debug)]
+pub struct Opt {
+    /// The name of the camera. Must be a name in the config
+    pub camera: String,
+    /// Keep printing the events as lines of JSON until stopped
+    #[arg(short, long)]
+    pub follow: bool,
+}
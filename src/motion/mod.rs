@@ -0,0 +1,157 @@
+///
+/// # Neolink Motion
+///
+/// This module prints whether a camera sees motion, or with `--follow` its
+/// events as they happen for debugging and for shell automations that do not
+/// want an MQTT broker
+///
+/// With `--follow` each event is a line of the same JSON that is sent to MQTT
+/// and the webhooks
+///
+/// - `motion_start` and `motion_stop` as the camera reports them
+/// - `detection` with the `sources` and AI `objects` of the merged triggers
+/// - `smart` for the smart detections such as a line crossing
+/// - `visitor` when the doorbell is pressed, from the push notifications
+/// - `connected` and `disconnected` when the camera connection changes
+///
+/// # Usage
+///
+/// ```bash
+/// # Prints motion, still or unknown
+/// neolink motion --config=config.toml CameraName
+/// neolink motion --config=config.toml CameraName --follow | jq -r .event
+/// ```
+///
+use anyhow::Result;
+use log::*;
+use tokio::{
+    sync::{broadcast::error::RecvError, watch::Receiver as WatchReceiver},
+    time::{timeout, Duration},
+};
+
+mod cmdline;
+
+use crate::common::{Event, EventKind, MdState, NeoInstance, NeoReactor};
+pub(crate) use cmdline::Opt;
+
+/// Entry point for the motion subcommand
+///
+/// Opt is the command line options
+pub(crate) async fn main(opt: Opt, reactor: NeoReactor) -> Result<()> {
+    let camera = reactor.get(&opt.camera).await?;
+    let mut motion = camera.motion().await?;
+    if opt.follow {
+        return follow(&camera, motion).await;
+    }
+
+    // The state is unknown until the camera first reports it
+    let _ = timeout(
+        Duration::from_secs(10),
+        motion.wait_for(|state| !matches!(state, MdState::Unknown)),
+    )
+    .await;
+    let state = match *motion.borrow() {
+        MdState::Start(_) => "motion",
+        MdState::Stop(_) => "still",
+        MdState::Unknown => "unknown",
+    };
+    println!("{}", state);
+    Ok(())
+}
+
+/// Print the events of the camera until stopped
+async fn follow(camera: &NeoInstance, mut motion: WatchReceiver<MdState>) -> Result<()> {
+    let camera_config = camera.config().await?;
+    let mut detections = camera.events().await?;
+    let mut smart = camera.smart_events().await?;
+    let mut connection = camera.camera();
+    let mut connected = connection.borrow_and_update().upgrade().is_some();
+    motion.borrow_and_update();
+    // Visitors are only known from the push notifications
+    let mut notifications = match camera.push_notifications().await {
+        Ok(notifications) => Some(notifications),
+        Err(e) => {
+            debug!("No push notifications so visitors are not printed: {:?}", e);
+            None
+        }
+    };
+    let mut prev_noti = notifications
+        .as_mut()
+        .and_then(|notifications| notifications.borrow_and_update().clone());
+    // The name of the camera and the time in its `timezone`
+    let stamp = || {
+        let camera_config = camera_config.borrow();
+        (camera_config.name.clone(), camera_config.timezone().now())
+    };
+    loop {
+        let event = tokio::select! {
+            v = detections.recv() => match v {
+                Ok(detection) => {
+                    let (name, now) = stamp();
+                    Event::detection(&name, &detection, now)
+                }
+                Err(RecvError::Lagged(_)) => continue,
+                Err(e) => return Err(e.into()),
+            },
+            v = smart.recv() => match v {
+                Ok(smart) => {
+                    let (name, now) = stamp();
+                    Event::smart(&name, &smart, now)
+                }
+                Err(RecvError::Lagged(_)) => continue,
+                Err(e) => return Err(e.into()),
+            },
+            v = motion.changed() => {
+                v?;
+                let kind = match *motion.borrow_and_update() {
+                    MdState::Start(_) => EventKind::MotionStart,
+                    MdState::Stop(_) => EventKind::MotionStop,
+                    MdState::Unknown => continue,
+                };
+                let (name, now) = stamp();
+                Event::new(&name, kind, now)
+            },
+            v = connection.changed() => {
+                v?;
+                let now_connected = connection.borrow_and_update().upgrade().is_some();
+                if now_connected == connected {
+                    continue;
+                }
+                connected = now_connected;
+                let (name, now) = stamp();
+                if connected {
+                    Event::new(&name, EventKind::Connected, now)
+                } else {
+                    Event::new(&name, EventKind::Disconnected, now)
+                }
+            },
+            v = async {
+                match notifications.as_mut() {
+                    Some(notifications) => notifications
+                        .wait_for(|noti| noti != &prev_noti && noti.is_some())
+                        .await
+                        .map(|noti| noti.clone()),
+                    None => futures::future::pending().await,
+                }
+            } => {
+                let noti = v?;
+                let visitor = noti
+                    .as_ref()
+                    .is_some_and(|noti| is_visitor(&noti.message));
+                prev_noti = noti;
+                if !visitor {
+                    continue;
+                }
+                let (name, now) = stamp();
+                Event::new(&name, EventKind::Visitor, now)
+            },
+        };
+        println!("{}", event.to_json()?);
+    }
+}
+
+/// If the push notification is of the doorbell being pressed
+fn is_visitor(message: &str) -> bool {
+    serde_json::from_str::<serde_json::Value>(message)
+        .is_ok_and(|message| message["alarm"]["type"].as_str() == Some("visitor"))
+}
@@ -0,0 +1,178 @@
+//! The alarm of all cameras
+//!
+//! An alarm system can raise one alarm on `neolink/control/alarm` instead of
+//! sending a message to each camera. The `[alarm]` config picks which cameras
+//! take part and what they do: sound their siren, turn on their floodlight and
+//! record continuously to the SD card. `off` undoes all of it and restores the
+//! recording schedules that were replaced
+use anyhow::anyhow;
+use serde::Serialize;
+use time::format_description::well_known::Rfc3339;
+
+use super::mqttc::MqttInstance;
+use crate::{
+    common::{audit, NeoReactor},
+    config::AlarmConfig,
+    AnyResult,
+};
+
+/// The message published on `/status/alarm`
+#[derive(Serialize)]
+struct AlarmStatus<'a> {
+    state: &'a str,
+    time: String,
+    /// The cameras that failed to take part
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    failed: Vec<String>,
+}
+
+/// Raises and clears the alarm
+///
+/// The replaced recording schedules are kept by each camera, see
+/// [`crate::common::SavedRecord`], so that they outlive this
+pub(super) struct Alarm;
+
+impl Alarm {
+    /// Handle a message of `neolink/control/alarm`
+    pub(super) async fn handle(
+        &self,
+        message: &str,
+        reactor: &NeoReactor,
+        mqtt: &MqttInstance,
+    ) -> AnyResult<()> {
+        audit::mqtt("", "control/alarm", message);
+        let config = reactor.config().await?.borrow().clone();
+        let Some(alarm) = config.alarm.as_ref() else {
+            mqtt.send_message("control/alarm", "FAIL: There is no [alarm] config", false)
+                .await?;
+            return Ok(());
+        };
+        let on = match message.trim() {
+            "on" => true,
+            "off" => false,
+            _ => {
+                mqtt.send_message("control/alarm", "FAIL: Expected `on` or `off`", false)
+                    .await?;
+                return Ok(());
+            }
+        };
+        let names = config
+            .cameras
+            .iter()
+            .filter(|camera| camera.enabled)
+            .map(|camera| camera.name.clone())
+            .filter(|name| alarm.cameras.is_empty() || alarm.cameras.contains(name))
+            .collect::<Vec<_>>();
+
+        if on {
+            log::warn!("Alarm: Raised on {}", names.join(", "));
+        } else {
+            log::info!("Alarm: Cleared on {}", names.join(", "));
+        }
+
+        // All cameras at once so that the sirens sound together
+        let results = futures::future::join_all(
+            names
+                .iter()
+                .map(|name| self.camera(name, on, alarm, reactor, mqtt)),
+        )
+        .await;
+        let mut failed = vec![];
+        for (name, result) in names.iter().zip(results) {
+            match result {
+                Ok(()) => {}
+                Err(e) => {
+                    log::error!("{name}: Alarm failed: {:?}", e);
+                    failed.push(name.clone());
+                }
+            }
+        }
+        let state = if on { "on" } else { "off" };
+        if alarm.notify {
+            let now = time::OffsetDateTime::now_utc();
+            let status = AlarmStatus {
+                state,
+                time: now.format(&Rfc3339).unwrap_or_else(|_| now.to_string()),
+                failed: failed.clone(),
+            };
+            mqtt.send_message("status/alarm", &serde_json::to_string(&status)?, true)
+                .await?;
+        }
+        let reply = if failed.is_empty() {
+            "OK".to_string()
+        } else {
+            format!("FAIL: {}", failed.join(", "))
+        };
+        mqtt.send_message("control/alarm", &reply, false).await?;
+        Ok(())
+    }
+
+    /// Raise or clear the alarm on one camera
+    async fn camera(
+        &self,
+        name: &str,
+        on: bool,
+        alarm: &AlarmConfig,
+        reactor: &NeoReactor,
+        mqtt: &MqttInstance,
+    ) -> AnyResult<()> {
+        let camera = reactor.get(name).await?;
+        let siren = alarm.siren;
+        let floodlight = alarm.floodlight;
+        let record = alarm.record;
+        let saved_record = camera.saved_record().await?;
+        let mut saved = saved_record.lock().await;
+        if record && !on && saved.is_none() {
+            return Err(anyhow!(
+                "No saved recording schedule to restore, set it again in the app"
+            ));
+        }
+        let previous = saved.clone();
+        let replaced = camera
+            .run_command(|cam| {
+                let previous = previous.clone();
+                Box::pin(async move {
+                    if siren {
+                        cam.siren_manual(on).await?;
+                    }
+                    if let Some(brightness) = floodlight {
+                        cam.set_floodlight(on, on.then_some(brightness)).await?;
+                    }
+                    let replaced = match (record, on, previous) {
+                        (true, true, _) => Some(cam.start_manual_record().await?),
+                        (true, false, Some(previous)) => {
+                            cam.stop_manual_record(previous).await?;
+                            None
+                        }
+                        _ => None,
+                    };
+                    AnyResult::Ok(replaced)
+                })
+            })
+            .await?;
+        if record {
+            if on {
+                // Keep the first schedule if raised twice so that off
+                // restores the original and not the alarm one
+                if saved.is_none() {
+                    *saved = replaced;
+                }
+            } else {
+                *saved = None;
+            }
+        }
+        drop(saved);
+
+        if alarm.notify {
+            let mqtt_template = camera.config().await?.borrow().mqtt_template.clone();
+            let topic_name = match mqtt_template {
+                Some(template) => camera.expand_template(&template, None).await?,
+                None => name.to_string(),
+            };
+            let state = if on { "on" } else { "off" };
+            mqtt.send_message(&format!("{topic_name}/status/alarm"), state, true)
+                .await?;
+        }
+        Ok(())
+    }
+}
@@ -1,4 +1,21 @@
 use clap::Parser;
+use std::path::PathBuf;
+use std::str::FromStr;
 
+/// The mqtt command will bridge all cameras in the config to an mqtt broker
 #[derive(Parser, Debug)]
-pub struct Opt {}
+pub struct Opt {
+    /// Do not contact any cameras, instead replay a scripted set of events
+    /// to the broker. Useful for building automations before the cameras are mounted
+    #[structopt(long)]
+    pub dry_run: bool,
+    /// A toml script of events to replay in dry run mode.
+    ///
+    /// If not given a built in script is used that sends motion at t+5s
+    /// and a visitor at t+20s
+    #[structopt(long, value_parser = PathBuf::from_str, requires = "dry_run")]
+    pub script: Option<PathBuf>,
+    /// Repeat the dry run script forever rather than playing it once
+    #[structopt(long = "loop", requires = "dry_run")]
+    pub repeat: bool,
+}
@@ -16,8 +16,10 @@ pub(crate) enum Discoveries {
     Floodlight,
     #[serde(alias = "camera", alias = "preview", alias = "Preview")]
     Camera,
-    #[serde(alias = "motion", alias = "md", alias = "pir")]
+    #[serde(alias = "motion", alias = "md")]
     Motion,
+    #[serde(alias = "pir")]
+    Pir,
     #[serde(alias = "led")]
     Led,
     #[serde(alias = "ir")]
@@ -480,6 +482,45 @@ pub(crate) async fn enable_discovery(
                     )
                 })?;
             }
+            Discoveries::Pir => {
+                let config_data = DiscoverySwitch {
+                    // Common across all potential features
+                    device: device.clone(),
+                    availability: availability.clone(),
+
+                    // Identifiers
+                    name: format!("{} PIR", friendly_name.as_str()),
+                    unique_id: format!("neolink_{}_pir", cam_config.name),
+                    icon: Some("mdi:motion-sensor".to_string()),
+
+                    // Switch specific
+                    command_topic: format!("neolink/{}/control/pir", cam_config.name),
+                    payload_off: "off".to_string(),
+                    payload_on: "on".to_string(),
+                    state_topic: None,
+                    state_off: None,
+                    state_on: None,
+                };
+
+                // Each feature needs to be individually registered
+                mqtt.send_message_with_root_topic(
+                    &format!(
+                        "{}/switch/{}",
+                        discovery_config.topic, &config_data.unique_id
+                    ),
+                    "config",
+                    &serde_json::to_string(&config_data)
+                        .with_context(|| "Cound not serialise discovery pir config into json")?,
+                    true,
+                )
+                .await
+                .with_context(|| {
+                    format!(
+                        "Failed to publish pir auto-discover data on over MQTT for {}",
+                        cam_config.name
+                    )
+                })?;
+            }
             Discoveries::Reboot => {
                 let config_data = DiscoveryButton {
                     // Common across all potential features
@@ -9,30 +9,58 @@
 //!
 //! Control messages:
 //!
-//! - `/control/floodlight [on|off]` Turns floodlight (if equipped) on/off
+//! - `/control/floodlight [on|off] (brightness)` Turns floodlight (if equipped) on/off, optionally at a brightness of 0-100%
+//! - `/control/siren [on (times)|continuous|off]` Sounds the siren once, a number of times or until it is turned off
 //! - `/control/led [on|off]` Turns status LED on/off
+//! - `/control/led/doorbell [on|off|auto]` Sets the light of the doorbell button, auto lights it when pressed
 //! - `/control/pir [on|off]` Turns PIR on/off
 //! - `/control/ir [on|off|auto]` Turn IR lights on/off or automatically via light detection
 //! - `/control/reboot` Reboot the camera
-//! - `/control/ptz` [up|down|left|right|in|out] (amount) Control the PTZ movements, amount defaults to 32.0
+//! - `/control/record [on|off]` Start/stop recording to the SD card regardless of motion
+//! - `/control/ptz` [up|down|left|right|in|out|near|far] (amount) Control the PTZ movements, zoom and focus, amount defaults to 32.0
 //! - `/control/ptz/preset` [id] Move the camera to a known preset
 //! - `/control/ptz/assign` [id] [name] Assign the current ptz position to an ID and name
+//! - `/control/preset` [id|name] Move the camera to a preset by its ID or name
+//! - `/control/zoom/digital` [x y width height|reset] Set the part of the frame shown at the rtsp `/zoom` path
+//! - `/control/mute` [on|off] (main|sub|extern) Strip the audio from the rtsp streams, defaults to all streams
+//! - `/control/bookmark` (label) Mark the current moment for later review
+//! - `/control/log_level` [trace|debug|info] (minutes) Raise the log level of the camera, reverting after the minutes, 10 by default
+//! - `/control/play_clip` [name] Play a clip of the `[[clips]]` config on the camera's speaker
+//!
+//! `neolink/control/alarm [on|off]` Raises or clears the alarm on all the cameras of the `[alarm]` config
 //!
 //! Status Messages:
 //!
 //! `/status offline` Sent when the neolink goes offline this is a LastWill message
 //! `/status disconnected` Sent when the camera goes offline
+//! `/status busy` Sent when the camera refuses the login because it has reached its maximum number of clients
+//! `/status/motion/time` The time of the last motion start in the camera's `timezone`
+//! `/status/smart/{line_crossing|intrusion|loitering}` A smart detection as JSON with its zone
+//! `/status/event` The motion, PIR, AI and smart detections of one event merged as JSON
+//! `/status/bookmark` A bookmark as JSON with its label, sent for each `/control/bookmark`
+//! `/status/motion/stats` The motion by weekday and hour as JSON, sent at midnight and for each `/query/motion/stats`
+//! `/status/thumbnail` A base64 encoded jpeg of the substream every `thumbnail_update` ms
 //! `/status/battery` Sent in reply to a `/query/battery`
+//! `/status/battery_level` The % charge of the battery every `battery_update` ms
+//! `/status/battery_info` The charge, charging status and adapter of the battery as JSON every `battery_update` ms
 //! `/status/pir` Sent in reply to a `/query/pir`
+//! `/status/ir` The state of the IR lights, on, off or auto, sent in reply to a `/query/ir`
 //! `/status/ptz/preset` Sent in reply to a `/query/ptz/preset`
+//! `/status/stream/{main|sub|extern}` The health of the stream: started, stopped, stalled or bitrate_dropped
+//! `/status/clock_drift` The seconds that the camera's clock is off as JSON, sent when it is more than the `max_drift`
+//! `/status/alarm` Sent when the alarm is raised or cleared, `neolink/status/alarm` has it as JSON with the cameras that failed
 //!
 //! Query Messages:
 //!
 //! `/query/battery` Request that the camera reports its battery level
 //! `/query/pir` Request that the camera reports its pir status
+//! `/query/ir` Request that the camera reports the state of its IR lights
 //! `/query/ptz/preset` Request that the camera reports the PTZ presets
+//! `/query/motion/stats` Request the motion statistics including today so far
 //! `/query/preview` Request that the camera post a base64 encoded jpeg
 //!    of the stream to `/status/preview`
+//! `/query/thumbnail` Request a base64 encoded jpeg of the substream in
+//!    `/status/thumbnail` now
 //!
 //!
 //! # Usage
@@ -41,6 +69,14 @@
 //! neolink mqtt --config=config.toml
 //! ```
 //!
+//! To test automations without any cameras use `--dry-run` which will
+//! replay a script of events (motion at t+5s, a visitor at t+20s) to the
+//! broker. A custom script can be given with `--script=events.toml`
+//!
+//! ```bash
+//! neolink mqtt --config=config.toml --dry-run
+//! ```
+//!
 //! # Example Config
 //!
 //! ```toml
@@ -60,9 +96,13 @@
 //! `credentials` are the username and password required to identify with the mqtt server
 //!
 use base64::{engine::general_purpose::STANDARD as BASE64, Engine as _};
-use std::collections::{HashMap, HashSet};
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Arc,
+};
+use time::{format_description::well_known::Rfc3339, OffsetDateTime};
 use tokio::{
-    sync::mpsc::channel as mpsc,
+    sync::{broadcast::error::RecvError, mpsc::channel as mpsc, Mutex},
     task::JoinSet,
     time::{interval, sleep, Duration, MissedTickBehavior},
 };
@@ -70,15 +110,25 @@ use tokio_stream::{wrappers::IntervalStream, StreamExt};
 use tokio_util::sync::CancellationToken;
 use validator::Validate;
 
-use neolink_core::bc_protocol::{Direction as BcDirection, LightState};
+use neolink_core::{
+    bc::xml::Record,
+    bc_protocol::{Direction as BcDirection, LightState, StreamKind},
+};
 
+mod alarm;
 mod cmdline;
 mod discovery;
+mod motionstats;
 mod mqttc;
+mod replay;
 
 use crate::{
-    common::{MdState, NeoInstance, NeoReactor},
-    config::Config,
+    common::{
+        audit, logfile, loglevel, objects, sleep_until_wall, Event, EventKind, MdState,
+        NeoInstance, NeoReactor, Wake,
+    },
+    config::{Config, ZoomRect},
+    utils::lightstate_name,
     AnyResult,
 };
 use anyhow::{anyhow, Context, Result};
@@ -88,14 +138,20 @@ use log::*;
 use mqttc::{Mqtt, MqttReplyRef};
 
 use self::{
+    alarm::Alarm,
     discovery::enable_discovery,
+    motionstats::MotionStats,
     mqttc::{MqttInstance, MqttReply},
+    replay::ReplayScript,
 };
 
 /// Entry point for the mqtt subcommand
 ///
 /// Opt is the command line options
-pub(crate) async fn main(_: Opt, reactor: NeoReactor) -> Result<()> {
+pub(crate) async fn main(opt: Opt, reactor: NeoReactor) -> Result<()> {
+    if opt.dry_run {
+        return dry_run(opt, reactor).await;
+    }
     let mut set = tokio::task::JoinSet::new();
     let global_cancel = CancellationToken::new();
     let cancel_drop = global_cancel.clone().drop_guard();
@@ -130,12 +186,20 @@ pub(crate) async fn main(_: Opt, reactor: NeoReactor) -> Result<()> {
 
                             let thread_global_cancel = thread_cancel2.clone();
                             let thread_reactor2 = thread_reactor.clone();
-                            let mqtt_instance = thread_instance.subscribe(name).await?;
+                            let mqtt_root = thread_instance.subscribe("").await?;
                             let name = name.clone();
                             set.spawn(async move {
                                 loop {
                                     let camera = thread_reactor2.get(&name).await?;
-                                    let mqtt_instance = mqtt_instance.resubscribe().await?;
+                                    let mqtt_template = camera.config().await?.borrow().mqtt_template.clone();
+                                    // Expanding the template needs the camera so an offline camera is
+                                    // published under its config name until it connects
+                                    let connected = camera.camera().borrow().upgrade().is_some();
+                                    let topic_name = match mqtt_template.as_ref() {
+                                        Some(template) if connected => camera.expand_template(template, None).await?,
+                                        _ => name.clone(),
+                                    };
+                                    let mqtt_instance = mqtt_root.subscribe(topic_name.clone()).await?;
                                     let r = tokio::select!{
                                         _ = thread_global_cancel.cancelled() => {
                                             AnyResult::Ok(())
@@ -143,9 +207,25 @@ pub(crate) async fn main(_: Opt, reactor: NeoReactor) -> Result<()> {
                                         _ = local_cancel.cancelled() => {
                                             AnyResult::Ok(())
                                         },
-                                        v = listen_on_camera(camera, mqtt_instance) => {
+                                        v = listen_on_camera(camera.clone(), mqtt_instance) => {
                                             v
                                         },
+                                        expanded = async {
+                                            match mqtt_template.as_ref() {
+                                                Some(template) if !connected => match camera.expand_template(template, None).await {
+                                                    Ok(expanded) if expanded != topic_name => expanded,
+                                                    Ok(_) => futures::future::pending().await,
+                                                    Err(e) => {
+                                                        log::warn!("{name}: Failed to expand the mqtt_template, staying on {topic_name}: {:?}", e);
+                                                        futures::future::pending().await
+                                                    }
+                                                },
+                                                _ => futures::future::pending().await,
+                                            }
+                                        } => {
+                                            log::info!("{name}: MQTT Moving to {expanded} now that the camera is connected");
+                                            continue;
+                                        },
                                     };
                                     if let Ok(()) = &r {
                                         break r
@@ -239,6 +319,28 @@ pub(crate) async fn main(_: Opt, reactor: NeoReactor) -> Result<()> {
         }
     });
 
+    // This threads raises and clears the alarm of all cameras
+    let mut thread_instance = mqtt.subscribe("").await?;
+    let thread_reactor = reactor.clone();
+    let thread_cancel = global_cancel.clone();
+    set.spawn(async move {
+        tokio::select! {
+            _ = thread_cancel.cancelled() => AnyResult::Ok(()),
+            v = async {
+                let alarm = Alarm;
+                while let Ok(msg) = thread_instance.recv().await {
+                    if msg.topic == "control/alarm"
+                        && !["OK", "FAIL"].contains(&msg.message.as_str())
+                        && !msg.message.starts_with("FAIL:")
+                    {
+                        alarm.handle(&msg.message, &thread_reactor, &thread_instance).await?;
+                    }
+                }
+                AnyResult::Ok(())
+            } => v,
+        }
+    });
+
     while let Some(result) = set.join_next().await {
         if let Err(_) | Ok(Err(_)) = &result {
             global_cancel.cancel();
@@ -250,6 +352,44 @@ pub(crate) async fn main(_: Opt, reactor: NeoReactor) -> Result<()> {
     Ok(())
 }
 
+/// Replays a script of events to the broker for every enabled
+/// camera in the config without contacting any of them
+async fn dry_run(opt: Opt, reactor: NeoReactor) -> Result<()> {
+    let script = match opt.script.as_ref() {
+        Some(path) => ReplayScript::load(path)?,
+        None => ReplayScript::canned(),
+    };
+    let config = reactor.config().await?;
+    let mqtt = Mqtt::new(config.clone()).await;
+
+    let names = config
+        .borrow()
+        .cameras
+        .iter()
+        .filter(|a| a.enabled)
+        .map(|cam_config| cam_config.name.clone())
+        .collect::<Vec<_>>();
+
+    let mut set = JoinSet::<AnyResult<()>>::new();
+    for name in names {
+        log::info!("{name}: MQTT Dry Run Starting");
+        let mqtt_instance = mqtt.subscribe(name).await?;
+        let script = script.clone();
+        let repeat = opt.repeat;
+        set.spawn(async move {
+            let _drop_message = mqtt_instance.last_will("status", "disconnected").await?;
+            replay::replay(&script, &mqtt_instance, repeat).await?;
+            // Stay connected so that the retained state remains valid
+            futures::future::pending().await
+        });
+    }
+
+    while let Some(result) = set.join_next().await {
+        result??;
+    }
+    Ok(())
+}
+
 async fn listen_on_camera(camera: NeoInstance, mqtt_instance: MqttInstance) -> Result<()> {
     let mut watch_config = camera.config().await?;
     let camera_name = watch_config.borrow().name.clone();
@@ -258,8 +398,9 @@ async fn listen_on_camera(camera: NeoInstance, mqtt_instance: MqttInstance) -> R
     let drop_cancel = cancel.clone().drop_guard();
     let r = loop {
         config = watch_config.borrow().clone().mqtt;
+        let timezone = watch_config.borrow().timezone();
         break tokio::select! {
-            v = watch_config.wait_for(|new_config| config != new_config.mqtt) => {
+            v = watch_config.wait_for(|new_config| config != new_config.mqtt || timezone != new_config.timezone()) => {
                 v?;
                 continue;
             }
@@ -288,28 +429,54 @@ async fn listen_on_camera(camera: NeoInstance, mqtt_instance: MqttInstance) -> R
                 let mut mqtt_msg = mqtt_instance.resubscribe().await?;
                 let cancel_msg = cancel.clone();
                 let mut set_msg = JoinSet::new();
+                let saved_record = camera.saved_record().await?;
+                let motion_stats = Arc::new(Mutex::new(MotionStats::load(config.motion_stats.clone())));
 
                 let mut camera_watch = camera.camera();
                 let mqtt_watch = mqtt_instance.resubscribe().await?;
 
+                let mut camera_busy = camera.busy().await?;
+                let mqtt_busy = mqtt_instance.resubscribe().await?;
+
                 let camera_floodlight = camera.clone();
                 let mqtt_floodlight = mqtt_instance.resubscribe().await?;
 
                 let camera_motion = camera.clone();
                 let mqtt_motion = mqtt_instance.resubscribe().await?;
 
+                let camera_objects = camera.clone();
+                let mqtt_objects = mqtt_instance.resubscribe().await?;
+
+                let camera_smart = camera.clone();
+                let mqtt_smart = mqtt_instance.resubscribe().await?;
+
+                let camera_events = camera.clone();
+                let mqtt_events = mqtt_instance.resubscribe().await?;
+
+                let camera_stats = camera.clone();
+                let mqtt_stats = mqtt_instance.resubscribe().await?;
+
                 let camera_pn = camera.clone();
                 let mqtt_pn = mqtt_instance.resubscribe().await?;
 
                 let camera_snap = camera.clone();
                 let mqtt_snap = mqtt_instance.resubscribe().await?;
 
+                let camera_thumb = camera.clone();
+                let mqtt_thumb = mqtt_instance.resubscribe().await?;
+
                 let camera_battery = camera.clone();
                 let mqtt_battery = mqtt_instance.resubscribe().await?;
 
                 let camera_floodlight_tasks = camera.clone();
                 let mqtt_floodlight_tasks = mqtt_instance.resubscribe().await?;
 
+                let camera_health = camera.clone();
+                let mqtt_health = mqtt_instance.resubscribe().await?;
+
+                let mut camera_drift = camera.clock_drift().await?;
+                let mqtt_drift = mqtt_instance.resubscribe().await?;
+
                 tokio::select! {
                     _ = cancel.cancelled() => AnyResult::Ok(()),
                     // Handles incomming requests
@@ -321,13 +488,15 @@ async fn listen_on_camera(camera: NeoInstance, mqtt_instance: MqttInstance) -> R
                                 while let Ok(msg) = mqtt_msg.recv().await {
                                     let mqtt_msg = mqtt_msg.resubscribe().await?;
                                     let camera_msg = camera_msg.clone();
+                                    let saved_record = saved_record.clone();
+                                    let motion_stats = motion_stats.clone();
                                     let tx = tx.clone();
                                     let cancel_msg = cancel_msg.clone();
                                     set_msg.spawn(async move {
                                         tokio::select!{
                                             _ = cancel_msg.cancelled() => AnyResult::Ok(()),
                                             v = async {
-                                                let res = handle_mqtt_message(msg, &mqtt_msg, &camera_msg).await;
+                                                let res = handle_mqtt_message(msg, &mqtt_msg, &camera_msg, &saved_record, &motion_stats).await;
                                                 if res.is_err() {
                                                     tx.send(res).await?;
                                                 }
@@ -366,6 +535,22 @@ async fn listen_on_camera(camera: NeoInstance, mqtt_instance: MqttInstance) -> R
                     } => {
                         v
                     },
+                    // Handle the camera refusing us because it has too many clients
+                    v = async {
+                        loop {
+                            camera_busy.wait_for(|busy| *busy).await.with_context(|| {
+                                format!("{}: Busy Watch Dropped", camera_name)
+                            })?;
+                            mqtt_busy.send_message("status", "busy", true).await.with_context(|| {
+                                format!("{}: Failed to publish busy", camera_name)
+                            })?;
+                            camera_busy.wait_for(|busy| !*busy).await.with_context(|| {
+                                format!("{}: Busy Watch Dropped", camera_name)
+                            })?;
+                        }
+                    } => {
+                        v
+                    },
                     // Handle the floodlight
                     v = async {
                         let (tx, mut rx) = mpsc(100);
@@ -425,6 +610,10 @@ async fn listen_on_camera(camera: NeoInstance, mqtt_instance: MqttInstance) -> R
                                 mqtt_motion.send_message("status/motion", "on", true).await.with_context(|| {
                                     format!("{}: Failed to publish motion start", camera_name)
                                 })?;
+                                let timestamp = timezone.now().format(&Rfc3339)?;
+                                mqtt_motion.send_message("status/motion/time", &timestamp, true).await.with_context(|| {
+                                    format!("{}: Failed to publish motion time", camera_name)
+                                })?;
                                 md.wait_for(|state| matches!(state, MdState::Stop(_))).await.with_context(|| {
                                     format!("{}: MdStop Watch Dropped", camera_name)
                                 })?;
@@ -441,6 +630,116 @@ async fn listen_on_camera(camera: NeoInstance, mqtt_instance: MqttInstance) -> R
                             }?;
                         }
                     }, if config.enable_motion => v,
+                    // Handle the object detection on motion
+                    v = async {
+                        let mut md = camera_objects.motion().await?;
+                        let camera_config = camera_objects.config().await?;
+                        loop {
+                            md.wait_for(|state| matches!(state, MdState::Start(_))).await.with_context(|| {
+                                format!("{}: MdStart Watch Dropped", camera_name)
+                            })?;
+                            let detection = camera_config.borrow().object_detection.clone();
+                            if let Some(detection) = detection {
+                                let found = async {
+                                    let jpeg = camera_objects.snapshot().await?;
+                                    objects::detect(&detection, &jpeg).await
+                                }.await;
+                                match found {
+                                    Ok(found) => {
+                                        log::debug!("{}: Detected {:?}", camera_name, found);
+                                        mqtt_objects.send_message("status/motion/objects", &serde_json::to_string(&found)?, true).await.with_context(|| {
+                                            format!("{}: Failed to publish motion objects", camera_name)
+                                        })?;
+                                    }
+                                    Err(e) => log::warn!("{}: Object detection failed: {:#}", camera_name, e),
+                                }
+                            }
+                            md.wait_for(|state| matches!(state, MdState::Stop(_))).await.with_context(|| {
+                                format!("{}: MdStop Watch Dropped", camera_name)
+                            })?;
+                        }
+                    }, if config.enable_motion => v,
+                    // Handle the smart detections such as line crossings
+                    v = async {
+                        let mut smart = camera_smart.smart_events().await?;
+                        loop {
+                            let event = match smart.recv().await {
+                                // Missed some, carry on with the next
+                                Err(RecvError::Lagged(_)) => continue,
+                                event => event.with_context(|| {
+                                    format!("{}: Smart detections dropped", camera_name)
+                                })?,
+                            };
+                            let message = Event::smart(&camera_name, &event, timezone.now());
+                            mqtt_smart.send_message(&format!("status/smart/{}", event.kind), &message.to_json()?, false).await.with_context(|| {
+                                format!("{}: Failed to publish smart detection", camera_name)
+                            })?;
+                        }
+                    }, if config.enable_motion => v,
+                    // Handle the merged events
+                    v = async {
+                        let mut events = camera_events.events().await?;
+                        loop {
+                            let event = match events.recv().await {
+                                // Missed some, carry on with the next
+                                Err(RecvError::Lagged(_)) => continue,
+                                event => event.with_context(|| {
+                                    format!("{}: Events dropped", camera_name)
+                                })?,
+                            };
+                            let message = Event::detection(&camera_name, &event, timezone.now());
+                            mqtt_events.send_message("status/event", &message.to_json()?, false).await.with_context(|| {
+                                format!("{}: Failed to publish event", camera_name)
+                            })?;
+                        }
+                    }, if config.enable_motion => v,
+                    // Handle the motion statistics
+                    v = async {
+                        let mut md = camera_stats.motion().await?;
+                        let mut started = None;
+                        loop {
+                            tokio::select! {
+                                v = md.changed() => {
+                                    v.with_context(|| format!("{}: Motion Watch Dropped", camera_name))?;
+                                    let now = timezone.now();
+                                    let moving = matches!(*md.borrow_and_update(), MdState::Start(_));
+                                    let mut stats = motion_stats.lock().await;
+                                    match (moving, started) {
+                                        (true, None) => {
+                                            stats.start(now);
+                                            started = Some(now);
+                                        }
+                                        (false, Some(start)) => {
+                                            stats.stop(start, now);
+                                            started = None;
+                                        }
+                                        _ => continue,
+                                    }
+                                    if let Err(e) = stats.save() {
+                                        log::warn!("{}: {:?}", camera_name, e);
+                                    }
+                                }
+                                wake = sleep_until_wall(timezone.next_midnight(OffsetDateTime::now_utc())) => {
+                                    if let Wake::Jumped(_) = wake {
+                                        // Work out the midnight again
+                                        continue;
+                                    }
+                                    let now = timezone.now();
+                                    let json = {
+                                        let mut stats = motion_stats.lock().await;
+                                        match stats.roll_day(now) {
+                                            Some(day) => serde_json::to_string(&stats.message(day))?,
+                                            // Woken just before midnight
+                                            None => continue,
+                                        }
+                                    };
+                                    mqtt_stats.send_message("status/motion/stats", &json, true).await.with_context(|| {
+                                        format!("{}: Failed to publish motion stats", camera_name)
+                                    })?;
+                                }
+                            }
+                        }
+                    }, if config.enable_motion => v,
                     // Handle the SNAP (image preview)
                     v = async {
                         let mut wait = IntervalStream::new({
@@ -450,12 +749,7 @@ async fn listen_on_camera(camera: NeoInstance, mqtt_instance: MqttInstance) -> R
                         });
                         let v = async {
                             while wait.next().await.is_some() {
-                                let image = camera_snap.run_passive_task(|cam| {
-                                    Box::pin(async move {
-                                        let image = cam.get_snapshot().await?;
-                                        AnyResult::Ok(image)
-                                    })
-                                }).await;
+                                let image = camera_snap.passive_snapshot().await;
                                 let image = match image {
                                     Err(e) => match e.downcast::<neolink_core::Error>() {
                                         Ok(neolink_core::Error::CameraServiceUnavailable{..}) => {
@@ -484,6 +778,29 @@ async fn listen_on_camera(camera: NeoInstance, mqtt_instance: MqttInstance) -> R
                         }?;
                         AnyResult::Ok(())
                     }, if config.enable_preview => v,
+                    // Handle the substream thumbnail
+                    v = async {
+                        let mut wait = IntervalStream::new({
+                            let mut i = interval(Duration::from_millis(config.thumbnail_update));
+                            i.set_missed_tick_behavior(MissedTickBehavior::Skip);
+                            i
+                        });
+                        while wait.next().await.is_some() {
+                            // A missed thumbnail is not worth restarting mqtt for
+                            match camera_thumb.thumbnail().await {
+                                Ok(image) => {
+                                    mqtt_thumb
+                                        .send_message("status/thumbnail", BASE64.encode(image).as_str(), true)
+                                        .await
+                                        .with_context(|| {
+                                            format!("{}: Failed to publish thumbnail", camera_name)
+                                        })?;
+                                }
+                                Err(e) => log::debug!("{}: No thumbnail: {:?}", camera_name, e),
+                            }
+                        }
+                        AnyResult::Ok(())
+                    }, if config.enable_thumbnail => v,
                     // Handle the battery publish
                     v = async {
                         let mut wait = IntervalStream::new({
@@ -494,13 +811,15 @@ async fn listen_on_camera(camera: NeoInstance, mqtt_instance: MqttInstance) -> R
 
                         let v = async {
                             while wait.next().await.is_some() {
-                                let xml = camera_battery.run_passive_task(|cam| {
+                                let subscription = camera_battery.subscriptions().await?.acquire("battery").await?;
+                                let info = camera_battery.run_passive_task(|cam| {
                                     Box::pin(async move {
-                                        let xml = cam.battery_info().await?;
-                                        AnyResult::Ok(xml)
+                                        let info = cam.get_battery_info().await?;
+                                        AnyResult::Ok(info)
                                     })
                                 }).await;
-                                let xml = match xml {
+                                drop(subscription);
+                                let info = match info {
                                     Err(e) => match e.downcast::<neolink_core::Error>() {
                                         Ok(neolink_core::Error::CameraServiceUnavailable{..}) => {
                                             log::debug!("Battery not supported");
@@ -512,11 +831,17 @@ async fn listen_on_camera(camera: NeoInstance, mqtt_instance: MqttInstance) -> R
                                     n => n,
                                 }?;
                                 mqtt_battery
-                                        .send_message("status/battery_level", format!("{}", xml.battery_percent).as_str(), true)
+                                        .send_message("status/battery_level", format!("{}", info.percent).as_str(), true)
                                         .await
                                         .with_context(|| {
                                             format!("{}: Failed to publish battery", camera_name)
                                         })?;
+                                mqtt_battery
+                                        .send_message("status/battery_info", &serde_json::to_string(&info)?, true)
+                                        .await
+                                        .with_context(|| {
+                                            format!("{}: Failed to publish battery info", camera_name)
+                                        })?;
                             }
                             AnyResult::Ok(())
                         }.await;
@@ -551,6 +876,49 @@ async fn listen_on_camera(camera: NeoInstance, mqtt_instance: MqttInstance) -> R
                             }?;
                         }
                     } => v,
+                    // Handle the stream health
+                    v = async {
+                        let kinds = camera_health.config().await?.borrow().stream.as_stream_kinds();
+                        let mut streams = vec![];
+                        for kind in kinds {
+                            let mut stream = camera_health.stream(kind).await?;
+                            // Only watch the health, do not keep the stream running
+                            stream.deactivate().await?;
+                            streams.push(stream);
+                        }
+                        futures::future::try_join_all(streams.iter_mut().map(|stream| {
+                            let mqtt_health = &mqtt_health;
+                            let camera_name = &camera_name;
+                            async move {
+                                let topic = format!("status/stream/{}", stream_topic(stream.name));
+                                loop {
+                                    let health = *stream.health.borrow_and_update();
+                                    mqtt_health.send_message(&topic, &health.to_string(), true).await.with_context(|| {
+                                        format!("{}: Failed to publish stream health", camera_name)
+                                    })?;
+                                    if stream.health.changed().await.is_err() {
+                                        break;
+                                    }
+                                }
+                                AnyResult::Ok(())
+                            }
+                        })).await?;
+                        AnyResult::Ok(())
+                    }, if config.enable_stream_health => v,
+                    // Warn when the camera's clock has drifted from the host's
+                    v = async {
+                        loop {
+                            camera_drift.changed().await.with_context(|| {
+                                format!("{}: Clock Drift Watch Dropped", camera_name)
+                            })?;
+                            let drift = *camera_drift.borrow_and_update();
+                            if let Some(drift) = drift.filter(|drift| drift.exceeded) {
+                                mqtt_drift.send_message("status/clock_drift", &serde_json::to_string(&drift)?, false).await.with_context(|| {
+                                    format!("{}: Failed to publish clock drift", camera_name)
+                                })?;
+                            }
+                        }
+                    } => v,
                     // Handle the floodlight task activation
                     v = async {
                         let flt_status = camera_floodlight_tasks.run_passive_task(|cam| Box::pin(async move {
@@ -601,11 +969,31 @@ async fn listen_on_camera(camera: NeoInstance, mqtt_instance: MqttInstance) -> R
     Ok(())
 }
 
+/// The name of the stream in the `status/stream/{stream}` topics
+fn stream_topic(stream: StreamKind) -> &'static str {
+    match stream {
+        StreamKind::Main => "main",
+        StreamKind::Sub => "sub",
+        StreamKind::Extern => "extern",
+    }
+}
+
 async fn handle_mqtt_message(
     msg: MqttReply,
     mqtt: &MqttInstance,
     camera: &NeoInstance,
+    saved_record: &Mutex<Option<Record>>,
+    motion_stats: &Mutex<MotionStats>,
 ) -> Result<()> {
+    // Our own OK and FAIL replies come back on the same topics
+    let reply = ["OK", "FAIL"].contains(&msg.message.as_str())
+        || msg.message.starts_with("OK:")
+        || msg.message.starts_with("FAIL:");
+    if msg.topic.starts_with("control/") && !reply {
+        let name = camera.config().await?.borrow().name.clone();
+        audit::mqtt(&name, &msg.topic, &msg.message);
+    }
+
     match msg.as_ref() {
         MqttReplyRef {
             topic: _,
@@ -624,56 +1012,50 @@ async fn handle_mqtt_message(
         }
         MqttReplyRef {
             topic: "control/floodlight",
-            message: "on",
-        } => {
-            let res = camera
-                .run_task(|cam| {
-                    Box::pin(async move {
-                        cam.set_floodlight_manual(true, 180).await?;
-                        AnyResult::Ok(())
-                    })
-                })
-                .await;
-            let reply = if res.is_err() {
-                error!("Failed to turn on the floodlight light: {:?}", res.err());
-                "FAIL"
-            } else {
-                "OK"
-            }
-            .to_string();
-            mqtt.send_message("control/floodlight", &reply, false)
-                .await
-                .with_context(|| "Failed to publish camera status light on")?;
-        }
-        MqttReplyRef {
-            topic: "control/floodlight",
-            message: "off",
+            message,
         } => {
-            let res = camera
-                .run_task(|cam| {
-                    Box::pin(async move {
-                        cam.set_floodlight_manual(false, 180).await?;
-                        AnyResult::Ok(())
+            let mut args = message.split_whitespace();
+            let state = match args.next() {
+                Some("on") => Some(true),
+                Some("off") => Some(false),
+                _ => None,
+            };
+            let brightness = match args.next() {
+                None => Some(None),
+                Some(brightness) => brightness
+                    .parse::<u32>()
+                    .ok()
+                    .filter(|brightness| *brightness <= 100)
+                    .map(Some),
+            };
+            let reply = if let (Some(state), Some(brightness)) = (state, brightness) {
+                let res = camera
+                    .run_command(|cam| {
+                        Box::pin(async move {
+                            cam.set_floodlight(state, brightness).await?;
+                            AnyResult::Ok(())
+                        })
                     })
-                })
-                .await;
-            let reply = if res.is_err() {
-                error!("Failed to turn off the floodlight light: {:?}", res.err());
-                "FAIL"
+                    .await;
+                if let Err(e) = res {
+                    error!("Failed to set the floodlight light: {:?}", e);
+                    "FAIL".to_string()
+                } else {
+                    "OK".to_string()
+                }
             } else {
-                "OK"
-            }
-            .to_string();
+                "FAIL: Expected `on|off` and optionally a brightness of 0-100".to_string()
+            };
             mqtt.send_message("control/floodlight", &reply, false)
                 .await
-                .with_context(|| "Failed to publish camera status light off")?;
+                .with_context(|| "Failed to publish camera floodlight")?;
         }
         MqttReplyRef {
             topic: "control/led",
             message: "on",
         } => {
             let res = camera
-                .run_task(|cam| {
+                .run_command(|cam| {
                     Box::pin(async move {
                         cam.led_light_set(true).await?;
                         AnyResult::Ok(())
@@ -696,7 +1078,7 @@ async fn handle_mqtt_message(
             message: "off",
         } => {
             let res = camera
-                .run_task(|cam| {
+                .run_command(|cam| {
                     Box::pin(async move {
                         cam.led_light_set(false).await?;
                         AnyResult::Ok(())
@@ -719,9 +1101,9 @@ async fn handle_mqtt_message(
             message: "on",
         } => {
             let res = camera
-                .run_task(|cam| {
+                .run_command(|cam| {
                     Box::pin(async move {
-                        cam.irled_light_set(LightState::On).await?;
+                        cam.set_ir_led(LightState::On).await?;
                         AnyResult::Ok(())
                     })
                 })
@@ -742,9 +1124,9 @@ async fn handle_mqtt_message(
             message: "off",
         } => {
             let res = camera
-                .run_task(|cam| {
+                .run_command(|cam| {
                     Box::pin(async move {
-                        cam.irled_light_set(LightState::Off).await?;
+                        cam.set_ir_led(LightState::Off).await?;
                         AnyResult::Ok(())
                     })
                 })
@@ -765,9 +1147,9 @@ async fn handle_mqtt_message(
             message: "auto",
         } => {
             let res = camera
-                .run_task(|cam| {
+                .run_command(|cam| {
                     Box::pin(async move {
-                        cam.irled_light_set(LightState::Auto).await?;
+                        cam.set_ir_led(LightState::Auto).await?;
                         AnyResult::Ok(())
                     })
                 })
@@ -783,12 +1165,45 @@ async fn handle_mqtt_message(
                 .await
                 .with_context(|| "Failed to publish ir auto")?;
         }
+        MqttReplyRef {
+            topic: "control/led/doorbell",
+            message,
+        } => {
+            let state = match message {
+                "on" => Some(LightState::On),
+                "off" => Some(LightState::Off),
+                "auto" => Some(LightState::Auto),
+                _ => None,
+            };
+            let reply = if let Some(state) = state {
+                let res = camera
+                    .run_command(|cam| {
+                        Box::pin(async move {
+                            cam.doorbell_light_set(state).await?;
+                            AnyResult::Ok(())
+                        })
+                    })
+                    .await;
+                if res.is_err() {
+                    error!("Failed to set the doorbell light: {:?}", res.err());
+                    "FAIL"
+                } else {
+                    "OK"
+                }
+            } else {
+                "FAIL: Expected on, off or auto"
+            }
+            .to_string();
+            mqtt.send_message("control/led/doorbell", &reply, false)
+                .await
+                .with_context(|| "Failed to publish doorbell light")?;
+        }
         MqttReplyRef {
             topic: "control/reboot",
             ..
         } => {
             let res = camera
-                .run_task(|cam| {
+                .run_command(|cam| {
                     Box::pin(async move {
                         cam.reboot().await?;
                         AnyResult::Ok(())
@@ -806,13 +1221,204 @@ async fn handle_mqtt_message(
                 .await
                 .with_context(|| "Failed to publish reboot on the camera")?;
         }
+        MqttReplyRef {
+            topic: "control/record",
+            message: "on",
+        } => {
+            let mut saved = saved_record.lock().await;
+            let res = camera
+                .run_command(|cam| Box::pin(async move { Ok(cam.start_manual_record().await?) }))
+                .await;
+            let reply = match res {
+                Ok(previous) => {
+                    // Keep the first schedule if already recording so that
+                    // off restores the original and not the manual one
+                    if saved.is_none() {
+                        *saved = Some(previous);
+                    }
+                    "OK"
+                }
+                Err(e) => {
+                    error!("Failed to start recording: {:?}", e);
+                    "FAIL"
+                }
+            }
+            .to_string();
+            mqtt.send_message("control/record", &reply, false)
+                .await
+                .with_context(|| "Failed to publish record on")?;
+        }
+        MqttReplyRef {
+            topic: "control/record",
+            message: "off",
+        } => {
+            let mut saved = saved_record.lock().await;
+            let res = match saved.clone() {
+                Some(previous) => {
+                    camera
+                        .run_command(|cam| {
+                            let previous = previous.clone();
+                            Box::pin(async move {
+                                cam.stop_manual_record(previous).await?;
+                                AnyResult::Ok(())
+                            })
+                        })
+                        .await
+                }
+                // Nothing is written rather than guessing the schedule
+                None => Err(anyhow!(
+                    "No saved recording schedule to restore, set it again in the app"
+                )),
+            };
+            let reply = match res {
+                Ok(()) => {
+                    *saved = None;
+                    "OK".to_string()
+                }
+                Err(e) => {
+                    error!("Failed to stop recording: {:?}", e);
+                    format!("FAIL: {}", e)
+                }
+            };
+            mqtt.send_message("control/record", &reply, false)
+                .await
+                .with_context(|| "Failed to publish record off")?;
+        }
+        MqttReplyRef {
+            topic: "control/zoom/digital",
+            message,
+        } => {
+            let rect = if message.trim() == "reset" {
+                Some(ZoomRect::default())
+            } else {
+                ZoomRect::parse(message)
+            };
+            let reply = if let Some(rect) = rect {
+                if let Err(e) = camera.set_digital_zoom(rect).await {
+                    error!("Failed to set the digital zoom: {:?}", e);
+                    format!("FAIL: {e:?}")
+                } else {
+                    "OK".to_string()
+                }
+            } else {
+                "FAIL: Expected `x y width height` as fractions of the frame".to_string()
+            };
+
+            mqtt.send_message("control/zoom/digital", &reply, false)
+                .await
+                .with_context(|| "Failed to publish digital zoom")?;
+        }
+        MqttReplyRef {
+            topic: "control/mute",
+            message,
+        } => {
+            let all_streams = [StreamKind::Main, StreamKind::Sub, StreamKind::Extern];
+            let mut args = message.split_whitespace();
+            let muted = match args.next() {
+                Some("on") => Some(true),
+                Some("off") => Some(false),
+                _ => None,
+            };
+            let streams = match args.next() {
+                None | Some("all") => Some(all_streams.to_vec()),
+                Some(stream) => all_streams
+                    .iter()
+                    .find(|kind| stream_topic(**kind) == stream)
+                    .map(|kind| vec![*kind]),
+            };
+            let reply = if let (Some(muted), Some(streams)) = (muted, streams) {
+                let mut reply = "OK".to_string();
+                for stream in streams {
+                    if let Err(e) = camera.set_mute(stream, muted).await {
+                        error!("Failed to set the audio mute: {:?}", e);
+                        reply = format!("FAIL: {e:?}");
+                        break;
+                    }
+                }
+                reply
+            } else {
+                "FAIL: Expected `on|off` and optionally `main|sub|extern`".to_string()
+            };
+
+            mqtt.send_message("control/mute", &reply, false)
+                .await
+                .with_context(|| "Failed to publish audio mute")?;
+        }
+        MqttReplyRef {
+            topic: "control/bookmark",
+            message,
+        } => {
+            let config = camera.config().await?.borrow().clone();
+            let bookmark = Event {
+                label: Some(message.trim().to_string()),
+                recording: Some(saved_record.lock().await.is_some()),
+                ..Event::new(&config.name, EventKind::Bookmark, config.timezone().now())
+            };
+            let json = bookmark.to_json()?;
+
+            let reply = match config
+                .mqtt
+                .bookmarks
+                .as_ref()
+                .map(|path| logfile::append_line(path, &json))
+            {
+                Some(Err(e)) => {
+                    error!("Failed to save the bookmark: {:?}", e);
+                    format!("FAIL: {e:?}")
+                }
+                _ => "OK".to_string(),
+            };
+            mqtt.send_message("status/bookmark", &json, false)
+                .await
+                .with_context(|| "Failed to publish bookmark")?;
+            mqtt.send_message("control/bookmark", &reply, false)
+                .await
+                .with_context(|| "Failed to publish bookmark reply")?;
+        }
+        #[cfg(feature = "gstreamer")]
+        MqttReplyRef {
+            topic: "control/play_clip",
+            message,
+        } => {
+            let clips = crate::talk::clips::configured();
+            let reply = match crate::talk::clips::play(camera, &clips, message.trim()).await {
+                Ok(()) => "OK".to_string(),
+                Err(e) => {
+                    error!("Failed to play the clip: {:?}", e);
+                    format!("FAIL: {e:#}")
+                }
+            };
+            mqtt.send_message("control/play_clip", &reply, false)
+                .await
+                .with_context(|| "Failed to publish play clip reply")?;
+        }
+        MqttReplyRef {
+            topic: "control/log_level",
+            message,
+        } => {
+            let name = camera.config().await?.borrow().name.clone();
+            let mut args = message.split_whitespace();
+            let level = args.next().unwrap_or_default().parse::<LevelFilter>();
+            let minutes = args.next().map(|m| m.parse::<u64>()).unwrap_or(Ok(10));
+            let reply = match (level, minutes) {
+                (Ok(level), Ok(minutes)) => match loglevel::boost(&name, level, minutes) {
+                    Ok(()) => "OK".to_string(),
+                    Err(e) => format!("FAIL: {e:?}"),
+                },
+                _ => "FAIL: Expected a level such as trace, debug or info and the minutes"
+                    .to_string(),
+            };
+            mqtt.send_message("control/log_level", &reply, false)
+                .await
+                .with_context(|| "Failed to publish log level reply")?;
+        }
         MqttReplyRef {
             topic: "control/zoom",
             message,
         } => {
             let reply = if let Ok(amount) = message.parse::<f32>() {
                 if let Err(e) = camera
-                    .run_task(|cam| {
+                    .run_command(|cam| {
                         Box::pin(async move {
                             cam.zoom_to((amount * 1000.0) as u32).await?;
                             AnyResult::Ok(())
@@ -859,18 +1465,15 @@ async fn handle_mqtt_message(
                         }
                     };
 
-                    let bc_direction = match direction_txt {
-                        "up" => Some(BcDirection::Up),
-                        "down" => Some(BcDirection::Down),
-                        "left" => Some(BcDirection::Left),
-                        "right" => Some(BcDirection::Right),
-                        n => {
-                            error!("Unrecognized PTZ direction \"{}\"", n);
-                            None
-                        }
+                    let direction = match direction_txt {
+                        "stop" => None,
+                        n => <crate::ptz::CmdDirection as clap::ValueEnum>::from_str(n, true).ok(),
                     };
+                    if direction.is_none() {
+                        error!("Unrecognized PTZ direction \"{}\"", direction_txt);
+                    }
 
-                    if let (Some(seconds), Some(bc_direction)) = (seconds, bc_direction) {
+                    if let (Some(seconds), Some(direction)) = (seconds, direction) {
                         // On drop send the stop command again just to make sure it stops
                         let _drop_command = camera.clone().drop_command(
                             move |cam| {
@@ -882,9 +1485,9 @@ async fn handle_mqtt_message(
                             Duration::from_millis(100),
                         );
                         if let Err(e) = camera
-                            .run_task(|cam| {
+                            .run_command(|cam| {
                                 Box::pin(async move {
-                                    cam.send_ptz(bc_direction, speed).await?;
+                                    crate::ptz::start_move(cam, direction, speed).await?;
                                     sleep(Duration::from_secs_f32(seconds)).await;
                                     cam.send_ptz(BcDirection::Stop, speed).await?;
                                     AnyResult::Ok(())
@@ -905,7 +1508,7 @@ async fn handle_mqtt_message(
                     "FAIL"
                 }
             } else {
-                error!("No PTZ Direction given. Please add up/down/left/right/in/out");
+                error!("No PTZ Direction given. Please add up/down/left/right/in/out/near/far");
                 "FAIL"
             }
             .to_string();
@@ -920,7 +1523,7 @@ async fn handle_mqtt_message(
         } => {
             let reply = if let Ok(id) = message.parse::<u8>() {
                 let res = camera
-                    .run_task(|cam| {
+                    .run_command(|cam| {
                         Box::pin(async move {
                             cam.moveto_ptz_preset(id).await?;
                             AnyResult::Ok(())
@@ -938,7 +1541,7 @@ async fn handle_mqtt_message(
                 "FAIL"
             }
             .to_string();
-            mqtt.send_message("control/ir", &reply, false)
+            mqtt.send_message("control/ptz/preset", &reply, false)
                 .await
                 .with_context(|| "Failed to publish ptz move")?;
         }
@@ -953,7 +1556,7 @@ async fn handle_mqtt_message(
             let reply = if let (Some(Ok(id)), Some(name)) = (id.map(|id| id.parse::<u8>()), name) {
                 let name = name.to_owned();
                 let res = camera
-                    .run_task(|cam| {
+                    .run_command(|cam| {
                         let name = name.clone();
                         Box::pin(async move {
                             cam.set_ptz_preset(id, name).await?;
@@ -977,16 +1580,44 @@ async fn handle_mqtt_message(
                 "FAIL"
             }
             .to_string();
-            mqtt.send_message("control/ir", &reply, false)
+            mqtt.send_message("control/ptz/assign", &reply, false)
                 .await
-                .with_context(|| "Failed to publish ptz move")?;
+                .with_context(|| "Failed to publish ptz assign")?;
+        }
+        MqttReplyRef {
+            topic: "control/preset",
+            message,
+        } => {
+            let preset = message.trim().to_string();
+            let res = camera
+                .run_command(|cam| {
+                    let preset = preset.clone();
+                    Box::pin(async move {
+                        match preset.parse::<u8>() {
+                            Ok(id) => cam.moveto_ptz_preset(id).await?,
+                            Err(_) => cam.moveto_ptz_preset_named(&preset).await?,
+                        }
+                        AnyResult::Ok(())
+                    })
+                })
+                .await;
+            let reply = match res {
+                Err(e) => {
+                    error!("Failed to move to ptz preset {}: {:?}", preset, e);
+                    format!("FAIL: {e:?}")
+                }
+                Ok(()) => "OK".to_string(),
+            };
+            mqtt.send_message("control/preset", &reply, false)
+                .await
+                .with_context(|| "Failed to publish preset move")?;
         }
         MqttReplyRef {
             topic: "control/pir",
             message: "on",
         } => {
             let res = camera
-                .run_task(|cam| {
+                .run_command(|cam| {
                     Box::pin(async move {
                         cam.pir_set(true).await?;
                         AnyResult::Ok(())
@@ -1009,7 +1640,7 @@ async fn handle_mqtt_message(
             message: "off",
         } => {
             let res = camera
-                .run_task(|cam| {
+                .run_command(|cam| {
                     Box::pin(async move {
                         cam.pir_set(false).await?;
                         AnyResult::Ok(())
@@ -1080,7 +1711,7 @@ async fn handle_mqtt_message(
             let reply = match state {
                 Ok(state) => {
                     if let Err(e) = camera
-                        .run_task(|cam| {
+                        .run_command(|cam| {
                             Box::pin(async move {
                                 cam.flightlight_tasks_enable(state).await?;
                                 AnyResult::Ok(())
@@ -1102,21 +1733,37 @@ async fn handle_mqtt_message(
         }
         MqttReplyRef {
             topic: "control/siren",
-            message: "on",
+            message,
         } => {
-            let res = camera
-                .run_task(|cam| {
-                    Box::pin(async move {
-                        cam.siren().await?;
-                        AnyResult::Ok(())
+            let mut args = message.split_whitespace();
+            let play = match (args.next(), args.next(), args.next()) {
+                (Some("on"), None, None) => Some(SirenPlay::Times(1)),
+                (Some("on"), Some(times), None) => times.parse().ok().map(SirenPlay::Times),
+                (Some("continuous"), None, None) => Some(SirenPlay::Manual(true)),
+                (Some("off"), None, None) => Some(SirenPlay::Manual(false)),
+                _ => None,
+            };
+            let reply = if let Some(play) = play {
+                let res = camera
+                    .run_command(|cam| {
+                        Box::pin(async move {
+                            match play {
+                                SirenPlay::Times(times) => cam.siren_times(times).await?,
+                                SirenPlay::Manual(on) => cam.siren_manual(on).await?,
+                            }
+                            AnyResult::Ok(())
+                        })
                     })
-                })
-                .await;
-            let reply = if let Err(e) = res {
-                error!("Failed to trigger siren: {:?}", e);
-                format!("FAIL: {e:?}")
+                    .await;
+                if let Err(e) = res {
+                    error!("Failed to trigger siren: {:?}", e);
+                    format!("FAIL: {e:?}")
+                } else {
+                    "OK".to_string()
+                }
             } else {
-                "OK".to_string()
+                "FAIL: Expected `on` with an optional number of times, `continuous` or `off`"
+                    .to_string()
             };
 
             mqtt.send_message("control/siren", &reply, false)
@@ -1128,7 +1775,7 @@ async fn handle_mqtt_message(
             ..
         } => {
             let res = camera
-                .run_task(|cam| {
+                .run_command(|cam| {
                     Box::pin(async move {
                         let xml = cam.battery_info().await?;
                         AnyResult::Ok(xml)
@@ -1170,11 +1817,39 @@ async fn handle_mqtt_message(
                 .await
                 .with_context(|| "Failed to publish battery query")?;
         }
+        MqttReplyRef {
+            topic: "query/ir", ..
+        } => {
+            let res = camera
+                .run_command(|cam| {
+                    Box::pin(async move {
+                        let state = cam.get_ir_led().await?;
+                        AnyResult::Ok(state)
+                    })
+                })
+                .await;
+            let reply = match res {
+                Err(e) => {
+                    error!("Failed to get the ir state: {:?}", e);
+                    "FAIL"
+                }
+                Ok(state) => {
+                    mqtt.send_message("status/ir", lightstate_name(state), false)
+                        .await
+                        .with_context(|| "Failed to publish ir state")?;
+                    "OK"
+                }
+            }
+            .to_string();
+            mqtt.send_message("query/ir", &reply, false)
+                .await
+                .with_context(|| "Failed to publish ir query")?;
+        }
         MqttReplyRef {
             topic: "query/pir", ..
         } => {
             let res = camera
-                .run_task(|cam| {
+                .run_command(|cam| {
                     Box::pin(async move {
                         let xml = cam.get_pirstate().await?;
                         AnyResult::Ok(xml)
@@ -1221,7 +1896,7 @@ async fn handle_mqtt_message(
             ..
         } => {
             let res = camera
-                .run_task(|cam| {
+                .run_command(|cam| {
                     Box::pin(async move {
                         let xml = cam.get_ptz_preset().await?;
                         AnyResult::Ok(xml)
@@ -1263,18 +1938,23 @@ async fn handle_mqtt_message(
                 .await
                 .with_context(|| "Failed to publish ptz query")?;
         }
+        MqttReplyRef {
+            topic: "query/motion/stats",
+            ..
+        } => {
+            let json = serde_json::to_string(&motion_stats.lock().await.today())?;
+            mqtt.send_message("status/motion/stats", &json, true)
+                .await
+                .with_context(|| "Failed to publish motion stats")?;
+            mqtt.send_message("query/motion/stats", "OK", false)
+                .await
+                .with_context(|| "Failed to publish motion stats query")?;
+        }
         MqttReplyRef {
             topic: "query/preview",
             ..
         } => {
-            let res = camera
-                .run_task(|cam| {
-                    Box::pin(async move {
-                        let data = cam.get_snapshot().await?;
-                        AnyResult::Ok(data)
-                    })
-                })
-                .await;
+            let res = camera.snapshot().await;
             let reply = match res {
                 Err(e) => {
                     error!("Failed to get snapshot: {:?}", e);
@@ -1298,7 +1978,43 @@ async fn handle_mqtt_message(
                 .await
                 .with_context(|| "Failed to publish preview query")?;
         }
+        MqttReplyRef {
+            topic: "query/thumbnail",
+            ..
+        } => {
+            let res = camera.thumbnail().await;
+            let reply = match res {
+                Err(e) => {
+                    error!("Failed to get thumbnail: {:?}", e);
+                    "FAIL"
+                }
+                Ok(bytes) => {
+                    if let Err(e) = mqtt
+                        .send_message("status/thumbnail", BASE64.encode(bytes).as_str(), true)
+                        .await
+                        .with_context(|| "Failed to publish thumbnail")
+                    {
+                        error!("Failed to send thumbnail: {e:?}");
+                        "FAIL"
+                    } else {
+                        "OK"
+                    }
+                }
+            }
+            .to_string();
+            mqtt.send_message("query/thumbnail", &reply, false)
+                .await
+                .with_context(|| "Failed to publish thumbnail query")?;
+        }
         _ => {}
     }
     Ok(())
 }
+
+/// How `/control/siren` sounds the siren
+#[derive(Clone, Copy)]
+enum SirenPlay {
+    Times(u32),
+    /// Until it is turned off
+    Manual(bool),
+}
@@ -0,0 +1,146 @@
+//! Statistics of when a camera sees motion
+//!
+//! Motion is counted by the weekday and hour in the camera's `timezone`, this
+//! makes a heatmap of the busiest times of the week which helps when placing a
+//! camera or setting its schedules
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use time::{format_description::well_known::Rfc3339, Duration, OffsetDateTime, Time};
+
+/// Motion counted since `since`, optionally kept in a file
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub(super) struct MotionStats {
+    /// When the counting started
+    since: Option<String>,
+    /// Motion starts by weekday, Monday first, and hour
+    events: [[u32; 24]; 7],
+    /// Seconds of motion by weekday, Monday first, and hour
+    seconds: [[u64; 24]; 7],
+    /// The motion of the current day
+    today: DayStats,
+    #[serde(skip)]
+    path: Option<PathBuf>,
+}
+
+/// The motion of one day
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub(super) struct DayStats {
+    /// The date in the camera's `timezone`
+    date: String,
+    events: u32,
+    seconds: u64,
+}
+
+/// The message published on `/status/motion/stats`
+#[derive(Serialize)]
+pub(super) struct MotionStatsMessage<'a> {
+    since: Option<&'a str>,
+    /// The day that this summarises, the day just ended or today for a query
+    day: DayStats,
+    /// Up to three hours with the most motion starts, busiest first
+    busiest_hours: Vec<u8>,
+    /// Motion starts by hour summed over the week
+    hours: [u32; 24],
+    /// Motion starts by weekday, Monday first, and hour
+    events: &'a [[u32; 24]; 7],
+    /// Seconds of motion by weekday, Monday first, and hour
+    seconds: &'a [[u64; 24]; 7],
+}
+
+impl MotionStats {
+    /// Continue the stats saved at `path` or start new ones
+    pub(super) fn load(path: Option<PathBuf>) -> Self {
+        let saved = path.as_ref().and_then(|path| {
+            let json = std::fs::read_to_string(path).ok()?;
+            serde_json::from_str::<Self>(&json)
+                .map_err(|e| log::warn!("Ignoring the motion stats in {:?}: {}", path, e))
+                .ok()
+        });
+        Self {
+            path,
+            ..saved.unwrap_or_default()
+        }
+    }
+
+    /// Write the stats to their file if they have one
+    pub(super) fn save(&self) -> Result<()> {
+        if let Some(path) = self.path.as_ref() {
+            std::fs::write(path, serde_json::to_string(self)?)
+                .with_context(|| format!("Failed to save the motion stats to {:?}", path))?;
+        }
+        Ok(())
+    }
+
+    /// Count a motion start
+    pub(super) fn start(&mut self, now: OffsetDateTime) {
+        self.roll_day(now);
+        self.since
+            .get_or_insert_with(|| now.format(&Rfc3339).unwrap_or_default());
+        self.events[weekday(now)][now.hour() as usize] += 1;
+        self.today.events += 1;
+    }
+
+    /// Count the time of a motion that has stopped in the hours it spanned
+    pub(super) fn stop(&mut self, start: OffsetDateTime, stop: OffsetDateTime) {
+        self.roll_day(stop);
+        let mut from = start;
+        while from < stop {
+            let next_hour =
+                from.replace_time(Time::MIDNIGHT) + Duration::hours(from.hour() as i64 + 1);
+            let to = next_hour.min(stop);
+            self.seconds[weekday(from)][from.hour() as usize] += (to - from).whole_seconds() as u64;
+            from = to;
+        }
+        self.today.seconds += (stop - start).whole_seconds().max(0) as u64;
+    }
+
+    /// Start a new day if `now` is past the current one, returns the day that ended
+    pub(super) fn roll_day(&mut self, now: OffsetDateTime) -> Option<DayStats> {
+        let date = now.date().to_string();
+        if self.today.date == date {
+            return None;
+        }
+        let ended = std::mem::replace(
+            &mut self.today,
+            DayStats {
+                date,
+                ..Default::default()
+            },
+        );
+        (!ended.date.is_empty()).then_some(ended)
+    }
+
+    /// Summarise the stats with the motion of `day`
+    pub(super) fn message(&self, day: DayStats) -> MotionStatsMessage<'_> {
+        let mut hours = [0u32; 24];
+        for day in self.events.iter() {
+            for (hour, count) in day.iter().enumerate() {
+                hours[hour] += count;
+            }
+        }
+        let mut busiest_hours = (0..24u8)
+            .filter(|hour| hours[*hour as usize] > 0)
+            .collect::<Vec<_>>();
+        busiest_hours.sort_by_key(|hour| std::cmp::Reverse(hours[*hour as usize]));
+        busiest_hours.truncate(3);
+
+        MotionStatsMessage {
+            since: self.since.as_deref(),
+            day,
+            busiest_hours,
+            hours,
+            events: &self.events,
+            seconds: &self.seconds,
+        }
+    }
+
+    /// Summarise the stats with the motion of today so far
+    pub(super) fn today(&self) -> MotionStatsMessage<'_> {
+        self.message(self.today.clone())
+    }
+}
+
+fn weekday(time: OffsetDateTime) -> usize {
+    time.weekday().number_days_from_monday() as usize
+}
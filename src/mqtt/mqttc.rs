@@ -9,7 +9,7 @@ use rumqttc::{
     AsyncClient, ConnectReturnCode, Event, Incoming, LastWill, MqttOptions, QoS, TlsConfiguration,
     Transport,
 };
-use std::sync::Arc;
+use std::{collections::HashMap, sync::Arc};
 use tokio::task::JoinSet;
 use tokio::{
     sync::{
@@ -17,6 +17,7 @@ use tokio::{
         mpsc::{channel as mpsc, Receiver as MpscReceiver, Sender as MpscSender},
         oneshot::{channel as oneshot, Sender as OneshotSender},
         watch::Receiver as WatchReceiver,
+        Mutex,
     },
     time::{sleep, Duration},
 };
@@ -24,6 +25,10 @@ use tokio_stream::{wrappers::BroadcastStream, StreamExt};
 use tokio_util::sync::CancellationToken;
 use uuid::Uuid;
 
+/// The last message of each retained topic that was published, kept so they
+/// can be published again when the broker or home assistant restarts
+type RetainedCache = Arc<Mutex<HashMap<String, Arc<String>>>>;
+
 pub(crate) struct Mqtt {
     cancel: CancellationToken,
     outgoing_tx: MpscSender<MqttRequest>,
@@ -36,6 +41,8 @@ impl Mqtt {
         let (outgoing_tx, mut outgoing_rx) = mpsc::<MqttRequest>(100);
         let cancel = CancellationToken::new();
         let mut set = JoinSet::<AnyResult<()>>::new();
+        // Shared by each backend so that it lasts over reconnects
+        let retained: RetainedCache = Default::default();
 
         // Thread that handles the mqttc side
         // including restarting it if the config changes
@@ -59,6 +66,7 @@ impl Mqtt {
                             outgoing_rx: &mut outgoing_rx,
                             outgoing_tx: thread_outgoing_tx.clone(),
                             config: mqtt_config.as_ref().unwrap(),
+                            retained: retained.clone(),
                             cancel: CancellationToken::new(),
                         };
                         backend.run().await
@@ -119,6 +127,7 @@ struct MqttBackend<'a> {
     outgoing_rx: &'a mut MpscReceiver<MqttRequest>,
     outgoing_tx: MpscSender<MqttRequest>,
     config: &'a MqttServerConfig,
+    retained: RetainedCache,
     cancel: CancellationToken,
 }
 
@@ -202,6 +211,7 @@ impl<'a> MqttBackend<'a> {
                     let cancel = self.cancel.clone();
                     let thread_cancel = loop_cancel.clone();
                     let server_config = self.config.clone();
+                    let retained = self.retained.clone();
                     tokio::task::spawn(async move {
                         tokio::select!{
                             _ = cancel.cancelled() => AnyResult::Ok(()),
@@ -235,6 +245,13 @@ impl<'a> MqttBackend<'a> {
                                         ).await;
                                         match &v {
                                             Ok(()) => {
+                                                let mut retained = retained.lock().await;
+                                                // An empty message clears the retained topic
+                                                if msg.message.is_empty() {
+                                                    retained.remove(&msg.topic);
+                                                } else {
+                                                    retained.insert(msg.topic.clone(), msg.message.clone());
+                                                }
                                                 let _ = tx.send(Ok(()));
                                             },
                                             Err(rumqttc::ClientError::Request(_)) | Err(rumqttc::ClientError::TryRequest(_)) => {
@@ -285,6 +302,8 @@ impl<'a> MqttBackend<'a> {
                     let incomming_tx = self.incomming_tx.clone();
                     let cancel = self.cancel.clone();
                     let thread_cancel = loop_cancel.clone();
+                    let retained = self.retained.clone();
+                    let birth_topic = self.config.birth_topic.clone();
                     tokio::task::spawn(async move {
                         tokio::select!{
                             _ = cancel.cancelled() => AnyResult::Ok(()),
@@ -306,6 +325,19 @@ impl<'a> MqttBackend<'a> {
                                             client
                                             .subscribe("neolink/#".to_string(), QoS::AtMostOnce)
                                             .await?;
+                                            if !birth_topic.is_empty() {
+                                                client
+                                                .subscribe(birth_topic, QoS::AtMostOnce)
+                                                .await?;
+                                            }
+                                            // The broker may have lost the retained messages if it restarted
+                                            republish(&client, &retained).await?;
+                                        }
+                                    }
+                                    Event::Incoming(Incoming::Publish(published_message)) if !birth_topic.is_empty() && published_message.topic == birth_topic => {
+                                        if published_message.payload.as_ref() == b"online" {
+                                            log::debug!("MQTT Birth message on {}", birth_topic);
+                                            republish(&client, &retained).await?;
                                         }
                                     }
                                     Event::Incoming(Incoming::Publish(published_message)) => {
@@ -339,6 +371,23 @@ impl<'a> MqttBackend<'a> {
     }
 }
 
+/// Publish the retained messages again
+async fn republish(client: &AsyncClient, retained: &RetainedCache) -> AnyResult<()> {
+    let messages = retained
+        .lock()
+        .await
+        .iter()
+        .map(|(topic, message)| (topic.clone(), message.clone()))
+        .collect::<Vec<_>>();
+    log::debug!("MQTT Republishing {} retained messages", messages.len());
+    for (topic, message) in messages {
+        client
+            .publish(topic, QoS::AtLeastOnce, true, (*message).clone())
+            .await?;
+    }
+    Ok(())
+}
+
 impl<'a> Drop for MqttBackend<'a> {
     fn drop(&mut self) {
         self.cancel.cancel();
@@ -0,0 +1,130 @@
+//! Dry run replay of scripted camera events
+//!
+//! In dry run mode no camera is contacted. Instead a script of
+//! events is published to the broker under each enabled camera's
+//! topics so that automations can be built and tested before
+//! the hardware is mounted.
+//!
+//! A script is a toml file of events, each event is published to
+//! `neolink/{CAMERANAME}/{topic}` at `at` seconds after the start
+//!
+//! ```toml
+//! [[events]]
+//! at = 5
+//! topic = "status/motion"
+//! message = "on"
+//!
+//! [[events]]
+//! at = 10
+//! topic = "status/motion"
+//! message = "off"
+//! ```
+//!
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use std::{fs, path::Path};
+use tokio::time::{sleep_until, Duration, Instant};
+
+use super::mqttc::MqttInstance;
+
+#[derive(Debug, Deserialize, Clone, PartialEq, Eq)]
+pub(crate) struct ReplayEvent {
+    /// Seconds after the start of the script to send this event
+    pub(crate) at: u64,
+    /// The topic relative to `neolink/{CAMERANAME}`
+    pub(crate) topic: String,
+    pub(crate) message: String,
+    #[serde(default = "default_retain")]
+    pub(crate) retain: bool,
+}
+
+fn default_retain() -> bool {
+    true
+}
+
+#[derive(Debug, Deserialize, Clone, PartialEq, Eq)]
+pub(crate) struct ReplayScript {
+    #[serde(default)]
+    pub(crate) events: Vec<ReplayEvent>,
+}
+
+impl ReplayScript {
+    /// Load a script from a toml file
+    pub(crate) fn load(path: &Path) -> Result<Self> {
+        let mut script: Self = toml::from_str(
+            &fs::read_to_string(path)
+                .with_context(|| format!("Failed to read {:?}", path))?,
+        )
+        .with_context(|| format!("Failed to parse the {:?} replay script", path))?;
+        script.events.sort_by_key(|event| event.at);
+        Ok(script)
+    }
+
+    fn event<T: Into<String>, U: Into<String>>(at: u64, topic: T, message: U) -> ReplayEvent {
+        ReplayEvent {
+            at,
+            topic: topic.into(),
+            message: message.into(),
+            retain: true,
+        }
+    }
+
+    /// The built in script: motion at t+5s and a visitor at t+20s
+    pub(crate) fn canned() -> Self {
+        Self {
+            events: vec![
+                Self::event(5, "status/motion", "on"),
+                Self::event(15, "status/motion", "off"),
+                Self::event(20, "status/motion", "on"),
+                Self::event(
+                    20,
+                    "status/notification",
+                    r#"{"alarm":{"type":"visitor","channel":0}}"#,
+                ),
+                Self::event(30, "status/motion", "off"),
+            ],
+        }
+    }
+
+    /// Length of the script, used as the period when looping
+    fn duration(&self) -> Duration {
+        // Leave a gap after the last event so a loop does not
+        // immediately re-trigger the first one
+        Duration::from_secs(self.events.iter().map(|e| e.at).max().unwrap_or(0) + 5)
+    }
+}
+
+/// Replay the script for one camera
+pub(crate) async fn replay(script: &ReplayScript, mqtt: &MqttInstance, repeat: bool) -> Result<()> {
+    let camera_name = mqtt.get_name().to_string();
+    mqtt.send_message("status", "connected", true)
+        .await
+        .with_context(|| format!("{}: Failed to publish connected", camera_name))?;
+    mqtt.send_message("status/motion", "off", true)
+        .await
+        .with_context(|| format!("{}: Failed to publish motion off", camera_name))?;
+
+    loop {
+        let start = Instant::now();
+        for event in script.events.iter() {
+            sleep_until(start + Duration::from_secs(event.at)).await;
+            log::info!(
+                "{}: Dry run publishing {} {}",
+                camera_name,
+                event.topic,
+                event.message
+            );
+            mqtt.send_message(&event.topic, &event.message, event.retain)
+                .await
+                .with_context(|| {
+                    format!("{}: Failed to publish {}", camera_name, event.topic)
+                })?;
+        }
+        if !repeat {
+            break;
+        }
+        sleep_until(start + script.duration()).await;
+    }
+    Ok(())
+}
+
@@ -0,0 +1,10 @@
+use clap::Parser;
+
+/// The name command will get or set the device name of the camera
+#[derive(Parser, Debug)]
+pub struct Opt {
+    /// The name of the camera. Must be a name in the config
+    pub camera: String,
+    /// The new device name. If not given the current device name is printed
+    pub name: Option<String>,
+}
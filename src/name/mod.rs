@@ -0,0 +1,58 @@
+///
+/// # Neolink Name
+///
+/// This module handles getting and setting the device name
+/// of the camera. This is the name shown in the Reolink app, the
+/// name drawn on the video is set with `neolink osd`
+///
+///
+/// # Usage
+///
+/// ```bash
+/// # To print the device name
+/// neolink name --config=config.toml CameraName
+/// # To set the device name
+/// neolink name --config=config.toml CameraName "Front Door"
+/// ```
+///
+use anyhow::{Context, Result};
+
+mod cmdline;
+
+use crate::common::{audit, NeoReactor};
+pub(crate) use cmdline::Opt;
+
+/// Entry point for the name subcommand
+///
+/// Opt is the command line options
+pub(crate) async fn main(opt: Opt, reactor: NeoReactor) -> Result<()> {
+    let camera = reactor.get(&opt.camera).await?;
+
+    if let Some(name) = opt.name {
+        let result = camera
+            .run_task(|cam| {
+                let name = name.clone();
+                Box::pin(async move {
+                    cam.set_device_name(&name)
+                        .await
+                        .context("Unable to set camera device name")
+                })
+            })
+            .await;
+        audit::cli(&opt.camera, "name", &name, &result);
+        result?;
+    } else {
+        let name = camera
+            .run_task(|cam| {
+                Box::pin(async move {
+                    cam.get_device_name()
+                        .await
+                        .context("Unable to get camera device name")
+                })
+            })
+            .await?;
+        println!("{}", name);
+    }
+
+    Ok(())
+}
@@ -0,0 +1,7 @@
+use clap::Parser;
+
+/// The onvif command lets NVRs find the rtsp streams of the cameras over ONVIF
+///
+/// It only points at the streams so `neolink rtsp` should be run along side it
+#[derive(Parser, Debug)]
+pub struct Opt {}
@@ -0,0 +1,128 @@
+//! WS-Discovery so that NVRs can find the cameras
+//!
+//! Each enabled camera answers a probe with its own ProbeMatches message
+use anyhow::{Context, Result};
+use log::*;
+use once_cell::sync::Lazy;
+use quick_xml::escape::escape;
+use regex::Regex;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr, UdpSocket as StdUdpSocket};
+use tokio::{net::UdpSocket, sync::watch::Receiver as WatchReceiver};
+use uuid::Uuid;
+
+use super::{device_uuid, scopes, Endpoint};
+use crate::config::Config;
+
+const DISCOVERY_PORT: u16 = 3702;
+const DISCOVERY_GROUP: Ipv4Addr = Ipv4Addr::new(239, 255, 255, 250);
+
+static RE_PROBE: Lazy<Regex> = Lazy::new(|| Regex::new(r"<(?:[\w-]+:)?Probe[\s>/]").unwrap());
+static RE_MESSAGE_ID: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"<(?:[\w-]+:)?MessageID[^>]*>\s*([^<\s]+)\s*<").unwrap());
+static RE_TYPES: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"<(?:[\w-]+:)?Types[^>]*>([^<]*)<").unwrap());
+
+/// Answers WS-Discovery probes until an error occurs
+pub(super) async fn run(config: WatchReceiver<Config>) -> Result<()> {
+    let socket = UdpSocket::bind(("0.0.0.0", DISCOVERY_PORT))
+        .await
+        .with_context(|| format!("Failed to bind the WS-Discovery port {}", DISCOVERY_PORT))?;
+    socket
+        .join_multicast_v4(DISCOVERY_GROUP, Ipv4Addr::UNSPECIFIED)
+        .context("Failed to join the WS-Discovery multicast group")?;
+    info!("ONVIF: Listening for WS-Discovery probes");
+
+    let mut buf = vec![0u8; 65536];
+    loop {
+        let (n, addr) = socket.recv_from(&mut buf).await?;
+        let message = String::from_utf8_lossy(&buf[..n]);
+        if !RE_PROBE.is_match(&message) || !wants_video(&message) {
+            continue;
+        }
+        let Some(message_id) = RE_MESSAGE_ID
+            .captures(&message)
+            .and_then(|caps| caps.get(1))
+            .map(|id| id.as_str().to_string())
+        else {
+            continue;
+        };
+        let local_ip = match local_ip_for(addr) {
+            Ok(ip) => ip,
+            Err(e) => {
+                debug!("ONVIF: No route to the prober {}: {:?}", addr, e);
+                continue;
+            }
+        };
+        trace!("ONVIF: Probe {} from {}", message_id, addr);
+
+        let replies = {
+            let config = config.borrow();
+            config
+                .cameras
+                .iter()
+                .filter(|camera| camera.enabled)
+                .map(|camera| {
+                    let endpoint = Endpoint::new(&config, camera, local_ip);
+                    probe_match(
+                        &message_id,
+                        &device_uuid(camera),
+                        &scopes(camera).join(" "),
+                        &endpoint.device,
+                    )
+                })
+                .collect::<Vec<_>>()
+        };
+        for reply in replies {
+            if let Err(e) = socket.send_to(reply.as_bytes(), addr).await {
+                debug!("ONVIF: Failed to answer the probe from {}: {:?}", addr, e);
+            }
+        }
+    }
+}
+
+/// A probe without types asks for everything, otherwise it must ask for a camera
+fn wants_video(message: &str) -> bool {
+    match RE_TYPES.captures(message).and_then(|caps| caps.get(1)) {
+        Some(types) if !types.as_str().trim().is_empty() => types
+            .as_str()
+            .split_whitespace()
+            .any(|t| t.ends_with(":NetworkVideoTransmitter") || t.ends_with(":Device")),
+        _ => true,
+    }
+}
+
+/// The address of this host on the route towards `addr`
+fn local_ip_for(addr: SocketAddr) -> Result<IpAddr> {
+    let socket = StdUdpSocket::bind(("0.0.0.0", 0))?;
+    socket.connect(addr)?;
+    Ok(socket.local_addr()?.ip())
+}
+
+fn probe_match(relates_to: &str, uuid: &str, scopes: &str, xaddr: &str) -> String {
+    format!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\
+        <s:Envelope xmlns:s=\"http://www.w3.org/2003/05/soap-envelope\" \
+            xmlns:a=\"http://schemas.xmlsoap.org/ws/2004/08/addressing\" \
+            xmlns:d=\"http://schemas.xmlsoap.org/ws/2005/04/discovery\" \
+            xmlns:dn=\"http://www.onvif.org/ver10/network/wsdl\">\
+            <s:Header>\
+                <a:MessageID>urn:uuid:{}</a:MessageID>\
+                <a:RelatesTo>{}</a:RelatesTo>\
+                <a:To>http://schemas.xmlsoap.org/ws/2004/08/addressing/role/anonymous</a:To>\
+                <a:Action>http://schemas.xmlsoap.org/ws/2005/04/discovery/ProbeMatches</a:Action>\
+            </s:Header>\
+            <s:Body><d:ProbeMatches><d:ProbeMatch>\
+                <a:EndpointReference><a:Address>urn:uuid:{}</a:Address></a:EndpointReference>\
+                <d:Types>dn:NetworkVideoTransmitter</d:Types>\
+                <d:Scopes>{}</d:Scopes>\
+                <d:XAddrs>{}</d:XAddrs>\
+                <d:MetadataVersion>1</d:MetadataVersion>\
+            </d:ProbeMatch></d:ProbeMatches></s:Body>\
+        </s:Envelope>",
+        Uuid::new_v4(),
+        escape(relates_to),
+        uuid,
+        escape(scopes),
+        escape(xaddr)
+    )
+}
@@ -0,0 +1,150 @@
+///
+/// # Neolink ONVIF
+///
+/// This module lets NVRs such as Blue Iris or Synology Surveillance Station
+/// find the cameras and add them without typing in their rtsp urls
+///
+/// It answers WS-Discovery probes on the local network and serves a
+/// minimal ONVIF device and media service for each camera. The media
+/// service only points at the rtsp streams of `neolink rtsp` so both
+/// should be run
+///
+/// Each camera is its own ONVIF device at
+///
+/// `http://my.ip.address:8000/onvif/{CameraName}/device_service`
+///
+/// # Usage
+///
+/// ```bash
+/// neolink onvif --config=config.toml
+/// ```
+///
+/// # Example Config
+///
+/// ```toml
+/// [onvif]
+/// port = 8000
+/// address = "192.168.1.2" # Optional, the address NVRs are told to use
+/// ```
+///
+use anyhow::{Context, Result};
+use log::*;
+use std::net::{IpAddr, SocketAddr};
+use tokio::{net::TcpListener, sync::watch::Receiver as WatchReceiver};
+use uuid::Uuid;
+
+mod cmdline;
+mod discovery;
+mod service;
+
+use crate::{
+    common::{
+        httpd::{self, Response},
+        NeoReactor,
+    },
+    config::{CameraConfig, Config},
+};
+pub(crate) use cmdline::Opt;
+
+/// Largest request body that is accepted, ONVIF requests are only a few kB
+const MAX_BODY: usize = 64 * 1024;
+
+/// Entry point for the onvif subcommand
+///
+/// Opt is the command line options
+pub(crate) async fn main(_opt: Opt, reactor: NeoReactor) -> Result<()> {
+    let config = reactor.config().await?;
+    let port = config.borrow().onvif.port;
+    let listener = TcpListener::bind(("0.0.0.0", port))
+        .await
+        .with_context(|| format!("Failed to bind the ONVIF port {}", port))?;
+    info!("ONVIF: Serving on port {}", port);
+
+    tokio::select! {
+        v = discovery::run(config.clone()) => v,
+        v = serve(listener, config) => v,
+    }
+}
+
+async fn serve(listener: TcpListener, config: WatchReceiver<Config>) -> Result<()> {
+    httpd::serve(listener, "ONVIF", MAX_BODY, move |request| {
+        let config = config.borrow().clone();
+        async move {
+            let body = String::from_utf8_lossy(&request.body);
+            let (status, reply) =
+                service::handle(&request.path, &body, &config, request.local_addr.ip());
+            trace!("ONVIF: {} {} -> {}", request.path, body, status);
+            Response::new(
+                status,
+                "application/soap+xml; charset=utf-8",
+                reply.into_bytes(),
+            )
+        }
+    })
+    .await
+}
+
+/// The addresses that a camera is advertised at
+struct Endpoint {
+    /// The ONVIF device service
+    device: String,
+    /// The ONVIF media service
+    media: String,
+    /// `host:port` of the rtsp server
+    rtsp: String,
+}
+
+impl Endpoint {
+    /// `local_ip` is the address of neolink on the network of the NVR
+    fn new(config: &Config, camera: &CameraConfig, local_ip: IpAddr) -> Self {
+        let onvif_host = match config.onvif.address.as_ref() {
+            Some(address) => format!("{}:{}", address, config.onvif.port),
+            None => SocketAddr::new(local_ip, config.onvif.port).to_string(),
+        };
+        let rtsp = match (
+            config.onvif.address.as_ref(),
+            config.bind_addr.parse::<IpAddr>(),
+        ) {
+            (Some(address), _) => format!("{}:{}", address, config.bind_port),
+            (None, Ok(ip)) if !ip.is_unspecified() => {
+                SocketAddr::new(ip, config.bind_port).to_string()
+            }
+            _ => SocketAddr::new(local_ip, config.bind_port).to_string(),
+        };
+        let name = encode_name(&camera.name);
+        Self {
+            device: format!("http://{}/onvif/{}/device_service", onvif_host, name),
+            media: format!("http://{}/onvif/{}/media_service", onvif_host, name),
+            rtsp,
+        }
+    }
+}
+
+/// A UUID that stays the same for the camera so that NVRs recognise it again
+fn device_uuid(camera: &CameraConfig) -> String {
+    Uuid::from_bytes(md5::compute(format!("neolink-onvif-{}", camera.name)).0)
+        .hyphenated()
+        .to_string()
+}
+
+/// The WS-Discovery scopes of the camera
+fn scopes(camera: &CameraConfig) -> Vec<String> {
+    vec![
+        "onvif://www.onvif.org/type/video_encoder".to_string(),
+        "onvif://www.onvif.org/Profile/Streaming".to_string(),
+        "onvif://www.onvif.org/hardware/Neolink".to_string(),
+        format!("onvif://www.onvif.org/name/{}", encode_name(&camera.name)),
+    ]
+}
+
+/// Percent encode a camera name for use in urls and scopes
+fn encode_name(name: &str) -> String {
+    name.bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                (b as char).to_string()
+            }
+            _ => format!("%{:02X}", b),
+        })
+        .collect()
+}
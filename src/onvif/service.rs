@@ -0,0 +1,230 @@
+//! The ONVIF device and media services
+//!
+//! Only what an NVR needs to add a camera is answered: the device
+//! information, the services, the profiles and the rtsp url of each profile
+use neolink_core::bc_protocol::StreamKind;
+use once_cell::sync::Lazy;
+use quick_xml::escape::escape;
+use regex::Regex;
+use std::net::IpAddr;
+use time::OffsetDateTime;
+
+use super::{device_uuid, encode_name, scopes, Endpoint};
+use crate::config::{CameraConfig, Config};
+
+/// The first element of the SOAP body is the action
+static RE_ACTION: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"<(?:[\w-]+:)?Body[^>]*>\s*<(?:[\w-]+:)?(\w+)").unwrap());
+static RE_PROFILE_TOKEN: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"<(?:[\w-]+:)?ProfileToken[^>]*>\s*([^<\s]+)\s*<").unwrap());
+
+/// Reply to the SOAP request with the HTTP status and body
+pub(super) fn handle(
+    path: &str,
+    body: &str,
+    config: &Config,
+    local_ip: IpAddr,
+) -> (&'static str, String) {
+    let Some(camera) = camera_for_path(path, config) else {
+        return (
+            "404 Not Found",
+            fault("ter:InvalidArgVal", "No camera at this path"),
+        );
+    };
+    let Some(action) = RE_ACTION.captures(body).and_then(|caps| caps.get(1)) else {
+        return (
+            "400 Bad Request",
+            fault("ter:WellFormed", "Expected a SOAP request"),
+        );
+    };
+    let endpoint = Endpoint::new(config, camera, local_ip);
+    let token = RE_PROFILE_TOKEN
+        .captures(body)
+        .and_then(|caps| caps.get(1))
+        .map(|token| token.as_str());
+
+    let reply = match action.as_str() {
+        "GetSystemDateAndTime" => system_date_and_time(),
+        "GetDeviceInformation" => format!(
+            "<tds:GetDeviceInformationResponse>\
+                <tds:Manufacturer>Neolink</tds:Manufacturer>\
+                <tds:Model>{}</tds:Model>\
+                <tds:FirmwareVersion>{}</tds:FirmwareVersion>\
+                <tds:SerialNumber>{}</tds:SerialNumber>\
+                <tds:HardwareId>neolink</tds:HardwareId>\
+            </tds:GetDeviceInformationResponse>",
+            escape(&camera.name),
+            env!("NEOLINK_VERSION"),
+            device_uuid(camera),
+        ),
+        "GetCapabilities" => format!(
+            "<tds:GetCapabilitiesResponse><tds:Capabilities>\
+                <tt:Device><tt:XAddr>{}</tt:XAddr></tt:Device>\
+                <tt:Media><tt:XAddr>{}</tt:XAddr><tt:StreamingCapabilities>\
+                    <tt:RTPMulticast>false</tt:RTPMulticast>\
+                    <tt:RTP_TCP>true</tt:RTP_TCP>\
+                    <tt:RTP_RTSP_TCP>true</tt:RTP_RTSP_TCP>\
+                </tt:StreamingCapabilities></tt:Media>\
+            </tds:Capabilities></tds:GetCapabilitiesResponse>",
+            escape(&endpoint.device),
+            escape(&endpoint.media),
+        ),
+        "GetServices" => format!(
+            "<tds:GetServicesResponse>{}{}</tds:GetServicesResponse>",
+            service("http://www.onvif.org/ver10/device/wsdl", &endpoint.device),
+            service("http://www.onvif.org/ver10/media/wsdl", &endpoint.media),
+        ),
+        "GetScopes" => format!(
+            "<tds:GetScopesResponse>{}</tds:GetScopesResponse>",
+            scopes(camera)
+                .iter()
+                .map(|scope| format!(
+                    "<tds:Scopes><tt:ScopeDef>Fixed</tt:ScopeDef><tt:ScopeItem>{}</tt:ScopeItem></tds:Scopes>",
+                    escape(scope)
+                ))
+                .collect::<String>()
+        ),
+        "GetProfiles" => format!(
+            "<trt:GetProfilesResponse>{}</trt:GetProfilesResponse>",
+            camera
+                .stream
+                .as_stream_kinds()
+                .iter()
+                .map(|kind| profile("trt:Profiles", *kind))
+                .collect::<String>()
+        ),
+        "GetProfile" => match profile_kind(camera, token) {
+            Some(kind) => format!(
+                "<trt:GetProfileResponse>{}</trt:GetProfileResponse>",
+                profile("trt:Profile", kind)
+            ),
+            None => return ("400 Bad Request", no_profile()),
+        },
+        "GetStreamUri" => match profile_kind(camera, token) {
+            Some(kind) => format!(
+                "<trt:GetStreamUriResponse><trt:MediaUri>\
+                    <tt:Uri>{}</tt:Uri>\
+                    <tt:InvalidAfterConnect>false</tt:InvalidAfterConnect>\
+                    <tt:InvalidAfterReboot>false</tt:InvalidAfterReboot>\
+                    <tt:Timeout>PT0S</tt:Timeout>\
+                </trt:MediaUri></trt:GetStreamUriResponse>",
+                escape(&format!(
+                    "rtsp://{}/{}/{}",
+                    endpoint.rtsp,
+                    encode_name(&camera.name),
+                    profile_token(kind)
+                )),
+            ),
+            None => return ("400 Bad Request", no_profile()),
+        },
+        other => {
+            log::debug!("ONVIF: Unsupported action {}", other);
+            return (
+                "400 Bad Request",
+                fault("ter:ActionNotSupported", "Action not supported by neolink"),
+            );
+        }
+    };
+    ("200 OK", envelope(&reply))
+}
+
+/// The camera is given by the path `/onvif/{CameraName}/{service}`
+///
+/// Some NVRs always use `/onvif/device_service`, which works when there is only one camera
+fn camera_for_path<'a>(path: &str, config: &'a Config) -> Option<&'a CameraConfig> {
+    let mut cameras = config.cameras.iter().filter(|camera| camera.enabled);
+    let rest = path.strip_prefix("/onvif/")?;
+    match rest.split_once('/') {
+        Some((name, _)) => cameras.find(|camera| encode_name(&camera.name) == name),
+        None => {
+            let camera = cameras.next();
+            cameras.next().is_none().then_some(camera).flatten()
+        }
+    }
+}
+
+fn profile_token(kind: StreamKind) -> &'static str {
+    match kind {
+        StreamKind::Main => "main",
+        StreamKind::Sub => "sub",
+        StreamKind::Extern => "extern",
+    }
+}
+
+/// The stream of the requested profile if the camera serves it
+fn profile_kind(camera: &CameraConfig, token: Option<&str>) -> Option<StreamKind> {
+    camera
+        .stream
+        .as_stream_kinds()
+        .into_iter()
+        .find(|kind| Some(profile_token(*kind)) == token)
+}
+
+fn profile(tag: &str, kind: StreamKind) -> String {
+    format!(
+        "<{tag} token=\"{token}\" fixed=\"true\"><tt:Name>{token}Stream</tt:Name></{tag}>",
+        tag = tag,
+        token = profile_token(kind)
+    )
+}
+
+fn service(namespace: &str, xaddr: &str) -> String {
+    format!(
+        "<tds:Service>\
+            <tds:Namespace>{}</tds:Namespace>\
+            <tds:XAddr>{}</tds:XAddr>\
+            <tds:Version><tt:Major>2</tt:Major><tt:Minor>0</tt:Minor></tds:Version>\
+        </tds:Service>",
+        namespace,
+        escape(xaddr)
+    )
+}
+
+fn system_date_and_time() -> String {
+    let now = OffsetDateTime::now_utc();
+    format!(
+        "<tds:GetSystemDateAndTimeResponse><tds:SystemDateAndTime>\
+            <tt:DateTimeType>Manual</tt:DateTimeType>\
+            <tt:DaylightSavings>false</tt:DaylightSavings>\
+            <tt:TimeZone><tt:TZ>UTC0</tt:TZ></tt:TimeZone>\
+            <tt:UTCDateTime>\
+                <tt:Time><tt:Hour>{}</tt:Hour><tt:Minute>{}</tt:Minute><tt:Second>{}</tt:Second></tt:Time>\
+                <tt:Date><tt:Year>{}</tt:Year><tt:Month>{}</tt:Month><tt:Day>{}</tt:Day></tt:Date>\
+            </tt:UTCDateTime>\
+        </tds:SystemDateAndTime></tds:GetSystemDateAndTimeResponse>",
+        now.hour(),
+        now.minute(),
+        now.second(),
+        now.year(),
+        u8::from(now.month()),
+        now.day()
+    )
+}
+
+fn no_profile() -> String {
+    fault("ter:NoProfile", "No profile with this token")
+}
+
+fn fault(subcode: &str, reason: &str) -> String {
+    envelope(&format!(
+        "<s:Fault>\
+            <s:Code><s:Value>s:Sender</s:Value><s:Subcode><s:Value>{}</s:Value></s:Subcode></s:Code>\
+            <s:Reason><s:Text xml:lang=\"en\">{}</s:Text></s:Reason>\
+        </s:Fault>",
+        subcode, reason
+    ))
+}
+
+fn envelope(body: &str) -> String {
+    format!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\
+        <s:Envelope xmlns:s=\"http://www.w3.org/2003/05/soap-envelope\" \
+            xmlns:tds=\"http://www.onvif.org/ver10/device/wsdl\" \
+            xmlns:trt=\"http://www.onvif.org/ver10/media/wsdl\" \
+            xmlns:tt=\"http://www.onvif.org/ver10/schema\" \
+            xmlns:ter=\"http://www.onvif.org/ver10/error\">\
+            <s:Body>{}</s:Body>\
+        </s:Envelope>",
+        body
+    )
+}
@@ -0,0 +1,56 @@
+use anyhow::{anyhow, Result};
+use clap::Parser;
+
+fn onoff_parse(src: &str) -> Result<bool> {
+    match src {
+        "true" | "on" | "yes" => Ok(true),
+        "false" | "off" | "no" => Ok(false),
+        _ => Err(anyhow!(
+            "Could not understand {}, check your input, should be true/false, on/off or yes/no",
+            src
+        )),
+    }
+}
+
+fn position_parse(src: &str) -> Result<(u32, u32)> {
+    src.split_once(',')
+        .and_then(|(x, y)| Some((x.trim().parse().ok()?, y.trim().parse().ok()?)))
+        .ok_or_else(|| anyhow!("Could not understand {}, should be x,y", src))
+}
+
+/// The osd command will get or set the on screen display of the camera
+#[derive(Parser, Debug)]
+pub struct Opt {
+    /// The name of the camera. Must be a name in the config
+    pub camera: String,
+    /// The name shown on the OSD, this is also the device name
+    #[arg(long)]
+    pub name: Option<String>,
+    /// Whether to show the name
+    #[arg(long, value_parser = onoff_parse, value_name = "on|off")]
+    pub show_name: Option<bool>,
+    /// The position of the name as `x,y` as printed for the camera
+    #[arg(long, value_parser = position_parse, value_name = "x,y")]
+    pub name_position: Option<(u32, u32)>,
+    /// Whether to show the date and time
+    #[arg(long, value_parser = onoff_parse, value_name = "on|off")]
+    pub show_date: Option<bool>,
+    /// The position of the date and time as `x,y` as printed for the camera
+    #[arg(long, value_parser = position_parse, value_name = "x,y")]
+    pub date_position: Option<(u32, u32)>,
+    /// Whether to show the Reolink watermark
+    #[arg(long, value_parser = onoff_parse, value_name = "on|off")]
+    pub watermark: Option<bool>,
+}
+
+impl Opt {
+    /// True if nothing is to be changed
+    pub(crate) fn is_query(&self) -> bool {
+        self.name.is_none()
+            && self.show_name.is_none()
+            && self.name_position.is_none()
+            && self.show_date.is_none()
+            && self.date_position.is_none()
+            && self.watermark.is_none()
+    }
+}
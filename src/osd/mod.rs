@@ -0,0 +1,136 @@
+///
+/// # Neolink OSD
+///
+/// This module handles getting and setting the on screen display of the
+/// camera: the name and date that are drawn onto the video and the Reolink
+/// watermark
+///
+///
+/// # Usage
+///
+/// ```bash
+/// # To print the OSD
+/// neolink osd --config=config.toml CameraName
+/// # To set the name and hide the watermark
+/// neolink osd --config=config.toml CameraName --name "Front Door" --watermark off
+/// # To move the date and time
+/// neolink osd --config=config.toml CameraName --show-date on --date-position 10,10
+/// ```
+///
+/// The positions are in the camera's own units, print the OSD first to see
+/// the values that the camera uses
+///
+use anyhow::{Context, Result};
+use neolink_core::bc::xml::{OsdChannelName, OsdDatetime};
+
+mod cmdline;
+
+use crate::common::{audit, NeoReactor};
+pub(crate) use cmdline::Opt;
+
+/// Entry point for the osd subcommand
+///
+/// Opt is the command line options
+pub(crate) async fn main(opt: Opt, reactor: NeoReactor) -> Result<()> {
+    let camera = reactor.get(&opt.camera).await?;
+
+    let (mut osd_name, mut osd_date) = camera
+        .run_task(|cam| {
+            Box::pin(async move {
+                cam.get_osd()
+                    .await
+                    .context("Unable to get the camera's OSD")
+            })
+        })
+        .await?;
+
+    if opt.is_query() {
+        print_osd(&osd_name, osd_date.as_ref());
+        return Ok(());
+    }
+
+    let mut args = vec![];
+    if let Some(name) = opt.name.as_ref() {
+        osd_name.name = name.clone();
+        args.push(format!("name {:?}", name));
+    }
+    if let Some(show) = opt.show_name {
+        osd_name.enable = show as u8;
+        args.push(format!("show-name {}", onoff(show)));
+    }
+    if let Some((x, y)) = opt.name_position {
+        osd_name.top_left_x = Some(x);
+        osd_name.top_left_y = Some(y);
+        args.push(format!("name-position {},{}", x, y));
+    }
+    if let Some(show) = opt.watermark {
+        osd_name.en_watermark = Some(show as u8);
+        args.push(format!("watermark {}", onoff(show)));
+    }
+    if opt.show_date.is_some() || opt.date_position.is_some() {
+        let date = osd_date.get_or_insert_with(|| OsdDatetime {
+            version: osd_name.version.clone(),
+            channel_id: osd_name.channel_id,
+            ..Default::default()
+        });
+        if let Some(show) = opt.show_date {
+            date.enable = show as u8;
+            args.push(format!("show-date {}", onoff(show)));
+        }
+        if let Some((x, y)) = opt.date_position {
+            date.top_left_x = Some(x);
+            date.top_left_y = Some(y);
+            args.push(format!("date-position {},{}", x, y));
+        }
+    }
+
+    let result = camera
+        .run_task(|cam| {
+            let osd_name = osd_name.clone();
+            let osd_date = osd_date.clone();
+            Box::pin(async move {
+                cam.set_osd(osd_name, osd_date)
+                    .await
+                    .context("Unable to set the camera's OSD")
+            })
+        })
+        .await;
+    audit::cli(&opt.camera, "osd", &args.join(" "), &result);
+    result?;
+
+    Ok(())
+}
+
+fn onoff(on: bool) -> &'static str {
+    if on {
+        "on"
+    } else {
+        "off"
+    }
+}
+
+fn position(x: Option<u32>, y: Option<u32>) -> String {
+    match (x, y) {
+        (Some(x), Some(y)) => format!("{},{}", x, y),
+        _ => "unknown".to_string(),
+    }
+}
+
+fn print_osd(osd_name: &OsdChannelName, osd_date: Option<&OsdDatetime>) {
+    println!("Name: {}", osd_name.name);
+    println!("Show name: {}", onoff(osd_name.enable == 1));
+    println!(
+        "Name position: {}",
+        position(osd_name.top_left_x, osd_name.top_left_y)
+    );
+    if let Some(watermark) = osd_name.en_watermark {
+        println!("Watermark: {}", onoff(watermark == 1));
+    }
+    if let Some(osd_date) = osd_date {
+        println!("Show date: {}", onoff(osd_date.enable == 1));
+        println!(
+            "Date position: {}",
+            position(osd_date.top_left_x, osd_date.top_left_y)
+        );
+    }
+}
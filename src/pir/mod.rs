@@ -17,7 +17,7 @@ use anyhow::{Context, Result};
 
 mod cmdline;
 
-use crate::common::NeoReactor;
+use crate::common::{audit, NeoReactor};
 pub(crate) use cmdline::Opt;
 
 /// Entry point for the pir subcommand
@@ -27,7 +27,7 @@ pub(crate) async fn main(opt: Opt, reactor: NeoReactor) -> Result<()> {
     let camera = reactor.get(&opt.camera).await?;
 
     if let Some(on) = opt.on {
-        camera
+        let result = camera
             .run_task(|cam| {
                 Box::pin(async move {
                     cam.pir_set(on)
@@ -35,7 +35,9 @@ pub(crate) async fn main(opt: Opt, reactor: NeoReactor) -> Result<()> {
                         .context("Unable to set camera PIR state")
                 })
             })
-            .await?;
+            .await;
+        audit::cli(&opt.camera, "pir", if on { "on" } else { "off" }, &result);
+        result?;
     } else {
         let pir_state = camera
             .run_task(|cam| {
@@ -0,0 +1,27 @@
+use clap::Parser;
+
+/// The privacy-mask command will get or set the areas that the camera masks itself
+#[derive(Parser, Debug)]
+pub struct Opt {
+    /// The name of the camera. Must be a name in the config
+    pub camera: String,
+    /// If not given the current mask is printed
+    #[command(subcommand)]
+    pub cmd: Option<PrivacyMaskCommand>,
+}
+
+#[derive(Parser, Debug)]
+pub enum PrivacyMaskCommand {
+    /// Replace the masked areas and turn the mask on
+    Set {
+        /// Each area as `"x y width height"` in fractions of the frame
+        #[arg(required = true)]
+        areas: Vec<String>,
+    },
+    /// Remove all masked areas
+    Clear,
+    /// Turn the mask on without changing the areas
+    On,
+    /// Turn the mask off without changing the areas
+    Off,
+}
@@ -0,0 +1,162 @@
+///
+/// # Neolink Privacy Mask
+///
+/// This module handles getting and setting the privacy mask of the camera.
+/// These areas are blacked out by the camera itself so they are hidden from
+/// every client, snapshot and recording
+///
+///
+/// # Usage
+///
+/// ```bash
+/// # To print the masked areas
+/// neolink privacy-mask --config=config.toml CameraName
+/// # To mask two areas given as "x y width height" fractions of the frame
+/// neolink privacy-mask --config=config.toml CameraName set "0.7 0 0.3 0.25" "0 0.8 0.2 0.2"
+/// # To remove all areas
+/// neolink privacy-mask --config=config.toml CameraName clear
+/// # To turn the mask on or off without changing the areas
+/// neolink privacy-mask --config=config.toml CameraName [on|off]
+/// ```
+///
+use anyhow::{anyhow, Context, Result};
+use neolink_core::bc::xml::{Shelter, ShelterInfo};
+
+mod cmdline;
+
+use crate::{
+    common::{audit, NeoReactor},
+    config::ZoomRect,
+};
+pub(crate) use cmdline::Opt;
+use cmdline::PrivacyMaskCommand;
+
+/// The coordinate space of the areas when the camera does not report one
+const DEFAULT_SPACE: (u32, u32) = (704, 480);
+
+/// Entry point for the privacy-mask subcommand
+///
+/// Opt is the command line options
+pub(crate) async fn main(opt: Opt, reactor: NeoReactor) -> Result<()> {
+    let camera = reactor.get(&opt.camera).await?;
+
+    let shelter = camera
+        .run_task(|cam| {
+            Box::pin(async move {
+                cam.get_shelter()
+                    .await
+                    .context("Unable to get the camera's privacy mask")
+            })
+        })
+        .await?;
+
+    let args = match &opt.cmd {
+        None => String::new(),
+        Some(PrivacyMaskCommand::Set { areas }) => format!("set {}", areas.join(", ")),
+        Some(PrivacyMaskCommand::Clear) => "clear".to_string(),
+        Some(PrivacyMaskCommand::On) => "on".to_string(),
+        Some(PrivacyMaskCommand::Off) => "off".to_string(),
+    };
+    let shelter = match opt.cmd {
+        None => {
+            print_shelter(&shelter);
+            return Ok(());
+        }
+        Some(PrivacyMaskCommand::Set { areas }) => {
+            let rects = areas
+                .iter()
+                .map(|area| {
+                    ZoomRect::parse(area).ok_or_else(|| {
+                        anyhow!(
+                            "{:?} is not an area inside the frame as \"x y width height\"",
+                            area
+                        )
+                    })
+                })
+                .collect::<Result<Vec<_>>>()?;
+            with_areas(shelter, &rects)
+        }
+        Some(PrivacyMaskCommand::Clear) => with_areas(shelter, &[]),
+        Some(PrivacyMaskCommand::On) => Shelter {
+            enable: 1,
+            ..shelter
+        },
+        Some(PrivacyMaskCommand::Off) => Shelter {
+            enable: 0,
+            ..shelter
+        },
+    };
+
+    let result = camera
+        .run_task(|cam| {
+            let shelter = shelter.clone();
+            Box::pin(async move {
+                cam.set_shelter(shelter)
+                    .await
+                    .context("Unable to set the camera's privacy mask")
+            })
+        })
+        .await;
+    audit::cli(&opt.camera, "privacymask", &args, &result);
+    result?;
+
+    Ok(())
+}
+
+fn space(shelter: &Shelter) -> (f64, f64) {
+    let (width, height) = match (shelter.width, shelter.height) {
+        (Some(width), Some(height)) if width > 0 && height > 0 => (width, height),
+        _ => DEFAULT_SPACE,
+    };
+    (width as f64, height as f64)
+}
+
+fn print_shelter(shelter: &Shelter) {
+    println!(
+        "Privacy mask: {}",
+        if shelter.enable == 1 { "on" } else { "off" }
+    );
+    let (width, height) = space(shelter);
+    for info in shelter
+        .shelter_list
+        .shelter_info
+        .iter()
+        .filter(|info| info.enable == 1)
+    {
+        println!(
+            "{:.3} {:.3} {:.3} {:.3}",
+            info.x as f64 / width,
+            info.y as f64 / height,
+            info.width as f64 / width,
+            info.height as f64 / height
+        );
+    }
+}
+
+/// Replace the areas of the mask
+///
+/// Cameras have a fixed number of areas so unused ones are kept but disabled
+fn with_areas(mut shelter: Shelter, rects: &[ZoomRect]) -> Shelter {
+    let (width, height) = space(&shelter);
+    let count = shelter.shelter_list.shelter_info.len().max(rects.len());
+    shelter.shelter_list.shelter_info = (0..count)
+        .map(|i| match rects.get(i) {
+            Some(rect) => ShelterInfo {
+                id: i as u8,
+                enable: 1,
+                x: (rect.x * width) as u32,
+                y: (rect.y * height) as u32,
+                width: (rect.width * width) as u32,
+                height: (rect.height * height) as u32,
+            },
+            None => ShelterInfo {
+                id: i as u8,
+                ..Default::default()
+            },
+        })
+        .collect();
+    if !rects.is_empty() {
+        shelter.enable = 1;
+    }
+    shelter
+}
@@ -1,12 +1,20 @@
 use clap::Parser;
 
-#[derive(clap::ValueEnum, Clone, Debug)]
+#[derive(clap::ValueEnum, Clone, Copy, Debug)]
 pub enum CmdDirection {
     Left,
     Right,
     Up,
     Down,
     Stop,
+    /// Zoom the lens in
+    In,
+    /// Zoom the lens out
+    Out,
+    /// Focus the lens nearer
+    Near,
+    /// Focus the lens further away
+    Far,
 }
 
 /// The ptz command will control the positioning of the camera
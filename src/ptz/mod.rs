@@ -16,6 +16,9 @@
 /// neolink ptz --config=config.toml CameraName preset 0
 /// # Save the current position as preset ID 0 with name PresetName
 /// neolink ptz --config=config.toml CameraName assign 0 PresetName
+/// # Zoom the lens in by 32 or focus it further away by 16
+/// neolink ptz --config=config.toml CameraName control 32 in
+/// neolink ptz --config=config.toml CameraName control 16 far
 /// ```
 ///
 use anyhow::{Context, Result};
@@ -23,11 +26,11 @@ use tokio::time::{sleep, Duration};
 
 mod cmdline;
 
-use crate::common::NeoReactor;
-use crate::ptz::cmdline::CmdDirection;
+use crate::common::{audit, NeoInstance, NeoReactor};
+use crate::AnyResult;
 use crate::ptz::cmdline::PtzCommand;
-pub(crate) use cmdline::Opt;
-use neolink_core::bc_protocol::Direction;
+pub(crate) use cmdline::{CmdDirection, Opt};
+use neolink_core::bc_protocol::{BcCamera, Direction, FocusDirection, ZoomDirection};
 
 /// Entry point for the ptz subcommand
 ///
@@ -35,7 +38,39 @@ use neolink_core::bc_protocol::Direction;
 pub(crate) async fn main(opt: Opt, reactor: NeoReactor) -> Result<()> {
     let camera = reactor.get(&opt.camera).await?;
 
-    match opt.cmd {
+    // The moves are written to the audit log, listing the presets is not
+    let audit = match &opt.cmd {
+        PtzCommand::Preset { preset_id: None } => None,
+        PtzCommand::Preset {
+            preset_id: Some(preset_id),
+        } => Some(("ptz/preset", preset_id.to_string())),
+        PtzCommand::Assign { preset_id, name } => {
+            Some(("ptz/assign", format!("{} {}", preset_id, name)))
+        }
+        PtzCommand::Control {
+            amount,
+            command,
+            speed,
+        } => Some((
+            "ptz",
+            format!(
+                "{} {} {}",
+                format!("{:?}", command).to_lowercase(),
+                amount,
+                speed.unwrap_or(32)
+            ),
+        )),
+        PtzCommand::Zoom { amount } => Some(("ptz/zoom", amount.to_string())),
+    };
+    let result = run_ptz(&camera, opt.cmd).await;
+    if let Some((action, args)) = audit {
+        audit::cli(&opt.camera, action, &args, &result);
+    }
+    result
+}
+
+async fn run_ptz(camera: &NeoInstance, cmd: PtzCommand) -> Result<()> {
+    match cmd {
         PtzCommand::Preset { preset_id } => {
             if let Some(preset_id) = preset_id {
                 camera
@@ -85,20 +120,13 @@ pub(crate) async fn main(opt: Opt, reactor: NeoReactor) -> Result<()> {
             command,
             speed,
         } => {
-            let direction = match command {
-                CmdDirection::Left => Direction::Left,
-                CmdDirection::Right => Direction::Right,
-                CmdDirection::Up => Direction::Up,
-                CmdDirection::Down => Direction::Down,
-                CmdDirection::Stop => Direction::Stop,
-            };
             let speed = speed.unwrap_or(32) as f32;
             let seconds = amount as f32 / speed;
             let duration = Duration::from_secs_f32(seconds);
             camera
                 .run_task(|cam| {
                     Box::pin(async move {
-                        cam.send_ptz(direction, speed)
+                        start_move(cam, command, speed)
                             .await
                             .context("Unable to execute PTZ move command")?;
                         Ok(())
@@ -135,3 +163,23 @@ pub(crate) async fn main(opt: Opt, reactor: NeoReactor) -> Result<()> {
 
     Ok(())
 }
+
+/// Start moving in the direction, the move continues until it is sent a stop
+pub(crate) async fn start_move(
+    cam: &BcCamera,
+    command: CmdDirection,
+    speed: f32,
+) -> AnyResult<()> {
+    match command {
+        CmdDirection::Left => cam.send_ptz(Direction::Left, speed).await?,
+        CmdDirection::Right => cam.send_ptz(Direction::Right, speed).await?,
+        CmdDirection::Up => cam.send_ptz(Direction::Up, speed).await?,
+        CmdDirection::Down => cam.send_ptz(Direction::Down, speed).await?,
+        CmdDirection::Stop => cam.send_ptz(Direction::Stop, speed).await?,
+        CmdDirection::In => cam.zoom(ZoomDirection::In, speed).await?,
+        CmdDirection::Out => cam.zoom(ZoomDirection::Out, speed).await?,
+        CmdDirection::Near => cam.focus(FocusDirection::Near, speed).await?,
+        CmdDirection::Far => cam.focus(FocusDirection::Far, speed).await?,
+    }
+    Ok(())
+}
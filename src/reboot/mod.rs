@@ -15,7 +15,7 @@ use anyhow::{Context, Result};
 
 mod cmdline;
 
-use crate::common::NeoReactor;
+use crate::common::{audit, NeoReactor};
 pub(crate) use cmdline::Opt;
 
 /// Entry point for the reboot subcommand
@@ -24,7 +24,7 @@ pub(crate) use cmdline::Opt;
 pub(crate) async fn main(opt: Opt, reactor: NeoReactor) -> Result<()> {
     let camera = reactor.get(&opt.camera).await?;
 
-    camera
+    let result = camera
         .run_task(|camera| {
             Box::pin(async move {
                 camera
@@ -33,7 +33,9 @@ pub(crate) async fn main(opt: Opt, reactor: NeoReactor) -> Result<()> {
                     .context("Could not send reboot command to the camera")
             })
         })
-        .await?;
+        .await;
+    audit::cli(&opt.camera, "reboot", "", &result);
+    result?;
 
     Ok(())
 }
@@ -0,0 +1,12 @@
+use clap::Parser;
+
+/// The record command writes the stream of the cameras to disk
+///
+/// The video is written in segments of `[cameras.record] segment` seconds to
+/// the `path` of the camera's config and old segments are removed after
+/// `retention` hours
+#[derive(Parser, Debug)]
+pub struct Opt {
+    /// The cameras to record, all cameras with a `[cameras.record]` when none are given
+    pub cameras: Vec<String>,
+}
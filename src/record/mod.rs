@@ -0,0 +1,418 @@
+///
+/// # Neolink Record
+///
+/// This module records the stream of each camera to disk in segments of a
+/// fixed length, removing the oldest ones once they are older than the
+/// retention. It replaces running an ffmpeg per camera against the rtsp server
+///
+/// The segments are named `{CameraName}_{YYYYmmdd_HHMMSS}.{mkv|mp4}` in the
+/// camera's `timezone`. Each one starts on a keyframe so it plays on its own
+///
+/// # Usage
+///
+/// ```bash
+/// neolink record --config=config.toml
+/// # Only some cameras
+/// neolink record --config=config.toml FrontDoor Garage
+/// ```
+///
+/// # Example Config
+///
+/// ```toml
+/// [[cameras]]
+/// name = "FrontDoor"
+/// [cameras.record]
+/// path = "/mnt/nas/recordings"
+/// segment = 300
+/// retention = 168
+/// ```
+///
+use anyhow::{anyhow, Context, Result};
+use futures::stream::StreamExt;
+use gstreamer::{
+    parse::launch_full, prelude::*, ClockTime, MessageView, ParseFlags, Pipeline, State,
+};
+use gstreamer_app::AppSrc;
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+use tokio::{
+    task::JoinSet,
+    time::{interval, sleep, timeout, Duration},
+};
+use tokio_stream::wrappers::BroadcastStream;
+
+mod cmdline;
+
+use crate::{
+    common::{AudFormat, NeoInstance, NeoReactor, StampedData, Timezone, VidFormat},
+    config::{RecordConfig, RecordFormat},
+    AnyResult,
+};
+pub(crate) use cmdline::Opt;
+
+/// How often old segments are looked for
+const CLEANUP_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Entry point for the record subcommand
+///
+/// Opt is the command line options
+pub(crate) async fn main(opt: Opt, reactor: NeoReactor) -> Result<()> {
+    let names = if opt.cameras.is_empty() {
+        reactor
+            .config()
+            .await?
+            .borrow()
+            .cameras
+            .iter()
+            .filter(|camera| camera.enabled && camera.record.is_some())
+            .map(|camera| camera.name.clone())
+            .collect::<Vec<_>>()
+    } else {
+        opt.cameras
+    };
+    if names.is_empty() {
+        return Err(anyhow!(
+            "No cameras to record, add a [cameras.record] to the config"
+        ));
+    }
+
+    let mut set = JoinSet::new();
+    for name in names {
+        let camera = reactor.get(&name).await?;
+        set.spawn(async move {
+            record_camera(camera)
+                .await
+                .with_context(|| format!("{}: Recording stopped", name))
+        });
+    }
+    while let Some(res) = set.join_next().await {
+        res??;
+    }
+    Ok(())
+}
+
+/// Record the camera, restarting on errors and when its `record` config changes
+async fn record_camera(camera: NeoInstance) -> AnyResult<()> {
+    let mut config = camera.config().await?;
+    let name = config.borrow().name.clone();
+    if config.borrow().record.is_none() {
+        log::info!("{}: Waiting for a [cameras.record] in the config", name);
+    }
+    loop {
+        let record = config
+            .wait_for(|config| config.record.is_some())
+            .await?
+            .record
+            .clone();
+        let Some(record) = record else {
+            continue;
+        };
+        tokio::select! {
+            r = record_stream(&camera, &name, &record) => {
+                log::warn!("{}: Recording failed, restarting: {:?}", name, r);
+                sleep(Duration::from_secs(1)).await;
+            },
+            v = config.wait_for(|config| config.record.as_ref() != Some(&record)) => {
+                v?;
+                log::info!("{}: Record configuration changed", name);
+            },
+        }
+    }
+}
+
+/// Record until the stream fails or its format changes
+async fn record_stream(camera: &NeoInstance, name: &str, record: &RecordConfig) -> AnyResult<()> {
+    let kind = *record
+        .stream
+        .as_stream_kinds()
+        .first()
+        .ok_or_else(|| anyhow!("No stream to record"))?;
+    fs::create_dir_all(&record.path)
+        .with_context(|| format!("Failed to create {}", record.path.display()))?;
+    let timezone = camera.config().await?.borrow().timezone();
+
+    let mut stream = camera.stream(kind).await?;
+    stream.config.wait_for(|config| config.vid_ready()).await?;
+    // After vid give it 1s to look for audio
+    if let Ok(v) = timeout(
+        Duration::from_secs(1),
+        stream.config.wait_for(|config| config.aud_ready()),
+    )
+    .await
+    {
+        v?;
+    }
+    let stream_config = stream.config.borrow().clone();
+    // Only AAC can be put into the containers as is
+    let audio = record.audio && matches!(stream_config.aud_format, AudFormat::Aac);
+
+    let (pipeline, vidsrc, audsrc) = create_pipeline(
+        stream_config.vid_format,
+        audio,
+        record,
+        name.to_string(),
+        timezone,
+    )?;
+    log::info!(
+        "{}: Recording the {:?} stream to {}",
+        name,
+        kind,
+        record.path.display()
+    );
+    let mut set = JoinSet::<AnyResult<()>>::new();
+    let thread_pipeline = pipeline.clone();
+    set.spawn_blocking(move || start_pipeline(thread_pipeline));
+
+    let mut vid = BroadcastStream::new(stream.vid.resubscribe());
+    let mut aud = BroadcastStream::new(stream.aud.resubscribe());
+    let res = tokio::select! {
+        v = async {
+            // The first segment must start on a keyframe
+            let mut started = false;
+            loop {
+                tokio::select! {
+                    data = vid.next() => match data {
+                        Some(Ok(StampedData { data, keyframe, .. })) => {
+                            started |= keyframe;
+                            if started {
+                                push(&vidsrc, &data)?;
+                            }
+                        }
+                        // Lagged, the muxer copes with a gap
+                        Some(Err(_)) => {}
+                        None => return Err(anyhow!("The stream ended")),
+                    },
+                    data = aud.next(), if audsrc.is_some() => match (data, audsrc.as_ref()) {
+                        (Some(Ok(StampedData { data, .. })), Some(audsrc)) if started => {
+                            push(audsrc, &data)?;
+                        }
+                        (None, _) => return Err(anyhow!("The audio ended")),
+                        _ => {}
+                    },
+                }
+            }
+        } => v,
+        v = stream.config.wait_for(|config| config != &stream_config) => {
+            v?;
+            log::debug!("{}: The stream format changed", name);
+            Ok(())
+        },
+        v = remove_old(record, name) => v,
+        v = set.join_next() => v
+            .unwrap_or(Ok(Ok(())))
+            .map_err(anyhow::Error::from)
+            .and_then(|v| v)
+            .and_then(|_| Err(anyhow!("The recorder stopped"))),
+    };
+
+    // Let the muxer finish the last segment so it can be played
+    let _ = vidsrc.end_of_stream();
+    if let Some(audsrc) = audsrc.as_ref() {
+        let _ = audsrc.end_of_stream();
+    }
+    if timeout(Duration::from_secs(5), async {
+        while set.join_next().await.is_some() {}
+    })
+    .await
+    .is_err()
+    {
+        log::debug!("{}: The last segment was not finished in time", name);
+    }
+    let _ = pipeline.set_state(State::Null);
+    while set.join_next().await.is_some() {}
+    res
+}
+
+fn push(source: &AppSrc, data: &[u8]) -> AnyResult<()> {
+    let mut buf = gstreamer::Buffer::with_size(data.len())?;
+    buf.get_mut()
+        .ok_or_else(|| anyhow!("New buffer should be writable"))?
+        .copy_from_slice(0, data)
+        .map_err(|_| anyhow!("Failed to copy the frame"))?;
+    source
+        .push_buffer(buf)
+        .map_err(|e| anyhow!("Streamer Error: {e:?}"))?;
+    Ok(())
+}
+
+/// Delete the camera's segments that are older than the retention
+async fn remove_old(record: &RecordConfig, name: &str) -> AnyResult<()> {
+    if record.retention == 0 {
+        futures::future::pending::<()>().await;
+    }
+    let max_age = Duration::from_secs(record.retention * 60 * 60);
+    let mut ticker = interval(CLEANUP_INTERVAL);
+    loop {
+        ticker.tick().await;
+        let dir = record.path.clone();
+        let prefix = format!("{}_", name);
+        let removed = tokio::task::spawn_blocking(move || old_segments(&dir, &prefix, max_age))
+            .await??
+            .into_iter()
+            .filter(|path| match fs::remove_file(path) {
+                Ok(()) => true,
+                Err(e) => {
+                    log::warn!("Failed to remove {}: {:?}", path.display(), e);
+                    false
+                }
+            })
+            .count();
+        if removed > 0 {
+            log::debug!("{}: Removed {} old segments", name, removed);
+        }
+    }
+}
+
+/// The segments in `dir` starting with `prefix` that were last written before `max_age` ago
+fn old_segments(dir: &Path, prefix: &str, max_age: Duration) -> AnyResult<Vec<PathBuf>> {
+    let now = SystemTime::now();
+    let mut old = vec![];
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let path = entry.path();
+        let is_segment = entry
+            .file_name()
+            .to_str()
+            .is_some_and(|file| file.starts_with(prefix))
+            && path.extension().is_some_and(|ext| {
+                ext == RecordFormat::Mkv.extension() || ext == RecordFormat::Mp4.extension()
+            });
+        if !is_segment {
+            continue;
+        }
+        let age = entry
+            .metadata()?
+            .modified()
+            .ok()
+            .and_then(|modified| now.duration_since(modified).ok());
+        if age.is_some_and(|age| age > max_age) {
+            old.push(path);
+        }
+    }
+    Ok(old)
+}
+
+/// The file of a segment that is started now
+fn segment_path(dir: &Path, name: &str, format: RecordFormat, timezone: Timezone) -> PathBuf {
+    let now = timezone.now();
+    dir.join(format!(
+        "{}_{:04}{:02}{:02}_{:02}{:02}{:02}.{}",
+        name.replace(['/', '\\'], "_"),
+        now.year(),
+        u8::from(now.month()),
+        now.day(),
+        now.hour(),
+        now.minute(),
+        now.second(),
+        format.extension()
+    ))
+}
+
+fn start_pipeline(pipeline: Pipeline) -> AnyResult<()> {
+    pipeline.set_state(State::Playing)?;
+
+    let bus = pipeline
+        .bus()
+        .expect("Pipeline without bus. Shouldn't happen!");
+
+    loop {
+        match bus.timed_pop(ClockTime::from_mseconds(500)) {
+            Some(msg) => match msg.view() {
+                MessageView::Eos(..) => break,
+                MessageView::Error(err) => {
+                    pipeline
+                        .set_state(State::Null)
+                        .context("Error in gstreamer when setting state to Null")?;
+                    return Err(anyhow!("Error from gstreamer in the recorder: {:?}", err));
+                }
+                _ => (),
+            },
+            // Stopped by the recorder
+            None if pipeline.current_state() == State::Null => break,
+            None => (),
+        }
+    }
+
+    pipeline
+        .set_state(State::Null)
+        .context("Error in gstreamer when setting state to Null")?;
+
+    Ok(())
+}
+
+/// Mux the stream into segments with a new file for each
+fn create_pipeline(
+    format: VidFormat,
+    audio: bool,
+    record: &RecordConfig,
+    name: String,
+    timezone: Timezone,
+) -> AnyResult<(Pipeline, AppSrc, Option<AppSrc>)> {
+    gstreamer::init()
+        .context("Unable to start gstreamer ensure it and all plugins are installed")?;
+
+    let parser = match format {
+        VidFormat::H264 => "h264parse",
+        VidFormat::H265 => "h265parse",
+        VidFormat::None => unreachable!(),
+    };
+    let mut launch_str = format!(
+        "splitmuxsink name=mux max-size-time={} muxer-factory={} \
+        appsrc name=vidsrc is-live=true do-timestamp=true format=time \
+        ! {} \
+        ! queue \
+        ! mux.video",
+        record.segment * 1_000_000_000,
+        record.format.muxer(),
+        parser,
+    );
+    if audio {
+        launch_str.push_str(
+            " appsrc name=audsrc is-live=true do-timestamp=true format=time \
+            ! aacparse \
+            ! queue \
+            ! mux.audio_0",
+        );
+    }
+    log::debug!("{}", launch_str);
+
+    let pipeline = launch_full(&launch_str, None, ParseFlags::empty())
+        .context("Unable to load gstreamer pipeline ensure all gstramer plugins are installed")?
+        .dynamic_cast::<Pipeline>()
+        .map_err(|_| {
+            anyhow!("Unable to create gstreamer pipeline ensure all gstramer plugins are installed")
+        })?;
+
+    let mux = pipeline.by_name("mux").expect("There shoud be a `mux`");
+    let dir = record.path.clone();
+    let record_format = record.format;
+    mux.connect("format-location", false, move |_| {
+        let path = segment_path(&dir, &name, record_format, timezone);
+        log::debug!("Recording to {}", path.display());
+        Some(path.to_string_lossy().into_owned().to_value())
+    });
+
+    let vidsrc = pipeline
+        .by_name("vidsrc")
+        .expect("There shoud be a `vidsrc`")
+        .dynamic_cast::<AppSrc>()
+        .map_err(|_| anyhow!("Cannot find appsource in gstreamer, check your gstreamer plugins"))?;
+    let audsrc = if audio {
+        Some(
+            pipeline
+                .by_name("audsrc")
+                .expect("There shoud be an `audsrc`")
+                .dynamic_cast::<AppSrc>()
+                .map_err(|_| {
+                    anyhow!("Cannot find appsource in gstreamer, check your gstreamer plugins")
+                })?,
+        )
+    } else {
+        None
+    };
+
+    Ok((pipeline, vidsrc, audsrc))
+}
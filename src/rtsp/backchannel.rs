@@ -0,0 +1,97 @@
+//! Plays the audio of an ONVIF backchannel on the camera's speaker
+//!
+//! Clients such as Home Assistant that send `Require: www.onvif.org/ver20/backchannel`
+//! get an extra `a=sendonly` PCMU track. Their audio comes out of an appsink in
+//! the media and is encoded for the camera in the same way as `neolink talk`
+
+use anyhow::Context;
+use gstreamer::{prelude::*, FlowError, FlowSuccess, State};
+use gstreamer_app::{AppSink, AppSinkCallbacks};
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+};
+use tokio::time::{interval, Duration};
+
+use crate::{
+    common::NeoInstance,
+    talk::{gst, talk_config},
+    AnyResult,
+};
+
+/// Relay the audio of the `sink` to the camera until the client leaves
+pub(super) async fn talk_backchannel(camera: &NeoInstance, sink: AppSink) -> AnyResult<()> {
+    let name = camera.config().await?.borrow().name.clone();
+    let talk_config = talk_config(camera)
+        .await
+        .with_context(|| format!("Camera {} does not support talk", name))?;
+    let format = (
+        (talk_config.audio_config.length_per_encoder / 2) + 4,
+        talk_config.audio_config.sample_rate,
+    );
+
+    let (mut set, mut rxs, appsrc) = gst::from_appsrc(
+        "appsrc name=thesource is-live=true do-timestamp=true format=time \
+        caps=\"audio/x-raw,format=S16LE,layout=interleaved,rate=8000,channels=1\"",
+        1.0,
+        &[format],
+    )
+    .context("Failed to setup gst for the backchannel")?;
+    let rx = rxs.remove(0);
+
+    // Copied into new buffers so they are stamped by the talk pipeline
+    // rather than with the times of the rtsp client
+    let started = Arc::new(AtomicBool::new(false));
+    let thread_started = started.clone();
+    let thread_appsrc = appsrc.clone();
+    let eos_appsrc = appsrc.clone();
+    sink.set_callbacks(
+        AppSinkCallbacks::builder()
+            .new_sample(move |sink| {
+                let sample = sink.pull_sample().map_err(|_| FlowError::Eos)?;
+                let buffer = sample.buffer().ok_or(FlowError::Error)?;
+                let map = buffer.map_readable().map_err(|_| FlowError::Error)?;
+                let mut buf =
+                    gstreamer::Buffer::with_size(map.len()).map_err(|_| FlowError::Error)?;
+                buf.get_mut()
+                    .ok_or(FlowError::Error)?
+                    .copy_from_slice(0, map.as_slice())
+                    .map_err(|_| FlowError::Error)?;
+                thread_started.store(true, Ordering::Relaxed);
+                thread_appsrc.push_buffer(buf)?;
+                Ok(FlowSuccess::Ok)
+            })
+            .eos(move |_| {
+                let _ = eos_appsrc.end_of_stream();
+            })
+            .build(),
+    );
+    log::info!("{}: Playing the backchannel audio", name);
+
+    let res = tokio::select! {
+        v = camera.run_task(|cam| {
+            let rx = rx.clone();
+            let talk_config = talk_config.clone();
+            Box::pin(async move {
+                cam.talk_stream(rx, talk_config).await?;
+                Ok(())
+            })
+        }) => v.with_context(|| format!("{}: Talk stream ended early", name)),
+        // The media is stopped rather than sent an EOS when the client leaves
+        _ = async {
+            let mut check = interval(Duration::from_secs(1));
+            loop {
+                check.tick().await;
+                if started.load(Ordering::Relaxed) && sink.current_state() == State::Null {
+                    break;
+                }
+            }
+        } => Ok(()),
+    };
+    log::info!("{}: Backchannel audio ended", name);
+
+    let _ = appsrc.end_of_stream();
+    drop(rx);
+    while set.join_next().await.is_some() {}
+    res
+}
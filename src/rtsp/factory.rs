@@ -1,11 +1,19 @@
 use anyhow::{anyhow, Context, Result};
-use gstreamer::{prelude::*, Bin, Caps, Element, ElementFactory, GhostPad};
-use gstreamer_app::{AppSrc, AppSrcCallbacks, AppStreamType};
+use gstreamer::{
+    prelude::*, Bin, Caps, Element, ElementFactory, GhostPad, PadProbeData, PadProbeReturn,
+    PadProbeType,
+};
+use gstreamer_app::{AppSink, AppSrc, AppSrcCallbacks, AppStreamType};
+use std::{ops::Range, path::PathBuf};
 use tokio::sync::mpsc::{channel as mpsc, Receiver as MpscReceiver};
 
 use crate::{
     common::{AudFormat, StreamConfig, VidFormat},
-    rtsp::gst::NeoMediaFactory,
+    config::{
+        AudioMode, CameraConfig, DigitalZoomConfig, LatencyMode, OverlayConfig, PrivacyMaskConfig,
+        PrivacyMaskStyle, ZoomRect,
+    },
+    rtsp::gst::{NeoMediaFactory, BACKCHANNEL_BIN, BACKCHANNEL_SINK},
     AnyResult,
 };
 
@@ -16,51 +24,114 @@ pub(super) struct ClientSourceData {
 pub(super) struct ClientData {
     pub(super) vid: Option<ClientSourceData>,
     pub(super) aud: Option<ClientSourceData>,
+    /// The elements that can be changed while the stream plays, when it is re-encoded
+    pub(super) reencode: Option<ReencodeElements>,
+    /// The audio that the client sends back over the ONVIF backchannel
+    pub(super) backchannel: Option<AppSink>,
+}
+
+/// What is drawn behind the text of the splash and offline slates
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(super) enum SlateSource {
+    /// A `videotestsrc` pattern
+    Pattern(String),
+    /// A still image file
+    Image(PathBuf),
+}
+
+impl SlateSource {
+    /// The fallback image of the camera or its splash pattern if it has none
+    pub(super) fn from_config(config: &CameraConfig) -> Self {
+        match config.fallback_image.as_ref() {
+            Some(image) => SlateSource::Image(image.clone()),
+            None => SlateSource::Pattern(config.splash_pattern.to_string()),
+        }
+    }
 }
 
 pub(super) async fn make_dummy_factory(
     use_splash: bool,
-    pattern: String,
+    source: SlateSource,
 ) -> AnyResult<NeoMediaFactory> {
     NeoMediaFactory::new_with_callback(move |element| {
         clear_bin(&element)?;
         if !use_splash {
             Ok(None)
         } else {
-            build_unknown(&element, &pattern)?;
+            build_unknown(&element, &source)?;
             Ok(Some(element))
         }
     })
     .await
 }
 
+/// A factory that shows a camera offline slate for as long as the client stays connected
+pub(super) async fn make_offline_factory(source: SlateSource) -> AnyResult<NeoMediaFactory> {
+    NeoMediaFactory::new_with_callback(move |element| {
+        clear_bin(&element)?;
+        build_slate(&element, &source, "Camera Offline", -1)?;
+        Ok(Some(element))
+    })
+    .await
+}
+
 pub(super) async fn make_factory(
     stream_config: &StreamConfig,
+    zoom: Option<&DigitalZoomConfig>,
+    overlay: Option<&OverlayConfig>,
+    masks: &[PrivacyMaskConfig],
+    backchannel: bool,
+    transcode: bool,
+    audio: AudioMode,
+    latency: LatencyMode,
 ) -> AnyResult<(NeoMediaFactory, MpscReceiver<ClientData>)> {
     let (client_tx, client_rx) = mpsc(100);
     let factory = {
         let stream_config = stream_config.clone();
+        let zoom = zoom.cloned();
+        let overlay = overlay.cloned();
+        let masks = masks.to_vec();
+        // The re-encode is always H264
+        let reencode = transcode || zoom.is_some() || overlay.is_some() || !masks.is_empty();
 
         NeoMediaFactory::new_with_callback(move |element| {
             clear_bin(&element)?;
-            let vid = match stream_config.vid_format {
-                VidFormat::None => {
+            let mut reencode_elements = None;
+            let vid = match (stream_config.vid_format, reencode) {
+                (VidFormat::None, _) => {
                     // This should not be reachable
                     log::debug!("Building unknown during normal make factory");
-                    build_unknown(&element, "black")?;
+                    build_unknown(&element, &SlateSource::Pattern("black".to_string()))?;
                     AnyResult::Ok(None)
                 }
-                VidFormat::H264 => {
-                    let app = build_h264(&element, &stream_config)?;
+                (_, true) => {
+                    let (app, elements) = build_reencode(
+                        &element,
+                        &stream_config,
+                        zoom.as_ref(),
+                        overlay.as_ref(),
+                        &masks,
+                        latency,
+                    )?;
                     app.set_callbacks(
                         AppSrcCallbacks::builder()
                             .seek_data(move |_, _seek_pos| true)
                             .build(),
                     );
+                    reencode_elements = Some(elements);
                     AnyResult::Ok(Some(app))
                 }
-                VidFormat::H265 => {
-                    let app = build_h265(&element, &stream_config)?;
+                (VidFormat::H264, false) => {
+                    let app = build_h264(&element, &stream_config, latency)?;
+                    app.set_callbacks(
+                        AppSrcCallbacks::builder()
+                            .seek_data(move |_, _seek_pos| true)
+                            .build(),
+                    );
+                    AnyResult::Ok(Some(app))
+                }
+                (VidFormat::H265, false) => {
+                    let app = build_h265(&element, &stream_config, latency)?;
 
                     app.set_callbacks(
                         AppSrcCallbacks::builder()
@@ -76,7 +147,7 @@ pub(super) async fn make_factory(
                 match stream_config.aud_format {
                     AudFormat::None => AnyResult::Ok(None),
                     AudFormat::Aac => {
-                        let app = build_aac(&element, &stream_config)?;
+                        let app = build_aac(&element, &stream_config, audio, latency)?;
                         app.set_callbacks(
                             AppSrcCallbacks::builder()
                                 .seek_data(move |_, _seek_pos| true)
@@ -85,7 +156,8 @@ pub(super) async fn make_factory(
                         AnyResult::Ok(Some(app))
                     }
                     AudFormat::Adpcm(block_size) => {
-                        let app = build_adpcm(&element, block_size, &stream_config)?;
+                        let app =
+                            build_adpcm(&element, block_size, &stream_config, audio, latency)?;
                         app.set_callbacks(
                             AppSrcCallbacks::builder()
                                 .seek_data(move |_, _seek_pos| true)
@@ -96,14 +168,27 @@ pub(super) async fn make_factory(
                 }?
             };
 
+            // Only there when the client asked for the backchannel
+            let backchannel = element
+                .clone()
+                .dynamic_cast::<Bin>()
+                .ok()
+                .and_then(|bin| bin.by_name(BACKCHANNEL_SINK))
+                .and_then(|sink| sink.dynamic_cast::<AppSink>().ok());
+
             client_tx.blocking_send(ClientData {
                 vid: vid.map(|app| ClientSourceData { app }),
                 aud: aud.map(|app| ClientSourceData { app }),
+                reencode: reencode_elements,
+                backchannel,
             })?;
             Ok(Some(element))
         })
         .await
     }?;
+    if backchannel {
+        factory.enable_backchannel();
+    }
 
     Ok((factory, client_rx))
 }
@@ -113,34 +198,71 @@ fn clear_bin(bin: &Element) -> Result<()> {
         .clone()
         .dynamic_cast::<Bin>()
         .map_err(|_| anyhow!("Media source's element should be a bin"))?;
-    // Clear the autogenerated ones
+    // Clear the autogenerated ones, the backchannel is linked up by the server
     for element in bin.iterate_elements().into_iter().flatten() {
-        bin.remove(&element)?;
+        if element.name() != BACKCHANNEL_BIN {
+            bin.remove(&element)?;
+        }
     }
 
     Ok(())
 }
 
-fn build_unknown(bin: &Element, pattern: &str) -> Result<()> {
+fn build_unknown(bin: &Element, source: &SlateSource) -> Result<()> {
+    log::debug!("Building Unknown Pipeline");
+    build_slate(bin, source, "Stream not Ready", 500) // Send buffers then EOS
+}
+
+/// Builds a test pattern or still image with a text overlay, `num_buffers` of -1 never ends
+fn build_slate(bin: &Element, source: &SlateSource, text: &str, num_buffers: i32) -> Result<()> {
     let bin = bin
         .clone()
         .dynamic_cast::<Bin>()
         .map_err(|_| anyhow!("Media source's element should be a bin"))?;
-    log::debug!("Building Unknown Pipeline");
-    let source = make_element("videotestsrc", "testvidsrc")?;
-    source.set_property_from_str("pattern", pattern);
-    source.set_property("num-buffers", 500i32); // Send buffers then EOS
+    let source = match source {
+        SlateSource::Pattern(pattern) => {
+            let source = make_element("videotestsrc", "testvidsrc")?;
+            source.set_property_from_str("pattern", pattern);
+            source.set_property("num-buffers", num_buffers);
+            bin.add(&source)?;
+            source
+        }
+        SlateSource::Image(image) => {
+            let file = make_element("filesrc", "imagesrc")?;
+            file.set_property("location", image.to_string_lossy().as_ref());
+            let decode = make_element("decodebin", "imagedecode")?;
+            let freeze = make_element("imagefreeze", "imagefreeze")?;
+            freeze.set_property("num-buffers", num_buffers);
+            let convert = make_element("videoconvert", "imageconvert")?;
+            let scale = make_element("videoscale", "imagescale")?;
+            bin.add_many([&file, &decode, &freeze, &convert, &scale])?;
+            file.link(&decode)?;
+            // The decoder only has a pad once it has seen the image format
+            let thread_freeze = freeze.clone();
+            decode.connect_pad_added(move |_, pad| {
+                if let Some(sink) = thread_freeze.static_pad("sink") {
+                    if !sink.is_linked() {
+                        if let Err(e) = pad.link(&sink) {
+                            log::warn!("Could not decode the fallback image: {e:?}");
+                        }
+                    }
+                }
+            });
+            Element::link_many([&freeze, &convert, &scale])?;
+            scale
+        }
+    };
     let queue = make_queue("queue0", 1024 * 1024 * 4)?;
 
     let overlay = make_element("textoverlay", "overlay")?;
-    overlay.set_property("text", "Stream not Ready");
+    overlay.set_property("text", text);
     overlay.set_property_from_str("valignment", "top");
     overlay.set_property_from_str("halignment", "left");
     overlay.set_property("font-desc", "Sans, 16");
     let encoder = make_element("jpegenc", "encoder")?;
     let payload = make_element("rtpjpegpay", "pay0")?;
 
-    bin.add_many([&source, &queue, &overlay, &encoder, &payload])?;
+    bin.add_many([&queue, &overlay, &encoder, &payload])?;
     source.link_filtered(
         &queue,
         &Caps::builder("video/x-raw")
@@ -155,8 +277,8 @@ fn build_unknown(bin: &Element, pattern: &str) -> Result<()> {
     Ok(())
 }
 
-fn build_h264(bin: &Element, stream_config: &StreamConfig) -> Result<AppSrc> {
-    let buffer_size = buffer_size(stream_config.bitrate);
+fn build_h264(bin: &Element, stream_config: &StreamConfig, latency: LatencyMode) -> Result<AppSrc> {
+    let buffer_size = buffer_size(stream_config.bitrate) * latency.buffer_scale();
     log::debug!(
         "buffer_size: {buffer_size}, bitrate: {}",
         stream_config.bitrate
@@ -181,7 +303,7 @@ fn build_h264(bin: &Element, stream_config: &StreamConfig) -> Result<AppSrc> {
     let source = source
         .dynamic_cast::<Element>()
         .map_err(|_| anyhow!("Cannot cast back"))?;
-    let queue = make_queue("source_queue", buffer_size)?;
+    let queue = make_source_queue("source_queue", buffer_size, latency)?;
     let parser = make_element("h264parse", "parser")?;
     // let stamper = make_element("h264timestamper", "stamper")?;
     let payload = make_element("rtph264pay", "pay0")?;
@@ -194,8 +316,8 @@ fn build_h264(bin: &Element, stream_config: &StreamConfig) -> Result<AppSrc> {
     Ok(source)
 }
 
-fn build_h265(bin: &Element, stream_config: &StreamConfig) -> Result<AppSrc> {
-    let buffer_size = buffer_size(stream_config.bitrate);
+fn build_h265(bin: &Element, stream_config: &StreamConfig, latency: LatencyMode) -> Result<AppSrc> {
+    let buffer_size = buffer_size(stream_config.bitrate) * latency.buffer_scale();
     let bin = bin
         .clone()
         .dynamic_cast::<Bin>()
@@ -215,7 +337,7 @@ fn build_h265(bin: &Element, stream_config: &StreamConfig) -> Result<AppSrc> {
     let source = source
         .dynamic_cast::<Element>()
         .map_err(|_| anyhow!("Cannot cast back"))?;
-    let queue = make_queue("source_queue", buffer_size)?;
+    let queue = make_source_queue("source_queue", buffer_size, latency)?;
     let parser = make_element("h265parse", "parser")?;
     // let stamper = make_element("h265timestamper", "stamper")?;
     let payload = make_element("rtph265pay", "pay0")?;
@@ -228,14 +350,290 @@ fn build_h265(bin: &Element, stream_config: &StreamConfig) -> Result<AppSrc> {
     Ok(source)
 }
 
-fn build_aac(bin: &Element, stream_config: &StreamConfig) -> Result<AppSrc> {
+/// The elements of a re-encoded stream that can be changed while it plays
+pub(super) struct ReencodeElements {
+    /// The videocrop element of the digital zoom
+    pub(super) crop: Option<Element>,
+    /// The text element of the overlay
+    pub(super) text: Option<Element>,
+}
+
+/// Decodes the stream, applies the privacy masks, digital zoom and
+/// overlay then re-encodes it as H264
+fn build_reencode(
+    bin: &Element,
+    stream_config: &StreamConfig,
+    zoom: Option<&DigitalZoomConfig>,
+    overlay: Option<&OverlayConfig>,
+    masks: &[PrivacyMaskConfig],
+    latency: LatencyMode,
+) -> Result<(AppSrc, ReencodeElements)> {
+    let buffer_size = buffer_size(stream_config.bitrate) * latency.buffer_scale();
+    let bin = bin
+        .clone()
+        .dynamic_cast::<Bin>()
+        .map_err(|_| anyhow!("Media source's element should be a bin"))?;
+    log::debug!("Building Re-encode Pipeline");
+    let source = make_element("appsrc", "vidsrc")?
+        .dynamic_cast::<AppSrc>()
+        .map_err(|_| anyhow!("Cannot cast to appsrc."))?;
+    source.set_is_live(false);
+    source.set_block(false);
+    source.set_min_latency(1000 / (stream_config.fps as i64));
+    source.set_property("emit-signals", false);
+    source.set_max_bytes(buffer_size as u64);
+    source.set_do_timestamp(false);
+    source.set_stream_type(AppStreamType::Seekable);
+
+    let source = source
+        .dynamic_cast::<Element>()
+        .map_err(|_| anyhow!("Cannot cast back"))?;
+    let queue = make_source_queue("source_queue", buffer_size, latency)?;
+    let (parser, decoder) = match stream_config.vid_format {
+        VidFormat::H265 => (
+            make_element("h265parse", "parser")?,
+            make_element("avdec_h265", "decoder")?,
+        ),
+        _ => (
+            make_element("h264parse", "parser")?,
+            make_element("avdec_h264", "decoder")?,
+        ),
+    };
+    let mut elements = vec![source.clone(), queue, parser, decoder];
+
+    // Masks are drawn before the crop so that they are in the
+    // coordinates of the full frame
+    if !masks.is_empty() {
+        let convert = make_element("videoconvert", "maskconvert")?;
+        let filter = make_element("capsfilter", "maskfilter")?;
+        filter.set_property(
+            "caps",
+            Caps::builder("video/x-raw").field("format", "I420").build(),
+        );
+        let masks = masks.to_vec();
+        filter
+            .static_pad("src")
+            .ok_or_else(|| anyhow!("Mask filter has no src pad"))?
+            .add_probe(PadProbeType::BUFFER, move |pad, info| {
+                let size = pad.current_caps().and_then(|caps| {
+                    let structure = caps.structure(0)?;
+                    Some((
+                        structure.get::<i32>("width").ok()? as usize,
+                        structure.get::<i32>("height").ok()? as usize,
+                    ))
+                });
+                if let (Some((width, height)), Some(PadProbeData::Buffer(buffer))) =
+                    (size, info.data.as_mut())
+                {
+                    if let Ok(mut map) = buffer.make_mut().map_writable() {
+                        draw_masks(map.as_mut_slice(), width, height, &masks);
+                    }
+                }
+                PadProbeReturn::Ok
+            });
+        elements.extend([convert, filter]);
+    }
+
+    let crop = if let Some(zoom) = zoom {
+        let crop = make_element("videocrop", "crop")?;
+        apply_zoom(&crop, &zoom.rect(), stream_config.resolution);
+        let scale = make_element("videoscale", "scale")?;
+
+        // Default to the size of the crop so that it is not upscaled
+        let rect = zoom.rect();
+        let even = |v: f64| ((v as i32) / 2 * 2).max(2);
+        let width = zoom
+            .output_width
+            .map(|w| w as i32)
+            .unwrap_or_else(|| even(rect.width * stream_config.resolution[0] as f64));
+        let height = zoom
+            .output_height
+            .map(|h| h as i32)
+            .unwrap_or_else(|| even(rect.height * stream_config.resolution[1] as f64));
+        let filter = make_element("capsfilter", "scalefilter")?;
+        filter.set_property(
+            "caps",
+            Caps::builder("video/x-raw")
+                .field("width", width)
+                .field("height", height)
+                .build(),
+        );
+        elements.extend([crop.clone(), scale, filter]);
+        Some(crop)
+    } else {
+        None
+    };
+
+    let text = if let Some(overlay) = overlay {
+        let text = if overlay.timestamp {
+            let clock = make_element("clockoverlay", "overlay")?;
+            clock.set_property("time-format", &overlay.time_format);
+            clock
+        } else {
+            make_element("textoverlay", "overlay")?
+        };
+        let (valignment, halignment) = overlay.alignment();
+        text.set_property_from_str("valignment", valignment);
+        text.set_property_from_str("halignment", halignment);
+        text.set_property("font-desc", &overlay.font);
+        text.set_property("shaded-background", true);
+        elements.push(text.clone());
+
+        if let Some(image) = overlay.image.as_ref() {
+            let watermark = make_element("gdkpixbufoverlay", "watermark")?;
+            watermark.set_property("location", image.to_string_lossy().as_ref());
+            watermark.set_property("offset-x", overlay.image_x);
+            watermark.set_property("offset-y", overlay.image_y);
+            // Images are often RGBA, convert so they can be blended
+            elements.push(make_element("videoconvert", "watermarkconvert")?);
+            elements.push(watermark);
+        }
+        Some(text)
+    } else {
+        None
+    };
+
+    let convert = make_element("videoconvert", "convert")?;
+    let encoder = make_element("x264enc", "encoder")?;
+    encoder.set_property_from_str("tune", "zerolatency");
+    encoder.set_property_from_str("speed-preset", "ultrafast");
+    let payload = make_element("rtph264pay", "pay0")?;
+    elements.extend([convert, encoder, payload]);
+
+    bin.add_many(elements.iter())?;
+    Element::link_many(elements.iter())?;
+
+    let source = source
+        .dynamic_cast::<AppSrc>()
+        .map_err(|_| anyhow!("Cannot convert appsrc"))?;
+    Ok((source, ReencodeElements { crop, text }))
+}
+
+/// Set the pixels that the videocrop element removes from each edge
+pub(super) fn apply_zoom(crop: &Element, rect: &ZoomRect, resolution: [u32; 2]) {
+    let [width, height] = resolution.map(|v| v as f64);
+    let left = (rect.x * width) as i32;
+    let top = (rect.y * height) as i32;
+    let right = (width - (rect.x + rect.width) * width).max(0.0) as i32;
+    let bottom = (height - (rect.y + rect.height) * height).max(0.0) as i32;
+    crop.set_property("left", left);
+    crop.set_property("right", right);
+    crop.set_property("top", top);
+    crop.set_property("bottom", bottom);
+}
+
+/// Black out or pixelate the masked areas of an I420 frame
+fn draw_masks(data: &mut [u8], width: usize, height: usize, masks: &[PrivacyMaskConfig]) {
+    // The default I420 layout: rows padded to 4 bytes with the
+    // chroma planes at half the resolution
+    let round_up_4 = |v: usize| (v + 3) & !3;
+    let (chroma_width, chroma_height) = ((width + 1) / 2, (height + 1) / 2);
+    let luma_stride = round_up_4(width);
+    let chroma_stride = round_up_4(chroma_width);
+    let luma_size = luma_stride * height;
+    let chroma_size = chroma_stride * chroma_height;
+    if data.len() < luma_size + 2 * chroma_size {
+        log::trace!("Unexpected frame size for the privacy mask");
+        return;
+    }
+    let (luma, chroma) = data.split_at_mut(luma_size);
+    let (u, v) = chroma.split_at_mut(chroma_size);
+    // About 32 blocks across the frame
+    let block = (width / 32).max(8);
+
+    for mask in masks.iter() {
+        let rect = mask.rect();
+        let xs = (rect.x * width as f64) as usize
+            ..(((rect.x + rect.width) * width as f64).ceil() as usize).min(width);
+        let ys = (rect.y * height as f64) as usize
+            ..(((rect.y + rect.height) * height as f64).ceil() as usize).min(height);
+        let chroma_xs = xs.start / 2..(xs.end + 1) / 2;
+        let chroma_ys = ys.start / 2..(ys.end + 1) / 2;
+        match mask.style {
+            PrivacyMaskStyle::Black => {
+                fill_plane(luma, luma_stride, &xs, &ys, 16);
+                fill_plane(u, chroma_stride, &chroma_xs, &chroma_ys, 128);
+                fill_plane(v, chroma_stride, &chroma_xs, &chroma_ys, 128);
+            }
+            PrivacyMaskStyle::Blur => {
+                pixelate_plane(luma, luma_stride, &xs, &ys, block);
+                pixelate_plane(u, chroma_stride, &chroma_xs, &chroma_ys, block / 2);
+                pixelate_plane(v, chroma_stride, &chroma_xs, &chroma_ys, block / 2);
+            }
+        }
+    }
+}
+
+fn fill_plane(plane: &mut [u8], stride: usize, xs: &Range<usize>, ys: &Range<usize>, value: u8) {
+    for y in ys.clone() {
+        plane[y * stride + xs.start..y * stride + xs.end].fill(value);
+    }
+}
+
+/// Replace each block of the area with its average
+fn pixelate_plane(
+    plane: &mut [u8],
+    stride: usize,
+    xs: &Range<usize>,
+    ys: &Range<usize>,
+    block: usize,
+) {
+    for block_y in ys.clone().step_by(block) {
+        let block_ys = block_y..(block_y + block).min(ys.end);
+        for block_x in xs.clone().step_by(block) {
+            let block_xs = block_x..(block_x + block).min(xs.end);
+            let sum: usize = block_ys
+                .clone()
+                .map(|y| {
+                    plane[y * stride + block_xs.start..y * stride + block_xs.end]
+                        .iter()
+                        .map(|&p| p as usize)
+                        .sum::<usize>()
+                })
+                .sum();
+            let average = sum / (block_ys.len() * block_xs.len());
+            fill_plane(plane, stride, &block_xs, &block_ys, average as u8);
+        }
+    }
+}
+
+/// The elements after the decoder that encode and payload the raw audio,
+/// they are added to the bin and linked together
+///
+/// Returns the first element which the decoder should link to
+fn build_aud_encoder(bin: &Bin, audio: AudioMode) -> Result<Element> {
+    let convert = make_element("audioconvert", "audencoder")?;
+    match audio {
+        AudioMode::Opus => {
+            let resample = make_element("audioresample", "audresample")?;
+            let encoder = make_element("opusenc", "audopusenc")?;
+            let payload = make_element("rtpopuspay", "pay1")?;
+            bin.add_many([&convert, &resample, &encoder, &payload])?;
+            Element::link_many([&convert, &resample, &encoder, &payload])?;
+        }
+        // Passthru only reaches here for formats that have no RTP payload
+        AudioMode::Pcm | AudioMode::Passthru | AudioMode::Off => {
+            let payload = make_element("rtpL16pay", "pay1")?;
+            bin.add_many([&convert, &payload])?;
+            Element::link_many([&convert, &payload])?;
+        }
+    }
+    Ok(convert)
+}
+
+fn build_aac(
+    bin: &Element,
+    stream_config: &StreamConfig,
+    audio: AudioMode,
+    latency: LatencyMode,
+) -> Result<AppSrc> {
     // Audio seems to run at about 800kbs
-    let buffer_size = 512 * 1416;
+    let buffer_size = 512 * 1416 * latency.buffer_scale();
     let bin = bin
         .clone()
         .dynamic_cast::<Bin>()
         .map_err(|_| anyhow!("Media source's element should be a bin"))?;
-    log::debug!("Building Aac pipeline");
+    log::debug!("Building Aac pipeline as {:?}", audio);
     let source = make_element("appsrc", "audsrc")?
         .dynamic_cast::<AppSrc>()
         .map_err(|_| anyhow!("Cannot cast to appsrc."))?;
@@ -252,40 +650,45 @@ fn build_aac(bin: &Element, stream_config: &StreamConfig) -> Result<AppSrc> {
         .dynamic_cast::<Element>()
         .map_err(|_| anyhow!("Cannot cast back"))?;
 
-    let queue = make_queue("audqueue", buffer_size)?;
+    let queue = make_source_queue("audqueue", buffer_size, latency)?;
     let parser = make_element("aacparse", "audparser")?;
-    let decoder = match make_element("faad", "auddecoder_faad") {
-        Ok(ele) => Ok(ele),
-        Err(_) => make_element("avdec_aac", "auddecoder_avdec_aac"),
-    }?;
-
-    // The fallback
-    let silence = make_element("audiotestsrc", "audsilence")?;
-    silence.set_property_from_str("wave", "silence");
-    let fallback_switch = make_element("fallbackswitch", "audfallbackswitch");
-    if let Ok(fallback_switch) = fallback_switch.as_ref() {
-        fallback_switch.set_property("timeout", 3u64 * 1_000_000_000u64);
-        fallback_switch.set_property("immediate-fallback", true);
-    }
 
-    let encoder = make_element("audioconvert", "audencoder")?;
-    let payload = make_element("rtpL16pay", "pay1")?;
-
-    bin.add_many([&source, &queue, &parser, &decoder, &encoder, &payload])?;
-    if let Ok(fallback_switch) = fallback_switch.as_ref() {
-        bin.add_many([&silence, fallback_switch])?;
-        Element::link_many([
-            &source,
-            &queue,
-            &parser,
-            &decoder,
-            fallback_switch,
-            &encoder,
-            &payload,
-        ])?;
-        Element::link_many([&silence, fallback_switch])?;
+    if audio == AudioMode::Passthru {
+        // The camera's AAC is sent as it is
+        let payload = make_element("rtpmp4gpay", "pay1")?;
+        bin.add_many([&source, &queue, &parser, &payload])?;
+        Element::link_many([&source, &queue, &parser, &payload])?;
     } else {
-        Element::link_many([&source, &queue, &parser, &decoder, &encoder, &payload])?;
+        let decoder = match make_element("faad", "auddecoder_faad") {
+            Ok(ele) => Ok(ele),
+            Err(_) => make_element("avdec_aac", "auddecoder_avdec_aac"),
+        }?;
+
+        // The fallback
+        let silence = make_element("audiotestsrc", "audsilence")?;
+        silence.set_property_from_str("wave", "silence");
+        let fallback_switch = make_element("fallbackswitch", "audfallbackswitch");
+        if let Ok(fallback_switch) = fallback_switch.as_ref() {
+            fallback_switch.set_property("timeout", 3u64 * 1_000_000_000u64);
+            fallback_switch.set_property("immediate-fallback", true);
+        }
+
+        bin.add_many([&source, &queue, &parser, &decoder])?;
+        let encoder = build_aud_encoder(&bin, audio)?;
+        if let Ok(fallback_switch) = fallback_switch.as_ref() {
+            bin.add_many([&silence, fallback_switch])?;
+            Element::link_many([
+                &source,
+                &queue,
+                &parser,
+                &decoder,
+                fallback_switch,
+                &encoder,
+            ])?;
+            Element::link_many([&silence, fallback_switch])?;
+        } else {
+            Element::link_many([&source, &queue, &parser, &decoder, &encoder])?;
+        }
     }
 
     let source = source
@@ -294,13 +697,22 @@ fn build_aac(bin: &Element, stream_config: &StreamConfig) -> Result<AppSrc> {
     Ok(source)
 }
 
-fn build_adpcm(bin: &Element, block_size: u32, stream_config: &StreamConfig) -> Result<AppSrc> {
-    let buffer_size = 512 * 1416;
+fn build_adpcm(
+    bin: &Element,
+    block_size: u32,
+    stream_config: &StreamConfig,
+    audio: AudioMode,
+    latency: LatencyMode,
+) -> Result<AppSrc> {
+    let buffer_size = 512 * 1416 * latency.buffer_scale();
     let bin = bin
         .clone()
         .dynamic_cast::<Bin>()
         .map_err(|_| anyhow!("Media source's element should be a bin"))?;
-    log::debug!("Building Adpcm pipeline");
+    log::debug!("Building Adpcm pipeline as {:?}", audio);
+    if audio == AudioMode::Passthru {
+        log::debug!("ADPCM has no RTP payload, it is decoded to PCM");
+    }
     // Original command line
     // caps=audio/x-adpcm,layout=dvi,block_align={},channels=1,rate=8000
     // ! queue silent=true max-size-bytes=10485760 min-threshold-bytes=1024
@@ -332,14 +744,12 @@ fn build_adpcm(bin: &Element, block_size: u32, stream_config: &StreamConfig) ->
         .dynamic_cast::<Element>()
         .map_err(|_| anyhow!("Cannot cast back"))?;
 
-    let queue = make_queue("audqueue", buffer_size)?;
+    let queue = make_source_queue("audqueue", buffer_size, latency)?;
     let decoder = make_element("decodebin", "auddecoder")?;
-    let encoder = make_element("audioconvert", "audencoder")?;
-    let payload = make_element("rtpL16pay", "pay1")?;
 
-    bin.add_many([&source, &queue, &decoder, &encoder, &payload])?;
+    bin.add_many([&source, &queue, &decoder])?;
+    let encoder = build_aud_encoder(&bin, audio)?;
     Element::link_many([&source, &queue, &decoder])?;
-    Element::link_many([&encoder, &payload])?;
     decoder.connect_pad_added(move |_element, pad| {
         let sink_pad = encoder
             .static_pad("sink")
@@ -369,11 +779,23 @@ fn make_element(kind: &str, name: &str) -> AnyResult<Element> {
             "rtpjitterbuffer" => "rtp (gst-plugins-good)",
             "aacparse" => "audioparsers (gst-plugins-good)",
             "rtpL16pay" => "rtp (gst-plugins-good)",
+            "rtpmp4gpay" => "rtp (gst-plugins-good)",
+            "rtpopuspay" => "rtp (gst-plugins-good)",
+            "opusenc" => "opus (gst-plugins-base)",
+            "audioresample" => "audioresample (gst-plugins-base)",
             "x264enc" => "x264 (gst-plugins-ugly)",
             "x265enc" => "x265 (gst-plugins-bad)",
             "avdec_h264" => "libav (gst-libav)",
             "avdec_h265" => "libav (gst-libav)",
             "videotestsrc" => "videotestsrc (gst-plugins-base)",
+            "videocrop" => "videocrop (gst-plugins-good)",
+            "videoscale" => "videoscale (gst-plugins-base)",
+            "videoconvert" => "videoconvert (gst-plugins-base)",
+            "capsfilter" => "coreelements (gstreamer)",
+            "filesrc" => "coreelements (gstreamer)",
+            "textoverlay" => "pango (gst-plugins-base)",
+            "clockoverlay" => "pango (gst-plugins-base)",
+            "gdkpixbufoverlay" => "gdkpixbuf (gst-plugins-good)",
             "imagefreeze" => "imagefreeze (gst-plugins-good)",
             "audiotestsrc" => "audiotestsrc (gst-plugins-base)",
             "decodebin" => "playback (gst-plugins-good)",
@@ -446,6 +868,20 @@ fn make_queue(name: &str, buffer_size: u32) -> AnyResult<Element> {
     Ok(queue)
 }
 
+/// The queue after an appsrc, sized for the `latency`
+fn make_source_queue(name: &str, buffer_size: u32, latency: LatencyMode) -> AnyResult<Element> {
+    let queue = make_queue(name, buffer_size)?;
+    queue.set_property(
+        "max-size-time",
+        std::convert::TryInto::<u64>::try_into(latency.queue_time().as_nanos()).unwrap_or(0),
+    );
+    if latency == LatencyMode::Realtime {
+        // Drop the old frames rather than fall behind
+        queue.set_property_from_str("leaky", "downstream");
+    }
+    Ok(queue)
+}
+
 fn buffer_size(bitrate: u32) -> u32 {
     // 0.1 seconds (according to bitrate) or 4kb what ever is larger
     std::cmp::max(bitrate * 2 / 8u32, 4u32 * 1024u32)
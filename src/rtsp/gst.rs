@@ -1,10 +1,12 @@
 //! This module provides an "RtspServer" abstraction that allows consumers of its API to feed it
 //! data using an ordinary std::io::Write interface.
 
+mod client;
 mod factory;
 mod server;
 mod shared;
 
+pub(crate) use client::FallbackTargets;
 pub(crate) use factory::*;
 
 pub(crate) use self::server::NeoRtspServer;
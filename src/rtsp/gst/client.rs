@@ -0,0 +1,237 @@
+//! Attempts to subclass RTSPClient
+//!
+//! We are now messing with gstreamer glib objects
+//! expect issues
+//!
+//! The client rewrites the path of the clients that keep failing to
+//! play a stream so that they are served the next stream of the codec
+//! fallback instead
+
+use gstreamer::glib::{self, object_subclass, translate::*, GString, Object};
+use gstreamer_rtsp::RTSPUrl;
+use gstreamer_rtsp_server::{
+    prelude::*, subclass::prelude::*, RTSPClient, RTSPContext, RTSPMountPoints, RTSPOnvifClient,
+};
+use once_cell::sync::OnceCell;
+use std::{
+    collections::HashMap,
+    ffi::CStr,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+/// Where the clients of a stream are sent when they keep failing to play it
+#[derive(Debug, Clone)]
+pub(crate) struct FallbackTargets {
+    /// The path that the clients are served at each level, `None` for the stream itself
+    pub(crate) targets: Vec<Option<String>>,
+    /// Failed plays before a client moves to the next level
+    pub(crate) failures: u32,
+    /// Plays that end sooner than this are a failure
+    pub(crate) min_play: Duration,
+}
+
+#[derive(Debug, Default)]
+struct ClientFallback {
+    level: usize,
+    failures: u32,
+}
+
+/// The fallback of each path and how far each client has fallen back
+///
+/// Clients are told apart by their ip and their level is kept while the
+/// stream reloads
+#[derive(Default)]
+pub(crate) struct CodecFallbacks {
+    /// The first path of the stream, used as its key, and its targets
+    paths: Mutex<HashMap<String, (String, Arc<FallbackTargets>)>>,
+    /// The level of each client by the key of the stream and the ip
+    clients: Mutex<HashMap<(String, String), ClientFallback>>,
+}
+
+impl CodecFallbacks {
+    /// Set the fallback of a stream's paths, `None` to serve them as they are
+    pub(crate) fn set(&self, paths: &[String], targets: Option<FallbackTargets>) {
+        let mut locked_paths = self.paths.lock().unwrap();
+        match (paths.first(), targets) {
+            (Some(key), Some(targets)) => {
+                let targets = Arc::new(targets);
+                for path in paths.iter() {
+                    locked_paths.insert(path.clone(), (key.clone(), targets.clone()));
+                }
+            }
+            _ => {
+                for path in paths.iter() {
+                    locked_paths.remove(path);
+                }
+            }
+        }
+    }
+
+    /// The path that the client is served in place of the mount `path`
+    fn redirect(&self, path: &str, ip: &str) -> Option<String> {
+        let (key, targets) = self.paths.lock().unwrap().get(path).cloned()?;
+        let clients = self.clients.lock().unwrap();
+        let level = clients.get(&(key, ip.to_string()))?.level;
+        targets.targets.get(level).cloned().flatten()
+    }
+
+    /// Count a play of the mount `path` that ended after `played`
+    fn ended(&self, path: &str, ip: &str, played: Duration) {
+        let Some((key, targets)) = self.paths.lock().unwrap().get(path).cloned() else {
+            return;
+        };
+        let mut clients = self.clients.lock().unwrap();
+        let client = clients.entry((key, ip.to_string())).or_default();
+        if played >= targets.min_play {
+            client.failures = 0;
+            return;
+        }
+        client.failures += 1;
+        log::debug!(
+            "{}: Play of {} ended after {:?}, {}/{} failures",
+            ip,
+            path,
+            played,
+            client.failures,
+            targets.failures
+        );
+        if client.failures >= targets.failures && client.level + 1 < targets.targets.len() {
+            client.level += 1;
+            client.failures = 0;
+            log::info!(
+                "{}: Keeps failing to play {}, serving {} instead",
+                ip,
+                path,
+                targets.targets[client.level].as_deref().unwrap_or(path)
+            );
+        }
+    }
+}
+
+glib::wrapper! {
+    /// The wrapped RTSPClient
+    ///
+    /// It is an ONVIF client so that it can use the audio backchannel
+    pub(crate) struct NeoRtspClient(ObjectSubclass<NeoRtspClientImpl>) @extends RTSPOnvifClient, RTSPClient;
+}
+
+impl NeoRtspClient {
+    pub(crate) fn new(fallbacks: Arc<CodecFallbacks>) -> Self {
+        let client = Object::new::<NeoRtspClient>();
+        let _ = client.imp().fallbacks.set(fallbacks);
+        client
+    }
+}
+
+unsafe impl Send for NeoRtspClient {}
+unsafe impl Sync for NeoRtspClient {}
+
+#[derive(Default)]
+pub(crate) struct NeoRtspClientImpl {
+    fallbacks: OnceCell<Arc<CodecFallbacks>>,
+    /// The mount path that is playing and when it started
+    playing: Mutex<Option<(String, Instant)>>,
+}
+
+impl NeoRtspClientImpl {
+    /// The ip of the client
+    fn ip(&self) -> Option<String> {
+        unsafe {
+            let connection = gstreamer_rtsp_server::ffi::gst_rtsp_client_get_connection(
+                self.obj().upcast_ref::<RTSPClient>().to_glib_none().0,
+            );
+            if connection.is_null() {
+                return None;
+            }
+            let ip = gstreamer_rtsp::ffi::gst_rtsp_connection_get_ip(connection);
+            if ip.is_null() {
+                return None;
+            }
+            Some(CStr::from_ptr(ip).to_string_lossy().into_owned())
+        }
+    }
+
+    /// The path of the mount that serves `path`, without the stream suffix
+    fn mount_path<'a>(&self, path: &'a str) -> Option<&'a str> {
+        let mounts: RTSPMountPoints = self.obj().mount_points()?;
+        let mut matched = 0;
+        unsafe {
+            let factory = gstreamer_rtsp_server::ffi::gst_rtsp_mount_points_match(
+                mounts.to_glib_none().0,
+                path.to_glib_none().0,
+                &mut matched,
+            );
+            if factory.is_null() {
+                return None;
+            }
+            glib::gobject_ffi::g_object_unref(factory as *mut _);
+        }
+        path.get(..matched as usize)
+    }
+
+    /// Count the play that has just ended
+    fn ended(&self) {
+        let Some((path, started)) = self.playing.lock().unwrap().take() else {
+            return;
+        };
+        if let (Some(fallbacks), Some(ip)) = (self.fallbacks.get(), self.ip()) {
+            fallbacks.ended(&path, &ip, started.elapsed());
+        }
+    }
+}
+
+impl ObjectImpl for NeoRtspClientImpl {}
+impl RTSPOnvifClientImpl for NeoRtspClientImpl {}
+impl RTSPClientImpl for NeoRtspClientImpl {
+    fn make_path_from_uri(&self, url: &RTSPUrl) -> Option<GString> {
+        let path = self.parent_make_path_from_uri(url)?;
+        let redirect = self
+            .fallbacks
+            .get()
+            .zip(self.ip())
+            .zip(self.mount_path(path.as_str()))
+            .and_then(|((fallbacks, ip), mount)| {
+                fallbacks
+                    .redirect(mount, &ip)
+                    .map(|target| format!("{}{}", target, &path.as_str()[mount.len()..]))
+            });
+        match redirect {
+            Some(redirect) => Some(redirect.into()),
+            None => Some(path),
+        }
+    }
+
+    fn play_request(&self, ctx: &RTSPContext) {
+        self.parent_play_request(ctx);
+        let path = ctx
+            .uri()
+            .and_then(|url| self.parent_make_path_from_uri(url));
+        if let Some(mount) = path
+            .as_ref()
+            .and_then(|path| self.mount_path(path.as_str()))
+        {
+            self.playing
+                .lock()
+                .unwrap()
+                .get_or_insert_with(|| (mount.to_string(), Instant::now()));
+        }
+    }
+
+    fn teardown_request(&self, ctx: &RTSPContext) {
+        self.ended();
+        self.parent_teardown_request(ctx);
+    }
+
+    fn closed(&self) {
+        self.ended();
+        self.parent_closed();
+    }
+}
+
+#[object_subclass]
+impl ObjectSubclass for NeoRtspClientImpl {
+    const NAME: &'static str = "NeoRtspClient";
+    type Type = NeoRtspClient;
+    type ParentType = RTSPOnvifClient;
+}
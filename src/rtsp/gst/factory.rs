@@ -14,6 +14,7 @@ use gstreamer_rtsp::RTSPUrl;
 use gstreamer_rtsp_server::prelude::*;
 use gstreamer_rtsp_server::subclass::prelude::*;
 use gstreamer_rtsp_server::RTSPMediaFactory;
+use gstreamer_rtsp_server::RTSPOnvifMediaFactory;
 use gstreamer_rtsp_server::RTSPTransportMode;
 use gstreamer_rtsp_server::{RTSP_PERM_MEDIA_FACTORY_ACCESS, RTSP_PERM_MEDIA_FACTORY_CONSTRUCT};
 use log::*;
@@ -21,9 +22,17 @@ use std::collections::HashSet;
 use std::sync::Arc;
 use tokio::sync::Mutex;
 
+/// The name that the server gives the bin of the audio backchannel
+pub(crate) const BACKCHANNEL_BIN: &str = "onvif-backchannel";
+
+/// The name of the appsink that the backchannel audio comes out of
+pub(crate) const BACKCHANNEL_SINK: &str = "backchannel_sink";
+
 glib::wrapper! {
     /// The wrapped RTSPMediaFactory
-    pub(crate) struct NeoMediaFactory(ObjectSubclass<NeoMediaFactoryImpl>) @extends RTSPMediaFactory;
+    ///
+    /// It is an ONVIF factory so that it can offer an audio backchannel
+    pub(crate) struct NeoMediaFactory(ObjectSubclass<NeoMediaFactoryImpl>) @extends RTSPOnvifMediaFactory, RTSPMediaFactory;
 }
 
 impl Default for NeoMediaFactory {
@@ -54,6 +63,25 @@ impl NeoMediaFactory {
         Ok(factory)
     }
 
+    /// Offer an ONVIF audio backchannel to the clients that ask for one
+    ///
+    /// The server adds a bin named [`BACKCHANNEL_BIN`] to the media of those
+    /// clients. Their audio comes out of the appsink named [`BACKCHANNEL_SINK`]
+    /// as 8kHz mono S16LE
+    pub(crate) fn enable_backchannel(&self) {
+        self.set_backchannel_launch(Some(&format!(
+            "capsfilter name=depay_backchannel caps=\"application/x-rtp,media=audio,payload=0,clock-rate=8000,encoding-name=PCMU\" \
+            ! rtppcmudepay \
+            ! mulawdec \
+            ! audioconvert \
+            ! audioresample \
+            ! audio/x-raw,format=S16LE,layout=interleaved,rate=8000,channels=1 \
+            ! appsink name={} sync=false async=false max-buffers=50 drop=true",
+            BACKCHANNEL_SINK
+        )));
+        self.set_backchannel_bandwidth(64000);
+    }
+
     pub(crate) fn add_permitted_roles<T: AsRef<str>>(&self, permitted_roles: &HashSet<T>) {
         for permitted_role in permitted_roles {
             let s = permitted_role.as_ref();
@@ -137,6 +165,7 @@ impl NeoMediaFactoryImpl {
 }
 
 impl ObjectImpl for NeoMediaFactoryImpl {}
+impl RTSPOnvifMediaFactoryImpl for NeoMediaFactoryImpl {}
 impl RTSPMediaFactoryImpl for NeoMediaFactoryImpl {
     fn create_element(&self, url: &RTSPUrl) -> Option<Element> {
         self.parent_create_element(url)
@@ -148,5 +177,5 @@ impl RTSPMediaFactoryImpl for NeoMediaFactoryImpl {
 impl ObjectSubclass for NeoMediaFactoryImpl {
     const NAME: &'static str = "NeoMediaFactory";
     type Type = super::NeoMediaFactory;
-    type ParentType = RTSPMediaFactory;
+    type ParentType = RTSPOnvifMediaFactory;
 }
@@ -3,7 +3,10 @@
 //! We are now messing with gstreamer glib objects
 //! expect issues
 
-use super::AnyResult;
+use super::{
+    client::{CodecFallbacks, FallbackTargets, NeoRtspClient},
+    AnyResult,
+};
 use crate::config::*;
 
 use anyhow::Context;
@@ -13,7 +16,8 @@ use gstreamer_rtsp_server::{
     gio::{TlsAuthenticationMode, TlsCertificate},
     prelude::*,
     subclass::prelude::*,
-    RTSPAuth, RTSPFilterResult, RTSPServer, RTSPToken, RTSP_TOKEN_MEDIA_FACTORY_ROLE,
+    RTSPAuth, RTSPClient, RTSPFilterResult, RTSPOnvifServer, RTSPServer, RTSPToken,
+    RTSP_TOKEN_MEDIA_FACTORY_ROLE,
 };
 use log::*;
 use std::{
@@ -30,7 +34,9 @@ use tokio_util::sync::CancellationToken;
 
 glib::wrapper! {
     /// The wrapped RTSPServer
-    pub(crate) struct NeoRtspServer(ObjectSubclass<NeoRtspServerImpl>) @extends RTSPServer;
+    ///
+    /// It is an ONVIF server so that clients may ask for the audio backchannel
+    pub(crate) struct NeoRtspServer(ObjectSubclass<NeoRtspServerImpl>) @extends RTSPOnvifServer, RTSPServer;
 }
 
 impl Default for NeoRtspServer {
@@ -157,6 +163,13 @@ impl NeoRtspServer {
     pub(crate) async fn get_users(&self) -> AnyResult<HashSet<String>> {
         self.imp().get_users().await
     }
+
+    /// Serve the clients that keep failing to play these paths the `targets` instead
+    ///
+    /// `None` serves the paths to every client again
+    pub(crate) fn set_fallback(&self, paths: &[String], targets: Option<FallbackTargets>) {
+        self.imp().fallbacks.set(paths, targets)
+    }
 }
 
 unsafe impl Send for NeoRtspServer {}
@@ -167,16 +180,30 @@ pub(crate) struct NeoRtspServerImpl {
     threads: RwLock<JoinSet<AnyResult<()>>>,
     users: RwLock<HashMap<String, String>>,
     main_loop: RwLock<Option<Arc<MainLoop>>>,
+    fallbacks: Arc<CodecFallbacks>,
 }
 
 impl ObjectImpl for NeoRtspServerImpl {}
-impl RTSPServerImpl for NeoRtspServerImpl {}
+impl RTSPServerImpl for NeoRtspServerImpl {
+    fn create_client(&self) -> Option<RTSPClient> {
+        // Set up in the same way as the ONVIF server does for its own clients
+        let server = self.obj();
+        let client = NeoRtspClient::new(self.fallbacks.clone());
+        client.set_session_pool(server.session_pool().as_ref());
+        client.set_mount_points(server.mount_points().as_ref());
+        client.set_auth(server.auth().as_ref());
+        client.set_thread_pool(server.thread_pool().as_ref());
+        client.set_content_length_limit(server.content_length_limit());
+        Some(client.upcast())
+    }
+}
+impl RTSPOnvifServerImpl for NeoRtspServerImpl {}
 
 #[object_subclass]
 impl ObjectSubclass for NeoRtspServerImpl {
     const NAME: &'static str = "NeoRtspServer";
     type Type = NeoRtspServer;
-    type ParentType = RTSPServer;
+    type ParentType = RTSPOnvifServer;
 }
 
 impl NeoRtspServerImpl {
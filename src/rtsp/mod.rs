@@ -39,12 +39,16 @@
 //   [cameras.pause]
 //   on_motion = false
 //   on_client = false
+//   on_demand = false
 //   mode = "none"
 //   timeout = 1.0
+//   linger = 0.0
 // ```
 //
 // - When `on_motion` is true the camera will pause streaming when motion is stopped and resume it when motion is started
 // - When `on_client` is true the camera will pause while there is no client connected.
+// - When `on_demand` is true the camera only streams while a client is connected, without the initial 30s buffering
+// - `linger` is how many seconds to keep streaming after the last client disconnects
 // - `timeout` handels how long to wait after motion stops before pausing the stream
 // - `mode` has the following values:
 //   - `"black"`: Switches to a black screen. Requires more cpu as the stream is fully reencoded
@@ -67,6 +71,7 @@ use tokio_stream::wrappers::IntervalStream;
 use tokio_stream::StreamExt;
 use tokio_util::sync::CancellationToken;
 
+mod backchannel;
 mod cmdline;
 mod factory;
 mod gst;
@@ -76,7 +81,7 @@ use crate::common::{NeoInstance, NeoReactor};
 use factory::*;
 use stream::*;
 
-use super::config::UserConfig;
+use super::config::{CameraConfig, CodecFallback, UserConfig};
 pub(crate) use cmdline::Opt;
 use gst::NeoRtspServer;
 
@@ -292,17 +297,28 @@ async fn camera_main(camera: NeoInstance, rtsp: &NeoRtspServer) -> Result<()> {
     let mut camera_config = camera.config().await?.clone();
     loop {
         let prev_stream_config = camera_config.borrow_and_update().stream;
+        let prev_stream_paths = camera_config.borrow().streams.clone();
         let prev_stream_users = camera_config.borrow().permitted_users.clone();
-        let active_streams = prev_stream_config
-            .as_stream_kinds()
-            .drain(..)
-            .collect::<HashSet<_>>();
+        let stream_paths = rtsp_paths(&camera_config.borrow());
+        let active_streams = stream_paths.keys().copied().collect::<HashSet<_>>();
         let use_splash = camera_config.borrow().use_splash;
-        let splash_pattern = camera_config.borrow().splash_pattern.to_string();
+        let splash_source = SlateSource::from_config(&camera_config.borrow());
+        let rtsp_template = camera_config.borrow().rtsp_template.clone();
+        let digital_zoom = camera_config.borrow().digital_zoom.clone();
+        let zoom_kind = digital_zoom
+            .as_ref()
+            .and_then(|zoom| zoom.stream.as_stream_kinds().first().copied());
+        let transcode = camera_config
+            .borrow()
+            .codec_fallback
+            .as_ref()
+            .map_or(false, |fallback| {
+                fallback.priority.contains(&CodecFallback::H264)
+            });
 
         // This select is for changes to camera_config.stream
         break tokio::select! {
-            v = camera_config.wait_for(|config| config.stream != prev_stream_config || config.permitted_users != prev_stream_users || config.use_splash != use_splash) => {
+            v = camera_config.wait_for(|config| config.stream != prev_stream_config || config.streams != prev_stream_paths || config.permitted_users != prev_stream_users || config.use_splash != use_splash || SlateSource::from_config(config) != splash_source || config.rtsp_template != rtsp_template || config.digital_zoom != digital_zoom || config.codec_fallback.as_ref().map_or(false, |fallback| fallback.priority.contains(&CodecFallback::H264)) != transcode) => {
                 if let Err(e) = v {
                     AnyResult::Err(e.into())
                 } else {
@@ -328,25 +344,17 @@ async fn camera_main(camera: NeoInstance, rtsp: &NeoRtspServer) -> Result<()> {
                 };
 
                 // Create the dummy factory
-                let dummy_factory = make_dummy_factory(use_splash, splash_pattern).await?;
+                let dummy_factory = make_dummy_factory(use_splash, splash_source).await?;
                 dummy_factory.add_permitted_roles(&permitted_users);
                 let mut supported_streams_1 = supported_streams.clone();
                 let mut supported_streams_2 = supported_streams.clone();
                 let mut supported_streams_3 = supported_streams.clone();
+                let mut supported_streams_4 = supported_streams.clone();
+                let mut supported_streams_5 = supported_streams.clone();
                 tokio::select! {
                     v = async {
                         let name = camera.config().await?.borrow().name.clone();
-                        let mut paths = vec![
-                            format!("/{name}/main"),
-                            format!("/{name}/Main"),
-                            format!("/{name}/mainStream"),
-                            format!("/{name}/MainStream"),
-                            format!("/{name}/Mainstream"),
-                            format!("/{name}/mainstream"),
-                        ];
-                        paths.push(
-                            format!("/{name}")
-                        );
+                        let mut paths = stream_paths[&StreamKind::Main].clone();
                         // Create a dummy factory so that the URL will not return 404 while waiting
                         // for configuration to compete
                         //
@@ -362,23 +370,15 @@ async fn camera_main(camera: NeoInstance, rtsp: &NeoRtspServer) -> Result<()> {
                         log::debug!("{}: Preparing at {}", name, paths.join(", "));
 
                         supported_streams_1.wait_for(|ss| ss.contains(&StreamKind::Main)).await?;
-                        stream_main(camera.stream(StreamKind::Main).await?, camera.clone(), rtsp, &permitted_users, &paths).await
+                        if let Some(template) = rtsp_template.as_ref() {
+                            let path = format!("/{}", camera.expand_template(template, Some(StreamKind::Main)).await?);
+                            paths.push(path);
+                        }
+                        stream_main(camera.stream(StreamKind::Main).await?, camera.clone(), rtsp, &permitted_users, &paths, None, false).await
                     }, if active_streams.contains(&StreamKind::Main) => v,
                     v = async {
                         let name = camera.config().await?.borrow().name.clone();
-                        let mut paths = vec![
-                            format!("/{name}/sub"),
-                            format!("/{name}/Sub"),
-                            format!("/{name}/subStream"),
-                            format!("/{name}/SubStream"),
-                            format!("/{name}/Substream"),
-                            format!("/{name}/substream"),
-                        ];
-                        if ! active_streams.contains(&StreamKind::Main) {
-                            paths.push(
-                                format!("/{name}")
-                            );
-                        }
+                        let mut paths = stream_paths[&StreamKind::Sub].clone();
 
                         // Create a dummy factory so that the URL will not return 404 while waiting
                         // for configuration to compete
@@ -396,23 +396,15 @@ async fn camera_main(camera: NeoInstance, rtsp: &NeoRtspServer) -> Result<()> {
                         log::debug!("{}: Preparing at {}", name, paths.join(", "));
 
                         supported_streams_2.wait_for(|ss| ss.contains(&StreamKind::Sub)).await?;
-                        stream_main(camera.stream(StreamKind::Sub).await?,camera.clone(), rtsp, &permitted_users, &paths).await
+                        if let Some(template) = rtsp_template.as_ref() {
+                            let path = format!("/{}", camera.expand_template(template, Some(StreamKind::Sub)).await?);
+                            paths.push(path);
+                        }
+                        stream_main(camera.stream(StreamKind::Sub).await?,camera.clone(), rtsp, &permitted_users, &paths, None, false).await
                     }, if active_streams.contains(&StreamKind::Sub) => v,
                     v = async {
                         let name = camera.config().await?.borrow().name.clone();
-                        let mut paths = vec![
-                            format!("/{name}/extern"),
-                            format!("/{name}/Extern"),
-                            format!("/{name}/externStream"),
-                            format!("/{name}/ExternStream"),
-                            format!("/{name}/Externstream"),
-                            format!("/{name}/externstream"),
-                        ];
-                        if ! active_streams.contains(&StreamKind::Main) && ! active_streams.contains(&StreamKind::Sub) {
-                            paths.push(
-                                format!("/{name}")
-                            );
-                        }
+                        let mut paths = stream_paths[&StreamKind::Extern].clone();
 
                         // Create a dummy factory so that the URL will not return 404 while waiting
                         // for configuration to compete
@@ -429,8 +421,55 @@ async fn camera_main(camera: NeoInstance, rtsp: &NeoRtspServer) -> Result<()> {
                         log::debug!("{}: Preparing at {}", name, paths.join(", "));
 
                         supported_streams_3.wait_for(|ss| ss.contains(&StreamKind::Extern)).await?;
-                        stream_main(camera.stream(StreamKind::Extern).await?,camera.clone(), rtsp, &permitted_users, &paths).await
+                        if let Some(template) = rtsp_template.as_ref() {
+                            let path = format!("/{}", camera.expand_template(template, Some(StreamKind::Extern)).await?);
+                            paths.push(path);
+                        }
+                        stream_main(camera.stream(StreamKind::Extern).await?,camera.clone(), rtsp, &permitted_users, &paths, None, false).await
                     }, if active_streams.contains(&StreamKind::Extern) => v,
+                    v = async {
+                        let name = camera.config().await?.borrow().name.clone();
+                        let paths = vec![
+                            format!("/{name}/zoom"),
+                            format!("/{name}/Zoom"),
+                        ];
+
+                        // Create a dummy factory so that the URL will not return 404 while waiting
+                        // for configuration to compete
+                        let mounts = rtsp
+                            .mount_points()
+                            .ok_or(anyhow!("RTSP server lacks mount point"))?;
+                        for path in paths.iter() {
+                            log::debug!("Path: {}", path);
+                            mounts.add_factory(path, dummy_factory.clone());
+                        }
+                        log::debug!("{}: Preparing at {}", name, paths.join(", "));
+
+                        let kind = zoom_kind.expect("Guarded by the select");
+                        supported_streams_4.wait_for(|ss| ss.contains(&kind)).await?;
+                        stream_main(camera.stream(kind).await?, camera.clone(), rtsp, &permitted_users, &paths, digital_zoom.as_ref(), false).await
+                    }, if zoom_kind.is_some() => v,
+                    v = async {
+                        let name = camera.config().await?.borrow().name.clone();
+                        let paths = vec![
+                            format!("/{name}/h264"),
+                            format!("/{name}/H264"),
+                        ];
+
+                        // Create a dummy factory so that the URL will not return 404 while waiting
+                        // for configuration to compete
+                        let mounts = rtsp
+                            .mount_points()
+                            .ok_or(anyhow!("RTSP server lacks mount point"))?;
+                        for path in paths.iter() {
+                            log::debug!("Path: {}", path);
+                            mounts.add_factory(path, dummy_factory.clone());
+                        }
+                        log::debug!("{}: Preparing at {}", name, paths.join(", "));
+
+                        supported_streams_5.wait_for(|ss| ss.contains(&StreamKind::Main)).await?;
+                        stream_main(camera.stream(StreamKind::Main).await?, camera.clone(), rtsp, &permitted_users, &paths, None, true).await
+                    }, if transcode && active_streams.contains(&StreamKind::Main) => v,
                     else => {
                         // all disabled just wait here until config is changed
                         futures::future::pending().await
@@ -442,3 +481,50 @@ async fn camera_main(camera: NeoInstance, rtsp: &NeoRtspServer) -> Result<()> {
 
     Ok(())
 }
+
+/// The rtsp paths of each stream that is served
+///
+/// These are the `streams` of the config if given else `/{name}/main` and the
+/// like of each stream in `stream`. The best of the streams is also served at `/{name}`
+fn rtsp_paths(config: &CameraConfig) -> HashMap<StreamKind, Vec<String>> {
+    let name = &config.name;
+    let mut paths: HashMap<StreamKind, Vec<String>> = HashMap::new();
+    let root = if config.streams.is_empty() {
+        for kind in config.stream.as_stream_kinds() {
+            let (lower, upper) = match kind {
+                StreamKind::Main => ("main", "Main"),
+                StreamKind::Sub => ("sub", "Sub"),
+                StreamKind::Extern => ("extern", "Extern"),
+            };
+            paths.insert(
+                kind,
+                vec![
+                    format!("/{name}/{lower}"),
+                    format!("/{name}/{upper}"),
+                    format!("/{name}/{lower}Stream"),
+                    format!("/{name}/{upper}Stream"),
+                    format!("/{name}/{upper}stream"),
+                    format!("/{name}/{lower}stream"),
+                ],
+            );
+        }
+        [StreamKind::Main, StreamKind::Sub, StreamKind::Extern]
+            .into_iter()
+            .find(|kind| paths.contains_key(kind))
+    } else {
+        for path in config.streams.iter() {
+            if let Some(kind) = path.stream_kind() {
+                paths
+                    .entry(kind)
+                    .or_default()
+                    .push(format!("/{name}/{}", path.name));
+            }
+        }
+        // The first of the custom paths
+        config.streams.iter().find_map(|path| path.stream_kind())
+    };
+    if let Some(root) = root.and_then(|kind| paths.get_mut(&kind)) {
+        root.push(format!("/{name}"));
+    }
+    paths
+}
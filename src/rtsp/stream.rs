@@ -5,20 +5,35 @@ use gstreamer_rtsp_server::prelude::*;
 use std::collections::{HashMap, HashSet};
 use std::sync::Arc;
 use tokio::{
-    sync::{broadcast::channel as broadcast, watch::channel as watch},
+    sync::{
+        broadcast::channel as broadcast,
+        watch::{channel as watch, Receiver as WatchReceiver},
+    },
     task::JoinSet,
-    time::{sleep, Duration},
+    time::{interval, sleep, Duration},
+};
+use tokio_stream::{
+    wrappers::{BroadcastStream, IntervalStream},
+    Stream, StreamExt,
 };
-use tokio_stream::{wrappers::BroadcastStream, Stream, StreamExt};
 use tokio_util::sync::CancellationToken;
 
-use crate::common::{Permit, StampedData, UseCounter, VidFormat};
+use crate::common::{AudFormat, NeoCamThreadState, Permit, StampedData, UseCounter, VidFormat};
 use crate::{
     common::{NeoInstance, StreamConfig, StreamInstance},
+    config::{
+        AudioMode, CameraConfig, CodecFallback, CodecFallbackConfig, DigitalZoomConfig,
+        LatencyMode, OverlayConfig, PrivacyMaskConfig, ZoomRect,
+    },
     AnyResult,
 };
+use neolink_core::bc_protocol::StreamKind;
 
-use super::{factory::*, gst::NeoRtspServer};
+use super::{
+    backchannel::talk_backchannel,
+    factory::*,
+    gst::{FallbackTargets, NeoRtspServer},
+};
 
 #[derive(Clone)]
 struct PauseAffectors {
@@ -34,9 +49,25 @@ pub(super) async fn stream_main(
     rtsp: &NeoRtspServer,
     users: &HashSet<String>,
     paths: &[String],
+    zoom: Option<&DigitalZoomConfig>,
+    transcode: bool,
 ) -> Result<()> {
     let mut camera_config = camera.config().await?.clone();
     let name = camera_config.borrow().name.clone();
+    let zoom_rect = match zoom {
+        Some(_) => Some(camera.digital_zoom().await?),
+        None => None,
+    };
+
+    let mut curr_overlay;
+    let mut curr_masks;
+    let mut curr_offline;
+    let mut curr_backchannel;
+    let mut curr_fallback;
+    let mut curr_audio;
+    let mut curr_latency;
+    let mut mute = camera.muted().await?;
+    let mut curr_muted;
 
     let mut curr_pause;
     loop {
@@ -62,8 +93,33 @@ pub(super) async fn stream_main(
         }
 
         curr_pause = camera_config.borrow().pause.clone();
+        curr_overlay = camera_config
+            .borrow()
+            .overlay
+            .clone()
+            .filter(|overlay| overlay.applies_to(stream_instance.name));
+        curr_masks = privacy_masks(&camera_config.borrow(), stream_instance.name);
+        curr_offline = offline_source(&camera_config.borrow());
+        curr_backchannel = camera_config.borrow().backchannel;
+        curr_fallback = codec_fallback(
+            &camera_config.borrow(),
+            stream_instance.name,
+            zoom.is_some() || transcode,
+        );
+        curr_muted = mute.borrow_and_update().contains(&stream_instance.name);
+        curr_audio = camera_config.borrow().audio;
+        curr_latency = latency_paths(&camera_config.borrow(), paths);
 
         let last_stream_config = stream_instance.config.borrow().clone();
+        // A muted stream is served as if the camera had no audio
+        let served_stream_config = if curr_muted || curr_audio == AudioMode::Off {
+            StreamConfig {
+                aud_format: AudFormat::None,
+                ..last_stream_config.clone()
+            }
+        } else {
+            last_stream_config.clone()
+        };
         let mut thread_stream_config = stream_instance.config.clone();
 
         let (pause_affector_tx, pause_affector) = watch(PauseAffectors {
@@ -76,12 +132,12 @@ pub(super) async fn stream_main(
         let mut set = JoinSet::<AnyResult<()>>::new();
         // Handles the on off of the stream with the client pause
         let client_counter = UseCounter::new().await;
-        let client_count = client_counter.create_deactivated().await?;
 
         // Client count affector
-        if curr_pause.on_disconnect {
+        if curr_pause.on_client() {
             let thread_name = name.clone();
             let client_count = client_counter.create_deactivated().await?;
+            let linger = Duration::from_secs_f64(curr_pause.linger);
             let thread_pause_affector_tx = pause_affector_tx.clone();
             let cancel = this_loop_cancel.clone();
             set.spawn(async move {
@@ -96,6 +152,16 @@ pub(super) async fn stream_main(
                             });
 
                             client_count.dropped_users().await?;
+                            // Keep streaming a while in case a client comes back
+                            if !linger.is_zero() {
+                                tokio::select! {
+                                    v = client_count.aquired_users() => {
+                                        v?;
+                                        continue;
+                                    }
+                                    _ = sleep(linger) => {}
+                                }
+                            }
                             log::info!("{}: Pausing Client", thread_name);
                             thread_pause_affector_tx.send_modify(|current| {
                                 current.client = false;
@@ -179,7 +245,7 @@ pub(super) async fn stream_main(
             });
         }
 
-        if curr_pause.on_motion || curr_pause.on_disconnect {
+        if curr_pause.on_motion || curr_pause.on_client() {
             // Take over activation
             let cancel = this_loop_cancel.clone();
             let mut client_activator = stream_instance.activator_handle().await;
@@ -192,7 +258,7 @@ pub(super) async fn stream_main(
                     _ = cancel.cancelled() => AnyResult::Ok(()),
                     v = async {
                         while let Some(state) = pause_affector.next().await {
-                            if thread_curr_pause.on_motion && thread_curr_pause.on_disconnect {
+                            if thread_curr_pause.on_motion && thread_curr_pause.on_client() {
                                 if state.client && (state.motion || state.push) {
                                     client_activator.activate().await?;
                                 } else {
@@ -204,7 +270,7 @@ pub(super) async fn stream_main(
                                 } else {
                                     client_activator.deactivate().await?;
                                 }
-                            } else if thread_curr_pause.on_disconnect {
+                            } else if thread_curr_pause.on_client() {
                                 if state.client {
                                     client_activator.activate().await?;
                                 } else {
@@ -221,10 +287,15 @@ pub(super) async fn stream_main(
         }
 
         // This thread jsut keeps it active for 30s after an initial start to build the buffer
+        // unless on demand where the stream only runs for clients
         let cancel = this_loop_cancel.clone();
         let mut init_activator = stream_instance.activator_handle().await;
         let init_camera = camera.clone();
+        let on_demand = curr_pause.on_demand;
         set.spawn(async move {
+            if on_demand {
+                return AnyResult::Ok(());
+            }
             tokio::select! {
                 _ = cancel.cancelled() => AnyResult::Ok(()),
                 v = async {
@@ -259,6 +330,67 @@ pub(super) async fn stream_main(
             }
         });
 
+        // Keeps the overlay text up to date with the battery level
+        let overlay_text = if let Some(overlay) = curr_overlay.as_ref() {
+            let template = overlay.text.clone().unwrap_or_default();
+            let (overlay_text_tx, overlay_text) =
+                watch(expand_overlay_text(&template, &name, None));
+            if template.contains("{battery}") {
+                let cancel = this_loop_cancel.clone();
+                let thread_name = name.clone();
+                let thread_camera = camera.clone();
+                set.spawn(async move {
+                    tokio::select! {
+                        _ = cancel.cancelled() => AnyResult::Ok(()),
+                        v = async {
+                            let mut i = IntervalStream::new(interval(Duration::from_secs(60)));
+                            while i.next().await.is_some() {
+                                let battery = thread_camera
+                                    .run_passive_task(|cam| {
+                                        Box::pin(async move { Ok(cam.battery_info().await?) })
+                                    })
+                                    .await
+                                    .ok()
+                                    .map(|info| info.battery_percent);
+                                overlay_text_tx.send_replace(expand_overlay_text(
+                                    &template,
+                                    &thread_name,
+                                    battery,
+                                ));
+                            }
+                            AnyResult::Ok(())
+                        } => v,
+                    }
+                });
+            }
+            Some(overlay_text)
+        } else {
+            None
+        };
+
+        // Each latency has its own factory
+        let mut runs = vec![];
+        for (latency, latency_paths) in curr_latency.iter() {
+            let client_count = client_counter.create_deactivated().await?;
+            runs.push(Box::pin(stream_run(
+                &name,
+                &stream_instance,
+                rtsp,
+                &served_stream_config,
+                users,
+                latency_paths,
+                client_count,
+                zoom.zip(zoom_rect.clone()),
+                curr_overlay.as_ref().zip(overlay_text.clone()),
+                &curr_masks,
+                curr_backchannel.then_some(&camera),
+                transcode,
+                curr_fallback.as_ref(),
+                curr_audio,
+                *latency,
+            )));
+        }
+
         // This runs the actual stream.
         // The select will restart if the stream's config updates
         break tokio::select! {
@@ -269,18 +401,29 @@ pub(super) async fn stream_main(
                 log::trace!("    From {:?} to {:?}", last_stream_config, v.clone());
                 continue;
             },
-            v = camera_config.wait_for(|new_conf| new_conf.pause != curr_pause ) => {
+            v = camera_config.wait_for(|new_conf| new_conf.pause != curr_pause || new_conf.overlay.as_ref().filter(|overlay| overlay.applies_to(stream_instance.name)) != curr_overlay.as_ref() || privacy_masks(new_conf, stream_instance.name) != curr_masks || offline_source(new_conf) != curr_offline || new_conf.backchannel != curr_backchannel || codec_fallback(new_conf, stream_instance.name, zoom.is_some() || transcode) != curr_fallback || new_conf.audio != curr_audio || latency_paths(new_conf, paths) != curr_latency) => {
+                v?;
+                // If pause, overlay, mask, offline, backchannel, fallback, audio or latency config changes restart
+                log::info!("{}: Pause, Overlay, Privacy Mask, Offline, Backchannel, Codec Fallback, Audio or Latency Configuration Changed. Reloading Streams", &name);
+                continue;
+            },
+            v = mute.wait_for(|muted| muted.contains(&stream_instance.name) != curr_muted) => {
+                v?;
+                log::info!("{}: Audio {}. Reloading Streams", &name, if curr_muted { "Unmuted" } else { "Muted" });
+                continue;
+            },
+            v = offline_slate(&name, &camera, rtsp, users, paths, curr_offline.clone()) => {
                 v?;
-                // If pause config changes restart
-                log::info!("{}: Pause Configuration Changed. Reloading Streams", &name);
+                // Camera is back so mount the real stream again
                 continue;
             },
-            v = stream_run(&name, &stream_instance, rtsp, &last_stream_config, users, paths, client_count) => v,
+            (v, _, _) = futures::future::select_all(runs) => v,
         };
     }
 }
 
 /// This handles the stream itself by creating the factory and pushing messages into it
+#[allow(clippy::too_many_arguments)]
 async fn stream_run(
     name: &str,
     stream_instance: &StreamInstance,
@@ -289,6 +432,14 @@ async fn stream_run(
     users: &HashSet<String>,
     paths: &[String],
     client_count: Permit,
+    zoom: Option<(&DigitalZoomConfig, WatchReceiver<ZoomRect>)>,
+    overlay: Option<(&OverlayConfig, WatchReceiver<String>)>,
+    masks: &[PrivacyMaskConfig],
+    backchannel: Option<&NeoInstance>,
+    transcode: bool,
+    fallback: Option<&CodecFallbackConfig>,
+    audio: AudioMode,
+    latency: LatencyMode,
 ) -> AnyResult<()> {
     let vidstream = stream_instance.vid.resubscribe();
     let audstream = stream_instance.aud.resubscribe();
@@ -300,7 +451,17 @@ async fn stream_run(
         .mount_points()
         .ok_or(anyhow!("RTSP server lacks mount point"))?;
     // Create the factory
-    let (factory, mut client_rx) = make_factory(stream_config).await?;
+    let (factory, mut client_rx) = make_factory(
+        stream_config,
+        zoom.as_ref().map(|(config, _)| *config),
+        overlay.as_ref().map(|(config, _)| *config),
+        masks,
+        backchannel.is_some(),
+        transcode,
+        audio,
+        latency,
+    )
+    .await?;
 
     factory.add_permitted_roles(users);
 
@@ -310,6 +471,12 @@ async fn stream_run(
     }
     log::info!("{}: Available at {}", name, paths.join(", "));
 
+    // Only HEVC is known to be unplayable by some clients
+    let fallback = fallback
+        .filter(|_| matches!(stream_config.vid_format, VidFormat::H265))
+        .map(|fallback| fallback_targets(name, fallback));
+    rtsp.set_fallback(paths, fallback);
+
     let stream_cancel = CancellationToken::new();
     let drop_guard = stream_cancel.clone().drop_guard();
     let mut set = JoinSet::new();
@@ -319,6 +486,68 @@ async fn stream_run(
         let vid = client_data.vid.take().map(|data| data.app);
         let aud = client_data.aud.take().map(|data| data.app);
 
+        let reencode = client_data.reencode.take();
+
+        // This thread plays the audio that the client sends back on the camera
+        if let (Some(sink), Some(camera)) = (client_data.backchannel.take(), backchannel) {
+            let camera = camera.clone();
+            let thread_name = name.to_string();
+            let thread_stream_cancel = stream_cancel.clone();
+            set.spawn(async move {
+                tokio::select! {
+                    _ = thread_stream_cancel.cancelled() => {},
+                    v = talk_backchannel(&camera, sink) => {
+                        if let Err(e) = v {
+                            log::warn!("{}: Backchannel audio failed: {:?}", thread_name, e);
+                        }
+                    },
+                }
+                AnyResult::Ok(())
+            });
+        }
+
+        // This thread keeps the digital zoom crop in sync with the requested rect
+        if let (Some(crop), Some((_, zoom_rect))) = (
+            reencode.as_ref().and_then(|elements| elements.crop.clone()),
+            zoom.as_ref(),
+        ) {
+            let mut zoom_rect = zoom_rect.clone();
+            let thread_stream_cancel = stream_cancel.clone();
+            let resolution = stream_config.resolution;
+            set.spawn(async move {
+                tokio::select! {
+                    _ = thread_stream_cancel.cancelled() => AnyResult::Ok(()),
+                    v = async {
+                        loop {
+                            apply_zoom(&crop, &zoom_rect.borrow_and_update(), resolution);
+                            zoom_rect.changed().await?;
+                        }
+                    } => v,
+                }
+            });
+        }
+
+        // This thread keeps the overlay text in sync
+        if let (Some(text), Some((_, overlay_text))) = (
+            reencode.as_ref().and_then(|elements| elements.text.clone()),
+            overlay.as_ref(),
+        ) {
+            let mut overlay_text = overlay_text.clone();
+            let thread_stream_cancel = stream_cancel.clone();
+            set.spawn(async move {
+                tokio::select! {
+                    _ = thread_stream_cancel.cancelled() => AnyResult::Ok(()),
+                    v = async {
+                        loop {
+                            let value = overlay_text.borrow_and_update().clone();
+                            text.set_property("text", value);
+                            overlay_text.changed().await?;
+                        }
+                    } => v,
+                }
+            });
+        }
+
         // This is the data that gets sent to gstreamer thread
         // It represents the combination of the camera stream and the appsrc seek messages
         // At 30fps for 15s with audio you need about 900 frames
@@ -338,8 +567,14 @@ async fn stream_run(
                     // Send Initial
                     {
                         let history = thread_vid_history.borrow();
+                        // A realtime client starts at the last keyframe rather than replaying the buffer
+                        let start = if latency.replay_buffer() {
+                            0
+                        } else {
+                            history.iter().rposition(|data| data.keyframe).unwrap_or(history.len())
+                        };
                         // let last_ts = history.back().map(|s| s.ts);
-                        for data in history.iter() {
+                        for data in history.iter().skip(start) {
                             thread_vid_data_tx.send(
                                 // StampedData {
                                 //     keyframe: data.keyframe,
@@ -371,15 +606,27 @@ async fn stream_run(
         let thread_stream_cancel = stream_cancel.clone();
         let thread_aud_data_tx = aud_data_tx.clone();
         let thread_aud_history = aud_history.clone();
+        let thread_vid_history = vid_history.clone();
         set.spawn(async move {
             let r = tokio::select! {
                 _ = thread_stream_cancel.cancelled() => AnyResult::Ok(()),
                 v = async {
                     // Send Initial
                     {
+                        // The audio of the frames that the video starts from
+                        let start = if latency.replay_buffer() {
+                            Duration::ZERO
+                        } else {
+                            thread_vid_history
+                                .borrow()
+                                .iter()
+                                .rev()
+                                .find(|data| data.keyframe)
+                                .map_or(Duration::MAX, |data| data.ts)
+                        };
                         let history = thread_aud_history.borrow();
                         // let last_ts = history.back().map(|s| s.ts);
-                        for data in history.iter() {
+                        for data in history.iter().filter(|data| data.ts >= start) {
                             thread_aud_data_tx.send(
                                 // StampedData {
                                 //     keyframe: data.keyframe,
@@ -441,7 +688,8 @@ async fn stream_run(
                             // ),
                             thread_format,
                         ),
-                        &thread_vid
+                        &thread_vid,
+                        latency.jitter_buffer(),
                     ) => {
                         v
                     },
@@ -473,7 +721,8 @@ async fn stream_run(
                                 ts_rx,
                             ),
                             aud_framerate),
-                        &thread_aud) => {
+                        &thread_aud,
+                        latency.jitter_buffer()) => {
                         v
                     },
                 };
@@ -488,10 +737,126 @@ async fn stream_run(
     stream_cancel.cancel();
     drop(drop_guard);
     while set.join_next().await.is_some() {}
+    rtsp.set_fallback(paths, None);
     log::trace!("Stream done");
     AnyResult::Ok(())
 }
 
+/// Waits for the camera to lose its connection then keeps the paths alive
+/// with an offline slate until it reconnects, never returns if there is no slate
+///
+/// Intentional disconnects such as the idle disconnect are ignored so that
+/// a new client can still wake the camera
+async fn offline_slate(
+    name: &str,
+    camera: &NeoInstance,
+    rtsp: &NeoRtspServer,
+    users: &HashSet<String>,
+    paths: &[String],
+    source: Option<SlateSource>,
+) -> AnyResult<()> {
+    let Some(source) = source else {
+        return futures::future::pending().await;
+    };
+    let mut camera_watch = camera.camera();
+    loop {
+        camera_watch.wait_for(|cam| cam.upgrade().is_none()).await?;
+        if matches!(camera.get_state().await?, NeoCamThreadState::Connected) {
+            break;
+        }
+        camera_watch.wait_for(|cam| cam.upgrade().is_some()).await?;
+    }
+
+    let mounts = rtsp
+        .mount_points()
+        .ok_or(anyhow!("RTSP server lacks mount point"))?;
+    let factory = make_offline_factory(source).await?;
+    factory.add_permitted_roles(users);
+    for path in paths.iter() {
+        mounts.add_factory(path, factory.clone());
+    }
+    log::info!("{}: Camera offline, serving the offline slate", name);
+
+    camera_watch.wait_for(|cam| cam.upgrade().is_some()).await?;
+    log::info!("{}: Camera back online. Reloading Streams", name);
+    Ok(())
+}
+
+/// The source of the offline slate or None if it is disabled
+/// The paths grouped by their `latency`, the custom paths of `streams` can
+/// set their own and the rest use the camera's
+fn latency_paths(config: &CameraConfig, paths: &[String]) -> Vec<(LatencyMode, Vec<String>)> {
+    let mut groups: Vec<(LatencyMode, Vec<String>)> = vec![];
+    for path in paths.iter() {
+        let latency = config
+            .streams
+            .iter()
+            .find(|custom| format!("/{}/{}", config.name, custom.name) == *path)
+            .and_then(|custom| custom.latency)
+            .unwrap_or(config.latency);
+        match groups.iter_mut().find(|(group, _)| *group == latency) {
+            Some((_, group_paths)) => group_paths.push(path.clone()),
+            None => groups.push((latency, vec![path.clone()])),
+        }
+    }
+    groups
+}
+
+fn offline_source(config: &CameraConfig) -> Option<SlateSource> {
+    config
+        .offline_slate
+        .then(|| SlateSource::from_config(config))
+}
+
+/// The privacy masks of the camera that are drawn on this stream
+fn privacy_masks(config: &CameraConfig, stream: StreamKind) -> Vec<PrivacyMaskConfig> {
+    config
+        .privacy_masks
+        .iter()
+        .filter(|mask| mask.applies_to(stream))
+        .cloned()
+        .collect()
+}
+
+/// The codec fallback of the stream, only the main stream as the camera sends it has one
+fn codec_fallback(
+    config: &CameraConfig,
+    stream: StreamKind,
+    reencoded: bool,
+) -> Option<CodecFallbackConfig> {
+    config
+        .codec_fallback
+        .clone()
+        .filter(|_| stream == StreamKind::Main && !reencoded)
+}
+
+/// The paths that the clients of the main stream fall back to
+fn fallback_targets(name: &str, config: &CodecFallbackConfig) -> FallbackTargets {
+    FallbackTargets {
+        targets: config
+            .priority
+            .iter()
+            .map(|codec| match codec {
+                CodecFallback::H265 => None,
+                CodecFallback::H264 => Some(format!("/{name}/h264")),
+                CodecFallback::Sub => Some(format!("/{name}/sub")),
+            })
+            .collect(),
+        failures: config.failures,
+        min_play: Duration::from_secs(config.min_play),
+    }
+}
+
+/// Fill in the placeholders of the overlay text
+fn expand_overlay_text(template: &str, name: &str, battery: Option<u32>) -> String {
+    let battery = battery
+        .map(|battery| format!("{battery}%"))
+        .unwrap_or_default();
+    template
+        .replace("{name}", name)
+        .replace("{battery}", &battery)
+}
+
 fn check_live(app: &AppSrc) -> Result<()> {
     app.bus().ok_or(anyhow!("App source is closed"))?;
     app.pads()
@@ -788,15 +1153,23 @@ fn repeat_keyframe<E, T: Stream<Item = Result<StampedData, E>> + Unpin>(
 }
 
 /// Takes a stream and sends it to an appsrc
+///
+/// With a `jitter_buffer` the appsrc waits for its buffer to fill before
+/// playing and pauses when it runs low, otherwise it plays straight away
 async fn send_to_appsrc<E, T: Stream<Item = Result<StampedData, E>> + Unpin>(
     mut stream: T,
     appsrc: &AppSrc,
+    jitter_buffer: bool,
 ) -> AnyResult<()> {
     let mut ts_0 = Duration::MAX;
     let mut wait_for_iframe = true;
     let mut pools: HashMap<usize, gstreamer::BufferPool> = Default::default();
-    let mut paused = true;
-    appsrc.set_state(gstreamer::State::Paused).unwrap();
+    let mut paused = jitter_buffer;
+    if jitter_buffer {
+        appsrc.set_state(gstreamer::State::Paused).unwrap();
+    } else {
+        appsrc.set_state(gstreamer::State::Playing).unwrap();
+    }
 
     let (tx, mut rx) = tokio::sync::mpsc::channel::<StampedData>(2000);
 
@@ -866,10 +1239,17 @@ async fn send_to_appsrc<E, T: Stream<Item = Result<StampedData, E>> + Unpin>(
                     }
                     Err(e) => Err(anyhow!("Error in streaming: {e:?}")),
                 }?;
-                if appsrc.current_level_bytes() >= appsrc.max_bytes() * 2 / 3 && paused {
+                // Without the jitter buffer it is always playing
+                if jitter_buffer
+                    && paused
+                    && appsrc.current_level_bytes() >= appsrc.max_bytes() * 2 / 3
+                {
                     appsrc.set_state(gstreamer::State::Playing).unwrap();
                     paused = false;
-                } else if appsrc.current_level_bytes() <= appsrc.max_bytes() / 3 && !paused {
+                } else if jitter_buffer
+                    && !paused
+                    && appsrc.current_level_bytes() <= appsrc.max_bytes() / 3
+                {
                     appsrc.set_state(gstreamer::State::Paused).unwrap();
                     paused = true;
                 }
@@ -0,0 +1,20 @@
+use clap::Parser;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+/// The selftest command tries every message neolink knows against a camera and reports which work
+#[derive(Parser, Debug)]
+pub struct Opt {
+    /// The name of the camera to test. Must be a name in the config
+    pub camera: String,
+    /// Also send the settings messages. Each is sent back with the value just read from
+    /// the camera so nothing is changed
+    #[arg(long)]
+    pub write: bool,
+    /// How long in seconds to wait for each message
+    #[arg(short, long, default_value_t = 15)]
+    pub timeout: u64,
+    /// Save the report as JSON to this path, for sharing in a compatibility report
+    #[arg(short, long, value_parser = PathBuf::from_str)]
+    pub output: Option<PathBuf>,
+}
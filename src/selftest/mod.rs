@@ -0,0 +1,516 @@
+///
+/// # Neolink Selftest
+///
+/// This module handles the selftest subcommand
+///
+/// Every message that neolink implements is sent to the camera in turn
+/// and the result is printed as a table. By default only messages that
+/// read from the camera are sent, with `--write` the settings are also
+/// written back unchanged. The report can be saved as JSON and shared
+/// so that the supported models and firmwares can be collected
+///
+/// # Usage
+///
+/// ```bash
+/// neolink selftest --config=config.toml CameraName
+/// # Also write the settings back and save the report
+/// neolink selftest --config=config.toml --write --output=report.json CameraName
+/// ```
+///
+use anyhow::{Context, Result};
+use neolink_core::bc_protocol::{BcCamera, StreamKind};
+use neolink_core::bcmedia::model::BcMedia;
+use serde::Serialize;
+use tokio::time::{timeout, Duration};
+
+mod cmdline;
+
+use crate::{common::NeoInstance, common::NeoReactor, AnyResult};
+pub(crate) use cmdline::Opt;
+
+#[derive(Serialize)]
+struct Report {
+    neolink: String,
+    model: Option<String>,
+    hardware: Option<String>,
+    firmware: Option<String>,
+    checks: Vec<Check>,
+}
+
+#[derive(Serialize)]
+struct Check {
+    name: &'static str,
+    write: bool,
+    result: Outcome,
+    detail: String,
+}
+
+#[derive(Serialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+enum Outcome {
+    Ok,
+    /// The camera replied that it cannot do this
+    Unsupported,
+    /// The camera replied with something neolink does not understand
+    Unexpected,
+    Timeout,
+    Error,
+}
+
+impl std::fmt::Display for Outcome {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let s = match self {
+            Outcome::Ok => "ok",
+            Outcome::Unsupported => "unsupported",
+            Outcome::Unexpected => "unexpected",
+            Outcome::Timeout => "timeout",
+            Outcome::Error => "error",
+        };
+        f.pad(s)
+    }
+}
+
+/// Entry point for the selftest subcommand
+///
+/// Opt is the command line options
+pub(crate) async fn main(opt: Opt, reactor: NeoReactor) -> Result<()> {
+    let camera = reactor.get(&opt.camera).await?;
+    let wait = Duration::from_secs(opt.timeout);
+
+    let mut report = Report {
+        neolink: env!("CARGO_PKG_VERSION").to_string(),
+        model: None,
+        hardware: None,
+        firmware: None,
+        checks: vec![],
+    };
+
+    match timeout(
+        wait,
+        camera.run_task(|cam| Box::pin(async move { Ok(cam.version().await?) })),
+    )
+    .await
+    {
+        Ok(Ok(version)) => {
+            report.model = version.model.or(Some(version.name));
+            report.hardware = Some(version.hardwareVersion);
+            report.firmware = Some(version.firmwareVersion);
+        }
+        Ok(Err(e)) => log::warn!("{}: Unable to get the version: {:?}", opt.camera, e),
+        Err(_) => log::warn!("{}: Timed out getting the version", opt.camera),
+    }
+
+    let checks = &mut report.checks;
+    checks.push(
+        check(&camera, "Version", wait, |cam| {
+            Box::pin(async move { Ok(cam.version().await?.firmwareVersion) })
+        })
+        .await,
+    );
+    checks.push(
+        check(&camera, "Ping", wait, |cam| {
+            Box::pin(async move {
+                cam.ping().await?;
+                Ok(String::new())
+            })
+        })
+        .await,
+    );
+    checks.push(
+        check(&camera, "Uid", wait, |cam| {
+            Box::pin(async move { Ok(cam.uid().await?) })
+        })
+        .await,
+    );
+    checks.push(
+        check(&camera, "AbilityInfo", wait, |cam| {
+            Box::pin(async move {
+                cam.get_abilityinfo().await?;
+                Ok(String::new())
+            })
+        })
+        .await,
+    );
+    checks.push(
+        check(&camera, "Support", wait, |cam| {
+            Box::pin(async move {
+                cam.get_support().await?;
+                Ok(String::new())
+            })
+        })
+        .await,
+    );
+    checks.push(
+        check(&camera, "LinkType", wait, |cam| {
+            Box::pin(async move { Ok(cam.get_linktype().await?.link_type) })
+        })
+        .await,
+    );
+    checks.push(
+        check(&camera, "StreamInfoList", wait, |cam| {
+            Box::pin(async move {
+                let info = cam.get_stream_info().await?;
+                Ok(info
+                    .stream_infos
+                    .iter()
+                    .flat_map(|info| info.encode_tables.iter())
+                    .map(|table| {
+                        format!(
+                            "{} {}x{}",
+                            table.name, table.resolution.width, table.resolution.height
+                        )
+                    })
+                    .collect::<Vec<_>>()
+                    .join(", "))
+            })
+        })
+        .await,
+    );
+    checks.push(
+        check(&camera, "Time", wait, |cam| {
+            Box::pin(async move {
+                Ok(cam
+                    .get_time()
+                    .await?
+                    .map(|time| time.to_string())
+                    .unwrap_or_else(|| "not set".to_string()))
+            })
+        })
+        .await,
+    );
+    checks.push(
+        check(&camera, "Osd", wait, |cam| {
+            Box::pin(async move { Ok(cam.get_channel_name().await?) })
+        })
+        .await,
+    );
+    checks.push(
+        check(&camera, "WifiSignal", wait, |cam| {
+            Box::pin(async move { Ok(format!("{}", cam.get_wifi_signal().await?.signal)) })
+        })
+        .await,
+    );
+    checks.push(
+        check(&camera, "BatteryInfo", wait, |cam| {
+            Box::pin(async move { Ok(format!("{}%", cam.battery_info().await?.battery_percent)) })
+        })
+        .await,
+    );
+    checks.push(
+        check(&camera, "PirState", wait, |cam| {
+            Box::pin(async move { Ok(format!("enable {}", cam.get_pirstate().await?.enable)) })
+        })
+        .await,
+    );
+    checks.push(
+        check(&camera, "LedState", wait, |cam| {
+            Box::pin(async move { Ok(cam.get_ledstate().await?.state) })
+        })
+        .await,
+    );
+    checks.push(
+        check(&camera, "Record", wait, |cam| {
+            Box::pin(async move {
+                cam.get_record().await?;
+                Ok(String::new())
+            })
+        })
+        .await,
+    );
+    checks.push(
+        check(&camera, "Shelter", wait, |cam| {
+            Box::pin(async move { Ok(format!("enable {}", cam.get_shelter().await?.enable)) })
+        })
+        .await,
+    );
+    checks.push(
+        check(&camera, "PtzPreset", wait, |cam| {
+            Box::pin(async move {
+                Ok(format!(
+                    "{} presets",
+                    cam.get_ptz_preset().await?.preset_list.preset.len()
+                ))
+            })
+        })
+        .await,
+    );
+    checks.push(
+        check(&camera, "PtzZoomFocus", wait, |cam| {
+            Box::pin(async move {
+                cam.get_zoom().await?;
+                Ok(String::new())
+            })
+        })
+        .await,
+    );
+    checks.push(
+        check(&camera, "FloodlightTask", wait, |cam| {
+            Box::pin(async move {
+                Ok(format!(
+                    "enable {}",
+                    cam.get_flightlight_tasks().await?.enable
+                ))
+            })
+        })
+        .await,
+    );
+    checks.push(
+        check(&camera, "ServerPort", wait, |cam| {
+            Box::pin(async move {
+                cam.get_serverport().await?;
+                Ok(String::new())
+            })
+        })
+        .await,
+    );
+    checks.push(
+        check(&camera, "HttpPort", wait, |cam| {
+            Box::pin(async move {
+                cam.get_http().await?;
+                Ok(String::new())
+            })
+        })
+        .await,
+    );
+    checks.push(
+        check(&camera, "HttpsPort", wait, |cam| {
+            Box::pin(async move {
+                cam.get_https().await?;
+                Ok(String::new())
+            })
+        })
+        .await,
+    );
+    checks.push(
+        check(&camera, "RtspPort", wait, |cam| {
+            Box::pin(async move {
+                cam.get_rtsp().await?;
+                Ok(String::new())
+            })
+        })
+        .await,
+    );
+    checks.push(
+        check(&camera, "RtmpPort", wait, |cam| {
+            Box::pin(async move {
+                cam.get_rtmp().await?;
+                Ok(String::new())
+            })
+        })
+        .await,
+    );
+    checks.push(
+        check(&camera, "OnvifPort", wait, |cam| {
+            Box::pin(async move {
+                cam.get_onvif().await?;
+                Ok(String::new())
+            })
+        })
+        .await,
+    );
+    checks.push(
+        check(&camera, "Snap", wait, |cam| {
+            Box::pin(async move { Ok(format!("{} bytes", cam.get_snapshot().await?.len())) })
+        })
+        .await,
+    );
+    checks.push(
+        check(&camera, "Motion", wait, |cam| {
+            Box::pin(async move {
+                cam.listen_on_motion().await?;
+                Ok(String::new())
+            })
+        })
+        .await,
+    );
+    checks.push(
+        check(&camera, "Video main", wait, |cam| {
+            Box::pin(async move { first_frame(cam, StreamKind::Main).await })
+        })
+        .await,
+    );
+    checks.push(
+        check(&camera, "Video sub", wait, |cam| {
+            Box::pin(async move { first_frame(cam, StreamKind::Sub).await })
+        })
+        .await,
+    );
+    checks.push(
+        check(&camera, "Video extern", wait, |cam| {
+            Box::pin(async move { first_frame(cam, StreamKind::Extern).await })
+        })
+        .await,
+    );
+
+    if opt.write {
+        // Each setting is written back exactly as it was read
+        checks.push(
+            write_check(&camera, "LedState", wait, |cam| {
+                Box::pin(async move {
+                    cam.set_ledstate(cam.get_ledstate().await?).await?;
+                    Ok(String::new())
+                })
+            })
+            .await,
+        );
+        checks.push(
+            write_check(&camera, "PirState", wait, |cam| {
+                Box::pin(async move {
+                    cam.set_pirstate(cam.get_pirstate().await?).await?;
+                    Ok(String::new())
+                })
+            })
+            .await,
+        );
+        checks.push(
+            write_check(&camera, "Record", wait, |cam| {
+                Box::pin(async move {
+                    cam.set_record(cam.get_record().await?).await?;
+                    Ok(String::new())
+                })
+            })
+            .await,
+        );
+        checks.push(
+            write_check(&camera, "Shelter", wait, |cam| {
+                Box::pin(async move {
+                    cam.set_shelter(cam.get_shelter().await?).await?;
+                    Ok(String::new())
+                })
+            })
+            .await,
+        );
+        checks.push(
+            write_check(&camera, "FloodlightTask", wait, |cam| {
+                Box::pin(async move {
+                    cam.set_flightlight_tasks(cam.get_flightlight_tasks().await?)
+                        .await?;
+                    Ok(String::new())
+                })
+            })
+            .await,
+        );
+    }
+
+    print_report(&opt.camera, &report);
+    if let Some(output) = opt.output.as_ref() {
+        tokio::fs::write(output, serde_json::to_string_pretty(&report)?)
+            .await
+            .with_context(|| format!("Unable to save the report to {:?}", output))?;
+        println!("Report saved to {:?}", output);
+    }
+
+    Ok(())
+}
+
+async fn check<F>(camera: &NeoInstance, name: &'static str, wait: Duration, task: F) -> Check
+where
+    F: for<'a> Fn(
+        &'a BcCamera,
+    ) -> std::pin::Pin<
+        Box<dyn futures::Future<Output = AnyResult<String>> + Send + 'a>,
+    >,
+{
+    run_check(camera, name, false, wait, task).await
+}
+
+async fn write_check<F>(camera: &NeoInstance, name: &'static str, wait: Duration, task: F) -> Check
+where
+    F: for<'a> Fn(
+        &'a BcCamera,
+    ) -> std::pin::Pin<
+        Box<dyn futures::Future<Output = AnyResult<String>> + Send + 'a>,
+    >,
+{
+    run_check(camera, name, true, wait, task).await
+}
+
+async fn run_check<F>(
+    camera: &NeoInstance,
+    name: &'static str,
+    write: bool,
+    wait: Duration,
+    task: F,
+) -> Check
+where
+    F: for<'a> Fn(
+        &'a BcCamera,
+    ) -> std::pin::Pin<
+        Box<dyn futures::Future<Output = AnyResult<String>> + Send + 'a>,
+    >,
+{
+    log::debug!("Selftest: {}", name);
+    let (result, detail) = match timeout(wait, camera.run_task(task)).await {
+        Ok(Ok(detail)) => (Outcome::Ok, detail),
+        Ok(Err(e)) => match e.downcast_ref::<neolink_core::Error>() {
+            Some(neolink_core::Error::CameraServiceUnavailable { code, .. }) => {
+                (Outcome::Unsupported, format!("code {}", code))
+            }
+            Some(neolink_core::Error::MissingAbility { .. }) => {
+                (Outcome::Unsupported, format!("{}", e))
+            }
+            Some(
+                neolink_core::Error::UnintelligibleReply { .. }
+                | neolink_core::Error::UnintelligibleXml { .. },
+            ) => (Outcome::Unexpected, format!("{}", e)),
+            _ => (Outcome::Error, format!("{:#}", e)),
+        },
+        Err(_) => (Outcome::Timeout, String::new()),
+    };
+    Check {
+        name,
+        write,
+        result,
+        detail,
+    }
+}
+
+/// Start a stream and wait for its first video frame
+async fn first_frame(cam: &BcCamera, stream: StreamKind) -> AnyResult<String> {
+    let mut data = cam.start_video(stream, 0, false).await?;
+    loop {
+        match data.get_data().await?? {
+            BcMedia::InfoV1(info) => {
+                return Ok(format!("{}x{}", info.video_width, info.video_height))
+            }
+            BcMedia::InfoV2(info) => {
+                return Ok(format!("{}x{}", info.video_width, info.video_height))
+            }
+            BcMedia::Iframe(_) | BcMedia::Pframe(_) => return Ok(String::new()),
+            _ => {}
+        }
+    }
+}
+
+fn print_report(camera: &str, report: &Report) {
+    println!("Camera:   {}", camera);
+    println!("Model:    {}", report.model.as_deref().unwrap_or("-"));
+    println!("Hardware: {}", report.hardware.as_deref().unwrap_or("-"));
+    println!("Firmware: {}", report.firmware.as_deref().unwrap_or("-"));
+    println!("Neolink:  {}", report.neolink);
+    println!();
+
+    let width = report
+        .checks
+        .iter()
+        .map(|check| check.name.len() + if check.write { 8 } else { 0 })
+        .max()
+        .unwrap_or(0);
+    println!("{:<width$}  {:<11}  Detail", "Message", "Result");
+    for check in report.checks.iter() {
+        let name = if check.write {
+            format!("{} (write)", check.name)
+        } else {
+            check.name.to_string()
+        };
+        println!("{:<width$}  {:<11}  {}", name, check.result, check.detail);
+    }
+
+    let passed = report
+        .checks
+        .iter()
+        .filter(|check| check.result == Outcome::Ok)
+        .count();
+    println!();
+    println!("{} of {} messages worked", passed, report.checks.len());
+}
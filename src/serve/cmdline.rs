@@ -0,0 +1,8 @@
+use clap::Parser;
+
+/// The serve command runs rtsp, mqtt and the other services of the config together
+///
+/// Which services run is set by the `[serve]` section of the config. They all
+/// share one connection to each camera
+#[derive(Parser, Debug)]
+pub struct Opt {}
@@ -0,0 +1,148 @@
+///
+/// # Neolink Serve
+///
+/// This module runs the long running commands together in one process from
+/// one config, so that a deployment only needs one service rather than one
+/// each for `neolink rtsp`, `neolink mqtt` and the rest
+///
+/// The services all share one login to each camera. If any of them stops
+/// then neolink exits so that the service manager can restart it
+///
+/// # Usage
+///
+/// ```bash
+/// neolink serve --config=config.toml
+/// ```
+///
+/// # Example Config
+///
+/// ```toml
+/// [serve]
+/// rtsp = true # Serve the cameras over rtsp
+/// mqtt = true # Only when there is an [mqtt] section
+/// record = true # Only the cameras with a [cameras.record] section
+/// onvif = false
+/// http = false
+/// webrtc = false
+/// hls = false
+/// timelapse = true # Only the cameras with a [cameras.timelapse] section
+/// ```
+///
+use anyhow::{anyhow, Result};
+use futures::future::{select_all, LocalBoxFuture};
+use log::*;
+
+mod cmdline;
+
+use crate::{common::NeoReactor, http, mqtt, onvif, AnyResult};
+pub(crate) use cmdline::Opt;
+
+/// Entry point for the serve subcommand
+///
+/// Opt is the command line options
+pub(crate) async fn main(_opt: Opt, reactor: NeoReactor) -> Result<()> {
+    let config = reactor.config().await?.borrow().clone();
+    let serve = &config.serve;
+
+    let mut services: Vec<(&'static str, LocalBoxFuture<'static, AnyResult<()>>)> = vec![];
+    if serve.rtsp {
+        #[cfg(feature = "gstreamer")]
+        services.push((
+            "RTSP",
+            Box::pin(crate::rtsp::main(crate::rtsp::Opt {}, reactor.clone())),
+        ));
+        #[cfg(not(feature = "gstreamer"))]
+        warn!("Serve: This neolink was built without gstreamer so it has no rtsp server");
+    }
+    if serve.mqtt && config.mqtt.is_some() {
+        let opt = mqtt::Opt {
+            dry_run: false,
+            script: None,
+            repeat: false,
+        };
+        services.push(("MQTT", Box::pin(mqtt::main(opt, reactor.clone()))));
+    }
+    if serve.record
+        && config
+            .cameras
+            .iter()
+            .any(|camera| camera.enabled && camera.record.is_some())
+    {
+        #[cfg(feature = "gstreamer")]
+        services.push((
+            "Record",
+            Box::pin(crate::record::main(
+                crate::record::Opt { cameras: vec![] },
+                reactor.clone(),
+            )),
+        ));
+        #[cfg(not(feature = "gstreamer"))]
+        warn!("Serve: This neolink was built without gstreamer so it cannot record");
+    }
+    if serve.onvif {
+        services.push((
+            "ONVIF",
+            Box::pin(onvif::main(onvif::Opt {}, reactor.clone())),
+        ));
+    }
+    if serve.http {
+        services.push(("HTTP", Box::pin(http::main(http::Opt {}, reactor.clone()))));
+    }
+    if serve.webrtc {
+        #[cfg(feature = "gstreamer")]
+        services.push((
+            "WebRTC",
+            Box::pin(crate::webrtc::main(crate::webrtc::Opt {}, reactor.clone())),
+        ));
+        #[cfg(not(feature = "gstreamer"))]
+        warn!("Serve: This neolink was built without gstreamer so it has no webrtc server");
+    }
+    if serve.hls {
+        #[cfg(feature = "gstreamer")]
+        services.push((
+            "HLS",
+            Box::pin(crate::hls::main(crate::hls::Opt {}, reactor.clone())),
+        ));
+        #[cfg(not(feature = "gstreamer"))]
+        warn!("Serve: This neolink was built without gstreamer so it has no hls server");
+    }
+    if serve.timelapse
+        && config
+            .cameras
+            .iter()
+            .any(|camera| camera.enabled && camera.timelapse.is_some())
+    {
+        #[cfg(feature = "gstreamer")]
+        services.push((
+            "Timelapse",
+            Box::pin(crate::timelapse::main(
+                crate::timelapse::Opt { cameras: vec![] },
+                reactor.clone(),
+            )),
+        ));
+        #[cfg(not(feature = "gstreamer"))]
+        warn!("Serve: This neolink was built without gstreamer so it cannot take a timelapse");
+    }
+
+    if services.is_empty() {
+        return Err(anyhow!(
+            "Nothing to serve, enable a service in the [serve] section of the config"
+        ));
+    }
+    info!(
+        "Serve: Running {}",
+        services
+            .iter()
+            .map(|(name, _)| *name)
+            .collect::<Vec<_>>()
+            .join(", ")
+    );
+
+    let (names, services): (Vec<_>, Vec<_>) = services.into_iter().unzip();
+    // The services run until neolink exits, so any that ends takes the rest with it
+    let (result, index, _) = select_all(services).await;
+    match result {
+        Ok(()) => Err(anyhow!("{} stopped", names[index])),
+        Err(e) => Err(e.context(format!("{} failed", names[index]))),
+    }
+}
@@ -0,0 +1,54 @@
+///
+/// # Neolink Siren
+///
+/// This module handles the siren of the cameras that have one
+///
+///
+/// # Usage
+///
+/// ```bash
+/// # To sound the siren once
+/// neolink siren --config=config.toml CameraName
+/// # Or three times
+/// neolink siren --config=config.toml CameraName --times=3
+/// # To sound it until it is turned off
+/// neolink siren --config=config.toml CameraName on
+/// neolink siren --config=config.toml CameraName off
+/// ```
+///
+use anyhow::{Context, Result};
+
+mod cmdline;
+
+use crate::common::{audit, NeoReactor};
+pub(crate) use cmdline::Opt;
+
+/// Entry point for the siren subcommand
+///
+/// Opt is the command line options
+pub(crate) async fn main(opt: Opt, reactor: NeoReactor) -> Result<()> {
+    let camera = reactor.get(&opt.camera).await?;
+
+    let times = opt.times;
+    let on = opt.on;
+    let result = camera
+        .run_command(|cam| {
+            Box::pin(async move {
+                match on {
+                    Some(on) => cam.siren_manual(on).await,
+                    None => cam.siren_times(times).await,
+                }
+                .context("Unable to sound the camera siren")
+            })
+        })
+        .await;
+    let args = match on {
+        Some(true) => "continuous".to_string(),
+        Some(false) => "off".to_string(),
+        None => format!("on {}", times),
+    };
+    audit::cli(&opt.camera, "siren", &args, &result);
+    result?;
+
+    Ok(())
+}
@@ -0,0 +1,17 @@
+use clap::Parser;
+
+/// The status command will query every camera in the config and print a summary table
+///
+/// The running streams and last motion are asked from a running neolink
+/// when it has a `[control]` section in its config
+#[derive(Parser, Debug)]
+pub struct Opt {
+    /// How long in seconds to wait for each camera before reporting it as unreachable
+    #[arg(short, long, default_value_t = 15)]
+    pub timeout: u64,
+    /// Print the last errors of each camera from a neolink that is already running
+    ///
+    /// The running neolink must have a `[control]` section in its config
+    #[arg(long)]
+    pub errors: bool,
+}
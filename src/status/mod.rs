@@ -0,0 +1,298 @@
+///
+/// # Neolink Status
+///
+/// This module handles the status subcommand
+///
+/// It queries every enabled camera in the config at the same time
+/// and prints a table of their status
+///
+/// # Usage
+///
+/// ```bash
+/// neolink status --config=config.toml
+/// ```
+///
+/// Which prints something like
+///
+/// ```text
+/// Camera   Reachable  Transport  Firmware                Battery  Signal   Streams        Motion
+/// Garage   yes        local      v3.0.0.136_20121100     -        -52dBm   main,sub       5m ago
+/// Drive    no         -          -                       -        -        -              -
+/// Porch    busy       -          -                       -        -        -              -
+/// ```
+///
+/// A camera is `busy` when it refuses the login because it has reached
+/// its maximum number of clients, for example while the app is open
+///
+/// The streams that are running and when motion last started are only known
+/// to a neolink that has been running for a while, such as `neolink rtsp`.
+/// They are asked from it over its control socket when there is one
+///
+/// With `--errors` the cameras are not queried. Instead the last errors that
+/// dropped each camera are asked from a neolink that is already running,
+/// such as `neolink rtsp`, over its control socket
+///
+/// ```bash
+/// neolink status --config=config.toml --errors
+/// ```
+///
+/// ```text
+/// Camera  Time                  Kind     Error
+/// Garage  2026-10-16T03:12:45Z  timeout  Timed out waiting for the camera
+/// ```
+///
+use anyhow::Result;
+use futures::future::join_all;
+use time::{format_description::well_known::Rfc3339, OffsetDateTime};
+use tokio::time::{timeout, Duration};
+
+mod cmdline;
+
+use crate::common::{CameraActivity, CameraErrors, NeoInstance, NeoReactor};
+use crate::AnyResult;
+pub(crate) use cmdline::Opt;
+
+#[derive(Default)]
+struct CameraStatus {
+    name: String,
+    reachable: bool,
+    busy: bool,
+    transport: Option<String>,
+    firmware: Option<String>,
+    battery: Option<u32>,
+    signal: Option<i32>,
+    streams: Option<String>,
+    motion: Option<String>,
+}
+
+/// Entry point for the status subcommand
+///
+/// Opt is the command line options
+pub(crate) async fn main(opt: Opt, reactor: NeoReactor) -> Result<()> {
+    let config = reactor.config().await?.borrow().clone();
+    if opt.errors {
+        let control = config.control.clone().unwrap_or_default();
+        let reply = crate::ctl::send_request(&control, "errors").await?;
+        let cameras: Vec<CameraErrors> = serde_json::from_str(&reply)?;
+        print_errors(&cameras);
+        return Ok(());
+    }
+    let wait = Duration::from_secs(opt.timeout);
+
+    let control = config.control.clone().unwrap_or_default();
+    let running = match timeout(wait, crate::ctl::send_request(&control, "activity")).await {
+        Ok(Ok(reply)) => serde_json::from_str::<Vec<CameraActivity>>(&reply).ok(),
+        Ok(Err(e)) => {
+            log::debug!("No running neolink to ask for the activity: {:?}", e);
+            None
+        }
+        Err(_) => None,
+    };
+
+    let statuses = join_all(
+        config
+            .cameras
+            .iter()
+            .filter(|a| a.enabled)
+            .map(|cam_config| {
+                let activity = running.as_ref().and_then(|running| {
+                    running
+                        .iter()
+                        .find(|activity| activity.name == cam_config.name)
+                        .cloned()
+                });
+                query_camera(&reactor, cam_config.name.clone(), wait, activity)
+            }),
+    )
+    .await;
+
+    print_table(&statuses);
+    Ok(())
+}
+
+async fn query_camera(
+    reactor: &NeoReactor,
+    name: String,
+    wait: Duration,
+    activity: Option<CameraActivity>,
+) -> CameraStatus {
+    let mut status = CameraStatus {
+        name: name.clone(),
+        ..Default::default()
+    };
+    let camera = match timeout(wait, reactor.get(&name)).await {
+        Ok(Ok(camera)) => camera,
+        Ok(Err(e)) => {
+            log::debug!("{}: {:?}", name, e);
+            return status;
+        }
+        Err(_) => return status,
+    };
+
+    match timeout(
+        wait,
+        camera.run_task(|cam| {
+            Box::pin(async move { Ok((cam.transport(), cam.version().await?)) })
+        }),
+    )
+    .await
+    {
+        Ok(Ok((transport, version))) => {
+            status.reachable = true;
+            status.transport = Some(transport.to_string());
+            status.firmware = Some(version.firmwareVersion);
+        }
+        Ok(Err(e)) => {
+            log::debug!("{}: {:?}", name, e);
+            status.busy = is_busy(&camera).await;
+            return status;
+        }
+        Err(_) => {
+            status.busy = is_busy(&camera).await;
+            return status;
+        }
+    }
+
+    // The remaining queries are optional so a short timeout is used
+    // since unsupported features may not reply at all
+    let short_wait = Duration::from_secs(5).min(wait);
+    status.battery = optional(
+        &camera,
+        short_wait,
+        |cam| Box::pin(async move { Ok(cam.battery_info().await?.battery_percent) }),
+    )
+    .await;
+    status.signal = optional(
+        &camera,
+        short_wait,
+        |cam| Box::pin(async move { Ok(cam.get_wifi_signal().await?.signal) }),
+    )
+    .await;
+
+    // Without a running neolink only what this one has seen is known
+    let activity = match activity {
+        Some(activity) => Some(activity),
+        None => camera.activity().await.ok(),
+    };
+    if let Some(activity) = activity {
+        status.streams = (!activity.streams.is_empty()).then(|| activity.streams.join(","));
+        status.motion = activity.last_motion.as_deref().and_then(ago);
+    }
+
+    status
+}
+
+/// How long ago the RFC3339 `time` was, e.g. `5m ago`
+fn ago(time: &str) -> Option<String> {
+    let time = OffsetDateTime::parse(time, &Rfc3339).ok()?;
+    let secs = (OffsetDateTime::now_utc() - time).whole_seconds().max(0);
+    Some(match secs {
+        0..=59 => format!("{}s ago", secs),
+        60..=3599 => format!("{}m ago", secs / 60),
+        3600..=86399 => format!("{}h ago", secs / 3600),
+        _ => format!("{}d ago", secs / 86400),
+    })
+}
+
+async fn is_busy(camera: &NeoInstance) -> bool {
+    match camera.busy().await {
+        Ok(busy) => *busy.borrow(),
+        Err(_) => false,
+    }
+}
+
+async fn optional<F, T>(camera: &NeoInstance, wait: Duration, task: F) -> Option<T>
+where
+    F: for<'a> Fn(
+        &'a neolink_core::bc_protocol::BcCamera,
+    )
+        -> std::pin::Pin<Box<dyn futures::Future<Output = AnyResult<T>> + Send + 'a>>,
+{
+    timeout(wait, camera.run_passive_task(task))
+        .await
+        .ok()
+        .and_then(|r| r.ok())
+}
+
+fn print_table(statuses: &[CameraStatus]) {
+    let dash = || "-".to_string();
+    let rows = statuses
+        .iter()
+        .map(|status| {
+            [
+                status.name.clone(),
+                match (status.reachable, status.busy) {
+                    (true, _) => "yes",
+                    (false, true) => "busy",
+                    (false, false) => "no",
+                }
+                .to_string(),
+                status.transport.clone().unwrap_or_else(dash),
+                status.firmware.clone().unwrap_or_else(dash),
+                status
+                    .battery
+                    .map(|b| format!("{}%", b))
+                    .unwrap_or_else(dash),
+                status
+                    .signal
+                    .map(|s| format!("{}dBm", s))
+                    .unwrap_or_else(dash),
+                status.streams.clone().unwrap_or_else(dash),
+                status.motion.clone().unwrap_or_else(dash),
+            ]
+        })
+        .collect::<Vec<_>>();
+    let header = [
+        "Camera",
+        "Reachable",
+        "Transport",
+        "Firmware",
+        "Battery",
+        "Signal",
+        "Streams",
+        "Motion",
+    ]
+    .map(|s| s.to_string());
+    print_rows(&header, &rows);
+}
+
+fn print_errors(cameras: &[CameraErrors]) {
+    let rows = cameras
+        .iter()
+        .flat_map(|camera| {
+            camera.errors.iter().map(|error| {
+                [
+                    camera.name.clone(),
+                    error.time.clone(),
+                    error.category.to_string(),
+                    error.error.lines().next().unwrap_or_default().to_string(),
+                ]
+            })
+        })
+        .collect::<Vec<_>>();
+    if rows.is_empty() {
+        println!("No errors");
+        return;
+    }
+    let header = ["Camera", "Time", "Kind", "Error"].map(|s| s.to_string());
+    print_rows(&header, &rows);
+}
+
+fn print_rows<const N: usize>(header: &[String; N], rows: &[[String; N]]) {
+    let mut widths = header.clone().map(|h| h.len());
+    for row in rows.iter() {
+        for (width, cell) in widths.iter_mut().zip(row.iter()) {
+            *width = (*width).max(cell.len());
+        }
+    }
+
+    for row in std::iter::once(header).chain(rows.iter()) {
+        let line = row
+            .iter()
+            .zip(widths.iter())
+            .map(|(cell, width)| format!("{:width$}", cell, width = width))
+            .collect::<Vec<_>>()
+            .join("  ");
+        println!("{}", line.trim_end());
+    }
+}
@@ -1,5 +1,6 @@
 use anyhow::{anyhow, Result};
 use clap::Parser;
+use neolink_core::bc_protocol::LightState;
 
 fn onoff_parse(src: &str) -> Result<bool> {
     match src {
@@ -12,12 +13,29 @@ fn onoff_parse(src: &str) -> Result<bool> {
     }
 }
 
+fn lightstate_parse(src: &str) -> Result<LightState> {
+    match src {
+        "true" | "on" | "yes" => Ok(LightState::On),
+        "false" | "off" | "no" => Ok(LightState::Off),
+        "auto" => Ok(LightState::Auto),
+        _ => Err(anyhow!(
+            "Could not understand {}, check your input, should be on, off or auto",
+            src
+        )),
+    }
+}
+
 /// The status-light command will control the blue status light on the camera
+///
+/// Without any state it prints the lights of the camera and their states
 #[derive(Parser, Debug)]
 pub struct Opt {
     /// The name of the camera to change the lights of. Must be a name in the config
     pub camera: String,
     /// Whether to turn the light on or off
     #[arg(value_parser = onoff_parse, action = clap::ArgAction::Set, name = "on|off")]
-    pub on: bool,
+    pub on: Option<bool>,
+    /// Set the light of the doorbell button to on, off or auto where it lights when pressed
+    #[arg(long, value_parser = lightstate_parse, value_name = "on|off|auto")]
+    pub doorbell: Option<LightState>,
 }
@@ -13,13 +13,20 @@
 /// neolink status-light --config=config.toml CameraName on
 /// # Or off
 /// neolink status-light --config=config.toml CameraName off
+/// # Doorbells can also set the light of their button to on, off or auto
+/// neolink status-light --config=config.toml CameraName --doorbell auto
+/// # To see the lights that the camera has
+/// neolink status-light --config=config.toml CameraName
 /// ```
 ///
 use anyhow::{Context, Result};
 
 mod cmdline;
 
-use crate::common::NeoReactor;
+use crate::{
+    common::{audit, NeoReactor},
+    utils::lightstate_name,
+};
 pub(crate) use cmdline::Opt;
 
 /// Entry point for the ledstatus subcommand
@@ -28,17 +35,57 @@ pub(crate) use cmdline::Opt;
 pub(crate) async fn main(opt: Opt, reactor: NeoReactor) -> Result<()> {
     let camera = reactor.get(&opt.camera).await?;
 
-    let on = opt.on;
-    camera
-        .run_task(|camera| {
-            Box::pin(async move {
-                camera
-                    .led_light_set(on)
-                    .await
-                    .context("Unable to set camera light state")
+    if opt.on.is_none() && opt.doorbell.is_none() {
+        let led_state = camera
+            .run_task(|cam| {
+                Box::pin(async move {
+                    cam.get_ledstate()
+                        .await
+                        .context("Unable to get camera light state")
+                })
             })
-        })
-        .await?;
+            .await?;
+        let led_ser = String::from_utf8(
+            {
+                let mut buf = bytes::BytesMut::new();
+                quick_xml::se::to_writer(&mut buf, &led_state).map(|_| buf.to_vec())
+            }
+            .expect("Should Ser the struct"),
+        )
+        .expect("Should be UTF8");
+        println!("{}", led_ser);
+        return Ok(());
+    }
+
+    if let Some(on) = opt.on {
+        let result = camera
+            .run_task(|camera| {
+                Box::pin(async move {
+                    camera
+                        .led_light_set(on)
+                        .await
+                        .context("Unable to set camera light state")
+                })
+            })
+            .await;
+        audit::cli(&opt.camera, "led", if on { "on" } else { "off" }, &result);
+        result?;
+    }
+
+    if let Some(state) = opt.doorbell {
+        let result = camera
+            .run_task(|camera| {
+                Box::pin(async move {
+                    camera
+                        .doorbell_light_set(state)
+                        .await
+                        .context("Unable to set the doorbell light state")
+                })
+            })
+            .await;
+        audit::cli(&opt.camera, "led/doorbell", lightstate_name(state), &result);
+        result?;
+    }
 
     Ok(())
 }
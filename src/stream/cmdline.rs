@@ -0,0 +1,36 @@
+use clap::{Parser, ValueEnum};
+use std::path::PathBuf;
+use std::str::FromStr;
+
+/// The stream command writes the live stream of a camera to stdout or a file
+///
+/// It keeps writing until it is stopped or the reader goes away
+#[derive(Parser, Debug)]
+pub struct Opt {
+    /// The name of the camera. Must be a name in the config
+    pub camera: String,
+    /// How the stream is written
+    #[arg(short, long, value_enum, default_value = "mpegts")]
+    pub format: StreamFormat,
+    /// The file to write to, `-` for stdout
+    #[arg(short, long, value_parser = PathBuf::from_str, default_value = "-")]
+    pub output: PathBuf,
+    /// Write the substream instead of the main stream
+    #[arg(long)]
+    pub sub: bool,
+    /// Leave out the audio of the mkv and mpegts formats
+    #[arg(long)]
+    pub no_audio: bool,
+}
+
+#[derive(Parser, Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum StreamFormat {
+    /// The video as the camera sends it, H264 or H265 in Annex B
+    #[value(alias = "h265", alias = "raw")]
+    H264,
+    /// Matroska that can be played while it is written
+    Mkv,
+    /// MPEG transport stream
+    #[value(alias = "ts")]
+    Mpegts,
+}
@@ -0,0 +1,315 @@
+///
+/// # Neolink Stream
+///
+/// This module writes the live stream of a camera to stdout or a file so that
+/// it can be piped into ffmpeg, mpv or other tools without going through the
+/// rtsp server
+///
+/// The formats are
+///
+/// - `h264` the video as the camera sends it, H264 or H265 in Annex B. This is
+///   written without gstreamer touching it
+/// - `mkv` Matroska that can be played while it is written, with the AAC audio
+/// - `mpegts` an MPEG transport stream, with the AAC audio
+///
+/// Writing starts on the next keyframe. The logs are written to stderr so they
+/// do not mix with the stream
+///
+/// # Usage
+///
+/// ```bash
+/// neolink stream --config=config.toml --format=mpegts -o - CameraName | mpv -
+/// neolink stream --config=config.toml --format=h264 -o - CameraName | ffmpeg -f h264 -i - ...
+/// # The substream to a file
+/// neolink stream --config=config.toml --format=mkv --sub -o camera.mkv CameraName
+/// ```
+///
+use anyhow::{anyhow, Context, Result};
+use futures::stream::StreamExt;
+use gstreamer::{
+    parse::launch_full, prelude::*, ClockTime, MessageView, ParseFlags, Pipeline, State,
+};
+use gstreamer_app::AppSrc;
+use neolink_core::bc_protocol::StreamKind;
+use std::path::Path;
+use tokio::{
+    io::{AsyncWrite, AsyncWriteExt},
+    task::JoinSet,
+    time::{timeout, Duration},
+};
+use tokio_stream::wrappers::BroadcastStream;
+
+mod cmdline;
+
+use crate::{
+    common::{AudFormat, NeoReactor, StampedData, StreamInstance, VidFormat},
+    AnyResult,
+};
+pub(crate) use cmdline::Opt;
+use cmdline::StreamFormat;
+
+/// Entry point for the stream subcommand
+///
+/// Opt is the command line options
+pub(crate) async fn main(opt: Opt, reactor: NeoReactor) -> Result<()> {
+    let camera = reactor.get(&opt.camera).await?;
+    let kind = if opt.sub {
+        StreamKind::Sub
+    } else {
+        StreamKind::Main
+    };
+    let mut stream = camera.stream(kind).await.context("Failed to start video")?;
+    stream.config.wait_for(|config| config.vid_ready()).await?;
+    // After vid give it 1s to look for audio
+    if let Ok(v) = timeout(
+        Duration::from_secs(1),
+        stream.config.wait_for(|config| config.aud_ready()),
+    )
+    .await
+    {
+        v?;
+    }
+    let stream_config = stream.config.borrow().clone();
+    let mut config = stream.config.clone();
+    log::info!(
+        "{}: Writing the {:?} stream to {}",
+        opt.camera,
+        kind,
+        opt.output.display()
+    );
+
+    let res = tokio::select! {
+        v = async {
+            match opt.format {
+                StreamFormat::H264 => {
+                    if matches!(stream_config.vid_format, VidFormat::H265) {
+                        log::info!("{}: The camera sends H265, it is written as raw H265", opt.camera);
+                    }
+                    if opt.output == Path::new("-") {
+                        write_raw(&stream, tokio::io::stdout()).await
+                    } else {
+                        let file = tokio::fs::File::create(&opt.output)
+                            .await
+                            .with_context(|| format!("Failed to create {}", opt.output.display()))?;
+                        write_raw(&stream, file).await
+                    }
+                }
+                format => {
+                    // Only AAC can be put into the containers as is
+                    let audio = !opt.no_audio && matches!(stream_config.aud_format, AudFormat::Aac);
+                    write_muxed(&stream, format, stream_config.vid_format, audio, &opt.output).await
+                }
+            }
+        } => v,
+        v = config.wait_for(|config| config != &stream_config) => {
+            v?;
+            Err(anyhow!("The stream format changed"))
+        },
+    };
+    res.with_context(|| format!("{}: Streaming stopped", opt.camera))
+}
+
+/// Write the video frames as they are
+async fn write_raw<W: AsyncWrite + Unpin>(stream: &StreamInstance, mut out: W) -> AnyResult<()> {
+    let mut vid = BroadcastStream::new(stream.vid.resubscribe());
+    let mut started = false;
+    loop {
+        match vid.next().await {
+            Some(Ok(StampedData { data, keyframe, .. })) => {
+                started |= keyframe;
+                if !started {
+                    continue;
+                }
+                match out.write_all(&data).await {
+                    Ok(()) => {}
+                    // The reader went away
+                    Err(e) if e.kind() == std::io::ErrorKind::BrokenPipe => return Ok(()),
+                    Err(e) => return Err(e.into()),
+                }
+                out.flush().await?;
+            }
+            // Lagged, the decoder copes with a gap
+            Some(Err(_)) => {}
+            None => return Err(anyhow!("The stream ended")),
+        }
+    }
+}
+
+/// Mux the stream with gstreamer and write it out
+async fn write_muxed(
+    stream: &StreamInstance,
+    format: StreamFormat,
+    vid_format: VidFormat,
+    audio: bool,
+    output: &Path,
+) -> AnyResult<()> {
+    let (pipeline, vidsrc, audsrc) = create_pipeline(format, vid_format, audio, output)?;
+    let mut set = JoinSet::<AnyResult<()>>::new();
+    let thread_pipeline = pipeline.clone();
+    set.spawn_blocking(move || start_pipeline(thread_pipeline));
+
+    let mut vid = BroadcastStream::new(stream.vid.resubscribe());
+    let mut aud = BroadcastStream::new(stream.aud.resubscribe());
+    let res = tokio::select! {
+        v = async {
+            // The muxer must start on a keyframe
+            let mut started = false;
+            loop {
+                tokio::select! {
+                    data = vid.next() => match data {
+                        Some(Ok(StampedData { data, keyframe, .. })) => {
+                            started |= keyframe;
+                            if started {
+                                push(&vidsrc, &data)?;
+                            }
+                        }
+                        // Lagged, the muxer copes with a gap
+                        Some(Err(_)) => {}
+                        None => return Err(anyhow!("The stream ended")),
+                    },
+                    data = aud.next(), if audsrc.is_some() => match (data, audsrc.as_ref()) {
+                        (Some(Ok(StampedData { data, .. })), Some(audsrc)) if started => {
+                            push(audsrc, &data)?;
+                        }
+                        (None, _) => return Err(anyhow!("The audio ended")),
+                        _ => {}
+                    },
+                }
+            }
+        } => v,
+        v = set.join_next() => v
+            .unwrap_or(Ok(Ok(())))
+            .map_err(anyhow::Error::from)
+            .and_then(|v| v),
+    };
+
+    let _ = vidsrc.end_of_stream();
+    if let Some(audsrc) = audsrc.as_ref() {
+        let _ = audsrc.end_of_stream();
+    }
+    if timeout(Duration::from_secs(5), async {
+        while set.join_next().await.is_some() {}
+    })
+    .await
+    .is_err()
+    {
+        log::debug!("The muxer did not finish in time");
+    }
+    let _ = pipeline.set_state(State::Null);
+    while set.join_next().await.is_some() {}
+    res
+}
+
+fn push(source: &AppSrc, data: &[u8]) -> AnyResult<()> {
+    let mut buf = gstreamer::Buffer::with_size(data.len())?;
+    buf.get_mut()
+        .ok_or_else(|| anyhow!("New buffer should be writable"))?
+        .copy_from_slice(0, data)
+        .map_err(|_| anyhow!("Failed to copy the frame"))?;
+    source
+        .push_buffer(buf)
+        .map_err(|e| anyhow!("Streamer Error: {e:?}"))?;
+    Ok(())
+}
+
+fn start_pipeline(pipeline: Pipeline) -> AnyResult<()> {
+    pipeline.set_state(State::Playing)?;
+
+    let bus = pipeline
+        .bus()
+        .expect("Pipeline without bus. Shouldn't happen!");
+
+    let res = loop {
+        match bus.timed_pop(ClockTime::from_mseconds(500)) {
+            Some(msg) => match msg.view() {
+                MessageView::Eos(..) => break Ok(()),
+                MessageView::Error(err) => {
+                    break Err(anyhow!("Error from gstreamer in the muxer: {:?}", err));
+                }
+                _ => (),
+            },
+            // Stopped by the writer
+            None if pipeline.current_state() == State::Null => break Ok(()),
+            None => (),
+        }
+    };
+
+    pipeline
+        .set_state(State::Null)
+        .context("Error in gstreamer when setting state to Null")?;
+    res
+}
+
+/// Mux the stream into one stream written to the output
+fn create_pipeline(
+    format: StreamFormat,
+    vid_format: VidFormat,
+    audio: bool,
+    output: &Path,
+) -> AnyResult<(Pipeline, AppSrc, Option<AppSrc>)> {
+    gstreamer::init()
+        .context("Unable to start gstreamer ensure it and all plugins are installed")?;
+
+    let parser = match vid_format {
+        VidFormat::H264 => "h264parse",
+        VidFormat::H265 => "h265parse",
+        VidFormat::None => unreachable!(),
+    };
+    let muxer = match format {
+        StreamFormat::Mkv => "matroskamux streamable=true",
+        StreamFormat::Mpegts => "mpegtsmux",
+        StreamFormat::H264 => unreachable!(),
+    };
+    let sink = if output == Path::new("-") {
+        "fdsink fd=1".to_string()
+    } else {
+        format!("filesink location=\"{}\"", output.display())
+    };
+    // The parameter sets are repeated on each keyframe so that a reader
+    // that starts late can still decode it
+    let mut launch_str = format!(
+        "{} name=mux ! {} \
+        appsrc name=vidsrc is-live=true do-timestamp=true format=time \
+        ! {} config-interval=-1 \
+        ! queue \
+        ! mux.",
+        muxer, sink, parser,
+    );
+    if audio {
+        launch_str.push_str(
+            " appsrc name=audsrc is-live=true do-timestamp=true format=time \
+            ! aacparse \
+            ! queue \
+            ! mux.",
+        );
+    }
+    log::debug!("{}", launch_str);
+
+    let pipeline = launch_full(&launch_str, None, ParseFlags::empty())
+        .context("Unable to load gstreamer pipeline ensure all gstramer plugins are installed")?
+        .dynamic_cast::<Pipeline>()
+        .map_err(|_| {
+            anyhow!("Unable to create gstreamer pipeline ensure all gstramer plugins are installed")
+        })?;
+
+    let vidsrc = pipeline
+        .by_name("vidsrc")
+        .expect("There shoud be a `vidsrc`")
+        .dynamic_cast::<AppSrc>()
+        .map_err(|_| anyhow!("Cannot find appsource in gstreamer, check your gstreamer plugins"))?;
+    let audsrc = if audio {
+        Some(
+            pipeline
+                .by_name("audsrc")
+                .expect("There shoud be an `audsrc`")
+                .dynamic_cast::<AppSrc>()
+                .map_err(|_| {
+                    anyhow!("Cannot find appsource in gstreamer, check your gstreamer plugins")
+                })?,
+        )
+    } else {
+        None
+    };
+
+    Ok((pipeline, vidsrc, audsrc))
+}
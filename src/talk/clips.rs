@@ -0,0 +1,156 @@
+//! Audio clips that are played on the camera speakers by name
+//!
+//! Transcoding a file to the camera's adpcm takes a moment so the clips of the
+//! `[[clips]]` config are transcoded once and kept. They are transcoded at
+//! startup to the format that most cameras use and to any other format the
+//! first time a camera with it plays them
+use anyhow::{anyhow, Context, Result};
+use neolink_core::bc::xml::TalkConfig;
+use once_cell::sync::Lazy;
+use std::{
+    collections::HashMap,
+    path::PathBuf,
+    sync::{Arc, Mutex},
+};
+
+use super::{
+    gst::{self, AdpcmFormat},
+    talk_config,
+};
+use crate::{
+    common::{NeoInstance, NeoReactor},
+    config::ClipConfig,
+};
+
+/// The block align and sample rate of most cameras
+const COMMON_FORMAT: AdpcmFormat = (516, 16000);
+
+/// A clip is transcoded again if its file or volume changes in the config
+#[derive(Hash, PartialEq, Eq)]
+struct ClipKey {
+    path: PathBuf,
+    volume: u32,
+    format: AdpcmFormat,
+}
+
+impl ClipKey {
+    fn new(clip: &ClipConfig, format: AdpcmFormat) -> Self {
+        Self {
+            path: clip.path.clone(),
+            volume: clip.volume.to_bits(),
+            format,
+        }
+    }
+}
+
+/// The clips of the current config for the commands that play them by name
+static CLIPS: Lazy<Mutex<Vec<ClipConfig>>> = Lazy::new(Default::default);
+
+static TRANSCODED: Lazy<Mutex<HashMap<ClipKey, Arc<Vec<u8>>>>> = Lazy::new(Default::default);
+
+/// The talk configs of the cameras so that playing a clip is only one message
+static TALK_CONFIGS: Lazy<Mutex<HashMap<String, TalkConfig>>> = Lazy::new(Default::default);
+
+/// Keep the clips of the config transcoded as it changes
+pub(crate) async fn serve(reactor: NeoReactor) -> Result<()> {
+    let mut config = reactor.config().await?;
+    loop {
+        let clips = config.borrow_and_update().clips.clone();
+        *CLIPS.lock().unwrap() = clips.clone();
+        preload(&clips).await;
+        config.changed().await?;
+    }
+}
+
+/// The clips of the current config
+pub(crate) fn configured() -> Vec<ClipConfig> {
+    CLIPS.lock().unwrap().clone()
+}
+
+/// Transcode the clips for the common camera format
+async fn preload(clips: &[ClipConfig]) {
+    for clip in clips.iter() {
+        match transcoded(clip, COMMON_FORMAT).await {
+            Ok(adpcm) => log::info!("Clip {}: Ready, {} bytes", clip.name, adpcm.len()),
+            Err(e) => log::warn!("Clip {}: Failed to transcode: {:?}", clip.name, e),
+        }
+    }
+}
+
+/// Play the clip called `name` on the camera's speaker
+pub(crate) async fn play(camera: &NeoInstance, clips: &[ClipConfig], name: &str) -> Result<()> {
+    let clip = clips
+        .iter()
+        .find(|clip| clip.name == name)
+        .ok_or_else(|| anyhow!("There is no clip called {}", name))?;
+    let camera_name = camera.config().await?.borrow().name.clone();
+
+    let cached = TALK_CONFIGS.lock().unwrap().get(&camera_name).cloned();
+    let talk_config = match cached {
+        Some(talk_config) => talk_config,
+        None => {
+            let talk_config = talk_config(camera)
+                .await
+                .with_context(|| format!("Camera {} does not support talk", camera_name))?;
+            TALK_CONFIGS
+                .lock()
+                .unwrap()
+                .insert(camera_name.clone(), talk_config.clone());
+            talk_config
+        }
+    };
+    let format = (
+        (talk_config.audio_config.length_per_encoder / 2) + 4,
+        talk_config.audio_config.sample_rate,
+    );
+    let adpcm = transcoded(clip, format).await?;
+
+    log::info!("{}: Playing clip {}", camera_name, clip.name);
+    camera
+        .run_task(|cam| {
+            let adpcm = adpcm.clone();
+            let talk_config = talk_config.clone();
+            Box::pin(async move {
+                cam.talk(&adpcm, talk_config).await?;
+                Ok(())
+            })
+        })
+        .await
+        .with_context(|| format!("{}: Failed to play clip {}", camera_name, clip.name))
+}
+
+/// The clip in the format, transcoding it if it is not already
+async fn transcoded(clip: &ClipConfig, format: AdpcmFormat) -> Result<Arc<Vec<u8>>> {
+    let key = ClipKey::new(clip, format);
+    if let Some(adpcm) = TRANSCODED.lock().unwrap().get(&key) {
+        return Ok(adpcm.clone());
+    }
+
+    let path = clip
+        .path
+        .to_str()
+        .ok_or_else(|| anyhow!("Clip path {:?} is not UTF8", clip.path))?;
+    if !clip.path.is_file() {
+        return Err(anyhow!("Clip file {:?} does not exist", clip.path));
+    }
+    let (mut set, mut rxs) = gst::from_input(
+        &format!("filesrc location=\"{}\"", path),
+        clip.volume,
+        &[format],
+    )
+    .with_context(|| format!("Failed to setup gst with the clip {:?}", clip.path))?;
+    let rx = rxs
+        .pop()
+        .expect("There should be a receiver for the format");
+    // The receiver ends when the pipeline reaches the end of the file
+    let adpcm =
+        tokio::task::spawn_blocking(move || rx.iter().flatten().collect::<Vec<u8>>()).await?;
+    while set.join_next().await.is_some() {}
+    if adpcm.is_empty() {
+        return Err(anyhow!("Clip {:?} has no audio", clip.path));
+    }
+
+    let adpcm = Arc::new(adpcm);
+    TRANSCODED.lock().unwrap().insert(key, adpcm.clone());
+    Ok(adpcm)
+}
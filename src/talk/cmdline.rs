@@ -8,16 +8,25 @@ use std::str::FromStr;
 /// This should be ok with most common formats.
 ///
 /// `gst-launch` can be used to prepare this data
+///
+/// When several cameras are given the audio is played on all of them at once
 #[derive(Parser, Debug)]
 pub struct Opt {
-    /// The name of the camera to talk through. Must be a name in the config
-    pub camera: String,
+    /// The names of the cameras to talk through. Must be names in the config
+    #[arg(required_unless_present = "all", conflicts_with = "all")]
+    pub cameras: Vec<String>,
+    /// Talk through every enabled camera in the config
+    #[arg(long)]
+    pub all: bool,
     /// The path to the audio file.
-    #[arg(short, long, value_parser = PathBuf::from_str, conflicts_with = "microphone")]
+    #[arg(short, long, value_parser = PathBuf::from_str, conflicts_with_all = ["microphone", "clip"])]
     pub file_path: Option<PathBuf>,
+    /// The name of a clip in the `[[clips]]` of the config
+    #[arg(long, conflicts_with_all = ["microphone", "file_path"])]
+    pub clip: Option<String>,
     /// Use the microphone as the source. Defaults to autoaudiosrc - Which microphone depends
     /// on [gstreamer](https://gstreamer.freedesktop.org/documentation/autodetect/autoaudiosrc.html?gi-language=c#autoaudiosrc-page)
-    #[arg(short, long, conflicts_with = "file_path")]
+    #[arg(short, long, conflicts_with_all = ["file_path", "clip"])]
     pub microphone: bool,
     /// Use a specific microphone like "alsasrc device=hw:1"
     #[arg(
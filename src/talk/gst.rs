@@ -5,35 +5,67 @@ use gstreamer::{
     element_error, parse::launch_full, prelude::*, Caps, ClockTime, FlowError, FlowSuccess,
     MessageView, ParseFlags, Pipeline, ResourceError, State,
 };
-use gstreamer_app::{AppSink, AppSinkCallbacks};
+use gstreamer_app::{AppSink, AppSinkCallbacks, AppSrc};
 use tokio::task::JoinSet;
 
 use byte_slice_cast::*;
 
+/// The block align and sample rate of the adpcm that a camera plays
+pub(crate) type AdpcmFormat = (u16, u16);
+
+/// Encode the input once for each of the formats
+///
+/// The input is only opened once so a microphone can be sent to
+/// several cameras at the same time
 #[allow(clippy::type_complexity)]
 pub(super) fn from_input(
     input_src: &str,
     volume: f32,
-    block_align: u16,
-    sample_rate: u16,
-) -> Result<(JoinSet<AnyResult<()>>, Receiver<Vec<u8>>)> {
-    let pipeline = create_pipeline(input_src, volume, block_align, sample_rate)?;
-    input(pipeline)
+    formats: &[AdpcmFormat],
+) -> Result<(JoinSet<AnyResult<()>>, Vec<Receiver<Vec<u8>>>)> {
+    let pipeline = create_pipeline(input_src, volume, formats)?;
+    input(pipeline, formats.len())
+}
+
+/// Encode the audio pushed into the returned appsrc, such as the audio of another camera
+///
+/// `source` must start with an appsrc named `thesource`
+#[allow(clippy::type_complexity)]
+pub(crate) fn from_appsrc(
+    source: &str,
+    volume: f32,
+    formats: &[AdpcmFormat],
+) -> Result<(JoinSet<AnyResult<()>>, Vec<Receiver<Vec<u8>>>, AppSrc)> {
+    let pipeline = create_pipeline(source, volume, formats)?;
+    let appsrc = pipeline
+        .by_name("thesource")
+        .expect("There shoud be a `thesource`")
+        .dynamic_cast::<AppSrc>()
+        .map_err(|_| anyhow!("Cannot find appsource in gstreamer, check your gstreamer plugins"))?;
+    let (set, rxs) = input(pipeline, formats.len())?;
+    Ok((set, rxs, appsrc))
 }
 
 #[allow(clippy::type_complexity)]
-fn input(pipeline: Pipeline) -> Result<(JoinSet<AnyResult<()>>, Receiver<Vec<u8>>)> {
-    let appsink = get_sink(&pipeline)?;
-    let (tx, rx) = bounded(30);
+fn input(
+    pipeline: Pipeline,
+    count: usize,
+) -> Result<(JoinSet<AnyResult<()>>, Vec<Receiver<Vec<u8>>>)> {
+    let mut rxs = vec![];
+    for i in 0..count {
+        let appsink = get_sink(&pipeline, i)?;
+        let (tx, rx) = bounded(30);
+        set_data_channel(&appsink, tx);
+        rxs.push(rx);
+    }
     let mut set = JoinSet::<AnyResult<()>>::new();
-    set_data_channel(&appsink, tx);
 
     set.spawn_blocking(move || {
         let _ = start_pipeline(pipeline);
         AnyResult::Ok(())
     });
 
-    Ok((set, rx))
+    Ok((set, rxs))
 }
 
 fn start_pipeline(pipeline: Pipeline) -> Result<()> {
@@ -66,10 +98,10 @@ fn start_pipeline(pipeline: Pipeline) -> Result<()> {
     Ok(())
 }
 
-fn get_sink(pipeline: &Pipeline) -> Result<AppSink> {
+fn get_sink(pipeline: &Pipeline, index: usize) -> Result<AppSink> {
     let sink = pipeline
-        .by_name("thesink")
-        .expect("There shoud be a `thesink`");
+        .by_name(&format!("thesink{}", index))
+        .expect("There shoud be a `thesink` for each format");
     sink.dynamic_cast::<AppSink>()
         .map_err(|_| anyhow!("Cannot find appsink in gstreamer, check your gstreamer plugins"))
 }
@@ -132,27 +164,32 @@ fn set_data_channel(appsink: &AppSink, tx: Sender<Vec<u8>>) {
     );
 }
 
-fn create_pipeline(
-    source: &str,
-    volume: f32,
-    block_align: u16,
-    sample_rate: u16,
-) -> Result<Pipeline> {
+fn create_pipeline(source: &str, volume: f32, formats: &[AdpcmFormat]) -> Result<Pipeline> {
     gstreamer::init()
         .context("Unable to start gstreamer ensure it and all plugins are installed")?;
 
-    let launch_str = format!(
+    // Each camera gets its own branch of the tee as they can
+    // each want a different rate and block size
+    let mut launch_str = format!(
         "{} \
         ! decodebin \
         ! audioconvert \
-        ! audioresample \
-        ! audio/x-raw,rate={},channels=1 \
         ! volume volume={:.2} \
-        ! queue  \
-        ! adpcmenc blockalign={} layout=dvi \
-        ! appsink name=thesink",
-        source, sample_rate, volume, block_align
+        ! tee name=split",
+        source, volume
     );
+    for (i, (block_align, sample_rate)) in formats.iter().enumerate() {
+        launch_str.push_str(&format!(
+            " split. \
+            ! queue \
+            ! audioconvert \
+            ! audioresample \
+            ! audio/x-raw,rate={},channels=1 \
+            ! adpcmenc blockalign={} layout=dvi \
+            ! appsink name=thesink{}",
+            sample_rate, block_align, i
+        ));
+    }
 
     log::info!("{}", launch_str);
 
@@ -165,20 +202,22 @@ fn create_pipeline(
         anyhow!("Unable to create gstreamer pipeline ensure all gstramer plugins are installed")
     })?;
 
-    let appsink = get_sink(&pipeline)?;
-
-    // Tell the appsink what format we want. It will then be the audiotestsrc's job to
-    // provide the format we request.
-    // This can be set after linking the two objects, because format negotiation between
-    // both elements will happen during pre-rolling of the pipeline.
-    appsink.set_caps(Some(
-        &Caps::builder("audio/x-adpcm")
-            .field("layout", "dvi")
-            .field("block_align", block_align as i32)
-            .field("channels", 1i32)
-            .field("rate", sample_rate as i32)
-            .build(),
-    ));
+    for (i, (block_align, sample_rate)) in formats.iter().enumerate() {
+        let appsink = get_sink(&pipeline, i)?;
+
+        // Tell the appsink what format we want. It will then be the audiotestsrc's job to
+        // provide the format we request.
+        // This can be set after linking the two objects, because format negotiation between
+        // both elements will happen during pre-rolling of the pipeline.
+        appsink.set_caps(Some(
+            &Caps::builder("audio/x-adpcm")
+                .field("layout", "dvi")
+                .field("block_align", *block_align as i32)
+                .field("channels", 1i32)
+                .field("rate", *sample_rate as i32)
+                .build(),
+        ));
+    }
 
     Ok(pipeline)
 }
@@ -9,22 +9,164 @@
 ///
 /// ```bash
 /// neolink talk --config=config.toml --adpcm-file=data.adpcm --sample-rate=16000 --block-size=512 CameraName
+/// # Announce through several cameras at once
+/// neolink talk --config=config.toml --file-path=dinner.mp3 Kitchen Garden Garage
+/// neolink talk --config=config.toml --microphone --all
+/// # Play a clip of the config
+/// neolink talk --config=config.toml --clip=dog_bark Garden
 /// ```
 ///
+/// # Example Config
+///
+/// ```toml
+/// [[clips]]
+/// name = "dog_bark"
+/// path = "/etc/neolink/dog_bark.wav"
+/// volume = 1.0
+/// ```
+///
+/// Clips are transcoded once and kept, so they start sooner than a file
+/// and can also be played over MQTT and HTTP
+///
 use anyhow::{anyhow, Context, Result};
+use futures::future::join_all;
 use neolink_core::bc::xml::TalkConfig;
 
+pub(crate) mod clips;
 mod cmdline;
-mod gst;
+pub(crate) mod gst;
 
-use crate::common::NeoReactor;
+use crate::{
+    common::{NeoInstance, NeoReactor},
+    AnyResult,
+};
 pub(crate) use cmdline::Opt;
 
 /// Entry point for the talk subcommand
 ///
 /// Opt is the command line options
 pub(crate) async fn main(opt: Opt, reactor: NeoReactor) -> Result<()> {
-    let camera = reactor.get(&opt.camera).await?;
+    if opt.file_path.is_none() && opt.clip.is_none() && !opt.microphone {
+        return Err(anyhow!(
+            "Expected one of --file-path, --clip or --microphone"
+        ));
+    }
+    let names = if opt.all {
+        reactor
+            .config()
+            .await?
+            .borrow()
+            .cameras
+            .iter()
+            .filter(|cam_config| cam_config.enabled)
+            .map(|cam_config| cam_config.name.clone())
+            .collect()
+    } else {
+        opt.cameras.clone()
+    };
+
+    // Each camera has its own audio format so they are all asked at once
+    let talkers = join_all(names.iter().map(|name| async {
+        let camera = reactor.get(name).await?;
+        let talk_config = talk_config(&camera)
+            .await
+            .with_context(|| format!("Camera {} does not support talk", name))?;
+        AnyResult::Ok((name, camera, talk_config))
+    }))
+    .await
+    .into_iter()
+    .filter_map(|talker| match talker {
+        Ok(talker) => Some(talker),
+        Err(e) => {
+            log::warn!("{:#}", e);
+            None
+        }
+    })
+    .collect::<Vec<_>>();
+    if talkers.is_empty() {
+        return Err(anyhow!("None of the cameras support talk"));
+    }
+
+    if let Some(clip) = opt.clip.as_ref() {
+        let clips = reactor.config().await?.borrow().clips.clone();
+        let results = join_all(
+            talkers
+                .iter()
+                .map(|(_, camera, _)| clips::play(camera, &clips, clip)),
+        )
+        .await;
+        return report(results, talkers.len());
+    }
+
+    let formats = talkers
+        .iter()
+        .map(|(_, _, talk_config)| {
+            (
+                (talk_config.audio_config.length_per_encoder / 2) + 4,
+                talk_config.audio_config.sample_rate,
+            )
+        })
+        .collect::<Vec<_>>();
+
+    let (mut set, rxs) = match (&opt.file_path, &opt.microphone) {
+        (Some(path), false) => gst::from_input(
+            &format!(
+                "filesrc location={}",
+                path.to_str().expect("File path not UTF8 complient")
+            ),
+            opt.volume,
+            &formats,
+        )
+        .with_context(|| format!("Failed to setup gst with the file: {:?}", path))?,
+        (None, true) => gst::from_input(&opt.input_src, opt.volume, &formats)
+            .context("Failed to setup gst using the microphone")?,
+        _ => unreachable!(),
+    };
+
+    let results = join_all(talkers.iter().zip(rxs).map(
+        |((name, camera, talk_config), rx)| async move {
+            let res = camera
+                .run_task(|cam| {
+                    let rx = rx.clone();
+                    let talk_config = talk_config.clone();
+                    Box::pin(async move {
+                        cam.talk_stream(rx, talk_config).await?;
+                        Ok(())
+                    })
+                })
+                .await
+                .with_context(|| format!("{}: Talk stream ended early", name));
+            // Dropping the receiver lets the other cameras carry on
+            // if this one fails
+            drop(rx);
+            res
+        },
+    ))
+    .await;
+
+    while set.join_next().await.is_some() {}
+
+    report(results, talkers.len())
+}
+
+/// Warn of the cameras that failed and fail if they all did
+fn report(results: Vec<Result<()>>, talkers: usize) -> Result<()> {
+    let failed = results
+        .into_iter()
+        .filter_map(|res| res.err())
+        .collect::<Vec<_>>();
+    for e in failed.iter() {
+        log::warn!("{:#}", e);
+    }
+    if failed.len() == talkers {
+        return Err(anyhow!("Talk failed on every camera"));
+    }
+
+    Ok(())
+}
+
+/// Get the talk config from the first talk ability of the camera
+pub(crate) async fn talk_config(camera: &NeoInstance) -> Result<TalkConfig> {
     let config = camera.config().await?.borrow().clone();
     let name = config.name.clone();
 
@@ -35,8 +177,7 @@ pub(crate) async fn main(opt: Opt, reactor: NeoReactor) -> Result<()> {
                 Ok(talk_ability)
             })
         })
-        .await
-        .with_context(|| format!("Camera {} does not support talk", name))?;
+        .await?;
 
     if talk_ability.duplex_list.is_empty()
         || talk_ability.audio_stream_mode_list.is_empty()
@@ -69,37 +210,5 @@ pub(crate) async fn main(opt: Opt, reactor: NeoReactor) -> Result<()> {
             name
         ));
     }
-
-    let (mut set, rx) = match (&opt.file_path, &opt.microphone) {
-        (Some(path), false) => gst::from_input(
-            &format!(
-                "filesrc location={}",
-                path.to_str().expect("File path not UTF8 complient")
-            ),
-            opt.volume,
-            block_size,
-            sample_rate,
-        )
-        .with_context(|| format!("Failed to setup gst with the file: {:?}", path))?,
-        (None, true) => gst::from_input(&opt.input_src, opt.volume, block_size, sample_rate)
-            .context("Failed to setup gst using the microphone")?,
-        _ => unreachable!(),
-    };
-
-    camera
-        .run_task(|cam| {
-            let rx = rx.clone();
-            let talk_config = talk_config.clone();
-            Box::pin(async move {
-                cam.talk_stream(rx, talk_config).await?;
-                Ok(())
-            })
-        })
-        .await
-        .context("Talk stream ended early")?;
-
-    drop(rx);
-    while set.join_next().await.is_some() {}
-
-    Ok(())
+    Ok(talk_config)
 }
@@ -0,0 +1,12 @@
+use clap::Parser;
+
+/// The timelapse command takes a snapshot of the cameras every few minutes
+///
+/// The snapshots are written to the `path` of the camera's `[cameras.timelapse]`
+/// every `interval` minutes and with `video = true` each day's snapshots are
+/// made into a video after midnight
+#[derive(Parser, Debug)]
+pub struct Opt {
+    /// The cameras to take snapshots of, all cameras with a `[cameras.timelapse]` when none are given
+    pub cameras: Vec<String>,
+}
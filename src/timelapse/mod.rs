@@ -0,0 +1,339 @@
+///
+/// # Neolink Timelapse
+///
+/// This module takes a snapshot of each camera every `interval` minutes for
+/// a timelapse, in the same way as `neolink image` but kept running
+///
+/// The snapshots are named `{CameraName}_{YYYYmmdd_HHMMSS}.jpeg` in the
+/// camera's `timezone`. With `video = true` the snapshots of each day are made
+/// into `{CameraName}_{YYYYmmdd}.mp4` after midnight
+///
+/// The camera is only used while a snapshot is taken so a battery camera
+/// with `idle_disconnect = true` sleeps between them. The snapshots share the
+/// one login to the camera with the other commands
+///
+/// # Usage
+///
+/// ```bash
+/// neolink timelapse --config=config.toml
+/// # Only some cameras
+/// neolink timelapse --config=config.toml Garden
+/// ```
+///
+/// # Example Config
+///
+/// ```toml
+/// [[cameras]]
+/// name = "Garden"
+/// idle_disconnect = true
+/// [cameras.timelapse]
+/// path = "/mnt/nas/timelapse"
+/// interval = 10
+/// video = true
+/// fps = 24
+/// ```
+///
+use anyhow::{anyhow, Context, Result};
+use gstreamer::{
+    parse::launch_full, prelude::*, ClockTime, MessageView, ParseFlags, Pipeline, State,
+};
+use gstreamer_app::AppSrc;
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+use time::{Date, OffsetDateTime};
+use tokio::{
+    task::JoinSet,
+    time::{interval, sleep, timeout, Duration, MissedTickBehavior},
+};
+
+mod cmdline;
+
+use crate::{
+    common::{NeoInstance, NeoReactor},
+    config::TimelapseConfig,
+    AnyResult,
+};
+pub(crate) use cmdline::Opt;
+
+/// The longest that a snapshot can take before it is skipped
+const SNAPSHOT_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Entry point for the timelapse subcommand
+///
+/// Opt is the command line options
+pub(crate) async fn main(opt: Opt, reactor: NeoReactor) -> Result<()> {
+    let names = if opt.cameras.is_empty() {
+        reactor
+            .config()
+            .await?
+            .borrow()
+            .cameras
+            .iter()
+            .filter(|camera| camera.enabled && camera.timelapse.is_some())
+            .map(|camera| camera.name.clone())
+            .collect::<Vec<_>>()
+    } else {
+        opt.cameras
+    };
+    if names.is_empty() {
+        return Err(anyhow!(
+            "No cameras for a timelapse, add a [cameras.timelapse] to the config"
+        ));
+    }
+
+    let mut set = JoinSet::new();
+    for name in names {
+        let camera = reactor.get(&name).await?;
+        set.spawn(async move {
+            timelapse_camera(camera)
+                .await
+                .with_context(|| format!("{}: Timelapse stopped", name))
+        });
+    }
+    while let Some(res) = set.join_next().await {
+        res??;
+    }
+    Ok(())
+}
+
+/// Run the timelapse of the camera, restarting when its `timelapse` config changes
+async fn timelapse_camera(camera: NeoInstance) -> AnyResult<()> {
+    let mut config = camera.config().await?;
+    let name = config.borrow().name.clone();
+    if config.borrow().timelapse.is_none() {
+        log::info!("{}: Waiting for a [cameras.timelapse] in the config", name);
+    }
+    loop {
+        let timelapse = config
+            .wait_for(|config| config.timelapse.is_some())
+            .await?
+            .timelapse
+            .clone();
+        let Some(timelapse) = timelapse else {
+            continue;
+        };
+        tokio::select! {
+            r = run_timelapse(&camera, &name, &timelapse) => {
+                log::warn!("{}: Timelapse failed, restarting: {:?}", name, r);
+                sleep(Duration::from_secs(1)).await;
+            },
+            v = config.wait_for(|config| config.timelapse.as_ref() != Some(&timelapse)) => {
+                v?;
+                log::info!("{}: Timelapse configuration changed", name);
+            },
+        }
+    }
+}
+
+/// Take the snapshots and make the daily videos
+async fn run_timelapse(
+    camera: &NeoInstance,
+    name: &str,
+    timelapse: &TimelapseConfig,
+) -> AnyResult<()> {
+    fs::create_dir_all(&timelapse.path)
+        .with_context(|| format!("Failed to create {}", timelapse.path.display()))?;
+    let config = camera.config().await?;
+    log::info!(
+        "{}: Taking a snapshot every {} minutes to {}",
+        name,
+        timelapse.interval,
+        timelapse.path.display()
+    );
+
+    let mut ticker = interval(Duration::from_secs(timelapse.interval * 60));
+    // A late snapshot moves the next ones rather than taking several at once
+    ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    let mut day = config.borrow().timezone().now().date();
+    loop {
+        ticker.tick().await;
+        let now = config.borrow().timezone().now();
+        if now.date() != day {
+            if timelapse.video {
+                if let Err(e) = make_video(name, timelapse, day).await {
+                    log::warn!("{}: Failed to make the timelapse video: {:?}", name, e);
+                }
+            }
+            day = now.date();
+        }
+
+        let path = snapshot_path(&timelapse.path, name, now);
+        // Wakes a camera that is idle disconnected until the snapshot is taken
+        let permit = camera.permit().await?;
+        match timeout(
+            SNAPSHOT_TIMEOUT,
+            crate::image::capture(camera, timelapse.use_stream, &path),
+        )
+        .await
+        {
+            Ok(Ok(())) => log::debug!("{}: Saved {}", name, path.display()),
+            Ok(Err(e)) => log::warn!("{}: Failed to take the snapshot: {:?}", name, e),
+            Err(_) => log::warn!("{}: The snapshot was not taken in time", name),
+        }
+        drop(permit);
+    }
+}
+
+/// Make the video of the snapshots of `day`
+async fn make_video(name: &str, timelapse: &TimelapseConfig, day: Date) -> AnyResult<()> {
+    let prefix = format!("{}_{}_", file_name(name), day_stamp(day));
+    let dir = timelapse.path.clone();
+    let snapshots = tokio::task::spawn_blocking(move || day_snapshots(&dir, &prefix)).await??;
+    if snapshots.is_empty() {
+        return Ok(());
+    }
+
+    let output = timelapse
+        .path
+        .join(format!("{}_{}.mp4", file_name(name), day_stamp(day)));
+    log::info!(
+        "{}: Making {} from {} snapshots",
+        name,
+        output.display(),
+        snapshots.len()
+    );
+    let fps = timelapse.fps;
+    let frames = snapshots.clone();
+    tokio::task::spawn_blocking(move || encode_video(&frames, fps, &output)).await??;
+
+    if timelapse.remove_snapshots {
+        for path in snapshots.iter() {
+            if let Err(e) = fs::remove_file(path) {
+                log::warn!("Failed to remove {}: {:?}", path.display(), e);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// The snapshots in `dir` starting with `prefix` in the order they were taken
+fn day_snapshots(dir: &Path, prefix: &str) -> AnyResult<Vec<PathBuf>> {
+    let mut snapshots = vec![];
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let path = entry.path();
+        let is_snapshot = entry
+            .file_name()
+            .to_str()
+            .is_some_and(|file| file.starts_with(prefix))
+            && path.extension().is_some_and(|ext| ext == "jpeg");
+        if is_snapshot {
+            snapshots.push(path);
+        }
+    }
+    // The names end in the time so they sort in order
+    snapshots.sort();
+    Ok(snapshots)
+}
+
+/// The camera name as it is used in the file names
+fn file_name(name: &str) -> String {
+    name.replace(['/', '\\'], "_")
+}
+
+fn day_stamp(day: Date) -> String {
+    format!(
+        "{:04}{:02}{:02}",
+        day.year(),
+        u8::from(day.month()),
+        day.day()
+    )
+}
+
+/// The file of a snapshot taken at `now`
+fn snapshot_path(dir: &Path, name: &str, now: OffsetDateTime) -> PathBuf {
+    dir.join(format!(
+        "{}_{}_{:02}{:02}{:02}.jpeg",
+        file_name(name),
+        day_stamp(now.date()),
+        now.hour(),
+        now.minute(),
+        now.second(),
+    ))
+}
+
+/// Encode the jpegs as the frames of an H264 mp4
+fn encode_video(frames: &[PathBuf], fps: u32, output: &Path) -> AnyResult<()> {
+    gstreamer::init()
+        .context("Unable to start gstreamer ensure it and all plugins are installed")?;
+
+    // The snapshots are decoded and scaled to the size of the first
+    let launch_str = format!(
+        "appsrc name=src format=time block=true caps=image/jpeg,framerate={}/1 \
+        ! jpegdec \
+        ! videoconvert \
+        ! videoscale \
+        ! x264enc \
+        ! h264parse \
+        ! mp4mux \
+        ! filesink location=\"{}\"",
+        fps,
+        output.display(),
+    );
+    log::debug!("{}", launch_str);
+
+    let pipeline = launch_full(&launch_str, None, ParseFlags::empty())
+        .context("Unable to load gstreamer pipeline ensure all gstramer plugins are installed")?
+        .dynamic_cast::<Pipeline>()
+        .map_err(|_| {
+            anyhow!("Unable to create gstreamer pipeline ensure all gstramer plugins are installed")
+        })?;
+    let source = pipeline
+        .by_name("src")
+        .expect("There shoud be a `src`")
+        .dynamic_cast::<AppSrc>()
+        .map_err(|_| anyhow!("Cannot find appsource in gstreamer, check your gstreamer plugins"))?;
+
+    pipeline.set_state(State::Playing)?;
+    let res = push_frames(&source, frames, fps).and_then(|_| wait_for_eos(&pipeline));
+    pipeline
+        .set_state(State::Null)
+        .context("Error in gstreamer when setting state to Null")?;
+    res
+}
+
+fn push_frames(source: &AppSrc, frames: &[PathBuf], fps: u32) -> AnyResult<()> {
+    let frame_time = 1_000_000_000 / u64::from(fps);
+    for (i, path) in frames.iter().enumerate() {
+        let data = match fs::read(path) {
+            Ok(data) => data,
+            Err(e) => {
+                log::warn!("Failed to read {}: {:?}", path.display(), e);
+                continue;
+            }
+        };
+        let mut buf = gstreamer::Buffer::from_mut_slice(data);
+        {
+            let buf = buf
+                .get_mut()
+                .ok_or_else(|| anyhow!("New buffer should be writable"))?;
+            buf.set_pts(ClockTime::from_nseconds(frame_time * i as u64));
+            buf.set_duration(ClockTime::from_nseconds(frame_time));
+        }
+        source
+            .push_buffer(buf)
+            .map_err(|e| anyhow!("Streamer Error: {e:?}"))?;
+    }
+    source
+        .end_of_stream()
+        .map_err(|e| anyhow!("Streamer Error: {e:?}"))?;
+    Ok(())
+}
+
+fn wait_for_eos(pipeline: &Pipeline) -> AnyResult<()> {
+    let bus = pipeline
+        .bus()
+        .expect("Pipeline without bus. Shouldn't happen!");
+    for msg in bus.iter_timed(ClockTime::NONE) {
+        match msg.view() {
+            MessageView::Eos(..) => return Ok(()),
+            MessageView::Error(err) => {
+                return Err(anyhow!("Error from gstreamer in the timelapse: {:?}", err));
+            }
+            _ => (),
+        }
+    }
+    Ok(())
+}
@@ -2,15 +2,17 @@
 //!
 use log::*;
 
-use super::config::CameraConfig;
+use super::config::{CameraConfig, ReconnectConfig};
 use anyhow::{anyhow, Context, Error, Result};
 use neolink_core::bc_protocol::{
-    BcCamera, BcCameraOpt, ConnectionProtocol, Credentials, DiscoveryMethods, MaxEncryption,
+    BcCamera, BcCameraOpt, ConnectionProtocol, Credentials, DiscoveryMethods, LightState,
+    MaxEncryption, Transport,
 };
+use rand::Rng;
 use std::{
     fmt::{Display, Error as FmtError, Formatter},
-    net::{IpAddr, ToSocketAddrs},
-    str::FromStr,
+    net::ToSocketAddrs,
+    time::Duration,
 };
 
 pub(crate) fn timeout<F>(future: F) -> tokio::time::Timeout<F>
@@ -20,6 +22,15 @@ where
     tokio::time::timeout(tokio::time::Duration::from_secs(15), future)
 }
 
+/// The name of a light state as it is given on the command line and over mqtt
+pub(crate) fn lightstate_name(state: LightState) -> &'static str {
+    match state {
+        LightState::On => "on",
+        LightState::Off => "off",
+        LightState::Auto => "auto",
+    }
+}
+
 pub(crate) enum AddressOrUid {
     Address(String),
     #[allow(dead_code)]
@@ -65,43 +76,7 @@ impl AddressOrUid {
         &self,
         camera_config: &CameraConfig,
     ) -> Result<BcCamera, Error> {
-        let (port, addrs) = {
-            if let Some(addr_str) = camera_config.camera_addr.as_ref() {
-                match addr_str.to_socket_addrs() {
-                    Ok(addr_iter) => {
-                        let mut port = None;
-                        let mut ipaddrs = vec![];
-                        for addr in addr_iter {
-                            port = Some(addr.port());
-                            ipaddrs.push(addr.ip());
-                        }
-                        Ok((port, ipaddrs))
-                    }
-                    Err(_) => match IpAddr::from_str(addr_str) {
-                        Ok(ip) => Ok((None, vec![ip])),
-                        Err(_) => Err(anyhow!("Could not parse address in config")),
-                    },
-                }
-            } else {
-                Ok((None, vec![]))
-            }
-        }?;
-
-        let options = BcCameraOpt {
-            name: camera_config.name.clone(),
-            channel_id: camera_config.channel_id,
-            addrs,
-            port,
-            uid: camera_config.camera_uid.clone(),
-            protocol: ConnectionProtocol::TcpUdp,
-            discovery: camera_config.discovery,
-            credentials: Credentials {
-                username: camera_config.username.clone(),
-                password: camera_config.password.clone(),
-            },
-            debug: camera_config.debug,
-            max_discovery_retries: camera_config.max_discovery_retries,
-        };
+        let options = camera_options(camera_config)?;
 
         trace!("Camera Info: {:?}", options);
 
@@ -109,6 +84,56 @@ impl AddressOrUid {
     }
 }
 
+// Translate a camera_config into the options used to find the camera
+fn camera_options(camera_config: &CameraConfig) -> Result<BcCameraOpt, Error> {
+    let (port, addrs) = {
+        if let Some(addr_str) = camera_config.camera_addr.as_ref() {
+            match addr_str.to_socket_addrs() {
+                Ok(addr_iter) => {
+                    let mut port = None;
+                    let mut ipaddrs = vec![];
+                    for addr in addr_iter {
+                        port = Some(addr.port());
+                        ipaddrs.push(addr.ip());
+                    }
+                    Ok((port, ipaddrs))
+                }
+                // No port in the address so use the configured one,
+                // this also resolves hostnames
+                Err(_) => match (addr_str.as_str(), camera_config.port.unwrap_or(9000))
+                    .to_socket_addrs()
+                {
+                    Ok(addr_iter) => Ok((
+                        camera_config.port,
+                        addr_iter.map(|addr| addr.ip()).collect(),
+                    )),
+                    Err(_) => Err(anyhow!("Could not parse address in config")),
+                },
+            }
+        } else {
+            Ok((camera_config.port, vec![]))
+        }
+    }?;
+
+    Ok(BcCameraOpt {
+        name: camera_config.name.clone(),
+        channel_id: camera_config.channel_id,
+        addrs,
+        port,
+        uid: camera_config.camera_uid.clone(),
+        protocol: ConnectionProtocol::TcpUdp,
+        discovery: camera_config.discovery,
+        credentials: Credentials {
+            username: camera_config.username.clone(),
+            password: camera_config.password.clone(),
+        },
+        debug: camera_config.debug,
+        max_discovery_retries: camera_config.max_discovery_retries,
+        udp: camera_config.udp,
+        servers: camera_config.discovery_servers.clone(),
+    })
+}
+
 pub(crate) async fn connect_and_login(camera_config: &CameraConfig) -> Result<BcCamera> {
     let camera_addr = AddressOrUid::new(
         &camera_config.camera_addr,
@@ -142,7 +167,128 @@ pub(crate) async fn connect_and_login(camera_config: &CameraConfig) -> Result<Bc
         .await
         .with_context(|| format!("Failed to login to {}", camera_config.name))??;
 
-    info!("{}: Connected and logged in", camera_config.name);
+    info!(
+        "{}: Connected and logged in over {}",
+        camera_config.name,
+        camera.transport()
+    );
 
     Ok(camera)
 }
+
+/// Find the best transport that can currently reach the camera without connecting to it
+pub(crate) async fn probe_transport(camera_config: &CameraConfig) -> Result<Transport> {
+    Ok(BcCamera::probe_transport(&camera_options(camera_config)?).await?)
+}
+
+/// Exponential backoff with jitter between attempts to reconnect to a camera
+pub(crate) struct Backoff {
+    config: ReconnectConfig,
+    delay: Duration,
+    attempts: u32,
+}
+
+impl Backoff {
+    pub(crate) fn new(config: &ReconnectConfig) -> Self {
+        Self {
+            config: config.clone(),
+            delay: Duration::from_secs_f64(config.backoff),
+            attempts: 0,
+        }
+    }
+
+    /// Failed reconnects since the last reset
+    pub(crate) fn attempts(&self) -> u32 {
+        self.attempts
+    }
+
+    /// Go back to `backoff` after a connection that lasted
+    pub(crate) fn reset(&mut self) {
+        self.delay = Duration::from_secs_f64(self.config.backoff);
+        self.attempts = 0;
+    }
+
+    /// The time to wait before the next attempt or None once `max_retries` is used up
+    pub(crate) fn next_delay(&mut self) -> Option<Duration> {
+        if self.config.max_retries > 0 && self.attempts >= self.config.max_retries {
+            return None;
+        }
+        self.attempts += 1;
+
+        let max = Duration::from_secs_f64(self.config.max_backoff);
+        let delay = self.delay.min(max);
+        self.delay = (self.delay * 2).min(max);
+
+        let jitter = self.config.jitter.clamp(0.0, 1.0);
+        if jitter > 0.0 {
+            let scale = rand::thread_rng().gen_range((1.0 - jitter)..=(1.0 + jitter));
+            Some(delay.mul_f64(scale))
+        } else {
+            Some(delay)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn reconnect(jitter: f64, max_retries: u32) -> ReconnectConfig {
+        ReconnectConfig {
+            backoff: 0.05,
+            max_backoff: 1.0,
+            jitter,
+            max_retries,
+            reset_after: 60,
+        }
+    }
+
+    #[test]
+    fn test_backoff_doubles_up_to_max() {
+        let mut backoff = Backoff::new(&reconnect(0.0, 0));
+        let delays: Vec<_> = (0..8).map(|_| backoff.next_delay().unwrap()).collect();
+        assert_eq!(
+            delays,
+            [50, 100, 200, 400, 800, 1000, 1000, 1000]
+                .iter()
+                .map(|&ms| Duration::from_millis(ms))
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(backoff.attempts(), 8);
+    }
+
+    #[test]
+    fn test_backoff_reset() {
+        let mut backoff = Backoff::new(&reconnect(0.0, 0));
+        for _ in 0..5 {
+            backoff.next_delay();
+        }
+        backoff.reset();
+        assert_eq!(backoff.attempts(), 0);
+        assert_eq!(backoff.next_delay(), Some(Duration::from_millis(50)));
+    }
+
+    #[test]
+    fn test_backoff_jitter_bounds() {
+        let mut backoff = Backoff::new(&reconnect(0.2, 0));
+        for _ in 0..100 {
+            let expected = backoff.delay.min(Duration::from_secs(1));
+            let delay = backoff.next_delay().unwrap();
+            assert!(delay >= expected.mul_f64(0.8), "{:?} < {:?}", delay, expected);
+            assert!(delay <= expected.mul_f64(1.2), "{:?} > {:?}", delay, expected);
+        }
+    }
+
+    #[test]
+    fn test_backoff_max_retries() {
+        let mut backoff = Backoff::new(&reconnect(0.0, 3));
+        for _ in 0..3 {
+            assert!(backoff.next_delay().is_some());
+        }
+        assert_eq!(backoff.next_delay(), None);
+        assert_eq!(backoff.attempts(), 3);
+
+        backoff.reset();
+        assert!(backoff.next_delay().is_some());
+    }
+}
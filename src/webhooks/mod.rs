@@ -0,0 +1,182 @@
+///
+/// # Neolink Webhooks
+///
+/// This module posts the motion events of the cameras to urls as JSON, such
+/// as to an http in node of Node-RED, without needing an MQTT broker
+///
+/// It runs alongside the commands that keep running such as `neolink rtsp`,
+/// `neolink mqtt` and `neolink http` when there is a `[webhooks]` section in
+/// the config
+///
+/// # Example Config
+///
+/// ```toml
+/// [webhooks]
+/// urls = ["http://127.0.0.1:1880/neolink"]
+/// cameras = ["Garage"] # All cameras when not given
+/// snapshot = true # Add a jpeg to the motion start events
+/// timeout = 10
+///   [webhooks.headers]
+///   Authorization = "Bearer ****"
+/// ```
+///
+/// Each event is posted as the same versioned JSON as the MQTT events
+///
+/// ```json
+/// {
+///   "version": 1,
+///   "camera": "Garage",
+///   "event": "motion_start",
+///   "time": "2026-10-16T13:05:00+10:00",
+///   "snapshot": "/9j/4AAQSkZJRg..."
+/// }
+/// ```
+///
+/// The event is `motion_start` or `motion_stop` and the time is in the
+/// camera's `timezone`. The snapshot is the base64 of a jpeg and is only on
+/// the motion start events when `snapshot` is true
+///
+use anyhow::Result;
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine as _};
+use log::*;
+use std::collections::HashMap;
+use tokio::{sync::watch::Receiver as WatchReceiver, task::JoinSet, time::Duration};
+use tokio_util::sync::CancellationToken;
+
+use crate::{
+    common::{Event, EventKind, MdState, NeoInstance, NeoReactor},
+    config::{Config, WebhookConfig},
+    AnyResult,
+};
+
+/// Post the motion events of the cameras while there is a `[webhooks]` config
+///
+/// Cameras are followed as they are added to or removed from the config
+pub(crate) async fn serve(reactor: NeoReactor) -> Result<()> {
+    let mut config = reactor.config().await?;
+    let client = reqwest::Client::new();
+    let mut set = JoinSet::<(String, AnyResult<()>)>::new();
+    let mut cameras: HashMap<String, CancellationToken> = Default::default();
+    loop {
+        let names = {
+            let config = config.borrow_and_update();
+            let webhooks = config.webhooks.as_ref();
+            config
+                .cameras
+                .iter()
+                .filter(|camera| camera.enabled)
+                .map(|camera| camera.name.clone())
+                .filter(|name| {
+                    webhooks.is_some_and(|webhooks| {
+                        webhooks.cameras.is_empty() || webhooks.cameras.contains(name)
+                    })
+                })
+                .collect::<Vec<_>>()
+        };
+
+        cameras.retain(|name, cancel| {
+            if names.contains(name) {
+                true
+            } else {
+                cancel.cancel();
+                false
+            }
+        });
+        for name in names {
+            if cameras.contains_key(&name) {
+                continue;
+            }
+            let cancel = CancellationToken::new();
+            cameras.insert(name.clone(), cancel.clone());
+            let reactor = reactor.clone();
+            let client = client.clone();
+            set.spawn(async move {
+                let result = tokio::select! {
+                    _ = cancel.cancelled() => AnyResult::Ok(()),
+                    v = async {
+                        let camera = reactor.get(&name).await?;
+                        camera_main(&camera, reactor.config().await?, &client).await
+                    } => v,
+                };
+                (name, result)
+            });
+        }
+
+        tokio::select! {
+            v = config.changed() => v?,
+            Some(joined) = set.join_next() => {
+                // Only a cancelled camera ends without an error, those that
+                // failed are started again on the next config change
+                let (name, result) = joined?;
+                if let Err(e) = result {
+                    error!("{}: Webhooks: {:?}", name, e);
+                    cameras.remove(&name);
+                }
+            }
+        }
+    }
+}
+
+/// Post the motion events of one camera
+async fn camera_main(
+    camera: &NeoInstance,
+    config: WatchReceiver<Config>,
+    client: &reqwest::Client,
+) -> AnyResult<()> {
+    let mut motion = camera.motion().await?;
+    let mut last = None;
+    loop {
+        motion.changed().await?;
+        let event = match *motion.borrow_and_update() {
+            MdState::Start(_) => EventKind::MotionStart,
+            MdState::Stop(_) => EventKind::MotionStop,
+            MdState::Unknown => continue,
+        };
+        if last.replace(event) == Some(event) {
+            continue;
+        }
+
+        let Some(webhooks) = config.borrow().webhooks.clone() else {
+            continue;
+        };
+        let camera_config = camera.config().await?.borrow().clone();
+        let snapshot = if webhooks.snapshot && event == EventKind::MotionStart {
+            match camera.snapshot().await {
+                Ok(jpeg) => Some(BASE64.encode(jpeg)),
+                Err(e) => {
+                    warn!("{}: Webhook snapshot failed: {:?}", camera_config.name, e);
+                    None
+                }
+            }
+        } else {
+            None
+        };
+        let payload = Event {
+            snapshot,
+            ..Event::new(&camera_config.name, event, camera_config.timezone().now())
+        };
+
+        for url in webhooks.urls.iter() {
+            if let Err(e) = post(client, &webhooks, url, &payload).await {
+                warn!("{}: Webhook to {} failed: {:?}", camera_config.name, url, e);
+            }
+        }
+    }
+}
+
+async fn post(
+    client: &reqwest::Client,
+    webhooks: &WebhookConfig,
+    url: &str,
+    payload: &Event,
+) -> AnyResult<()> {
+    let mut request = client
+        .post(url)
+        .timeout(Duration::from_secs(webhooks.timeout))
+        .json(payload);
+    for (key, value) in webhooks.headers.iter() {
+        request = request.header(key, value);
+    }
+    request.send().await?.error_for_status()?;
+    Ok(())
+}
@@ -0,0 +1,8 @@
+use clap::Parser;
+
+/// The webrtc command serves the cameras to browsers over WebRTC
+///
+/// Each camera has a WHEP endpoint at `/{CameraName}/whep` and a page that
+/// plays it at `/{CameraName}`
+#[derive(Parser, Debug)]
+pub struct Opt {}
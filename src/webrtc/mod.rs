@@ -0,0 +1,567 @@
+///
+/// # Neolink WebRTC
+///
+/// This module serves the cameras to browsers over WebRTC so that they can be
+/// watched with well under a second of latency without an rtsp to webrtc
+/// gateway such as go2rtc
+///
+/// Each camera has a [WHEP](https://datatracker.ietf.org/doc/draft-ietf-wish-whep/)
+/// endpoint. A browser or a WHEP player POSTs its SDP offer to
+/// `http://my.ip.address:8889/{CameraName}/whep` and gets the answer with
+/// all of the ICE candidates. The session is ended with a DELETE to the
+/// `Location` of the reply or when the browser goes away
+///
+/// `http://my.ip.address:8889/{CameraName}` is a page that plays it
+///
+/// Add `?stream=sub` to either for the substream
+///
+/// The video is sent as the camera sends it, H264 or H265, so the browser must
+/// support the camera's codec. AAC audio is transcoded to opus
+///
+/// # Usage
+///
+/// ```bash
+/// neolink webrtc --config=config.toml
+/// ```
+///
+/// # Example Config
+///
+/// ```toml
+/// [webrtc]
+/// port = 8889
+/// # Only needed for browsers on another network
+/// stun_server = "stun://stun.l.google.com:19302"
+/// ```
+///
+/// When `users` are in the config the request must give one of them with HTTP
+/// basic auth, limited to the camera's `permitted_users` in the same way as rtsp
+///
+use anyhow::{anyhow, Context, Result};
+use crossbeam_channel::bounded;
+use futures::stream::StreamExt;
+use gstreamer::{
+    parse::launch_full, prelude::*, ClockTime, Element, MessageView, ParseFlags, Pipeline, Promise,
+    State,
+};
+use gstreamer_app::AppSrc;
+use gstreamer_sdp::SDPMessage;
+use gstreamer_webrtc::{
+    WebRTCICEGatheringState, WebRTCPeerConnectionState, WebRTCSDPType, WebRTCSessionDescription,
+};
+use log::*;
+use neolink_core::bc_protocol::StreamKind;
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+use tokio::{
+    net::TcpListener,
+    sync::mpsc::channel as mpsc,
+    task::JoinSet,
+    time::{timeout, Duration},
+};
+use tokio_stream::wrappers::BroadcastStream;
+use tokio_util::sync::CancellationToken;
+
+mod cmdline;
+
+use crate::{
+    common::{
+        httpd::{self, Request, Response},
+        AudFormat, NeoReactor, StampedData, StreamInstance, VidFormat,
+    },
+    config::{CameraConfig, Config},
+    http::{basic_auth, decode_name, permitted},
+    AnyResult,
+};
+pub(crate) use cmdline::Opt;
+
+/// Largest request body that is accepted, an SDP offer is a few KiB
+const MAX_BODY: usize = 64 * 1024;
+
+/// The page at `/{CameraName}` that plays the camera's WHEP endpoint
+const PLAYER: &str = include_str!("player.html");
+
+/// The running sessions by their id, cancelled by a DELETE
+type Sessions = Arc<Mutex<HashMap<String, CancellationToken>>>;
+
+/// Entry point for the webrtc subcommand
+///
+/// Opt is the command line options
+pub(crate) async fn main(_opt: Opt, reactor: NeoReactor) -> Result<()> {
+    gstreamer::init()
+        .context("Unable to start gstreamer ensure it and all plugins are installed")?;
+    let config = reactor.config().await?;
+    let (bind, port) = {
+        let config = config.borrow();
+        (config.webrtc.bind.clone(), config.webrtc.port)
+    };
+    let listener = TcpListener::bind((bind.as_str(), port))
+        .await
+        .with_context(|| format!("Failed to bind the WebRTC port {}:{}", bind, port))?;
+    info!("WebRTC: Serving on {}:{}", bind, port);
+
+    let sessions: Sessions = Default::default();
+    httpd::serve(listener, "WebRTC", MAX_BODY, move |request| {
+        let config = config.borrow().clone();
+        let reactor = reactor.clone();
+        let sessions = sessions.clone();
+        async move {
+            handle_request(request, &config, &reactor, &sessions)
+                .await
+                .with_header("Access-Control-Allow-Origin", "*")
+                .with_header("Access-Control-Allow-Methods", "GET, POST, DELETE, OPTIONS")
+                .with_header(
+                    "Access-Control-Allow-Headers",
+                    "Authorization, Content-Type",
+                )
+                .with_header("Access-Control-Expose-Headers", "Location")
+        }
+    })
+    .await
+}
+
+async fn handle_request(
+    request: Request,
+    config: &Config,
+    reactor: &NeoReactor,
+    sessions: &Sessions,
+) -> Response {
+    let auth = request.header("authorization").and_then(basic_auth);
+    let path = request.path.as_str();
+    match request.method.as_str() {
+        // The CORS preflight of a WHEP player on another origin
+        "OPTIONS" => {
+            Response::text("204 No Content", "").with_header("Accept-Post", "application/sdp")
+        }
+        "GET" | "HEAD" => player(path, auth, config),
+        "POST" => offer(path, &request.body, auth, config, reactor, sessions).await,
+        "DELETE" => hangup(path, auth, config, sessions),
+        _ => Response::text(
+            "405 Method Not Allowed",
+            "Only GET, POST and DELETE are supported",
+        ),
+    }
+}
+
+/// The config of the camera in the path if the user may see it
+fn camera<'a>(
+    name: &str,
+    auth: Option<&(String, String)>,
+    config: &'a Config,
+) -> Result<&'a CameraConfig, Response> {
+    let name = decode_name(name);
+    let Some(camera_config) = config
+        .cameras
+        .iter()
+        .find(|camera| camera.enabled && camera.name == name)
+    else {
+        return Err(Response::text("404 Not Found", "No camera with this name"));
+    };
+    if !permitted(config, camera_config, auth) {
+        return Err(Response::text(
+            "401 Unauthorized",
+            "A permitted user is required",
+        ));
+    }
+    Ok(camera_config)
+}
+
+/// `GET /{CameraName}`
+fn player(path: &str, auth: Option<(String, String)>, config: &Config) -> Response {
+    let path = path.split('?').next().unwrap_or_default();
+    let name = path.trim_matches('/');
+    if name.is_empty() || name.contains('/') {
+        return Response::text("404 Not Found", "Expected /{CameraName}");
+    }
+    if let Err(response) = camera(name, auth.as_ref(), config) {
+        return response;
+    }
+    Response::new(
+        "200 OK",
+        "text/html; charset=utf-8",
+        PLAYER.as_bytes().to_vec(),
+    )
+}
+
+/// `POST /{CameraName}/whep` with the SDP offer
+async fn offer(
+    path: &str,
+    sdp: &[u8],
+    auth: Option<(String, String)>,
+    config: &Config,
+    reactor: &NeoReactor,
+    sessions: &Sessions,
+) -> Response {
+    let (path, query) = path.split_once('?').unwrap_or((path, ""));
+    let Some(name) = path
+        .strip_prefix('/')
+        .and_then(|path| path.strip_suffix("/whep"))
+    else {
+        return Response::text("404 Not Found", "Expected /{CameraName}/whep");
+    };
+    let camera_config = match camera(name, auth.as_ref(), config) {
+        Ok(camera_config) => camera_config,
+        Err(response) => return response,
+    };
+    let kind = match query
+        .split('&')
+        .find_map(|pair| pair.strip_prefix("stream="))
+    {
+        Some("sub") => StreamKind::Sub,
+        Some("extern") => StreamKind::Extern,
+        _ => StreamKind::Main,
+    };
+    let Ok(offer) = SDPMessage::parse_buffer(sdp) else {
+        return Response::text("400 Bad Request", "Expected an SDP offer");
+    };
+
+    let name = &camera_config.name;
+    match start_session(name, kind, offer, config, reactor, sessions).await {
+        Ok((id, answer)) => Response::new("201 Created", "application/sdp", answer.into_bytes())
+            .with_header("Location", format!("{}/{}", path, id)),
+        Err(e) => {
+            warn!("{}: WebRTC: Failed to start the session: {:?}", name, e);
+            Response::text("503 Service Unavailable", "Failed to start the session")
+        }
+    }
+}
+
+/// `DELETE /{CameraName}/whep/{Session}`
+fn hangup(
+    path: &str,
+    auth: Option<(String, String)>,
+    config: &Config,
+    sessions: &Sessions,
+) -> Response {
+    let path = path.split('?').next().unwrap_or_default();
+    let Some((name, id)) = path
+        .strip_prefix('/')
+        .and_then(|path| path.rsplit_once("/whep/"))
+    else {
+        return Response::text("404 Not Found", "Expected /{CameraName}/whep/{Session}");
+    };
+    if let Err(response) = camera(name, auth.as_ref(), config) {
+        return response;
+    }
+    match sessions
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .remove(id)
+    {
+        Some(cancel) => {
+            cancel.cancel();
+            Response::text("200 OK", "OK")
+        }
+        None => Response::text("404 Not Found", "No session with this id"),
+    }
+}
+
+/// Answer the offer and start sending the stream to the browser
+///
+/// Returns the id of the session and the answer
+async fn start_session(
+    name: &str,
+    kind: StreamKind,
+    offer: SDPMessage,
+    config: &Config,
+    reactor: &NeoReactor,
+    sessions: &Sessions,
+) -> AnyResult<(String, String)> {
+    let camera = reactor.get(name).await?;
+    let mut stream = camera.stream(kind).await.context("Failed to start video")?;
+    timeout(
+        Duration::from_secs(15),
+        stream.config.wait_for(|config| config.vid_ready()),
+    )
+    .await
+    .context("The camera did not send its video in time")??;
+    // After vid give it 1s to look for audio
+    if let Ok(v) = timeout(
+        Duration::from_secs(1),
+        stream.config.wait_for(|config| config.aud_ready()),
+    )
+    .await
+    {
+        v?;
+    }
+    let stream_config = stream.config.borrow().clone();
+    // Browsers cannot play ADPCM and it is not worth a second transcoder
+    let audio = matches!(stream_config.aud_format, AudFormat::Aac);
+    let (pipeline, webrtc, vidsrc, audsrc) = create_pipeline(
+        stream_config.vid_format,
+        audio,
+        config.webrtc.stun_server.as_deref(),
+    )?;
+
+    let answer = {
+        let pipeline = pipeline.clone();
+        let webrtc = webrtc.clone();
+        tokio::task::spawn_blocking(move || negotiate(&pipeline, &webrtc, offer)).await?
+    };
+    let answer = match answer {
+        Ok(answer) => answer,
+        Err(e) => {
+            let _ = pipeline.set_state(State::Null);
+            return Err(e);
+        }
+    };
+
+    let id = format!("{:016x}", rand::random::<u64>());
+    let cancel = CancellationToken::new();
+    sessions
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .insert(id.clone(), cancel.clone());
+
+    let name = name.to_string();
+    let session = id.clone();
+    let sessions = sessions.clone();
+    tokio::task::spawn(async move {
+        info!("{}: WebRTC: Session {} started", name, session);
+        match run_session(&stream, pipeline, webrtc, vidsrc, audsrc, cancel).await {
+            Ok(()) => info!("{}: WebRTC: Session {} ended", name, session),
+            Err(e) => info!("{}: WebRTC: Session {} ended: {:?}", name, session, e),
+        }
+        sessions
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .remove(&session);
+    });
+    Ok((id, answer))
+}
+
+/// Set the offer on the webrtcbin and make the answer
+///
+/// The answer is given once the ICE candidates are gathered so that it holds
+/// all of them, as WHEP players do not all support trickle ICE
+fn negotiate(pipeline: &Pipeline, webrtc: &Element, offer: SDPMessage) -> AnyResult<String> {
+    let (gathered_tx, gathered_rx) = bounded(1);
+    webrtc.connect_notify(Some("ice-gathering-state"), move |webrtc, _| {
+        if webrtc.property::<WebRTCICEGatheringState>("ice-gathering-state")
+            == WebRTCICEGatheringState::Complete
+        {
+            let _ = gathered_tx.try_send(());
+        }
+    });
+    pipeline.set_state(State::Playing)?;
+
+    let offer = WebRTCSessionDescription::new(WebRTCSDPType::Offer, offer);
+    let promise = Promise::new();
+    webrtc.emit_by_name::<()>("set-remote-description", &[&offer, &promise]);
+    promise.wait();
+
+    let promise = Promise::new();
+    webrtc.emit_by_name::<()>("create-answer", &[&None::<gstreamer::Structure>, &promise]);
+    promise.wait();
+    let answer = promise
+        .get_reply()
+        .and_then(|reply| reply.get::<WebRTCSessionDescription>("answer").ok())
+        .ok_or_else(|| {
+            anyhow!(
+                "The offer could not be answered, the browser may not support the camera's codec"
+            )
+        })?;
+
+    let promise = Promise::new();
+    webrtc.emit_by_name::<()>("set-local-description", &[&answer, &promise]);
+    promise.wait();
+
+    if gathered_rx
+        .recv_timeout(std::time::Duration::from_secs(5))
+        .is_err()
+    {
+        debug!("WebRTC: Gathering the ICE candidates timed out, answering with those found");
+    }
+    let answer = webrtc
+        .property::<Option<WebRTCSessionDescription>>("local-description")
+        .unwrap_or(answer);
+    answer
+        .sdp()
+        .as_text()
+        .map_err(|e| anyhow!("Failed to write the answer: {:?}", e))
+}
+
+/// Send the frames to the browser until the session is ended
+async fn run_session(
+    stream: &StreamInstance,
+    pipeline: Pipeline,
+    webrtc: Element,
+    vidsrc: AppSrc,
+    audsrc: Option<AppSrc>,
+    cancel: CancellationToken,
+) -> AnyResult<()> {
+    let (closed_tx, mut closed_rx) = mpsc(1);
+    webrtc.connect_notify(Some("connection-state"), move |webrtc, _| {
+        let state = webrtc.property::<WebRTCPeerConnectionState>("connection-state");
+        // The player makes a new offer to reconnect
+        if matches!(
+            state,
+            WebRTCPeerConnectionState::Disconnected
+                | WebRTCPeerConnectionState::Failed
+                | WebRTCPeerConnectionState::Closed
+        ) {
+            let _ = closed_tx.try_send(state);
+        }
+    });
+
+    let mut set = JoinSet::<AnyResult<()>>::new();
+    let thread_pipeline = pipeline.clone();
+    set.spawn_blocking(move || watch_pipeline(thread_pipeline));
+
+    let stream_config = stream.config.borrow().clone();
+    let mut config = stream.config.clone();
+    let mut vid = BroadcastStream::new(stream.vid.resubscribe());
+    let mut aud = BroadcastStream::new(stream.aud.resubscribe());
+    let res = tokio::select! {
+        v = async {
+            // The browser cannot decode until the first keyframe
+            let mut started = false;
+            loop {
+                tokio::select! {
+                    data = vid.next() => match data {
+                        Some(Ok(StampedData { data, keyframe, .. })) => {
+                            started |= keyframe;
+                            if started {
+                                push(&vidsrc, &data)?;
+                            }
+                        }
+                        // Lagged, the browser copes with a gap
+                        Some(Err(_)) => {}
+                        None => return Err(anyhow!("The stream ended")),
+                    },
+                    data = aud.next(), if audsrc.is_some() => match (data, audsrc.as_ref()) {
+                        (Some(Ok(StampedData { data, .. })), Some(audsrc)) if started => {
+                            push(audsrc, &data)?;
+                        }
+                        (None, _) => return Err(anyhow!("The audio ended")),
+                        _ => {}
+                    },
+                }
+            }
+        } => v,
+        _ = cancel.cancelled() => Ok(()),
+        state = closed_rx.recv() => match state {
+            Some(state) => Err(anyhow!("The browser went away: {:?}", state)),
+            None => Ok(()),
+        },
+        v = config.wait_for(|config| config != &stream_config) => {
+            v?;
+            Err(anyhow!("The stream format changed"))
+        },
+        v = set.join_next() => v
+            .unwrap_or(Ok(Ok(())))
+            .map_err(anyhow::Error::from)
+            .and_then(|v| v),
+    };
+
+    let _ = pipeline.set_state(State::Null);
+    while set.join_next().await.is_some() {}
+    res
+}
+
+fn push(source: &AppSrc, data: &[u8]) -> AnyResult<()> {
+    let mut buf = gstreamer::Buffer::with_size(data.len())?;
+    buf.get_mut()
+        .ok_or_else(|| anyhow!("New buffer should be writable"))?
+        .copy_from_slice(0, data)
+        .map_err(|_| anyhow!("Failed to copy the frame"))?;
+    source
+        .push_buffer(buf)
+        .map_err(|e| anyhow!("Streamer Error: {e:?}"))?;
+    Ok(())
+}
+
+/// Wait for an error on the pipeline, it is started by [`negotiate`]
+fn watch_pipeline(pipeline: Pipeline) -> AnyResult<()> {
+    let bus = pipeline
+        .bus()
+        .expect("Pipeline without bus. Shouldn't happen!");
+
+    loop {
+        match bus.timed_pop(ClockTime::from_mseconds(500)) {
+            Some(msg) => match msg.view() {
+                MessageView::Eos(..) => break Ok(()),
+                MessageView::Error(err) => {
+                    break Err(anyhow!("Error from gstreamer in webrtc: {:?}", err));
+                }
+                _ => (),
+            },
+            // Stopped by the session
+            None if pipeline.current_state() == State::Null => break Ok(()),
+            None => (),
+        }
+    }
+}
+
+/// Payload the stream into a webrtcbin
+fn create_pipeline(
+    vid_format: VidFormat,
+    audio: bool,
+    stun_server: Option<&str>,
+) -> AnyResult<(Pipeline, Element, AppSrc, Option<AppSrc>)> {
+    let (parser, payloader, encoding) = match vid_format {
+        VidFormat::H264 => ("h264parse", "rtph264pay", "H264"),
+        VidFormat::H265 => ("h265parse", "rtph265pay", "H265"),
+        VidFormat::None => return Err(anyhow!("The camera has not sent its video format")),
+    };
+    let stun = stun_server
+        .map(|stun| format!(" stun-server=\"{}\"", stun))
+        .unwrap_or_default();
+    // The parameter sets are sent on each keyframe so that the browser can
+    // start on any of them
+    let mut launch_str = format!(
+        "webrtcbin name=webrtc bundle-policy=max-bundle{} \
+        appsrc name=vidsrc is-live=true do-timestamp=true format=time \
+        ! {} config-interval=-1 \
+        ! {} config-interval=-1 \
+        ! application/x-rtp,media=video,encoding-name={},payload=96 \
+        ! webrtc.",
+        stun, parser, payloader, encoding,
+    );
+    if audio {
+        launch_str.push_str(
+            " appsrc name=audsrc is-live=true do-timestamp=true format=time \
+            ! aacparse \
+            ! decodebin \
+            ! audioconvert \
+            ! audioresample \
+            ! opusenc \
+            ! rtpopuspay \
+            ! application/x-rtp,media=audio,encoding-name=OPUS,payload=97 \
+            ! webrtc.",
+        );
+    }
+    debug!("{}", launch_str);
+
+    let pipeline = launch_full(&launch_str, None, ParseFlags::empty())
+        .context("Unable to load gstreamer pipeline ensure all gstramer plugins are installed")?
+        .dynamic_cast::<Pipeline>()
+        .map_err(|_| {
+            anyhow!("Unable to create gstreamer pipeline ensure all gstramer plugins are installed")
+        })?;
+
+    let webrtc = pipeline
+        .by_name("webrtc")
+        .expect("There shoud be a `webrtc`");
+    let vidsrc = pipeline
+        .by_name("vidsrc")
+        .expect("There shoud be a `vidsrc`")
+        .dynamic_cast::<AppSrc>()
+        .map_err(|_| anyhow!("Cannot find appsource in gstreamer, check your gstreamer plugins"))?;
+    let audsrc = if audio {
+        Some(
+            pipeline
+                .by_name("audsrc")
+                .expect("There shoud be an `audsrc`")
+                .dynamic_cast::<AppSrc>()
+                .map_err(|_| {
+                    anyhow!("Cannot find appsource in gstreamer, check your gstreamer plugins")
+                })?,
+        )
+    } else {
+        None
+    };
+
+    Ok((pipeline, webrtc, vidsrc, audsrc))
+}